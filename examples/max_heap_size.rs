@@ -20,7 +20,12 @@ fn main() -> Result<(), Error> {
     // Will return a `HeapExhausted` error
     let module_handle = runtime.load_module(&module);
 
-    assert!(module_handle.is_err());
+    match module_handle {
+        Err(Error::HeapExhausted { used, limit }) => {
+            println!("Heap exhausted: {used} bytes in use, limit was {limit} bytes");
+        }
+        other => panic!("Expected a heap exhaustion error, got {other:?}"),
+    }
 
     Ok(())
 }
@@ -0,0 +1,232 @@
+//! Procedural macros for rustyscript
+//!
+//! This crate is re-exported by rustyscript, and should not be used directly
+#![warn(missing_docs)]
+#![warn(clippy::pedantic)]
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{
+    parse_macro_input, spanned::Spanned, FnArg, ImplItem, ImplItemFn, ItemImpl, Pat, Type,
+    Visibility,
+};
+
+/// Exposes the public methods of an impl block to javascript
+///
+/// Implements `rustyscript::JsApi` for the type, so an instance can be given to `Runtime::register_api`
+/// See the documentation of `rustyscript::JsApi` for details and an example
+#[proc_macro_attribute]
+pub fn js_api(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as ItemImpl);
+
+    let expanded = if attr.is_empty() {
+        expand(&input).unwrap_or_else(syn::Error::into_compile_error)
+    } else {
+        syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "js_api does not take any arguments",
+        )
+        .into_compile_error()
+    };
+
+    quote! {
+        #input
+        #expanded
+    }
+    .into()
+}
+
+/// A method exposed to javascript
+struct Method<'a> {
+    function: &'a ImplItemFn,
+    args: Vec<(&'a syn::Ident, &'a Type)>,
+    mutable: bool,
+}
+
+impl<'a> Method<'a> {
+    /// Returns None for methods that are not exposed - those that are not public, or have no receiver
+    fn parse(function: &'a ImplItemFn) -> syn::Result<Option<Self>> {
+        if !matches!(function.vis, Visibility::Public(_)) {
+            return Ok(None);
+        }
+
+        let mut inputs = function.sig.inputs.iter();
+        let Some(FnArg::Receiver(receiver)) = inputs.next() else {
+            return Ok(None);
+        };
+        if receiver.reference.is_none() {
+            return Err(syn::Error::new(
+                receiver.span(),
+                "exposed methods must take &self or &mut self",
+            ));
+        }
+
+        let mutable = receiver.mutability.is_some();
+        if mutable && function.sig.asyncness.is_some() {
+            return Err(syn::Error::new(
+                receiver.span(),
+                "async exposed methods must take &self",
+            ));
+        }
+
+        let mut args = Vec::new();
+        for input in inputs {
+            let FnArg::Typed(arg) = input else {
+                continue;
+            };
+            let Pat::Ident(pat) = arg.pat.as_ref() else {
+                return Err(syn::Error::new(
+                    arg.pat.span(),
+                    "exposed method arguments must be simple identifiers",
+                ));
+            };
+            if matches!(arg.ty.as_ref(), Type::Reference(_)) {
+                return Err(syn::Error::new(
+                    arg.ty.span(),
+                    "exposed method arguments must be owned types",
+                ));
+            }
+            args.push((&pat.ident, arg.ty.as_ref()));
+        }
+
+        Ok(Some(Self {
+            function,
+            args,
+            mutable,
+        }))
+    }
+
+    fn is_async(&self) -> bool {
+        self.function.sig.asyncness.is_some()
+    }
+
+    /// Generates the registration of the method as a rustyscript function
+    fn registration(&self) -> proc_macro2::TokenStream {
+        let ident = &self.function.sig.ident;
+        let name = ident.to_string();
+        let arg_names = self.args.iter().map(|(name, _)| name).collect::<Vec<_>>();
+        let arg_types = self.args.iter().map(|(_, ty)| ty);
+
+        let bind_args = if self.args.is_empty() {
+            quote! { let _ = args; }
+        } else {
+            quote! { let mut args = args; }
+        };
+        let parse_args = quote! {
+            #bind_args
+            #(
+                let #arg_names: #arg_types = ::rustyscript::serde_json::from_value(
+                    args.next().unwrap_or(::rustyscript::serde_json::Value::Null)
+                ).map_err(|e| ::rustyscript::Error::Runtime(
+                    format!("Invalid argument for {}: {e}", #name)
+                ))?;
+            )*
+        };
+        let borrow = if self.mutable {
+            quote! { let mut this = this.try_borrow_mut() }
+        } else {
+            quote! { let this = this.try_borrow() }
+        };
+        let borrow = quote! {
+            #borrow.map_err(|_| ::rustyscript::Error::Runtime(
+                format!("{} was called while the API was already in use", #name)
+            ))?;
+        };
+
+        if self.is_async() {
+            quote! {
+                {
+                    let this = ::std::rc::Rc::clone(&this);
+                    runtime.register_async_function(
+                        &format!("{namespace}.{}", #name),
+                        move |args: ::std::vec::Vec<::rustyscript::serde_json::Value>| {
+                            let this = ::std::rc::Rc::clone(&this);
+                            let future = async move {
+                                let args = args.into_iter();
+                                #parse_args
+
+                                // Runs on a clone, so the API is not borrowed while the method is awaited
+                                let this: Self = {
+                                    #borrow
+                                    ::std::clone::Clone::clone(&*this)
+                                };
+                                let result = this.#ident(#(#arg_names),*).await?;
+                                ::rustyscript::serde_json::to_value(result)
+                                    .map_err(|e| ::rustyscript::Error::Runtime(e.to_string()))
+                            };
+                            ::std::boxed::Box::pin(future)
+                                as ::std::pin::Pin<::std::boxed::Box<dyn ::std::future::Future<
+                                    Output = ::std::result::Result<::rustyscript::serde_json::Value, ::rustyscript::Error>
+                                >>>
+                        },
+                    )?;
+                }
+            }
+        } else {
+            quote! {
+                {
+                    let this = ::std::rc::Rc::clone(&this);
+                    runtime.register_function(
+                        &format!("{namespace}.{}", #name),
+                        move |args: &[::rustyscript::serde_json::Value]| {
+                            let args = args.iter().cloned();
+                            #parse_args
+                            #borrow
+                            let result = this.#ident(#(#arg_names),*)?;
+                            ::rustyscript::serde_json::to_value(result)
+                                .map_err(|e| ::rustyscript::Error::Runtime(e.to_string()))
+                        },
+                    )?;
+                }
+            }
+        }
+    }
+}
+
+fn expand(input: &ItemImpl) -> syn::Result<proc_macro2::TokenStream> {
+    if let Some((_, path, _)) = &input.trait_ {
+        return Err(syn::Error::new(
+            path.span(),
+            "js_api can only be used on inherent impl blocks",
+        ));
+    }
+
+    let mut methods = Vec::new();
+    for item in &input.items {
+        if let ImplItem::Fn(function) = item {
+            if let Some(method) = Method::parse(function)? {
+                methods.push(method);
+            }
+        }
+    }
+
+    let self_ty = &input.self_ty;
+    let (impl_generics, _, where_clause) = input.generics.split_for_impl();
+
+    let sync_names = methods
+        .iter()
+        .filter(|m| !m.is_async())
+        .map(|m| m.function.sig.ident.to_string());
+    let async_names = methods
+        .iter()
+        .filter(|m| m.is_async())
+        .map(|m| m.function.sig.ident.to_string());
+    let registrations = methods.iter().map(Method::registration);
+
+    Ok(quote! {
+        impl #impl_generics ::rustyscript::JsApi for #self_ty #where_clause {
+            const METHODS: &'static [&'static str] = &[#(#sync_names),*];
+            const ASYNC_METHODS: &'static [&'static str] = &[#(#async_names),*];
+
+            fn register_functions(
+                self,
+                runtime: &mut ::rustyscript::Runtime,
+                namespace: &str,
+            ) -> ::std::result::Result<(), ::rustyscript::Error> {
+                #[allow(unused_variables)]
+                let this = ::std::rc::Rc::new(::std::cell::RefCell::new(self));
+                #(#registrations)*
+                Ok(())
+            }
+        }
+    })
+}
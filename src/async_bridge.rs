@@ -1,13 +1,83 @@
-use crate::Error;
-use std::rc::Rc;
+use crate::{ext::rustyscript::ScriptExit, preemption::Preemption, Error};
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+    time::Instant,
+};
 use tokio_util::sync::CancellationToken;
 
+/// Signals that the isolate has exhausted its heap, and records how much memory was in use
+/// Shared between the near-heap-limit callback and the async bridge
+#[derive(Clone, Default)]
+pub struct HeapExhaustedToken {
+    token: CancellationToken,
+    usage: Rc<Cell<(usize, usize)>>,
+}
+
+impl HeapExhaustedToken {
+    /// Cancels the token, recording the heap usage and configured limit in bytes
+    pub fn cancel(&self, used: usize, limit: usize) {
+        self.usage.set((used, limit));
+        self.token.cancel();
+    }
+
+    /// Returns the `(used, limit)` heap sizes recorded at cancellation, in bytes
+    pub fn usage(&self) -> (usize, usize) {
+        self.usage.get()
+    }
+
+    /// Returns the underlying cancellation token
+    pub fn token(&self) -> CancellationToken {
+        self.token.clone()
+    }
+}
+
+/// Source of the cancellation tokens given to cancellable async callbacks
+///
+/// Tokens are cancelled when a blocking call times out or exhausts the heap,
+/// and when the runtime is dropped - async calls never cancel them, see [`crate::Runtime::register_cancellable_function`]
+#[derive(Clone)]
+pub struct CallbackCancellation {
+    shutdown: CancellationToken,
+    current: Rc<RefCell<CancellationToken>>,
+}
+
+impl Default for CallbackCancellation {
+    fn default() -> Self {
+        let shutdown = CancellationToken::new();
+        let current = Rc::new(RefCell::new(shutdown.child_token()));
+        Self { shutdown, current }
+    }
+}
+
+impl CallbackCancellation {
+    /// Returns a token for a new callback invocation
+    pub fn token(&self) -> CancellationToken {
+        self.current.borrow().child_token()
+    }
+
+    /// Cancels all tokens handed out so far
+    /// Tokens handed out afterwards are unaffected
+    pub fn cancel_pending(&self) {
+        let next = self.shutdown.child_token();
+        self.current.replace(next).cancel();
+    }
+
+    /// Cancels all tokens, including any handed out in the future
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
+}
+
 /// A bridge to the tokio runtime that connects the Deno and Tokio runtimes
 /// Implements common patterns used throughout the codebase
 pub struct AsyncBridge {
     tokio: Rc<tokio::runtime::Runtime>,
     timeout: std::time::Duration,
-    heap_exhausted_token: CancellationToken,
+    heap_exhausted_token: HeapExhaustedToken,
+    callback_cancellation: CallbackCancellation,
+    preemption: Option<Preemption>,
+    script_exit: ScriptExit,
 }
 
 impl AsyncBridge {
@@ -28,11 +98,14 @@ impl AsyncBridge {
         timeout: std::time::Duration,
         tokio: Rc<tokio::runtime::Runtime>,
     ) -> Self {
-        let heap_exhausted_token = CancellationToken::new();
+        let heap_exhausted_token = HeapExhaustedToken::default();
         Self {
             tokio,
             timeout,
             heap_exhausted_token,
+            callback_cancellation: CallbackCancellation::default(),
+            preemption: None,
+            script_exit: ScriptExit::default(),
         }
     }
 
@@ -46,7 +119,7 @@ impl AsyncBridge {
     /// Then the internal tokio runtime will be returned
     #[must_use]
     pub fn into_tokio_runtime(self) -> Rc<tokio::runtime::Runtime> {
-        self.tokio
+        self.tokio.clone()
     }
 
     /// Returns the timeout for the runtime
@@ -55,12 +128,67 @@ impl AsyncBridge {
         self.timeout
     }
 
+    /// Sets the timeout for the runtime
+    pub fn set_timeout(&mut self, timeout: std::time::Duration) {
+        self.timeout = timeout;
+    }
+
     /// Returns the heap exhausted token for the runtime
     /// Used to detect when the runtime has run out of memory
     #[must_use]
-    pub fn heap_exhausted_token(&self) -> CancellationToken {
+    pub fn heap_exhausted_token(&self) -> HeapExhaustedToken {
         self.heap_exhausted_token.clone()
     }
+
+    /// Returns the source of cancellation tokens for cancellable async callbacks
+    #[must_use]
+    pub fn callback_cancellation(&self) -> CallbackCancellation {
+        self.callback_cancellation.clone()
+    }
+
+    /// Enables epoch-based preemption of the isolate, if an interval is provided
+    /// See [`crate::RuntimeOptions::preemption_interval`]
+    ///
+    /// # Errors
+    /// Will return an error if the interval is zero, which would keep the ticker thread spinning
+    pub fn enable_preemption(
+        &mut self,
+        isolate: deno_core::v8::IsolateHandle,
+        interval: Option<std::time::Duration>,
+    ) -> Result<(), Error> {
+        if interval.is_some_and(|interval| interval.is_zero()) {
+            return Err(Error::Runtime(
+                "The preemption interval must be greater than zero".to_string(),
+            ));
+        }
+
+        self.preemption = interval.map(|interval| Preemption::start(isolate, interval));
+        Ok(())
+    }
+
+    /// Returns the preemption state of the runtime, if enabled
+    #[must_use]
+    pub fn preemption(&self) -> Option<Preemption> {
+        self.preemption.clone()
+    }
+
+    /// Fails blocking calls once the script has exited, see [`crate::ExitPolicy::Terminate`]
+    pub fn watch_exit(&mut self, script_exit: ScriptExit) {
+        self.script_exit = script_exit;
+    }
+
+    /// Returns the exit code of the script, if it has exited
+    #[must_use]
+    pub fn exit_code(&self) -> Option<i32> {
+        self.script_exit.code()
+    }
+}
+
+impl Drop for AsyncBridge {
+    fn drop(&mut self) {
+        // Signal any host tasks still running on behalf of the runtime
+        self.callback_cancellation.shutdown();
+    }
 }
 
 pub trait AsyncBridgeExt {
@@ -74,12 +202,59 @@ pub trait AsyncBridgeExt {
         let timeout = self.bridge().timeout();
         let rt = self.bridge().tokio_runtime();
         let heap_exhausted_token = self.bridge().heap_exhausted_token();
+        let callback_cancellation = self.bridge().callback_cancellation();
+
+        // The runtime was terminated by `Deno.exit`
+        let script_exit = self.bridge().script_exit.clone();
+        if let Some(code) = script_exit.code() {
+            return Err(Error::ScriptExited(code));
+        }
+
+        let cancelled = heap_exhausted_token.token();
 
-        rt.block_on(async move {
+        // Interrupts synchronous code, which the timeout below cannot
+        let preemption = self.bridge().preemption();
+        let previous_deadline = preemption.as_ref().map(|p| p.arm(timeout));
+
+        let start = Instant::now();
+        let mut result = rt.block_on(async move {
             tokio::select! {
-                result = tokio::time::timeout(timeout, f(self)) => result?,
-                () = heap_exhausted_token.cancelled() => Err(Error::HeapExhausted),
+                result = tokio::time::timeout(timeout, f(self)) => match result {
+                    Ok(result) => result,
+                    Err(_) => Err(Error::Timeout {
+                        elapsed: start.elapsed(),
+                        limit: timeout,
+                    }),
+                },
+                () = cancelled.cancelled() => {
+                    let (used, limit) = heap_exhausted_token.usage();
+                    Err(Error::HeapExhausted { used, limit })
+                },
+            }
+        });
+
+        if let (Some(preemption), Some(previous)) = (preemption, previous_deadline) {
+            if preemption.disarm(previous) {
+                result = Err(Error::Timeout {
+                    elapsed: start.elapsed(),
+                    limit: timeout,
+                });
             }
-        })
+        }
+
+        // Whatever the call returned, it was cut short by the exit
+        if let Some(code) = script_exit.code() {
+            result = Err(Error::ScriptExited(code));
+        }
+
+        // Cancel host work started by callbacks that will never be awaited
+        if matches!(
+            result,
+            Err(Error::Timeout { .. } | Error::HeapExhausted { .. } | Error::ScriptExited(_))
+        ) {
+            callback_cancellation.cancel_pending();
+        }
+
+        result
     }
 }
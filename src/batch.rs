@@ -0,0 +1,235 @@
+//! Batches of calls and value lookups, run together, see [`Runtime::batch`]
+use crate::{async_bridge::AsyncBridgeExt, Error, ModuleHandle, Runtime};
+use deno_core::serde_json;
+
+/// A function call or value lookup queued in a [`Batch`]
+pub(crate) enum BatchEntry {
+    Call {
+        module: Option<ModuleHandle>,
+        name: String,
+        args: Result<serde_json::Value, Error>,
+    },
+    Get {
+        module: Option<ModuleHandle>,
+        name: String,
+    },
+}
+
+impl BatchEntry {
+    pub fn module(&self) -> Option<&ModuleHandle> {
+        match self {
+            Self::Call { module, .. } | Self::Get { module, .. } => module.as_ref(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Call { name, .. } | Self::Get { name, .. } => name,
+        }
+    }
+}
+
+/// A set of function calls and value lookups, run together to reduce the overhead of each
+///
+/// Every entry is run in a single pass over the runtime, and the event loop is driven once
+/// until all of the resulting promises are resolved - rather than once per call
+///
+/// Entries run in the order they were added, and each has its own result,
+/// so one failing call does not prevent the others from running
+///
+/// ```rust
+/// use rustyscript::{ json_args, Module, Runtime };
+///
+/// # fn main() -> Result<(), rustyscript::Error> {
+/// let mut runtime = Runtime::new(Default::default())?;
+/// let module = runtime.load_module(&Module::new("test.js", "
+///     export const add = (a, b) => a + b;
+///     export const fetchName = async () => 'world';
+///     export const version = 3;
+/// "))?;
+///
+/// let results = runtime
+///     .batch()
+///     .call(Some(&module), "add", json_args!(1, 2))
+///     .call(Some(&module), "fetchName", json_args!())
+///     .get(Some(&module), "version")
+///     .run()?;
+///
+/// assert_eq!(results.get::<i64>(0)?, 3);
+/// assert_eq!(results.get::<String>(1)?, "world");
+/// assert_eq!(results.get::<i64>(2)?, 3);
+/// # Ok(())
+/// # }
+/// ```
+pub struct Batch<'a> {
+    runtime: &'a mut Runtime,
+    entries: Vec<BatchEntry>,
+}
+
+impl<'a> Batch<'a> {
+    pub(crate) fn new(runtime: &'a mut Runtime) -> Self {
+        Self {
+            runtime,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Queue a call to a function, as with [`Runtime::call_function`]
+    ///
+    /// If the function returns a promise, its resolved value is the result
+    #[must_use]
+    pub fn call(
+        mut self,
+        module_context: Option<&ModuleHandle>,
+        name: &str,
+        args: &impl serde::Serialize,
+    ) -> Self {
+        self.entries.push(BatchEntry::Call {
+            module: module_context.cloned(),
+            name: name.to_string(),
+            args: serde_json::to_value(args).map_err(Error::from),
+        });
+        self
+    }
+
+    /// Queue a lookup of a value, as with [`Runtime::get_value`]
+    #[must_use]
+    pub fn get(mut self, module_context: Option<&ModuleHandle>, name: &str) -> Self {
+        self.entries.push(BatchEntry::Get {
+            module: module_context.cloned(),
+            name: name.to_string(),
+        });
+        self
+    }
+
+    /// Returns the number of entries in the batch
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if the batch has no entries
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Run every entry in the batch, returning their results in the same order
+    ///
+    /// # Errors
+    /// Will return an error if the batch as a whole could not be run, such as if it times out
+    /// Errors from individual entries are returned by [`BatchResults::get`] instead
+    pub fn run(self) -> Result<BatchResults, Error> {
+        let entries = self.entries;
+        self.runtime.block_on(|runtime| async move {
+            let results = runtime.run_batch(&entries).await?;
+            Ok(BatchResults(results))
+        })
+    }
+
+    /// Run every entry in the batch, returning their results in the same order
+    ///
+    /// See [`Batch::run`]
+    ///
+    /// # Errors
+    /// Will return an error if the batch as a whole could not be run
+    pub async fn run_async(self) -> Result<BatchResults, Error> {
+        let results = self.runtime.run_batch(&self.entries).await?;
+        Ok(BatchResults(results))
+    }
+}
+
+/// The results of a [`Batch`], one per entry, in the order the entries were added
+#[derive(Debug)]
+pub struct BatchResults(Vec<Result<serde_json::Value, Error>>);
+
+impl BatchResults {
+    /// Returns the number of results
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns true if there are no results
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Deserialize the result of the entry at `index`
+    ///
+    /// # Errors
+    /// Will return the entry's error if it failed, or an error if there is no such entry,
+    /// or if the result cannot be deserialized into the requested type
+    pub fn get<T>(&self, index: usize) -> Result<T, Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        match self.0.get(index) {
+            Some(Ok(value)) => Ok(T::deserialize(value)?),
+            Some(Err(e)) => Err(e.clone()),
+            None => Err(Error::Runtime(format!("No batch entry at index {index}"))),
+        }
+    }
+
+    /// Returns the results, as untyped values
+    #[must_use]
+    pub fn into_results(self) -> Vec<Result<serde_json::Value, Error>> {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{json_args, Error, Module, Runtime, RuntimeOptions};
+
+    #[test]
+    fn test_batch() {
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let module = Module::new(
+            "test.js",
+            "
+            let count = 0;
+            export const increment = () => ++count;
+            export const delayed = async (n) => {
+                await new Promise((r) => setTimeout(r, 10));
+                return n * 2;
+            };
+            export const fail = () => { throw new Error('failed'); };
+            export const value = 'hello';
+        ",
+        );
+        let handle = runtime.load_module(&module).unwrap();
+
+        let batch = runtime
+            .batch()
+            .call(Some(&handle), "increment", json_args!())
+            .call(Some(&handle), "delayed", json_args!(2))
+            .call(Some(&handle), "delayed", json_args!(5))
+            .call(Some(&handle), "fail", json_args!())
+            .call(Some(&handle), "value", json_args!())
+            .get(Some(&handle), "value")
+            .call(Some(&handle), "increment", json_args!())
+            .get(None, "missing");
+        assert_eq!(batch.len(), 8);
+        let results = batch.run().unwrap();
+
+        assert_eq!(results.get::<i64>(0).unwrap(), 1);
+        assert_eq!(results.get::<i64>(1).unwrap(), 4);
+        assert_eq!(results.get::<i64>(2).unwrap(), 10);
+        results.get::<i64>(3).expect_err("Error was not returned");
+        assert!(matches!(
+            results.get::<i64>(4),
+            Err(Error::ValueNotCallable(_))
+        ));
+        assert_eq!(results.get::<String>(5).unwrap(), "hello");
+        assert_eq!(results.get::<i64>(6).unwrap(), 2);
+        assert!(matches!(
+            results.get::<i64>(7),
+            Err(Error::ValueNotFound(_))
+        ));
+        results
+            .get::<i64>(8)
+            .expect_err("Missing entry was returned");
+    }
+}
@@ -0,0 +1,218 @@
+//! Micro-benchmarks for script functions, see [`crate::Runtime::bench_function`]
+use crate::{js_value::Function, Error, ModuleHandle, Runtime};
+use deno_core::v8;
+use std::time::{Duration, Instant};
+
+/// Calls a function repeatedly, timing each call from within v8
+/// Timestamps come from `op_bench_now`, which is only available while a benchmark is running
+const HARNESS: &str = "(async (f, args, iterations) => {
+    const now = () => Deno.core.ops.op_bench_now();
+    const samples = new Array(iterations);
+    for (let i = 0; i < iterations; i++) {
+        const start = now();
+        const result = f(...args);
+        if (result instanceof Promise) await result;
+        samples[i] = now() - start;
+    }
+    return samples;
+})";
+
+/// Monotonic clock used by `op_bench_now`
+/// Only present in the `OpState` while a benchmark is running
+pub(crate) struct BenchClock(Instant);
+
+impl BenchClock {
+    /// Nanoseconds elapsed since the benchmark started
+    pub(crate) fn now(&self) -> f64 {
+        self.0.elapsed().as_secs_f64() * 1e9
+    }
+}
+
+/// Options for [`crate::Runtime::bench_function`]
+#[derive(Clone, Copy, Debug)]
+pub struct BenchOptions {
+    /// Number of untimed calls made first, allowing v8 to optimize the function
+    ///
+    /// Default: 10
+    pub warmup_iterations: u32,
+
+    /// Number of timed calls
+    ///
+    /// Default: 100
+    pub iterations: u32,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        Self {
+            warmup_iterations: 10,
+            iterations: 100,
+        }
+    }
+}
+
+/// Statistics gathered by [`crate::Runtime::bench_function`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BenchStats {
+    /// Number of timed calls
+    pub iterations: u32,
+
+    /// Mean time per call
+    pub mean: Duration,
+
+    /// Median time per call
+    pub median: Duration,
+
+    /// 95th percentile time per call
+    pub p95: Duration,
+
+    /// Fastest call
+    pub min: Duration,
+
+    /// Slowest call
+    pub max: Duration,
+
+    /// Standard deviation of the time per call
+    pub std_dev: Duration,
+
+    /// Growth of the v8 heap across the timed calls, in bytes
+    /// None if the heap shrank, which means a garbage collection ran during the benchmark
+    pub heap_growth: Option<usize>,
+}
+
+impl BenchStats {
+    /// Calculates statistics from per-call samples, in nanoseconds
+    fn from_samples(mut samples: Vec<f64>, heap_growth: Option<usize>) -> Self {
+        samples.sort_by(f64::total_cmp);
+
+        let len = samples.len();
+        let at = |i: usize| nanos(samples.get(i).copied().unwrap_or_default());
+
+        #[allow(clippy::cast_precision_loss)]
+        let n = len.max(1) as f64;
+        let mean = samples.iter().sum::<f64>() / n;
+        let variance = samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / n;
+
+        #[allow(
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss,
+            clippy::cast_precision_loss
+        )]
+        let p95 = ((len as f64 * 0.95).ceil() as usize).saturating_sub(1);
+
+        Self {
+            iterations: u32::try_from(len).unwrap_or(u32::MAX),
+            mean: nanos(mean),
+            median: at(len / 2),
+            p95: at(p95),
+            min: at(0),
+            max: at(len.saturating_sub(1)),
+            std_dev: nanos(variance.sqrt()),
+            heap_growth,
+        }
+    }
+}
+
+/// Converts a sample in nanoseconds to a duration
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn nanos(ns: f64) -> Duration {
+    Duration::from_nanos(ns.max(0.0).round() as u64)
+}
+
+/// Runs a benchmark, see [`crate::Runtime::bench_function`]
+pub(crate) fn bench_function(
+    runtime: &mut Runtime,
+    module_context: Option<&ModuleHandle>,
+    name: &str,
+    args: &impl serde::Serialize,
+    options: BenchOptions,
+) -> Result<BenchStats, Error> {
+    let function: Function = runtime.get_value(module_context, name)?;
+    let harness: Function = runtime.eval(HARNESS)?;
+
+    let state = runtime.deno_runtime().op_state();
+    state.try_borrow_mut()?.put(BenchClock(Instant::now()));
+
+    let result = (|| {
+        harness.call::<Vec<f64>>(
+            runtime,
+            module_context,
+            &(&function, args, options.warmup_iterations),
+        )?;
+
+        let heap_before = used_heap_size(runtime);
+        let samples = harness.call::<Vec<f64>>(
+            runtime,
+            module_context,
+            &(&function, args, options.iterations),
+        )?;
+        let heap_growth = used_heap_size(runtime).checked_sub(heap_before);
+
+        Ok(BenchStats::from_samples(samples, heap_growth))
+    })();
+
+    state.try_borrow_mut()?.try_take::<BenchClock>();
+    result
+}
+
+/// Returns the current v8 heap usage of a runtime, in bytes
+pub(crate) fn used_heap_size(runtime: &mut Runtime) -> usize {
+    let mut stats = v8::HeapStatistics::default();
+    runtime
+        .deno_runtime()
+        .v8_isolate()
+        .get_heap_statistics(&mut stats);
+    stats.used_heap_size()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{json_args, Module, RuntimeOptions};
+
+    #[test]
+    fn test_stats() {
+        let samples = (1..=100).map(|i| f64::from(i) * 1000.0).collect();
+        let stats = BenchStats::from_samples(samples, None);
+        assert_eq!(stats.iterations, 100);
+        assert_eq!(stats.min, Duration::from_micros(1));
+        assert_eq!(stats.max, Duration::from_micros(100));
+        assert_eq!(stats.p95, Duration::from_micros(95));
+        assert_eq!(stats.mean, Duration::from_nanos(50_500));
+    }
+
+    #[test]
+    fn test_bench_function() {
+        let module = Module::new(
+            "test.js",
+            "
+            export const sum = (n) => { let s = 0; for (let i = 0; i < n; i++) s += i; return s; };
+            export const sleep = () => new Promise((r) => setTimeout(r, 1));
+        ",
+        );
+
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let handle = runtime.load_module(&module).unwrap();
+
+        let options = BenchOptions {
+            warmup_iterations: 2,
+            iterations: 20,
+        };
+        let stats = runtime
+            .bench_function(Some(&handle), "sum", json_args!(1000), options)
+            .unwrap();
+        assert_eq!(stats.iterations, 20);
+        assert!(stats.min <= stats.median && stats.median <= stats.p95 && stats.p95 <= stats.max);
+
+        // Promises are awaited
+        let stats = runtime
+            .bench_function(Some(&handle), "sleep", json_args!(), options)
+            .unwrap();
+        assert!(stats.min >= Duration::from_millis(1));
+
+        // The clock is only available while benchmarking
+        runtime
+            .eval::<f64>("Deno.core.ops.op_bench_now()")
+            .expect_err("Clock was available outside of a benchmark");
+    }
+}
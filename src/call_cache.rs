@@ -0,0 +1,118 @@
+use deno_core::{serde_json, ModuleId};
+use std::collections::HashMap;
+
+/// Identifies a call to a pure function - the module it was called from, its name, and its arguments as JSON
+type CallKey = (ModuleId, String, String);
+
+/// Results of calls to pure functions, see [`crate::Runtime::call_cached`]
+///
+/// Once full, the least recently used result is discarded for each new one
+pub(crate) struct CallCache {
+    capacity: usize,
+    ticks: u64,
+    entries: HashMap<CallKey, (u64, serde_json::Value)>,
+}
+
+impl CallCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            ticks: 0,
+            entries: HashMap::new(),
+        }
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.ticks += 1;
+        self.ticks
+    }
+
+    /// Returns the cached result of a call, marking it as recently used
+    pub(crate) fn get(
+        &mut self,
+        module: ModuleId,
+        name: &str,
+        args: &str,
+    ) -> Option<serde_json::Value> {
+        let tick = self.tick();
+        let key = (module, name.to_string(), args.to_string());
+        let (last_used, value) = self.entries.get_mut(&key)?;
+        *last_used = tick;
+        Some(value.clone())
+    }
+
+    /// Caches the result of a call, discarding the least recently used result if the cache is full
+    pub(crate) fn insert(
+        &mut self,
+        module: ModuleId,
+        name: &str,
+        args: &str,
+        value: serde_json::Value,
+    ) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let key = (module, name.to_string(), args.to_string());
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.capacity {
+            let lru = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (last_used, _))| *last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(lru) = lru {
+                self.entries.remove(&lru);
+            }
+        }
+
+        let tick = self.tick();
+        self.entries.insert(key, (tick, value));
+    }
+
+    /// Discards the cached results of calls into a module - such as when it is loaded again
+    pub(crate) fn invalidate(&mut self, module: ModuleId) {
+        self.entries.retain(|(id, _, _), _| *id != module);
+    }
+
+    /// Discards every cached result
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// The number of results cached
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CallCache;
+    use deno_core::serde_json::json;
+
+    #[test]
+    fn test_call_cache_eviction() {
+        let mut cache = CallCache::new(2);
+        cache.insert(1, "f", "[1]", json!(1));
+        cache.insert(1, "f", "[2]", json!(2));
+
+        // Using the first result makes the second the least recently used
+        assert_eq!(cache.get(1, "f", "[1]"), Some(json!(1)));
+        cache.insert(1, "f", "[3]", json!(3));
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(1, "f", "[2]"), None);
+        assert_eq!(cache.get(1, "f", "[1]"), Some(json!(1)));
+        assert_eq!(cache.get(1, "f", "[3]"), Some(json!(3)));
+
+        cache.insert(2, "f", "[1]", json!(4));
+        cache.invalidate(1);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(2, "f", "[1]"), Some(json!(4)));
+
+        let mut disabled = CallCache::new(0);
+        disabled.insert(1, "f", "[]", json!(null));
+        assert_eq!(disabled.len(), 0);
+    }
+}
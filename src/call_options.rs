@@ -0,0 +1,70 @@
+//! Per-call options, see [`crate::Runtime::call_function_with_options`]
+use std::sync::Arc;
+
+/// Limits applied to a single call, see [`crate::Runtime::call_function_with_options`]
+///
+/// Not `Copy`, since it can hold a [`HeapGrowthCallback`] - clone it to reuse the same options
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CallOptions {
+    /// Maximum execution cost of the call, as measured by [`crate::RuntimeOptions::metering`]
+    ///
+    /// The call fails with [`crate::Error::CostExceeded`] once the limit is exceeded
+    /// Since the cost does not depend on the speed of the host, the same call always fails at the same point
+    ///
+    /// Requires metering to be enabled
+    ///
+    /// Default: `None` (no limit)
+    pub max_cost: Option<u64>,
+
+    /// Maximum growth of the v8 heap during the call, in bytes
+    ///
+    /// Unlike [`crate::RuntimeOptions::max_heap_size`], which caps the whole isolate, this only limits what a single call adds
+    /// The heap is checked whenever the garbage collector runs during the call, and once more after it returns -
+    /// the call fails with [`crate::Error::HeapGrowthExceeded`], and the runtime remains usable
+    ///
+    /// Since garbage is only collected as the heap fills up, short-lived allocations can count towards the limit
+    ///
+    /// Default: `None` (no limit)
+    pub max_heap_growth: Option<usize>,
+
+    /// Called when the call reaches [`CallOptions::max_heap_growth`], see [`HeapGrowthCallback`]
+    ///
+    /// Default: `None` - the call fails as soon as the limit is reached
+    pub on_heap_growth_limit: Option<HeapGrowthCallback>,
+}
+
+/// Decides what happens when a call reaches its [`CallOptions::max_heap_growth`]
+///
+/// The callback receives the growth of the heap and the current limit, both in bytes,
+/// and returns a new, higher limit to let the call continue - or `None` to stop it
+///
+/// It runs during garbage collection, so it should be quick, and it cannot call into the runtime
+#[derive(Clone)]
+pub struct HeapGrowthCallback(Arc<dyn Fn(usize, usize) -> Option<usize> + Send + Sync>);
+
+impl HeapGrowthCallback {
+    /// Create a new callback
+    #[must_use]
+    pub fn new(callback: impl Fn(usize, usize) -> Option<usize> + Send + Sync + 'static) -> Self {
+        Self(Arc::new(callback))
+    }
+
+    /// Returns the new limit for a call that has grown the heap by `growth` bytes, or `None` to stop it
+    pub(crate) fn call(&self, growth: usize, limit: usize) -> Option<usize> {
+        (self.0)(growth, limit).filter(|new_limit| *new_limit > growth)
+    }
+}
+
+impl std::fmt::Debug for HeapGrowthCallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("HeapGrowthCallback").finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for HeapGrowthCallback {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for HeapGrowthCallback {}
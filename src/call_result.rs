@@ -0,0 +1,101 @@
+//! A call's return value, together with what it printed and what it cost, see [`crate::Runtime::call_function_with_result`]
+use crate::{Error, Runtime};
+use std::time::{Duration, Instant};
+
+/// Output printed to stdout while a call is recorded
+/// Only present in the `OpState` during [`crate::Runtime::call_function_with_result`]
+pub(crate) struct ConsoleCapture(pub String);
+
+/// The result of [`crate::Runtime::call_function_with_result`] or [`crate::Runtime::call_entrypoint_with_result`]
+///
+/// `T` can be a tuple, to receive a function returning several values as an array:
+/// `[count, name]` deserializes into `CallResult<(u32, String)>`
+#[derive(Clone, Debug, PartialEq)]
+pub struct CallResult<T> {
+    /// The value returned by the function, once resolved
+    pub value: T,
+
+    /// Everything printed to stdout during the call, such as by `console.log`
+    /// Output to stderr is not captured
+    pub stdout: String,
+
+    /// Time taken by the call, including the event loop
+    pub duration: Duration,
+
+    /// Resource usage of the call
+    pub stats: CallStats,
+}
+
+/// Resource usage measured by [`crate::Runtime::call_function_with_result`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CallStats {
+    /// The v8 heap usage after the call, in bytes
+    pub heap_used: usize,
+
+    /// Growth of the v8 heap across the call, in bytes
+    /// None if the heap shrank, which means a garbage collection ran during the call
+    pub heap_growth: Option<usize>,
+
+    /// Execution cost of the call, as measured by [`crate::RuntimeOptions::metering`]
+    /// None unless metering is enabled
+    pub cost: Option<u64>,
+}
+
+/// Records a call, between [`CallRecorder::start`] and [`CallRecorder::finish`]
+pub(crate) struct CallRecorder {
+    started: Instant,
+    heap_before: usize,
+    metering: bool,
+}
+
+impl CallRecorder {
+    /// Starts capturing output, and resets the execution cost if metering is enabled
+    pub(crate) fn start(runtime: &mut Runtime, metering: bool) -> Result<Self, Error> {
+        if metering {
+            crate::metering::start(runtime, None)?;
+        }
+
+        let state = runtime.deno_runtime().op_state();
+        state.try_borrow_mut()?.put(ConsoleCapture(String::new()));
+
+        Ok(Self {
+            started: Instant::now(),
+            heap_before: crate::bench::used_heap_size(runtime),
+            metering,
+        })
+    }
+
+    /// Stops capturing output, and combines the measurements with the call's result
+    pub(crate) fn finish<T>(
+        self,
+        runtime: &mut Runtime,
+        result: Result<T, Error>,
+    ) -> Result<CallResult<T>, Error> {
+        let duration = self.started.elapsed();
+        let heap_used = crate::bench::used_heap_size(runtime);
+
+        let state = runtime.deno_runtime().op_state();
+        let stdout = state
+            .try_borrow_mut()?
+            .try_take::<ConsoleCapture>()
+            .map(|capture| capture.0)
+            .unwrap_or_default();
+
+        let cost = if self.metering {
+            Some(crate::metering::stop(runtime)?)
+        } else {
+            None
+        };
+
+        Ok(CallResult {
+            value: result?,
+            stdout,
+            duration,
+            stats: CallStats {
+                heap_used,
+                heap_growth: heap_used.checked_sub(self.heap_before),
+                cost,
+            },
+        })
+    }
+}
@@ -0,0 +1,329 @@
+//! Restricted views of a runtime, for modules with fewer privileges than the host - see [`Capabilities`]
+use crate::{ext::rustyscript::Meter, preemption::Preemption, Error};
+use std::{
+    collections::HashSet,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Restrictions applied to a module loaded with [`crate::Runtime::load_module_with_capabilities`]
+///
+/// The restrictions apply while the module is evaluated, and while a call made through its handle is running,
+/// allowing one runtime to host trusted modules alongside less trusted ones
+///
+/// Functions that are not permitted appear not to exist to the module - calls fail with [`Error::ValueNotCallable`]
+///
+/// Restrictions follow the call, not the code - they are not a sandbox:
+/// - The module's functions run with the capabilities of whichever call is running when they are called.
+///   A function it exports, called by another module or through another handle, is not restricted
+/// - Callbacks the module schedules, such as timers and promise handlers, are only restricted
+///   if they run before the call through its handle returns
+/// - The module shares globals, prototypes and the event loop with every other module in the runtime,
+///   so it can still affect them. Use a separate [`crate::Runtime`] for code that must be isolated
+///
+/// ```rust
+/// use rustyscript::{ json_args, Capabilities, Module, Runtime, serde_json::Value };
+/// use std::time::Duration;
+///
+/// # fn main() -> Result<(), rustyscript::Error> {
+/// let mut runtime = Runtime::new(Default::default())?;
+/// runtime.register_function("log", |_| Ok(Value::Null))?;
+/// runtime.register_function("delete_everything", |_| Ok(Value::Null))?;
+///
+/// let module = Module::new("plugin.js", "
+///     export const run = () => rustyscript.functions.delete_everything();
+/// ");
+/// let capabilities = Capabilities {
+///     functions: Some(["log".to_string()].into()),
+///     timeout: Some(Duration::from_secs(1)),
+///     ..Default::default()
+/// };
+/// let plugin = runtime.load_module_with_capabilities(&module, capabilities)?;
+///
+/// let result = runtime.call_function::<Value>(Some(&plugin), "run", json_args!());
+/// assert!(result.is_err());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Names of the registered rust functions the module may call, or `None` to allow all of them
+    ///
+    /// Applies to functions registered with [`crate::Runtime::register_function`], and its async variants
+    ///
+    /// Default: `None`
+    pub functions: Option<HashSet<String>>,
+
+    /// Maximum time each call through the module's handle may run, if lower than [`crate::RuntimeOptions::timeout`]
+    ///
+    /// Synchronous loops are only interrupted if [`crate::RuntimeOptions::preemption_interval`] is set
+    ///
+    /// Default: `None`
+    pub timeout: Option<Duration>,
+
+    /// Maximum execution cost of each call through the module's handle, if lower than the call's own limit
+    ///
+    /// Calls exceeding it fail with [`Error::CostExceeded`] - see [`crate::CallOptions::max_cost`]
+    /// Requires [`crate::RuntimeOptions::metering`]
+    ///
+    /// Default: `None`
+    pub max_cost: Option<u64>,
+}
+
+impl Capabilities {
+    /// Returns true if the module may call the registered rust function with the given name
+    #[must_use]
+    pub fn permits_function(&self, name: &str) -> bool {
+        self.functions
+            .as_ref()
+            .is_none_or(|functions| functions.contains(name))
+    }
+
+    /// Checks that the limits can be enforced by a runtime
+    pub(crate) fn validate(&self, metering: bool) -> Result<(), Error> {
+        if self.max_cost.is_some() && !metering {
+            return Err(Error::Runtime(
+                "Capabilities::max_cost requires RuntimeOptions::metering".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The capabilities of the module whose call is running, kept in the runtime's `OpState`
+#[derive(Default)]
+pub(crate) struct ActiveCapabilities(pub Option<Arc<Capabilities>>);
+
+impl ActiveCapabilities {
+    /// Returns true if the running call may use the registered rust function with the given name
+    pub fn permits_function(state: &deno_core::OpState, name: &str) -> bool {
+        state
+            .try_borrow::<Self>()
+            .and_then(|active| active.0.as_ref())
+            .is_none_or(|capabilities| capabilities.permits_function(name))
+    }
+}
+
+/// Tracks a call made under a module's capabilities, so the previous state can be restored afterwards
+pub(crate) struct CapabilityScope {
+    previous: Option<Arc<Capabilities>>,
+    timeout: Option<Duration>,
+    start: Instant,
+    preemption: Option<(Preemption, u64)>,
+
+    /// The cost budget, the cost counted when the scope was entered, and the meter's limit before it
+    cost: Option<(u64, u64, Option<u64>)>,
+}
+
+impl CapabilityScope {
+    /// Applies a module's capabilities to the runtime, until the scope is exited
+    pub fn enter(
+        state: &mut deno_core::OpState,
+        capabilities: Option<Arc<Capabilities>>,
+        preemption: Option<Preemption>,
+    ) -> Self {
+        let timeout = capabilities.as_ref().and_then(|c| c.timeout);
+        let preemption = match (preemption, timeout) {
+            (Some(preemption), Some(timeout)) => {
+                let previous = preemption.arm(timeout);
+                Some((preemption, previous))
+            }
+            _ => None,
+        };
+
+        let budget = capabilities.as_ref().and_then(|c| c.max_cost);
+        let cost = match (budget, state.try_borrow_mut::<Meter>()) {
+            (Some(budget), Some(meter)) => Some((budget, meter.cost(), meter.restrict(budget))),
+            _ => None,
+        };
+
+        if !state.has::<ActiveCapabilities>() {
+            state.put(ActiveCapabilities::default());
+        }
+        let active = state.borrow_mut::<ActiveCapabilities>();
+        let previous = match capabilities {
+            Some(capabilities) => active.0.replace(capabilities),
+            None => active.0.clone(),
+        };
+
+        Self {
+            previous,
+            timeout,
+            start: Instant::now(),
+            preemption,
+            cost,
+        }
+    }
+
+    /// Runs a future, failing it if it outlasts the module's timeout
+    pub async fn limit<T>(
+        &self,
+        future: impl std::future::Future<Output = Result<T, Error>>,
+    ) -> Result<T, Error> {
+        match self.timeout {
+            Some(limit) => {
+                let remaining = limit.saturating_sub(self.start.elapsed());
+                tokio::time::timeout(remaining, future)
+                    .await
+                    .unwrap_or_else(|_| Err(self.timeout_error(limit)))
+            }
+            None => future.await,
+        }
+    }
+
+    /// Restores the capabilities in place before the scope was entered
+    /// Replaces the result with a timeout error if the call was preempted
+    pub fn exit<T>(
+        self,
+        state: &mut deno_core::OpState,
+        result: Result<T, Error>,
+    ) -> Result<T, Error> {
+        state
+            .borrow_mut::<ActiveCapabilities>()
+            .0
+            .clone_from(&self.previous);
+
+        // Checked here as well as by the meter, so the call fails even if the script caught the error
+        let cost_exceeded = self.cost.and_then(|(budget, start, previous)| {
+            let meter = state.borrow_mut::<Meter>();
+            meter.restore(previous);
+            (meter.cost().saturating_sub(start) > budget).then_some(budget)
+        });
+
+        if let Some((preemption, previous)) = &self.preemption {
+            if preemption.disarm(*previous) {
+                if let Some(limit) = self.timeout {
+                    return Err(self.timeout_error(limit));
+                }
+            }
+        }
+
+        match cost_exceeded {
+            Some(limit) => Err(Error::CostExceeded { limit }),
+            None => result,
+        }
+    }
+
+    fn timeout_error(&self, limit: Duration) -> Error {
+        Error::Timeout {
+            elapsed: self.start.elapsed(),
+            limit,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{json_args, Module, Runtime, RuntimeOptions};
+    use deno_core::serde_json::Value;
+
+    #[test]
+    fn test_capabilities() {
+        let mut runtime = Runtime::new(RuntimeOptions {
+            preemption_interval: Some(Duration::from_millis(5)),
+            ..Default::default()
+        })
+        .unwrap();
+        runtime
+            .register_function("read", |_| Ok(Value::from("data")))
+            .unwrap();
+        runtime
+            .register_function("write", |_| Ok(Value::Null))
+            .unwrap();
+
+        let code = "
+            export const read = () => rustyscript.functions.read();
+            export const write = () => rustyscript.functions.write();
+            export const later = async () => {
+                await new Promise((r) => setTimeout(r, 10));
+                return rustyscript.functions.write();
+            };
+            export const spin = () => { for (;;) {} };
+        ";
+        let trusted = runtime
+            .load_module(&Module::new("trusted.js", code))
+            .unwrap();
+        let untrusted = runtime
+            .load_module_with_capabilities(
+                &Module::new("untrusted.js", code),
+                Capabilities {
+                    functions: Some(["read".to_string()].into()),
+                    timeout: Some(Duration::from_millis(50)),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert!(untrusted.capabilities().is_some());
+
+        let value: String = runtime
+            .call_function(Some(&untrusted), "read", json_args!())
+            .unwrap();
+        assert_eq!(value, "data");
+        for name in ["write", "later"] {
+            let e = runtime
+                .call_function::<Value>(Some(&untrusted), name, json_args!())
+                .expect_err("Function was not restricted");
+            assert!(e.to_string().contains("write is not a function"), "{e}");
+        }
+
+        let e = runtime
+            .call_function::<Value>(Some(&untrusted), "spin", json_args!())
+            .expect_err("Timeout was not applied");
+        assert!(
+            matches!(e, Error::Timeout { limit, .. } if limit == Duration::from_millis(50)),
+            "{e}"
+        );
+
+        // Restrictions do not leak into calls through other handles
+        runtime
+            .call_function::<Value>(Some(&trusted), "later", json_args!())
+            .unwrap();
+
+        // They follow the call, not the code - the untrusted module's functions are not restricted elsewhere
+        let write: crate::js_value::Function =
+            runtime.get_value(Some(&untrusted), "write").unwrap();
+        runtime
+            .call_stored_function::<Value>(Some(&trusted), &write, json_args!())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_capabilities_cost() {
+        let code = "export const spin = (n) => { for (let i = 0; i < n; i++) {} };";
+        let capabilities = Capabilities {
+            max_cost: Some(100),
+            ..Default::default()
+        };
+
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        runtime
+            .load_module_with_capabilities(&Module::new("limited.js", code), capabilities.clone())
+            .expect_err("max_cost was accepted without metering");
+
+        let mut runtime = Runtime::new(RuntimeOptions {
+            metering: true,
+            ..Default::default()
+        })
+        .unwrap();
+        let limited = runtime
+            .load_module_with_capabilities(&Module::new("limited.js", code), capabilities)
+            .unwrap();
+        let unlimited = runtime
+            .load_module(&Module::new("unlimited.js", code))
+            .unwrap();
+
+        runtime
+            .call_function::<Value>(Some(&limited), "spin", json_args!(10))
+            .unwrap();
+        let e = runtime
+            .call_function::<Value>(Some(&limited), "spin", json_args!(1000))
+            .expect_err("Cost limit was not applied");
+        assert!(matches!(e, Error::CostExceeded { limit: 100 }), "{e}");
+
+        // The limit only lasts as long as the call
+        runtime
+            .call_function::<Value>(Some(&unlimited), "spin", json_args!(1000))
+            .unwrap();
+    }
+}
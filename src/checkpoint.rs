@@ -0,0 +1,123 @@
+//! Checkpoints of a warmed-up runtime, see [`crate::SnapshotBuilder::into_checkpoint`]
+use crate::{Error, Module, ModuleHandle, Runtime, RuntimeOptions};
+use deno_core::ModuleId;
+
+/// The state of a runtime after its initialization code has run, which can be restored many times
+///
+/// Restoring a checkpoint creates a new runtime from a v8 snapshot, with every module already loaded and
+/// evaluated, and every global already set - so expensive initialization code does not need to run again
+///
+/// Each restored runtime is independent, and changes made to one do not affect the others
+///
+/// The snapshot is kept in memory for the rest of the program, since runtimes can only start from a `'static` snapshot
+/// Checkpoints are therefore intended to be created once, and restored as often as needed
+///
+/// ```rust
+/// use rustyscript::{ json_args, Module, SnapshotBuilder };
+///
+/// # fn main() -> Result<(), rustyscript::Error> {
+/// let module = Module::new("counter.js", "
+///     let count = 0;
+///     export const increment = () => ++count;
+/// ");
+///
+/// let mut builder = SnapshotBuilder::new(Default::default())?;
+/// let handle = builder.load_module(&module)?;
+/// builder.call_function::<i64>(Some(&handle), "increment", json_args!())?;
+/// let checkpoint = builder.into_checkpoint(&[handle]);
+///
+/// let (mut runtime, handles) = checkpoint.restore(Default::default())?;
+/// let count: i64 = runtime.call_function(Some(&handles[0]), "increment", json_args!())?;
+/// assert_eq!(count, 2);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Checkpoint {
+    snapshot: &'static [u8],
+    modules: Vec<(Module, ModuleId)>,
+}
+
+impl Checkpoint {
+    /// Create a checkpoint from a snapshot, and the modules loaded into it
+    pub(crate) fn new(snapshot: Box<[u8]>, handles: &[ModuleHandle]) -> Self {
+        Self {
+            snapshot: Box::leak(snapshot),
+            modules: handles
+                .iter()
+                .map(|handle| (handle.module().clone(), handle.id()))
+                .collect(),
+        }
+    }
+
+    /// Returns the v8 snapshot underlying the checkpoint
+    #[must_use]
+    pub fn snapshot(&self) -> &'static [u8] {
+        self.snapshot
+    }
+
+    /// Create a new runtime from the checkpoint
+    ///
+    /// Returns the runtime, and a handle for each of the modules given to [`crate::SnapshotBuilder::into_checkpoint`], in the same order
+    ///
+    /// The options must provide the same extensions as those used to create the checkpoint,
+    /// and any [`RuntimeOptions::startup_snapshot`] is replaced by the checkpoint's snapshot
+    ///
+    /// # Errors
+    /// Will return an error if the runtime cannot be created,
+    /// or if a module's entrypoint cannot be found
+    pub fn restore(&self, options: RuntimeOptions) -> Result<(Runtime, Vec<ModuleHandle>), Error> {
+        let mut runtime = Runtime::new(RuntimeOptions {
+            startup_snapshot: Some(self.snapshot),
+            ..options
+        })?;
+
+        let handles = self
+            .modules
+            .iter()
+            .map(|(module, id)| runtime.restore_module_handle(module, *id))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok((runtime, handles))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{json_args, SnapshotBuilder};
+
+    #[test]
+    fn test_checkpoint() {
+        let module = Module::new(
+            "counter.js",
+            "
+            globalThis.initialized = (globalThis.initialized ?? 0) + 1;
+            let count = 0;
+            export const increment = () => ++count;
+            export default () => count;
+        ",
+        );
+
+        let mut builder = SnapshotBuilder::new(RuntimeOptions::default()).unwrap();
+        let handle = builder.load_module(&module).unwrap();
+        builder
+            .call_function::<i64>(Some(&handle), "increment", json_args!())
+            .unwrap();
+        let checkpoint = builder.into_checkpoint(&[handle]);
+
+        let (mut a, a_handles) = checkpoint.restore(RuntimeOptions::default()).unwrap();
+        let (mut b, b_handles) = checkpoint.restore(RuntimeOptions::default()).unwrap();
+
+        // State carries over, without the initialization code running again
+        let count: i64 = a
+            .call_function(Some(&a_handles[0]), "increment", json_args!())
+            .unwrap();
+        assert_eq!(count, 2);
+        let initialized: i64 = a.eval("globalThis.initialized").unwrap();
+        assert_eq!(initialized, 1);
+
+        // Restored runtimes are independent
+        let count: i64 = b.call_entrypoint(&b_handles[0], json_args!()).unwrap();
+        assert_eq!(count, 1);
+    }
+}
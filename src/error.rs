@@ -28,6 +28,99 @@ impl Default for ErrorFormattingOptions {
     }
 }
 
+/// A structured error, returned by a rust function to be thrown in javascript - see [`JsErrorBuilder`]
+///
+/// Javascript receives an instance of `rustyscript.HostError`, a subclass of `Error`,
+/// with the same `name`, `message`, `code` and `data`
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ThrownError {
+    /// The error's name, such as `ValidationError`
+    pub name: String,
+
+    /// The error's message
+    pub message: String,
+
+    /// An optional machine-readable code, such as `E_INVALID`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+
+    /// Optional extra data describing the error
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<deno_core::serde_json::Value>,
+}
+
+/// Builds a [`ThrownError`], for rust functions to return structured errors to javascript
+///
+/// ```rust
+/// use rustyscript::{ error::JsErrorBuilder, serde_json::json, Runtime, RuntimeOptions };
+///
+/// # fn main() -> Result<(), rustyscript::Error> {
+/// let mut runtime = Runtime::new(RuntimeOptions::default())?;
+/// runtime.register_function("validate", |_| {
+///     Err(JsErrorBuilder::new("age must be positive")
+///         .with_name("ValidationError")
+///         .with_code("E_RANGE")
+///         .with_data(json!({ "field": "age" }))
+///         .build())
+/// })?;
+///
+/// let caught: String = runtime.eval("(() => {
+///     try { rustyscript.functions.validate(-1) }
+///     catch (e) { return `${e instanceof rustyscript.HostError} ${e.name} ${e.code} ${e.data.field}` }
+/// })()")?;
+/// assert_eq!(caught, "true ValidationError E_RANGE age");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct JsErrorBuilder(ThrownError);
+
+impl JsErrorBuilder {
+    /// Starts an error with the given message, named `Error`
+    #[must_use]
+    pub fn new(message: impl Into<String>) -> Self {
+        Self(ThrownError {
+            name: "Error".to_string(),
+            message: message.into(),
+            code: None,
+            data: None,
+        })
+    }
+
+    /// Sets the error's name
+    #[must_use]
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.0.name = name.into();
+        self
+    }
+
+    /// Sets the error's machine-readable code
+    #[must_use]
+    pub fn with_code(mut self, code: impl Into<String>) -> Self {
+        self.0.code = Some(code.into());
+        self
+    }
+
+    /// Attaches extra data to the error
+    #[must_use]
+    pub fn with_data(mut self, data: deno_core::serde_json::Value) -> Self {
+        self.0.data = Some(data);
+        self
+    }
+
+    /// Finishes the error
+    #[must_use]
+    pub fn build(self) -> Error {
+        Error::Thrown(self.0)
+    }
+}
+
+impl From<JsErrorBuilder> for Error {
+    fn from(builder: JsErrorBuilder) -> Self {
+        builder.build()
+    }
+}
+
 /// Represents the errors that can occur during execution of a module
 #[derive(Error, Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub enum Error {
@@ -51,14 +144,43 @@ pub enum Error {
     #[error("value could not be deserialized: {0}")]
     JsonDecode(String),
 
+    /// Triggers when a value being decoded contains a circular reference
+    /// `path` is the location of the reference, and `target` the location it refers back to
+    #[error(
+        "value could not be deserialized: circular reference at `{path}` refers back to `{target}`"
+    )]
+    CircularReference {
+        /// Path to the property containing the circular reference, such as `$.a[0].parent`
+        path: String,
+
+        /// Path to the value being referred back to
+        target: String,
+    },
+
+    /// Triggers when a value being decoded is nested more deeply than [`crate::RuntimeOptions::max_decode_depth`]
+    #[error("value could not be deserialized: nested more than {0} levels deep")]
+    DecodeDepthExceeded(usize),
+
     /// Triggers when a module could not be loaded from the filesystem
     #[error("{0}")]
     ModuleNotFound(String),
 
+    /// Triggers when one or more files could not be loaded by [`crate::Module::load_dir_with`]
+    /// Each failure names the file or directory, and the reason it could not be read
+    #[error("{} file(s) could not be loaded: {}", failures.len(), failures.iter().map(|(path, e)| format!("{}: {e}", path.display())).collect::<Vec<_>>().join(", "))]
+    ModuleLoadFailed {
+        /// The files or directories that could not be read, and the reason for each
+        failures: Vec<(std::path::PathBuf, String)>,
+    },
+
     /// Triggers when attempting to use a worker that has already been shutdown
     #[error("This worker has been destroyed")]
     WorkerHasStopped,
 
+    /// Triggers when no worker becomes available within the time given, such as the `checkout_timeout` of a `ScriptServer`
+    #[error("No worker became available within {0:?}")]
+    WorkerUnavailable(std::time::Duration),
+
     /// Triggers on runtime issues during execution of a module
     #[error("{0}")]
     Runtime(String),
@@ -67,16 +189,109 @@ pub enum Error {
     #[error("{0}")]
     JsError(#[from] deno_core::error::JsError),
 
+    /// Triggers when a promise is rejected, with the reason it was rejected with
+    /// The reason is kept as the original javascript value, along with a JSON copy - see [`crate::js_value::Rejection`]
+    #[error("Promise rejected: {0}")]
+    PromiseRejected(crate::js_value::Rejection),
+
     /// Triggers when a module times out before finishing
-    #[error("Module timed out: {0}")]
-    Timeout(String),
+    /// `elapsed` is the time spent before the call was interrupted, and `limit` the configured timeout
+    #[error("Module timed out after {elapsed:?} (limit: {limit:?})")]
+    Timeout {
+        /// Time spent executing before the call was interrupted
+        elapsed: std::time::Duration,
+
+        /// The timeout configured for the runtime
+        limit: std::time::Duration,
+    },
 
     /// Triggers when the heap (via `max_heap_size`) is exhausted during execution
-    #[error("Heap exhausted")]
-    HeapExhausted,
+    /// Sizes are in bytes
+    #[error("Heap exhausted: {used} bytes in use (limit: {limit} bytes)")]
+    HeapExhausted {
+        /// Heap size when the near-heap-limit callback fired
+        used: usize,
+
+        /// The configured `max_heap_size`
+        limit: usize,
+    },
+
+    /// Triggers when a call grows the heap by more than its `max_heap_growth` (via [`crate::CallOptions`])
+    /// Sizes are in bytes
+    #[error("Heap grew by {growth} bytes during the call (limit: {limit} bytes)")]
+    HeapGrowthExceeded {
+        /// Growth of the heap when the limit was enforced
+        growth: usize,
+
+        /// The limit in effect, after any increase by the near-limit callback
+        limit: usize,
+    },
+
+    /// Triggers when a call exceeds its `max_cost` (via [`crate::CallOptions`] or [`crate::Capabilities`])
+    #[error("Execution cost exceeded the limit of {limit}")]
+    CostExceeded {
+        /// The configured `max_cost`
+        limit: u64,
+    },
+
+    /// Triggers when a script calls `Deno.exit` or `process.exit`, with the exit code it gave
+    /// The runtime is terminated, and every later call fails the same way - see [`crate::ExitPolicy`]
+    #[error("Script exited with code {0}")]
+    ScriptExited(i32),
+
+    /// A structured error returned by a rust function, thrown in javascript with its fields - see [`JsErrorBuilder`]
+    #[error("{}: {}", .0.name, .0.message)]
+    Thrown(ThrownError),
+
+    /// Triggers when a module's top-level await stops making progress (via `module_stall_timeout`)
+    /// Usually a promise waiting on something that will never happen, such as a host function that is never called
+    #[error("Top-level await in {module} made no progress for {interval:?}\n{pending}")]
+    ModuleStalled {
+        /// The specifier of the module being evaluated
+        module: String,
+
+        /// The configured `module_stall_timeout`
+        interval: std::time::Duration,
+
+        /// A summary of the work that was still pending, see [`crate::PendingWork`]
+        pending: String,
+    },
 }
 
 impl Error {
+    /// Describes the error as it would be seen by javascript - an object with a `name` and `message`,
+    /// and the `code` and `data` of structured errors
+    ///
+    /// Errors thrown by javascript keep their original name and message
+    #[must_use]
+    pub fn as_js_value(&self) -> deno_core::serde_json::Value {
+        let thrown = match self {
+            Error::Thrown(thrown) => thrown.clone(),
+            Error::JsError(e) => ThrownError {
+                name: e.name.clone().unwrap_or_else(|| "Error".to_string()),
+                message: e
+                    .message
+                    .clone()
+                    .unwrap_or_else(|| e.exception_message.clone()),
+                code: None,
+                data: None,
+            },
+            Error::PromiseRejected(reason) => ThrownError {
+                name: "Error".to_string(),
+                message: self.to_string(),
+                code: None,
+                data: Some(reason.json().clone()),
+            },
+            e => ThrownError {
+                name: "Error".to_string(),
+                message: e.to_string(),
+                code: None,
+                data: None,
+            },
+        };
+        deno_core::serde_json::to_value(thrown).unwrap_or_default()
+    }
+
     /// Formats an error for display in a terminal
     /// If the error is a `JsError`, it will attempt to highlight the source line
     /// in this format:
@@ -211,14 +426,11 @@ map_error!(deno_core::anyhow::Error, |e| {
     }
 });
 
-map_error!(tokio::time::error::Elapsed, |e| {
-    Error::Timeout(e.to_string())
-});
 map_error!(tokio::task::JoinError, |e| {
-    Error::Timeout(e.to_string())
+    Error::Runtime(e.to_string())
 });
 map_error!(deno_core::futures::channel::oneshot::Canceled, |e| {
-    Error::Timeout(e.to_string())
+    Error::Runtime(e.to_string())
 });
 
 #[cfg(feature = "broadcast_channel")]
@@ -228,7 +440,72 @@ map_error!(deno_broadcast_channel::BroadcastChannelError, |e| {
 
 #[cfg(test)]
 mod test {
-    use crate::{error::ErrorFormattingOptions, Module, Runtime, RuntimeOptions, Undefined};
+    use super::{Error, JsErrorBuilder};
+    use crate::{
+        error::ErrorFormattingOptions,
+        json_args,
+        serde_json::{self, json},
+        Module, Runtime, RuntimeOptions, Undefined,
+    };
+
+    #[test]
+    fn test_thrown_errors() {
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        runtime
+            .register_function("check", |_| {
+                Err(JsErrorBuilder::new("out of range")
+                    .with_name("RangeCheck")
+                    .with_code("E_RANGE")
+                    .with_data(json!({ "max": 10 }))
+                    .build())
+            })
+            .unwrap();
+        runtime
+            .register_async_function("check_async", |_| {
+                Box::pin(async { Err(JsErrorBuilder::new("later").with_code("E_LATE").into()) })
+            })
+            .unwrap();
+
+        let module = Module::new(
+            "test.js",
+            "
+            const describe = (e) => [e instanceof rustyscript.HostError, e instanceof Error, e.name, e.message, e.code, e.data ?? null];
+            export const sync = () => { try { rustyscript.functions.check() } catch (e) { return describe(e) } };
+            export const later = async () => { try { await rustyscript.async_functions.check_async() } catch (e) { return describe(e) } };
+            export const rethrow = () => rustyscript.functions.check();
+            ",
+        );
+        let handle = runtime.load_module(&module).unwrap();
+
+        let value: serde_json::Value = runtime
+            .call_function(Some(&handle), "sync", json_args!())
+            .unwrap();
+        assert_eq!(
+            value,
+            json!([true, true, "RangeCheck", "out of range", "E_RANGE", { "max": 10 }])
+        );
+
+        let value: serde_json::Value = runtime
+            .call_function(Some(&handle), "later", json_args!())
+            .unwrap();
+        assert_eq!(value, json!([true, true, "Error", "later", "E_LATE", null]));
+
+        // Uncaught, the error reaches rust as a javascript error
+        let e = runtime
+            .call_function::<Undefined>(Some(&handle), "rethrow", json_args!())
+            .unwrap_err();
+        assert!(matches!(e, Error::JsError(_)), "{e}");
+        assert_eq!(
+            e.as_js_value(),
+            json!({ "name": "RangeCheck", "message": "out of range" })
+        );
+
+        let e: Error = JsErrorBuilder::new("bad").with_code("E_BAD").into();
+        assert_eq!(
+            e.as_js_value(),
+            json!({ "name": "Error", "message": "bad", "code": "E_BAD" })
+        );
+    }
 
     #[test]
     #[rustfmt::skip]
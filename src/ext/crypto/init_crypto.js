@@ -1,3 +1,4 @@
+import { core } from "ext:core/mod.js";
 import * as crypto from "ext:deno_crypto/00_crypto.js";
 
 import { applyToGlobal, nonEnumerable, readOnly } from 'ext:rustyscript/rustyscript.js';
@@ -7,3 +8,75 @@ applyToGlobal({
     Crypto: nonEnumerable(crypto.Crypto),
     SubtleCrypto: nonEnumerable(crypto.SubtleCrypto),
 });
+
+// When the host provides an rng - see `ExtensionOptions::crypto_rng` - secret keys are generated from it
+// `op_crypto_generate_key` draws from the OS directly, so the key is filled by `getRandomValues` and imported instead
+// Anything else, including invalid parameters, goes to the original so its errors are unchanged
+const AES_ALGORITHMS = ['AES-CTR', 'AES-CBC', 'AES-GCM', 'AES-KW'];
+const HMAC_BLOCK_SIZES = { 'SHA-1': 512, 'SHA-256': 512, 'SHA-384': 1024, 'SHA-512': 1024 };
+function secretKeyLength(algorithm) {
+    const name = (typeof algorithm === 'string' ? algorithm : algorithm?.name)?.toUpperCase?.();
+    if (AES_ALGORITHMS.includes(name)) {
+        return [128, 192, 256].includes(algorithm.length) ? algorithm.length : undefined;
+    }
+
+    if (name === 'HMAC') {
+        const hash = typeof algorithm.hash === 'string' ? algorithm.hash : algorithm.hash?.name;
+        const blockSize = HMAC_BLOCK_SIZES[hash?.toUpperCase?.()];
+        if (blockSize === undefined || algorithm.length === undefined) {
+            return blockSize;
+        }
+        const length = algorithm.length;
+        return Number.isInteger(length) && length > 0 && length % 8 === 0 && length <= 1024 ? length : undefined;
+    }
+}
+
+const { generateKey: originalGenerateKey, importKey } = crypto.SubtleCrypto.prototype;
+Object.defineProperty(crypto.SubtleCrypto.prototype, 'generateKey', {
+    ...Object.getOwnPropertyDescriptor(crypto.SubtleCrypto.prototype, 'generateKey'),
+    value: function generateKey(algorithm, extractable, keyUsages) {
+        const length = core.ops.op_crypto_host_rng() ? secretKeyLength(algorithm) : undefined;
+        if (length === undefined) {
+            return originalGenerateKey.call(this, algorithm, extractable, keyUsages);
+        }
+
+        const data = crypto.crypto.getRandomValues(new Uint8Array(length / 8));
+        return importKey.call(this, 'raw', data, algorithm, extractable, keyUsages);
+    },
+});
+
+// Moves keys between the host and scripts - see `Runtime::import_crypto_key` and `Runtime::export_crypto_key`
+// Key bytes cross as arrays, and OKP keys are converted to and from JSON Web Keys
+const encodeBase64Url = (bytes) => core.ops.op_crypto_base64url_encode(new Uint8Array(bytes));
+const decodeBase64Url = (text) => Array.from(core.ops.op_crypto_base64url_decode(text));
+Object.defineProperty(globalThis, '__rustyscript_import_crypto_key', {
+    value: (material, algorithm, extractable, usages) => {
+        let { format, data } = material;
+        if (format === 'okp') {
+            const jwk = { kty: 'OKP', crv: data.curve, x: encodeBase64Url(data.publicKey) };
+            if (data.privateKey) {
+                jwk.d = encodeBase64Url(data.privateKey);
+            }
+            format = 'jwk';
+            data = jwk;
+        } else if (format !== 'jwk') {
+            data = new Uint8Array(data);
+        }
+        return crypto.crypto.subtle.importKey(format, data, algorithm, extractable, usages);
+    },
+});
+Object.defineProperty(globalThis, '__rustyscript_export_crypto_key', {
+    value: async (key, format) => {
+        if (format === 'okp') {
+            const jwk = await crypto.crypto.subtle.exportKey('jwk', key);
+            if (jwk.kty !== 'OKP') {
+                throw new TypeError(`Cannot export a ${key.algorithm.name} key as an octet key pair`);
+            }
+            const privateKey = jwk.d === undefined ? null : decodeBase64Url(jwk.d);
+            return { format, data: { curve: jwk.crv, privateKey, publicKey: decodeBase64Url(jwk.x) } };
+        }
+
+        const data = await crypto.crypto.subtle.exportKey(format, key);
+        return { format, data: format === 'jwk' ? data : Array.from(new Uint8Array(data)) };
+    },
+});
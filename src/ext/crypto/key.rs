@@ -0,0 +1,143 @@
+//! Moves Web Crypto keys between the host and scripts, see [`crate::Runtime::import_crypto_key`]
+use deno_core::serde_json;
+use serde::{Deserialize, Serialize};
+
+/// Key material passed to [`crate::Runtime::import_crypto_key`], or returned by [`crate::Runtime::export_crypto_key`]
+///
+/// The variants match the formats of `SubtleCrypto.importKey`, plus [`KeyMaterial::Okp`]
+/// for Ed25519, X25519 and X448 keys given as raw bytes
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "format", content = "data", rename_all = "lowercase")]
+pub enum KeyMaterial {
+    /// Raw bytes - secret keys, or the public key of an elliptic curve key pair
+    Raw(Vec<u8>),
+
+    /// A DER encoded PKCS #8 private key
+    Pkcs8(Vec<u8>),
+
+    /// A DER encoded `SubjectPublicKeyInfo` public key
+    Spki(Vec<u8>),
+
+    /// A JSON Web Key, such as `{ "kty": "oct", "k": "..." }`
+    Jwk(serde_json::Value),
+
+    /// An octet key pair, as used by Ed25519, X25519 and X448
+    /// Imported as a JSON Web Key with `kty: "OKP"`
+    #[serde(rename_all = "camelCase")]
+    Okp {
+        /// The curve name, such as `Ed25519`
+        curve: String,
+
+        /// The private key bytes - None for a public key
+        private_key: Option<Vec<u8>>,
+
+        /// The public key bytes
+        public_key: Vec<u8>,
+    },
+}
+
+impl KeyMaterial {
+    /// Returns the format of this key material
+    #[must_use]
+    pub fn format(&self) -> KeyFormat {
+        match self {
+            Self::Raw(_) => KeyFormat::Raw,
+            Self::Pkcs8(_) => KeyFormat::Pkcs8,
+            Self::Spki(_) => KeyFormat::Spki,
+            Self::Jwk(_) => KeyFormat::Jwk,
+            Self::Okp { .. } => KeyFormat::Okp,
+        }
+    }
+}
+
+/// The format to export a key in, with [`crate::Runtime::export_crypto_key`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyFormat {
+    /// See [`KeyMaterial::Raw`]
+    Raw,
+
+    /// See [`KeyMaterial::Pkcs8`]
+    Pkcs8,
+
+    /// See [`KeyMaterial::Spki`]
+    Spki,
+
+    /// See [`KeyMaterial::Jwk`]
+    Jwk,
+
+    /// See [`KeyMaterial::Okp`] - only valid for Ed25519, X25519 and X448 keys
+    Okp,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{json_args, Module, Runtime, RuntimeOptions};
+
+    #[test]
+    fn test_crypto_key_bridge() {
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let module = runtime
+            .load_module(&Module::new(
+                "test.js",
+                "
+                export const sign = async (key, text) => {
+                    const signature = await crypto.subtle.sign('HMAC', key, new TextEncoder().encode(text));
+                    return Array.from(new Uint8Array(signature));
+                };
+                export const generate = async () => {
+                    const pair = await crypto.subtle.generateKey('Ed25519', true, ['sign', 'verify']);
+                    return pair.privateKey;
+                };
+                export const algorithm = (key) => key.algorithm.name;
+                ",
+            ))
+            .unwrap();
+
+        // A key provisioned by the host can be used, but not read, by scripts
+        let material = KeyMaterial::Raw(vec![0x2a; 32]);
+        let algorithm = serde_json::json!({ "name": "HMAC", "hash": "SHA-256" });
+        let key = runtime
+            .import_crypto_key(&material, &algorithm, false, &["sign"])
+            .unwrap();
+        let signature: Vec<u8> = runtime
+            .call_function(Some(&module), "sign", &(&key, "hello"))
+            .unwrap();
+        assert_eq!(signature.len(), 32);
+        runtime.export_crypto_key(&key, KeyFormat::Raw).unwrap_err();
+
+        // Keys generated by scripts can be exported if extractable
+        let generated: crate::js_value::Value = runtime
+            .call_function(Some(&module), "generate", json_args!())
+            .unwrap();
+        let exported = runtime
+            .export_crypto_key(&generated, KeyFormat::Okp)
+            .unwrap();
+        let KeyMaterial::Okp {
+            curve,
+            private_key,
+            public_key,
+        } = &exported
+        else {
+            panic!("Expected an OKP key, got {exported:?}");
+        };
+        assert_eq!(curve, "Ed25519");
+        assert_eq!(private_key.as_ref().map(Vec::len), Some(32));
+        assert_eq!(public_key.len(), 32);
+
+        let imported = runtime
+            .import_crypto_key(&exported, &serde_json::json!("Ed25519"), true, &["sign"])
+            .unwrap();
+        let name: String = runtime
+            .call_function(Some(&module), "algorithm", &(&imported,))
+            .unwrap();
+        assert_eq!(name, "Ed25519");
+        assert_eq!(
+            runtime
+                .export_crypto_key(&imported, KeyFormat::Okp)
+                .unwrap(),
+            exported
+        );
+    }
+}
@@ -1,15 +1,31 @@
 use super::ExtensionTrait;
 use deno_core::{extension, Extension};
+use std::sync::Arc;
+
+mod key;
+mod rng;
+pub use key::{KeyFormat, KeyMaterial};
+pub use rng::SecureRng;
 
 extension!(
     init_crypto,
     deps = [rustyscript],
+    ops = [rng::op_crypto_host_rng],
     esm_entry_point = "ext:init_crypto/init_crypto.js",
     esm = [ dir "src/ext/crypto", "init_crypto.js" ],
+    options = {
+        rng: Option<Arc<dyn SecureRng>>
+    },
+    middleware = rng::middleware,
+    state = |state, config| {
+        if let Some(rng) = config.rng {
+            state.put(rng::RngContainer(rng));
+        }
+    },
 );
-impl ExtensionTrait<()> for init_crypto {
-    fn init((): ()) -> Extension {
-        init_crypto::init_ops_and_esm()
+impl ExtensionTrait<Option<Arc<dyn SecureRng>>> for init_crypto {
+    fn init(rng: Option<Arc<dyn SecureRng>>) -> Extension {
+        init_crypto::init_ops_and_esm(rng)
     }
 }
 impl ExtensionTrait<Option<u64>> for deno_crypto::deno_crypto {
@@ -18,9 +34,13 @@ impl ExtensionTrait<Option<u64>> for deno_crypto::deno_crypto {
     }
 }
 
-pub fn extensions(seed: Option<u64>, is_snapshot: bool) -> Vec<Extension> {
+pub fn extensions(
+    seed: Option<u64>,
+    rng: Option<Arc<dyn SecureRng>>,
+    is_snapshot: bool,
+) -> Vec<Extension> {
     vec![
         deno_crypto::deno_crypto::build(seed, is_snapshot),
-        init_crypto::build((), is_snapshot),
+        init_crypto::build(rng, is_snapshot),
     ]
 }
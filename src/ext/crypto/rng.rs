@@ -0,0 +1,168 @@
+//! Draws the randomness of `deno_crypto` from an entropy source provided by the host
+//!
+//! `deno_crypto` only supports a fixed seed, so its random ops are replaced with these,
+//! which use the [`ExtensionOptions::crypto_rng`] of the runtime when one is set, and behave like the originals otherwise
+//!
+//! Key generation happens inside a single op that cannot be wrapped, so `init_crypto.js` generates
+//! AES and HMAC keys from `crypto.getRandomValues` instead, and imports them
+//!
+//! [`ExtensionOptions::crypto_rng`]: crate::ExtensionOptions::crypto_rng
+use crate::Error;
+use deno_core::{op2, OpDecl, OpState};
+use deno_crypto::rand::{self, rngs::StdRng, Rng};
+use std::sync::{Arc, Mutex};
+
+/// A source of cryptographically secure random bytes, for [`crate::ExtensionOptions::crypto_rng`]
+///
+/// Used by `crypto.getRandomValues`, `crypto.randomUUID`, and to generate AES and HMAC keys
+/// RSA, EC, Ed25519, X25519 and X448 key pairs, and the padding of RSA-OAEP, still use the OS entropy source
+///
+/// Implemented for a [`Mutex`] around any [`rand::RngCore`] marked as a [`rand::CryptoRng`]
+pub trait SecureRng: Send + Sync {
+    /// Fill `dest` with random bytes
+    ///
+    /// # Errors
+    /// Can fail if the entropy source is unavailable - the error is thrown into javascript
+    fn fill_bytes(&self, dest: &mut [u8]) -> Result<(), Error>;
+}
+
+impl<R> SecureRng for Mutex<R>
+where
+    R: rand::RngCore + rand::CryptoRng + Send,
+{
+    fn fill_bytes(&self, dest: &mut [u8]) -> Result<(), Error> {
+        let mut rng = self.lock().map_err(|e| Error::Runtime(e.to_string()))?;
+        rng.try_fill_bytes(dest)
+            .map_err(|e| Error::Runtime(e.to_string()))
+    }
+}
+
+/// The host's entropy source, stored in the op state
+#[derive(Clone)]
+pub(crate) struct RngContainer(pub Arc<dyn SecureRng>);
+
+/// Replaces the random ops of `deno_crypto` - see the module docs
+pub fn middleware(op: OpDecl) -> OpDecl {
+    match op.name {
+        "op_crypto_get_random_values" => op.with_implementation_from(&op_get_random_values()),
+        "op_crypto_random_uuid" => op.with_implementation_from(&op_random_uuid()),
+        _ => op,
+    }
+}
+
+/// Returns true if the runtime was given an entropy source
+/// `SubtleCrypto.generateKey` then creates AES and HMAC keys from `crypto.getRandomValues`
+#[op2(fast)]
+pub fn op_crypto_host_rng(state: &mut OpState) -> bool {
+    state.has::<RngContainer>()
+}
+
+/// Fills the buffer from the host's entropy source if there is one
+/// Otherwise from the seeded rng of `deno_crypto`, or the thread rng, like the original ops
+fn fill(state: &mut OpState, dest: &mut [u8]) -> Result<(), Error> {
+    if let Some(RngContainer(rng)) = state.try_borrow::<RngContainer>() {
+        rng.fill_bytes(dest)
+    } else if let Some(seeded) = state.try_borrow_mut::<StdRng>() {
+        seeded.fill(dest);
+        Ok(())
+    } else {
+        rand::thread_rng().fill(dest);
+        Ok(())
+    }
+}
+
+#[op2(fast)]
+fn op_get_random_values(
+    state: &mut OpState,
+    #[buffer] out: &mut [u8],
+) -> Result<(), deno_core::anyhow::Error> {
+    if out.len() > 65536 {
+        return Err(deno_crypto::Error::ArrayBufferViewLengthExceeded(out.len()).into());
+    }
+    fill(state, out)?;
+    Ok(())
+}
+
+#[op2]
+#[string]
+fn op_random_uuid(state: &mut OpState) -> Result<String, deno_core::anyhow::Error> {
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+    let mut bytes = [0u8; 16];
+    fill(state, &mut bytes)?;
+    bytes[6] = (bytes[6] & 0x0f) | 0x40; // Version 4
+    bytes[8] = (bytes[8] & 0x3f) | 0x80; // RFC 4122 variant
+
+    let mut uuid = String::with_capacity(36);
+    for (i, byte) in bytes.into_iter().enumerate() {
+        if matches!(i, 4 | 6 | 8 | 10) {
+            uuid.push('-');
+        }
+        uuid.push(char::from(HEX[usize::from(byte >> 4)]));
+        uuid.push(char::from(HEX[usize::from(byte & 0x0f)]));
+    }
+    Ok(uuid)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ExtensionOptions, Runtime, RuntimeOptions};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Fills every buffer with the same byte, counting the bytes requested
+    struct FixedRng(AtomicUsize);
+    impl SecureRng for FixedRng {
+        fn fill_bytes(&self, dest: &mut [u8]) -> Result<(), Error> {
+            self.0.fetch_add(dest.len(), Ordering::Relaxed);
+            dest.fill(0x2a);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_crypto_rng() {
+        let rng = Arc::new(FixedRng(AtomicUsize::new(0)));
+        let mut runtime = Runtime::new(RuntimeOptions {
+            extension_options: ExtensionOptions {
+                crypto_rng: Some(rng.clone()),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .unwrap();
+
+        let values: Vec<u8> = runtime
+            .eval("Array.from(crypto.getRandomValues(new Uint8Array(4)))")
+            .unwrap();
+        assert_eq!(values, [0x2a; 4]);
+
+        let uuid: String = runtime.eval("crypto.randomUUID()").unwrap();
+        assert_eq!(uuid, "2a2a2a2a-2a2a-4a2a-aa2a-2a2a2a2a2a2a");
+
+        let key: Vec<u8> = runtime
+            .eval(
+                "crypto.subtle.generateKey({ name: 'AES-GCM', length: 128 }, true, ['encrypt'])
+                    .then(key => crypto.subtle.exportKey('raw', key))
+                    .then(raw => Array.from(new Uint8Array(raw)))",
+            )
+            .unwrap();
+        assert_eq!(key, [0x2a; 16]);
+        assert_eq!(rng.0.load(Ordering::Relaxed), 4 + 16 + 16);
+
+        // Key pairs still come from the original op
+        let algorithm: String = runtime
+            .eval(
+                "crypto.subtle.generateKey({ name: 'ECDSA', namedCurve: 'P-256' }, true, ['sign', 'verify'])
+                    .then(pair => pair.privateKey.algorithm.name)",
+            )
+            .unwrap();
+        assert_eq!(algorithm, "ECDSA");
+
+        // Without a host rng, the original ops are used
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let values: Vec<u8> = runtime
+            .eval("Array.from(crypto.getRandomValues(new Uint8Array(32)))")
+            .unwrap();
+        assert_ne!(values, [0x2a; 32]);
+    }
+}
@@ -11,6 +11,10 @@ mod tty_unix;
 #[cfg(unix)]
 use tty_unix as tty;
 
+mod stdin;
+pub use stdin::StdinWriter;
+pub(crate) use stdin::{HostStdinResource, STDIN_RID};
+
 extension!(
     init_io,
     deps = [rustyscript],
@@ -0,0 +1,160 @@
+use crate::Error;
+use deno_core::{AsyncRefCell, AsyncResult, BufMutView, BufView, RcRef, Resource};
+use std::{
+    borrow::Cow,
+    pin::Pin,
+    rc::Rc,
+    task::{ready, Context, Poll},
+};
+use tokio::{
+    io::{AsyncRead, ReadBuf},
+    sync::mpsc,
+};
+
+/// The resource ID `deno_io` gives to stdin
+pub(crate) const STDIN_RID: deno_core::ResourceId = 0;
+
+/// Feeds bytes to a script's stdin, see [`crate::Runtime::stdin_writer`]
+///
+/// The writer can be cloned, and is `Send` - so input can be fed from another thread or task
+/// The script sees the end of its input once [`crate::Runtime::close_stdin`] is called, and every writer is dropped
+#[derive(Clone, Debug)]
+pub struct StdinWriter(mpsc::UnboundedSender<Vec<u8>>);
+
+impl StdinWriter {
+    /// Creates a writer, and the reader that receives its bytes
+    pub(crate) fn channel() -> (Self, ChannelReader) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let reader = ChannelReader {
+            receiver: rx,
+            pending: Vec::new(),
+        };
+        (Self(tx), reader)
+    }
+
+    /// Sends bytes to the script's stdin
+    ///
+    /// # Errors
+    /// Will return an error if the script's stdin has been replaced, or the runtime dropped
+    pub fn write(&self, bytes: impl Into<Vec<u8>>) -> Result<(), Error> {
+        self.0
+            .send(bytes.into())
+            .map_err(|_| Error::Runtime("The script's stdin has been closed".to_string()))
+    }
+
+    /// Returns true once the script's stdin has been replaced, or the runtime dropped
+    #[must_use]
+    pub fn is_closed(&self) -> bool {
+        self.0.is_closed()
+    }
+}
+
+/// Reads the bytes sent by a [`StdinWriter`]
+pub(crate) struct ChannelReader {
+    receiver: mpsc::UnboundedReceiver<Vec<u8>>,
+    pending: Vec<u8>,
+}
+
+impl AsyncRead for ChannelReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        while self.pending.is_empty() {
+            match ready!(self.receiver.poll_recv(cx)) {
+                Some(chunk) => self.pending = chunk,
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+
+        let n = buf.remaining().min(self.pending.len());
+        buf.put_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Replaces the process stdin given to scripts, see [`crate::Runtime::set_stdin`]
+pub(crate) struct HostStdinResource(AsyncRefCell<Pin<Box<dyn AsyncRead>>>);
+
+impl HostStdinResource {
+    pub fn new(reader: impl AsyncRead + 'static) -> Self {
+        Self(AsyncRefCell::new(Box::pin(reader)))
+    }
+
+    /// Reads a chunk into `buf`, returning the number of bytes read - 0 at the end of the input
+    async fn read_into(self: Rc<Self>, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut reader = RcRef::map(&self, |r| &r.0).borrow_mut().await;
+        std::future::poll_fn(|cx| {
+            let mut read_buf = ReadBuf::new(buf);
+            ready!(reader.as_mut().poll_read(cx, &mut read_buf))?;
+            Poll::Ready(Ok(read_buf.filled().len()))
+        })
+        .await
+    }
+}
+
+impl Resource for HostStdinResource {
+    fn name(&self) -> Cow<'_, str> {
+        "stdin".into()
+    }
+
+    fn read(self: Rc<Self>, limit: usize) -> AsyncResult<BufView> {
+        Box::pin(async move {
+            let mut buf = vec![0; limit.min(64 * 1024)];
+            let n = self.read_into(&mut buf).await?;
+            buf.truncate(n);
+            Ok(BufView::from(buf))
+        })
+    }
+
+    fn read_byob(self: Rc<Self>, mut buf: BufMutView) -> AsyncResult<(usize, BufMutView)> {
+        Box::pin(async move {
+            let n = self.read_into(&mut buf).await?;
+            Ok((n, buf))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{json_args, Module, Runtime, RuntimeOptions};
+
+    const MODULE: &str = "
+        export const read = () => new Response(Deno.stdin.readable).text();
+    ";
+
+    #[test]
+    fn test_write_stdin() {
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let handle = runtime
+            .load_module(&Module::new("test.js", MODULE))
+            .unwrap();
+
+        let writer = runtime.stdin_writer().unwrap();
+        let thread = std::thread::spawn(move || writer.write("hello ").unwrap());
+        thread.join().unwrap();
+        runtime.write_stdin("world").unwrap();
+        runtime.close_stdin();
+
+        let input: String = runtime
+            .call_function(Some(&handle), "read", json_args!())
+            .unwrap();
+        assert_eq!(input, "hello world");
+    }
+
+    #[test]
+    fn test_set_stdin() {
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let handle = runtime
+            .load_module(&Module::new("test.js", MODULE))
+            .unwrap();
+
+        runtime.set_stdin(&b"line 1\nline 2\n"[..]).unwrap();
+        let input: String = runtime
+            .call_function(Some(&handle), "read", json_args!())
+            .unwrap();
+        assert_eq!(input, "line 1\nline 2\n");
+    }
+}
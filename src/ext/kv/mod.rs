@@ -139,6 +139,17 @@ impl KvStore {
         Self(KvStoreBuilder::Remote { http_options }, config)
     }
 
+    /// Returns the directory of a local key-value store, if one was set
+    ///
+    /// Remote stores, and local stores kept in memory, return `None`
+    #[must_use]
+    pub fn local_path(&self) -> Option<&std::path::Path> {
+        match &self.0 {
+            KvStoreBuilder::Local { path, .. } => path.as_deref(),
+            KvStoreBuilder::Remote { .. } => None,
+        }
+    }
+
     /// Get the handler for the key-value store
     ///
     /// This is used to create the extension
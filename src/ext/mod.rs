@@ -78,6 +78,15 @@ pub mod kv;
 #[cfg(feature = "cron")]
 pub mod cron;
 
+#[cfg(feature = "rustyscript_std")]
+pub mod rustyscript_std;
+
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+#[cfg(feature = "sql_bridge")]
+pub mod sql_bridge;
+
 #[cfg(feature = "node_experimental")]
 pub mod napi;
 #[cfg(feature = "node_experimental")]
@@ -101,6 +110,16 @@ pub struct ExtensionOptions {
     #[cfg_attr(docsrs, doc(cfg(feature = "crypto")))]
     pub crypto_seed: Option<u64>,
 
+    /// Optional entropy source for the `deno_crypto` extension, such as an HSM or an audited generator
+    ///
+    /// Used by `crypto.getRandomValues`, `crypto.randomUUID`, and to generate AES and HMAC keys - see [`crate::SecureRng`]
+    /// Takes precedence over `crypto_seed` for those operations
+    ///
+    /// Requires the `crypto` feature to be enabled
+    #[cfg(feature = "crypto")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "crypto")))]
+    pub crypto_rng: Option<std::sync::Arc<dyn crypto::SecureRng>>,
+
     /// Configures the stdin/out/err pipes for the `deno_io` extension
     ///
     /// Requires the `io` feature to be enabled
@@ -122,6 +141,16 @@ pub struct ExtensionOptions {
     #[cfg_attr(docsrs, doc(cfg(feature = "cache")))]
     pub cache: Option<deno_cache::CreateCache<cache::CacheBackend>>,
 
+    /// The directory given to `CacheBackend::new_sqlite`, if the cache is stored in sqlite
+    ///
+    /// The cache backend does not expose where it stores its data,
+    /// so this must be set for [`crate::Runtime::export_state`] to include the cache
+    ///
+    /// Requires the `cache` feature to be enabled
+    #[cfg(feature = "cache")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "cache")))]
+    pub cache_storage_dir: Option<std::path::PathBuf>,
+
     /// Filesystem implementation for the `deno_fs` extension
     ///
     /// Requires the `fs` feature to be enabled
@@ -144,6 +173,20 @@ pub struct ExtensionOptions {
     #[cfg_attr(docsrs, doc(cfg(feature = "kv")))]
     pub kv_store: kv::KvStore,
 
+    /// Storage and connection limits for the `sqlite` extension
+    ///
+    /// Requires the `sqlite` feature to be enabled
+    #[cfg(feature = "sqlite")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "sqlite")))]
+    pub sqlite: sqlite::SqliteOptions,
+
+    /// Runs the queries of `host.sql` through a database connection owned by the host
+    ///
+    /// Requires the `sql_bridge` feature to be enabled
+    #[cfg(feature = "sql_bridge")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "sql_bridge")))]
+    pub sql_bridge: Option<std::sync::Arc<dyn sql_bridge::SqlBridge>>,
+
     /// Package resolver for the `deno_node` extension
     /// `RustyResolver` allows you to select the base dir for modules
     /// as well as the filesystem implementation to use
@@ -152,6 +195,13 @@ pub struct ExtensionOptions {
     #[cfg(feature = "node_experimental")]
     #[cfg_attr(docsrs, doc(cfg(feature = "node_experimental")))]
     pub node_resolver: std::sync::Arc<node::RustyResolver>,
+
+    /// Startup options seen by scripts, such as `Deno.args`, `navigator.userAgent` and the reported versions
+    ///
+    /// Requires the `node_experimental` feature to be enabled
+    #[cfg(feature = "node_experimental")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "node_experimental")))]
+    pub bootstrap: runtime::BootstrapOptions,
 }
 
 impl Default for ExtensionOptions {
@@ -163,6 +213,9 @@ impl Default for ExtensionOptions {
             #[cfg(feature = "crypto")]
             crypto_seed: None,
 
+            #[cfg(feature = "crypto")]
+            crypto_rng: None,
+
             #[cfg(feature = "io")]
             io_pipes: Some(deno_io::Stdio::default()),
 
@@ -172,6 +225,9 @@ impl Default for ExtensionOptions {
             #[cfg(feature = "cache")]
             cache: Some(cache::CacheBackend::new_memory()),
 
+            #[cfg(feature = "cache")]
+            cache_storage_dir: None,
+
             #[cfg(feature = "fs")]
             filesystem: std::sync::Arc::new(deno_fs::RealFs),
 
@@ -181,8 +237,17 @@ impl Default for ExtensionOptions {
             #[cfg(feature = "kv")]
             kv_store: kv::KvStore::default(),
 
+            #[cfg(feature = "sqlite")]
+            sqlite: sqlite::SqliteOptions::default(),
+
+            #[cfg(feature = "sql_bridge")]
+            sql_bridge: None,
+
             #[cfg(feature = "node_experimental")]
             node_resolver: std::sync::Arc::new(node::RustyResolver::default()),
+
+            #[cfg(feature = "node_experimental")]
+            bootstrap: runtime::BootstrapOptions::default(),
         }
     }
 }
@@ -191,6 +256,7 @@ pub(crate) fn all_extensions(
     user_extensions: Vec<Extension>,
     options: ExtensionOptions,
     shared_array_buffer_store: Option<CrossIsolateStore<SharedRef<BackingStore>>>,
+    origin_policy: Option<crate::module_loader::OriginPolicy>,
     is_snapshot: bool,
 ) -> Vec<Extension> {
     let mut extensions = rustyscript::extensions(is_snapshot);
@@ -220,7 +286,11 @@ pub(crate) fn all_extensions(
     extensions.extend(web_stub::extensions(is_snapshot));
 
     #[cfg(feature = "crypto")]
-    extensions.extend(crypto::extensions(options.crypto_seed, is_snapshot));
+    extensions.extend(crypto::extensions(
+        options.crypto_seed,
+        options.crypto_rng.clone(),
+        is_snapshot,
+    ));
 
     #[cfg(feature = "io")]
     extensions.extend(io::extensions(options.io_pipes.clone(), is_snapshot));
@@ -252,6 +322,18 @@ pub(crate) fn all_extensions(
     #[cfg(feature = "cron")]
     extensions.extend(cron::extensions(is_snapshot));
 
+    #[cfg(feature = "rustyscript_std")]
+    extensions.extend(rustyscript_std::extensions(is_snapshot));
+
+    #[cfg(feature = "sqlite")]
+    extensions.extend(sqlite::extensions(options.sqlite.clone(), is_snapshot));
+
+    #[cfg(feature = "sql_bridge")]
+    extensions.extend(sql_bridge::extensions(
+        options.sql_bridge.clone(),
+        is_snapshot,
+    ));
+
     #[cfg(feature = "node_experimental")]
     {
         extensions.extend(napi::extensions(is_snapshot));
@@ -260,6 +342,7 @@ pub(crate) fn all_extensions(
         extensions.extend(runtime::extensions(
             &options,
             shared_array_buffer_store,
+            origin_policy,
             is_snapshot,
         ));
     }
@@ -0,0 +1,26 @@
+import { core } from "ext:core/mod.js";
+import process from "node:process";
+
+// Report the node version configured in `BootstrapOptions::node_version`
+const { node } = core.ops.op_rustyscript_versions();
+if (node) {
+    Object.defineProperty(process, "version", {
+        value: `v${node}`,
+        enumerable: true,
+        configurable: true,
+    });
+    process.versions.node = node;
+}
+
+// Arguments given with `Runtime::load_module_with_args`, after the executable and module as in node
+// Until a module is given arguments, the bootstrap arguments seen in `Deno.args` are reported instead
+const argv0 = process.argv0 || "rustyscript";
+Object.defineProperty(process, "argv", {
+    get: () => {
+        const [module, args] = core.ops.op_script_args();
+        if (module === null) return [argv0, "", ...(globalThis.Deno?.args ?? [])];
+        return [argv0, module, ...args];
+    },
+    enumerable: true,
+    configurable: true,
+});
@@ -0,0 +1,86 @@
+use deno_core::{op2, serde_json, OpState};
+
+/// The startup options seen by scripts through `Deno` and `process`, see [`crate::ExtensionOptions::bootstrap`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BootstrapOptions {
+    /// Arguments returned by `Deno.args`
+    ///
+    /// Defaults to `["--colors"]`
+    pub args: Vec<String>,
+
+    /// Value of `Deno.noColor` - if true, console output is not colored
+    pub no_color: bool,
+
+    /// Value of `navigator.userAgent`
+    pub user_agent: String,
+
+    /// Names of the unstable deno features to enable, such as `"kv"` or `"cron"`
+    ///
+    /// Unknown names are ignored
+    pub unstable_features: Vec<String>,
+
+    /// Value of `navigator.language`
+    pub locale: String,
+
+    /// Value of `Deno.version.deno`
+    pub deno_version: String,
+
+    /// Value of `process.version`, without the leading `v`
+    ///
+    /// If not set, the version built into the node compatibility layer is reported
+    pub node_version: Option<String>,
+}
+
+impl Default for BootstrapOptions {
+    fn default() -> Self {
+        let defaults = deno_runtime::BootstrapOptions::default();
+        Self {
+            args: vec!["--colors".to_string()],
+            no_color: false,
+            user_agent: defaults.user_agent,
+            unstable_features: Vec::new(),
+            locale: defaults.locale,
+            deno_version: defaults.deno_version,
+            node_version: None,
+        }
+    }
+}
+
+impl BootstrapOptions {
+    /// Convert to the bootstrap options used by `deno_runtime`
+    #[must_use]
+    pub fn to_deno(&self) -> deno_runtime::BootstrapOptions {
+        let unstable_features = self
+            .unstable_features
+            .iter()
+            .filter_map(|name| {
+                deno_runtime::UNSTABLE_GRANULAR_FLAGS
+                    .iter()
+                    .find(|flag| flag.name == name)
+                    .map(|flag| flag.id)
+            })
+            .collect();
+
+        deno_runtime::BootstrapOptions {
+            args: self.args.clone(),
+            no_color: self.no_color,
+            user_agent: self.user_agent.clone(),
+            unstable_features,
+            locale: self.locale.clone(),
+            deno_version: self.deno_version.clone(),
+            ..deno_runtime::BootstrapOptions::default()
+        }
+    }
+}
+
+/// Returns the version strings reported to scripts
+#[op2]
+#[serde]
+pub fn op_rustyscript_versions(state: &OpState) -> serde_json::Value {
+    let options = state.borrow::<BootstrapOptions>();
+    serde_json::json!({
+        "deno": options.deno_version,
+        "v8": deno_core::v8::VERSION_STRING,
+        "node": options.node_version,
+    })
+}
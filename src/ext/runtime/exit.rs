@@ -0,0 +1,20 @@
+use crate::ext::rustyscript::{ExitPolicy, ExitState};
+use deno_core::{op2, OpState};
+
+/// Replaces the process exit behind `Deno.exit`, see [`ExitPolicy`]
+///
+/// Only registered here, alongside the exit handler that calls it, so scripts cannot reach it where `Deno.exit` is not exposed
+#[op2(fast)]
+pub fn op_rustyscript_exit(state: &mut OpState, code: i32) {
+    let Some(exit) = state.try_borrow::<ExitState>() else {
+        return;
+    };
+
+    match exit.policy {
+        ExitPolicy::Process => std::process::exit(code),
+        ExitPolicy::Terminate => {
+            exit.exit.record(code);
+            exit.isolate.terminate_execution();
+        }
+    }
+}
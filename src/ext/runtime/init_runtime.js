@@ -19,6 +19,13 @@ const opPid = scopeWindow.memoizeLazy(() => core.ops.op_bootstrap_pid());
 import { core } from "ext:core/mod.js";
 import { applyToDeno, getterOnly, readOnly, nonEnumerable } from "ext:rustyscript/rustyscript.js";
 
+// Versions configured in `BootstrapOptions`
+const versions = core.ops.op_rustyscript_versions();
+version.setVersions(versions.deno, versions.v8, "");
+
+// `Deno.exit` must not take the host process down with it, see `ExitPolicy`
+os.setExitHandler((code) => core.ops.op_rustyscript_exit(code));
+
 //applyToDeno(denoNs);
 applyToDeno({    
     pid: getterOnly(opPid),
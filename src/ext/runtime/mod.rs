@@ -1,7 +1,7 @@
 use super::node::RustyResolver;
 use super::web::PermissionsContainer;
 use super::{ExtensionOptions, ExtensionTrait};
-use crate::module_loader::{LoaderOptions, RustyLoader};
+use crate::module_loader::{LoaderOptions, OriginPolicy, RustyLoader};
 use ::deno_permissions::Permissions;
 use deno_core::v8::{BackingStore, SharedRef};
 use deno_core::{extension, CrossIsolateStore, Extension, FeatureChecker};
@@ -11,6 +11,11 @@ use std::collections::HashSet;
 use std::rc::Rc;
 use std::sync::Arc;
 
+mod bootstrap;
+pub use bootstrap::BootstrapOptions;
+
+mod exit;
+
 fn build_permissions(
     permissions_container: &PermissionsContainer,
 ) -> ::deno_permissions::PermissionsContainer {
@@ -26,26 +31,24 @@ fn build_permissions(
 // It will always be the last initialized extension
 extension!(
     init_runtime,
+    ops = [bootstrap::op_rustyscript_versions, exit::op_rustyscript_exit],
     esm_entry_point = "ext:init_runtime/init_runtime.js",
     esm = [ dir "src/ext/runtime", "init_runtime.js" ],
-    state = |state| {
-        let options = BootstrapOptions {
-            no_color: false,
-            args: vec![
-                "--colors".to_string(),
-            ],
-            ..BootstrapOptions::default()
-        };
-        state.put(options);
+    options = {
+        bootstrap: BootstrapOptions
+    },
+    state = |state, config| {
+        state.put(config.bootstrap.to_deno());
+        state.put(config.bootstrap);
 
         let container = state.borrow::<PermissionsContainer>();
         let permissions = build_permissions(container);
         state.put(permissions);
     }
 );
-impl ExtensionTrait<()> for init_runtime {
-    fn init((): ()) -> Extension {
-        init_runtime::init_ops_and_esm()
+impl ExtensionTrait<BootstrapOptions> for init_runtime {
+    fn init(bootstrap: BootstrapOptions) -> Extension {
+        init_runtime::init_ops_and_esm(bootstrap)
     }
 }
 
@@ -70,15 +73,17 @@ impl
     ExtensionTrait<(
         &ExtensionOptions,
         Option<CrossIsolateStore<SharedRef<BackingStore>>>,
+        Option<OriginPolicy>,
     )> for deno_worker_host
 {
     fn init(
         options: (
             &ExtensionOptions,
             Option<CrossIsolateStore<SharedRef<BackingStore>>>,
+            Option<OriginPolicy>,
         ),
     ) -> Extension {
-        let options = WebWorkerCallbackOptions::new(options.0, options.1);
+        let options = WebWorkerCallbackOptions::new(options.0, options.1, options.2);
         let callback = create_web_worker_callback(options);
         deno_worker_host::init_ops_and_esm(callback, None)
     }
@@ -129,6 +134,7 @@ impl ExtensionTrait<()> for deno_fs_events {
 pub fn extensions(
     options: &ExtensionOptions,
     shared_array_buffer_store: Option<CrossIsolateStore<SharedRef<BackingStore>>>,
+    origin_policy: Option<OriginPolicy>,
     is_snapshot: bool,
 ) -> Vec<Extension> {
     vec![
@@ -138,17 +144,20 @@ pub fn extensions(
         deno_signal::build((), is_snapshot),
         deno_process::build(options.node_resolver.clone(), is_snapshot),
         deno_web_worker::build((), is_snapshot),
-        deno_worker_host::build((options, shared_array_buffer_store), is_snapshot),
+        deno_worker_host::build(
+            (options, shared_array_buffer_store, origin_policy),
+            is_snapshot,
+        ),
         deno_permissions::build((), is_snapshot),
         //
         deno_runtime::runtime::build((), is_snapshot),
-        init_runtime::build((), is_snapshot),
+        init_runtime::build(options.bootstrap.clone(), is_snapshot),
     ]
 }
 
 use deno_runtime::web_worker::{WebWorker, WebWorkerOptions, WebWorkerServiceOptions};
 use deno_runtime::worker::ExitCode;
-use deno_runtime::{colors, BootstrapOptions, WorkerExecutionMode, WorkerLogLevel};
+use deno_runtime::{colors, WorkerExecutionMode, WorkerLogLevel};
 #[derive(Clone)]
 pub struct WebWorkerCallbackOptions {
     shared_array_buffer_store: Option<CrossIsolateStore<SharedRef<BackingStore>>>,
@@ -159,11 +168,14 @@ pub struct WebWorkerCallbackOptions {
     seed: Option<u64>,
     stdio: deno_io::Stdio,
     blob_store: Arc<deno_web::BlobStore>,
+    origin_policy: Option<OriginPolicy>,
+    bootstrap: BootstrapOptions,
 }
 impl WebWorkerCallbackOptions {
     pub fn new(
         options: &ExtensionOptions,
         shared_array_buffer_store: Option<CrossIsolateStore<SharedRef<BackingStore>>>,
+        origin_policy: Option<OriginPolicy>,
     ) -> Self {
         Self {
             shared_array_buffer_store,
@@ -177,6 +189,8 @@ impl WebWorkerCallbackOptions {
             seed: options.crypto_seed,
             stdio: options.io_pipes.clone().unwrap_or_default(),
             blob_store: options.web.blob_store.clone(),
+            origin_policy,
+            bootstrap: options.bootstrap.clone(),
         }
     }
 }
@@ -190,6 +204,11 @@ fn create_web_worker_callback(options: WebWorkerCallbackOptions) -> Arc<CreateWe
             import_provider: None,
             schema_whlist: HashSet::default(),
             node_resolver: node_resolver.clone(),
+            origin_policy: options
+                .origin_policy
+                .as_ref()
+                .map(|policy| policy.for_worker(&args.main_module)),
+            policy_checks_main_module: true,
             ..Default::default()
         }));
 
@@ -212,27 +231,23 @@ fn create_web_worker_callback(options: WebWorkerCallbackOptions) -> Arc<CreateWe
             npm_process_state_provider: Some(node_resolver.clone()),
             permissions: args.permissions,
         };
+        // Workers see the same startup options as the main runtime
+        let bootstrap = options.bootstrap.to_deno();
         let options = WebWorkerOptions {
             name: args.name,
             main_module: args.main_module.clone(),
             worker_id: args.worker_id,
-            bootstrap: BootstrapOptions {
-                deno_version: env!("CARGO_PKG_VERSION").to_string(),
-                args: vec![],
+            bootstrap: deno_runtime::BootstrapOptions {
                 cpu_count: std::thread::available_parallelism()
                     .map(std::num::NonZero::get)
                     .unwrap_or(1),
                 log_level: WorkerLogLevel::default(),
                 enable_op_summary_metrics: false,
                 enable_testing_features: false,
-                locale: deno_core::v8::icu::get_language_tag(),
                 location: Some(args.main_module),
-                no_color: !colors::use_color(),
                 color_level: colors::get_color_level(),
                 is_stdout_tty: false,
                 is_stderr_tty: false,
-                unstable_features: vec![],
-                user_agent: concat!("rustyscript_", env!("CARGO_PKG_VERSION")).to_string(),
                 inspect: false,
                 has_node_modules_dir: node_resolver.has_node_modules_dir(),
                 argv0: None,
@@ -242,6 +257,7 @@ fn create_web_worker_callback(options: WebWorkerCallbackOptions) -> Arc<CreateWe
                 serve_port: None,
                 serve_host: None,
                 otel_config: None,
+                ..bootstrap
             },
             extensions: vec![],
             startup_snapshot: None,
@@ -0,0 +1,144 @@
+use crate::js_value::Value;
+use deno_core::{serde_json, AsyncRefCell, RcRef, Resource};
+use std::{rc::Rc, sync::Arc};
+use tokio::sync::watch;
+
+/// The state shared between an [`AbortHandle`] and the script's signal
+/// `None` until aborted, then the reason given to `AbortController.abort`
+type AbortState = Option<serde_json::Value>;
+
+/// Aborts an [`AbortSignal`] created with [`crate::Runtime::create_abort_signal`]
+///
+/// The handle can be cloned, and is `Send` - so a signal can be aborted from another thread
+/// while the runtime is busy with the call it was passed to
+#[derive(Clone, Debug)]
+pub struct AbortHandle(Arc<watch::Sender<AbortState>>);
+
+impl AbortHandle {
+    /// Aborts the signal, with the default `AbortError` reason
+    /// Has no effect if the signal was already aborted
+    pub fn abort(&self) {
+        self.abort_with(serde_json::Value::Null);
+    }
+
+    /// Aborts the signal, with the given value as its reason
+    /// Has no effect if the signal was already aborted
+    ///
+    /// # Errors
+    /// Will return an error if the reason cannot be serialized
+    pub fn abort_with_reason<T>(&self, reason: &T) -> Result<(), crate::Error>
+    where
+        T: serde::Serialize,
+    {
+        self.abort_with(serde_json::to_value(reason)?);
+        Ok(())
+    }
+
+    /// Returns true once the signal has been aborted from rust
+    #[must_use]
+    pub fn is_aborted(&self) -> bool {
+        self.0.borrow().is_some()
+    }
+
+    fn abort_with(&self, reason: serde_json::Value) {
+        self.0.send_if_modified(|state| {
+            if state.is_some() {
+                return false;
+            }
+            *state = Some(reason);
+            true
+        });
+    }
+}
+
+/// A javascript `AbortSignal` that can be aborted from rust
+///
+/// Pass it to a javascript function as an argument - it serializes as the signal itself
+/// The signal is aborted the next time the event loop runs after [`AbortSignal::abort`],
+/// so cancellation is cooperative - scripts observe it with `signal.aborted`, `signal.throwIfAborted()`,
+/// or an `abort` event listener
+///
+/// Obtained with [`crate::Runtime::create_abort_signal`]
+/// Can only be used on the runtime it was created on
+#[derive(Clone, Debug)]
+pub struct AbortSignal {
+    signal: Value,
+    handle: AbortHandle,
+}
+
+impl AbortSignal {
+    /// Returns a handle that can abort the signal, which can be moved to another thread
+    #[must_use]
+    pub fn handle(&self) -> AbortHandle {
+        self.handle.clone()
+    }
+
+    /// Returns the javascript `AbortSignal`
+    #[must_use]
+    pub fn signal(&self) -> &Value {
+        &self.signal
+    }
+
+    /// Aborts the signal, with the default `AbortError` reason
+    /// See [`AbortHandle::abort`]
+    pub fn abort(&self) {
+        self.handle.abort();
+    }
+
+    /// Aborts the signal, with the given value as its reason
+    /// See [`AbortHandle::abort_with_reason`]
+    ///
+    /// # Errors
+    /// Will return an error if the reason cannot be serialized
+    pub fn abort_with_reason<T>(&self, reason: &T) -> Result<(), crate::Error>
+    where
+        T: serde::Serialize,
+    {
+        self.handle.abort_with_reason(reason)
+    }
+
+    /// Returns true once the signal has been aborted from rust
+    #[must_use]
+    pub fn is_aborted(&self) -> bool {
+        self.handle.is_aborted()
+    }
+}
+
+impl serde::Serialize for AbortSignal {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.signal.serialize(serializer)
+    }
+}
+
+/// Holds the receiving end of an abort signal, while javascript waits for it
+pub(crate) struct AbortResource(AsyncRefCell<watch::Receiver<AbortState>>);
+
+impl AbortResource {
+    /// Creates the resource, and the handle that triggers it
+    pub(crate) fn new() -> (Self, AbortHandle) {
+        let (tx, rx) = watch::channel(None);
+        (Self(AsyncRefCell::new(rx)), AbortHandle(Arc::new(tx)))
+    }
+
+    /// Wraps the javascript signal and its handle
+    pub(crate) fn signal(signal: Value, handle: AbortHandle) -> AbortSignal {
+        AbortSignal { signal, handle }
+    }
+
+    /// Waits until the signal is aborted, returning the reason
+    /// Returns None if every handle was dropped without aborting
+    pub(crate) async fn wait(self: Rc<Self>) -> Option<serde_json::Value> {
+        let mut receiver = RcRef::map(&self, |r| &r.0).borrow_mut().await;
+        let state = receiver.wait_for(Option::is_some).await.ok()?;
+        state.clone()
+    }
+}
+
+impl Resource for AbortResource {
+    fn name(&self) -> std::borrow::Cow<'_, str> {
+        "abortSignal".into()
+    }
+}
@@ -0,0 +1,68 @@
+//! Runs an async host function to completion from a synchronous op, for `rustyscript.blocking_functions`
+//!
+//! The event loop cannot advance while the op blocks, so the future is polled on the current thread
+//! until it completes or the deadline passes - it must not depend on anything the script would do next
+//!
+//! On a current-thread tokio runtime, timers and IO are driven by the thread now blocked, so a future waiting on them
+//! holds the thread until the deadline passes - nothing else can interrupt it, including the runtime's timeouts
+use crate::Error;
+use deno_core::serde_json;
+use std::{
+    future::Future,
+    pin::pin,
+    sync::Arc,
+    task::{Context, Poll, Wake},
+    thread::Thread,
+    time::{Duration, Instant},
+};
+
+/// Deadline used when the script does not provide one
+pub(crate) const DEFAULT_DEADLINE: Duration = Duration::from_secs(5);
+
+/// Wakes the blocked thread
+struct ThreadWaker(Thread);
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Polls the future until it completes, or fails once the deadline passes
+pub(crate) fn block_on_with_deadline(
+    name: &str,
+    future: impl Future<Output = Result<serde_json::Value, Error>>,
+    deadline: Duration,
+) -> Result<serde_json::Value, Error> {
+    let started = Instant::now();
+    let waker = Arc::new(ThreadWaker(std::thread::current())).into();
+    let mut context = Context::from_waker(&waker);
+    let mut future = pin!(future);
+
+    loop {
+        if let Poll::Ready(result) = future.as_mut().poll(&mut context) {
+            return result;
+        }
+
+        let Some(remaining) = deadline.checked_sub(started.elapsed()) else {
+            return Err(deadline_error(name, deadline));
+        };
+        std::thread::park_timeout(remaining);
+    }
+}
+
+fn deadline_error(name: &str, deadline: Duration) -> Error {
+    // Timers and IO are driven by the thread now blocked, unless the tokio runtime has worker threads
+    let hint = match tokio::runtime::Handle::try_current().map(|h| h.runtime_flavor()) {
+        Ok(tokio::runtime::RuntimeFlavor::CurrentThread) => {
+            " - on a single-threaded tokio runtime, functions awaiting timers or IO cannot complete while blocking"
+        }
+        _ => "",
+    };
+    Error::Runtime(format!(
+        "Blocking call to `{name}` did not complete within {deadline:?}{hint}"
+    ))
+}
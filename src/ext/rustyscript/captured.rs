@@ -0,0 +1,87 @@
+use crate::{js_value::FunctionToken, Error};
+use deno_core::{op2, serde_json, v8, OpState};
+use std::collections::BTreeMap;
+
+/// The most functions kept at once - past this, the oldest untaken functions are released
+const MAX_CAPTURED_FUNCTIONS: usize = 1024;
+
+/// Functions passed by scripts as arguments to rust functions, until taken with [`crate::Runtime::take_function`]
+#[derive(Default)]
+pub(crate) struct CapturedFunctions {
+    next_id: u32,
+    functions: BTreeMap<u32, v8::Global<v8::Function>>,
+
+    /// Ids captured since the last call started - the only tokens that call may receive
+    pending: Vec<u32>,
+}
+
+impl CapturedFunctions {
+    /// Removes a captured function, returning it if it had not been taken yet
+    pub fn take(&mut self, id: u32) -> Option<v8::Global<v8::Function>> {
+        self.functions.remove(&id)
+    }
+
+    /// Checks that every token in the arguments of a call was captured for that call
+    ///
+    /// Tokens are plain objects, so without this a script could name a function captured for another call
+    ///
+    /// # Errors
+    /// Will return an error if any argument contains a token that was not captured for this call
+    pub fn verify(state: &mut OpState, args: &[serde_json::Value]) -> Result<(), Error> {
+        let pending = state
+            .try_borrow_mut::<Self>()
+            .map(|captured| std::mem::take(&mut captured.pending))
+            .unwrap_or_default();
+
+        for arg in args {
+            let captured = is_token(arg)
+                && FunctionToken::from_value(arg).is_some_and(|t| pending.contains(&t.id()));
+            if !captured && contains_token(arg) {
+                return Err(Error::Runtime(
+                    "Function tokens can only be created by passing a function".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// True for an object holding nothing but a token, as created by `captureFunctions`
+fn is_token(value: &serde_json::Value) -> bool {
+    value
+        .as_object()
+        .is_some_and(|object| object.len() == 1 && object.contains_key("$rustyscript_function"))
+}
+
+/// True if a token appears anywhere in the value
+fn contains_token(value: &serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::Object(object) => {
+            object.contains_key("$rustyscript_function") || object.values().any(contains_token)
+        }
+        serde_json::Value::Array(array) => array.iter().any(contains_token),
+        _ => false,
+    }
+}
+
+/// Keeps a function passed as an argument to a rust function, returning the id of its [`crate::js_value::FunctionToken`]
+#[op2]
+pub fn op_capture_function(
+    state: &mut OpState,
+    #[global] function: v8::Global<v8::Function>,
+) -> u32 {
+    if !state.has::<CapturedFunctions>() {
+        state.put(CapturedFunctions::default());
+    }
+
+    let captured = state.borrow_mut::<CapturedFunctions>();
+    if captured.functions.len() >= MAX_CAPTURED_FUNCTIONS {
+        captured.functions.pop_first();
+    }
+
+    let id = captured.next_id;
+    captured.next_id = captured.next_id.wrapping_add(1);
+    captured.functions.insert(id, function);
+    captured.pending.push(id);
+    id
+}
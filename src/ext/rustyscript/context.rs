@@ -0,0 +1,20 @@
+use deno_core::{op2, OpState};
+
+/// Permission to enter or leave a call context, given by [`crate::Runtime::with_context`]
+///
+/// Kept in the op state, so only the host can open it - and each check closes it again
+#[derive(Default)]
+pub struct ContextGate(bool);
+impl ContextGate {
+    /// Opens or closes the gate
+    pub fn set(&mut self, open: bool) {
+        self.0 = open;
+    }
+}
+
+/// Checked by the context globals before changing the context
+/// Returns false unless the host opened the gate for this call
+#[op2(fast)]
+pub fn op_context_gate(state: &mut OpState) -> bool {
+    std::mem::take(&mut state.borrow_mut::<ContextGate>().0)
+}
@@ -0,0 +1,42 @@
+use deno_core::v8;
+use std::{cell::Cell, rc::Rc};
+
+/// What happens when a script calls `Deno.exit` or `process.exit`
+///
+/// See [`crate::RuntimeOptions::exit_policy`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ExitPolicy {
+    /// Terminate the runtime only
+    ///
+    /// The call in progress fails with [`crate::Error::ScriptExited`], as does every call made afterwards
+    #[default]
+    Terminate,
+
+    /// Exit the host process with the script's exit code, as `deno` would
+    Process,
+}
+
+/// The exit code of a script that has exited, shared between the exit OP and the async bridge
+#[derive(Clone, Default)]
+pub struct ScriptExit(Rc<Cell<Option<i32>>>);
+impl ScriptExit {
+    /// Returns the exit code, if the script has exited
+    pub fn code(&self) -> Option<i32> {
+        self.0.get()
+    }
+
+    /// Records the exit code - only the first exit counts, later ones happen while the runtime is unwinding
+    #[cfg_attr(not(feature = "node_experimental"), allow(dead_code))]
+    pub(crate) fn record(&self, code: i32) {
+        if self.0.get().is_none() {
+            self.0.set(Some(code));
+        }
+    }
+}
+
+/// Placed in the OP state by the runtime
+pub(crate) struct ExitState {
+    pub policy: ExitPolicy,
+    pub isolate: v8::IsolateHandle,
+    pub exit: ScriptExit,
+}
@@ -0,0 +1,118 @@
+// Freezes the built-in intrinsics, once extensions are initialized and before any user code runs
+// Used when `RuntimeOptions::harden` is set - see `restrict` in mod.rs
+((globalThis) => {
+    const { defineProperty, freeze, getOwnPropertyDescriptor, getPrototypeOf, isFrozen } = Object;
+
+    // Constructors whose prototypes, and the constructors themselves, are frozen
+    const constructors = [
+        'Object', 'Function', 'Array', 'String', 'Number', 'Boolean', 'Symbol', 'BigInt',
+        'Date', 'RegExp', 'Promise', 'Map', 'Set', 'WeakMap', 'WeakSet', 'WeakRef', 'FinalizationRegistry',
+        'ArrayBuffer', 'SharedArrayBuffer', 'DataView',
+        'Int8Array', 'Uint8Array', 'Uint8ClampedArray', 'Int16Array', 'Uint16Array',
+        'Int32Array', 'Uint32Array', 'Float32Array', 'Float64Array', 'BigInt64Array', 'BigUint64Array',
+        'Iterator', 'Proxy',
+    ];
+
+    // Error constructors are locked rather than frozen - `stackTraceLimit` stays writable, since the runtime relies on it
+    // Everything else, including `captureStackTrace`, is read-only, and hooks like `prepareStackTrace` cannot be added
+    const errors = [
+        'Error', 'EvalError', 'RangeError', 'ReferenceError', 'SyntaxError', 'TypeError', 'URIError', 'AggregateError',
+    ];
+
+    // Namespace objects
+    const namespaces = ['Math', 'JSON', 'Reflect', 'Atomics', 'Intl'];
+
+    // Intrinsics that are not reachable by name
+    const hidden = [
+        getPrototypeOf(function* () {}),
+        getPrototypeOf(async function () {}),
+        getPrototypeOf(async function* () {}),
+        getPrototypeOf(Int8Array),
+        getPrototypeOf(getPrototypeOf([][Symbol.iterator]())),
+        getPrototypeOf([][Symbol.iterator]()),
+        getPrototypeOf(new Map()[Symbol.iterator]()),
+        getPrototypeOf(new Set()[Symbol.iterator]()),
+        getPrototypeOf(''[Symbol.iterator]()),
+        getPrototypeOf(/a/[Symbol.matchAll](''))
+    ];
+
+    // Properties commonly assigned on instances
+    // On a frozen prototype, assigning these would throw instead of creating an own property,
+    // so they are replaced with accessors which define the property on the instance instead
+    const overridable = ['toString', 'toLocaleString', 'valueOf', 'toJSON', 'name', 'message'];
+
+    const makeOverridable = (prototype) => {
+        for (const key of overridable) {
+            const descriptor = getOwnPropertyDescriptor(prototype, key);
+            if (!descriptor || !('value' in descriptor) || !descriptor.writable || !descriptor.configurable) {
+                continue;
+            }
+
+            const value = descriptor.value;
+            defineProperty(prototype, key, {
+                get() { return value; },
+                set(newValue) {
+                    if (this === prototype) {
+                        throw new TypeError(`Cannot assign to read only property '${key}' of a hardened intrinsic`);
+                    }
+                    defineProperty(this, key, { value: newValue, writable: true, enumerable: true, configurable: true });
+                },
+                enumerable: descriptor.enumerable,
+                configurable: false,
+            });
+        }
+    };
+
+    const harden = (object) => {
+        if (object === undefined || object === null || isFrozen(object)) return;
+        makeOverridable(object);
+        freeze(object);
+
+        // Methods and accessors of the object are frozen too, so they cannot be used to smuggle state
+        for (const key of Reflect.ownKeys(object)) {
+            const { value, get, set } = getOwnPropertyDescriptor(object, key);
+            for (const member of [value, get, set]) {
+                if (typeof member === 'function' && member !== object.constructor && !isFrozen(member)) {
+                    freeze(member);
+                }
+            }
+        }
+    };
+
+    for (const name of constructors) {
+        const constructor = globalThis[name];
+        if (typeof constructor !== 'function') continue;
+        harden(constructor.prototype);
+        harden(constructor);
+    }
+
+    const lockError = (constructor) => {
+        for (const key of Reflect.ownKeys(constructor)) {
+            const descriptor = getOwnPropertyDescriptor(constructor, key);
+            if (!descriptor.configurable) continue;
+            if ('value' in descriptor) {
+                descriptor.writable = key === 'stackTraceLimit';
+                if (typeof descriptor.value === 'function' && !isFrozen(descriptor.value)) freeze(descriptor.value);
+            }
+            descriptor.configurable = false;
+            defineProperty(constructor, key, descriptor);
+        }
+        Object.preventExtensions(constructor);
+    };
+
+    for (const name of errors) {
+        const constructor = globalThis[name];
+        if (typeof constructor !== 'function') continue;
+        harden(constructor.prototype);
+        lockError(constructor);
+    }
+
+    for (const name of namespaces) {
+        harden(globalThis[name]);
+    }
+
+    for (const intrinsic of hidden) {
+        harden(intrinsic);
+        if (Object.hasOwn(intrinsic, 'prototype')) harden(intrinsic.prototype);
+    }
+})(globalThis);
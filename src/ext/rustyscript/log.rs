@@ -0,0 +1,116 @@
+use deno_core::serde_json;
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, time::SystemTime};
+
+/// The severity of a record written with `host.log`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    /// `host.log.trace`
+    Trace,
+
+    /// `host.log.debug`
+    Debug,
+
+    /// `host.log.info`
+    Info,
+
+    /// `host.log.warn`
+    Warn,
+
+    /// `host.log.error`
+    Error,
+}
+
+/// A record written by a script with `host.log`
+///
+/// Obtained with [`crate::Runtime::drain_logs`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogRecord {
+    /// The severity of the record
+    pub level: LogLevel,
+
+    /// The message, converted to a string
+    pub message: String,
+
+    /// Structured fields attached to the record - empty if none were given
+    pub fields: serde_json::Map<String, serde_json::Value>,
+
+    /// When the record was written
+    pub timestamp: SystemTime,
+}
+
+/// Holds the most recent records written with `host.log`, kept in the runtime's `OpState`
+///
+/// Once full, the oldest record is discarded for each new one
+pub(crate) struct LogBuffer {
+    records: VecDeque<LogRecord>,
+    capacity: usize,
+}
+
+impl LogBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            records: VecDeque::with_capacity(capacity.min(1024)),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, record: LogRecord) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+
+    /// Removes and returns all records, oldest first
+    pub fn drain(&mut self) -> Vec<LogRecord> {
+        self.records.drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Runtime, RuntimeOptions};
+
+    #[test]
+    fn test_host_log() {
+        let mut runtime = Runtime::new(RuntimeOptions {
+            log_capacity: 3,
+            ..Default::default()
+        })
+        .unwrap();
+
+        runtime
+            .eval::<()>(
+                "
+                host.log.debug('dropped');
+                host.log.info('started', { user: 'bob', attempt: 1n });
+                host.log('warn', 'slow', { ms: 1200 });
+                host.log.error(new Error('failed'), { cause: new TypeError('bad') });
+                ",
+            )
+            .unwrap();
+
+        let logs = runtime.drain_logs().unwrap();
+        assert_eq!(logs.len(), 3);
+        assert_eq!(logs[0].level, LogLevel::Info);
+        assert_eq!(logs[0].message, "started");
+        assert_eq!(
+            serde_json::Value::Object(logs[0].fields.clone()),
+            serde_json::json!({ "user": "bob", "attempt": "1" })
+        );
+        assert_eq!(logs[1].fields["ms"], 1200);
+        assert_eq!(logs[2].level, LogLevel::Error);
+        assert_eq!(logs[2].message, "Error: failed");
+        assert_eq!(logs[2].fields["cause"]["message"], "bad");
+
+        assert!(runtime.drain_logs().unwrap().is_empty());
+        runtime.eval::<()>("host.log('fatal', 'oops')").unwrap_err();
+    }
+}
@@ -0,0 +1,56 @@
+use crate::Error;
+use deno_core::{error::AnyError, op2, OpState};
+
+/// Execution cost counted by modules instrumented with [`crate::RuntimeOptions::metering`]
+///
+/// Kept in the op state, so only the host can reset the count or change the limit
+#[derive(Default)]
+pub struct Meter {
+    cost: u64,
+    limit: Option<u64>,
+}
+impl Meter {
+    /// Resets the count, and sets the limit until [`Meter::stop`] is called
+    pub fn start(&mut self, limit: Option<u64>) {
+        self.cost = 0;
+        self.limit = limit;
+    }
+
+    /// Removes the limit, returning the cost counted since the last start
+    pub fn stop(&mut self) -> u64 {
+        self.limit = None;
+        self.cost
+    }
+
+    /// The cost counted since the last start
+    pub fn cost(&self) -> u64 {
+        self.cost
+    }
+
+    /// Lowers the limit, so that at most `budget` more can be counted - returns the limit to restore afterwards
+    pub fn restrict(&mut self, budget: u64) -> Option<u64> {
+        let previous = self.limit;
+        let limit = self.cost.saturating_add(budget);
+        self.limit = Some(previous.map_or(limit, |previous| previous.min(limit)));
+        previous
+    }
+
+    /// Restores a limit returned by [`Meter::restrict`]
+    pub fn restore(&mut self, limit: Option<u64>) {
+        self.limit = limit;
+    }
+}
+
+/// Called by instrumented code at the start of every function, and every iteration of every loop
+/// Once the limit is exceeded, every check fails - so a script cannot recover by catching the error
+#[op2(fast)]
+pub fn op_meter(state: &mut OpState) -> Result<(), AnyError> {
+    let meter = state.borrow_mut::<Meter>();
+    meter.cost += 1;
+    match meter.limit {
+        Some(limit) if meter.cost > limit => {
+            Err(Error::Runtime(format!("Execution cost exceeded the limit of {limit}")).into())
+        }
+        _ => Ok(()),
+    }
+}
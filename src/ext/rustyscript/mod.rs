@@ -1,13 +1,81 @@
 use super::ExtensionTrait;
-use crate::{error::Error, RsAsyncFunction, RsFunction};
-use deno_core::{anyhow::anyhow, extension, op2, serde_json, v8, Extension, OpState};
-use std::collections::HashMap;
+use crate::{capabilities::ActiveCapabilities, error::Error, RsAsyncFunction, RsFunction};
+use deno_core::{
+    anyhow::anyhow, error::AnyError, extension, op2, serde_json, v8, Extension, OpState, ResourceId,
+};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
 type FnCache = HashMap<String, Box<dyn RsFunction>>;
 type AsyncFnCache = HashMap<String, Box<dyn RsAsyncFunction>>;
 
+type MissingFn = dyn Fn(&str, &[serde_json::Value]) -> Result<serde_json::Value, Error>;
+
+/// Called in place of functions that are not registered, see [`crate::Runtime::set_missing_function_hook`]
+pub(crate) struct MissingFunctionHook(pub Box<MissingFn>);
+
+/// Freezes the built-in intrinsics, for [`crate::RuntimeOptions::harden`]
+const HARDEN: &str = include_str!("harden.js");
+
+mod blocking;
 mod callbacks;
 
+mod queue;
+pub use queue::QueueReceiver;
+pub(crate) use queue::{channel as queue_channel, QueueSender, QueueSlot};
+
+mod progress;
+use progress::ProgressResource;
+pub use progress::ProgressSender;
+pub(crate) use progress::{ProgressFn, ProgressFnCache};
+
+mod abort;
+pub(crate) use abort::AbortResource;
+pub use abort::{AbortHandle, AbortSignal};
+
+mod log;
+pub(crate) use log::LogBuffer;
+pub use log::{LogLevel, LogRecord};
+
+mod trace;
+pub(crate) use trace::{hash_bytes, TraceState};
+pub use trace::{ExecutionTrace, TraceEvent, TraceMode};
+
+mod exit;
+pub use exit::ExitPolicy;
+pub(crate) use exit::{ExitState, ScriptExit};
+
+mod panic;
+pub use panic::CallbackPanic;
+pub(crate) use panic::PanicHook;
+use panic::{catch, catch_async, catch_sync};
+
+mod reentrant;
+pub use reentrant::ReentrantScope;
+pub(crate) use reentrant::{ReentrantFn, ReentrantFnCache};
+
+mod captured;
+pub(crate) use captured::CapturedFunctions;
+
+mod meter;
+pub(crate) use meter::Meter;
+
+mod stall;
+pub(crate) use stall::TimerFirings;
+
+mod context;
+pub(crate) use context::ContextGate;
+
+mod thrown;
+pub(crate) use thrown::error_class_name;
+use thrown::into_js_error;
+
+/// Command-line style arguments given to a module, see [`crate::Runtime::load_module_with_args`]
+#[derive(Clone, Default)]
+pub(crate) struct ScriptArgs {
+    pub module: Option<String>,
+    pub args: Vec<String>,
+}
+
 /// Registers a JS function with the runtime as being the entrypoint for the module
 ///
 /// # Arguments
@@ -25,14 +93,38 @@ fn call_registered_function(
     #[string] name: &str,
     #[serde] args: Vec<serde_json::Value>,
     state: &mut OpState,
+) -> Result<serde_json::Value, AnyError> {
+    let result = match state.try_borrow::<TraceState>().cloned() {
+        Some(trace) => trace.host_call(name, &args, || {
+            catch_sync(state, name, |state| sync_call(state, name, &args))
+        }),
+        None => catch_sync(state, name, |state| sync_call(state, name, &args)),
+    };
+    result.map_err(into_js_error)
+}
+
+/// Calls a registered function, falling back to the missing function hook
+fn sync_call(
+    state: &mut OpState,
+    name: &str,
+    args: &[serde_json::Value],
 ) -> Result<serde_json::Value, Error> {
+    CapturedFunctions::verify(state, args)?;
+    if !ActiveCapabilities::permits_function(state, name) {
+        return Err(Error::ValueNotCallable(name.to_string()));
+    }
+
     if state.has::<FnCache>() {
         let table = state.borrow_mut::<FnCache>();
         if let Some(callback) = table.get(name) {
-            return callback(&args);
+            return callback(args);
         }
     }
 
+    if let Some(MissingFunctionHook(hook)) = state.try_borrow::<MissingFunctionHook>() {
+        return hook(name, args);
+    }
+
     Err(Error::ValueNotCallable(name.to_string()))
 }
 
@@ -42,17 +134,324 @@ fn call_registered_function_async(
     #[string] name: String,
     #[serde] args: Vec<serde_json::Value>,
     state: &mut OpState,
-) -> impl std::future::Future<Output = Result<serde_json::Value, Error>> {
-    if state.has::<AsyncFnCache>() {
+) -> impl std::future::Future<Output = Result<serde_json::Value, AnyError>> {
+    let future = async_call(state, name, args);
+    async move { future.await.map_err(into_js_error) }
+}
+
+/// Calls an async function, blocking until it resolves - see `rustyscript.blocking_functions`
+/// Fails if the call does not complete within `deadline` milliseconds, 5 seconds by default
+#[op2]
+#[serde]
+fn call_registered_function_blocking(
+    #[string] name: String,
+    #[serde] args: Vec<serde_json::Value>,
+    deadline: Option<f64>,
+    state: &mut OpState,
+) -> Result<serde_json::Value, AnyError> {
+    let deadline = match deadline {
+        Some(ms) => std::time::Duration::try_from_secs_f64(ms / 1000.0)
+            .map_err(|_| Error::Runtime(format!("Invalid deadline: {ms}")))?,
+        None => blocking::DEFAULT_DEADLINE,
+    };
+
+    let future = async_call(state, name.clone(), args);
+    blocking::block_on_with_deadline(&name, future, deadline).map_err(into_js_error)
+}
+
+/// Calls a function registered with [`crate::Runtime::register_reentrant_function`]
+/// The state is only borrowed while looking the function up, so the function can call back into the runtime
+#[op2]
+#[serde]
+fn call_reentrant_function(
+    scope: &mut v8::HandleScope,
+    state: Rc<RefCell<OpState>>,
+    #[string] name: String,
+    args: v8::Local<v8::Array>,
+) -> Result<serde_json::Value, AnyError> {
+    let (callback, hook, trace) = {
+        let state = state.borrow();
+        let callback = state
+            .try_borrow::<ReentrantFnCache>()
+            .and_then(|table| table.get(&name))
+            .filter(|_| ActiveCapabilities::permits_function(&state, &name))
+            .cloned();
+        let trace = state.try_borrow::<TraceState>().cloned();
+        (callback, PanicHook::get(&state), trace)
+    };
+    let Some(callback) = callback else {
+        return Err(Error::ValueNotCallable(name).into());
+    };
+
+    let (args, values) = reentrant::read_args(scope, args)?;
+    CapturedFunctions::verify(&mut state.borrow_mut(), &args)?;
+    let call = || {
+        catch(hook.as_ref(), &name, || {
+            callback(&mut ReentrantScope::new(scope, values), &args)
+        })
+    };
+    let result = match trace {
+        Some(trace) => trace.host_call(&name, &args, call),
+        None => call(),
+    };
+    result.map_err(into_js_error)
+}
+
+/// Starts a call to a registered async function, recording it if a trace is enabled
+fn async_call(
+    state: &mut OpState,
+    name: String,
+    args: Vec<serde_json::Value>,
+) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<serde_json::Value, Error>>>> {
+    if let Some(trace) = state.try_borrow::<TraceState>().cloned() {
+        let traced_args = args.clone();
+        return trace.host_call_async(name.clone(), &traced_args, || {
+            catch_async(state, name, |state, name| {
+                start_async_call(state, name, args)
+            })
+        });
+    }
+    catch_async(state, name, |state, name| {
+        start_async_call(state, name, args)
+    })
+}
+
+/// Starts a call to a registered async function, falling back to the missing function hook
+fn start_async_call(
+    state: &mut OpState,
+    name: String,
+    args: Vec<serde_json::Value>,
+) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<serde_json::Value, Error>>>> {
+    if let Err(e) = CapturedFunctions::verify(state, &args) {
+        return Box::pin(std::future::ready(Err(e)));
+    }
+
+    let permitted = ActiveCapabilities::permits_function(state, &name);
+    if permitted && state.has::<AsyncFnCache>() {
         let table = state.borrow_mut::<AsyncFnCache>();
         if let Some(callback) = table.get(&name) {
             return callback(args);
         }
     }
 
+    if let Some(MissingFunctionHook(hook)) = state.try_borrow::<MissingFunctionHook>() {
+        if permitted {
+            return Box::pin(std::future::ready(hook(&name, &args)));
+        }
+    }
+
+    Box::pin(std::future::ready(Err(Error::ValueNotCallable(name))))
+}
+
+/// Returns the functions registered in a namespace, as `(name, is_async)` pairs sorted by name
+/// Functions the running call is not permitted to use are left out
+/// See [`crate::Runtime::register_function_in`]
+pub(crate) fn namespace_functions(state: &OpState, namespace: &str) -> Vec<(String, bool)> {
+    let prefix = format!("{namespace}.");
+    let sync = state
+        .try_borrow::<FnCache>()
+        .into_iter()
+        .flat_map(|t| t.keys().map(|k| (k, false)));
+    let r#async = state
+        .try_borrow::<AsyncFnCache>()
+        .into_iter()
+        .flat_map(|t| t.keys().map(|k| (k, true)));
+
+    let mut functions: Vec<_> = sync
+        .chain(r#async)
+        .filter(|(name, _)| ActiveCapabilities::permits_function(state, name))
+        .filter_map(|(name, is_async)| {
+            let name = name.strip_prefix(&prefix)?;
+            (!name.contains('.')).then(|| (name.to_string(), is_async))
+        })
+        .collect();
+    functions.sort();
+    functions
+}
+
+/// Returns the module and arguments read by `rustyscript.args` and `process.argv`
+#[op2]
+#[serde]
+fn op_script_args(state: &OpState) -> (Option<String>, Vec<String>) {
+    let args = state
+        .try_borrow::<ScriptArgs>()
+        .cloned()
+        .unwrap_or_default();
+    (args.module, args.args)
+}
+
+#[op2]
+#[serde]
+fn op_namespace_functions(state: &mut OpState, #[string] namespace: &str) -> Vec<(String, bool)> {
+    namespace_functions(state, namespace)
+}
+
+/// Opens the channel used to deliver progress events for a single call
+#[op2(fast)]
+#[smi]
+fn op_progress_open(state: &mut OpState) -> ResourceId {
+    state.resource_table.add(ProgressResource::new())
+}
+
+#[op2(async)]
+#[serde]
+fn call_registered_progress_function(
+    #[string] name: String,
+    #[serde] args: Vec<serde_json::Value>,
+    #[smi] rid: ResourceId,
+    state: &mut OpState,
+) -> impl std::future::Future<Output = Result<serde_json::Value, AnyError>> {
+    let future = catch_async(state, name, |state, name| {
+        start_progress_call(state, name, args, rid)
+    });
+    async move { future.await.map_err(into_js_error) }
+}
+
+/// Starts a call to a registered progress function, sending its events through the channel `rid`
+fn start_progress_call(
+    state: &mut OpState,
+    name: String,
+    args: Vec<serde_json::Value>,
+    rid: ResourceId,
+) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<serde_json::Value, Error>>>> {
+    // Taken before anything else, so the channel closes if the call fails
+    let sender = state
+        .resource_table
+        .get::<ProgressResource>(rid)
+        .ok()
+        .and_then(|resource| resource.take_sender());
+
+    if let Err(e) = CapturedFunctions::verify(state, &args) {
+        return Box::pin(std::future::ready(Err(e)));
+    }
+
+    if let (Some(sender), Some(table)) = (sender, state.try_borrow::<ProgressFnCache>()) {
+        if let Some(callback) = table.get(&name) {
+            if ActiveCapabilities::permits_function(state, &name) {
+                // Ends the event stream once the function completes, even if the sender was cloned
+                let finish = sender.clone();
+                let future = callback(args, sender);
+                return Box::pin(async move {
+                    let result = future.await;
+                    finish.finish();
+                    result
+                });
+            }
+        }
+    }
+
     Box::pin(std::future::ready(Err(Error::ValueNotCallable(name))))
 }
 
+#[op2(async)]
+#[serde]
+async fn op_progress_next(
+    state: Rc<RefCell<OpState>>,
+    #[smi] rid: ResourceId,
+) -> Result<Option<Vec<serde_json::Value>>, Error> {
+    let resource = state.borrow().resource_table.get::<ProgressResource>(rid)?;
+    Ok(resource.next_batch().await)
+}
+
+/// Resolves once the signal is aborted from rust, with the reason - or with null if it can no longer be aborted
+#[op2(async)]
+#[serde]
+async fn op_abort_wait(
+    state: Rc<RefCell<OpState>>,
+    #[smi] rid: ResourceId,
+) -> Result<Option<serde_json::Value>, Error> {
+    let resource = state.borrow().resource_table.get::<AbortResource>(rid)?;
+    Ok(resource
+        .wait()
+        .await
+        .map(|reason| serde_json::json!({ "reason": reason })))
+}
+
+#[op2(async)]
+async fn op_queue_push(
+    state: Rc<RefCell<OpState>>,
+    #[serde] item: serde_json::Value,
+) -> Result<(), Error> {
+    let sender = state
+        .borrow()
+        .try_borrow::<QueueSender>()
+        .cloned()
+        .ok_or_else(|| Error::Runtime("The message queue is not available".to_string()))?;
+
+    // Resolves only once there is space in the queue
+    sender
+        .0
+        .send(item)
+        .await
+        .map_err(|_| Error::Runtime("The message queue has been closed".to_string()))
+}
+
+/// Writes a record into the log buffer, see [`crate::Runtime::drain_logs`]
+#[op2]
+fn op_host_log(
+    state: &mut OpState,
+    #[serde] level: LogLevel,
+    #[string] message: String,
+    #[serde] fields: Option<serde_json::Map<String, serde_json::Value>>,
+) {
+    if let Some(buffer) = state.try_borrow_mut::<LogBuffer>() {
+        buffer.push(LogRecord {
+            level,
+            message,
+            fields: fields.unwrap_or_default(),
+            timestamp: std::time::SystemTime::now(),
+        });
+    }
+}
+
+/// Records a value from `Math.random` or `Date.now`, or returns the recorded one while replaying
+/// See [`crate::RuntimeOptions::trace`]
+#[op2(fast)]
+fn op_trace_value(state: &mut OpState, #[string] kind: &str, value: f64) -> Result<f64, Error> {
+    trace::trace_value(state, kind, value)
+}
+
+/// Records a timer firing, or checks it against the trace while replaying
+#[op2(fast)]
+fn op_trace_timer(state: &mut OpState, #[number] id: u64) -> Result<(), Error> {
+    match state.try_borrow::<TraceState>() {
+        Some(trace) => trace.check(TraceEvent::Timer { id }),
+        None => Ok(()),
+    }
+}
+
+/// Returns the time elapsed since the running benchmark started, in nanoseconds
+/// See [`crate::Runtime::bench_function`]
+#[op2(fast)]
+fn op_bench_now(state: &mut OpState) -> Result<f64, Error> {
+    state
+        .try_borrow::<crate::bench::BenchClock>()
+        .map(crate::bench::BenchClock::now)
+        .ok_or_else(|| Error::Runtime("No benchmark is running".to_string()))
+}
+
+/// Replaces `Deno.core.print`, so output can be captured by [`crate::Runtime::call_function_with_result`]
+#[op2(fast)]
+fn op_print2(
+    state: &mut OpState,
+    #[string] msg: &str,
+    is_err: bool,
+) -> Result<(), deno_core::anyhow::Error> {
+    use std::io::Write;
+    if is_err {
+        let mut stderr = std::io::stderr();
+        stderr.write_all(msg.as_bytes())?;
+        stderr.flush()?;
+    } else if let Some(crate::call_result::ConsoleCapture(output)) = state.try_borrow_mut() {
+        output.push_str(msg);
+    } else {
+        let mut stdout = std::io::stdout();
+        stdout.write_all(msg.as_bytes())?;
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
 #[op2(fast)]
 fn op_panic2(#[string] msg: &str) -> Result<(), deno_core::anyhow::Error> {
     Err(anyhow!(msg.to_string()))
@@ -60,11 +459,16 @@ fn op_panic2(#[string] msg: &str) -> Result<(), deno_core::anyhow::Error> {
 
 extension!(
     rustyscript,
-    ops = [op_register_entrypoint, call_registered_function, call_registered_function_async],
+    ops = [op_register_entrypoint, call_registered_function, call_registered_function_async, call_registered_function_blocking, call_reentrant_function, op_queue_push, op_progress_open, call_registered_progress_function, op_progress_next, op_abort_wait, op_bench_now, op_namespace_functions, op_script_args, op_host_log, op_trace_value, op_trace_timer, stall::op_stall_timer_fired, captured::op_capture_function, meter::op_meter, context::op_context_gate],
     esm_entry_point = "ext:rustyscript/rustyscript.js",
     esm = [ dir "src/ext/rustyscript", "rustyscript.js" ],
+    state = |state| {
+        state.put(meter::Meter::default());
+        state.put(context::ContextGate::default());
+    },
     middleware = |op| match op.name {
         "op_panic" => op.with_implementation_from(&op_panic2()),
+        "op_print" => op.with_implementation_from(&op_print2()),
         _ => op,
     }
 );
@@ -74,6 +478,26 @@ impl ExtensionTrait<()> for rustyscript {
     }
 }
 
+/// Applies the restrictions requested by [`crate::RuntimeOptions::harden`] and [`crate::RuntimeOptions::disallow_dynamic_code`]
+pub fn restrict(
+    runtime: &mut deno_core::JsRuntime,
+    harden: bool,
+    disallow_dynamic_code: bool,
+) -> Result<(), Error> {
+    if harden {
+        runtime.execute_script("ext:rustyscript/harden.js", HARDEN)?;
+    }
+
+    // Makes `eval` and `new Function` throw an `EvalError` - dynamic imports are rejected by the loader
+    if disallow_dynamic_code {
+        let context = runtime.main_context();
+        let scope = &mut runtime.handle_scope();
+        v8::Local::new(scope, context).set_allow_generation_from_strings(false);
+    }
+
+    Ok(())
+}
+
 pub fn extensions(is_snapshot: bool) -> Vec<Extension> {
     vec![rustyscript::build((), is_snapshot)]
 }
@@ -0,0 +1,102 @@
+use crate::{error::JsErrorBuilder, Error};
+use deno_core::{futures::FutureExt, serde_json, OpState};
+use std::{
+    any::Any,
+    future::Future,
+    panic::{catch_unwind, AssertUnwindSafe},
+    pin::Pin,
+    rc::Rc,
+};
+
+type CallFuture = Pin<Box<dyn Future<Output = Result<serde_json::Value, Error>>>>;
+
+/// The name of the error thrown in javascript when a rust function panics
+const PANIC_ERROR_NAME: &str = "PanicError";
+
+/// Details of a panic caught in a registered rust function, see [`crate::Runtime::set_panic_hook`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallbackPanic {
+    /// The name the function was called by
+    pub function: String,
+
+    /// The panic message, or `unknown panic` if the payload was not a string
+    pub message: String,
+}
+
+/// Notified of panics in rust functions, see [`crate::Runtime::set_panic_hook`]
+#[derive(Clone)]
+pub(crate) struct PanicHook(pub Rc<dyn Fn(&CallbackPanic)>);
+
+impl PanicHook {
+    pub(crate) fn get(state: &OpState) -> Option<Self> {
+        state.try_borrow::<Self>().cloned()
+    }
+}
+
+/// Runs a call to a rust function, turning a panic into an error thrown in javascript
+pub(crate) fn catch_sync(
+    state: &mut OpState,
+    name: &str,
+    f: impl FnOnce(&mut OpState) -> Result<serde_json::Value, Error>,
+) -> Result<serde_json::Value, Error> {
+    let hook = PanicHook::get(state);
+    catch(hook.as_ref(), name, || f(state))
+}
+
+/// As [`catch_sync`], for calls made while the state is not borrowed - see [`PanicHook::get`]
+pub(crate) fn catch(
+    hook: Option<&PanicHook>,
+    name: &str,
+    f: impl FnOnce() -> Result<serde_json::Value, Error>,
+) -> Result<serde_json::Value, Error> {
+    catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or_else(|payload| Err(panicked(hook, name, payload.as_ref())))
+}
+
+/// Starts a call to an async rust function, turning a panic - while starting it or while polling it -
+/// into an error thrown in javascript
+pub(crate) fn catch_async(
+    state: &mut OpState,
+    name: String,
+    f: impl FnOnce(&mut OpState, String) -> CallFuture,
+) -> CallFuture {
+    let hook = PanicHook::get(state);
+    let future = match catch_unwind(AssertUnwindSafe(|| f(state, name.clone()))) {
+        Ok(future) => future,
+        Err(payload) => {
+            let e = panicked(hook.as_ref(), &name, payload.as_ref());
+            return Box::pin(std::future::ready(Err(e)));
+        }
+    };
+
+    Box::pin(async move {
+        match AssertUnwindSafe(future).catch_unwind().await {
+            Ok(result) => result,
+            Err(payload) => Err(panicked(hook.as_ref(), &name, payload.as_ref())),
+        }
+    })
+}
+
+/// Notifies the hook of a panic, and builds the error thrown in its place
+fn panicked(hook: Option<&PanicHook>, name: &str, payload: &(dyn Any + Send)) -> Error {
+    let message = payload
+        .downcast_ref::<&str>()
+        .map(ToString::to_string)
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string());
+
+    let panic = CallbackPanic {
+        function: name.to_string(),
+        message,
+    };
+    if let Some(PanicHook(hook)) = hook {
+        hook(&panic);
+    }
+
+    JsErrorBuilder::new(format!(
+        "Function `{}` panicked: {}",
+        panic.function, panic.message
+    ))
+    .with_name(PANIC_ERROR_NAME)
+    .build()
+}
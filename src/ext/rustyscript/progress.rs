@@ -0,0 +1,97 @@
+use crate::Error;
+use deno_core::{serde_json, AsyncRefCell, RcRef, Resource};
+use std::{cell::RefCell, collections::HashMap, future::Future, pin::Pin, rc::Rc};
+use tokio::sync::mpsc;
+
+/// A registered function reporting progress, see [`crate::Runtime::register_progress_function`]
+pub(crate) type ProgressFn = Box<
+    dyn Fn(
+        Vec<serde_json::Value>,
+        ProgressSender,
+    ) -> Pin<Box<dyn Future<Output = Result<serde_json::Value, Error>>>>,
+>;
+pub(crate) type ProgressFnCache = HashMap<String, ProgressFn>;
+
+/// Reports progress events to the script awaiting a function registered with [`crate::Runtime::register_progress_function`]
+///
+/// Events are delivered in order, before the function's final result
+/// The sender can be cloned, and is `Send` - so it can be moved into spawned tasks or threads
+#[derive(Clone, Debug)]
+pub struct ProgressSender(mpsc::UnboundedSender<Option<serde_json::Value>>);
+
+impl ProgressSender {
+    /// Sends a progress event to the script
+    ///
+    /// # Errors
+    /// Will return an error if the event cannot be serialized,
+    /// or if the script's call has already completed
+    pub fn send<T>(&self, event: &T) -> Result<(), Error>
+    where
+        T: serde::Serialize,
+    {
+        let event = serde_json::to_value(event)?;
+        self.0
+            .send(Some(event))
+            .map_err(|_| Error::Runtime("The progress receiver has been closed".to_string()))
+    }
+
+    /// Returns true once the script's call has completed, and events can no longer be delivered
+    #[must_use]
+    pub fn is_closed(&self) -> bool {
+        self.0.is_closed()
+    }
+
+    /// Marks the end of the call - queued events are still delivered, but no events sent after it
+    pub(crate) fn finish(&self) {
+        self.0.send(None).ok();
+    }
+}
+
+/// Holds the progress channel of a single call from javascript
+pub(crate) struct ProgressResource {
+    sender: RefCell<Option<ProgressSender>>,
+    receiver: AsyncRefCell<mpsc::UnboundedReceiver<Option<serde_json::Value>>>,
+}
+
+impl ProgressResource {
+    pub(crate) fn new() -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        Self {
+            sender: RefCell::new(Some(ProgressSender(tx))),
+            receiver: AsyncRefCell::new(rx),
+        }
+    }
+
+    /// Takes the sending half, which is handed to the registered function
+    /// Once every sender is dropped, [`ProgressResource::next_batch`] returns None
+    pub(crate) fn take_sender(&self) -> Option<ProgressSender> {
+        self.sender.borrow_mut().take()
+    }
+
+    /// Waits for at least one progress event, and returns every event currently waiting
+    /// Returns None once the function has completed and all events have been received
+    pub(crate) async fn next_batch(self: Rc<Self>) -> Option<Vec<serde_json::Value>> {
+        let mut receiver = RcRef::map(&self, |r| &r.receiver).borrow_mut().await;
+        let Some(event) = receiver.recv().await? else {
+            receiver.close();
+            return None;
+        };
+
+        let mut batch = vec![event];
+        while let Ok(event) = receiver.try_recv() {
+            let Some(event) = event else {
+                // Events already queued are still received, then the channel ends
+                receiver.close();
+                break;
+            };
+            batch.push(event);
+        }
+        Some(batch)
+    }
+}
+
+impl Resource for ProgressResource {
+    fn name(&self) -> std::borrow::Cow<'_, str> {
+        "progress".into()
+    }
+}
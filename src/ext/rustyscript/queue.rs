@@ -0,0 +1,72 @@
+use crate::Error;
+use deno_core::serde_json;
+use tokio::sync::mpsc;
+
+/// Sending half of the JS to Rust message queue, kept in the runtime's `OpState`
+#[derive(Clone)]
+pub(crate) struct QueueSender(pub mpsc::Sender<serde_json::Value>);
+
+/// Receiving half of the JS to Rust message queue, until it is claimed by the host
+pub(crate) struct QueueSlot(pub Option<QueueReceiver>);
+
+/// Creates the bounded message queue used by `rustyscript.queue.push`
+pub(crate) fn channel(capacity: usize) -> (QueueSender, QueueSlot) {
+    let (tx, rx) = mpsc::channel(capacity.max(1));
+    (QueueSender(tx), QueueSlot(Some(QueueReceiver(rx))))
+}
+
+/// Receives items pushed from javascript with `rustyscript.queue.push(item)`
+///
+/// The queue is bounded (see [`crate::RuntimeOptions::queue_capacity`]) - when it is full,
+/// the promise returned by `push` only resolves once an item has been received here
+///
+/// Obtained with [`crate::Runtime::queue_receiver`]
+/// The receiver is `Send`, so items can be consumed on another thread
+#[derive(Debug)]
+pub struct QueueReceiver(mpsc::Receiver<serde_json::Value>);
+
+impl QueueReceiver {
+    /// Waits for the next item in the queue
+    /// Returns None once the runtime has been dropped and the queue is empty
+    pub async fn recv(&mut self) -> Option<serde_json::Value> {
+        self.0.recv().await
+    }
+
+    /// Waits for the next item in the queue, and deserializes it into the given type
+    ///
+    /// # Errors
+    /// Will return an error if the item cannot be deserialized into the given type
+    pub async fn recv_as<T>(&mut self) -> Option<Result<T, Error>>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let value = self.0.recv().await?;
+        Some(serde_json::from_value(value).map_err(Error::from))
+    }
+
+    /// Blocks the current thread until the next item is available
+    /// Returns None once the runtime has been dropped and the queue is empty
+    ///
+    /// # Panics
+    /// Will panic if called from within an async context
+    pub fn blocking_recv(&mut self) -> Option<serde_json::Value> {
+        self.0.blocking_recv()
+    }
+
+    /// Returns the next item in the queue, if one is available
+    pub fn try_recv(&mut self) -> Option<serde_json::Value> {
+        self.0.try_recv().ok()
+    }
+
+    /// Returns the number of items waiting in the queue
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns true if no items are waiting in the queue
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
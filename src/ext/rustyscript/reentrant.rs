@@ -0,0 +1,116 @@
+use crate::{
+    inner_runtime::{caught_error, decode_args},
+    js_value::{decode_v8, Function},
+    Error,
+};
+use deno_core::{serde_json, v8};
+use std::{collections::HashMap, rc::Rc};
+
+/// A registered function that can call back into the runtime, see [`crate::Runtime::register_reentrant_function`]
+pub(crate) type ReentrantFn =
+    Rc<dyn Fn(&mut ReentrantScope, &[serde_json::Value]) -> Result<serde_json::Value, Error>>;
+pub(crate) type ReentrantFnCache = HashMap<String, ReentrantFn>;
+
+/// Lets a function registered with [`crate::Runtime::register_reentrant_function`] call back into the script calling it
+///
+/// Code runs synchronously, on top of the calling script's stack - promises it creates are returned unresolved,
+/// and timers do not fire until the function returns
+pub struct ReentrantScope<'s, 'a> {
+    scope: &'s mut v8::HandleScope<'a>,
+    args: Vec<v8::Global<v8::Value>>,
+}
+
+impl<'s, 'a> ReentrantScope<'s, 'a> {
+    pub(crate) fn new(
+        scope: &'s mut v8::HandleScope<'a>,
+        args: Vec<v8::Global<v8::Value>>,
+    ) -> Self {
+        Self { scope, args }
+    }
+
+    /// Returns the number of arguments the function was called with
+    #[must_use]
+    pub fn argument_count(&self) -> usize {
+        self.args.len()
+    }
+
+    /// Decodes an argument the function was called with
+    /// Unlike the JSON arguments, this can capture values such as a [`Function`] passed by the script
+    ///
+    /// # Errors
+    /// Will return an error if there is no argument at that index, or it cannot be deserialized into the given type
+    pub fn argument<T>(&mut self, index: usize) -> Result<T, Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let arg = self
+            .args
+            .get(index)
+            .ok_or_else(|| Error::Runtime(format!("No argument at index {index}")))?;
+        let arg = v8::Local::new(self.scope, arg);
+        decode_v8(self.scope, arg)
+    }
+
+    /// Evaluates a piece of non-ECMAScript-module JavaScript code in the global context
+    ///
+    /// # Errors
+    /// Will return an error if the code throws, or the result cannot be deserialized into the given type
+    pub fn eval<T>(&mut self, code: &str) -> Result<T, Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let scope = &mut v8::TryCatch::new(&mut *self.scope);
+        let source = v8::String::new(scope, code)
+            .ok_or_else(|| Error::Runtime("Could not allocate the source".to_string()))?;
+
+        let value = v8::Script::compile(scope, source, None)
+            .and_then(|script| script.run(scope))
+            .ok_or_else(|| caught_error(scope, None))?;
+        decode_v8(scope, value)
+    }
+
+    /// Calls a function, such as one passed as an argument - see [`Self::argument`]
+    ///
+    /// # Errors
+    /// Will return an error if the function throws, or the result cannot be deserialized into the given type
+    pub fn call<T>(
+        &mut self,
+        function: &Function,
+        args: &impl serde::ser::Serialize,
+    ) -> Result<T, Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let scope = &mut v8::TryCatch::new(&mut *self.scope);
+        let function = function.as_global(scope);
+        let function = v8::Local::new(scope, function);
+        let args = decode_args(args, scope)?;
+
+        let receiver = v8::undefined(scope).into();
+        let value = function
+            .call(scope, receiver, &args)
+            .ok_or_else(|| caught_error(scope, None))?;
+        decode_v8(scope, value)
+    }
+}
+
+/// Reads the arguments of a call both as JSON, and as the values given - functions are `null` in the JSON
+pub(crate) fn read_args(
+    scope: &mut v8::HandleScope,
+    args: v8::Local<v8::Array>,
+) -> Result<(Vec<serde_json::Value>, Vec<v8::Global<v8::Value>>), Error> {
+    let mut json = Vec::with_capacity(args.length() as usize);
+    let mut values = Vec::with_capacity(args.length() as usize);
+    for i in 0..args.length() {
+        let arg = args
+            .get_index(scope, i)
+            .ok_or_else(|| Error::Runtime(format!("Invalid argument at index {i}")))?;
+        json.push(if arg.is_function() {
+            serde_json::Value::Null
+        } else {
+            deno_core::serde_v8::from_v8(scope, arg)?
+        });
+        values.push(v8::Global::new(scope, arg));
+    }
+    Ok((json, values))
+}
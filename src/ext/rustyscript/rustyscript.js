@@ -25,25 +25,237 @@ const getterOnly = (getter) => {
 const applyToGlobal = (properties) => Object.defineProperties(globalThis, properties);
 const applyToDeno = (properties) => Object.defineProperties(globalThis.Deno, properties);
 
+// Structured errors returned by rust functions, built with `JsErrorBuilder`
+// The error crosses over as JSON, and is rebuilt here with its name, message, code and data
+class HostError extends Error {
+    constructor(name, message, code, data) {
+        super(message);
+        this.name = name;
+        if (code !== undefined) this.code = code;
+        if (data !== undefined) this.data = data;
+    }
+}
+Deno.core.registerErrorBuilder('RustyscriptHostError', (payload) => {
+    const { name, message, code, data } = JSON.parse(payload);
+    return new HostError(name, message, code, data);
+});
+
+// Arguments cross over as JSON, so functions passed to rust functions are replaced by a token
+// The function is kept until rust takes it with `Runtime::take_function` - see `js_value::FunctionToken`
+// Only tokens captured here, for the call being made, are accepted by the call
+const captureFunctions = (args) => {
+    if (!args.some((arg) => typeof arg === 'function')) return args;
+    return args.map((arg) => typeof arg === 'function'
+        ? { '$rustyscript_function': Deno.core.ops.op_capture_function(arg) }
+        : arg);
+};
+
+// Calls a function registered with `Runtime::register_progress_function`
+// The returned promise resolves to the final result, and also exposes the progress events:
+// - `onProgress(listener)` calls the listener with each event
+// - `for await (const event of call)` iterates over the events, until the function completes
+// Every event sent before the function completed is delivered before the promise settles
+const callProgressFunction = (name, args) => {
+    const rid = Deno.core.ops.op_progress_open();
+    const result = Deno.core.ops.call_registered_progress_function(name, captureFunctions(args), rid);
+
+    const events = [];
+    const listeners = [];
+    let waiting = [];
+    let done = false;
+    const wake = () => {
+        waiting.forEach((resolve) => resolve());
+        waiting = [];
+    };
+
+    const drained = (async () => {
+        for (;;) {
+            let batch = null;
+            try {
+                batch = await Deno.core.ops.op_progress_next(rid);
+            } catch {}
+            if (batch === null) break;
+
+            for (const event of batch) {
+                events.push(event);
+                listeners.forEach((listener) => listener(event));
+            }
+            wake();
+        }
+
+        done = true;
+        Deno.core.tryClose(rid);
+        wake();
+    })();
+
+    const call = (async () => {
+        try {
+            return await result;
+        } finally {
+            await drained;
+        }
+    })();
+
+    call.onProgress = (listener) => {
+        listeners.push(listener);
+        return call;
+    };
+    call[Symbol.asyncIterator] = async function* () {
+        for (let i = 0; ; ) {
+            if (i < events.length) yield events[i++];
+            else if (done) return;
+            else await new Promise((resolve) => waiting.push(resolve));
+        }
+    };
+    return call;
+};
+
+// Execution cost, counted by modules instrumented with `RuntimeOptions::metering`
+// The count and the limit are kept by the host, so scripts cannot reset them
+// The op is bound now, before any script could replace it
+const { op_meter, op_context_gate } = Deno.core.ops;
+Object.defineProperty(globalThis, '__rustyscript_meter', { value: () => op_meter() });
+
+// Creates an `AbortSignal` that rust can abort - see `Runtime::create_abort_signal`
+// The wait is unref'd, so a pending signal does not keep the event loop alive
+Object.defineProperty(globalThis, '__rustyscript_abort_signal', { value: (rid) => {
+    const controller = new AbortController();
+    const wait = Deno.core.ops.op_abort_wait(rid);
+    Deno.core.unrefOpPromise(wait);
+    wait.then((abort) => {
+        Deno.core.tryClose(rid);
+        if (abort !== null) controller.abort(abort.reason ?? undefined);
+    }, () => Deno.core.tryClose(rid));
+    return controller.signal;
+} });
+
+// The ambient context set by `Runtime::with_context`, read with `rustyscript.context()`
+// Kept in an async variable, so promises and timers created during a call keep the context of that call
+// Only the host can enter or leave a context - the op refuses calls it did not open the gate for
+const callContext = new Deno.core.AsyncVariable();
+const previousContexts = [];
+const deepFreeze = (value) => {
+    if (value !== null && typeof value === 'object' && !Object.isFrozen(value)) {
+        Object.freeze(value);
+        Object.values(value).forEach(deepFreeze);
+    }
+    return value;
+};
+Object.defineProperty(globalThis, '__rustyscript_enter_context', { value: (context) => {
+    if (!op_context_gate()) throw new Error('Contexts can only be set by the host');
+    previousContexts.push(callContext.enter(deepFreeze(context)));
+} });
+Object.defineProperty(globalThis, '__rustyscript_exit_context', { value: () => {
+    if (!op_context_gate()) throw new Error('Contexts can only be set by the host');
+    Deno.core.setAsyncContext(previousContexts.pop());
+} });
+
+// Builds `rustyscript.api[namespace]`, from the functions registered with `Runtime::register_function_in`
+// Returns undefined for namespaces with no functions, so scripts can detect optional APIs
+const namespaceApi = (namespace) => {
+    const functions = Deno.core.ops.op_namespace_functions(namespace);
+    if (functions.length === 0) return undefined;
+
+    const api = {};
+    for (const [name, isAsync] of functions) {
+        const qualified = `${namespace}.${name}`;
+        api[name] = isAsync
+            ? (...args) => Deno.core.ops.call_registered_function_async(qualified, captureFunctions(args))
+            : (...args) => Deno.core.ops.call_registered_function(qualified, captureFunctions(args));
+    }
+    return Object.freeze(api);
+};
+
 // Populate the global object
 globalThis.rustyscript = {
     'register_entrypoint': (f) => Deno.core.ops.op_register_entrypoint(f),
     'bail': (msg) => { throw new Error(msg) },
+    'context': () => callContext.get() ?? null,
+    'HostError': HostError,
+
+    // Set by `Runtime::load_module_with_args` - empty by default
+    get 'args'() {
+        return Object.freeze(Deno.core.ops.op_script_args()[1]);
+    },
     
     'functions': new Proxy({}, {
         get: function(_target, name) {
-            return (...args) => Deno.core.ops.call_registered_function(name, args);
+            return (...args) => Deno.core.ops.call_registered_function(name, captureFunctions(args));
         }
     }),
 
     'async_functions': new Proxy({}, {
         get: function(_target, name) {
-            return (...args) => Deno.core.ops.call_registered_function_async(name, args);
+            return (...args) => Deno.core.ops.call_registered_function_async(name, captureFunctions(args));
+        }
+    }),
+
+    // Async functions called synchronously - the event loop is paused until the call completes,
+    // so the function must not wait on anything else the script does
+    'blocking_functions': new Proxy({}, {
+        get: function(_target, name) {
+            return (...args) => Deno.core.ops.call_registered_function_blocking(name, captureFunctions(args), null);
+        }
+    }),
+
+    // As `blocking_functions`, with a deadline in milliseconds
+    'call_blocking': (name, args = [], deadline = null) => {
+        return Deno.core.ops.call_registered_function_blocking(name, captureFunctions(args), deadline);
+    },
+
+    'api': new Proxy({}, {
+        get: function(_target, namespace) {
+            return typeof namespace === 'string' ? namespaceApi(namespace) : undefined;
+        }
+    }),
+
+    'progress_functions': new Proxy({}, {
+        get: function(_target, name) {
+            return (...args) => callProgressFunction(name, args);
+        }
+    }),
+
+    // Functions that can call back into the script, such as functions passed as arguments
+    'reentrant_functions': new Proxy({}, {
+        get: function(_target, name) {
+            return (...args) => Deno.core.ops.call_reentrant_function(name, args);
         }
-    })
+    }),
+
+    'queue': Object.freeze({
+        'push': (item) => Deno.core.ops.op_queue_push(item),
+    }),
 };
 Object.freeze(globalThis.rustyscript);
 
+// Structured logging, buffered until read with `Runtime::drain_logs`
+// `host.log(level, message, fields)`, or `host.log.info(message, fields)` and so on
+// Fields are converted as JSON - BigInts become strings, and errors keep their name and message
+const logFieldReplacer = (_key, value) => {
+    if (typeof value === 'bigint') {
+        return value.toString();
+    } else if (value instanceof Error) {
+        return { name: value.name, message: value.message, stack: value.stack };
+    }
+    return value;
+};
+
+const log = (level, message, fields = null) => {
+    if (fields !== null && typeof fields === 'object' && !Array.isArray(fields)) {
+        fields = JSON.parse(JSON.stringify(fields, logFieldReplacer));
+    } else {
+        fields = null;
+    }
+    Deno.core.ops.op_host_log(level, String(message), fields);
+};
+for (const level of ['trace', 'debug', 'info', 'warn', 'error']) {
+    log[level] = (message, fields) => log(level, message, fields);
+}
+
+const host = globalThis.host ?? {};
+Object.defineProperty(host, 'log', { value: Object.freeze(log), enumerable: true });
+applyToGlobal({ host: nonEnumerable(host) });
+
 export {
     nonEnumerable, readOnly, writeable, getterOnly, applyToGlobal, applyToDeno
 };
\ No newline at end of file
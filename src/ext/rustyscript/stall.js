@@ -0,0 +1,21 @@
+// Installed when `RuntimeOptions::module_stall_timeout` is set
+// Counts timer callbacks, so that a module waiting on a timer is only considered active while its timers fire
+(() => {
+    const { op_stall_timer_fired } = Deno.core.ops;
+
+    for (const name of ['setTimeout', 'setInterval']) {
+        const original = globalThis[name];
+        if (typeof original !== 'function') continue;
+
+        globalThis[name] = function (callback, ...rest) {
+            if (typeof callback !== 'function') {
+                return original.call(this, callback, ...rest);
+            }
+
+            return original.call(this, (...args) => {
+                op_stall_timer_fired();
+                return callback(...args);
+            }, ...rest);
+        };
+    }
+})();
@@ -0,0 +1,31 @@
+use deno_core::{op2, OpState};
+
+/// Counts timer callbacks, see [`crate::RuntimeOptions::module_stall_timeout`]
+const STALL: &str = include_str!("stall.js");
+
+/// The number of timer callbacks run so far
+///
+/// Kept in the op state, and compared between checks for stalled modules
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TimerFirings(u64);
+impl TimerFirings {
+    /// Installs the javascript side of the counter
+    pub fn install(runtime: &mut deno_core::JsRuntime) -> Result<(), crate::Error> {
+        runtime.op_state().borrow_mut().put(Self::default());
+        runtime.execute_script("ext:rustyscript/stall.js", STALL)?;
+        Ok(())
+    }
+
+    /// The current count
+    pub fn get(state: &OpState) -> Self {
+        state.try_borrow::<Self>().copied().unwrap_or_default()
+    }
+}
+
+/// Called before every `setTimeout` and `setInterval` callback
+#[op2(fast)]
+pub fn op_stall_timer_fired(state: &mut OpState) {
+    if let Some(firings) = state.try_borrow_mut::<TimerFirings>() {
+        firings.0 += 1;
+    }
+}
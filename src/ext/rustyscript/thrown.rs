@@ -0,0 +1,42 @@
+use crate::{error::ThrownError, Error};
+use deno_core::{anyhow, serde_json};
+
+/// The javascript error class structured errors are built with, see `rustyscript.js`
+const THROWN_ERROR_CLASS: &str = "RustyscriptHostError";
+
+/// Carries a [`ThrownError`] to javascript - the message is the error serialized as JSON,
+/// which the error builder registered in `rustyscript.js` turns back into a `HostError`
+#[derive(Debug)]
+struct ThrownPayload(String);
+
+impl std::fmt::Display for ThrownPayload {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ThrownPayload {}
+
+/// Converts the error returned by a rust function into the one thrown in javascript
+/// Structured errors keep their fields - anything else is thrown as a plain `Error`
+pub(crate) fn into_js_error(e: Error) -> anyhow::Error {
+    match e {
+        Error::Thrown(thrown) => anyhow::Error::new(ThrownPayload(payload(&thrown))),
+        e => e.into(),
+    }
+}
+
+fn payload(thrown: &ThrownError) -> String {
+    serde_json::to_string(thrown).unwrap_or_else(|_| {
+        serde_json::json!({ "name": thrown.name, "message": thrown.message }).to_string()
+    })
+}
+
+/// Names the javascript class used for an error returned by an OP
+pub(crate) fn error_class_name(e: &anyhow::Error) -> &'static str {
+    if e.is::<ThrownPayload>() {
+        THROWN_ERROR_CLASS
+    } else {
+        "Error"
+    }
+}
@@ -0,0 +1,28 @@
+// Installed when an execution trace is recorded or replayed - see `TraceMode`
+// While replaying, these return the recorded values instead of the live ones
+(() => {
+    const { op_trace_value, op_trace_timer } = Deno.core.ops;
+
+    const random = Math.random;
+    Math.random = () => op_trace_value('random', random());
+
+    const now = Date.now;
+    Date.now = () => op_trace_value('clock', now());
+
+    for (const name of ['setTimeout', 'setInterval']) {
+        const original = globalThis[name];
+        if (typeof original !== 'function') continue;
+
+        globalThis[name] = function (callback, ...rest) {
+            if (typeof callback !== 'function') {
+                return original.call(this, callback, ...rest);
+            }
+
+            const id = original.call(this, (...args) => {
+                op_trace_timer(id);
+                return callback(...args);
+            }, ...rest);
+            return id;
+        };
+    }
+})();
@@ -0,0 +1,372 @@
+use crate::Error;
+use deno_core::{serde_json, OpState};
+use serde::{Deserialize, Serialize};
+use std::{cell::RefCell, future::Future, pin::Pin, rc::Rc};
+
+/// Wraps the sources of nondeterminism in javascript, when a trace is being recorded or replayed
+const TRACE: &str = include_str!("trace.js");
+
+/// Whether a runtime records its execution, or replays a recorded one - see [`crate::RuntimeOptions::trace`]
+#[derive(Clone, Debug, Default)]
+pub enum TraceMode {
+    /// Nothing is recorded
+    #[default]
+    Off,
+
+    /// Every crossing between the host and javascript is recorded, see [`crate::Runtime::take_trace`]
+    Record,
+
+    /// The runtime is driven by a recorded trace
+    ///
+    /// Registered functions are not called - their recorded results are returned instead,
+    /// and `Math.random` and `Date.now` return their recorded values
+    ///
+    /// Calls, module loads, and timers are compared to the trace, and an error
+    /// is returned from the first one that does not match
+    Replay(ExecutionTrace),
+}
+
+/// A single crossing between the host and javascript
+///
+/// Arguments are stored as hashes, so traces can be kept without holding on to the data itself
+/// Results of registered functions are stored in full, since they are needed for replay
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum TraceEvent {
+    /// A module was loaded
+    ModuleLoaded {
+        /// The filename of the module
+        filename: String,
+
+        /// A hash of the module's source
+        source_hash: u64,
+    },
+
+    /// The host called a javascript function
+    Call {
+        /// The name of the function - empty for anonymous functions
+        function: String,
+
+        /// A hash of the arguments
+        args_hash: u64,
+    },
+
+    /// Javascript called a function registered by the host
+    HostCall {
+        /// The name the function was registered under
+        function: String,
+
+        /// A hash of the arguments
+        args_hash: u64,
+
+        /// The value returned, or the error message
+        result: Result<serde_json::Value, String>,
+    },
+
+    /// A timer set with `setTimeout` or `setInterval` fired
+    Timer {
+        /// The timer's id
+        id: u64,
+    },
+
+    /// `Math.random` was called
+    Random {
+        /// The value returned
+        value: f64,
+    },
+
+    /// `Date.now` was called
+    Clock {
+        /// The value returned
+        value: f64,
+    },
+}
+
+impl TraceEvent {
+    /// Events of each stream are replayed in order, independently of the other streams
+    /// This keeps replay deterministic when async work completes in a different order
+    fn stream(&self) -> usize {
+        match self {
+            Self::ModuleLoaded { .. } | Self::Call { .. } => 0,
+            Self::HostCall { .. } => 1,
+            Self::Timer { .. } => 2,
+            Self::Random { .. } => 3,
+            Self::Clock { .. } => 4,
+        }
+    }
+}
+
+/// A recorded execution, see [`TraceMode`]
+///
+/// Traces can be serialized, to be stored alongside logs and replayed elsewhere
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExecutionTrace {
+    /// The events, in the order they occurred
+    pub events: Vec<TraceEvent>,
+}
+
+enum TraceInner {
+    Recording(Vec<TraceEvent>),
+    Replaying {
+        events: Vec<TraceEvent>,
+        consumed: Vec<bool>,
+        cursors: [usize; 5],
+    },
+}
+
+/// The trace of a runtime, kept in its `OpState` while tracing is enabled
+#[derive(Clone)]
+pub(crate) struct TraceState(Rc<RefCell<TraceInner>>);
+
+impl TraceState {
+    /// Creates the trace state for the given mode, or None if tracing is off
+    pub fn new(mode: TraceMode) -> Option<Self> {
+        let inner = match mode {
+            TraceMode::Off => return None,
+            TraceMode::Record => TraceInner::Recording(Vec::new()),
+            TraceMode::Replay(trace) => TraceInner::Replaying {
+                consumed: vec![false; trace.events.len()],
+                events: trace.events,
+                cursors: [0; 5],
+            },
+        };
+        Some(Self(Rc::new(RefCell::new(inner))))
+    }
+
+    /// Installs the javascript side of the trace
+    pub fn install(runtime: &mut deno_core::JsRuntime) -> Result<(), Error> {
+        runtime.execute_script("ext:rustyscript/trace.js", TRACE)?;
+        Ok(())
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        matches!(*self.0.borrow(), TraceInner::Replaying { .. })
+    }
+
+    /// Records an event - or while replaying, returns the next recorded event of the same kind
+    pub fn next(&self, event: TraceEvent) -> Result<TraceEvent, Error> {
+        match &mut *self.0.borrow_mut() {
+            TraceInner::Recording(events) => {
+                events.push(event.clone());
+                Ok(event)
+            }
+            TraceInner::Replaying {
+                events,
+                consumed,
+                cursors,
+            } => {
+                let stream = event.stream();
+                let index = (cursors[stream]..events.len())
+                    .find(|&i| events[i].stream() == stream)
+                    .ok_or_else(|| {
+                        Error::Runtime(format!("Replay diverged: {event:?} is not in the trace"))
+                    })?;
+                cursors[stream] = index + 1;
+                consumed[index] = true;
+                Ok(events[index].clone())
+            }
+        }
+    }
+
+    /// Records an event, or checks that it matches the trace while replaying
+    pub fn check(&self, event: TraceEvent) -> Result<(), Error> {
+        let recorded = self.next(event.clone())?;
+        if recorded == event {
+            Ok(())
+        } else {
+            Err(Error::Runtime(format!(
+                "Replay diverged: expected {recorded:?}, found {event:?}"
+            )))
+        }
+    }
+
+    /// Calls a registered function, recording its result
+    /// While replaying, the recorded result is returned instead
+    pub fn host_call(
+        &self,
+        name: &str,
+        args: &[serde_json::Value],
+        call: impl FnOnce() -> Result<serde_json::Value, Error>,
+    ) -> Result<serde_json::Value, Error> {
+        if self.is_replaying() {
+            return self.replay_host_call(name, args);
+        }
+
+        let result = call();
+        self.record_host_call(name, args, &result);
+        result
+    }
+
+    /// As [`TraceState::host_call`], for async functions
+    pub fn host_call_async(
+        &self,
+        name: String,
+        args: &[serde_json::Value],
+        call: impl FnOnce() -> Pin<Box<dyn Future<Output = Result<serde_json::Value, Error>>>>,
+    ) -> Pin<Box<dyn Future<Output = Result<serde_json::Value, Error>>>> {
+        if self.is_replaying() {
+            return Box::pin(std::future::ready(self.replay_host_call(&name, args)));
+        }
+
+        let future = call();
+        let args = args.to_vec();
+        let this = self.clone();
+        Box::pin(async move {
+            let result = future.await;
+            this.record_host_call(&name, &args, &result);
+            result
+        })
+    }
+
+    fn record_host_call(
+        &self,
+        name: &str,
+        args: &[serde_json::Value],
+        result: &Result<serde_json::Value, Error>,
+    ) {
+        if let TraceInner::Recording(events) = &mut *self.0.borrow_mut() {
+            events.push(TraceEvent::HostCall {
+                function: name.to_string(),
+                args_hash: hash_json(args),
+                result: result.as_ref().cloned().map_err(ToString::to_string),
+            });
+        }
+    }
+
+    fn replay_host_call(
+        &self,
+        name: &str,
+        args: &[serde_json::Value],
+    ) -> Result<serde_json::Value, Error> {
+        let args_hash = hash_json(args);
+        let event = TraceEvent::HostCall {
+            function: name.to_string(),
+            args_hash,
+            result: Ok(serde_json::Value::Null),
+        };
+
+        match self.next(event.clone())? {
+            TraceEvent::HostCall {
+                function,
+                args_hash: recorded_hash,
+                result,
+            } if function == name && recorded_hash == args_hash => result.map_err(Error::Runtime),
+            recorded => Err(Error::Runtime(format!(
+                "Replay diverged: expected {recorded:?}, found {event:?}"
+            ))),
+        }
+    }
+
+    /// Returns the recorded events - or while replaying, the events not yet replayed
+    pub fn take(&self) -> ExecutionTrace {
+        let events = match &mut *self.0.borrow_mut() {
+            TraceInner::Recording(events) => std::mem::take(events),
+            TraceInner::Replaying {
+                events, consumed, ..
+            } => events
+                .iter()
+                .zip(consumed.iter())
+                .filter(|(_, consumed)| !**consumed)
+                .map(|(event, _)| event.clone())
+                .collect(),
+        };
+        ExecutionTrace { events }
+    }
+}
+
+/// A stable FNV-1a hash, so traces can be replayed by a different build
+pub(crate) fn hash_bytes(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn hash_json(value: &[serde_json::Value]) -> u64 {
+    hash_bytes(serde_json::to_string(value).unwrap_or_default().as_bytes())
+}
+
+/// Records a value produced by `Math.random` or `Date.now` - or while replaying, returns the recorded one
+pub(crate) fn trace_value(state: &OpState, kind: &str, value: f64) -> Result<f64, Error> {
+    let Some(trace) = state.try_borrow::<TraceState>() else {
+        return Ok(value);
+    };
+
+    let event = match kind {
+        "random" => TraceEvent::Random { value },
+        "clock" => TraceEvent::Clock { value },
+        _ => return Err(Error::Runtime(format!("Unknown trace value: {kind}"))),
+    };
+    match trace.next(event)? {
+        TraceEvent::Random { value } | TraceEvent::Clock { value } => Ok(value),
+        _ => unreachable!("streams only hold events of one kind"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{json_args, Module, Runtime, RuntimeOptions};
+
+    const MODULE: &str = "
+        export const roll = (sides) => {
+            const roll = Math.floor(Math.random() * sides) + 1;
+            return rustyscript.functions.record(roll, Date.now() > 0);
+        };
+        export const later = () => new Promise((resolve) => setTimeout(() => resolve(Math.random()), 5));
+    ";
+
+    fn run(trace: TraceMode, counter: i64) -> (Runtime, Vec<serde_json::Value>) {
+        let mut runtime = Runtime::new(RuntimeOptions {
+            trace,
+            ..Default::default()
+        })
+        .unwrap();
+        runtime
+            .register_function("record", move |args| {
+                Ok(serde_json::json!([args[0], counter]))
+            })
+            .unwrap();
+
+        let module = runtime
+            .load_module(&Module::new("test.js", MODULE))
+            .unwrap();
+        let results = vec![
+            runtime
+                .call_function(Some(&module), "roll", json_args!(1000))
+                .unwrap(),
+            runtime
+                .call_function(Some(&module), "roll", json_args!(6))
+                .unwrap(),
+            runtime
+                .call_function(Some(&module), "later", json_args!())
+                .unwrap(),
+        ];
+        (runtime, results)
+    }
+
+    #[test]
+    fn test_trace_replay() {
+        let (mut runtime, recorded) = run(TraceMode::Record, 1);
+        let trace = runtime.take_trace().unwrap();
+        assert!(trace
+            .events
+            .iter()
+            .any(|e| matches!(e, TraceEvent::Timer { .. })));
+
+        // The host function now returns something else, but replay uses the recorded results
+        let (mut runtime, replayed) = run(TraceMode::Replay(trace.clone()), 2);
+        assert_eq!(recorded, replayed);
+        assert!(runtime.take_trace().unwrap().events.is_empty());
+
+        // Calls that differ from the trace are reported
+        let mut runtime = Runtime::new(RuntimeOptions {
+            trace: TraceMode::Replay(trace),
+            ..Default::default()
+        })
+        .unwrap();
+        let error = runtime
+            .load_module(&Module::new("test.js", "export const roll = () => 1;"))
+            .unwrap_err();
+        assert!(error.to_string().contains("Replay diverged"));
+    }
+}
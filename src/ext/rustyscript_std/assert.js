@@ -0,0 +1,140 @@
+// rustyscript:std/assert - assertions for tests and runtime invariants
+import { primordials } from "ext:core/mod.js";
+import { isDeepEqual, inspect } from "ext:rustyscript_std/internal.js";
+const { ErrorCaptureStackTrace, RegExpPrototypeTest } = primordials;
+
+class AssertionError extends Error {
+    constructor(message) {
+        super(message);
+        this.name = 'AssertionError';
+    }
+}
+
+const raise = (message, fallback, caller) => {
+    const error = new AssertionError(message ?? fallback);
+    ErrorCaptureStackTrace(error, caller);
+    throw error;
+};
+
+/**
+ * Throws if the condition is falsy
+ * @param {unknown} condition
+ * @param {string} [message]
+ */
+function assert(condition, message) {
+    if (!condition) raise(message, 'Assertion failed', assert);
+}
+
+/**
+ * Throws unless both values are deeply equal
+ * @param {unknown} actual
+ * @param {unknown} expected
+ * @param {string} [message]
+ */
+function assertEquals(actual, expected, message) {
+    if (!isDeepEqual(actual, expected)) {
+        raise(message, `Values are not equal:\n    actual: ${inspect(actual)}\n  expected: ${inspect(expected)}`, assertEquals);
+    }
+}
+
+/**
+ * Throws if both values are deeply equal
+ * @param {unknown} actual
+ * @param {unknown} expected
+ * @param {string} [message]
+ */
+function assertNotEquals(actual, expected, message) {
+    if (isDeepEqual(actual, expected)) {
+        raise(message, `Expected values to differ: ${inspect(actual)}`, assertNotEquals);
+    }
+}
+
+/**
+ * Throws unless both values are the same, as with `Object.is`
+ * @param {unknown} actual
+ * @param {unknown} expected
+ * @param {string} [message]
+ */
+function assertStrictEquals(actual, expected, message) {
+    if (!Object.is(actual, expected)) {
+        raise(message, `Values are not strictly equal:\n    actual: ${inspect(actual)}\n  expected: ${inspect(expected)}`, assertStrictEquals);
+    }
+}
+
+/**
+ * Throws unless the string matches the pattern
+ * @param {string} actual
+ * @param {RegExp} pattern
+ * @param {string} [message]
+ */
+function assertMatch(actual, pattern, message) {
+    if (!RegExpPrototypeTest(pattern, actual)) {
+        raise(message, `Expected ${inspect(actual)} to match ${pattern}`, assertMatch);
+    }
+}
+
+const checkError = (error, ErrorClass, includes, caller) => {
+    if (ErrorClass && !(error instanceof ErrorClass)) {
+        raise(undefined, `Expected error to be an instance of ${ErrorClass.name}, but got ${inspect(error)}`, caller);
+    }
+    if (includes !== undefined && !String(error?.message).includes(includes)) {
+        raise(undefined, `Expected error message to include ${inspect(includes)}, but got ${inspect(error?.message)}`, caller);
+    }
+    return error;
+};
+
+/**
+ * Throws unless the function throws, optionally checking the error's class and message
+ * Returns the error thrown
+ * @param {() => unknown} fn
+ * @param {Function} [ErrorClass]
+ * @param {string} [includes]
+ * @returns {unknown}
+ */
+function assertThrows(fn, ErrorClass, includes) {
+    try {
+        fn();
+    } catch (error) {
+        return checkError(error, ErrorClass, includes, assertThrows);
+    }
+    raise(undefined, 'Expected function to throw', assertThrows);
+}
+
+/**
+ * Rejects unless the function's promise rejects, optionally checking the error's class and message
+ * Resolves to the error thrown
+ * @param {() => Promise<unknown>} fn
+ * @param {Function} [ErrorClass]
+ * @param {string} [includes]
+ * @returns {Promise<unknown>}
+ */
+async function assertRejects(fn, ErrorClass, includes) {
+    try {
+        await fn();
+    } catch (error) {
+        return checkError(error, ErrorClass, includes, assertRejects);
+    }
+    raise(undefined, 'Expected promise to reject', assertRejects);
+}
+
+/**
+ * Always throws
+ * @param {string} [message]
+ */
+function fail(message) {
+    raise(message, 'Failed assertion', fail);
+}
+
+/**
+ * Throws, marking code that should never be reached
+ * @param {string} [message]
+ */
+function unreachable(message) {
+    raise(message, 'Unreachable code was reached', unreachable);
+}
+
+export {
+    AssertionError,
+    assert, assertEquals, assertNotEquals, assertStrictEquals, assertMatch, assertThrows, assertRejects,
+    fail, unreachable,
+};
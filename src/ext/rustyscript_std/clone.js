@@ -0,0 +1,45 @@
+// rustyscript:std/clone - deep copies using the structured clone algorithm
+import { core } from "ext:core/mod.js";
+
+class DataCloneError extends TypeError {
+    constructor(message) {
+        super(message);
+        this.name = 'DataCloneError';
+    }
+}
+
+const throwCloneError = (message) => {
+    throw new DataCloneError(message);
+};
+
+/**
+ * Deep copies a value with the structured clone algorithm, as used by `structuredClone` and `postMessage`
+ *
+ * Supports primitives, plain objects, arrays, dates, regular expressions, maps, sets, errors,
+ * array buffers and typed arrays, including cyclic references
+ * Class instances are cloned as plain objects, and functions or symbols throw a `DataCloneError`
+ *
+ * @template T
+ * @param {T} value
+ * @returns {T}
+ */
+function clone(value) {
+    const buffer = core.serialize(value, undefined, throwCloneError);
+    return core.deserialize(buffer);
+}
+
+/**
+ * Returns true if the value can be cloned with `clone`
+ * @param {unknown} value
+ * @returns {boolean}
+ */
+function isCloneable(value) {
+    try {
+        core.serialize(value, undefined, throwCloneError);
+        return true;
+    } catch {
+        return false;
+    }
+}
+
+export { clone, isCloneable, DataCloneError };
@@ -0,0 +1,170 @@
+// rustyscript:std/datetime - date formatting and arithmetic
+// All functions work in UTC unless `{ utc: false }` is given, so results do not depend on the host's timezone
+
+const MILLISECONDS = {
+    milliseconds: 1,
+    seconds: 1000,
+    minutes: 60 * 1000,
+    hours: 60 * 60 * 1000,
+    days: 24 * 60 * 60 * 1000,
+    weeks: 7 * 24 * 60 * 60 * 1000,
+};
+
+const toDate = (date) => {
+    const value = date instanceof Date ? new Date(date.getTime()) : new Date(date);
+    if (Number.isNaN(value.getTime())) {
+        throw new RangeError(`Invalid date: ${date}`);
+    }
+    return value;
+};
+
+const parts = (date, utc) => utc
+    ? {
+        year: date.getUTCFullYear(), month: date.getUTCMonth() + 1, day: date.getUTCDate(),
+        hour: date.getUTCHours(), minute: date.getUTCMinutes(), second: date.getUTCSeconds(),
+        millisecond: date.getUTCMilliseconds(), offset: 0,
+    }
+    : {
+        year: date.getFullYear(), month: date.getMonth() + 1, day: date.getDate(),
+        hour: date.getHours(), minute: date.getMinutes(), second: date.getSeconds(),
+        millisecond: date.getMilliseconds(), offset: -date.getTimezoneOffset(),
+    };
+
+const pad = (value, length = 2) => String(value).padStart(length, '0');
+
+const formatOffset = (offset) => {
+    if (offset === 0) return 'Z';
+    const sign = offset > 0 ? '+' : '-';
+    const minutes = Math.abs(offset);
+    return `${sign}${pad(Math.floor(minutes / 60))}:${pad(minutes % 60)}`;
+};
+
+const TOKENS = /yyyy|MM|dd|HH|mm|ss|SSS|XXX|\[[^\]]*\]/g;
+
+/**
+ * Formats a date using a pattern
+ *
+ * Supported tokens: `yyyy`, `MM`, `dd`, `HH`, `mm`, `ss`, `SSS` and `XXX` (UTC offset)
+ * Text inside square brackets is copied as-is
+ *
+ * @param {Date | number | string} date
+ * @param {string} [pattern] Defaults to an ISO 8601 timestamp
+ * @param {{ utc?: boolean }} [options]
+ * @returns {string}
+ */
+function format(date, pattern = "yyyy-MM-dd[T]HH:mm:ss.SSSXXX", { utc = true } = {}) {
+    const p = parts(toDate(date), utc);
+    return pattern.replace(TOKENS, (token) => {
+        switch (token) {
+            case 'yyyy': return pad(p.year, 4);
+            case 'MM': return pad(p.month);
+            case 'dd': return pad(p.day);
+            case 'HH': return pad(p.hour);
+            case 'mm': return pad(p.minute);
+            case 'ss': return pad(p.second);
+            case 'SSS': return pad(p.millisecond, 3);
+            case 'XXX': return formatOffset(p.offset);
+            default: return token.slice(1, -1);
+        }
+    });
+}
+
+/**
+ * Returns a new date, offset from the given one
+ * Months and years are calendar-aware, clamping to the end of shorter months
+ *
+ * @param {Date | number | string} date
+ * @param {{ years?: number, months?: number, weeks?: number, days?: number, hours?: number, minutes?: number, seconds?: number, milliseconds?: number }} duration
+ * @returns {Date}
+ */
+function add(date, duration) {
+    const result = toDate(date);
+    const months = (duration.years ?? 0) * 12 + (duration.months ?? 0);
+    if (months) {
+        const day = result.getUTCDate();
+        result.setUTCDate(1);
+        result.setUTCMonth(result.getUTCMonth() + months);
+        result.setUTCDate(Math.min(day, daysInMonth(result.getUTCFullYear(), result.getUTCMonth() + 1)));
+    }
+
+    let offset = 0;
+    for (const [unit, ms] of Object.entries(MILLISECONDS)) {
+        offset += (duration[unit] ?? 0) * ms;
+    }
+    return new Date(result.getTime() + offset);
+}
+
+/**
+ * Returns the difference `to - from` in the given unit, truncated towards zero
+ * @param {Date | number | string} from
+ * @param {Date | number | string} to
+ * @param {'milliseconds' | 'seconds' | 'minutes' | 'hours' | 'days' | 'weeks'} [unit]
+ * @returns {number}
+ */
+function difference(from, to, unit = 'milliseconds') {
+    const ms = MILLISECONDS[unit];
+    if (!ms) throw new RangeError(`Unknown unit: ${unit}`);
+    return Math.trunc((toDate(to).getTime() - toDate(from).getTime()) / ms);
+}
+
+/**
+ * Returns the start of the day containing the date
+ * @param {Date | number | string} date
+ * @param {{ utc?: boolean }} [options]
+ * @returns {Date}
+ */
+function startOfDay(date, { utc = true } = {}) {
+    const result = toDate(date);
+    if (utc) result.setUTCHours(0, 0, 0, 0);
+    else result.setHours(0, 0, 0, 0);
+    return result;
+}
+
+/**
+ * Returns true if the year is a leap year
+ * @param {number} year
+ * @returns {boolean}
+ */
+function isLeap(year) {
+    return (year % 4 === 0 && year % 100 !== 0) || year % 400 === 0;
+}
+
+/**
+ * Returns the number of days in a month
+ * @param {number} year
+ * @param {number} month 1-12
+ * @returns {number}
+ */
+function daysInMonth(year, month) {
+    return new Date(Date.UTC(year, month, 0)).getUTCDate();
+}
+
+/**
+ * Returns the day of the year, starting at 1
+ * @param {Date | number | string} date
+ * @param {{ utc?: boolean }} [options]
+ * @returns {number}
+ */
+function dayOfYear(date, { utc = true } = {}) {
+    const { year, month, day } = parts(toDate(date), utc);
+    return difference(Date.UTC(year, 0, 1), Date.UTC(year, month - 1, day), 'days') + 1;
+}
+
+/**
+ * Returns the ISO 8601 week number
+ * @param {Date | number | string} date
+ * @param {{ utc?: boolean }} [options]
+ * @returns {number}
+ */
+function weekOfYear(date, { utc = true } = {}) {
+    const { year, month, day } = parts(toDate(date), utc);
+    const target = new Date(Date.UTC(year, month - 1, day));
+
+    // The week belongs to the year containing its thursday
+    const weekday = target.getUTCDay() || 7;
+    target.setUTCDate(target.getUTCDate() + 4 - weekday);
+    const yearStart = Date.UTC(target.getUTCFullYear(), 0, 1);
+    return Math.ceil((difference(yearStart, target, 'days') + 1) / 7);
+}
+
+export { format, add, difference, startOfDay, isLeap, daysInMonth, dayOfYear, weekOfYear };
@@ -0,0 +1,139 @@
+// rustyscript:std/encoding - base64, base64url and hex encoding
+// Strings are encoded as UTF-8 before encoding, and `decode*` functions always return bytes
+import { core } from "ext:core/mod.js";
+
+const BASE64 = 'ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/';
+const BASE64URL = 'ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_';
+
+const toBytes = (data) => {
+    if (typeof data === 'string') return core.encode(data);
+    if (data instanceof ArrayBuffer) return new Uint8Array(data);
+    if (ArrayBuffer.isView(data)) return new Uint8Array(data.buffer, data.byteOffset, data.byteLength);
+    throw new TypeError('Expected a string, ArrayBuffer or ArrayBufferView');
+};
+
+const lookup = (alphabet) => {
+    const table = new Map();
+    for (let i = 0; i < alphabet.length; i++) table.set(alphabet[i], i);
+    return table;
+};
+const BASE64_LOOKUP = lookup(BASE64);
+const BASE64URL_LOOKUP = lookup(BASE64URL);
+
+const encode = (data, alphabet, padding) => {
+    const bytes = toBytes(data);
+    let output = '';
+    for (let i = 0; i < bytes.length; i += 3) {
+        const chunk = (bytes[i] << 16) | ((bytes[i + 1] ?? 0) << 8) | (bytes[i + 2] ?? 0);
+        const chars = Math.min(bytes.length - i, 3) + 1;
+        for (let j = 0; j < 4; j++) {
+            if (j < chars) output += alphabet[(chunk >> (18 - j * 6)) & 63];
+            else if (padding) output += '=';
+        }
+    }
+    return output;
+};
+
+const decode = (text, table, name) => {
+    if (typeof text !== 'string') throw new TypeError('Expected a string');
+    const input = text.replace(/=+$/, '');
+    if (input.length % 4 === 1) throw new TypeError(`Invalid ${name} string: bad length`);
+
+    const output = new Uint8Array(Math.floor((input.length * 3) / 4));
+    let buffer = 0;
+    let bits = 0;
+    let offset = 0;
+    for (const char of input) {
+        const value = table.get(char);
+        if (value === undefined) throw new TypeError(`Invalid ${name} string: unexpected character '${char}'`);
+        buffer = (buffer << 6) | value;
+        bits += 6;
+        if (bits >= 8) {
+            bits -= 8;
+            output[offset++] = (buffer >> bits) & 0xff;
+        }
+    }
+    return output;
+};
+
+/**
+ * @param {string | ArrayBuffer | ArrayBufferView} data
+ * @returns {string}
+ */
+function encodeBase64(data) {
+    return encode(data, BASE64, true);
+}
+
+/**
+ * @param {string} text
+ * @returns {Uint8Array}
+ */
+function decodeBase64(text) {
+    return decode(text, BASE64_LOOKUP, 'base64');
+}
+
+/**
+ * URL and filename safe base64, without padding
+ * @param {string | ArrayBuffer | ArrayBufferView} data
+ * @returns {string}
+ */
+function encodeBase64Url(data) {
+    return encode(data, BASE64URL, false);
+}
+
+/**
+ * @param {string} text
+ * @returns {Uint8Array}
+ */
+function decodeBase64Url(text) {
+    return decode(text, BASE64URL_LOOKUP, 'base64url');
+}
+
+/**
+ * Lowercase hex
+ * @param {string | ArrayBuffer | ArrayBufferView} data
+ * @returns {string}
+ */
+function encodeHex(data) {
+    return Array.from(toBytes(data), (byte) => byte.toString(16).padStart(2, '0')).join('');
+}
+
+/**
+ * @param {string} text
+ * @returns {Uint8Array}
+ */
+function decodeHex(text) {
+    if (typeof text !== 'string') throw new TypeError('Expected a string');
+    if (text.length % 2 !== 0 || /[^0-9a-fA-F]/.test(text)) {
+        throw new TypeError('Invalid hex string');
+    }
+
+    const output = new Uint8Array(text.length / 2);
+    for (let i = 0; i < output.length; i++) {
+        output[i] = parseInt(text.slice(i * 2, i * 2 + 2), 16);
+    }
+    return output;
+}
+
+/**
+ * Encodes a string as UTF-8 bytes
+ * @param {string} text
+ * @returns {Uint8Array}
+ */
+function encodeUtf8(text) {
+    return core.encode(text);
+}
+
+/**
+ * Decodes UTF-8 bytes into a string
+ * @param {ArrayBuffer | ArrayBufferView} data
+ * @returns {string}
+ */
+function decodeUtf8(data) {
+    return core.decode(toBytes(data));
+}
+
+export {
+    encodeBase64, decodeBase64, encodeBase64Url, decodeBase64Url,
+    encodeHex, decodeHex, encodeUtf8, decodeUtf8,
+};
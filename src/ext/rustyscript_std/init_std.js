@@ -0,0 +1,8 @@
+// Entry point for the rustyscript_std extension
+// Importing each module here evaluates it, so that scripts can import it as `rustyscript:std/<name>`
+import 'ext:rustyscript_std/assert.js';
+import 'ext:rustyscript_std/clone.js';
+import 'ext:rustyscript_std/datetime.js';
+import 'ext:rustyscript_std/encoding.js';
+import 'ext:rustyscript_std/path.js';
+import 'ext:rustyscript_std/uuid.js';
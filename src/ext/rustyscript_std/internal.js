@@ -0,0 +1,86 @@
+// Helpers shared by the rustyscript:std modules - not exposed as a rustyscript:std module itself
+
+/**
+ * Structural equality, comparing the contents of objects, arrays, maps, sets, dates, regular expressions and typed arrays
+ * @param {unknown} a
+ * @param {unknown} b
+ * @returns {boolean}
+ */
+function isDeepEqual(a, b, seen = new Map()) {
+    if (Object.is(a, b)) return true;
+    if (typeof a !== 'object' || typeof b !== 'object' || a === null || b === null) return false;
+    if (Object.getPrototypeOf(a) !== Object.getPrototypeOf(b)) return false;
+
+    // Cyclic structures are equal if they cycle at the same places
+    if (seen.get(a) === b) return true;
+    seen.set(a, b);
+
+    if (a instanceof Date) return Object.is(a.getTime(), b.getTime());
+    if (a instanceof RegExp) return a.source === b.source && a.flags === b.flags;
+    if (ArrayBuffer.isView(a)) {
+        const left = new Uint8Array(a.buffer, a.byteOffset, a.byteLength);
+        const right = new Uint8Array(b.buffer, b.byteOffset, b.byteLength);
+        return left.length === right.length && left.every((byte, i) => byte === right[i]);
+    }
+    if (a instanceof Set) {
+        if (a.size !== b.size) return false;
+        outer: for (const value of a) {
+            if (b.has(value)) continue;
+            for (const other of b) {
+                if (isDeepEqual(value, other, seen)) continue outer;
+            }
+            return false;
+        }
+        return true;
+    }
+    if (a instanceof Map) {
+        if (a.size !== b.size) return false;
+        for (const [key, value] of a) {
+            if (!b.has(key) || !isDeepEqual(value, b.get(key), seen)) return false;
+        }
+        return true;
+    }
+
+    const keys = Reflect.ownKeys(a);
+    if (keys.length !== Reflect.ownKeys(b).length) return false;
+    return keys.every((key) => Object.hasOwn(b, key) && isDeepEqual(a[key], b[key], seen));
+}
+
+/**
+ * A short, readable representation of a value for error messages
+ * @param {unknown} value
+ * @returns {string}
+ */
+function inspect(value, seen = new Set()) {
+    switch (typeof value) {
+        case 'string': return JSON.stringify(value);
+        case 'bigint': return `${value}n`;
+        case 'symbol': return value.toString();
+        case 'function': return `[Function: ${value.name || '(anonymous)'}]`;
+        case 'object': break;
+        default: return String(value);
+    }
+
+    if (value === null) return 'null';
+    if (seen.has(value)) return '[Circular]';
+    seen.add(value);
+
+    if (value instanceof Date) return value.toISOString();
+    if (value instanceof RegExp || value instanceof Error) return String(value);
+    if (value instanceof Map) {
+        const entries = [...value].map(([k, v]) => `${inspect(k, seen)} => ${inspect(v, seen)}`);
+        return `Map(${value.size}) { ${entries.join(', ')} }`;
+    }
+    if (value instanceof Set) {
+        return `Set(${value.size}) { ${[...value].map((v) => inspect(v, seen)).join(', ')} }`;
+    }
+    if (Array.isArray(value) || ArrayBuffer.isView(value)) {
+        const prefix = Array.isArray(value) ? '' : `${value.constructor.name}(${value.length}) `;
+        return `${prefix}[ ${Array.prototype.map.call(value, (v) => inspect(v, seen)).join(', ')} ]`;
+    }
+
+    const entries = Object.entries(value).map(([k, v]) => `${k}: ${inspect(v, seen)}`);
+    return `{ ${entries.join(', ')} }`;
+}
+
+export { isDeepEqual, inspect };
@@ -0,0 +1,114 @@
+//! A small standard library of pure-JS utilities, safe to use in a sandboxed runtime
+//!
+//! Scripts import the modules as `rustyscript:std/<name>`:
+//! - `assert` - assertions such as `assert`, `assertEquals` and `assertThrows`
+//! - `clone` - deep copies using the structured clone algorithm
+//! - `datetime` - date formatting and arithmetic
+//! - `encoding` - base64, base64url, hex and UTF-8 encoding
+//! - `path` - POSIX-style path manipulation, which never touches the filesystem
+//! - `uuid` - UUID generation, which requires the `crypto` feature, and validation
+use super::ExtensionTrait;
+use deno_core::{extension, Extension, ModuleSpecifier};
+
+/// The names of the modules scripts may import
+const MODULES: &[&str] = &["assert", "clone", "datetime", "encoding", "path", "uuid"];
+
+extension!(
+    rustyscript_std,
+    esm_entry_point = "ext:rustyscript_std/init_std.js",
+    esm = [ dir "src/ext/rustyscript_std", "init_std.js", "internal.js", "assert.js", "clone.js", "datetime.js", "encoding.js", "path.js", "uuid.js" ],
+);
+impl ExtensionTrait<()> for rustyscript_std {
+    fn init((): ()) -> Extension {
+        rustyscript_std::init_ops_and_esm()
+    }
+}
+
+pub fn extensions(is_snapshot: bool) -> Vec<Extension> {
+    vec![rustyscript_std::build((), is_snapshot)]
+}
+
+/// Resolves a `rustyscript:std/<name>` specifier to the extension module providing it
+pub fn resolve(specifier: &str) -> Option<ModuleSpecifier> {
+    let name = specifier.strip_prefix("rustyscript:std/")?;
+    if !MODULES.contains(&name) {
+        return None;
+    }
+
+    ModuleSpecifier::parse(&format!("ext:rustyscript_std/{name}.js")).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Module, Runtime, RuntimeOptions};
+
+    #[test]
+    fn test_std_modules() {
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let module = Module::new(
+            "test.js",
+            "
+            import { assert, assertEquals, assertThrows, AssertionError } from 'rustyscript:std/assert';
+            import { clone, DataCloneError } from 'rustyscript:std/clone';
+            import * as datetime from 'rustyscript:std/datetime';
+            import * as encoding from 'rustyscript:std/encoding';
+            import * as path from 'rustyscript:std/path';
+            import * as uuid from 'rustyscript:std/uuid';
+
+            assertEquals({ a: [1, new Map([[1, 2]])] }, { a: [1, new Map([[1, 2]])] });
+            assertThrows(() => assertEquals([1], [2]), AssertionError, 'not equal');
+
+            const value = { date: new Date(0), set: new Set([1]) };
+            value.self = value;
+            const copy = clone(value);
+            assert(copy !== value && copy.self === copy);
+            assertEquals(copy, value);
+            assertThrows(() => clone(() => {}), DataCloneError);
+
+            const date = Date.UTC(2024, 0, 31, 12, 30);
+            assertEquals(datetime.format(date, 'yyyy-MM-dd HH:mm'), '2024-01-31 12:30');
+            assertEquals(datetime.add(date, { months: 1 }).toISOString(), '2024-02-29T12:30:00.000Z');
+            assertEquals(datetime.difference(date, datetime.add(date, { days: 3 }), 'days'), 3);
+            assertEquals(datetime.weekOfYear(Date.UTC(2021, 0, 3)), 53);
+
+            assertEquals(encoding.encodeBase64('héllo'), 'aMOpbGxv');
+            assertEquals(encoding.decodeUtf8(encoding.decodeBase64('aMOpbGxv')), 'héllo');
+            assertEquals(encoding.encodeBase64Url(new Uint8Array([251, 255])), '-_8');
+            assertEquals(encoding.decodeHex(encoding.encodeHex('abc')), encoding.encodeUtf8('abc'));
+
+            assertEquals(path.join('/a/b', '../c', './d.txt'), '/a/c/d.txt');
+            assertEquals(path.relative('/a/b/c', '/a/d'), '../../d');
+            assertEquals(path.parse('/a/b.tar.gz'), { root: '/', dir: '/a', base: 'b.tar.gz', ext: '.gz', name: 'b.tar' });
+            assertEquals(path.format(path.parse('/a/b.txt')), '/a/b.txt');
+
+            assert(!uuid.validate('not-a-uuid'));
+        ",
+        );
+        runtime.load_module(&module).unwrap();
+
+        // Without the crypto extension there is no secure source of random bytes
+        let check = if cfg!(feature = "crypto") {
+            "assertEquals(uuid.version(uuid.v4()), 4); assertEquals(uuid.version(uuid.v7()), 7);"
+        } else {
+            "assertThrows(() => uuid.v4(), Error, 'crypto.getRandomValues');"
+        };
+        let module = Module::new(
+            "uuid.js",
+            &format!(
+                "
+                import {{ assertEquals, assertThrows }} from 'rustyscript:std/assert';
+                import * as uuid from 'rustyscript:std/uuid';
+                {check}
+            "
+            ),
+        );
+        runtime.load_module(&module).unwrap();
+
+        let module = Module::new("unknown.js", "import 'rustyscript:std/fs';");
+        let e = runtime.load_module(&module).unwrap_err();
+        assert!(
+            e.to_string().contains("unknown standard library module"),
+            "{e}"
+        );
+    }
+}
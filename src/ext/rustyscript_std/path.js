@@ -0,0 +1,180 @@
+// rustyscript:std/path - POSIX-style path manipulation
+// Pure string operations - nothing here touches the filesystem
+
+const sep = '/';
+const delimiter = ':';
+
+const assertPath = (path) => {
+    if (typeof path !== 'string') {
+        throw new TypeError(`Path must be a string, received ${typeof path}`);
+    }
+};
+
+// Resolves `.` and `..` segments, dropping `..` segments that would go above the root of absolute paths
+const normalizeSegments = (segments, isAbsolute) => {
+    const result = [];
+    for (const segment of segments) {
+        if (segment === '' || segment === '.') continue;
+        if (segment === '..') {
+            if (result.length && result[result.length - 1] !== '..') {
+                result.pop();
+            } else if (!isAbsolute) {
+                result.push('..');
+            }
+            continue;
+        }
+        result.push(segment);
+    }
+    return result;
+};
+
+/**
+ * Returns true if the path starts at the root
+ * @param {string} path
+ * @returns {boolean}
+ */
+function isAbsolute(path) {
+    assertPath(path);
+    return path.startsWith(sep);
+}
+
+/**
+ * Resolves `.` and `..` segments, and removes duplicate separators
+ * @param {string} path
+ * @returns {string}
+ */
+function normalize(path) {
+    assertPath(path);
+    if (path === '') return '.';
+
+    const absolute = isAbsolute(path);
+    const trailing = path.endsWith(sep);
+    let result = normalizeSegments(path.split(sep), absolute).join(sep);
+    if (result === '' && !absolute) result = '.';
+    if (result !== '' && trailing) result += sep;
+    return absolute ? sep + result : result;
+}
+
+/**
+ * Joins the segments with separators, and normalizes the result
+ * @param {...string} paths
+ * @returns {string}
+ */
+function join(...paths) {
+    paths.forEach(assertPath);
+    const joined = paths.filter((p) => p !== '').join(sep);
+    return joined === '' ? '.' : normalize(joined);
+}
+
+/**
+ * Resolves the segments, from right to left, into an absolute path
+ * Paths are resolved against the root, since the sandbox has no working directory
+ * @param {...string} paths
+ * @returns {string}
+ */
+function resolve(...paths) {
+    paths.forEach(assertPath);
+    let resolved = '';
+    for (let i = paths.length - 1; i >= 0 && !resolved.startsWith(sep); i--) {
+        if (paths[i] === '') continue;
+        resolved = resolved === '' ? paths[i] : `${paths[i]}${sep}${resolved}`;
+    }
+    return sep + normalizeSegments(resolved.split(sep), true).join(sep);
+}
+
+/**
+ * Returns the path from `from` to `to`, after resolving both
+ * @param {string} from
+ * @param {string} to
+ * @returns {string}
+ */
+function relative(from, to) {
+    const fromSegments = resolve(from).split(sep).filter(Boolean);
+    const toSegments = resolve(to).split(sep).filter(Boolean);
+
+    let common = 0;
+    while (common < fromSegments.length && fromSegments[common] === toSegments[common]) common++;
+
+    const up = fromSegments.slice(common).map(() => '..');
+    return [...up, ...toSegments.slice(common)].join(sep);
+}
+
+const trimTrailing = (path) => {
+    let end = path.length;
+    while (end > 1 && path[end - 1] === sep) end--;
+    return path.slice(0, end);
+};
+
+/**
+ * Returns the directory portion of the path
+ * @param {string} path
+ * @returns {string}
+ */
+function dirname(path) {
+    assertPath(path);
+    const trimmed = trimTrailing(path);
+    const index = trimmed.lastIndexOf(sep);
+    if (index === -1) return '.';
+    if (index === 0) return sep;
+    return trimTrailing(trimmed.slice(0, index));
+}
+
+/**
+ * Returns the last portion of the path, optionally removing a suffix such as an extension
+ * @param {string} path
+ * @param {string} [suffix]
+ * @returns {string}
+ */
+function basename(path, suffix) {
+    assertPath(path);
+    const trimmed = trimTrailing(path);
+    let base = trimmed.slice(trimmed.lastIndexOf(sep) + 1);
+    if (suffix && base !== suffix && base.endsWith(suffix)) {
+        base = base.slice(0, -suffix.length);
+    }
+    return base;
+}
+
+/**
+ * Returns the extension of the path, including the leading `.`, or an empty string
+ * @param {string} path
+ * @returns {string}
+ */
+function extname(path) {
+    const base = basename(path);
+    const index = base.lastIndexOf('.');
+    return index <= 0 ? '' : base.slice(index);
+}
+
+/**
+ * Splits a path into its root, directory, base name, extension and name
+ * @param {string} path
+ * @returns {{ root: string, dir: string, base: string, ext: string, name: string }}
+ */
+function parse(path) {
+    assertPath(path);
+    const base = basename(path);
+    const ext = extname(path);
+    const dir = path.includes(sep) ? dirname(path) : '';
+    return {
+        root: isAbsolute(path) ? sep : '',
+        dir,
+        base,
+        ext,
+        name: ext ? base.slice(0, -ext.length) : base,
+    };
+}
+
+/**
+ * The reverse of `parse`
+ * @param {{ root?: string, dir?: string, base?: string, ext?: string, name?: string }} parts
+ * @returns {string}
+ */
+function format({ root = '', dir, base, ext = '', name = '' }) {
+    const file = base ?? `${name}${ext && !ext.startsWith('.') ? '.' : ''}${ext}`;
+    const directory = dir ?? root;
+    if (!directory) return file;
+    return directory === root || directory.endsWith(sep) ? `${directory}${file}` : `${directory}${sep}${file}`;
+}
+
+export { sep, delimiter, isAbsolute, normalize, join, resolve, relative, dirname, basename, extname, parse, format };
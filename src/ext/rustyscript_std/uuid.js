@@ -0,0 +1,66 @@
+// rustyscript:std/uuid - UUID generation and validation
+// Random bytes come from `crypto.getRandomValues`, so generating UUIDs requires the crypto extension
+
+const NIL = '00000000-0000-0000-0000-000000000000';
+const PATTERN = /^[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}$/i;
+
+const randomBytes = (length) => {
+    const bytes = new Uint8Array(length);
+    if (typeof globalThis.crypto?.getRandomValues !== 'function') {
+        throw new Error('UUID generation requires crypto.getRandomValues - enable the crypto extension');
+    }
+    return globalThis.crypto.getRandomValues(bytes);
+};
+
+const stringify = (bytes) => {
+    const hex = Array.from(bytes, (byte) => byte.toString(16).padStart(2, '0')).join('');
+    return `${hex.slice(0, 8)}-${hex.slice(8, 12)}-${hex.slice(12, 16)}-${hex.slice(16, 20)}-${hex.slice(20)}`;
+};
+
+/**
+ * Generates a random (version 4) UUID
+ * @returns {string}
+ */
+function v4() {
+    const bytes = randomBytes(16);
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    return stringify(bytes);
+}
+
+/**
+ * Generates a time-ordered (version 7) UUID, which sorts by creation time
+ * @returns {string}
+ */
+function v7() {
+    const bytes = randomBytes(16);
+    let timestamp = Date.now();
+    for (let i = 5; i >= 0; i--) {
+        bytes[i] = timestamp % 256;
+        timestamp = Math.floor(timestamp / 256);
+    }
+    bytes[6] = (bytes[6] & 0x0f) | 0x70;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    return stringify(bytes);
+}
+
+/**
+ * Returns true if the string is a valid UUID, of any version
+ * @param {string} uuid
+ * @returns {boolean}
+ */
+function validate(uuid) {
+    return typeof uuid === 'string' && PATTERN.test(uuid);
+}
+
+/**
+ * Returns the version of a UUID
+ * @param {string} uuid
+ * @returns {number}
+ */
+function version(uuid) {
+    if (!validate(uuid)) throw new TypeError(`Invalid UUID: ${uuid}`);
+    return parseInt(uuid[14], 16);
+}
+
+export { NIL, v4, v7, validate, version };
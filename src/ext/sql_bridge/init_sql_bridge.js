@@ -0,0 +1,54 @@
+import { core } from "ext:core/mod.js";
+import { applyToGlobal, nonEnumerable } from 'ext:rustyscript/rustyscript.js';
+
+// Runs queries through the host's database connection - see `SqlBridge`
+// Resolves to an array of row objects, with the non-enumerable properties `rowsAffected` and `columns`
+
+function encodeParam(value) {
+    if (value === undefined) {
+        return null;
+    } else if (typeof value === 'bigint') {
+        return Number.isSafeInteger(Number(value)) ? Number(value) : value.toString();
+    } else if (value instanceof Date) {
+        return value.toISOString();
+    } else if (value instanceof ArrayBuffer) {
+        return new Uint8Array(value);
+    } else if (value !== null && typeof value === 'object' && !ArrayBuffer.isView(value)) {
+        return JSON.stringify(value);
+    }
+    return value;
+}
+
+function decodeValue(value, type) {
+    if (value === null) {
+        return null;
+    }
+
+    switch (type) {
+        case 'bigint': return BigInt(value);
+        case 'boolean': return Boolean(value);
+        case 'json': return typeof value === 'string' ? JSON.parse(value) : value;
+        case 'timestamp': return new Date(value);
+        default: return value;
+    }
+}
+
+async function sql(statement, params = []) {
+    const result = await core.ops.op_sql_bridge_query(String(statement), Array.from(params, encodeParam));
+    const rows = result.rows.map((row) => {
+        const object = {};
+        result.columns.forEach((column, i) => {
+            object[column.name] = decodeValue(row[i], column.type);
+        });
+        return object;
+    });
+
+    return Object.defineProperties(rows, {
+        rowsAffected: { value: result.rowsAffected },
+        columns: { value: result.columns },
+    });
+}
+
+const host = globalThis.host ?? {};
+Object.defineProperty(host, 'sql', { value: sql, enumerable: true });
+applyToGlobal({ host: nonEnumerable(host) });
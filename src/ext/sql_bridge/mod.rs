@@ -0,0 +1,357 @@
+//! Lets scripts run queries through a database connection owned by the host, see [`SqlBridge`]
+//!
+//! Scripts call `await host.sql(sql, params)`, and receive an array of row objects
+//! Values are decoded according to the [`SqlType`] of their column
+use super::ExtensionTrait;
+use crate::Error;
+use deno_core::{extension, op2, Extension, OpState};
+use serde::{de::Visitor, Deserialize, Serialize};
+use std::{cell::RefCell, future::Future, pin::Pin, rc::Rc, sync::Arc};
+
+/// Runs queries for scripts, through the host's own connection pool
+///
+/// Register a bridge with [`crate::ExtensionOptions::sql_bridge`]
+/// The bridge decides what scripts may do - it can reject statements, or run them as a restricted database user
+///
+/// ```rust
+/// use rustyscript::{ Error, SqlBridge, SqlColumn, SqlRows, SqlType, SqlValue };
+/// use std::{ future::Future, pin::Pin };
+///
+/// struct Echo;
+/// impl SqlBridge for Echo {
+///     fn query(&self, sql: String, params: Vec<SqlValue>) -> Pin<Box<dyn Future<Output = Result<SqlRows, Error>>>> {
+///         Box::pin(async move {
+///             Ok(SqlRows {
+///                 columns: vec![SqlColumn::new("sql", SqlType::Text)],
+///                 rows: vec![vec![SqlValue::Text(sql)]],
+///                 rows_affected: 0,
+///             })
+///         })
+///     }
+/// }
+/// ```
+pub trait SqlBridge: Send + Sync {
+    /// Runs a statement with the given parameters, returning its rows
+    ///
+    /// # Errors
+    /// The error is thrown into javascript, as the rejection of `host.sql`
+    fn query(
+        &self,
+        sql: String,
+        params: Vec<SqlValue>,
+    ) -> Pin<Box<dyn Future<Output = Result<SqlRows, Error>>>>;
+}
+
+/// A parameter or column value
+///
+/// Parameters from javascript are converted as follows:
+/// - `null` and `undefined` become [`SqlValue::Null`]
+/// - Numbers become [`SqlValue::Integer`] if they hold an integer, and [`SqlValue::Real`] otherwise
+/// - `BigInt`s become [`SqlValue::Integer`] within the safe integer range, and decimal [`SqlValue::Text`] otherwise
+/// - Dates become ISO 8601 [`SqlValue::Text`], and other objects become JSON [`SqlValue::Text`]
+/// - Typed arrays and `ArrayBuffer`s become [`SqlValue::Blob`]
+#[derive(Clone, Debug, PartialEq)]
+pub enum SqlValue {
+    /// `NULL`
+    Null,
+
+    /// A boolean
+    Bool(bool),
+
+    /// A 64-bit integer - values outside of the safe integer range become `BigInt`s in javascript
+    Integer(i64),
+
+    /// A floating point number
+    Real(f64),
+
+    /// A string
+    Text(String),
+
+    /// Binary data, a `Uint8Array` in javascript
+    Blob(Vec<u8>),
+}
+
+impl Serialize for SqlValue {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Null => serializer.serialize_unit(),
+            Self::Bool(v) => serializer.serialize_bool(*v),
+            Self::Integer(v) => serializer.serialize_i64(*v),
+            Self::Real(v) => serializer.serialize_f64(*v),
+            Self::Text(v) => serializer.serialize_str(v),
+            Self::Blob(v) => serializer.serialize_bytes(v),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SqlValue {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SqlValueVisitor;
+        impl Visitor<'_> for SqlValueVisitor {
+            type Value = SqlValue;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("null, a boolean, a number, a string, or binary data")
+            }
+
+            fn visit_unit<E>(self) -> Result<SqlValue, E> {
+                Ok(SqlValue::Null)
+            }
+
+            fn visit_none<E>(self) -> Result<SqlValue, E> {
+                Ok(SqlValue::Null)
+            }
+
+            fn visit_bool<E>(self, v: bool) -> Result<SqlValue, E> {
+                Ok(SqlValue::Bool(v))
+            }
+
+            fn visit_i64<E>(self, v: i64) -> Result<SqlValue, E> {
+                Ok(SqlValue::Integer(v))
+            }
+
+            fn visit_u64<E>(self, v: u64) -> Result<SqlValue, E> {
+                Ok(i64::try_from(v)
+                    .map_or_else(|_| SqlValue::Text(v.to_string()), SqlValue::Integer))
+            }
+
+            #[allow(clippy::cast_possible_truncation)]
+            fn visit_f64<E>(self, v: f64) -> Result<SqlValue, E> {
+                if v.fract() == 0.0 && v.abs() <= 9_007_199_254_740_991.0 {
+                    Ok(SqlValue::Integer(v as i64))
+                } else {
+                    Ok(SqlValue::Real(v))
+                }
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<SqlValue, E> {
+                Ok(SqlValue::Text(v.to_string()))
+            }
+
+            fn visit_string<E>(self, v: String) -> Result<SqlValue, E> {
+                Ok(SqlValue::Text(v))
+            }
+
+            fn visit_bytes<E>(self, v: &[u8]) -> Result<SqlValue, E> {
+                Ok(SqlValue::Blob(v.to_vec()))
+            }
+
+            fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<SqlValue, E> {
+                Ok(SqlValue::Blob(v))
+            }
+        }
+
+        deserializer.deserialize_any(SqlValueVisitor)
+    }
+}
+
+/// How the values of a column are decoded into javascript
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SqlType {
+    /// Values are passed through as-is
+    #[default]
+    Any,
+
+    /// Numbers
+    Integer,
+
+    /// `BigInt`s, from integers or decimal strings
+    BigInt,
+
+    /// Numbers
+    Real,
+
+    /// Strings
+    Text,
+
+    /// Booleans, from booleans or integers
+    Boolean,
+
+    /// Parsed JSON, from strings
+    Json,
+
+    /// `Date`s, from ISO 8601 strings or milliseconds since the epoch
+    Timestamp,
+
+    /// `Uint8Array`s
+    Blob,
+}
+
+/// A column of a query result
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SqlColumn {
+    /// The column name, used as the key of row objects
+    pub name: String,
+
+    /// How values in this column are decoded
+    #[serde(rename = "type")]
+    pub kind: SqlType,
+}
+
+impl SqlColumn {
+    /// Creates a new column
+    #[must_use]
+    pub fn new(name: impl ToString, kind: SqlType) -> Self {
+        Self {
+            name: name.to_string(),
+            kind,
+        }
+    }
+}
+
+/// The result of a query run by a [`SqlBridge`]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SqlRows {
+    /// The columns of the result
+    pub columns: Vec<SqlColumn>,
+
+    /// The rows, with one value per column
+    pub rows: Vec<Vec<SqlValue>>,
+
+    /// The number of rows changed by the statement, available to scripts as `rowsAffected`
+    pub rows_affected: u64,
+}
+
+/// The host's bridge, stored in the op state
+#[derive(Clone)]
+struct SqlBridgeContainer(Arc<dyn SqlBridge>);
+
+#[op2(async)]
+#[serde]
+async fn op_sql_bridge_query(
+    state: Rc<RefCell<OpState>>,
+    #[string] sql: String,
+    #[serde] params: Vec<SqlValue>,
+) -> Result<SqlRows, Error> {
+    let bridge = state
+        .borrow()
+        .try_borrow::<SqlBridgeContainer>()
+        .map(|bridge| bridge.0.clone())
+        .ok_or_else(|| {
+            Error::Runtime("No SQL bridge is configured for this runtime".to_string())
+        })?;
+
+    let result = bridge.query(sql, params).await?;
+    if let Some(row) = result.rows.iter().find(|r| r.len() != result.columns.len()) {
+        return Err(Error::Runtime(format!(
+            "SQL bridge returned a row of {} values for {} columns",
+            row.len(),
+            result.columns.len()
+        )));
+    }
+    Ok(result)
+}
+
+extension!(
+    init_sql_bridge,
+    deps = [rustyscript],
+    ops = [op_sql_bridge_query],
+    esm_entry_point = "ext:init_sql_bridge/init_sql_bridge.js",
+    esm = [ dir "src/ext/sql_bridge", "init_sql_bridge.js" ],
+    options = {
+        bridge: Option<Arc<dyn SqlBridge>>
+    },
+    state = |state, config| {
+        if let Some(bridge) = config.bridge {
+            state.put(SqlBridgeContainer(bridge));
+        }
+    },
+);
+impl ExtensionTrait<Option<Arc<dyn SqlBridge>>> for init_sql_bridge {
+    fn init(bridge: Option<Arc<dyn SqlBridge>>) -> Extension {
+        init_sql_bridge::init_ops_and_esm(bridge)
+    }
+}
+
+pub fn extensions(bridge: Option<Arc<dyn SqlBridge>>, is_snapshot: bool) -> Vec<Extension> {
+    vec![init_sql_bridge::build(bridge, is_snapshot)]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ExtensionOptions, Runtime, RuntimeOptions};
+    use deno_core::serde_json::{json, Value};
+    use std::sync::Mutex;
+
+    /// Records each query, and returns a fixed result
+    struct MockBridge(Mutex<Vec<(String, Vec<SqlValue>)>>);
+    impl SqlBridge for MockBridge {
+        fn query(
+            &self,
+            sql: String,
+            params: Vec<SqlValue>,
+        ) -> Pin<Box<dyn Future<Output = Result<SqlRows, Error>>>> {
+            self.0.lock().unwrap().push((sql.clone(), params));
+            Box::pin(async move {
+                if sql.starts_with("DELETE") {
+                    return Err(Error::Runtime("not allowed".to_string()));
+                }
+
+                Ok(SqlRows {
+                    columns: vec![
+                        SqlColumn::new("id", SqlType::BigInt),
+                        SqlColumn::new("active", SqlType::Boolean),
+                        SqlColumn::new("meta", SqlType::Json),
+                        SqlColumn::new("created", SqlType::Timestamp),
+                    ],
+                    rows: vec![vec![
+                        SqlValue::Integer(1),
+                        SqlValue::Integer(1),
+                        SqlValue::Text(r#"{"tags":["a"]}"#.to_string()),
+                        SqlValue::Text("2024-01-02T03:04:05.000Z".to_string()),
+                    ]],
+                    rows_affected: 0,
+                })
+            })
+        }
+    }
+
+    #[test]
+    fn test_sql_bridge() {
+        let bridge = Arc::new(MockBridge(Mutex::default()));
+        let mut runtime = Runtime::new(RuntimeOptions {
+            extension_options: ExtensionOptions {
+                sql_bridge: Some(bridge.clone()),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .unwrap();
+
+        let row: Value = runtime
+            .eval(
+                "host.sql('SELECT * FROM users WHERE id = ? AND name = ?', [1, 'bob', 1.5, null, new Uint8Array([7])])
+                    .then(([row]) => ({
+                        id: typeof row.id,
+                        active: row.active,
+                        tags: row.meta.tags,
+                        year: row.created.getUTCFullYear(),
+                    }))",
+            )
+            .unwrap();
+        assert_eq!(
+            row,
+            json!({ "id": "bigint", "active": true, "tags": ["a"], "year": 2024 })
+        );
+
+        let error: String = runtime
+            .eval("host.sql('DELETE FROM users').then(() => 'ok', e => e.message)")
+            .unwrap();
+        assert!(error.contains("not allowed"));
+
+        let queries = bridge.0.lock().unwrap();
+        assert_eq!(
+            queries[0].1,
+            [
+                SqlValue::Integer(1),
+                SqlValue::Text("bob".to_string()),
+                SqlValue::Real(1.5),
+                SqlValue::Null,
+                SqlValue::Blob(vec![7]),
+            ]
+        );
+    }
+}
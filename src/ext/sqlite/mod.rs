@@ -0,0 +1,509 @@
+//! A synchronous sqlite API for scripts, modelled on `node:sqlite`
+//!
+//! Scripts import `DatabaseSync` and `StatementSync` from `rustyscript:sqlite`
+//! Where databases are stored is decided by the host, through [`SqliteOptions`]
+use super::ExtensionTrait;
+use deno_core::{
+    anyhow::{anyhow, Error},
+    extension, op2, Extension, ModuleSpecifier, OpState, Resource, ResourceId, ToJsBuffer,
+};
+use rusqlite::{
+    hooks::{AuthAction, AuthContext, Authorization},
+    limits::Limit,
+    types::Value,
+    Connection, OpenFlags,
+};
+use serde::{de::Visitor, Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+};
+
+/// Where the databases opened by scripts are stored
+///
+/// In every mode `ATTACH`, `DETACH` and `VACUUM` are refused, so scripts cannot open any other file
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum SqliteStorage {
+    /// Every database is a private in-memory database, whatever path the script gives
+    /// Scripts cannot reach the filesystem
+    #[default]
+    Memory,
+
+    /// Databases are files in this directory, named by the path the script gives
+    /// Paths are relative to the directory, and cannot leave it - `:memory:` still opens an in-memory database
+    Directory(PathBuf),
+}
+
+/// Configuration for the `sqlite` extension
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SqliteOptions {
+    /// Where databases are stored
+    pub storage: SqliteStorage,
+
+    /// Maximum number of databases a runtime can have open at once
+    pub max_connections: usize,
+}
+
+impl Default for SqliteOptions {
+    fn default() -> Self {
+        Self {
+            storage: SqliteStorage::Memory,
+            max_connections: 8,
+        }
+    }
+}
+
+extension!(
+    init_sqlite,
+    deps = [rustyscript],
+    ops = [op_sqlite_open, op_sqlite_exec, op_sqlite_prepare, op_sqlite_query],
+    esm = [ dir "src/ext/sqlite", "sqlite.js" ],
+    options = {
+        options: SqliteOptions
+    },
+    state = |state, config| state.put(config.options),
+);
+impl ExtensionTrait<SqliteOptions> for init_sqlite {
+    fn init(options: SqliteOptions) -> Extension {
+        init_sqlite::init_ops_and_esm(options)
+    }
+}
+
+pub fn extensions(options: SqliteOptions, is_snapshot: bool) -> Vec<Extension> {
+    vec![init_sqlite::build(options, is_snapshot)]
+}
+
+/// Resolves the `rustyscript:sqlite` specifier to the extension module providing it
+pub fn resolve(specifier: &str) -> Option<ModuleSpecifier> {
+    if specifier == "rustyscript:sqlite" {
+        ModuleSpecifier::parse("ext:init_sqlite/sqlite.js").ok()
+    } else {
+        None
+    }
+}
+
+/// An open database, closed when the script calls `close` or the runtime is dropped
+struct SqliteDatabase(Connection);
+impl Resource for SqliteDatabase {
+    fn name(&self) -> Cow<'_, str> {
+        "sqliteDatabase".into()
+    }
+}
+
+fn database(state: &OpState, rid: ResourceId) -> Result<std::rc::Rc<SqliteDatabase>, Error> {
+    state
+        .resource_table
+        .get::<SqliteDatabase>(rid)
+        .map_err(|_| anyhow!("database is not open"))
+}
+
+/// Refuses statements that could reach files outside the storage directory
+///
+/// `ATTACH` could name any file, and `VACUUM INTO` attaches its target the same way
+fn authorize(context: AuthContext<'_>) -> Authorization {
+    match context.action {
+        AuthAction::Attach { .. } | AuthAction::Detach { .. } => Authorization::Deny,
+        _ => Authorization::Allow,
+    }
+}
+
+/// Resolves the path given by a script within the storage directory
+fn resolve_path(dir: &Path, path: &str) -> Result<PathBuf, Error> {
+    let relative = Path::new(path);
+    if path.is_empty()
+        || !relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(anyhow!(
+            "invalid database path `{path}` - paths must be relative, and stay within the storage directory"
+        ));
+    }
+    Ok(dir.join(relative))
+}
+
+#[op2(fast)]
+#[smi]
+fn op_sqlite_open(state: &mut OpState, #[string] path: &str) -> Result<ResourceId, Error> {
+    let options = state.borrow::<SqliteOptions>();
+    let open = state
+        .resource_table
+        .names()
+        .filter(|(_, name)| name == "sqliteDatabase")
+        .count();
+    if open >= options.max_connections {
+        return Err(anyhow!(
+            "cannot open more than {} databases at once",
+            options.max_connections
+        ));
+    }
+
+    // URI filenames are not enabled, so a path cannot select another file or VFS
+    let flags = OpenFlags::SQLITE_OPEN_READ_WRITE
+        | OpenFlags::SQLITE_OPEN_CREATE
+        | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+    let connection = match &options.storage {
+        SqliteStorage::Directory(dir) if path != ":memory:" => {
+            Connection::open_with_flags(resolve_path(dir, path)?, flags)?
+        }
+        _ => Connection::open_in_memory_with_flags(flags)?,
+    };
+
+    // Databases cannot be attached - this also stops `VACUUM`, which attaches a database internally
+    connection.set_limit(Limit::SQLITE_LIMIT_ATTACHED, 0);
+    connection.authorizer(Some(authorize));
+
+    Ok(state.resource_table.add(SqliteDatabase(connection)))
+}
+
+#[op2(fast)]
+fn op_sqlite_exec(
+    state: &mut OpState,
+    #[smi] rid: ResourceId,
+    #[string] sql: &str,
+) -> Result<(), Error> {
+    database(state, rid)?.0.execute_batch(sql)?;
+    Ok(())
+}
+
+/// Checks that the statement is valid, and returns its column names
+#[op2]
+#[serde]
+fn op_sqlite_prepare(
+    state: &mut OpState,
+    #[smi] rid: ResourceId,
+    #[string] sql: &str,
+) -> Result<Vec<String>, Error> {
+    let database = database(state, rid)?;
+    let statement = database.0.prepare_cached(sql)?;
+    Ok(statement
+        .column_names()
+        .into_iter()
+        .map(String::from)
+        .collect())
+}
+
+/// A value bound to a statement parameter
+struct Param(Value);
+impl<'de> Deserialize<'de> for Param {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ParamVisitor;
+        impl Visitor<'_> for ParamVisitor {
+            type Value = Param;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("null, a number, a string, or a Uint8Array")
+            }
+
+            fn visit_unit<E>(self) -> Result<Param, E> {
+                Ok(Param(Value::Null))
+            }
+
+            fn visit_bool<E>(self, v: bool) -> Result<Param, E> {
+                Ok(Param(Value::Integer(v.into())))
+            }
+
+            fn visit_i64<E>(self, v: i64) -> Result<Param, E> {
+                Ok(Param(Value::Integer(v)))
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Param, E> {
+                i64::try_from(v)
+                    .map(|v| Param(Value::Integer(v)))
+                    .map_err(E::custom)
+            }
+
+            #[allow(clippy::cast_possible_truncation)]
+            fn visit_f64<E>(self, v: f64) -> Result<Param, E> {
+                // Javascript numbers that hold integers are bound as integers
+                if v.fract() == 0.0 && v.abs() <= 9_007_199_254_740_991.0 {
+                    Ok(Param(Value::Integer(v as i64)))
+                } else {
+                    Ok(Param(Value::Real(v)))
+                }
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Param, E> {
+                Ok(Param(Value::Text(v.to_string())))
+            }
+
+            fn visit_string<E>(self, v: String) -> Result<Param, E> {
+                Ok(Param(Value::Text(v)))
+            }
+
+            fn visit_bytes<E>(self, v: &[u8]) -> Result<Param, E> {
+                Ok(Param(Value::Blob(v.to_vec())))
+            }
+
+            fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Param, E> {
+                Ok(Param(Value::Blob(v)))
+            }
+        }
+
+        deserializer.deserialize_any(ParamVisitor)
+    }
+}
+
+/// A value read from a row
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Column {
+    Null(()),
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(ToJsBuffer),
+}
+
+impl From<Value> for Column {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Null => Self::Null(()),
+            Value::Integer(v) => Self::Integer(v),
+            Value::Real(v) => Self::Real(v),
+            Value::Text(v) => Self::Text(v),
+            Value::Blob(v) => Self::Blob(v.into()),
+        }
+    }
+}
+
+/// How many rows a query should read
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum QueryMode {
+    /// Run the statement to completion, and return no rows
+    Run,
+
+    /// Return the first row
+    Get,
+
+    /// Return every row
+    All,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct QueryResult {
+    rows: Vec<Vec<Column>>,
+    changes: u64,
+    last_insert_rowid: i64,
+}
+
+#[op2]
+#[serde]
+fn op_sqlite_query(
+    state: &mut OpState,
+    #[smi] rid: ResourceId,
+    #[string] sql: &str,
+    #[serde] positional: Vec<Param>,
+    #[serde] named: Option<HashMap<String, Param>>,
+    #[serde] mode: QueryMode,
+) -> Result<QueryResult, Error> {
+    let database = database(state, rid)?;
+    let mut statement = database.0.prepare_cached(sql)?;
+
+    // Named parameters match with or without their prefix, like `node:sqlite`
+    let mut positional = positional.into_iter();
+    for index in 1..=statement.parameter_count() {
+        let value = match (statement.parameter_name(index), &named) {
+            (Some(name), Some(named)) => named
+                .get(name)
+                .or_else(|| named.get(&name[1..]))
+                .map(|p| p.0.clone())
+                .ok_or_else(|| anyhow!("missing value for parameter `{name}`"))?,
+            _ => positional.next().map_or(Value::Null, |p| p.0),
+        };
+        statement.raw_bind_parameter(index, value)?;
+    }
+
+    let column_count = statement.column_count();
+    let mut rows = Vec::new();
+    let mut cursor = statement.raw_query();
+    while let Some(row) = cursor.next()? {
+        if mode == QueryMode::Run {
+            continue;
+        }
+
+        let columns = (0..column_count)
+            .map(|i| row.get::<_, Value>(i).map(Column::from))
+            .collect::<Result<_, _>>()?;
+        rows.push(columns);
+        if mode == QueryMode::Get {
+            break;
+        }
+    }
+    drop(cursor);
+
+    Ok(QueryResult {
+        rows,
+        changes: database.0.changes(),
+        last_insert_rowid: database.0.last_insert_rowid(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{json_args, ExtensionOptions, Module, Runtime, RuntimeOptions};
+    use deno_core::serde_json::{json, Value as JsonValue};
+
+    fn runtime(options: SqliteOptions) -> Runtime {
+        Runtime::new(RuntimeOptions {
+            extension_options: ExtensionOptions {
+                sqlite: options,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_sqlite() {
+        let mut runtime = runtime(SqliteOptions::default());
+        let module = runtime
+            .load_module(&Module::new(
+                "test.js",
+                "
+                import { DatabaseSync } from 'rustyscript:sqlite';
+                const db = new DatabaseSync(':memory:');
+                db.exec('CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT, price REAL, data BLOB)');
+
+                const insert = db.prepare('INSERT INTO items (name, price, data) VALUES (?, ?, ?)');
+                export const inserted = [
+                    insert.run('apple', 1.5, new Uint8Array([1, 2])),
+                    insert.run('pear', 2, null),
+                ];
+                db.prepare('UPDATE items SET price = :price WHERE name = $name').run({ price: 3.25, name: 'pear' });
+
+                export const all = () => db.prepare('SELECT name, price FROM items ORDER BY id').all();
+                export const get = (name) => db.prepare('SELECT * FROM items WHERE name = ?').get(name);
+                export const blob = () => Array.from(get('apple').data);
+                export const close = () => { db.close(); return db.isOpen; };
+                ",
+            ))
+            .unwrap();
+
+        let inserted: JsonValue = runtime.get_value(Some(&module), "inserted").unwrap();
+        assert_eq!(
+            inserted,
+            json!([
+                { "changes": 1, "lastInsertRowid": 1 },
+                { "changes": 1, "lastInsertRowid": 2 },
+            ])
+        );
+
+        let all: JsonValue = runtime
+            .call_function(Some(&module), "all", json_args!())
+            .unwrap();
+        assert_eq!(
+            all,
+            json!([{ "name": "apple", "price": 1.5 }, { "name": "pear", "price": 3.25 }])
+        );
+
+        let missing: Option<JsonValue> = runtime
+            .call_function(Some(&module), "get", json_args!("plum"))
+            .unwrap();
+        assert_eq!(missing, None);
+
+        let blob: Vec<u8> = runtime
+            .call_function(Some(&module), "blob", json_args!())
+            .unwrap();
+        assert_eq!(blob, [1, 2]);
+
+        let open: bool = runtime
+            .call_function(Some(&module), "close", json_args!())
+            .unwrap();
+        assert!(!open);
+        runtime
+            .call_function::<JsonValue>(Some(&module), "all", json_args!())
+            .unwrap_err();
+    }
+
+    #[test]
+    fn test_sqlite_limits() {
+        let dir = std::env::temp_dir().join(format!("rustyscript_sqlite_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut runtime = runtime(SqliteOptions {
+            storage: SqliteStorage::Directory(dir.clone()),
+            max_connections: 1,
+        });
+        runtime
+            .eval::<()>(
+                "globalThis.sqlite = import('rustyscript:sqlite');
+                sqlite.then(({ DatabaseSync }) => {
+                    const db = new DatabaseSync('test.db');
+                    db.exec('CREATE TABLE t (x)');
+                    globalThis.db = db;
+                })",
+            )
+            .unwrap();
+        assert!(dir.join("test.db").exists());
+
+        // The connection limit applies until a database is closed
+        let error = runtime
+            .eval::<()>("sqlite.then(({ DatabaseSync }) => new DatabaseSync('other.db'))")
+            .unwrap_err();
+        assert!(error.to_string().contains("more than 1"));
+        runtime
+            .eval::<()>("db.close(); sqlite.then(({ DatabaseSync }) => new DatabaseSync(':memory:').close())")
+            .unwrap();
+
+        // Paths cannot leave the storage directory
+        for path in ["../escape.db", "/tmp/escape.db", ""] {
+            let error = runtime
+                .eval::<()>(format!(
+                    "sqlite.then(({{ DatabaseSync }}) => new DatabaseSync({path:?}))"
+                ))
+                .unwrap_err();
+            assert!(
+                error.to_string().contains("invalid database path"),
+                "{error}"
+            );
+        }
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_sqlite_attach() {
+        let dir =
+            std::env::temp_dir().join(format!("rustyscript_sqlite_attach_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let target = std::env::temp_dir().join(format!(
+            "rustyscript_sqlite_escape_{}.db",
+            std::process::id()
+        ));
+
+        for storage in [SqliteStorage::Memory, SqliteStorage::Directory(dir.clone())] {
+            let mut runtime = runtime(SqliteOptions {
+                storage,
+                ..Default::default()
+            });
+            runtime
+                .eval::<()>(
+                    "globalThis.sqlite = import('rustyscript:sqlite');
+                    sqlite.then(({ DatabaseSync }) => {
+                        globalThis.db = new DatabaseSync('test.db');
+                        db.exec('CREATE TABLE t (x)');
+                    })",
+                )
+                .unwrap();
+
+            // Neither statement can reach a file outside the storage directory
+            for sql in [
+                format!("ATTACH DATABASE '{}' AS other", target.display()),
+                format!("VACUUM INTO '{}'", target.display()),
+            ] {
+                runtime
+                    .eval::<()>(format!("db.exec({sql:?})"))
+                    .expect_err("Statement was not refused");
+                assert!(!target.exists(), "{sql} created a file");
+            }
+        }
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,149 @@
+// A synchronous SQLite API, modelled on `node:sqlite`
+// Imported by scripts as `rustyscript:sqlite`
+import { core } from "ext:core/mod.js";
+const { op_sqlite_open, op_sqlite_exec, op_sqlite_prepare, op_sqlite_query } = core.ops;
+
+let databaseRid;
+
+/**
+ * Converts the arguments of a statement call into positional and named parameters
+ * A plain object as the first argument holds the named parameters
+ */
+function bindParameters(args) {
+    let named = null;
+    if (args.length > 0 && args[0] !== null && typeof args[0] === 'object' && !ArrayBuffer.isView(args[0])) {
+        named = Object.fromEntries(Object.entries(args[0]).map(([k, v]) => [k, toSqlValue(v)]));
+        args = args.slice(1);
+    }
+    return [args.map(toSqlValue), named];
+}
+
+function toSqlValue(value) {
+    if (value === undefined) {
+        return null;
+    } else if (typeof value === 'bigint') {
+        if (value > BigInt(Number.MAX_SAFE_INTEGER) || value < BigInt(Number.MIN_SAFE_INTEGER)) {
+            throw new RangeError(`BigInt value ${value} is outside of the safe integer range`);
+        }
+        return Number(value);
+    } else if (typeof value === 'boolean') {
+        return value ? 1 : 0;
+    }
+    return value;
+}
+
+export class DatabaseSync {
+    #path;
+    #rid = null;
+
+    static {
+        databaseRid = (db) => db.#requireOpen();
+    }
+
+    /**
+     * Opens a database - where it is stored is decided by the host
+     * @param {string} path The name of the database, or `:memory:`
+     * @param {{ open?: boolean }} options Set `open` to false to open the database later, with `open()`
+     */
+    constructor(path, options = {}) {
+        this.#path = String(path);
+        if (options.open ?? true) {
+            this.open();
+        }
+    }
+
+    get isOpen() {
+        return this.#rid !== null;
+    }
+
+    open() {
+        if (this.#rid !== null) {
+            throw new Error('database is already open');
+        }
+        this.#rid = op_sqlite_open(this.#path);
+    }
+
+    close() {
+        core.close(this.#requireOpen());
+        this.#rid = null;
+    }
+
+    /**
+     * Runs one or more statements, without returning results
+     * @param {string} sql
+     */
+    exec(sql) {
+        op_sqlite_exec(this.#requireOpen(), String(sql));
+    }
+
+    /**
+     * @param {string} sql
+     * @returns {StatementSync}
+     */
+    prepare(sql) {
+        return new StatementSync(this, String(sql));
+    }
+
+    #requireOpen() {
+        if (this.#rid === null) {
+            throw new Error('database is not open');
+        }
+        return this.#rid;
+    }
+}
+
+export class StatementSync {
+    #db;
+    #sql;
+    #columns;
+
+    constructor(db, sql) {
+        if (!(db instanceof DatabaseSync)) {
+            throw new TypeError('Illegal constructor');
+        }
+        this.#db = db;
+        this.#sql = sql;
+        this.#columns = op_sqlite_prepare(databaseRid(db), sql);
+    }
+
+    get sourceSQL() {
+        return this.#sql;
+    }
+
+    /**
+     * Runs the statement, returning the number of changed rows and the last inserted rowid
+     * @returns {{ changes: number, lastInsertRowid: number }}
+     */
+    run(...args) {
+        const { changes, lastInsertRowid } = this.#query(args, 'run');
+        return { changes, lastInsertRowid };
+    }
+
+    /**
+     * Returns the first row, or undefined
+     */
+    get(...args) {
+        const [row] = this.#query(args, 'get').rows;
+        return row === undefined ? undefined : this.#toObject(row);
+    }
+
+    /**
+     * Returns every row
+     */
+    all(...args) {
+        return this.#query(args, 'all').rows.map((row) => this.#toObject(row));
+    }
+
+    #query(args, mode) {
+        const [positional, named] = bindParameters(args);
+        return op_sqlite_query(databaseRid(this.#db), this.#sql, positional, named, mode);
+    }
+
+    #toObject(row) {
+        const object = {};
+        for (let i = 0; i < this.#columns.length; i++) {
+            object[this.#columns[i]] = row[i];
+        }
+        return object;
+    }
+}
@@ -0,0 +1,353 @@
+use deno_core::{
+    op2,
+    url::{Host, Url},
+    OpState,
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+/// How `fetch` handles cookies, see [`super::WebOptions::cookies`]
+#[derive(Debug, Clone, Default)]
+pub enum CookiePolicy {
+    /// Cookies are not stored, but scripts may still send `Cookie` headers and read `Set-Cookie` ones
+    ///
+    /// This is the behaviour of `deno_fetch`
+    #[default]
+    Ignore,
+
+    /// Cookies are neither stored nor sent - `Cookie` headers are removed from requests,
+    /// and `Set-Cookie` headers from responses
+    Block,
+
+    /// Cookies are stored in the jar, and sent with matching requests
+    ///
+    /// The jar can be filled before execution and read back afterwards - give each tenant its own
+    /// jar so sessions are not shared between them
+    Jar(CookieJar),
+}
+
+/// A single cookie stored in a [`CookieJar`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cookie {
+    /// The name of the cookie
+    pub name: String,
+
+    /// The value of the cookie
+    pub value: String,
+
+    /// The domain the cookie is sent to
+    pub domain: String,
+
+    /// If false, the cookie is also sent to subdomains of [`Cookie::domain`]
+    pub host_only: bool,
+
+    /// The path prefix the cookie is sent to
+    pub path: String,
+
+    /// If true, the cookie is only sent over HTTPS
+    pub secure: bool,
+
+    /// The time the cookie expires, or `None` for a session cookie
+    pub expires: Option<SystemTime>,
+}
+impl Cookie {
+    /// Parse a `Set-Cookie` header value received from `url`
+    ///
+    /// Returns `None` if the header is malformed, or sets a cookie for a different domain
+    ///
+    /// `Domain` must name a domain with at least two labels, and is ignored on IP addresses - unless it names the
+    /// host itself, which keeps the cookie host-only. The public suffix list is not consulted, so a multi-label
+    /// public suffix such as `co.uk` is still accepted
+    #[must_use]
+    pub fn parse(url: &Url, header: &str) -> Option<Self> {
+        let host = url.host_str()?.to_ascii_lowercase();
+        let mut parts = header.split(';');
+        let (name, value) = parts.next()?.split_once('=')?;
+        let name = name.trim();
+        if name.is_empty() {
+            return None;
+        }
+
+        let mut cookie = Self {
+            name: name.to_string(),
+            value: value.trim().to_string(),
+            domain: host.clone(),
+            host_only: true,
+            path: default_path(url),
+            secure: false,
+            expires: None,
+        };
+
+        let mut max_age = None;
+        for attribute in parts {
+            let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "domain" if !value.is_empty() => {
+                    let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                    if !domain_matches(&host, &domain) {
+                        return None;
+                    }
+
+                    // Single labels such as `com` are treated as public suffixes
+                    let is_ip = !matches!(url.host(), Some(Host::Domain(_)));
+                    if is_ip || !domain.contains('.') {
+                        if domain != host {
+                            return None;
+                        }
+                        continue;
+                    }
+
+                    cookie.domain = domain;
+                    cookie.host_only = false;
+                }
+                "path" if value.starts_with('/') => cookie.path = value.to_string(),
+                "secure" => cookie.secure = true,
+                "max-age" => max_age = value.parse::<i64>().ok(),
+                "expires" => {
+                    if let Ok(expires) = httpdate::parse_http_date(value) {
+                        cookie.expires = Some(expires);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Max-Age takes precedence over Expires
+        if let Some(max_age) = max_age {
+            cookie.expires = Some(match u64::try_from(max_age) {
+                Ok(secs) if secs > 0 => SystemTime::now() + std::time::Duration::from_secs(secs),
+                _ => SystemTime::UNIX_EPOCH,
+            });
+        }
+
+        Some(cookie)
+    }
+
+    /// Returns true if the cookie has expired
+    #[must_use]
+    pub fn is_expired(&self) -> bool {
+        self.expires
+            .is_some_and(|expires| expires <= SystemTime::now())
+    }
+
+    /// Returns true if the cookie should be sent with a request to `url`
+    #[must_use]
+    pub fn matches(&self, url: &Url) -> bool {
+        let Some(host) = url.host_str() else {
+            return false;
+        };
+        let host = host.to_ascii_lowercase();
+
+        let domain_ok = if self.host_only {
+            host == self.domain
+        } else {
+            domain_matches(&host, &self.domain)
+        };
+        let path = url.path();
+        let path_ok = path == self.path
+            || (path.starts_with(&self.path)
+                && (self.path.ends_with('/') || path[self.path.len()..].starts_with('/')));
+
+        domain_ok && path_ok && (!self.secure || url.scheme() == "https") && !self.is_expired()
+    }
+}
+
+fn domain_matches(host: &str, domain: &str) -> bool {
+    host == domain
+        || (host.ends_with(domain)
+            && host[..host.len() - domain.len()].ends_with('.')
+            && host.parse::<std::net::IpAddr>().is_err())
+}
+
+fn default_path(url: &Url) -> String {
+    match url.path().rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(i) => url.path()[..i].to_string(),
+    }
+}
+
+/// A cookie jar shared between the host and `fetch`
+///
+/// Cloning it gives another handle to the same jar
+#[derive(Debug, Clone, Default)]
+pub struct CookieJar(Arc<Mutex<Vec<Cookie>>>);
+impl CookieJar {
+    /// Create a new, empty jar
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn borrow(&self) -> std::sync::MutexGuard<Vec<Cookie>> {
+        self.0.lock().expect("Could not lock cookie jar")
+    }
+
+    /// Store a cookie, replacing any with the same name, domain and path
+    ///
+    /// Expired cookies remove the one they replace instead
+    pub fn insert(&self, cookie: Cookie) {
+        let mut cookies = self.borrow();
+        cookies.retain(|c| {
+            !(c.name == cookie.name && c.domain == cookie.domain && c.path == cookie.path)
+        });
+        if !cookie.is_expired() {
+            cookies.push(cookie);
+        }
+    }
+
+    /// Store a cookie from a `Set-Cookie` header value, as if it was received from `url`
+    ///
+    /// Returns false if the header could not be parsed
+    pub fn set_cookie(&self, url: &Url, header: &str) -> bool {
+        match Cookie::parse(url, header) {
+            Some(cookie) => {
+                self.insert(cookie);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the value of the `Cookie` header for a request to `url`, if any cookie matches
+    #[must_use]
+    pub fn header_for(&self, url: &Url) -> Option<String> {
+        let mut cookies = self.borrow();
+        cookies.retain(|c| !c.is_expired());
+
+        let mut matching: Vec<_> = cookies.iter().filter(|c| c.matches(url)).collect();
+        if matching.is_empty() {
+            return None;
+        }
+
+        // Longer paths first, as browsers do
+        matching.sort_by(|a, b| b.path.len().cmp(&a.path.len()));
+        let pairs: Vec<_> = matching
+            .iter()
+            .map(|c| format!("{}={}", c.name, c.value))
+            .collect();
+        Some(pairs.join("; "))
+    }
+
+    /// Returns every unexpired cookie in the jar
+    #[must_use]
+    pub fn cookies(&self) -> Vec<Cookie> {
+        self.borrow()
+            .iter()
+            .filter(|c| !c.is_expired())
+            .cloned()
+            .collect()
+    }
+
+    /// Remove every cookie from the jar
+    pub fn clear(&self) {
+        self.borrow().clear();
+    }
+}
+
+/// Returns 0 for [`CookiePolicy::Ignore`], 1 for [`CookiePolicy::Block`] and 2 for [`CookiePolicy::Jar`]
+#[op2(fast)]
+pub fn op_fetch_cookie_policy(state: &OpState) -> u32 {
+    match state.try_borrow::<CookiePolicy>() {
+        None | Some(CookiePolicy::Ignore) => 0,
+        Some(CookiePolicy::Block) => 1,
+        Some(CookiePolicy::Jar(_)) => 2,
+    }
+}
+
+#[op2]
+#[serde]
+pub fn op_fetch_cookie_header(state: &OpState, #[string] url: &str) -> Option<String> {
+    let Some(CookiePolicy::Jar(jar)) = state.try_borrow::<CookiePolicy>() else {
+        return None;
+    };
+    jar.header_for(&Url::parse(url).ok()?)
+}
+
+#[op2]
+pub fn op_fetch_store_cookies(state: &OpState, #[string] url: &str, #[serde] headers: Vec<String>) {
+    let Some(CookiePolicy::Jar(jar)) = state.try_borrow::<CookiePolicy>() else {
+        return;
+    };
+    let Ok(url) = Url::parse(url) else {
+        return;
+    };
+    for header in headers {
+        jar.set_cookie(&url, &header);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cookie_jar() {
+        let jar = CookieJar::new();
+        let url = Url::parse("https://app.example.com/login").unwrap();
+
+        assert!(jar.set_cookie(&url, "session=abc; Path=/; Secure; HttpOnly"));
+        assert!(jar.set_cookie(&url, "theme=dark; Domain=example.com"));
+        assert!(!jar.set_cookie(&url, "evil=1; Domain=other.com"));
+
+        let api = Url::parse("https://api.example.com/").unwrap();
+        assert_eq!(jar.header_for(&api), Some("theme=dark".to_string()));
+
+        // Secure cookies are not sent over http
+        let insecure = Url::parse("http://app.example.com/").unwrap();
+        assert_eq!(jar.header_for(&insecure), Some("theme=dark".to_string()));
+
+        let header = jar.header_for(&Url::parse("https://app.example.com/home").unwrap());
+        assert_eq!(header, Some("session=abc; theme=dark".to_string()));
+
+        // Expiring a cookie removes it
+        jar.set_cookie(&url, "session=; Path=/; Max-Age=0");
+        assert_eq!(jar.cookies().len(), 1);
+
+        jar.clear();
+        assert!(jar.cookies().is_empty());
+    }
+
+    #[test]
+    fn test_cookie_path_matching() {
+        let url = Url::parse("https://example.com/docs/page").unwrap();
+        let cookie = Cookie::parse(&url, "a=1").unwrap();
+        assert_eq!(cookie.path, "/docs");
+
+        assert!(cookie.matches(&Url::parse("https://example.com/docs").unwrap()));
+        assert!(cookie.matches(&Url::parse("https://example.com/docs/other").unwrap()));
+        assert!(!cookie.matches(&Url::parse("https://example.com/docsx").unwrap()));
+        assert!(!cookie.matches(&Url::parse("https://sub.example.com/docs").unwrap()));
+    }
+
+    #[test]
+    fn test_cookie_domain_restrictions() {
+        let url = Url::parse("https://app.example.com/").unwrap();
+        assert!(Cookie::parse(&url, "a=1; Domain=com").is_none());
+        assert!(Cookie::parse(&url, "a=1; Domain=.com").is_none());
+        assert!(
+            !Cookie::parse(&url, "a=1; Domain=example.com")
+                .unwrap()
+                .host_only
+        );
+
+        // Naming the host itself keeps the cookie host-only
+        let url = Url::parse("http://localhost/").unwrap();
+        assert!(
+            Cookie::parse(&url, "a=1; Domain=localhost")
+                .unwrap()
+                .host_only
+        );
+
+        let url = Url::parse("http://10.0.0.1/").unwrap();
+        assert!(Cookie::parse(&url, "a=1; Domain=0.0.1").is_none());
+        let cookie = Cookie::parse(&url, "a=1; Domain=10.0.0.1").unwrap();
+        assert!(cookie.host_only);
+        assert!(!cookie.matches(&Url::parse("http://110.0.0.1/").unwrap()));
+
+        let url = Url::parse("http://[::1]/").unwrap();
+        assert!(Cookie::parse(&url, "a=1; Domain=1]").is_none());
+    }
+}
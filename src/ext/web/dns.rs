@@ -0,0 +1,392 @@
+use super::{PermissionDenied, SystemsPermissionKind, WebPermissions};
+use hyper_util::client::legacy::connect::dns::Name;
+use std::{
+    borrow::Cow,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// The outcome of a [`DnsResolver`] lookup
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DnsResolution {
+    /// Resolve the hostname normally, using the system resolver
+    System,
+
+    /// Connect to these addresses instead of resolving the hostname
+    ///
+    /// An empty list is treated as a rejection
+    Pinned(Vec<IpAddr>),
+
+    /// Refuse to connect, with the given reason
+    Rejected(String),
+}
+
+/// A hook mapping hostnames to addresses, or rejecting them, before a connection is made
+///
+/// Used by `fetch`, `WebSocket` and the network OPs - see [`super::WebOptions::dns_resolver`]
+pub trait DnsResolver: std::fmt::Debug + Send + Sync {
+    /// Decide how `host` should be resolved
+    ///
+    /// `host` is a hostname or an IP literal, without the port
+    fn resolve(&self, host: &str) -> DnsResolution;
+
+    /// Check the addresses a hostname resolved to, just before connecting
+    ///
+    /// Called with the pinned addresses, or the ones returned by the system resolver
+    /// Returning an error refuses the connection - the default accepts every address
+    ///
+    /// # Errors
+    /// If an error is returned, the connection will be refused with the error message as the reason
+    fn check_addrs(&self, _host: &str, _addrs: &[IpAddr]) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Check a hostname that `WebSocket` or the network OPs are about to connect to
+    ///
+    /// Those resolve the hostname again on their own when they connect, so the addresses checked with
+    /// [`DnsResolver::check_addrs`] are not necessarily the ones used - a DNS rebinding attack can swap them in between
+    /// Returning an error refuses the connection, leaving only IP addresses - the default accepts every hostname
+    ///
+    /// # Errors
+    /// If an error is returned, the connection will be refused with the error message as the reason
+    fn check_unpinned(&self, _host: &str) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// A [`DnsResolver`] backed by a static table of hostnames
+///
+/// Hostnames missing from the table are resolved normally, unless [`StaticDnsResolver::reject_unknown`] is set
+#[derive(Debug, Clone, Default)]
+pub struct StaticDnsResolver {
+    hosts: std::collections::HashMap<String, DnsResolution>,
+    reject_unknown: bool,
+}
+impl StaticDnsResolver {
+    /// Create a new resolver with an empty table
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resolve `host` to the given addresses
+    #[must_use]
+    pub fn pin(mut self, host: impl ToString, addrs: impl IntoIterator<Item = IpAddr>) -> Self {
+        self.hosts.insert(
+            host.to_string().to_ascii_lowercase(),
+            DnsResolution::Pinned(addrs.into_iter().collect()),
+        );
+        self
+    }
+
+    /// Refuse connections to `host`
+    #[must_use]
+    pub fn reject(mut self, host: impl ToString) -> Self {
+        self.hosts.insert(
+            host.to_string().to_ascii_lowercase(),
+            DnsResolution::Rejected("Host is blocked".to_string()),
+        );
+        self
+    }
+
+    /// Refuse connections to any hostname missing from the table
+    #[must_use]
+    pub fn reject_unknown(mut self, value: bool) -> Self {
+        self.reject_unknown = value;
+        self
+    }
+}
+impl DnsResolver for StaticDnsResolver {
+    fn resolve(&self, host: &str) -> DnsResolution {
+        match self.hosts.get(&host.to_ascii_lowercase()) {
+            Some(resolution) => resolution.clone(),
+            None if self.reject_unknown => DnsResolution::Rejected("Unknown host".to_string()),
+            None => DnsResolution::System,
+        }
+    }
+}
+
+/// Strips the brackets from IPv6 literals, as found in URLs
+fn bare_host(host: &str) -> &str {
+    host.trim_start_matches('[').trim_end_matches(']')
+}
+
+/// Resolve `host` through the hook, falling back to the system resolver
+async fn lookup(resolver: &dyn DnsResolver, host: &str) -> std::io::Result<Vec<IpAddr>> {
+    let denied = |reason: String| {
+        std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            format!("Connection to {host} refused: {reason}"),
+        )
+    };
+
+    let addrs = match resolver.resolve(host) {
+        DnsResolution::Rejected(reason) => return Err(denied(reason)),
+        DnsResolution::Pinned(addrs) if addrs.is_empty() => {
+            return Err(denied("No addresses".to_string()))
+        }
+        DnsResolution::Pinned(addrs) => addrs,
+        DnsResolution::System => match host.parse::<IpAddr>() {
+            Ok(ip) => vec![ip],
+            Err(_) => tokio::net::lookup_host((host, 0))
+                .await?
+                .map(|addr| addr.ip())
+                .collect(),
+        },
+    };
+
+    resolver.check_addrs(host, &addrs).map_err(denied)?;
+    Ok(addrs)
+}
+
+/// Adapts a [`DnsResolver`] to the resolver used by `deno_fetch`'s HTTP client
+///
+/// This runs at connection time, so the checked addresses are the ones actually used
+#[derive(Debug)]
+pub(crate) struct FetchResolver(pub Arc<dyn DnsResolver>);
+impl FetchResolver {
+    pub fn into_resolver(resolver: Arc<dyn DnsResolver>) -> deno_fetch::dns::Resolver {
+        deno_fetch::dns::Resolver::Custom(Arc::new(Self(resolver)))
+    }
+}
+impl deno_fetch::dns::Resolve for FetchResolver {
+    fn resolve(&self, name: Name) -> deno_fetch::dns::Resolving {
+        let resolver = self.0.clone();
+        Box::pin(async move {
+            let addrs = lookup(resolver.as_ref(), bare_host(name.as_str())).await?;
+            Ok(addrs
+                .into_iter()
+                .map(|ip| SocketAddr::new(ip, 0))
+                .collect::<Vec<_>>()
+                .into_iter())
+        })
+    }
+}
+
+/// Applies a [`DnsResolver`] on top of the permissions used by the network OPs and `WebSocket`
+///
+/// Those connect on their own, so hostnames are resolved and checked here instead - pinned addresses
+/// are checked with [`DnsResolver::check_addrs`], but only `fetch` connects to them
+/// Since the connection resolves the hostname again, hostnames are also checked with [`DnsResolver::check_unpinned`]
+#[derive(Debug)]
+pub(crate) struct ResolverPermissions {
+    pub inner: Arc<dyn WebPermissions>,
+    pub resolver: Arc<dyn DnsResolver>,
+}
+impl ResolverPermissions {
+    /// Checks a host before a connection is made to it
+    ///
+    /// `fetch` checks the addresses it connects to through [`FetchResolver`], so only rejections are checked here
+    fn check_resolution(&self, host: &str, is_fetch: bool) -> Result<(), PermissionDenied> {
+        let host = bare_host(host);
+        let resolution = self.resolver.resolve(host);
+        if is_fetch {
+            return match resolution {
+                DnsResolution::System => Ok(()),
+                DnsResolution::Pinned(addrs) if !addrs.is_empty() => Ok(()),
+                _ => PermissionDenied::oops(host),
+            };
+        }
+
+        let is_hostname = host.parse::<IpAddr>().is_err();
+        if is_hostname && self.resolver.check_unpinned(host).is_err() {
+            return PermissionDenied::oops(host);
+        }
+
+        let addrs = match resolution {
+            DnsResolution::System => match host.parse::<IpAddr>() {
+                Ok(ip) => vec![ip],
+
+                // A host that cannot be checked is refused
+                Err(_) => match std::net::ToSocketAddrs::to_socket_addrs(&(host, 0)) {
+                    Ok(addrs) => addrs.map(|addr| addr.ip()).collect(),
+                    Err(_) => return PermissionDenied::oops(host),
+                },
+            },
+            DnsResolution::Pinned(addrs) if !addrs.is_empty() => addrs,
+            _ => return PermissionDenied::oops(host),
+        };
+
+        self.resolver
+            .check_addrs(host, &addrs)
+            .or_else(|_| PermissionDenied::oops(host))
+    }
+}
+impl WebPermissions for ResolverPermissions {
+    fn allow_hrtime(&self) -> bool {
+        self.inner.allow_hrtime()
+    }
+
+    fn check_url(&self, url: &deno_core::url::Url, api_name: &str) -> Result<(), PermissionDenied> {
+        if let Some(host) = url.host_str() {
+            let is_fetch =
+                api_name.starts_with("fetch") || api_name.starts_with("Deno.createHttpClient");
+            self.check_resolution(host, is_fetch)?;
+        }
+        self.inner.check_url(url, api_name)
+    }
+
+    fn check_open<'a>(
+        &self,
+        resolved: bool,
+        read: bool,
+        write: bool,
+        path: &'a Path,
+        api_name: &str,
+    ) -> Option<Cow<'a, Path>> {
+        self.inner.check_open(resolved, read, write, path, api_name)
+    }
+
+    fn check_read<'a>(
+        &self,
+        p: &'a Path,
+        api_name: Option<&str>,
+    ) -> Result<Cow<'a, Path>, PermissionDenied> {
+        self.inner.check_read(p, api_name)
+    }
+
+    fn check_read_all(&self, api_name: Option<&str>) -> Result<(), PermissionDenied> {
+        self.inner.check_read_all(api_name)
+    }
+
+    fn check_read_blind(
+        &self,
+        p: &Path,
+        display: &str,
+        api_name: &str,
+    ) -> Result<(), PermissionDenied> {
+        self.inner.check_read_blind(p, display, api_name)
+    }
+
+    fn check_write<'a>(
+        &self,
+        p: &'a Path,
+        api_name: Option<&str>,
+    ) -> Result<Cow<'a, Path>, PermissionDenied> {
+        self.inner.check_write(p, api_name)
+    }
+
+    fn check_write_all(&self, api_name: &str) -> Result<(), PermissionDenied> {
+        self.inner.check_write_all(api_name)
+    }
+
+    fn check_write_blind(
+        &self,
+        p: &Path,
+        display: &str,
+        api_name: &str,
+    ) -> Result<(), PermissionDenied> {
+        self.inner.check_write_blind(p, display, api_name)
+    }
+
+    fn check_write_partial(&self, path: &str, api_name: &str) -> Result<PathBuf, PermissionDenied> {
+        self.inner.check_write_partial(path, api_name)
+    }
+
+    fn check_host(
+        &self,
+        host: &str,
+        port: Option<u16>,
+        api_name: &str,
+    ) -> Result<(), PermissionDenied> {
+        self.check_resolution(host, false)?;
+        self.inner.check_host(host, port, api_name)
+    }
+
+    fn check_sys(
+        &self,
+        kind: SystemsPermissionKind,
+        api_name: &str,
+    ) -> Result<(), PermissionDenied> {
+        self.inner.check_sys(kind, api_name)
+    }
+
+    fn check_env(&self, var: &str) -> Result<(), PermissionDenied> {
+        self.inner.check_env(var)
+    }
+
+    fn check_exec(&self) -> Result<(), PermissionDenied> {
+        self.inner.check_exec()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_static_resolver() {
+        let resolver = StaticDnsResolver::new()
+            .pin("Internal.Service", ["10.0.0.5".parse().unwrap()])
+            .reject("evil.example");
+
+        assert_eq!(
+            resolver.resolve("internal.service"),
+            DnsResolution::Pinned(vec!["10.0.0.5".parse().unwrap()])
+        );
+        assert!(matches!(
+            resolver.resolve("evil.example"),
+            DnsResolution::Rejected(_)
+        ));
+        assert_eq!(resolver.resolve("example.com"), DnsResolution::System);
+
+        let resolver = resolver.reject_unknown(true);
+        assert!(matches!(
+            resolver.resolve("example.com"),
+            DnsResolution::Rejected(_)
+        ));
+    }
+
+    #[test]
+    fn test_resolver_permissions() {
+        let permissions = ResolverPermissions {
+            inner: Arc::new(super::super::DefaultWebPermissions),
+            resolver: Arc::new(StaticDnsResolver::new().reject("evil.example")),
+        };
+
+        let url = deno_core::url::Url::parse("https://evil.example/path").unwrap();
+        assert!(permissions.check_url(&url, "fetch").is_err());
+        assert!(permissions
+            .check_host("evil.example", Some(80), "Deno.connect")
+            .is_err());
+        assert!(permissions
+            .check_host("localhost", None, "Deno.connect")
+            .is_ok());
+
+        // Hosts that cannot be resolved cannot be checked, and are refused
+        assert!(permissions
+            .check_host("missing.invalid", None, "Deno.connect")
+            .is_err());
+    }
+
+    #[test]
+    fn test_resolver_permissions_unpinned() {
+        let internal =
+            StaticDnsResolver::new().pin("internal.service", ["10.0.0.1".parse().unwrap()]);
+        let protection =
+            crate::SsrfProtection::wrap(Arc::new(internal)).allow_host("internal.service");
+
+        // Connections that cannot be pinned are limited to IP addresses and allowed hosts
+        let permissions = ResolverPermissions {
+            inner: Arc::new(super::super::DefaultWebPermissions),
+            resolver: Arc::new(protection),
+        };
+        assert!(permissions
+            .check_host("example.com", Some(443), "Deno.connect")
+            .is_err());
+        assert!(permissions
+            .check_host("10.0.0.1", Some(443), "Deno.connect")
+            .is_err());
+        assert!(permissions
+            .check_host("93.184.215.14", Some(443), "Deno.connect")
+            .is_ok());
+        assert!(permissions
+            .check_host("internal.service", Some(443), "Deno.connect")
+            .is_ok());
+
+        // `fetch` checks the addresses it connects to instead
+        let url = deno_core::url::Url::parse("https://example.com/").unwrap();
+        assert!(permissions.check_url(&url, "fetch()").is_ok());
+    }
+}
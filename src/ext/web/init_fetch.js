@@ -1,5 +1,5 @@
 import * as headers from "ext:deno_fetch/20_headers.js";
-import * as formData from "ext:deno_fetch/21_formdata.js";
+import * as formData from "ext:deno_fetch/21_formdata.js";
 import * as httpClient from "ext:deno_fetch/22_http_client.js";
 import * as request from "ext:deno_fetch/23_request.js";
 import * as response from "ext:deno_fetch/23_response.js";
@@ -10,8 +10,316 @@ Deno.core.setWasmStreamingCallback(fetch.handleWasmStreaming);
 
 import {applyToGlobal, writeable, nonEnumerable} from 'ext:rustyscript/rustyscript.js';
 
+// Applies the host's default headers after the script's own, so they cannot be overridden
+// See `WebOptions::default_headers`
+function applyDefaultHeaders(req, defaultHeaders) {
+    for (const [name] of defaultHeaders) {
+        req.headers.delete(name);
+    }
+    for (const [name, value] of defaultHeaders) {
+        req.headers.append(name, value);
+    }
+}
+
+function fetchWithPolicy(input, init = undefined) {
+    const defaultHeaders = Deno.core.ops.op_fetch_default_headers();
+    const limits = Deno.core.ops.op_fetch_limits();
+    const cookiePolicy = Deno.core.ops.op_fetch_cookie_policy();
+    const proxyBypass = Deno.core.ops.op_fetch_has_proxy_bypass();
+    const proxyRequired = Deno.core.ops.op_fetch_proxy_required();
+    const isPlain = limits === null && cookiePolicy === COOKIES_IGNORE && !proxyBypass && !proxyRequired;
+    if (defaultHeaders.length === 0 && isPlain) {
+        return fetch.fetch(input, init);
+    }
+
+    let req;
+    try {
+        req = new request.Request(input, init);
+    } catch (e) {
+        return Promise.reject(e);
+    }
+    applyDefaultHeaders(req, defaultHeaders);
+
+    if (isPlain) {
+        return fetch.fetch(req);
+    }
+
+    // A `Deno.HttpClient` chosen by the script takes precedence over the proxy bypass - unless the proxy is required
+    const client = proxyRequired ? null : init?.client;
+    return guardedFetch(req, limits ?? NO_LIMITS, cookiePolicy, client);
+}
+
+//
+// Proxy bypass
+// See `ProxyConfig::no_proxy`
+//
+
+let directClient = null;
+
+// Requests to hosts on the no-proxy list use a client without the runtime's proxy
+// A `null` client replaces any client the request was created with, so it uses the runtime's own
+function clientFor(url, client) {
+    if (!!client || !Deno.core.ops.op_fetch_proxy_bypass(url)) {
+        return client;
+    }
+
+    directClient ??= httpClient.createHttpClient({});
+    return directClient;
+}
+
+//
+// Cookie handling
+// See `WebOptions::cookies`
+//
+
+const COOKIES_IGNORE = 0;
+const COOKIES_BLOCK = 1;
+const COOKIES_JAR = 2;
+
+function applyCookies(req, cookiePolicy) {
+    if (cookiePolicy === COOKIES_BLOCK) {
+        req.headers.delete("cookie");
+    } else if (cookiePolicy === COOKIES_JAR) {
+        const stored = Deno.core.ops.op_fetch_cookie_header(req.url);
+        if (stored !== null) {
+            const existing = req.headers.get("cookie");
+            req.headers.set("cookie", existing ? `${existing}; ${stored}` : stored);
+        }
+    }
+}
+
+function storeCookies(url, res, cookiePolicy) {
+    if (cookiePolicy !== COOKIES_JAR) return;
+    const setCookies = res.headers.getSetCookie();
+    if (setCookies.length > 0) {
+        Deno.core.ops.op_fetch_store_cookies(url, setCookies);
+    }
+}
+
+// Sends a single request, applying the cookie policy and proxy bypass to it
+async function fetchOnce(req, cookiePolicy, client) {
+    applyCookies(req, cookiePolicy);
+    const res = await fetch.fetch(req, { client: clientFor(req.url, client) });
+    storeCookies(req.url, res, cookiePolicy);
+    return res;
+}
+
+//
+// Limits on redirects, body sizes and timeouts
+// See `WebOptions::fetch_limits`
+//
+
+const REDIRECT_STATUSES = [301, 302, 303, 307, 308];
+
+// The limit used by `deno_fetch` itself, for redirects followed by hand for cookies
+const DEFAULT_MAX_REDIRECTS = 20;
+
+const NO_LIMITS = {
+    maxRedirects: null,
+    maxResponseBytes: null,
+    maxRequestBytes: null,
+    connectTimeout: null,
+    readTimeout: null,
+};
+
+function timeoutError(message) {
+    return new DOMException(message, "TimeoutError");
+}
+
+// Reads a stream in full, failing as soon as it grows past `limit` bytes
+async function readLimited(stream, limit, what) {
+    const reader = stream.getReader();
+    const chunks = [];
+    let length = 0;
+    while (true) {
+        const { done, value } = await reader.read();
+        if (done) break;
+
+        length += value.byteLength;
+        if (limit !== null && length > limit) {
+            await reader.cancel();
+            throw new TypeError(`${what} exceeds the limit of ${limit} bytes`);
+        }
+        chunks.push(value);
+    }
+
+    const bytes = new Uint8Array(length);
+    let offset = 0;
+    for (const chunk of chunks) {
+        bytes.set(chunk, offset);
+        offset += chunk.byteLength;
+    }
+    return bytes;
+}
+
+// Follows redirects by hand, so they can be counted and each hop gets its own cookies
+async function fetchFollowing(req, body, maxRedirects, cookiePolicy, client) {
+    let current = req;
+    let redirects = 0;
+    while (true) {
+        const hop = new request.Request(current, { body, redirect: "manual" });
+        const res = await fetchOnce(hop, cookiePolicy, client);
+        const location = res.headers.get("location");
+        if (!REDIRECT_STATUSES.includes(res.status) || location === null) {
+            return [res, redirects > 0];
+        }
+
+        await res.body?.cancel();
+        if (++redirects > maxRedirects) {
+            throw new TypeError(`fetch exceeded the limit of ${maxRedirects} redirects`);
+        }
+
+        const url = new URL(location, current.url);
+        const nextHeaders = new headers.Headers(current.headers);
+        let method = current.method;
+        if (
+            (res.status === 303 && method !== "GET" && method !== "HEAD") ||
+            ((res.status === 301 || res.status === 302) && method === "POST")
+        ) {
+            method = "GET";
+            body = null;
+            for (const name of ["content-type", "content-length", "content-encoding", "content-language", "content-location"]) {
+                nextHeaders.delete(name);
+            }
+        }
+        if (url.origin !== new URL(current.url).origin) {
+            nextHeaders.delete("authorization");
+        }
+
+        current = new request.Request(url, { method, headers: nextHeaders, signal: current.signal });
+    }
+}
+
+// Wraps the response body to enforce the size limit and read timeout while it is streamed,
+// and removes the cookies the script may not see
+function finishResponse(res, redirected, limits, cookiePolicy) {
+    const maxBytes = limits.maxResponseBytes;
+    const readTimeout = limits.readTimeout;
+    const limitBody = res.body !== null && (maxBytes !== null || readTimeout !== null);
+    const stripCookies = cookiePolicy === COOKIES_BLOCK && res.headers.has("set-cookie");
+    if (!limitBody && !stripCookies && !redirected) {
+        return res;
+    }
+
+    let resHeaders = res.headers;
+    if (stripCookies) {
+        resHeaders = new headers.Headers(res.headers);
+        resHeaders.delete("set-cookie");
+    }
+    if (!limitBody) {
+        return rebuildResponse(res, res.body, resHeaders, redirected);
+    }
+
+    const contentLength = Number(res.headers.get("content-length"));
+    if (maxBytes !== null && contentLength > maxBytes) {
+        res.body.cancel();
+        throw new TypeError(`Response body exceeds the limit of ${maxBytes} bytes`);
+    }
+
+    const reader = res.body.getReader();
+    let received = 0;
+    const body = new ReadableStream({
+        async pull(controller) {
+            let timer;
+            const read = readTimeout === null ? reader.read() : Promise.race([
+                reader.read(),
+                new Promise((_, reject) => {
+                    timer = setTimeout(
+                        () => reject(timeoutError(`Response body stalled for more than ${readTimeout}ms`)),
+                        readTimeout,
+                    );
+                }),
+            ]);
+
+            let chunk;
+            try {
+                chunk = await read;
+            } catch (e) {
+                reader.cancel(e).catch(() => {});
+                controller.error(e);
+                return;
+            } finally {
+                clearTimeout(timer);
+            }
+
+            if (chunk.done) {
+                controller.close();
+                return;
+            }
+
+            received += chunk.value.byteLength;
+            if (maxBytes !== null && received > maxBytes) {
+                const error = new TypeError(`Response body exceeds the limit of ${maxBytes} bytes`);
+                reader.cancel(error).catch(() => {});
+                controller.error(error);
+                return;
+            }
+            controller.enqueue(chunk.value);
+        },
+        cancel(reason) {
+            return reader.cancel(reason);
+        },
+    });
+
+    return rebuildResponse(res, body, resHeaders, redirected);
+}
+
+function rebuildResponse(res, body, resHeaders, redirected) {
+    const rebuilt = new response.Response(body, {
+        status: res.status,
+        statusText: res.statusText,
+        headers: resHeaders,
+    });
+    Object.defineProperties(rebuilt, {
+        url: { value: res.url },
+        redirected: { value: redirected || res.redirected },
+    });
+    return rebuilt;
+}
+
+async function guardedFetch(req, limits, cookiePolicy, client) {
+    // A separate controller, so the connect timeout can abort the request without touching the script's signal
+    const controller = new AbortController();
+    const userSignal = req.signal;
+    if (userSignal.aborted) {
+        throw userSignal.reason;
+    }
+    userSignal.addEventListener("abort", () => controller.abort(userSignal.reason), { once: true });
+    req = new request.Request(req, { signal: controller.signal });
+
+    const followManually = req.redirect === "follow" &&
+        (limits.maxRedirects !== null || cookiePolicy !== COOKIES_IGNORE);
+    let body = null;
+    if (req.body !== null && (limits.maxRequestBytes !== null || followManually)) {
+        body = await readLimited(req.body, limits.maxRequestBytes, "Request body");
+    }
+
+    let timer;
+    if (limits.connectTimeout !== null) {
+        timer = setTimeout(
+            () => controller.abort(timeoutError(`fetch received no response within ${limits.connectTimeout}ms`)),
+            limits.connectTimeout,
+        );
+    }
+
+    try {
+        let res, redirected = false;
+        if (followManually) {
+            const maxRedirects = limits.maxRedirects ?? DEFAULT_MAX_REDIRECTS;
+            [res, redirected] = await fetchFollowing(req, body, maxRedirects, cookiePolicy, client);
+        } else if (body !== null) {
+            res = await fetchOnce(new request.Request(req, { body }), cookiePolicy, client);
+        } else {
+            res = await fetchOnce(req, cookiePolicy, client);
+        }
+        return finishResponse(res, redirected, limits, cookiePolicy);
+    } finally {
+        clearTimeout(timer);
+    }
+}
+
 applyToGlobal({
-    fetch: writeable(fetch.fetch),
+    fetch: writeable(fetchWithPolicy),
     Request: nonEnumerable(request.Request),
     Response: nonEnumerable(response.Response),
     Headers: nonEnumerable(headers.Headers),
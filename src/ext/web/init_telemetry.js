@@ -1,4 +1,23 @@
+import { core } from "ext:core/mod.js";
 import * as telemetry from "ext:deno_telemetry/telemetry.ts";
 import * as util from "ext:deno_telemetry/util.ts";
 
-globalThis.Deno.telemetry = telemetry.telemetry;
\ No newline at end of file
+globalThis.Deno.telemetry = telemetry.telemetry;
+
+// Spans are only recorded when the host provides a span processor - see `WebOptions::span_processor`
+// Console output is not captured, since the host already receives it
+if (core.ops.op_telemetry_enabled()) {
+    telemetry.bootstrap([0, 0]);
+}
+
+// Makes the spans created by later calls children of a span in the host - see `Runtime::set_trace_context`
+// The context is applied globally, and is captured by promises and timers as usual
+const SPAN_KEY = Symbol.for("OpenTelemetry Context Key SPAN");
+const contextManager = new telemetry.telemetry.ContextManager();
+Object.defineProperty(globalThis, '__rustyscript_set_trace_context', { value: (parent) => {
+    let context = contextManager.active().deleteValue(SPAN_KEY);
+    if (parent !== null) {
+        context = context.setValue(SPAN_KEY, { spanContext: () => parent });
+    }
+    core.setAsyncContext(contextManager.with(context, core.getAsyncContext));
+} });
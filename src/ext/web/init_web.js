@@ -99,4 +99,10 @@ applyToGlobal({
   
     structuredClone: writeable(messagePort.structuredClone),
     ImageData: nonEnumerable(imageData.ImageData),
+});
+
+// Lists the marks and measures recorded by scripts - see `Runtime::performance_entries`
+// Uses the module's instance, so it works even if a script replaces `globalThis.performance`
+Object.defineProperty(globalThis, '__rustyscript_performance_entries', {
+    value: () => performance.performance.getEntries().map((entry) => entry.toJSON()),
 });
\ No newline at end of file
@@ -1,26 +1,118 @@
 use super::ExtensionTrait;
-use deno_core::{extension, Extension};
+use deno_core::{extension, op2, Extension, OpState};
 use std::sync::Arc;
 
 mod options;
-pub use options::WebOptions;
+pub use options::{FetchLimits, WebOptions};
+
+mod cookies;
+pub use cookies::{Cookie, CookieJar, CookiePolicy};
+
+mod dns;
+pub use dns::{DnsResolution, DnsResolver, StaticDnsResolver};
+
+mod proxy;
+pub(crate) use proxy::restrict;
+pub use proxy::ProxyConfig;
+
+mod ssrf;
+pub use ssrf::SsrfProtection;
+
+mod performance;
+pub use performance::{PerformanceEntry, PerformanceEntryType};
 
 mod permissions;
+mod telemetry;
 pub(crate) use permissions::PermissionsContainer;
 pub use permissions::{
     AllowlistWebPermissions, DefaultWebPermissions, PermissionDenied, SystemsPermissionKind,
     WebPermissions,
 };
 
+/// Headers added to every fetch, see [`WebOptions::default_headers`]
+struct DefaultHeaders(Vec<(String, String)>);
+
+/// Read on each call, so runtimes started from a snapshot use their own headers
+#[op2]
+#[serde]
+fn op_fetch_default_headers(state: &OpState) -> Vec<(String, String)> {
+    state
+        .try_borrow::<DefaultHeaders>()
+        .map(|headers| headers.0.clone())
+        .unwrap_or_default()
+}
+
+/// The [`FetchLimits`] as seen by `init_fetch.js`, with durations in milliseconds
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsFetchLimits {
+    max_redirects: Option<usize>,
+    max_response_bytes: Option<usize>,
+    max_request_bytes: Option<usize>,
+    connect_timeout: Option<u64>,
+    read_timeout: Option<u64>,
+}
+
+fn millis(duration: std::time::Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
+
+/// Returns null if no limit is set, so `fetch` can skip the extra work
+#[op2]
+#[serde]
+fn op_fetch_limits(state: &OpState) -> Option<JsFetchLimits> {
+    let limits = state.try_borrow::<FetchLimits>()?;
+    if limits.is_empty() {
+        return None;
+    }
+
+    Some(JsFetchLimits {
+        max_redirects: limits.max_redirects,
+        max_response_bytes: limits.max_response_bytes,
+        max_request_bytes: limits.max_request_bytes,
+        connect_timeout: limits.connect_timeout.map(millis),
+        read_timeout: limits.read_timeout.map(millis),
+    })
+}
+
 extension!(
     init_fetch,
     deps = [rustyscript],
+    ops = [
+        op_fetch_default_headers,
+        op_fetch_limits,
+        cookies::op_fetch_cookie_policy,
+        cookies::op_fetch_cookie_header,
+        cookies::op_fetch_store_cookies,
+        proxy::op_fetch_has_proxy_bypass,
+        proxy::op_fetch_proxy_bypass,
+        proxy::op_fetch_proxy_required
+    ],
     esm_entry_point = "ext:init_fetch/init_fetch.js",
     esm = [ dir "src/ext/web", "init_fetch.js" ],
+    options = {
+        default_headers: Vec<(String, String)>,
+        limits: FetchLimits,
+        cookies: CookiePolicy,
+        proxy: Option<ProxyConfig>
+    },
+    state = |state, config| {
+        state.put(DefaultHeaders(config.default_headers));
+        state.put(config.limits);
+        state.put(config.cookies);
+        if let Some(proxy) = config.proxy {
+            state.put(proxy);
+        }
+    },
 );
 impl ExtensionTrait<WebOptions> for init_fetch {
     fn init(options: WebOptions) -> Extension {
-        init_fetch::init_ops_and_esm()
+        init_fetch::init_ops_and_esm(
+            options.default_headers,
+            options.fetch_limits,
+            options.cookies,
+            options.proxy_config,
+        )
     }
 }
 impl ExtensionTrait<WebOptions> for deno_fetch::deno_fetch {
@@ -28,13 +120,19 @@ impl ExtensionTrait<WebOptions> for deno_fetch::deno_fetch {
         let options = deno_fetch::Options {
             user_agent: options.user_agent.clone(),
             root_cert_store_provider: options.root_cert_store_provider.clone(),
-            proxy: options.proxy.clone(),
+            proxy: match &options.proxy_config {
+                Some(proxy) => Some(proxy.to_deno()),
+                None => options.proxy.clone(),
+            },
             request_builder_hook: options.request_builder_hook,
             unsafely_ignore_certificate_errors: options.unsafely_ignore_certificate_errors.clone(),
             client_cert_chain_and_key: options.client_cert_chain_and_key.clone(),
             file_fetch_handler: options.file_fetch_handler.clone(),
             client_builder_hook: options.client_builder_hook,
-            resolver: options.resolver.clone(),
+            resolver: match &options.dns_resolver {
+                Some(hook) => dns::FetchResolver::into_resolver(hook.clone()),
+                None => options.resolver.clone(),
+            },
         };
 
         deno_fetch::deno_fetch::init_ops_and_esm::<PermissionsContainer>(options)
@@ -64,12 +162,22 @@ impl ExtensionTrait<WebOptions> for deno_net::deno_net {
 extension!(
     init_telemetry,
     deps = [rustyscript],
+    ops = [telemetry::op_telemetry_enabled],
     esm_entry_point = "ext:init_telemetry/init_telemetry.js",
     esm = [ dir "src/ext/web", "init_telemetry.js" ],
+    options = {
+        span_processor: Option<Arc<dyn opentelemetry_sdk::trace::SpanProcessor>>
+    },
+    middleware = telemetry::middleware,
+    state = |state, config| {
+        if let Some(processor) = config.span_processor {
+            state.put(telemetry::SpanProcessorContainer(processor));
+        }
+    },
 );
-impl ExtensionTrait<()> for init_telemetry {
-    fn init((): ()) -> Extension {
-        init_telemetry::init_ops_and_esm()
+impl ExtensionTrait<WebOptions> for init_telemetry {
+    fn init(options: WebOptions) -> Extension {
+        init_telemetry::init_ops_and_esm(options.span_processor)
     }
 }
 
@@ -91,7 +199,21 @@ extension!(
 );
 impl ExtensionTrait<WebOptions> for init_web {
     fn init(options: WebOptions) -> Extension {
-        init_web::init_ops_and_esm(options.permissions)
+        let permissions: Arc<dyn WebPermissions> = match options.dns_resolver {
+            Some(resolver) => Arc::new(dns::ResolverPermissions {
+                inner: options.permissions,
+                resolver,
+            }),
+            None => options.permissions,
+        };
+        let permissions: Arc<dyn WebPermissions> = match options.proxy_config {
+            Some(proxy) if !proxy.allow_unproxied => Arc::new(proxy::ProxyPermissions {
+                inner: permissions,
+                proxy,
+            }),
+            _ => permissions,
+        };
+        init_web::init_ops_and_esm(permissions)
     }
 }
 
@@ -118,8 +240,397 @@ pub fn extensions(options: WebOptions, is_snapshot: bool) -> Vec<Extension> {
         deno_fetch::deno_fetch::build(options.clone(), is_snapshot),
         deno_tls::deno_tls::build((), is_snapshot),
         init_web::build(options.clone(), is_snapshot),
-        init_telemetry::build((), is_snapshot),
+        init_telemetry::build(options.clone(), is_snapshot),
         init_net::build(options.clone(), is_snapshot),
         init_fetch::build(options, is_snapshot),
     ]
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Runtime, RuntimeOptions};
+    use std::io::{Read, Write};
+    use std::time::Duration;
+
+    /// Part of a scripted response from [`serve`]
+    enum Step {
+        Send(String),
+        Wait(Duration),
+    }
+
+    /// Serves one scripted response per connection on 127.0.0.1, returning the port
+    /// The thread returns the requests it received, with lowercase headers
+    fn serve(responses: Vec<Vec<Step>>) -> (u16, std::thread::JoinHandle<Vec<String>>) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let mut requests = vec![];
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                stream
+                    .set_read_timeout(Some(Duration::from_secs(5)))
+                    .unwrap();
+                requests.push(read_request(&mut stream));
+
+                // The client may have given up on the response already
+                for step in response {
+                    match step {
+                        Step::Send(data) => {
+                            if stream.write_all(data.as_bytes()).is_err() {
+                                break;
+                            }
+                        }
+                        Step::Wait(duration) => std::thread::sleep(duration),
+                    }
+                }
+            }
+            requests
+        });
+        (port, server)
+    }
+
+    /// Reads the head and body of a request
+    fn read_request(stream: &mut std::net::TcpStream) -> String {
+        let mut request = vec![];
+        let mut buf = [0; 4096];
+        let head_len = loop {
+            let len = stream.read(&mut buf).unwrap();
+            assert!(len > 0, "Connection closed before the end of the request");
+            request.extend_from_slice(&buf[..len]);
+            if let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                break end + 4;
+            }
+        };
+
+        let head = String::from_utf8_lossy(&request[..head_len]).to_lowercase();
+        let body_len = head
+            .lines()
+            .find_map(|line| line.strip_prefix("content-length:"))
+            .map_or(0, |len| len.trim().parse::<usize>().unwrap());
+        while request.len() < head_len + body_len {
+            let len = stream.read(&mut buf).unwrap();
+            assert!(len > 0, "Connection closed before the end of the body");
+            request.extend_from_slice(&buf[..len]);
+        }
+
+        format!("{head}{}", String::from_utf8_lossy(&request[head_len..]))
+    }
+
+    /// The values of a header in a request from [`serve`]
+    fn header_values(request: &str, name: &str) -> Vec<String> {
+        let prefix = format!("{name}:");
+        request
+            .lines()
+            .filter_map(|line| line.strip_prefix(&prefix))
+            .flat_map(|value| value.split(','))
+            .map(|value| value.trim().to_string())
+            .collect()
+    }
+
+    fn ok(body: &str) -> Vec<Step> {
+        vec![Step::Send(format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        ))]
+    }
+
+    fn redirect(status: u16, location: &str) -> Vec<Step> {
+        vec![Step::Send(format!(
+            "HTTP/1.1 {status} Redirect\r\nLocation: {location}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        ))]
+    }
+
+    fn runtime(configure: impl FnOnce(&mut WebOptions)) -> Runtime {
+        let mut options = RuntimeOptions::default();
+        configure(&mut options.extension_options.web);
+        Runtime::new(options).unwrap()
+    }
+
+    /// Awaits a javascript expression, returning its result, or the name and message of the error it threw
+    fn fetch_result(runtime: &mut Runtime, expr: &str) -> String {
+        runtime
+            .eval(format!(
+                "(async () => {{
+                    try {{ return await {expr}; }}
+                    catch (e) {{ return `${{e.name}}: ${{e.message}}`; }}
+                }})()"
+            ))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_default_headers() {
+        let (port, server) = serve(vec![ok("a"), ok("b")]);
+        let mut runtime = runtime(|web| {
+            web.add_default_header("X-Tenant", "acme");
+            web.add_default_header("X-Route", "one");
+            web.add_default_header("X-Route", "two");
+        });
+
+        // Headers set by the script with the same name are replaced, and others are kept
+        let body = fetch_result(
+            &mut runtime,
+            &format!(
+                "fetch('http://127.0.0.1:{port}/', {{
+                    headers: {{ 'X-Tenant': 'evil', 'x-route': 'evil', 'X-Script': 'kept' }},
+                }}).then((r) => r.text())"
+            ),
+        );
+        assert_eq!(body, "a");
+
+        // Including when the script passes a `Request`
+        let body = fetch_result(
+            &mut runtime,
+            &format!(
+                "fetch(new Request('http://127.0.0.1:{port}/', {{ headers: {{ 'x-tenant': 'evil' }} }}))
+                    .then((r) => r.text())"
+            ),
+        );
+        assert_eq!(body, "b");
+
+        let requests = server.join().unwrap();
+        for request in &requests {
+            assert_eq!(header_values(request, "x-tenant"), vec!["acme"]);
+            assert_eq!(header_values(request, "x-route"), vec!["one", "two"]);
+            assert!(!request.contains("evil"), "{request}");
+        }
+        assert_eq!(header_values(&requests[0], "x-script"), vec!["kept"]);
+    }
+
+    #[test]
+    fn test_fetch_redirects() {
+        let (port, server) = serve(vec![
+            // A POST becomes a GET after a 303, and the body is dropped
+            redirect(303, "/next"),
+            redirect(302, "/final"),
+            ok("done"),
+            // One more redirect than the limit
+            redirect(302, "/one"),
+            redirect(302, "/two"),
+            redirect(302, "/three"),
+            // Manual redirects are returned as-is
+            redirect(302, "/elsewhere"),
+        ]);
+        let mut runtime = runtime(|web| {
+            web.fetch_limits.max_redirects = Some(2);
+        });
+
+        let result = fetch_result(
+            &mut runtime,
+            &format!(
+                "fetch('http://127.0.0.1:{port}/start', {{ method: 'POST', body: 'payload' }})
+                    .then(async (r) => `${{r.redirected}} ${{new URL(r.url).pathname}} ${{await r.text()}}`)"
+            ),
+        );
+        assert_eq!(result, "true /final done");
+
+        let result = fetch_result(
+            &mut runtime,
+            &format!("fetch('http://127.0.0.1:{port}/start').then((r) => r.text())"),
+        );
+        assert_eq!(result, "TypeError: fetch exceeded the limit of 2 redirects");
+
+        let result = fetch_result(
+            &mut runtime,
+            &format!(
+                "fetch('http://127.0.0.1:{port}/start', {{ redirect: 'manual' }})
+                    .then((r) => `${{r.status}} ${{r.headers.get('location')}}`)"
+            ),
+        );
+        assert_eq!(result, "302 /elsewhere");
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with("post /start "));
+        assert!(requests[0].ends_with("payload"));
+        assert!(requests[1].starts_with("get /next "));
+        assert!(header_values(&requests[1], "content-type").is_empty());
+        assert!(requests[1].ends_with("\r\n\r\n"));
+        assert!(requests[2].starts_with("get /final "));
+        assert!(requests[5].starts_with("get /two "));
+    }
+
+    #[test]
+    fn test_fetch_redirect_origin() {
+        let (target, target_server) = serve(vec![ok("done")]);
+        let (port, server) = serve(vec![redirect(
+            307,
+            &format!("http://localhost:{target}/final"),
+        )]);
+        let mut runtime = runtime(|web| {
+            web.fetch_limits.max_redirects = Some(5);
+        });
+
+        let result = fetch_result(
+            &mut runtime,
+            &format!(
+                "fetch('http://127.0.0.1:{port}/', {{
+                    headers: {{ Authorization: 'Bearer secret', 'X-Kept': 'yes' }},
+                }}).then((r) => r.text())"
+            ),
+        );
+        assert_eq!(result, "done");
+
+        // The authorization header is not sent to the new origin
+        let requests = server.join().unwrap();
+        assert_eq!(
+            header_values(&requests[0], "authorization"),
+            vec!["bearer secret"]
+        );
+        let requests = target_server.join().unwrap();
+        assert!(header_values(&requests[0], "authorization").is_empty());
+        assert_eq!(header_values(&requests[0], "x-kept"), vec!["yes"]);
+    }
+
+    #[test]
+    fn test_fetch_body_limits() {
+        let (port, server) = serve(vec![
+            ok("12345678"),
+            ok("123456789"),
+            vec![Step::Send(
+                "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n5\r\n12345\r\n5\r\n67890\r\n0\r\n\r\n"
+                    .to_string(),
+            )],
+        ]);
+        let mut runtime = runtime(|web| {
+            web.fetch_limits.max_request_bytes = Some(8);
+            web.fetch_limits.max_response_bytes = Some(8);
+        });
+
+        // Oversized request bodies are refused before connecting, including streamed ones
+        let result = fetch_result(
+            &mut runtime,
+            &format!("fetch('http://127.0.0.1:{port}/', {{ method: 'POST', body: '123456789' }})"),
+        );
+        assert_eq!(
+            result,
+            "TypeError: Request body exceeds the limit of 8 bytes"
+        );
+        let result = fetch_result(
+            &mut runtime,
+            &format!(
+                "fetch('http://127.0.0.1:{port}/', {{
+                    method: 'POST',
+                    body: new Blob(['12345', '6789']).stream(),
+                    duplex: 'half',
+                }})"
+            ),
+        );
+        assert_eq!(
+            result,
+            "TypeError: Request body exceeds the limit of 8 bytes"
+        );
+
+        // Bodies at the limit are allowed
+        let result = fetch_result(
+            &mut runtime,
+            &format!(
+                "fetch('http://127.0.0.1:{port}/', {{ method: 'POST', body: 'request!' }})
+                    .then((r) => r.text())"
+            ),
+        );
+        assert_eq!(result, "12345678");
+
+        // Oversized responses fail up front if their length is known, or while streamed if not
+        let result = fetch_result(
+            &mut runtime,
+            &format!("fetch('http://127.0.0.1:{port}/').then((r) => r.text())"),
+        );
+        assert_eq!(
+            result,
+            "TypeError: Response body exceeds the limit of 8 bytes"
+        );
+        let result = fetch_result(
+            &mut runtime,
+            &format!("fetch('http://127.0.0.1:{port}/').then((r) => r.text())"),
+        );
+        assert_eq!(
+            result,
+            "TypeError: Response body exceeds the limit of 8 bytes"
+        );
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].ends_with("request!"));
+    }
+
+    #[test]
+    fn test_fetch_connect_timeout() {
+        let (port, server) = serve(vec![
+            // The timeout stops applying once the headers arrive
+            vec![
+                Step::Send(
+                    "HTTP/1.1 200 OK\r\nContent-Length: 4\r\nConnection: close\r\n\r\nsl"
+                        .to_string(),
+                ),
+                Step::Wait(Duration::from_millis(600)),
+                Step::Send("ow".to_string()),
+            ],
+            vec![Step::Wait(Duration::from_secs(2))],
+        ]);
+        let mut runtime = runtime(|web| {
+            web.fetch_limits.connect_timeout = Some(Duration::from_millis(300));
+        });
+
+        let result = fetch_result(
+            &mut runtime,
+            &format!("fetch('http://127.0.0.1:{port}/').then((r) => r.text())"),
+        );
+        assert_eq!(result, "slow");
+
+        let result = fetch_result(
+            &mut runtime,
+            &format!("fetch('http://127.0.0.1:{port}/').then((r) => r.text())"),
+        );
+        assert_eq!(
+            result,
+            "TimeoutError: fetch received no response within 300ms"
+        );
+
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_fetch_read_timeout() {
+        let (port, server) = serve(vec![
+            // Each chunk gets its own timeout
+            vec![
+                Step::Send(
+                    "HTTP/1.1 200 OK\r\nContent-Length: 6\r\nConnection: close\r\n\r\nab"
+                        .to_string(),
+                ),
+                Step::Wait(Duration::from_millis(100)),
+                Step::Send("cd".to_string()),
+                Step::Wait(Duration::from_millis(100)),
+                Step::Send("ef".to_string()),
+            ],
+            vec![
+                Step::Send(
+                    "HTTP/1.1 200 OK\r\nContent-Length: 4\r\nConnection: close\r\n\r\nab"
+                        .to_string(),
+                ),
+                Step::Wait(Duration::from_secs(2)),
+                Step::Send("cd".to_string()),
+            ],
+        ]);
+        let mut runtime = runtime(|web| {
+            web.fetch_limits.read_timeout = Some(Duration::from_millis(500));
+        });
+
+        let result = fetch_result(
+            &mut runtime,
+            &format!("fetch('http://127.0.0.1:{port}/').then((r) => r.text())"),
+        );
+        assert_eq!(result, "abcdef");
+
+        let result = fetch_result(
+            &mut runtime,
+            &format!("fetch('http://127.0.0.1:{port}/').then((r) => r.text())"),
+        );
+        assert_eq!(
+            result,
+            "TimeoutError: Response body stalled for more than 500ms"
+        );
+
+        server.join().unwrap();
+    }
+}
@@ -1,6 +1,10 @@
-use super::{DefaultWebPermissions, WebPermissions};
+use super::{
+    CookiePolicy, DefaultWebPermissions, DnsResolver, ProxyConfig, SsrfProtection, WebPermissions,
+};
+use crate::Error;
 use deno_core::error::AnyError;
 use deno_fetch::dns::Resolver;
+use deno_tls::rustls::RootCertStore;
 use hyper_util::client::legacy::Builder;
 use std::sync::Arc;
 
@@ -13,12 +17,34 @@ pub struct WebOptions {
     /// User agent to use for fetch
     pub user_agent: String,
 
+    /// Headers added to every request made with `fetch`, such as an internal auth header or tenant ID
+    ///
+    /// They are applied after the headers set by the script, replacing any with the same name,
+    /// so scripts cannot override them
+    pub default_headers: Vec<(String, String)>,
+
+    /// Redirect, size and timeout limits applied to every request made with `fetch`
+    pub fetch_limits: FetchLimits,
+
+    /// How `fetch` stores and sends cookies - by default they are not stored
+    ///
+    /// Use [`CookiePolicy::Jar`] to keep cookies between requests, and read them back from the host
+    pub cookies: CookiePolicy,
+
     /// Root certificate store for TLS connections for fetches and network OPs
     pub root_cert_store_provider: Option<std::sync::Arc<dyn deno_tls::RootCertStoreProvider>>,
 
     /// Proxy for fetch
+    ///
+    /// Prefer [`WebOptions::proxy_config`], which replaces this when set
     pub proxy: Option<deno_tls::Proxy>,
 
+    /// Proxy settings for `fetch`, with credentials and a no-proxy list
+    ///
+    /// `WebSocket` and `Deno.connect` cannot use a proxy, and are refused unless the host
+    /// is on the no-proxy list - see [`ProxyConfig`]
+    pub proxy_config: Option<ProxyConfig>,
+
     /// Request builder hook for fetch
     #[allow(clippy::type_complexity)]
     pub request_builder_hook:
@@ -30,6 +56,8 @@ pub struct WebOptions {
     pub unsafely_ignore_certificate_errors: Option<Vec<String>>,
 
     /// Client certificate and key for fetch
+    ///
+    /// See [`WebOptions::set_client_identity_pem`] to load them from PEM
     pub client_cert_chain_and_key: deno_tls::TlsKeys,
 
     /// File fetch handler for fetch
@@ -49,8 +77,21 @@ pub struct WebOptions {
     /// Resolver for DNS resolution
     pub resolver: Resolver,
 
+    /// Hook mapping hostnames to addresses, or rejecting them, before `fetch`, `WebSocket`
+    /// or the network OPs connect
+    ///
+    /// Useful for pinning internal service names, blocking private ranges, or testing against fake endpoints
+    /// When set, it replaces [`WebOptions::resolver`] for `fetch` - see [`crate::StaticDnsResolver`] for a table-based hook
+    pub dns_resolver: Option<Arc<dyn DnsResolver>>,
+
     /// OpenTelemetry configuration for the `deno_telemetry` extension
     pub telemetry_config: deno_telemetry::OtelConfig,
+
+    /// Span processor that receives the spans recorded by scripts, including the ones created by `fetch`
+    ///
+    /// When set, span recording is enabled and spans are sent here instead of the OTLP exporter configured through
+    /// the `OTEL_*` environment variables - use [`crate::Runtime::set_trace_context`] to make them children of a host span
+    pub span_processor: Option<Arc<dyn opentelemetry_sdk::trace::SpanProcessor>>,
 }
 
 impl Default for WebOptions {
@@ -58,8 +99,12 @@ impl Default for WebOptions {
         Self {
             base_url: None,
             user_agent: String::new(),
+            default_headers: Vec::new(),
+            fetch_limits: FetchLimits::default(),
+            cookies: CookiePolicy::default(),
             root_cert_store_provider: None,
             proxy: None,
+            proxy_config: None,
             request_builder_hook: None,
             unsafely_ignore_certificate_errors: None,
             client_cert_chain_and_key: deno_tls::TlsKeys::Null,
@@ -68,7 +113,9 @@ impl Default for WebOptions {
             blob_store: Arc::new(deno_web::BlobStore::default()),
             client_builder_hook: None,
             resolver: Resolver::default(),
+            dns_resolver: None,
             telemetry_config: deno_telemetry::OtelConfig::default(),
+            span_processor: None,
         }
     }
 }
@@ -83,4 +130,119 @@ impl WebOptions {
             self.unsafely_ignore_certificate_errors = Some(vec![domain_or_ip.to_string()]);
         }
     }
+
+    /// Set the client certificate chain and private key presented by `fetch` for mutual TLS
+    ///
+    /// Both are PEM encoded - the key may be PKCS #8, PKCS #1 (RSA), or SEC1 (EC)
+    /// Connections opened with `Deno.connectTls` take their own certificate instead
+    ///
+    /// # Errors
+    /// Will return an error if no certificate or key can be decoded
+    pub fn set_client_identity_pem(
+        &mut self,
+        cert_chain: &[u8],
+        private_key: &[u8],
+    ) -> Result<(), Error> {
+        let certs = deno_tls::load_certs(&mut &*cert_chain)
+            .map_err(|e| Error::Runtime(format!("Invalid client certificate: {e}")))?;
+        let key = deno_tls::load_private_keys(private_key)
+            .map_err(|e| Error::Runtime(format!("Invalid client key: {e}")))?
+            .remove(0);
+
+        self.client_cert_chain_and_key = deno_tls::TlsKeys::Static(deno_tls::TlsKey(certs, key));
+        Ok(())
+    }
+
+    /// Set the root certificates trusted by `fetch`, `WebSocket`, and the network OPs, from PEM
+    ///
+    /// If `include_defaults` is true, the certificates are trusted in addition to the built-in
+    /// Mozilla roots - otherwise only these certificates are trusted, as with a private CA
+    ///
+    /// # Errors
+    /// Will return an error if no certificate can be decoded, or if one is invalid
+    pub fn set_root_certificates_pem(
+        &mut self,
+        pem: &[u8],
+        include_defaults: bool,
+    ) -> Result<(), Error> {
+        let mut store = if include_defaults {
+            deno_tls::create_default_root_cert_store()
+        } else {
+            RootCertStore::empty()
+        };
+
+        let certs = deno_tls::load_certs(&mut &*pem)
+            .map_err(|e| Error::Runtime(format!("Invalid root certificate: {e}")))?;
+        for cert in certs {
+            store
+                .add(cert)
+                .map_err(|e| Error::Runtime(format!("Invalid root certificate: {e}")))?;
+        }
+
+        self.root_cert_store_provider = Some(Arc::new(StaticRootCertStore(store)));
+        Ok(())
+    }
+
+    /// Block connections to private, loopback, link-local and cloud metadata addresses
+    ///
+    /// Wraps the current [`WebOptions::dns_resolver`], if any, with a default [`SsrfProtection`]
+    /// Set `dns_resolver` to a configured [`SsrfProtection`] directly to allow specific internal hosts
+    pub fn enable_ssrf_protection(&mut self) {
+        let protection = match self.dns_resolver.take() {
+            Some(inner) => SsrfProtection::wrap(inner),
+            None => SsrfProtection::new(),
+        };
+        self.dns_resolver = Some(Arc::new(protection));
+    }
+
+    /// Add a header to every request made with `fetch`, see [`WebOptions::default_headers`]
+    pub fn add_default_header(&mut self, name: impl ToString, value: impl ToString) {
+        self.default_headers
+            .push((name.to_string(), value.to_string()));
+    }
+}
+
+/// Limits applied to every request made with `fetch`, see [`WebOptions::fetch_limits`]
+///
+/// Exceeding one rejects the `fetch` call, or errors the response body stream, with a `TypeError`
+/// describing the limit - timeouts abort the request with a `TimeoutError` `DOMException` instead
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FetchLimits {
+    /// Maximum number of redirects followed before the request fails
+    ///
+    /// Does not apply to requests made with `redirect: "manual"` or `redirect: "error"`
+    pub max_redirects: Option<usize>,
+
+    /// Maximum size of a response body, enforced while it is streamed
+    pub max_response_bytes: Option<usize>,
+
+    /// Maximum size of a request body
+    ///
+    /// Streamed request bodies are read in full before being sent when this is set
+    pub max_request_bytes: Option<usize>,
+
+    /// Maximum time to wait for the response headers, including connecting and any redirects
+    pub connect_timeout: Option<std::time::Duration>,
+
+    /// Maximum time to wait for each chunk of the response body
+    pub read_timeout: Option<std::time::Duration>,
+}
+impl FetchLimits {
+    /// Returns true if no limit is set
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.max_redirects.is_none()
+            && self.max_response_bytes.is_none()
+            && self.max_request_bytes.is_none()
+            && self.connect_timeout.is_none()
+            && self.read_timeout.is_none()
+    }
+}
+
+/// A root certificate store built ahead of time, see [`WebOptions::set_root_certificates_pem`]
+struct StaticRootCertStore(RootCertStore);
+impl deno_tls::RootCertStoreProvider for StaticRootCertStore {
+    fn get_or_try_init(&self) -> Result<&RootCertStore, AnyError> {
+        Ok(&self.0)
+    }
 }
@@ -0,0 +1,35 @@
+use deno_core::serde_json;
+
+/// The kind of a [`PerformanceEntry`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PerformanceEntryType {
+    /// Created by `performance.mark`
+    Mark,
+
+    /// Created by `performance.measure`
+    Measure,
+}
+
+/// A `performance.mark` or `performance.measure` entry recorded by a script
+///
+/// Obtained with [`crate::Runtime::performance_entries`]
+#[derive(Clone, Debug, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PerformanceEntry {
+    /// The name given to the entry by the script
+    pub name: String,
+
+    /// Whether the entry is a mark or a measure
+    pub entry_type: PerformanceEntryType,
+
+    /// Milliseconds since the runtime started
+    pub start_time: f64,
+
+    /// Length of a measure in milliseconds - always 0 for marks
+    pub duration: f64,
+
+    /// The `detail` option given to the entry, if any
+    #[serde(default)]
+    pub detail: Option<serde_json::Value>,
+}
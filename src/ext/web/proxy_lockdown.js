@@ -0,0 +1,16 @@
+// Removes the ways scripts could create, or use, a `Deno.HttpClient` that connects directly instead of through the proxy
+// Used when `ProxyConfig::allow_unproxied` is false - see `restrict` in proxy.rs
+//
+// `fetch` keeps working, since it reaches these ops through its own imports
+((globalThis) => {
+    const deno = globalThis.Deno;
+    const ops = deno?.core?.ops ?? {};
+
+    delete deno?.createHttpClient;
+    delete ops.op_fetch_custom_client;
+    delete ops.op_fetch;
+
+    if (deno?.createHttpClient || ops.op_fetch_custom_client || ops.op_fetch) {
+        throw new Error("Could not restrict HTTP clients to the proxy");
+    }
+})(globalThis);
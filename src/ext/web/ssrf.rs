@@ -0,0 +1,218 @@
+use super::{DnsResolution, DnsResolver};
+use std::{
+    collections::HashSet,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    sync::Arc,
+};
+
+/// A [`DnsResolver`] blocking connections to private, loopback, link-local and cloud metadata addresses
+///
+/// Enable it with [`super::WebOptions::enable_ssrf_protection`]
+///
+/// `fetch` checks the addresses it actually connects to, after DNS resolution, on every connection - so DNS rebinding
+/// and redirects to internal hosts are refused too
+///
+/// `WebSocket` and the network OPs resolve hostnames on their own when they connect, so the addresses cannot be checked
+/// at that point, and rebinding could not be prevented - they can only connect to IP addresses that are not blocked,
+/// and to hosts allowed with [`SsrfProtection::allow_host`]
+#[derive(Debug, Clone, Default)]
+pub struct SsrfProtection {
+    inner: Option<Arc<dyn DnsResolver>>,
+    allowed_hosts: HashSet<String>,
+    allowed_addrs: HashSet<IpAddr>,
+}
+impl SsrfProtection {
+    /// Create a new preset, using the system resolver
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new preset on top of another resolver
+    ///
+    /// Addresses pinned by `inner` are checked like any other
+    #[must_use]
+    pub fn wrap(inner: Arc<dyn DnsResolver>) -> Self {
+        Self {
+            inner: Some(inner),
+            ..Self::default()
+        }
+    }
+
+    /// Allow connections to `host` even if it resolves to a blocked address
+    #[must_use]
+    pub fn allow_host(mut self, host: impl ToString) -> Self {
+        self.allowed_hosts
+            .insert(host.to_string().to_ascii_lowercase());
+        self
+    }
+
+    /// Allow connections to a specific blocked address
+    #[must_use]
+    pub fn allow_addr(mut self, addr: IpAddr) -> Self {
+        self.allowed_addrs.insert(addr);
+        self
+    }
+
+    /// Returns true if the address is not reachable from the public internet
+    ///
+    /// Covers RFC 1918, loopback, link-local (including `169.254.169.254`), carrier-grade NAT,
+    /// unspecified, broadcast, multicast and documentation ranges, IPv6 unique-local addresses,
+    /// and IPv4 addresses embedded in IPv6 ones
+    #[must_use]
+    pub fn is_blocked_addr(addr: &IpAddr) -> bool {
+        match addr {
+            IpAddr::V4(v4) => is_blocked_v4(v4),
+            IpAddr::V6(v6) => is_blocked_v6(v6),
+        }
+    }
+}
+
+fn is_blocked_v4(addr: &Ipv4Addr) -> bool {
+    let [a, b, ..] = addr.octets();
+    addr.is_private()
+        || addr.is_loopback()
+        || addr.is_link_local()
+        || addr.is_unspecified()
+        || addr.is_broadcast()
+        || addr.is_multicast()
+        || addr.is_documentation()
+        || a == 0
+        || (a == 100 && (64..128).contains(&b)) // Carrier-grade NAT
+        || (a == 192 && b == 0 && addr.octets()[2] == 0) // IETF protocol assignments
+        || a >= 240 // Reserved
+}
+
+fn is_blocked_v6(addr: &Ipv6Addr) -> bool {
+    if let Some(v4) = addr.to_ipv4_mapped() {
+        return is_blocked_v4(&v4);
+    }
+
+    let first = addr.segments()[0];
+    addr.is_loopback()
+        || addr.is_unspecified()
+        || addr.is_multicast()
+        || (first & 0xfe00) == 0xfc00 // Unique local, including fd00:ec2::254
+        || (first & 0xffc0) == 0xfe80 // Link-local
+        || (first == 0x2001 && addr.segments()[1] == 0x0db8) // Documentation
+        || (first == 0x0064 && addr.segments()[1] == 0xff9b) // NAT64
+        || (first == 0 && addr.segments()[1..6] == [0; 5]) // IPv4-compatible
+}
+
+impl DnsResolver for SsrfProtection {
+    fn resolve(&self, host: &str) -> DnsResolution {
+        match &self.inner {
+            Some(inner) => inner.resolve(host),
+            None => DnsResolution::System,
+        }
+    }
+
+    fn check_addrs(&self, host: &str, addrs: &[IpAddr]) -> Result<(), String> {
+        if let Some(inner) = &self.inner {
+            inner.check_addrs(host, addrs)?;
+        }
+
+        if self.allowed_hosts.contains(&host.to_ascii_lowercase()) {
+            return Ok(());
+        }
+
+        match addrs
+            .iter()
+            .find(|addr| !self.allowed_addrs.contains(addr) && Self::is_blocked_addr(addr))
+        {
+            Some(addr) => Err(format!("{addr} is a private or reserved address")),
+            None => Ok(()),
+        }
+    }
+
+    fn check_unpinned(&self, host: &str) -> Result<(), String> {
+        if let Some(inner) = &self.inner {
+            inner.check_unpinned(host)?;
+        }
+
+        if self.allowed_hosts.contains(&host.to_ascii_lowercase()) {
+            Ok(())
+        } else {
+            Err(format!(
+                "{host} would be resolved again when connecting, so its addresses cannot be checked"
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::StaticDnsResolver;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_blocked_addrs() {
+        for blocked in [
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "127.0.0.1",
+            "169.254.169.254",
+            "100.100.100.200",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "fd00:ec2::254",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+        ] {
+            assert!(SsrfProtection::is_blocked_addr(&ip(blocked)), "{blocked}");
+        }
+
+        for allowed in ["8.8.8.8", "1.1.1.1", "2606:4700:4700::1111"] {
+            assert!(!SsrfProtection::is_blocked_addr(&ip(allowed)), "{allowed}");
+        }
+    }
+
+    #[test]
+    fn test_check_addrs() {
+        let protection = SsrfProtection::new();
+        assert!(protection
+            .check_addrs("example.com", &[ip("93.184.215.14")])
+            .is_ok());
+        assert!(protection
+            .check_addrs("rebind.example", &[ip("93.184.215.14"), ip("10.0.0.1")])
+            .is_err());
+
+        let protection = SsrfProtection::new()
+            .allow_host("internal.service")
+            .allow_addr(ip("10.0.0.2"));
+        assert!(protection
+            .check_addrs("internal.service", &[ip("10.0.0.1")])
+            .is_ok());
+        assert!(protection
+            .check_addrs("other.service", &[ip("10.0.0.2")])
+            .is_ok());
+        assert!(protection
+            .check_addrs("other.service", &[ip("10.0.0.1")])
+            .is_err());
+    }
+
+    #[test]
+    fn test_wrapped_pins_are_checked() {
+        let inner = StaticDnsResolver::new().pin("sneaky.example", [ip("169.254.169.254")]);
+        let protection = SsrfProtection::wrap(Arc::new(inner));
+
+        let DnsResolution::Pinned(addrs) = protection.resolve("sneaky.example") else {
+            panic!("Expected the pinned address");
+        };
+        assert!(protection.check_addrs("sneaky.example", &addrs).is_err());
+    }
+
+    #[test]
+    fn test_unpinned_hosts() {
+        let protection = SsrfProtection::new().allow_host("internal.service");
+        assert!(protection.check_unpinned("internal.service").is_ok());
+        assert!(protection.check_unpinned("example.com").is_err());
+    }
+}
@@ -0,0 +1,318 @@
+//! Sends the spans recorded by `deno_telemetry` to a span processor provided by the host
+//!
+//! `deno_telemetry` only exports to a process-wide OTLP endpoint configured through environment variables,
+//! so its span ops are replaced with these, which forward to the [`WebOptions::span_processor`] of each runtime
+//!
+//! [`WebOptions::span_processor`]: super::WebOptions::span_processor
+use deno_core::{anyhow::anyhow, op2, v8, OpDecl, OpState};
+use opentelemetry::{
+    trace::{SpanContext, SpanId, SpanKind, Status, TraceFlags, TraceId, TraceState},
+    InstrumentationScope as Scope, KeyValue, StringValue, Value,
+};
+use opentelemetry_sdk::{
+    export::trace::SpanData,
+    trace::{SpanEvents, SpanLinks, SpanProcessor},
+};
+use std::{borrow::Cow, sync::Arc, time::SystemTime};
+
+/// The host's span processor, stored in the op state
+#[derive(Clone)]
+pub(crate) struct SpanProcessorContainer(pub Arc<dyn SpanProcessor>);
+
+/// Replaces the span ops of `deno_telemetry` - see the module docs
+pub fn middleware(op: OpDecl) -> OpDecl {
+    match op.name {
+        "op_otel_instrumentation_scope_create_and_enter" => {
+            op.with_implementation_from(&op_scope_create_and_enter())
+        }
+        "op_otel_instrumentation_scope_enter" => op.with_implementation_from(&op_scope_enter()),
+        "op_otel_instrumentation_scope_enter_builtin" => {
+            op.with_implementation_from(&op_scope_enter_builtin())
+        }
+        "op_otel_span_start" => op.with_implementation_from(&op_span_start()),
+        "op_otel_span_continue" => op.with_implementation_from(&op_span_continue()),
+        "op_otel_span_attribute" => op.with_implementation_from(&op_span_attribute()),
+        "op_otel_span_attribute2" => op.with_implementation_from(&op_span_attribute2()),
+        "op_otel_span_attribute3" => op.with_implementation_from(&op_span_attribute3()),
+        "op_otel_span_set_dropped" => op.with_implementation_from(&op_span_set_dropped()),
+        "op_otel_span_flush" => op.with_implementation_from(&op_span_flush()),
+        _ => op,
+    }
+}
+
+/// Returns true if the runtime was given a span processor, in which case span recording is enabled
+#[op2(fast)]
+pub fn op_telemetry_enabled(state: &mut OpState) -> bool {
+    state.has::<SpanProcessorContainer>()
+}
+
+#[derive(Debug, Clone)]
+struct InstrumentationScope(Scope);
+impl deno_core::GarbageCollected for InstrumentationScope {}
+
+/// The span being assembled by the current series of ops
+struct TemporarySpan(SpanData);
+
+fn builtin_scope() -> Scope {
+    Scope::builder("rustyscript")
+        .with_version(env!("CARGO_PKG_VERSION"))
+        .build()
+}
+
+fn to_string(scope: &mut v8::HandleScope<'_>, value: v8::Local<'_, v8::Value>) -> Option<String> {
+    let string = value.try_cast::<v8::String>().ok()?;
+    Some(string.to_rust_string_lossy(scope))
+}
+
+fn to_bytes<const N: usize>(
+    scope: &mut v8::HandleScope<'_>,
+    value: v8::Local<'_, v8::Value>,
+) -> Option<[u8; N]> {
+    if let Some(hex) = to_string(scope, value) {
+        let mut bytes = [0; N];
+        if hex.len() != N * 2 {
+            return None;
+        }
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+        }
+        Some(bytes)
+    } else {
+        let array = value.try_cast::<v8::Uint8Array>().ok()?;
+        if array.byte_length() != N {
+            return None;
+        }
+        let mut bytes = [0; N];
+        array.copy_contents(&mut bytes);
+        Some(bytes)
+    }
+}
+
+fn parse_trace_id(scope: &mut v8::HandleScope<'_>, value: v8::Local<'_, v8::Value>) -> TraceId {
+    to_bytes(scope, value).map_or(TraceId::INVALID, TraceId::from_bytes)
+}
+
+fn parse_span_id(scope: &mut v8::HandleScope<'_>, value: v8::Local<'_, v8::Value>) -> SpanId {
+    to_bytes(scope, value).map_or(SpanId::INVALID, SpanId::from_bytes)
+}
+
+fn push_attribute(
+    scope: &mut v8::HandleScope<'_>,
+    span: &mut SpanData,
+    key: v8::Local<'_, v8::Value>,
+    value: v8::Local<'_, v8::Value>,
+) {
+    let value = if let Ok(string) = value.try_cast::<v8::String>() {
+        Some(Value::String(StringValue::from(
+            string.to_rust_string_lossy(scope),
+        )))
+    } else if let Ok(number) = value.try_cast::<v8::Number>() {
+        Some(Value::F64(number.value()))
+    } else if let Ok(boolean) = value.try_cast::<v8::Boolean>() {
+        Some(Value::Bool(boolean.is_true()))
+    } else if let Ok(bigint) = value.try_cast::<v8::BigInt>() {
+        Some(Value::I64(bigint.i64_value().0))
+    } else {
+        None
+    };
+
+    match (to_string(scope, key), value) {
+        (Some(key), Some(value)) => span.attributes.push(KeyValue::new(key, value)),
+        _ => span.dropped_attributes_count += 1,
+    }
+}
+
+fn seconds_to_time(seconds: f64) -> Result<SystemTime, deno_core::anyhow::Error> {
+    std::time::Duration::try_from_secs_f64(seconds)
+        .ok()
+        .and_then(|duration| SystemTime::UNIX_EPOCH.checked_add(duration))
+        .ok_or_else(|| anyhow!("invalid span time"))
+}
+
+/// Sends a finished span to the host
+fn submit(state: &mut OpState, span: TemporarySpan) {
+    if let Some(SpanProcessorContainer(processor)) = state.try_borrow::<SpanProcessorContainer>() {
+        processor.on_end(span.0);
+    }
+}
+
+#[op2]
+#[cppgc]
+fn op_scope_create_and_enter(
+    state: &mut OpState,
+    #[string] name: String,
+    #[string] version: Option<String>,
+    #[string] schema_url: Option<String>,
+) -> InstrumentationScope {
+    let mut builder = Scope::builder(name);
+    if let Some(version) = version {
+        builder = builder.with_version(version);
+    }
+    if let Some(schema_url) = schema_url {
+        builder = builder.with_schema_url(schema_url);
+    }
+    let scope = InstrumentationScope(builder.build());
+    state.put(scope.clone());
+    scope
+}
+
+#[op2(fast)]
+fn op_scope_enter(state: &mut OpState, #[cppgc] scope: &InstrumentationScope) {
+    state.put(scope.clone());
+}
+
+#[op2(fast)]
+fn op_scope_enter_builtin(state: &mut OpState) {
+    state.put(InstrumentationScope(builtin_scope()));
+}
+
+#[allow(clippy::too_many_arguments)]
+#[op2(fast)]
+fn op_span_start<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    state: &mut OpState,
+    trace_id: v8::Local<'s, v8::Value>,
+    span_id: v8::Local<'s, v8::Value>,
+    parent_span_id: v8::Local<'s, v8::Value>,
+    #[smi] span_kind: u8,
+    name: v8::Local<'s, v8::Value>,
+    start_time: f64,
+    end_time: f64,
+) -> Result<(), deno_core::anyhow::Error> {
+    if let Some(span) = state.try_take::<TemporarySpan>() {
+        submit(state, span);
+    }
+
+    let Some(InstrumentationScope(instrumentation_scope)) =
+        state.try_borrow::<InstrumentationScope>().cloned()
+    else {
+        return Err(anyhow!("instrumentation scope not available"));
+    };
+
+    let trace_id = parse_trace_id(scope, trace_id);
+    if trace_id == TraceId::INVALID {
+        return Err(anyhow!("invalid trace_id"));
+    }
+
+    let span_id = parse_span_id(scope, span_id);
+    if span_id == SpanId::INVALID {
+        return Err(anyhow!("invalid span_id"));
+    }
+
+    let span = TemporarySpan(SpanData {
+        span_context: SpanContext::new(
+            trace_id,
+            span_id,
+            TraceFlags::SAMPLED,
+            false,
+            TraceState::default(),
+        ),
+        parent_span_id: parse_span_id(scope, parent_span_id),
+        span_kind: match span_kind {
+            0 => SpanKind::Internal,
+            1 => SpanKind::Server,
+            2 => SpanKind::Client,
+            3 => SpanKind::Producer,
+            4 => SpanKind::Consumer,
+            _ => return Err(anyhow!("invalid span kind")),
+        },
+        name: Cow::Owned(to_string(scope, name).unwrap_or_default()),
+        start_time: seconds_to_time(start_time)?,
+        end_time: seconds_to_time(end_time)?,
+        attributes: Vec::new(),
+        dropped_attributes_count: 0,
+        events: SpanEvents::default(),
+        links: SpanLinks::default(),
+        status: Status::Unset,
+        instrumentation_scope,
+    });
+    state.put(span);
+
+    Ok(())
+}
+
+#[op2(fast)]
+fn op_span_continue(
+    state: &mut OpState,
+    #[smi] status: u8,
+    #[string] error_description: Cow<'_, str>,
+) {
+    if let Some(TemporarySpan(span)) = state.try_borrow_mut::<TemporarySpan>() {
+        span.status = match status {
+            0 => Status::Unset,
+            1 => Status::Ok,
+            2 => Status::error(error_description.into_owned()),
+            _ => return,
+        };
+    }
+}
+
+#[op2(fast)]
+fn op_span_attribute<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    state: &mut OpState,
+    #[smi] _capacity: u32,
+    key: v8::Local<'s, v8::Value>,
+    value: v8::Local<'s, v8::Value>,
+) {
+    if let Some(TemporarySpan(span)) = state.try_borrow_mut::<TemporarySpan>() {
+        push_attribute(scope, span, key, value);
+    }
+}
+
+#[op2(fast)]
+fn op_span_attribute2<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    state: &mut OpState,
+    #[smi] _capacity: u32,
+    key1: v8::Local<'s, v8::Value>,
+    value1: v8::Local<'s, v8::Value>,
+    key2: v8::Local<'s, v8::Value>,
+    value2: v8::Local<'s, v8::Value>,
+) {
+    if let Some(TemporarySpan(span)) = state.try_borrow_mut::<TemporarySpan>() {
+        push_attribute(scope, span, key1, value1);
+        push_attribute(scope, span, key2, value2);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+#[op2(fast)]
+fn op_span_attribute3<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    state: &mut OpState,
+    #[smi] _capacity: u32,
+    key1: v8::Local<'s, v8::Value>,
+    value1: v8::Local<'s, v8::Value>,
+    key2: v8::Local<'s, v8::Value>,
+    value2: v8::Local<'s, v8::Value>,
+    key3: v8::Local<'s, v8::Value>,
+    value3: v8::Local<'s, v8::Value>,
+) {
+    if let Some(TemporarySpan(span)) = state.try_borrow_mut::<TemporarySpan>() {
+        push_attribute(scope, span, key1, value1);
+        push_attribute(scope, span, key2, value2);
+        push_attribute(scope, span, key3, value3);
+    }
+}
+
+#[op2(fast)]
+fn op_span_set_dropped(
+    state: &mut OpState,
+    #[smi] dropped_attributes_count: u32,
+    #[smi] dropped_links_count: u32,
+    #[smi] dropped_events_count: u32,
+) {
+    if let Some(TemporarySpan(span)) = state.try_borrow_mut::<TemporarySpan>() {
+        span.dropped_attributes_count += dropped_attributes_count;
+        span.links.dropped_count += dropped_links_count;
+        span.events.dropped_count += dropped_events_count;
+    }
+}
+
+#[op2(fast)]
+fn op_span_flush(state: &mut OpState) {
+    if let Some(span) = state.try_take::<TemporarySpan>() {
+        submit(state, span);
+    }
+}
@@ -0,0 +1,220 @@
+// A minimal `URL` and `URLSearchParams`, used when the `url` feature is disabled
+// Parsing is done by the `url` crate, so behaviour matches the full extension - `URLPattern` is not included
+import {
+    op_stub_url_can_parse,
+    op_stub_url_parse,
+    op_stub_url_parse_search_params,
+    op_stub_url_reparse,
+    op_stub_url_stringify_search_params,
+} from "ext:core/ops";
+
+const updateUrl = Symbol("updateUrl");
+const setList = Symbol("setList");
+
+class URLSearchParams {
+    #list = [];
+    #url = null;
+
+    /**
+     * @param {string | [string, string][] | Record<string, string>} init
+     */
+    constructor(init = "") {
+        if (typeof init === "object" && init !== null) {
+            const pairs = typeof init[Symbol.iterator] === "function" ? init : Object.entries(init);
+            for (const pair of pairs) {
+                const entry = [...pair];
+                if (entry.length !== 2) {
+                    throw new TypeError("Each query pair must be an iterable [name, value] tuple");
+                }
+                this.#list.push([String(entry[0]), String(entry[1])]);
+            }
+        } else {
+            let query = String(init);
+            if (query.startsWith("?")) query = query.slice(1);
+            this.#list = op_stub_url_parse_search_params(query);
+        }
+    }
+
+    static [setList](params, list, url) {
+        params.#list = list;
+        params.#url = url;
+    }
+
+    #update() {
+        this.#url?.[updateUrl](this.toString());
+    }
+
+    get size() {
+        return this.#list.length;
+    }
+
+    append(name, value) {
+        this.#list.push([String(name), String(value)]);
+        this.#update();
+    }
+
+    delete(name, value = undefined) {
+        name = String(name);
+        this.#list = this.#list.filter(([n, v]) => n !== name || (value !== undefined && v !== String(value)));
+        this.#update();
+    }
+
+    get(name) {
+        name = String(name);
+        return this.#list.find(([n]) => n === name)?.[1] ?? null;
+    }
+
+    getAll(name) {
+        name = String(name);
+        return this.#list.filter(([n]) => n === name).map(([, v]) => v);
+    }
+
+    has(name, value = undefined) {
+        name = String(name);
+        return this.#list.some(([n, v]) => n === name && (value === undefined || v === String(value)));
+    }
+
+    set(name, value) {
+        name = String(name);
+        value = String(value);
+        const index = this.#list.findIndex(([n]) => n === name);
+        if (index === -1) {
+            this.#list.push([name, value]);
+        } else {
+            this.#list[index][1] = value;
+            this.#list = this.#list.filter(([n], i) => n !== name || i <= index);
+        }
+        this.#update();
+    }
+
+    sort() {
+        // Stable sort by code units, as the spec requires
+        this.#list = this.#list
+            .map((entry, i) => [entry, i])
+            .sort(([a, i], [b, j]) => (a[0] < b[0] ? -1 : a[0] > b[0] ? 1 : i - j))
+            .map(([entry]) => entry);
+        this.#update();
+    }
+
+    forEach(callback, thisArg = undefined) {
+        for (const [name, value] of this.#list) {
+            callback.call(thisArg, value, name, this);
+        }
+    }
+
+    *keys() {
+        for (const [name] of this.#list) yield name;
+    }
+
+    *values() {
+        for (const [, value] of this.#list) yield value;
+    }
+
+    *entries() {
+        for (const [name, value] of this.#list) yield [name, value];
+    }
+
+    [Symbol.iterator]() {
+        return this.entries();
+    }
+
+    toString() {
+        return op_stub_url_stringify_search_params(this.#list);
+    }
+}
+
+class URL {
+    #components;
+    #searchParams = null;
+
+    /**
+     * @param {string} url
+     * @param {string} [base]
+     */
+    constructor(url, base = undefined) {
+        if (arguments.length === 0) {
+            throw new TypeError("Failed to construct 'URL': 1 argument required, but only 0 present");
+        }
+        this.#components = op_stub_url_parse(String(url), base === undefined ? null : String(base));
+    }
+
+    static canParse(url, base = undefined) {
+        return op_stub_url_can_parse(String(url), base === undefined ? null : String(base));
+    }
+
+    static parse(url, base = undefined) {
+        return URL.canParse(url, base) ? new URL(url, base) : null;
+    }
+
+    #set(component, value) {
+        this.#components = op_stub_url_reparse(this.#components.href, component, String(value));
+        if (component === "href" || component === "search") this.#syncSearchParams();
+    }
+
+    #syncSearchParams() {
+        if (this.#searchParams) {
+            const query = this.#components.search.slice(1);
+            URLSearchParams[setList](this.#searchParams, op_stub_url_parse_search_params(query), this);
+        }
+    }
+
+    // Called by the linked `URLSearchParams` when it is modified
+    // An empty query removes the `?` entirely
+    [updateUrl](query) {
+        this.#components = op_stub_url_reparse(this.#components.href, "search", query);
+    }
+
+    get searchParams() {
+        if (!this.#searchParams) {
+            this.#searchParams = new URLSearchParams();
+            this.#syncSearchParams();
+        }
+        return this.#searchParams;
+    }
+
+    get href() { return this.#components.href; }
+    set href(value) { this.#set("href", value); }
+
+    get origin() { return this.#components.origin; }
+
+    get protocol() { return this.#components.protocol; }
+    set protocol(value) { this.#set("protocol", value); }
+
+    get username() { return this.#components.username; }
+    set username(value) { this.#set("username", value); }
+
+    get password() { return this.#components.password; }
+    set password(value) { this.#set("password", value); }
+
+    get host() { return this.#components.host; }
+    set host(value) { this.#set("host", value); }
+
+    get hostname() { return this.#components.hostname; }
+    set hostname(value) { this.#set("hostname", value); }
+
+    get port() { return this.#components.port; }
+    set port(value) { this.#set("port", value); }
+
+    get pathname() { return this.#components.pathname; }
+    set pathname(value) { this.#set("pathname", value); }
+
+    get search() { return this.#components.search; }
+    set search(value) { this.#set("search", value); }
+
+    get hash() { return this.#components.hash; }
+    set hash(value) { this.#set("hash", value); }
+
+    toString() {
+        return this.#components.href;
+    }
+
+    toJSON() {
+        return this.#components.href;
+    }
+}
+
+for (const Class of [URL, URLSearchParams]) {
+    Object.defineProperty(Class.prototype, Symbol.toStringTag, { value: Class.name, configurable: true });
+}
+
+export { URL, URLSearchParams };
@@ -0,0 +1,36 @@
+// A minimal `performance` global, used when the `web` feature is disabled
+// Only the clock is provided - marks, measures and the performance timeline need the full extension
+import { opNow } from 'ext:deno_web/02_timers.js';
+
+const timeOrigin = Date.now() - opNow();
+
+class Performance {
+    constructor() {
+        throw new TypeError('Illegal constructor');
+    }
+
+    /**
+     * Milliseconds elapsed since the runtime started, at reduced precision
+     * @returns {number}
+     */
+    now() {
+        return opNow();
+    }
+
+    /**
+     * The time the runtime started, in milliseconds since the unix epoch
+     * @returns {number}
+     */
+    get timeOrigin() {
+        return timeOrigin;
+    }
+
+    toJSON() {
+        return { timeOrigin };
+    }
+}
+
+Object.defineProperty(Performance.prototype, Symbol.toStringTag, { value: 'Performance', configurable: true });
+const performance = Object.create(Performance.prototype);
+
+export { Performance, performance };
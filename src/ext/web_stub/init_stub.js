@@ -1,10 +1,17 @@
 import * as DOMException from 'ext:deno_web/01_dom_exception.js';
 import * as timers from 'ext:deno_web/02_timers.js';
 import * as base64 from 'ext:deno_web/05_base64.js';
+import * as encoding from 'ext:deno_web/08_text_encoding.js';
+import * as structuredClone from 'ext:deno_web/02_structured_clone.js';
+import * as event from 'ext:deno_web/02_event.js';
+import * as abortSignal from 'ext:deno_web/03_abort_signal.js';
+import * as url from 'ext:deno_web/00_url.js';
+import * as performance from 'ext:deno_web/15_performance.js';
+import { core } from 'ext:core/mod.js';
 
 import { applyToGlobal, nonEnumerable, writeable } from 'ext:rustyscript/rustyscript.js';
 applyToGlobal({
-    DOMException: nonEnumerable(DOMException),
+    DOMException: nonEnumerable(DOMException.DOMException),
 
     setImmediate: writeable(timers.setImmediate),
     clearInterval: writeable(timers.clearInterval),
@@ -16,5 +23,29 @@ applyToGlobal({
 
     atob: writeable(base64.atob),
     btoa: writeable(base64.btoa),
+
+    TextDecoder: nonEnumerable(encoding.TextDecoder),
+    TextEncoder: nonEnumerable(encoding.TextEncoder),
+    structuredClone: writeable(structuredClone.structuredClone),
+
+    Event: nonEnumerable(event.Event),
+    EventTarget: nonEnumerable(event.EventTarget),
+    AbortController: nonEnumerable(abortSignal.AbortController),
+    AbortSignal: nonEnumerable(abortSignal.AbortSignal),
+
+    queueMicrotask: writeable(core.queueMicrotask),
+    reportError: writeable(event.reportError),
+    Performance: nonEnumerable(performance.Performance),
+    performance: writeable(performance.performance),
 });
+event.saveGlobalThisReference(globalThis);
+core.setReportExceptionCallback(event.reportException);
+
+// The `url` extension provides the full implementation, including `URLPattern`, when enabled
+if (globalThis.URL === undefined) {
+    applyToGlobal({
+        URL: nonEnumerable(url.URL),
+        URLSearchParams: nonEnumerable(url.URLSearchParams),
+    });
+}
 
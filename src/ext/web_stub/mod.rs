@@ -7,15 +7,20 @@ use deno_core::{extension, Extension};
 
 mod encoding;
 mod timers;
+mod url;
 
 extension!(
     deno_web,
     ops = [
         timers::op_now, timers::op_defer,
         encoding::op_base64_decode, encoding::op_base64_atob, encoding::op_base64_encode, encoding::op_base64_btoa,
+        encoding::op_encoding_normalize_label, encoding::op_encoding_decode_single, encoding::op_encoding_decode_utf8,
+        encoding::op_encoding_new_decoder, encoding::op_encoding_decode, encoding::op_encoding_encode_into,
+        url::op_stub_url_parse, url::op_stub_url_can_parse, url::op_stub_url_reparse, url::op_stub_url_parse_search_params, url::op_stub_url_stringify_search_params,
     ],
     esm_entry_point = "ext:deno_web/init_stub.js",
-    esm = [ dir "src/ext/web_stub", "init_stub.js", "01_dom_exception.js", "02_timers.js", "05_base64.js" ],
+    esm = [ dir "src/ext/web_stub", "init_stub.js", "00_url.js", "01_dom_exception.js", "02_event.js", "02_structured_clone.js", "02_timers.js", "03_abort_signal.js", "05_base64.js", "08_text_encoding.js", "15_performance.js" ],
+    state = |state| state.put(timers::StartTime::now()),
 );
 impl ExtensionTrait<()> for deno_web {
     fn init((): ()) -> Extension {
@@ -26,3 +31,110 @@ impl ExtensionTrait<()> for deno_web {
 pub fn extensions(is_snapshot: bool) -> Vec<Extension> {
     vec![deno_web::build((), is_snapshot)]
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{Module, Runtime, RuntimeOptions};
+
+    #[test]
+    fn test_text_encoding() {
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let module = Module::new(
+            "test.js",
+            "
+            const bytes = new TextEncoder().encode('héllo');
+            if (bytes.length !== 6) throw new Error('bad encode');
+            if (new TextDecoder().decode(bytes) !== 'héllo') throw new Error('bad decode');
+
+            const into = new Uint8Array(3);
+            const { read, written } = new TextEncoder().encodeInto('héllo', into);
+            if (read !== 2 || written !== 3) throw new Error('bad encodeInto');
+
+            const utf16 = new TextDecoder('utf-16le').decode(new Uint8Array([104, 0, 105, 0]));
+            if (utf16 !== 'hi') throw new Error('bad utf-16');
+
+            const decoder = new TextDecoder('utf-8', { fatal: true });
+            let threw = false;
+            try { decoder.decode(new Uint8Array([0xff])); } catch { threw = true; }
+            if (!threw) throw new Error('fatal decoder accepted invalid data');
+
+            const value = { nested: { list: [1, 2] }, bytes };
+            const copy = structuredClone(value);
+            if (copy === value || copy.nested.list[1] !== 2 || copy.bytes[0] !== 104) throw new Error('bad clone');
+        ",
+        );
+        runtime.load_module(&module).unwrap();
+    }
+
+    #[test]
+    fn test_url() {
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+
+        // Imported directly, since the `url` extension replaces the stub when it is enabled
+        let module = Module::new(
+            "test.js",
+            "
+            import { URL, URLSearchParams } from 'ext:deno_web/00_url.js';
+
+            const url = new URL('../b/c?x=1&y=a+b#frag', 'https://user@example.com:8080/a/index.html');
+            if (url.href !== 'https://user@example.com:8080/b/c?x=1&y=a+b#frag') throw new Error(url.href);
+            if (url.origin !== 'https://example.com:8080' || url.pathname !== '/b/c') throw new Error('bad parts');
+            if (url.searchParams.get('y') !== 'a b') throw new Error('bad params');
+
+            url.searchParams.append('z', '1 2');
+            if (url.search !== '?x=1&y=a+b&z=1+2') throw new Error(url.search);
+            url.search = '';
+            if (url.searchParams.size !== 0 || url.href !== 'https://user@example.com:8080/b/c#frag') throw new Error(url.href);
+
+            url.port = 'invalid';
+            if (url.port !== '8080') throw new Error('invalid port was applied');
+
+            if (URL.canParse('not a url') || URL.parse('/relative') !== null) throw new Error('bad canParse');
+            let threw = false;
+            try { new URL('/relative'); } catch (e) { threw = e instanceof TypeError; }
+            if (!threw) throw new Error('invalid url was parsed');
+
+            const params = new URLSearchParams({ b: '2', a: '1' });
+            params.sort();
+            if (params.toString() !== 'a=1&b=2') throw new Error(params.toString());
+        ",
+        );
+        runtime.load_module(&module).unwrap();
+    }
+
+    #[test]
+    fn test_global_url() {
+        // The stub's URL ops are registered alongside `deno_url`'s with the default features, and must not collide
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let href: String = runtime
+            .eval("new URL('../b?c=1', 'https://example.com/a/index.html').href")
+            .unwrap();
+        assert_eq!(href, "https://example.com/b?c=1");
+    }
+
+    #[test]
+    fn test_microtasks_and_performance() {
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let module = Module::new(
+            "test.js",
+            "
+            const order = [];
+            queueMicrotask(() => order.push('microtask'));
+            order.push('sync');
+            await Promise.resolve();
+            if (order.join() !== 'sync,microtask') throw new Error(order.join());
+
+            const start = performance.now();
+            if (typeof start !== 'number' || start < 0) throw new Error('bad now');
+            if (Math.abs(performance.timeOrigin + start - Date.now()) > 1000) throw new Error('bad timeOrigin');
+            if (typeof reportError !== 'function') throw new Error('missing reportError');
+        ",
+        );
+        runtime.load_module(&module).unwrap();
+
+        // Reported errors are unhandled, since the global object is not an event target
+        let module = Module::new("report.js", "reportError(new Error('reported'));");
+        let err = runtime.load_module(&module).unwrap_err();
+        assert!(err.to_string().contains("reported"), "{err}");
+    }
+}
@@ -0,0 +1,108 @@
+//! Ops backing the minimal `URL` and `URLSearchParams` classes, used when the `url` feature is disabled
+use deno_core::{
+    error::{type_error, AnyError},
+    op2,
+    url::{form_urlencoded, quirks, Url},
+};
+
+/// The parts of a parsed URL, as exposed by the `URL` class
+#[derive(serde::Serialize)]
+pub struct UrlComponents {
+    href: String,
+    origin: String,
+    protocol: String,
+    username: String,
+    password: String,
+    host: String,
+    hostname: String,
+    port: String,
+    pathname: String,
+    search: String,
+    hash: String,
+}
+
+impl From<&Url> for UrlComponents {
+    fn from(url: &Url) -> Self {
+        Self {
+            href: quirks::href(url).to_string(),
+            origin: quirks::origin(url),
+            protocol: quirks::protocol(url).to_string(),
+            username: quirks::username(url).to_string(),
+            password: quirks::password(url).to_string(),
+            host: quirks::host(url).to_string(),
+            hostname: quirks::hostname(url).to_string(),
+            port: quirks::port(url).to_string(),
+            pathname: quirks::pathname(url).to_string(),
+            search: quirks::search(url).to_string(),
+            hash: quirks::hash(url).to_string(),
+        }
+    }
+}
+
+fn parse(href: &str, base: Option<&str>) -> Result<Url, AnyError> {
+    let base = base
+        .map(Url::parse)
+        .transpose()
+        .map_err(|_| type_error(format!("Invalid base URL: '{}'", base.unwrap_or_default())))?;
+    Url::options()
+        .base_url(base.as_ref())
+        .parse(href)
+        .map_err(|_| type_error(format!("Invalid URL: '{href}'")))
+}
+
+#[op2]
+#[serde]
+pub fn op_stub_url_parse(
+    #[string] href: &str,
+    #[string] base: Option<String>,
+) -> Result<UrlComponents, AnyError> {
+    let url = parse(href, base.as_deref())?;
+    Ok(UrlComponents::from(&url))
+}
+
+#[op2]
+pub fn op_stub_url_can_parse(#[string] href: &str, #[string] base: Option<String>) -> bool {
+    parse(href, base.as_deref()).is_ok()
+}
+
+/// Applies one of the `URL` setters - invalid values are ignored, as the spec requires
+#[op2]
+#[serde]
+pub fn op_stub_url_reparse(
+    #[string] href: &str,
+    #[string] setter: &str,
+    #[string] value: &str,
+) -> Result<UrlComponents, AnyError> {
+    let mut url = parse(href, None)?;
+    match setter {
+        "href" => quirks::set_href(&mut url, value)
+            .map_err(|_| type_error(format!("Invalid URL: '{value}'")))?,
+        "protocol" => quirks::set_protocol(&mut url, value).unwrap_or_default(),
+        "username" => quirks::set_username(&mut url, value).unwrap_or_default(),
+        "password" => quirks::set_password(&mut url, value).unwrap_or_default(),
+        "host" => quirks::set_host(&mut url, value).unwrap_or_default(),
+        "hostname" => quirks::set_hostname(&mut url, value).unwrap_or_default(),
+        "port" => quirks::set_port(&mut url, value).unwrap_or_default(),
+        "pathname" => quirks::set_pathname(&mut url, value),
+        "search" => quirks::set_search(&mut url, value),
+        "hash" => quirks::set_hash(&mut url, value),
+        _ => return Err(type_error(format!("Unknown URL component: '{setter}'"))),
+    }
+    Ok(UrlComponents::from(&url))
+}
+
+#[op2]
+#[serde]
+pub fn op_stub_url_parse_search_params(#[string] query: &str) -> Vec<(String, String)> {
+    form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .collect()
+}
+
+#[op2]
+#[string]
+pub fn op_stub_url_stringify_search_params(#[serde] params: Vec<(String, String)>) -> String {
+    form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(params)
+        .finish()
+}
@@ -0,0 +1,256 @@
+//! Assembles `deno_core` extensions at runtime, from ops and javascript source strings
+//!
+//! The `deno_core::extension!` macro requires an extension's contents to be known at compile time
+//! [`ExtensionBuilder`] instead accepts them at runtime, for hosts that build their API surface from plugins
+//!
+//! Extensions produced this way behave like any other: they can be given to [`crate::RuntimeOptions::extensions`],
+//! included in snapshots, and any typescript sources are transpiled when the runtime loads them
+//!
+//! Since `deno_core` requires extension names and source specifiers to be `'static`,
+//! each call to [`ExtensionBuilder::build`] leaks those strings - so extensions should be built once, not per-request
+//!
+//! ```rust
+//! use rustyscript::{ extension_builder::ExtensionBuilder, Runtime, RuntimeOptions };
+//!
+//! # fn main() -> Result<(), rustyscript::Error> {
+//! let extension = ExtensionBuilder::new("my_plugin")
+//!     .with_esm("ext:my_plugin/mod.ts", "globalThis.plugin = { version: (): number => 2 };")
+//!     .build()?;
+//!
+//! let mut runtime = Runtime::new(RuntimeOptions {
+//!     extensions: vec![extension],
+//!     ..Default::default()
+//! })?;
+//!
+//! let version: i64 = runtime.eval("plugin.version()")?;
+//! assert_eq!(version, 2);
+//! # Ok(())
+//! # }
+//! ```
+use crate::Error;
+use deno_core::{Extension, ExtensionFileSource, OpDecl, OpState};
+use std::{borrow::Cow, sync::Arc};
+
+/// A function initializing an extension's state
+type StateFn = Box<dyn FnOnce(&mut OpState)>;
+
+/// Builds a `deno_core::Extension` from ops and source strings, see the [module-level documentation](self)
+pub struct ExtensionBuilder {
+    name: String,
+    deps: Vec<String>,
+    ops: Vec<OpDecl>,
+    esm_files: Vec<(String, Arc<str>)>,
+    js_files: Vec<(String, Arc<str>)>,
+    esm_entry_point: Option<String>,
+    state: Vec<StateFn>,
+}
+
+impl ExtensionBuilder {
+    /// Create a new, empty extension with the given name
+    ///
+    /// Names must be unique among the extensions loaded into a runtime
+    #[must_use]
+    pub fn new(name: impl ToString) -> Self {
+        Self {
+            name: name.to_string(),
+            deps: Vec::new(),
+            ops: Vec::new(),
+            esm_files: Vec::new(),
+            js_files: Vec::new(),
+            esm_entry_point: None,
+            state: Vec::new(),
+        }
+    }
+
+    /// Add the name of an extension that must be loaded before this one
+    #[must_use]
+    pub fn with_dependency(mut self, name: impl ToString) -> Self {
+        self.deps.push(name.to_string());
+        self
+    }
+
+    /// Add an op, such as one defined with `#[op2]` - which is passed as `my_op()`
+    #[must_use]
+    pub fn with_op(mut self, op: OpDecl) -> Self {
+        self.ops.push(op);
+        self
+    }
+
+    /// Add a set of ops, see [`ExtensionBuilder::with_op`]
+    #[must_use]
+    pub fn with_ops(mut self, ops: impl IntoIterator<Item = OpDecl>) -> Self {
+        self.ops.extend(ops);
+        self
+    }
+
+    /// Add an ES module to the extension
+    ///
+    /// The specifier must use the `ext:` scheme, for example `ext:my_plugin/mod.js`
+    /// Typescript modules, with a `.ts` extension, are transpiled when loaded
+    #[must_use]
+    pub fn with_esm(mut self, specifier: impl ToString, code: impl Into<Arc<str>>) -> Self {
+        self.esm_files.push((specifier.to_string(), code.into()));
+        self
+    }
+
+    /// Add a classic script to the extension, which is run before any ES modules
+    ///
+    /// The specifier must use the `ext:` scheme
+    #[must_use]
+    pub fn with_script(mut self, specifier: impl ToString, code: impl Into<Arc<str>>) -> Self {
+        self.js_files.push((specifier.to_string(), code.into()));
+        self
+    }
+
+    /// Set the ES module evaluated when the extension is loaded
+    ///
+    /// Other modules are only evaluated if imported, directly or indirectly, by the entry point
+    /// Defaults to the first module added with [`ExtensionBuilder::with_esm`]
+    #[must_use]
+    pub fn with_entry_point(mut self, specifier: impl ToString) -> Self {
+        self.esm_entry_point = Some(specifier.to_string());
+        self
+    }
+
+    /// Add a value to the state of any runtime using the extension
+    /// It can then be retrieved by ops, from their `OpState`
+    #[must_use]
+    pub fn with_state<T: 'static>(mut self, value: T) -> Self {
+        self.state.push(Box::new(move |state| state.put(value)));
+        self
+    }
+
+    /// Build the extension
+    ///
+    /// # Errors
+    /// Will return an error if a specifier does not use the `ext:` scheme,
+    /// or if the entry point is not one of the extension's ES modules
+    pub fn build(self) -> Result<Extension, Error> {
+        self.build_extension(false)
+    }
+
+    /// Build the extension without any javascript sources, keeping only its ops and state
+    ///
+    /// Use this for runtimes started from a snapshot that already contains the extension,
+    /// such as one created by [`crate::SnapshotBuilder`] with the complete extension
+    ///
+    /// # Errors
+    /// Will return an error in the same cases as [`ExtensionBuilder::build`]
+    pub fn build_for_snapshot(self) -> Result<Extension, Error> {
+        self.build_extension(true)
+    }
+
+    fn build_extension(self, ops_only: bool) -> Result<Extension, Error> {
+        for (specifier, _) in self.esm_files.iter().chain(&self.js_files) {
+            if !specifier.starts_with("ext:") {
+                return Err(Error::Runtime(format!(
+                    "Extension sources must use the ext: scheme: {specifier}"
+                )));
+            }
+        }
+
+        let entry_point = self
+            .esm_entry_point
+            .or_else(|| self.esm_files.first().map(|(s, _)| s.clone()));
+        if let Some(entry_point) = &entry_point {
+            if !self.esm_files.iter().any(|(s, _)| s == entry_point) {
+                return Err(Error::Runtime(format!(
+                    "Extension entry point is not one of its modules: {entry_point}"
+                )));
+            }
+        }
+
+        let state = self.state;
+        let op_state_fn: Option<StateFn> = if state.is_empty() {
+            None
+        } else {
+            Some(Box::new(move |op_state| {
+                for f in state {
+                    f(op_state);
+                }
+            }))
+        };
+
+        let mut extension = Extension {
+            name: leak(self.name),
+            deps: Box::leak(self.deps.into_iter().map(leak).collect()),
+            ops: Cow::Owned(self.ops),
+            op_state_fn,
+            ..Default::default()
+        };
+
+        if !ops_only {
+            extension.esm_files = Cow::Owned(sources(self.esm_files));
+            extension.js_files = Cow::Owned(sources(self.js_files));
+            extension.esm_entry_point = entry_point.map(leak);
+        }
+
+        Ok(extension)
+    }
+}
+
+fn leak(s: String) -> &'static str {
+    Box::leak(s.into_boxed_str())
+}
+
+fn sources(files: Vec<(String, Arc<str>)>) -> Vec<ExtensionFileSource> {
+    files
+        .into_iter()
+        .map(|(specifier, code)| ExtensionFileSource::new_computed(leak(specifier), code))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Runtime, RuntimeOptions};
+    use deno_core::op2;
+
+    struct Greeting(String);
+
+    #[op2]
+    #[string]
+    fn op_plugin_greeting(state: &mut OpState) -> String {
+        state.borrow::<Greeting>().0.clone()
+    }
+
+    #[test]
+    fn test_extension_builder() {
+        let extension = ExtensionBuilder::new("test_plugin")
+            .with_op(op_plugin_greeting())
+            .with_state(Greeting("hello".to_string()))
+            .with_script("ext:test_plugin/init.js", "globalThis.initialized = true;")
+            .with_esm(
+                "ext:test_plugin/mod.ts",
+                "
+                import { suffix } from 'ext:test_plugin/suffix.js';
+                globalThis.greet = (): string => Deno.core.ops.op_plugin_greeting() + suffix;
+            ",
+            )
+            .with_esm("ext:test_plugin/suffix.js", "export const suffix = '!';")
+            .build()
+            .unwrap();
+
+        let mut runtime = Runtime::new(RuntimeOptions {
+            extensions: vec![extension],
+            ..Default::default()
+        })
+        .unwrap();
+
+        let greeting: String = runtime.eval("greet()").unwrap();
+        assert_eq!(greeting, "hello!");
+        let initialized: bool = runtime.eval("globalThis.initialized").unwrap();
+        assert!(initialized);
+
+        let invalid_specifier = ExtensionBuilder::new("bad")
+            .with_esm("bad/mod.js", "")
+            .build();
+        assert!(invalid_specifier.is_err());
+
+        let missing_entry_point = ExtensionBuilder::new("bad")
+            .with_esm("ext:bad/mod.js", "")
+            .with_entry_point("ext:bad/missing.js")
+            .build();
+        assert!(missing_entry_point.is_err());
+    }
+}
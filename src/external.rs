@@ -0,0 +1,348 @@
+//! Opaque handles to rust values that can be passed through javascript
+//!
+//! Large host objects that scripts only need to reference can be stored in the runtime
+//! instead of being serialized on every call.
+//!
+//! Scripts receive a small handle object, which they can store and pass back into registered functions.
+//! The values themselves never leave rust.
+use std::{
+    any::Any,
+    cell::{Ref, RefCell, RefMut},
+    collections::hash_map::RandomState,
+    hash::BuildHasher,
+    marker::PhantomData,
+    rc::Rc,
+};
+
+/// An opaque handle to a rust value stored in an [`ExternalStore`]
+///
+/// Serializes as a small object that scripts can store and pass back into registered functions,
+/// and deserializes from the same object - including from the `serde_json::Value` arguments of a registered function
+///
+/// Handles are generational: once a value is removed from the store, existing handles to it can no longer resolve,
+/// even if the slot is reused. A handle only resolves to a value of type `T`.
+///
+/// Each handle also carries a tag derived from a secret key held by its store, so scripts cannot forge a handle
+/// from a slot index, and a handle only resolves in the store that created it
+pub struct External<T> {
+    index: u32,
+    generation: u32,
+    tag: u64,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> External<T> {
+    /// Returns the slot index this handle refers to
+    #[must_use]
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Returns the generation of the slot this handle refers to
+    #[must_use]
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+impl<T> Clone for External<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<T> Copy for External<T> {}
+
+impl<T> PartialEq for External<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index && self.generation == other.generation && self.tag == other.tag
+    }
+}
+impl<T> Eq for External<T> {}
+
+impl<T> std::hash::Hash for External<T> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+        self.generation.hash(state);
+        self.tag.hash(state);
+    }
+}
+
+impl<T> std::fmt::Debug for External<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("External")
+            .field("type", &std::any::type_name::<T>())
+            .field("index", &self.index)
+            .field("generation", &self.generation)
+            .finish()
+    }
+}
+
+/// The javascript representation of an [`External`]
+/// The tag is a hex string, since javascript numbers cannot hold 64 bits
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ExternalRepr {
+    #[serde(rename = "$__rustyscript_external")]
+    index: u32,
+
+    #[serde(rename = "$__rustyscript_generation")]
+    generation: u32,
+
+    #[serde(rename = "$__rustyscript_tag")]
+    tag: String,
+}
+
+impl<T> serde::Serialize for External<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        ExternalRepr {
+            index: self.index,
+            generation: self.generation,
+            tag: format!("{:016x}", self.tag),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, T> serde::Deserialize<'de> for External<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let repr = ExternalRepr::deserialize(deserializer)?;
+        let tag = u64::from_str_radix(&repr.tag, 16)
+            .map_err(|_| serde::de::Error::custom("Invalid external handle tag"))?;
+        Ok(Self {
+            index: repr.index,
+            generation: repr.generation,
+            tag,
+            _marker: PhantomData,
+        })
+    }
+}
+
+struct Slot {
+    generation: u32,
+    value: Option<Box<dyn Any>>,
+}
+
+#[derive(Default)]
+struct Slots {
+    entries: Vec<Slot>,
+    free: Vec<u32>,
+
+    /// Randomly keyed for each store, so tags cannot be predicted - see [`External`]
+    key: RandomState,
+}
+
+impl Slots {
+    fn tag(&self, index: u32, generation: u32) -> u64 {
+        self.key.hash_one((index, generation))
+    }
+
+    /// Returns the slot a handle refers to, if it is still current and the handle was created by this store
+    fn slot(&self, index: u32, generation: u32, tag: u64) -> Option<usize> {
+        let slot = self.entries.get(index as usize)?;
+        (slot.generation == generation && self.tag(index, generation) == tag)
+            .then_some(index as usize)
+    }
+
+    fn get<T: 'static>(&self, handle: External<T>) -> Option<&T> {
+        let index = self.slot(handle.index, handle.generation, handle.tag)?;
+        self.entries[index].value.as_ref()?.downcast_ref()
+    }
+
+    fn get_mut<T: 'static>(&mut self, handle: External<T>) -> Option<&mut T> {
+        let index = self.slot(handle.index, handle.generation, handle.tag)?;
+        self.entries[index].value.as_mut()?.downcast_mut()
+    }
+}
+
+/// Storage for values referenced by [`External`] handles
+///
+/// Each runtime has one store, kept in its `OpState` - see [`crate::Runtime::external_store`]
+/// The store is reference counted, so it can be cloned into registered functions to resolve handles passed in by scripts
+///
+/// Values live until they are removed, or the runtime is dropped
+#[derive(Clone, Default)]
+pub struct ExternalStore(Rc<RefCell<Slots>>);
+
+impl ExternalStore {
+    /// Stores a value, returning a handle to it
+    ///
+    /// # Panics
+    /// Will panic if the store is already borrowed mutably, or if more than `u32::MAX` values are stored
+    #[must_use]
+    pub fn insert<T: 'static>(&self, value: T) -> External<T> {
+        let mut slots = self.0.borrow_mut();
+        let value: Box<dyn Any> = Box::new(value);
+
+        let (index, generation) = if let Some(index) = slots.free.pop() {
+            let slot = &mut slots.entries[index as usize];
+            slot.value = Some(value);
+            (index, slot.generation)
+        } else {
+            let index = u32::try_from(slots.entries.len()).expect("Too many external values");
+            slots.entries.push(Slot {
+                generation: 0,
+                value: Some(value),
+            });
+            (index, 0)
+        };
+
+        External {
+            index,
+            generation,
+            tag: slots.tag(index, generation),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns a reference to the value behind a handle
+    /// Returns None if the value has been removed, or is not of type `T`
+    ///
+    /// # Panics
+    /// Will panic if the store is already borrowed mutably
+    #[must_use]
+    pub fn get<T: 'static>(&self, handle: External<T>) -> Option<Ref<'_, T>> {
+        Ref::filter_map(self.0.borrow(), |slots| slots.get(handle)).ok()
+    }
+
+    /// Returns a mutable reference to the value behind a handle
+    /// Returns None if the value has been removed, or is not of type `T`
+    ///
+    /// # Panics
+    /// Will panic if the store is already borrowed
+    #[must_use]
+    pub fn get_mut<T: 'static>(&self, handle: External<T>) -> Option<RefMut<'_, T>> {
+        RefMut::filter_map(self.0.borrow_mut(), |slots| slots.get_mut(handle)).ok()
+    }
+
+    /// Returns true if the handle still refers to a value of type `T`
+    #[must_use]
+    pub fn contains<T: 'static>(&self, handle: External<T>) -> bool {
+        self.0.borrow().get(handle).is_some()
+    }
+
+    /// Removes a value from the store, returning it
+    /// Any remaining handles to the value will no longer resolve
+    ///
+    /// Returns None if the value has already been removed, or is not of type `T`
+    ///
+    /// # Panics
+    /// Will panic if the store is already borrowed
+    #[allow(clippy::must_use_candidate)]
+    pub fn remove<T: 'static>(&self, handle: External<T>) -> Option<T> {
+        let mut slots = self.0.borrow_mut();
+        slots.get(handle)?;
+
+        let slot = &mut slots.entries[handle.index as usize];
+        let value = slot.value.take()?;
+        slot.generation = slot.generation.wrapping_add(1);
+        slots.free.push(handle.index);
+
+        value.downcast().ok().map(|value| *value)
+    }
+
+    /// Returns the number of values in the store
+    #[must_use]
+    pub fn len(&self) -> usize {
+        let slots = self.0.borrow();
+        slots.entries.len() - slots.free.len()
+    }
+
+    /// Returns true if the store contains no values
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{json_args, Module, Runtime, RuntimeOptions};
+    use deno_core::serde_json;
+
+    #[test]
+    fn test_store() {
+        let store = ExternalStore::default();
+        let a = store.insert(1u32);
+        let b = store.insert("b".to_string());
+        assert_eq!(store.len(), 2);
+        assert_eq!(*store.get(a).unwrap(), 1);
+
+        *store.get_mut(a).unwrap() = 2;
+        assert_eq!(store.remove(a), Some(2));
+        assert!(!store.contains(a));
+
+        // Slot is reused, but the old handle does not resolve
+        let c = store.insert(3u32);
+        assert_eq!(c.index(), a.index());
+        assert!(store.get(a).is_none());
+        assert_eq!(*store.get(c).unwrap(), 3);
+
+        // Wrong type
+        let forged: External<u32> =
+            serde_json::from_value(serde_json::to_value(b).unwrap()).unwrap();
+        assert!(store.get(forged).is_none());
+        assert_eq!(store.get(b).unwrap().as_str(), "b");
+
+        // Handles cannot be made from a slot index, or used with another store
+        let mut repr = serde_json::to_value(c).unwrap();
+        repr["$__rustyscript_tag"] = serde_json::json!("0000000000000000");
+        let forged: External<u32> = serde_json::from_value(repr).unwrap();
+        assert!(store.get(forged).is_none());
+
+        let other = ExternalStore::default();
+        let d = other.insert(4u32);
+        assert_eq!((d.index(), d.generation()), (0, 0));
+        assert!(store.get(d).is_none());
+        assert!(other.get(c).is_none());
+    }
+
+    #[test]
+    fn test_external_through_js() {
+        struct Big {
+            data: Vec<u8>,
+        }
+
+        let module = Module::new(
+            "test.js",
+            "
+            let stored;
+            export const store = (handle) => { stored = handle; };
+            export const measure = () => rustyscript.functions.measure(stored);
+        ",
+        );
+
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let handle = runtime.load_module(&module).unwrap();
+
+        let external = runtime
+            .create_external(Big {
+                data: vec![0; 1024],
+            })
+            .unwrap();
+
+        let store = runtime.external_store().unwrap();
+        runtime
+            .register_function("measure", move |args| {
+                let handle: External<Big> = serde_json::from_value(args[0].clone())?;
+                let big = store
+                    .get(handle)
+                    .ok_or_else(|| crate::Error::Runtime("Invalid handle".to_string()))?;
+                Ok(serde_json::json!(big.data.len()))
+            })
+            .unwrap();
+
+        runtime
+            .call_function::<crate::Undefined>(Some(&handle), "store", &(external,))
+            .unwrap();
+        let len: usize = runtime
+            .call_function(Some(&handle), "measure", json_args!())
+            .unwrap();
+        assert_eq!(len, 1024);
+    }
+}
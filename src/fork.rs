@@ -0,0 +1,57 @@
+//! Creating identical runtimes from a configured one, see [`crate::Runtime::fork`]
+use crate::ext::rustyscript::ReentrantFn;
+use crate::inner_runtime::{RsAsyncFunction, RsFunction};
+use crate::RuntimeOptions;
+use std::rc::Rc;
+
+/// Creates the options for a forkable runtime, and for each of its forks
+pub(crate) type OptionsFactory = Rc<dyn Fn() -> RuntimeOptions>;
+
+/// A registered function, kept so that it can be registered again in a fork
+#[derive(Clone)]
+pub(crate) enum FunctionDefinition {
+    Sync(Rc<dyn RsFunction>),
+    Async(Rc<dyn RsAsyncFunction>),
+}
+
+/// The functions registered with a runtime, in registration order
+/// Reentrant functions are kept apart, since they are called from their own namespace
+#[derive(Clone, Default)]
+pub(crate) struct FunctionDefinitions {
+    functions: Vec<(String, FunctionDefinition)>,
+    reentrant: Vec<(String, ReentrantFn)>,
+}
+
+impl FunctionDefinitions {
+    /// Records a function, replacing any earlier one with the same name
+    pub fn insert(&mut self, name: &str, definition: FunctionDefinition) {
+        self.functions.retain(|(n, _)| n != name);
+        self.functions.push((name.to_string(), definition));
+    }
+
+    /// Records a reentrant function, replacing any earlier one with the same name
+    pub fn insert_reentrant(&mut self, name: &str, callback: ReentrantFn) {
+        self.reentrant.retain(|(n, _)| n != name);
+        self.reentrant.push((name.to_string(), callback));
+    }
+
+    /// Forgets a function of any kind
+    pub fn remove(&mut self, name: &str) {
+        self.functions.retain(|(n, _)| n != name);
+        self.reentrant.retain(|(n, _)| n != name);
+    }
+
+    /// Iterates over the functions, in registration order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &FunctionDefinition)> {
+        self.functions
+            .iter()
+            .map(|(name, definition)| (name.as_str(), definition))
+    }
+
+    /// Iterates over the reentrant functions, in registration order
+    pub fn iter_reentrant(&self) -> impl Iterator<Item = (&str, &ReentrantFn)> {
+        self.reentrant
+            .iter()
+            .map(|(name, callback)| (name.as_str(), callback))
+    }
+}
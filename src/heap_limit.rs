@@ -0,0 +1,222 @@
+//! Per-call heap growth limits, see [`crate::CallOptions::max_heap_growth`]
+//!
+//! The heap usage is recorded before the call, and checked from a GC prologue callback while it runs
+//! Once the growth passes the limit, the near-limit callback may raise it - otherwise execution is terminated,
+//! and the termination is cancelled once the call has unwound, so the runtime stays usable
+use crate::{Error, HeapGrowthCallback, Runtime};
+use deno_core::v8;
+use std::{cell::Cell, ffi::c_void};
+
+/// State shared with the GC callback
+struct GrowthState {
+    baseline: usize,
+    limit: Cell<usize>,
+    exceeded: Cell<Option<usize>>,
+    callback: Option<HeapGrowthCallback>,
+}
+
+impl GrowthState {
+    /// Checks the current heap usage, returning false if the call must be stopped
+    fn check(&self, used: usize) -> bool {
+        let growth = used.saturating_sub(self.baseline);
+        if growth <= self.limit.get() {
+            return true;
+        }
+
+        match self
+            .callback
+            .as_ref()
+            .and_then(|cb| cb.call(growth, self.limit.get()))
+        {
+            Some(limit) => {
+                self.limit.set(limit);
+                true
+            }
+            None => {
+                self.exceeded.set(Some(growth));
+                false
+            }
+        }
+    }
+}
+
+/// Enforces a heap growth limit until dropped
+///
+/// The isolate outlives the guard, since the guard is only held while the runtime is borrowed for a call
+pub struct HeapGrowthGuard {
+    state: Box<GrowthState>,
+    isolate: *mut v8::Isolate,
+}
+
+impl HeapGrowthGuard {
+    /// Records the current heap usage, and starts enforcing the limit
+    pub fn start(
+        runtime: &mut Runtime,
+        limit: usize,
+        callback: Option<HeapGrowthCallback>,
+    ) -> Self {
+        let isolate: &mut v8::Isolate = runtime.deno_runtime().v8_isolate();
+        let state = Box::new(GrowthState {
+            baseline: used_heap_size(isolate),
+            limit: Cell::new(limit),
+            exceeded: Cell::new(None),
+            callback,
+        });
+
+        // The state is boxed, so the pointer stays valid when the guard is moved
+        let data = std::ptr::from_ref::<GrowthState>(&state).cast_mut().cast();
+        isolate.add_gc_prologue_callback(on_gc, data, v8::GCType::ALL);
+
+        Self {
+            state,
+            isolate: std::ptr::from_mut(isolate),
+        }
+    }
+
+    /// Stops enforcing the limit, replacing the result of the call if the limit was reached
+    pub fn finish<T>(self, result: Result<T, Error>) -> Result<T, Error> {
+        // Safety: see the struct docs
+        let isolate = unsafe { &mut *self.isolate };
+
+        if self.state.exceeded.get().is_some() {
+            isolate.cancel_terminate_execution();
+        } else {
+            self.state.check(used_heap_size(isolate));
+        }
+
+        match self.state.exceeded.get() {
+            Some(growth) => Err(Error::HeapGrowthExceeded {
+                growth,
+                limit: self.state.limit.get(),
+            }),
+            None => result,
+        }
+    }
+
+    fn data(&self) -> *mut c_void {
+        std::ptr::from_ref::<GrowthState>(&self.state)
+            .cast_mut()
+            .cast()
+    }
+}
+
+impl Drop for HeapGrowthGuard {
+    fn drop(&mut self) {
+        // Safety: see the struct docs
+        let isolate = unsafe { &mut *self.isolate };
+        isolate.remove_gc_prologue_callback(on_gc, self.data());
+    }
+}
+
+fn used_heap_size(isolate: &mut v8::Isolate) -> usize {
+    let mut stats = v8::HeapStatistics::default();
+    isolate.get_heap_statistics(&mut stats);
+    stats.used_heap_size()
+}
+
+/// Runs on the isolate's thread before each garbage collection
+extern "C" fn on_gc(
+    isolate: *mut v8::Isolate,
+    _gc_type: v8::GCType,
+    _flags: v8::GCCallbackFlags,
+    data: *mut c_void,
+) {
+    // Safety: the callback is removed before the state is dropped
+    let state = unsafe { &*data.cast::<GrowthState>() };
+    let isolate = unsafe { &mut *isolate };
+
+    // Only terminate once - the call is already unwinding after that
+    if state.exceeded.get().is_none() && !state.check(used_heap_size(isolate)) {
+        isolate.terminate_execution();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        json_args, CallOptions, Error, HeapGrowthCallback, Module, Runtime, RuntimeOptions,
+    };
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    const MODULE: &str = "
+        export const small = () => 1;
+        export const grow = (n) => {
+            const keep = [];
+            for (let i = 0; i < n; i++) keep.push(new Array(1024).fill(i));
+            return keep.length;
+        };
+    ";
+
+    #[test]
+    fn test_heap_growth_limit() {
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let handle = runtime
+            .load_module(&Module::new("test.js", MODULE))
+            .unwrap();
+
+        let options = CallOptions {
+            max_heap_growth: Some(4 * 1024 * 1024),
+            ..Default::default()
+        };
+
+        let value: usize = runtime
+            .call_function_with_options(Some(&handle), "small", json_args!(), &options)
+            .unwrap();
+        assert_eq!(value, 1);
+
+        let e = runtime
+            .call_function_with_options::<usize>(
+                Some(&handle),
+                "grow",
+                json_args!(10_000),
+                &options,
+            )
+            .expect_err("Heap growth limit was not enforced");
+        assert!(
+            matches!(e, Error::HeapGrowthExceeded { limit, .. } if limit == 4 * 1024 * 1024),
+            "{e}"
+        );
+
+        // The runtime is still usable
+        let value: usize = runtime
+            .call_function(Some(&handle), "grow", json_args!(10))
+            .unwrap();
+        assert_eq!(value, 10);
+    }
+
+    #[test]
+    fn test_heap_growth_callback() {
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let handle = runtime
+            .load_module(&Module::new("test.js", MODULE))
+            .unwrap();
+
+        // Allow the call to double its limit once
+        let calls = Arc::new(AtomicUsize::new(0));
+        let calls_ = calls.clone();
+        let options = CallOptions {
+            max_heap_growth: Some(4 * 1024 * 1024),
+            on_heap_growth_limit: Some(HeapGrowthCallback::new(move |_, limit| {
+                (calls_.fetch_add(1, Ordering::SeqCst) == 0).then_some(limit * 2)
+            })),
+            ..Default::default()
+        };
+
+        let e = runtime
+            .call_function_with_options::<usize>(
+                Some(&handle),
+                "grow",
+                json_args!(10_000),
+                &options,
+            )
+            .expect_err("Heap growth limit was not enforced");
+        assert!(
+            matches!(e, Error::HeapGrowthExceeded { limit, .. } if limit == 8 * 1024 * 1024),
+            "{e}"
+        );
+        assert!(calls.load(Ordering::SeqCst) >= 2);
+    }
+}
@@ -1,24 +1,29 @@
 use crate::{
+    async_bridge::HeapExhaustedToken,
     ext,
+    fork::{FunctionDefinition, FunctionDefinitions},
     module_loader::{LoaderOptions, RustyLoader},
     traits::{ToDefinedValue, ToModuleSpecifier, ToV8String},
-    transpiler::transpile,
     utilities, Error, ExtensionOptions, Module, ModuleHandle,
 };
+use deno_core::error::{AnyError, JsError};
+use deno_core::stats::{RuntimeActivityStats, RuntimeActivityStatsFilter};
 use deno_core::{
-    futures::FutureExt, serde_json, serde_v8::from_v8, v8, FeatureChecker, JsRuntime,
-    JsRuntimeForSnapshot, PollEventLoopOptions,
+    futures::FutureExt, serde_json, v8, FeatureChecker, JsRuntime, JsRuntimeForSnapshot, ModuleId,
+    ModuleSpecifier, PollEventLoopOptions, SourceMapData,
 };
 use serde::de::DeserializeOwned;
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{hash_map::Entry, HashMap, HashSet, VecDeque},
     path::{Path, PathBuf},
     pin::Pin,
     rc::Rc,
     task::Poll,
-    time::Duration,
+    time::{Duration, Instant},
 };
-use tokio_util::sync::CancellationToken;
+
+/// The number of event loop errors kept until they are taken - older errors are discarded first
+const MAX_EVENT_LOOP_ERRORS: usize = 256;
 
 /// Wrapper trait to make the `InnerRuntime` generic over the runtime types
 pub trait RuntimeTrait {
@@ -78,14 +83,55 @@ impl<F> RsAsyncFunction for F where
 {
 }
 
+/// Context given to cancellable async functions
+/// See [`crate::Runtime::register_cancellable_function`]
+#[derive(Clone, Debug)]
+pub struct CallbackContext {
+    handle: tokio::runtime::Handle,
+    cancellation_token: tokio_util::sync::CancellationToken,
+}
+
+impl CallbackContext {
+    pub(crate) fn new(
+        handle: tokio::runtime::Handle,
+        cancellation_token: tokio_util::sync::CancellationToken,
+    ) -> Self {
+        Self {
+            handle,
+            cancellation_token,
+        }
+    }
+
+    /// Returns a handle to the tokio runtime the callback is running on
+    /// Can be used to spawn additional host work
+    #[must_use]
+    pub fn handle(&self) -> &tokio::runtime::Handle {
+        &self.handle
+    }
+
+    /// Returns a token that is cancelled when the blocking call running the script times out or exhausts its heap,
+    /// or when the runtime is dropped - see [`crate::Runtime::register_cancellable_function`]
+    #[must_use]
+    pub fn cancellation_token(&self) -> &tokio_util::sync::CancellationToken {
+        &self.cancellation_token
+    }
+
+    /// Returns true if the callback has been cancelled
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation_token.is_cancelled()
+    }
+}
+
 /// Decodes a set of arguments into a vector of v8 values
 /// This is used to pass arguments to a javascript function
 /// And is faster and more flexible than using `json_args!`
-fn decode_args<'a>(
+pub(crate) fn decode_args<'a>(
     args: &impl serde::ser::Serialize,
     scope: &mut v8::HandleScope<'a>,
 ) -> Result<Vec<v8::Local<'a, v8::Value>>, Error> {
     let args = deno_core::serde_v8::to_v8(scope, args)?;
+    let args = crate::js_value::TypeHooks::from_scope(scope).encode(scope, args)?;
     match v8::Local::<v8::Array>::try_from(args) {
         Ok(args) => {
             let len = args.length();
@@ -111,7 +157,72 @@ fn decode_args<'a>(
     }
 }
 
+/// Converts the exception caught while calling a function into an error, prefixed by its location
+pub(crate) fn caught_error(
+    scope: &mut v8::TryCatch<v8::HandleScope>,
+    module_context: Option<&ModuleHandle>,
+) -> Error {
+    let Some(e) = scope.message() else {
+        return Error::Runtime("Unknown error".to_string());
+    };
+
+    let filename = e.get_script_resource_name(scope);
+    let linenumber = e.get_line_number(scope).unwrap_or_default();
+    let filename = if let Some(v) = filename {
+        let filename = v.to_rust_string_lossy(scope);
+        format!("{filename}:{linenumber}: ")
+    } else if let Some(module_context) = module_context {
+        let filename = module_context.module().filename().to_string_lossy();
+        format!("{filename}:{linenumber}: ")
+    } else {
+        String::new()
+    };
+
+    let msg = e.get(scope).to_rust_string_lossy(scope);
+    Error::Runtime(format!("{filename}{msg}"))
+}
+
+/// Runs a single entry of a batch, returning the value found, or returned by the call
+fn run_batch_entry<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    global: v8::Local<'s, v8::Object>,
+    namespace: Option<v8::Local<'s, v8::Object>>,
+    entry: &crate::batch::BatchEntry,
+) -> Result<v8::Global<v8::Value>, Error> {
+    let name = entry.name();
+    let key = name.to_v8_string(scope)?;
+    let value = namespace
+        .and_then(|namespace| namespace.get(scope, key.into()).if_defined())
+        .or_else(|| global.get(scope, key.into()).if_defined())
+        .ok_or_else(|| Error::ValueNotFound(name.to_string()))?;
+
+    let crate::batch::BatchEntry::Call { args, module, .. } = entry else {
+        return Ok(v8::Global::new(scope, value));
+    };
+
+    let function: v8::Local<v8::Function> = value
+        .try_into()
+        .map_err(|_| Error::ValueNotCallable(name.to_string()))?;
+    let args = args.clone()?;
+
+    let mut scope = v8::TryCatch::new(scope);
+    let args = decode_args(&args, &mut scope)?;
+    let receiver = match namespace {
+        Some(namespace) => namespace.into(),
+        None => v8::undefined(&mut scope).into(),
+    };
+
+    match function.call(&mut scope, receiver, &args) {
+        Some(value) => Ok(v8::Global::new(&mut scope, value)),
+        None if scope.has_caught() => Err(caught_error(&mut scope, module.as_ref())),
+        None => Err(Error::Runtime(
+            "Unknown error during function execution".to_string(),
+        )),
+    }
+}
+
 /// Represents the set of options accepted by the runtime constructor
+#[allow(clippy::struct_excessive_bools)]
 pub struct RuntimeOptions {
     /// A set of `deno_core` extensions to add to the runtime
     pub extensions: Vec<deno_core::Extension>,
@@ -125,6 +236,31 @@ pub struct RuntimeOptions {
     /// Amount of time to run for before killing the thread
     pub timeout: Duration,
 
+    /// Optional interval at which to check whether a call has exceeded the timeout, while running javascript
+    ///
+    /// Without this, the timeout is only enforced when scripts yield to the event loop,
+    /// so a synchronous loop such as `while (true) {}` can run forever
+    ///
+    /// When set, a background thread interrupts the isolate at the next function call or loop iteration
+    /// once the deadline has passed - so the timeout is enforced to within about one interval
+    /// Shorter intervals are more precise, at the cost of more frequent wakeups - the interval must be greater than zero
+    ///
+    /// Only blocking calls are preempted - those made through the methods without an `_async` or `_immediate` suffix
+    /// The futures returned by those variants are driven by the caller's executor, so a synchronous loop they run is not interrupted
+    ///
+    /// Default: `None`
+    pub preemption_interval: Option<Duration>,
+
+    /// Optional limit on how long a module's top-level await can go without making progress, while the module is loaded
+    ///
+    /// Progress is any async op starting or completing, or a timer firing - a module waiting on a timer
+    /// that will not fire within the limit is considered stalled  
+    /// Once the limit is reached, loading fails with [`crate::Error::ModuleStalled`], listing the work still pending,
+    /// instead of waiting for [`RuntimeOptions::timeout`]
+    ///
+    /// Default: `None`
+    pub module_stall_timeout: Option<Duration>,
+
     /// Optional maximum heap size for the runtime
     pub max_heap_size: Option<usize>,
 
@@ -135,6 +271,65 @@ pub struct RuntimeOptions {
     /// Optional import provider for the module loader
     pub import_provider: Option<Box<dyn crate::module_loader::ImportProvider>>,
 
+    /// Optional transformation applied to the code of every javascript module loaded by the runtime
+    /// See [`crate::module_loader::SourceTransform`]
+    pub source_transform: Option<std::sync::Arc<dyn crate::module_loader::SourceTransform>>,
+
+    /// Measure the execution cost of calls, for billing or fairness
+    ///
+    /// Every module loaded by the runtime is instrumented to count function calls and loop iterations
+    /// The resulting cost is an abstract unit of work, which is the same for a given call on any host
+    /// It can be read with [`crate::Runtime::execution_cost`], and capped with [`crate::CallOptions::max_cost`]
+    ///
+    /// Code passed to [`crate::Runtime::eval`] is not instrumented, though functions it calls are
+    /// Instrumentation is applied after [`RuntimeOptions::source_transform`], and slows down scripts slightly
+    ///
+    /// Default: `false`
+    pub metering: bool,
+
+    /// Record the stack trace where each async op and timer is created
+    ///
+    /// The traces are included in [`crate::Runtime::pending_work`], to find the code keeping the event loop alive  
+    /// Capturing a trace for every op slows down async code, so this is intended for debugging
+    ///
+    /// Default: `false`
+    pub trace_pending_work: bool,
+
+    /// Freeze the built-in prototypes and constructors, such as `Object.prototype` and `Array`, before any user code runs
+    ///
+    /// Prevents prototype-pollution between untrusted scripts sharing the runtime - changes made to
+    /// built-ins by one script are silently ignored, or throw in strict mode code such as modules  
+    /// Commonly overridden properties, like `toString`, can still be assigned on individual objects
+    ///
+    /// Globals, and objects provided by extensions, are left untouched
+    ///
+    /// Default: `false`
+    pub harden: bool,
+
+    /// Disallow generating code at runtime from within loaded scripts
+    ///
+    /// `eval`, `new Function` and dynamic `import()` will throw catchable errors instead of running code  
+    /// Static imports, and code passed to [`crate::Runtime::eval`] or loaded from rust, are unaffected
+    ///
+    /// Useful where all code run by the runtime must come from a known source
+    ///
+    /// Default: `false`
+    pub disallow_dynamic_code: bool,
+
+    /// Optional policy controlling where modules may be imported from, and whether dynamic imports and workers are allowed
+    ///
+    /// Checked by the module loader in addition to options such as [`RuntimeOptions::schema_whlist`]  
+    /// See [`crate::module_loader::OriginPolicy`]
+    ///
+    /// Default: `None`
+    pub origin_policy: Option<crate::module_loader::OriginPolicy>,
+
+    /// Options controlling how typescript, JSX and TSX modules are transpiled
+    ///
+    /// Allows decorators to be transformed, and source map emission to be configured  
+    /// See [`crate::TranspilerOptions`]
+    pub transpiler_options: crate::TranspilerOptions,
+
     /// Optional snapshot to load into the runtime
     ///
     /// This will reduce load times, but requires the same extensions to be loaded as when the snapshot was created  
@@ -159,6 +354,96 @@ pub struct RuntimeOptions {
     ///
     /// By default only `http`/`https` (`url_import` crate feature), and `file` (`fs_import` crate feature) are allowed
     pub schema_whlist: HashSet<String>,
+
+    /// How integers outside of javascript's safe integer range are handled when decoding values
+    ///
+    /// By default they are decoded as-is, possibly losing precision
+    pub number_policy: crate::js_value::NumberPolicy,
+
+    /// How circular references are handled when decoding values
+    ///
+    /// By default an error is returned identifying the cyclic path
+    pub cycle_policy: crate::js_value::CyclePolicy,
+
+    /// The deepest a value can be nested when decoded, unless the cycle policy is `Unchecked`
+    ///
+    /// Deeper values fail with [`crate::Error::DecodeDepthExceeded`], and are only then walked for circular references
+    /// Defaults to 256
+    pub max_decode_depth: usize,
+
+    /// The maximum number of items waiting in the queue filled by `rustyscript.queue.push`
+    ///
+    /// Once the queue is full, `push` waits until an item is received - see [`crate::Runtime::queue_receiver`]
+    /// Defaults to 128
+    pub queue_capacity: usize,
+
+    /// The maximum number of records kept from `host.log`, until they are read with [`crate::Runtime::drain_logs`]
+    ///
+    /// Once full, the oldest record is discarded for each new one - 0 discards every record
+    /// Defaults to 1024
+    pub log_capacity: usize,
+
+    /// The maximum number of results kept by [`crate::Runtime::call_cached`]
+    ///
+    /// Once full, the least recently used result is discarded for each new one - 0 disables caching
+    /// Defaults to 256
+    pub call_cache_capacity: usize,
+
+    /// Records every crossing between the host and javascript, or replays a recorded execution
+    ///
+    /// Recorded traces are retrieved with [`crate::Runtime::take_trace`], and can be replayed
+    /// against a fresh runtime driven the same way, to reproduce nondeterministic failures
+    /// See [`crate::TraceMode`]
+    pub trace: crate::TraceMode,
+
+    /// What happens when a script calls `Deno.exit` or `process.exit` (`node_experimental` crate feature)
+    ///
+    /// Without `node_experimental`, scripts have no way to exit, and this has no effect
+    /// By default only the runtime is terminated, and calls fail with [`crate::Error::ScriptExited`]
+    /// See [`crate::ExitPolicy`]
+    pub exit_policy: crate::ExitPolicy,
+
+    /// Optional virtual base url for modules loaded from rust, such as `https://app.example/scripts/`
+    ///
+    /// Relative filenames, and the imports made from those modules, resolve under this url instead of the current directory
+    /// Keeps host paths out of error messages and `import.meta.url`, which then read the same on every machine
+    /// Modules under the base url are never fetched - they must be loaded or registered from rust
+    pub module_base_url: Option<ModuleSpecifier>,
+
+    /// Host directories shown to scripts as virtual urls, in stack traces, error messages and `import.meta.url`
+    /// See [`crate::module_loader::PathRedactions`]
+    pub path_redactions: crate::module_loader::PathRedactions,
+
+    /// The maximum number of remote modules fetched at once when resolving imports (`url_import` crate feature)
+    ///
+    /// Independent imports are fetched concurrently, up to this limit - 0 removes the limit
+    /// Defaults to 16
+    pub max_concurrent_fetches: usize,
+
+    /// Observers notified as modules are loaded and functions are called, for logging, metrics, or billing
+    /// See [`crate::RuntimeObserver`]
+    pub observers: Vec<Box<dyn crate::RuntimeObserver>>,
+
+    /// Optional HTTP client used to fetch remote modules (`url_import` crate feature)
+    ///
+    /// Allows proxies, timeouts, default headers such as authorization, and custom TLS roots
+    /// to be configured for module fetching - see [`reqwest::ClientBuilder`]
+    ///
+    /// By default a client with reqwest's default configuration is used, except that it does not follow redirects
+    /// The loader follows them itself, so a custom client should be built with [`reqwest::redirect::Policy::none`] -
+    /// otherwise headers from [`RuntimeOptions::module_fetch_credentials`] can be sent to the host being redirected to
+    #[cfg(feature = "url_import")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "url_import")))]
+    pub module_fetch_client: Option<reqwest::Client>,
+
+    /// Optional source of credentials, such as authorization headers, for remote module fetches (`url_import` crate feature)
+    ///
+    /// Called before each fetch, so secrets do not need to be stored in the options
+    /// See [`crate::module_loader::FetchCredentials`]
+    #[cfg(feature = "url_import")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "url_import")))]
+    pub module_fetch_credentials:
+        Option<std::sync::Arc<dyn crate::module_loader::FetchCredentials>>,
 }
 
 impl Default for RuntimeOptions {
@@ -167,13 +452,39 @@ impl Default for RuntimeOptions {
             extensions: Vec::default(),
             default_entrypoint: None,
             timeout: Duration::MAX,
+            preemption_interval: None,
+            module_stall_timeout: None,
             max_heap_size: None,
             module_cache: None,
             import_provider: None,
+            source_transform: None,
+            metering: false,
+            trace_pending_work: false,
+            harden: false,
+            disallow_dynamic_code: false,
+            origin_policy: None,
+            transpiler_options: crate::TranspilerOptions::default(),
             startup_snapshot: None,
             isolate_params: None,
             shared_array_buffer_store: None,
             schema_whlist: HashSet::default(),
+            number_policy: crate::js_value::NumberPolicy::default(),
+            cycle_policy: crate::js_value::CyclePolicy::default(),
+            max_decode_depth: 256,
+            queue_capacity: 128,
+            log_capacity: 1024,
+            call_cache_capacity: 256,
+            trace: crate::TraceMode::Off,
+            exit_policy: crate::ExitPolicy::default(),
+            module_base_url: None,
+            path_redactions: crate::module_loader::PathRedactions::default(),
+            max_concurrent_fetches: 16,
+            observers: Vec::new(),
+
+            #[cfg(feature = "url_import")]
+            module_fetch_client: None,
+            #[cfg(feature = "url_import")]
+            module_fetch_credentials: None,
 
             extension_options: ExtensionOptions::default(),
         }
@@ -191,19 +502,80 @@ pub struct InnerRuntime<RT: RuntimeTrait> {
     pub deno_runtime: RT,
 
     pub cwd: PathBuf,
+    pub base_url: Option<ModuleSpecifier>,
+    pub path_redactions: crate::module_loader::PathRedactions,
     pub default_entrypoint: Option<String>,
+
+    /// Directories where the extensions persist data, with their labels
+    pub storage_dirs: Vec<(&'static str, PathBuf)>,
+
+    /// True if loaded modules are instrumented to measure their execution cost
+    pub metering: bool,
+
+    /// How long a module's top-level await can go without making progress
+    pub module_stall_timeout: Option<Duration>,
+
+    /// Handles of the modules loaded so far, in load order
+    /// Only the latest handle of each module is kept, so this is bounded by the number of distinct modules
+    pub loaded_modules: Vec<(ModuleSpecifier, ModuleHandle)>,
+
+    /// Notified as modules are loaded and functions are called
+    pub observers: crate::observer::Observers,
+
+    /// The sync and async functions registered so far, see [`crate::Runtime::fork`]
+    pub function_definitions: FunctionDefinitions,
+
+    /// Errors raised by the event loop, until taken with [`crate::Runtime::take_event_loop_errors`]
+    pub event_loop_errors: VecDeque<JsError>,
+
+    /// Called with each error raised by the event loop
+    pub event_loop_error_hook: Option<Box<dyn Fn(&JsError)>>,
+
+    /// Names prepared so far, see [`crate::Runtime::prepare_name`]
+    pub prepared_names: HashMap<&'static str, crate::PreparedName>,
+
+    /// Results of calls to pure functions, see [`crate::Runtime::call_cached`]
+    pub call_cache: crate::call_cache::CallCache,
 }
 impl<RT: RuntimeTrait> InnerRuntime<RT> {
+    #[allow(clippy::too_many_lines)]
     pub fn new(
         options: RuntimeOptions,
-        heap_exhausted_token: CancellationToken,
+        heap_exhausted_token: HeapExhaustedToken,
     ) -> Result<Self, Error> {
         let cwd = std::env::current_dir()?;
+
+        // The base url is a directory, so relative filenames must be joined under it
+        let base_url = options.module_base_url.map(|mut url| {
+            if !url.path().ends_with('/') {
+                url.set_path(&format!("{}/", url.path()));
+            }
+            url
+        });
+
         let module_loader = Rc::new(RustyLoader::new(LoaderOptions {
             cache_provider: options.module_cache,
             import_provider: options.import_provider,
             schema_whlist: options.schema_whlist,
+            source_transform: if options.metering {
+                Some(std::sync::Arc::new(crate::metering::MeteringTransform {
+                    inner: options.source_transform,
+                }))
+            } else {
+                options.source_transform
+            },
+            max_concurrent_fetches: options.max_concurrent_fetches,
+            disallow_dynamic_imports: options.disallow_dynamic_code,
+            origin_policy: options.origin_policy.clone(),
+            transpiler_options: options.transpiler_options,
             cwd: cwd.clone(),
+            base_url: base_url.clone(),
+            path_redactions: options.path_redactions.clone(),
+
+            #[cfg(feature = "url_import")]
+            http_client: options.module_fetch_client,
+            #[cfg(feature = "url_import")]
+            fetch_credentials: options.module_fetch_credentials,
 
             #[cfg(feature = "node_experimental")]
             node_resolver: options.extension_options.node_resolver.clone(),
@@ -218,12 +590,19 @@ impl<RT: RuntimeTrait> InnerRuntime<RT> {
             deno_telemetry::init(otel_conf)?;
         }
 
+        let storage_dirs = crate::state_archive::storage_dirs(&options.extension_options);
+        let metering = options.metering;
+        let module_stall_timeout = options.module_stall_timeout;
+        let call_cache = crate::call_cache::CallCache::new(options.call_cache_capacity);
+        let observers = crate::observer::Observers::new(options.observers);
+
         // If a snapshot is provided, do not reload ESM for extensions
         let is_snapshot = options.startup_snapshot.is_some();
         let extensions = ext::all_extensions(
             options.extensions,
             options.extension_options,
             options.shared_array_buffer_store.clone(),
+            options.origin_policy,
             is_snapshot,
         );
 
@@ -251,10 +630,12 @@ impl<RT: RuntimeTrait> InnerRuntime<RT> {
 
         let mut deno_runtime = RT::try_new(deno_core::RuntimeOptions {
             module_loader: Some(module_loader.clone()),
+            import_meta_resolve_callback: Some(module_loader.as_import_meta_resolver()),
 
             feature_checker: Some(feature_checker.into()),
 
             extension_transpiler: Some(module_loader.as_extension_transpiler()),
+            get_error_class_fn: Some(&crate::ext::rustyscript::error_class_name),
             create_params: isolate_params,
             shared_array_buffer_store: options.shared_array_buffer_store.clone(),
 
@@ -265,7 +646,7 @@ impl<RT: RuntimeTrait> InnerRuntime<RT> {
         })?;
 
         // Add a callback to terminate the runtime if the max_heap_size limit is approached
-        if options.max_heap_size.is_some() {
+        if let Some(max_heap_size) = options.max_heap_size {
             let isolate_handle = deno_runtime.rt_mut().v8_isolate().thread_safe_handle();
 
             deno_runtime
@@ -274,7 +655,7 @@ impl<RT: RuntimeTrait> InnerRuntime<RT> {
                     isolate_handle.terminate_execution();
 
                     // Signal the outer runtime to cancel block_on future (avoid hanging) and return friendly error
-                    heap_exhausted_token.cancel();
+                    heap_exhausted_token.cancel(current_value, max_heap_size);
 
                     // Spike the heap limit while terminating to avoid segfaulting
                     // Callback may fire multiple times if memory usage increases quicker then termination finalizes
@@ -282,12 +663,88 @@ impl<RT: RuntimeTrait> InnerRuntime<RT> {
                 });
         }
 
+        // Used when decoding values returned from the runtime
+        {
+            let state = deno_runtime.rt_mut().op_state();
+            let mut state = state.borrow_mut();
+            state.put(options.number_policy);
+            state.put(options.cycle_policy);
+            state.put(crate::js_value::MaxDecodeDepth(options.max_decode_depth));
+        }
+
+        // Queue filled by `rustyscript.queue.push`
+        {
+            let (sender, slot) = crate::ext::rustyscript::queue_channel(options.queue_capacity);
+            let state = deno_runtime.rt_mut().op_state();
+            let mut state = state.borrow_mut();
+            state.put(sender);
+            state.put(slot);
+        }
+
+        // Replaces the process exit behind `Deno.exit`
+        {
+            let isolate = deno_runtime.rt_mut().v8_isolate().thread_safe_handle();
+            let state = deno_runtime.rt_mut().op_state();
+            state.borrow_mut().put(crate::ext::rustyscript::ExitState {
+                policy: options.exit_policy,
+                isolate,
+                exit: crate::ext::rustyscript::ScriptExit::default(),
+            });
+        }
+
+        // Records written with `host.log`
+        {
+            let state = deno_runtime.rt_mut().op_state();
+            let mut state = state.borrow_mut();
+            state.put(crate::ext::rustyscript::LogBuffer::new(
+                options.log_capacity,
+            ));
+        }
+
+        // Execution trace, installed before the runtime is locked down
+        if let Some(trace) = crate::ext::rustyscript::TraceState::new(options.trace) {
+            deno_runtime.rt_mut().op_state().borrow_mut().put(trace);
+            crate::ext::rustyscript::TraceState::install(deno_runtime.rt_mut())?;
+        }
+
+        if module_stall_timeout.is_some() {
+            crate::ext::rustyscript::TimerFirings::install(deno_runtime.rt_mut())?;
+        }
+
+        if options.trace_pending_work {
+            deno_runtime.rt_mut().execute_script(
+                "ext:rustyscript/trace_pending_work.js",
+                "Deno.core.setLeakTracingEnabled(true);",
+            )?;
+        }
+
+        // Extensions are initialized by now - lock the runtime down before user code can run
+        #[cfg(feature = "web")]
+        crate::ext::web::restrict(deno_runtime.rt_mut())?;
+        crate::ext::rustyscript::restrict(
+            deno_runtime.rt_mut(),
+            options.harden,
+            options.disallow_dynamic_code,
+        )?;
+
         let default_entrypoint = options.default_entrypoint;
         Ok(Self {
             module_loader,
             deno_runtime,
             cwd,
+            base_url,
+            path_redactions: options.path_redactions,
             default_entrypoint,
+            storage_dirs,
+            metering,
+            module_stall_timeout,
+            loaded_modules: Vec::new(),
+            function_definitions: FunctionDefinitions::default(),
+            event_loop_errors: VecDeque::new(),
+            event_loop_error_hook: None,
+            prepared_names: HashMap::new(),
+            call_cache,
+            observers,
         })
     }
 
@@ -319,6 +776,23 @@ impl<RT: RuntimeTrait> InnerRuntime<RT> {
         &self.cwd
     }
 
+    /// Converts the filename of a module loaded from rust into its specifier
+    /// Relative filenames resolve under the base url if one is set, or the current directory otherwise
+    /// Files in redacted directories are given their virtual url
+    pub fn module_specifier(&self, filename: &Path) -> Result<ModuleSpecifier, Error> {
+        let specifier = match &self.base_url {
+            Some(base) if filename.is_relative() => {
+                let path = filename.to_string_lossy().replace('\\', "/");
+                base.join(&path)?
+            }
+            _ => filename.to_module_specifier(&self.cwd)?,
+        };
+        Ok(self
+            .path_redactions
+            .to_virtual(&specifier)
+            .unwrap_or(specifier))
+    }
+
     /// Remove and return a value from the state
     pub fn take<T>(&mut self) -> Option<T>
     where
@@ -354,21 +828,53 @@ impl<RT: RuntimeTrait> InnerRuntime<RT> {
     where
         F: RsAsyncFunction,
     {
+        self.define_function(name, FunctionDefinition::Async(Rc::new(callback)))
+    }
+
+    /// Register a rust function reporting progress
+    /// Called from JS using `rustyscript.progress_functions`
+    pub fn register_progress_function(
+        &mut self,
+        name: &str,
+        callback: crate::ext::rustyscript::ProgressFn,
+    ) -> Result<(), Error> {
         let state = self.deno_runtime().op_state();
         let mut state = state.try_borrow_mut()?;
 
-        if !state.has::<HashMap<String, Box<dyn RsAsyncFunction>>>() {
-            state.put(HashMap::<String, Box<dyn RsAsyncFunction>>::new());
+        if !state.has::<crate::ext::rustyscript::ProgressFnCache>() {
+            state.put(crate::ext::rustyscript::ProgressFnCache::new());
         }
 
         // Insert the callback into the state
         state
-            .borrow_mut::<HashMap<String, Box<dyn RsAsyncFunction>>>()
-            .insert(name.to_string(), Box::new(callback));
+            .borrow_mut::<crate::ext::rustyscript::ProgressFnCache>()
+            .insert(name.to_string(), callback);
 
         Ok(())
     }
 
+    /// Register a rust function that can call back into the runtime, and record it so it can be registered again in a fork
+    /// Called from JS using `rustyscript.reentrant_functions`
+    pub fn register_reentrant_function(
+        &mut self,
+        name: &str,
+        callback: crate::ext::rustyscript::ReentrantFn,
+    ) -> Result<(), Error> {
+        let state = self.deno_runtime().op_state();
+        let mut state = state.try_borrow_mut()?;
+
+        if !state.has::<crate::ext::rustyscript::ReentrantFnCache>() {
+            state.put(crate::ext::rustyscript::ReentrantFnCache::new());
+        }
+
+        state
+            .borrow_mut::<crate::ext::rustyscript::ReentrantFnCache>()
+            .insert(name.to_string(), callback.clone());
+
+        self.function_definitions.insert_reentrant(name, callback);
+        Ok(())
+    }
+
     /// Register a rust function
     /// The function must return a `serde_json::Value`
     /// and accept a slice of `serde_json::Value` as arguments
@@ -376,35 +882,223 @@ impl<RT: RuntimeTrait> InnerRuntime<RT> {
     where
         F: RsFunction,
     {
+        self.define_function(name, FunctionDefinition::Sync(Rc::new(callback)))
+    }
+
+    /// Registers a sync or async function, and records it so it can be registered again in a fork
+    pub fn define_function(
+        &mut self,
+        name: &str,
+        definition: FunctionDefinition,
+    ) -> Result<(), Error> {
         let state = self.deno_runtime().op_state();
         let mut state = state.try_borrow_mut()?;
 
         if !state.has::<HashMap<String, Box<dyn RsFunction>>>() {
             state.put(HashMap::<String, Box<dyn RsFunction>>::new());
         }
+        if !state.has::<HashMap<String, Box<dyn RsAsyncFunction>>>() {
+            state.put(HashMap::<String, Box<dyn RsAsyncFunction>>::new());
+        }
 
-        // Insert the callback into the state
-        state
-            .borrow_mut::<HashMap<String, Box<dyn RsFunction>>>()
-            .insert(name.to_string(), Box::new(callback));
+        // Insert the callback into the state, replacing any function of either kind with that name
+        let sync = state.borrow_mut::<HashMap<String, Box<dyn RsFunction>>>();
+        sync.remove(name);
+        if let FunctionDefinition::Sync(callback) = &definition {
+            let callback = callback.clone();
+            sync.insert(
+                name.to_string(),
+                Box::new(move |args: &[serde_json::Value]| callback(args)),
+            );
+        }
+
+        let r#async = state.borrow_mut::<HashMap<String, Box<dyn RsAsyncFunction>>>();
+        r#async.remove(name);
+        if let FunctionDefinition::Async(callback) = &definition {
+            let callback = callback.clone();
+            r#async.insert(
+                name.to_string(),
+                Box::new(move |args: Vec<serde_json::Value>| callback(args)),
+            );
+        }
+
+        self.function_definitions.insert(name, definition);
+        Ok(())
+    }
+
+    /// Removes a registered function of any kind
+    /// Returns true if a function was removed
+    pub fn unregister_function(&mut self, name: &str) -> Result<bool, Error> {
+        self.function_definitions.remove(name);
+        let state = self.deno_runtime().op_state();
+        let mut state = state.try_borrow_mut()?;
+
+        let mut removed = false;
+        if let Some(table) = state.try_borrow_mut::<HashMap<String, Box<dyn RsFunction>>>() {
+            removed |= table.remove(name).is_some();
+        }
+        if let Some(table) = state.try_borrow_mut::<HashMap<String, Box<dyn RsAsyncFunction>>>() {
+            removed |= table.remove(name).is_some();
+        }
+        if let Some(table) = state.try_borrow_mut::<crate::ext::rustyscript::ProgressFnCache>() {
+            removed |= table.remove(name).is_some();
+        }
+        if let Some(table) = state.try_borrow_mut::<crate::ext::rustyscript::ReentrantFnCache>() {
+            removed |= table.remove(name).is_some();
+        }
+
+        Ok(removed)
+    }
+
+    /// Returns the names of the registered functions of every kind, sorted
+    pub fn list_functions(&mut self) -> Result<Vec<String>, Error> {
+        let state = self.deno_runtime().op_state();
+        let state = state.try_borrow_mut()?;
+
+        let sync = state.try_borrow::<HashMap<String, Box<dyn RsFunction>>>();
+        let r#async = state.try_borrow::<HashMap<String, Box<dyn RsAsyncFunction>>>();
+        let progress = state.try_borrow::<crate::ext::rustyscript::ProgressFnCache>();
+        let reentrant = state.try_borrow::<crate::ext::rustyscript::ReentrantFnCache>();
+
+        let mut names: Vec<String> = sync
+            .into_iter()
+            .flat_map(HashMap::keys)
+            .chain(r#async.into_iter().flat_map(HashMap::keys))
+            .chain(progress.into_iter().flat_map(HashMap::keys))
+            .chain(reentrant.into_iter().flat_map(HashMap::keys))
+            .cloned()
+            .collect();
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+
+    /// Registers a module named `host:{namespace}`, exporting the functions registered in the namespace
+    pub fn expose_namespace(&mut self, namespace: &str) -> Result<(), Error> {
+        let name = format!("host:{namespace}");
+        let specifier = ModuleSpecifier::parse(&name)
+            .map_err(|e| Error::Runtime(format!("invalid namespace `{namespace}`: {e}")))?;
+
+        let functions = {
+            let state = self.deno_runtime().op_state();
+            let state = state.try_borrow_mut()?;
+            crate::ext::rustyscript::namespace_functions(&state, namespace)
+        };
+        if functions.is_empty() {
+            return Err(Error::Runtime(format!(
+                "no functions are registered in namespace `{namespace}`"
+            )));
+        }
 
+        let exports = functions
+            .iter()
+            .enumerate()
+            .map(|(i, (function, _))| {
+                let function = serde_json::to_string(function)?;
+                Ok(format!(
+                    "const f{i} = api[{function}];\nexport {{ f{i} as {function} }};"
+                ))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let source = format!(
+            "const api = rustyscript.api[{}];\n{}",
+            serde_json::to_string(namespace)?,
+            exports.join("\n")
+        );
+
+        self.module_loader.add_lazy_module(&specifier, source);
+        self.module_loader.add_alias(&name, specifier);
         Ok(())
     }
 
+    pub fn external_store(&mut self) -> Result<crate::ExternalStore, Error> {
+        let state = self.deno_runtime().op_state();
+        let mut state = state.try_borrow_mut()?;
+
+        if !state.has::<crate::ExternalStore>() {
+            state.put(crate::ExternalStore::default());
+        }
+
+        Ok(state.borrow::<crate::ExternalStore>().clone())
+    }
+
+    pub fn queue_receiver(&mut self) -> Result<crate::QueueReceiver, Error> {
+        let state = self.deno_runtime().op_state();
+        let mut state = state.try_borrow_mut()?;
+        state
+            .try_borrow_mut::<crate::ext::rustyscript::QueueSlot>()
+            .and_then(|slot| slot.0.take())
+            .ok_or_else(|| Error::Runtime("The queue receiver has already been taken".to_string()))
+    }
+
+    pub fn drain_logs(&mut self) -> Result<Vec<crate::LogRecord>, Error> {
+        let state = self.deno_runtime().op_state();
+        let mut state = state.try_borrow_mut()?;
+        Ok(state
+            .try_borrow_mut::<crate::ext::rustyscript::LogBuffer>()
+            .map(crate::ext::rustyscript::LogBuffer::drain)
+            .unwrap_or_default())
+    }
+
+    pub fn take_trace(&mut self) -> Result<crate::ExecutionTrace, Error> {
+        let state = self.deno_runtime().op_state();
+        let state = state.try_borrow_mut()?;
+        state
+            .try_borrow::<crate::ext::rustyscript::TraceState>()
+            .map(crate::ext::rustyscript::TraceState::take)
+            .ok_or_else(|| Error::Runtime("Tracing is not enabled for this runtime".to_string()))
+    }
+
+    pub fn register_type_hook<T: 'static>(
+        &mut self,
+        hook: &crate::js_value::TypeHook,
+    ) -> Result<(), Error> {
+        let state = self.deno_runtime().op_state();
+        let mut hooks = state
+            .try_borrow_mut()?
+            .try_take::<crate::js_value::TypeHooks>()
+            .unwrap_or_default();
+
+        let result = {
+            let mut scope = self.deno_runtime().handle_scope();
+            hooks.register::<T>(&mut scope, hook)
+        };
+
+        state.try_borrow_mut()?.put(hooks);
+        result
+    }
+
+    /// Records an error raised by the event loop, and passes it on
+    /// Only javascript errors are recorded - see [`crate::Runtime::take_event_loop_errors`]
+    pub fn event_loop_error(&mut self, e: AnyError) -> AnyError {
+        if let Some(js_error) = e.downcast_ref::<JsError>() {
+            if let Some(hook) = &self.event_loop_error_hook {
+                hook(js_error);
+            }
+
+            if self.event_loop_errors.len() >= MAX_EVENT_LOOP_ERRORS {
+                self.event_loop_errors.pop_front();
+            }
+            self.event_loop_errors.push_back(js_error.clone());
+        }
+        e
+    }
+
     /// Runs the JS event loop to completion
     pub async fn await_event_loop(
         &mut self,
         options: PollEventLoopOptions,
         timeout: Option<Duration>,
     ) -> Result<(), Error> {
-        if let Some(timeout) = timeout {
-            Ok(tokio::select! {
+        let result = if let Some(timeout) = timeout {
+            tokio::select! {
                 r = self.deno_runtime().run_event_loop(options) => r,
                 () = tokio::time::sleep(timeout) => Ok(()),
-            }?)
+            }
         } else {
-            Ok(self.deno_runtime().run_event_loop(options).await?)
-        }
+            self.deno_runtime().run_event_loop(options).await
+        };
+        Ok(result.map_err(|e| self.event_loop_error(e))?)
     }
 
     /// Advances the JS event loop by one tick
@@ -415,7 +1109,7 @@ impl<RT: RuntimeTrait> InnerRuntime<RT> {
     ) -> Result<bool, Error> {
         let result = std::future::poll_fn(|cx| {
             Poll::Ready(match self.deno_runtime().poll_event_loop(cx, options) {
-                Poll::Ready(t) => t.map(|()| false),
+                Poll::Ready(t) => t.map(|()| false).map_err(|e| self.event_loop_error(e)),
                 Poll::Pending => Ok(true),
             })
         })
@@ -449,7 +1143,10 @@ impl<RT: RuntimeTrait> InnerRuntime<RT> {
     ///
     /// # Returns
     /// A `Result` containing the non-null value extracted or an error (`Error`)
-    pub fn get_global_value(&mut self, name: &str) -> Result<v8::Global<v8::Value>, Error> {
+    pub fn get_global_value<N>(&mut self, name: &N) -> Result<v8::Global<v8::Value>, Error>
+    where
+        N: ToV8String + ToString + ?Sized,
+    {
         let context = self.deno_runtime().main_context();
         let mut scope = self.deno_runtime().handle_scope();
         let global = context.open(&mut scope).global(&mut scope);
@@ -471,11 +1168,14 @@ impl<RT: RuntimeTrait> InnerRuntime<RT> {
     ///
     /// # Returns
     /// A `Result` containing the non-null value extracted or an error (`Error`)
-    pub fn get_module_export_value(
+    pub fn get_module_export_value<N>(
         &mut self,
         module_context: &ModuleHandle,
-        name: &str,
-    ) -> Result<v8::Global<v8::Value>, Error> {
+        name: &N,
+    ) -> Result<v8::Global<v8::Value>, Error>
+    where
+        N: ToV8String + ToString + ?Sized,
+    {
         let module_namespace = self
             .deno_runtime()
             .get_module_namespace(module_context.id())?;
@@ -510,14 +1210,17 @@ impl<RT: RuntimeTrait> InnerRuntime<RT> {
     {
         let mut scope = self.deno_runtime().handle_scope();
         let result = v8::Local::<v8::Value>::new(&mut scope, value);
-        Ok(from_v8(&mut scope, result)?)
+        crate::js_value::decode_v8(&mut scope, result)
     }
 
-    pub fn get_value_ref(
+    pub fn get_value_ref<N>(
         &mut self,
         module_context: Option<&ModuleHandle>,
-        name: &str,
-    ) -> Result<v8::Global<v8::Value>, Error> {
+        name: &N,
+    ) -> Result<v8::Global<v8::Value>, Error>
+    where
+        N: ToV8String + ToString + ?Sized,
+    {
         // Try to get the value from the module context first
         let result = module_context
             .and_then(|module_context| self.get_module_export_value(module_context, name).ok());
@@ -541,11 +1244,14 @@ impl<RT: RuntimeTrait> InnerRuntime<RT> {
     /// A `Result` containing a `v8::Global<v8::Function>` if
     /// the function is found, or an error (`Error`) if the function cannot be found or
     /// if it is not a valid javascript function.
-    pub fn get_function_by_name(
+    pub fn get_function_by_name<N>(
         &mut self,
         module_context: Option<&ModuleHandle>,
-        name: &str,
-    ) -> Result<v8::Global<v8::Function>, Error> {
+        name: &N,
+    ) -> Result<v8::Global<v8::Function>, Error>
+    where
+        N: ToV8String + ToString + ?Sized,
+    {
         // Get the value
         let value = self.get_value_ref(module_context, name)?;
 
@@ -560,12 +1266,37 @@ impl<RT: RuntimeTrait> InnerRuntime<RT> {
         Ok(v8::Global::<v8::Function>::new(&mut scope, f))
     }
 
+    /// Converts a name to an internalized v8 string, reusing the string if the name was prepared before
+    pub fn prepare_name(&mut self, name: &'static str) -> Result<crate::PreparedName, Error> {
+        if let Some(prepared) = self.prepared_names.get(name) {
+            return Ok(prepared.clone());
+        }
+
+        let mut scope = self.deno_runtime().handle_scope();
+        let prepared = crate::PreparedName::new(&mut scope, name)?;
+        drop(scope);
+
+        self.prepared_names.insert(name, prepared.clone());
+        Ok(prepared)
+    }
+
+    /// Returns the execution trace, if tracing is enabled
+    fn trace_state(&mut self) -> Option<crate::ext::rustyscript::TraceState> {
+        self.deno_runtime()
+            .op_state()
+            .borrow()
+            .try_borrow::<crate::ext::rustyscript::TraceState>()
+            .cloned()
+    }
+
     pub fn call_function_by_ref(
         &mut self,
         module_context: Option<&ModuleHandle>,
         function: &v8::Global<v8::Function>,
         args: &impl serde::ser::Serialize,
     ) -> Result<v8::Global<v8::Value>, Error> {
+        let trace = self.trace_state();
+
         // Namespace, if provided
         let module_namespace = if let Some(module_context) = module_context {
             Some(
@@ -594,6 +1325,20 @@ impl<RT: RuntimeTrait> InnerRuntime<RT> {
 
         // Prep arguments
         let args = decode_args(args, &mut scope)?;
+        if let Some(trace) = trace {
+            let function = function_instance.get_name(&mut scope);
+            let function = function.to_rust_string_lossy(&mut scope);
+            if !function.starts_with("__rustyscript_") {
+                let array = v8::Array::new_with_elements(&mut scope, &args);
+                let json = v8::json::stringify(&mut scope, array.into())
+                    .map(|json| json.to_rust_string_lossy(&mut scope))
+                    .unwrap_or_default();
+                trace.check(crate::TraceEvent::Call {
+                    function,
+                    args_hash: crate::ext::rustyscript::hash_bytes(json.as_bytes()),
+                })?;
+            }
+        }
 
         // Call the function
         let result = function_instance.call(&mut scope, namespace, &args);
@@ -602,34 +1347,68 @@ impl<RT: RuntimeTrait> InnerRuntime<RT> {
                 let value = v8::Global::new(&mut scope, value);
                 Ok(value)
             }
-            None if scope.has_caught() => {
-                let e = scope
-                    .message()
-                    .ok_or_else(|| Error::Runtime("Unknown error".to_string()))?;
-
-                let filename = e.get_script_resource_name(&mut scope);
-                let linenumber = e.get_line_number(&mut scope).unwrap_or_default();
-                let filename = if let Some(v) = filename {
-                    let filename = v.to_rust_string_lossy(&mut scope);
-                    format!("{filename}:{linenumber}: ")
-                } else if let Some(module_context) = module_context {
-                    let filename = module_context.module().filename().to_string_lossy();
-                    format!("{filename}:{linenumber}: ")
-                } else {
-                    String::new()
-                };
-
-                let msg = e.get(&mut scope).to_rust_string_lossy(&mut scope);
-
-                let s = format!("{filename}{msg}");
-                Err(Error::Runtime(s))
-            }
+            None if scope.has_caught() => Err(caught_error(&mut scope, module_context)),
             None => Err(Error::Runtime(
                 "Unknown error during function execution".to_string(),
             )),
         }
     }
 
+    /// Runs a batch of calls and value lookups in a single handle scope,
+    /// then drives the event loop once until every result is resolved
+    ///
+    /// Returns the result of each entry, in order
+    pub async fn run_batch(
+        &mut self,
+        entries: &[crate::batch::BatchEntry],
+    ) -> Result<Vec<Result<serde_json::Value, Error>>, Error> {
+        use crate::batch::BatchEntry;
+
+        let mut namespaces = HashMap::new();
+        for module in entries.iter().filter_map(BatchEntry::module) {
+            if let Entry::Vacant(slot) = namespaces.entry(module.id()) {
+                slot.insert(self.deno_runtime().get_module_namespace(module.id())?);
+            }
+        }
+
+        let context = self.deno_runtime().main_context();
+        let mut pending = Vec::with_capacity(entries.len());
+        {
+            let mut scope = self.deno_runtime().handle_scope();
+            let global = context.open(&mut scope).global(&mut scope);
+
+            for entry in entries {
+                let namespace = entry
+                    .module()
+                    .map(|module| v8::Local::new(&mut scope, &namespaces[&module.id()]));
+                let result = run_batch_entry(&mut scope, global, namespace, entry)
+                    .map(|value| JsRuntime::scoped_resolve(&mut scope, value));
+                pending.push(result);
+            }
+        }
+
+        let resolved =
+            deno_core::futures::future::join_all(pending.into_iter().map(|result| async move {
+                match result {
+                    Ok(future) => future.await.map_err(Error::from),
+                    Err(e) => Err(e),
+                }
+            }))
+            .map(Ok::<_, Error>);
+        let resolved = self
+            .with_event_loop_future(Box::pin(resolved), PollEventLoopOptions::default())
+            .await?;
+
+        let mut scope = self.deno_runtime().handle_scope();
+        Ok(resolved
+            .into_iter()
+            .map(|result| {
+                let value = v8::Local::new(&mut scope, result?);
+                crate::js_value::decode_v8(&mut scope, value)
+            })
+            .collect())
+    }
+
     /// A utility function that run provided future concurrently with the event loop.
     ///
     /// If the event loop resolves while polling the future, it will continue to be polled,
@@ -652,7 +1431,7 @@ impl<RT: RuntimeTrait> InnerRuntime<RT> {
                     self.deno_runtime().poll_event_loop(cx, poll_options)
                 {
                     // Run one more tick to check for errors
-                    Poll::Ready(Err(e.into()))
+                    Poll::Ready(Err(self.event_loop_error(e).into()))
                 } else {
                     // No errors - continue
                     Poll::Ready(t.map_err(Into::into))
@@ -661,7 +1440,7 @@ impl<RT: RuntimeTrait> InnerRuntime<RT> {
 
             if let Poll::Ready(Err(e)) = self.deno_runtime().poll_event_loop(cx, poll_options) {
                 // Event loop failed
-                return Poll::Ready(Err(e.into()));
+                return Poll::Ready(Err(self.event_loop_error(e).into()));
             }
 
             if self
@@ -714,6 +1493,234 @@ impl<RT: RuntimeTrait> InnerRuntime<RT> {
         Ok(None)
     }
 
+    /// Transpiles and transforms a module loaded from rust, into the code given to v8
+    #[allow(clippy::unused_async)]
+    async fn prepare_module(
+        &self,
+        module: &Module,
+    ) -> Result<(ModuleSpecifier, String, Option<SourceMapData>), Error> {
+        let module_specifier = self.module_specifier(module.filename())?;
+        let (code, sourcemap) = self
+            .module_loader
+            .transpile(&module_specifier, module.contents())?;
+        let code = self
+            .module_loader
+            .transform_source(&module_specifier, code)?;
+
+        // Now CJS translation, for node
+        #[cfg(feature = "node_experimental")]
+        let code = self
+            .module_loader
+            .translate_cjs(&module_specifier, &code)
+            .await?;
+
+        Ok((module_specifier, code, sourcemap))
+    }
+
+    /// Compiles a module, and returns the v8 code cache for the result
+    pub async fn module_code_cache(&mut self, module: &Module) -> Result<Vec<u8>, Error> {
+        let (module_specifier, code, _) = self.prepare_module(module).await?;
+
+        let mut scope = self.deno_runtime().handle_scope();
+        let name = module_specifier.as_str().to_v8_string(&mut scope)?;
+        let code = code.to_v8_string(&mut scope)?;
+        let origin = v8::ScriptOrigin::new(
+            &mut scope,
+            name.into(),
+            0,
+            0,
+            false,
+            0,
+            None,
+            false,
+            false,
+            true,
+            None,
+        );
+
+        let mut source = v8::script_compiler::Source::new(code, Some(&origin));
+        let mut scope = v8::TryCatch::new(&mut scope);
+        let Some(compiled) = v8::script_compiler::compile_module(&mut scope, &mut source) else {
+            let message = scope
+                .message()
+                .map(|m| m.get(&mut scope).to_rust_string_lossy(&mut scope))
+                .unwrap_or_default();
+            return Err(Error::Runtime(format!(
+                "Could not compile {module_specifier}: {message}"
+            )));
+        };
+
+        compiled
+            .get_unbound_module_script(&mut scope)
+            .create_code_cache()
+            .map(|cache| cache.to_vec())
+            .ok_or_else(|| {
+                Error::Runtime(format!(
+                    "Could not create a code cache for {module_specifier}"
+                ))
+            })
+    }
+
+    /// Stores a v8 code cache for a module, used when it is next loaded
+    pub fn add_code_cache(&mut self, filename: &Path, data: Vec<u8>) -> Result<(), Error> {
+        let module_specifier = self.module_specifier(filename)?;
+        self.module_loader.add_code_cache(&module_specifier, data);
+        Ok(())
+    }
+
+    /// Records the handle of a loaded module, replacing any earlier handle for the same module
+    ///
+    /// Cached results of calls into the module are discarded, see [`crate::Runtime::call_cached`]
+    pub fn remember_module(&mut self, specifier: ModuleSpecifier, handle: ModuleHandle) {
+        for (_, reloaded) in self.loaded_modules.iter().filter(|(s, _)| *s == specifier) {
+            self.call_cache.invalidate(reloaded.id());
+        }
+        self.loaded_modules
+            .retain(|(s, h)| *s != specifier && h.id() != handle.id());
+        self.loaded_modules.push((specifier, handle));
+    }
+
+    /// Finds the handle of a loaded module, by filename or specifier
+    /// If a module was loaded more than once, the latest handle is returned
+    pub fn find_module(&self, name: &str) -> Option<&ModuleHandle> {
+        let specifier = self.module_specifier(Path::new(name)).ok();
+        self.loaded_modules
+            .iter()
+            .rev()
+            .find(|(s, h)| {
+                s.as_str() == name
+                    || Some(s) == specifier.as_ref()
+                    || h.module().filename() == Path::new(name)
+            })
+            .map(|(_, h)| h)
+    }
+
+    /// Registers modules without evaluating them
+    /// Each is only loaded once it is imported by another module
+    pub fn register_lazy_modules(&mut self, modules: Vec<&Module>) -> Result<(), Error> {
+        for module in modules {
+            let module_specifier = self.module_specifier(module.filename())?;
+            self.module_loader
+                .add_lazy_module(&module_specifier, module.contents().to_string());
+        }
+        Ok(())
+    }
+
+    /// Makes imports of a bare specifier resolve to a module provided from rust
+    /// The module is registered lazily, and only loaded once it is imported
+    pub fn alias_module(&mut self, name: &str, module: &Module) -> Result<(), Error> {
+        let module_specifier = self.module_specifier(module.filename())?;
+        self.module_loader
+            .add_lazy_module(&module_specifier, module.contents().to_string());
+        self.module_loader.add_alias(name, module_specifier);
+        Ok(())
+    }
+
+    /// Loads and evaluates a single module, as the main module or as a side module
+    async fn load_module(&mut self, module: &Module, main: bool) -> Result<ModuleId, Error> {
+        if let Some(trace) = self.trace_state() {
+            trace.check(crate::TraceEvent::ModuleLoaded {
+                filename: module.filename().display().to_string(),
+                source_hash: crate::ext::rustyscript::hash_bytes(module.contents().as_bytes()),
+            })?;
+        }
+
+        let (module_specifier, code, sourcemap) = self.prepare_module(module).await?;
+        self.module_loader.record_module(
+            &module_specifier,
+            crate::module_loader::ModuleOrigin::Host,
+            module.contents().as_bytes(),
+        );
+
+        // Modules with a code cache go through the loader, which attaches it
+        let module_id = if self.module_loader.has_code_cache(&module_specifier) {
+            self.module_loader.stage_source(&module_specifier, code);
+            if main {
+                self.deno_runtime()
+                    .load_main_es_module(&module_specifier)
+                    .await?
+            } else {
+                self.deno_runtime()
+                    .load_side_es_module(&module_specifier)
+                    .await?
+            }
+        } else {
+            let fast_code = deno_core::FastString::from(code);
+            if main {
+                self.deno_runtime()
+                    .load_main_es_module_from_code(&module_specifier, fast_code)
+                    .await?
+            } else {
+                self.deno_runtime()
+                    .load_side_es_module_from_code(&module_specifier, fast_code)
+                    .await?
+            }
+        };
+
+        // Update source map cache - with the original source, which the map points into
+        self.module_loader.insert_source_map(
+            module_specifier.as_str(),
+            module.contents().to_string(),
+            sourcemap.map(|s| s.to_vec()),
+        );
+
+        // Finish execution
+        let mod_load = self.deno_runtime().mod_evaluate(module_id);
+        if let Some(interval) = self.module_stall_timeout {
+            self.evaluate_with_stall_detection(mod_load, interval, &module_specifier)
+                .await?;
+        } else {
+            self.with_event_loop_future(mod_load, PollEventLoopOptions::default())
+                .await?;
+        }
+        Ok(module_id)
+    }
+
+    /// Runs a module's evaluation, failing if it goes a full interval without any async op or timer starting or completing
+    async fn evaluate_with_stall_detection<E>(
+        &mut self,
+        mut mod_load: impl std::future::Future<Output = Result<(), E>> + Unpin,
+        interval: Duration,
+        module_specifier: &ModuleSpecifier,
+    ) -> Result<(), Error>
+    where
+        deno_core::error::AnyError: From<E>,
+        Error: std::convert::From<E>,
+    {
+        // Pending timers only count as progress once they fire, so they are tracked separately
+        let filter = RuntimeActivityStatsFilter::default()
+            .with_ops()
+            .with_resources();
+        let capture = |runtime: &mut Self| {
+            let stats = runtime
+                .deno_runtime()
+                .runtime_activity_stats_factory()
+                .capture(&filter);
+            let firings = crate::ext::rustyscript::TimerFirings::get(
+                &runtime.deno_runtime().op_state().borrow(),
+            );
+            (stats, firings)
+        };
+
+        let mut before = capture(self);
+        loop {
+            tokio::select! {
+                result = self.with_event_loop_future(&mut mod_load, PollEventLoopOptions::default()) => return result,
+                () = tokio::time::sleep(interval) => {}
+            }
+
+            let after = capture(self);
+            if after.1 == before.1 && RuntimeActivityStats::diff(&before.0, &after.0).is_empty() {
+                return Err(Error::ModuleStalled {
+                    module: module_specifier.to_string(),
+                    interval,
+                    pending: crate::PendingWork::capture(self.deno_runtime()).to_string(),
+                });
+            }
+            before = after;
+        }
+    }
+
     /// Load one or more modules
     /// Returns a future that resolves to a handle to the main module, or the last
     /// side-module
@@ -735,77 +1742,44 @@ impl<RT: RuntimeTrait> InnerRuntime<RT> {
 
         // Get additional modules first
         for side_module in side_modules {
-            let module_specifier = side_module.filename().to_module_specifier(&self.cwd)?;
-            let (code, sourcemap) = transpile(&module_specifier, side_module.contents())?;
-
-            // Now CJS translation, for node
-            #[cfg(feature = "node_experimental")]
-            let code = self
-                .module_loader
-                .translate_cjs(&module_specifier, &code)
-                .await?;
-
-            let fast_code = deno_core::FastString::from(code.clone());
-
-            let s_modid = self
-                .deno_runtime()
-                .load_side_es_module_from_code(&module_specifier, fast_code)
-                .await?;
-
-            // Update source map cache
-            self.module_loader.insert_source_map(
-                module_specifier.as_str(),
-                code,
-                sourcemap.map(|s| s.to_vec()),
-            );
-
-            let mod_load = self.deno_runtime().mod_evaluate(s_modid);
-            self.with_event_loop_future(mod_load, PollEventLoopOptions::default())
-                .await?;
-            module_handle_stub = ModuleHandle::new(side_module, s_modid, None);
+            let started = Instant::now();
+            let result = self.load_module(side_module, false).await;
+            let handle = result.map(|id| ModuleHandle::new(side_module, id, None));
+            self.observers
+                .module_loaded(side_module, started, handle.as_ref());
+            module_handle_stub = handle?;
+            let specifier = self.module_specifier(side_module.filename())?;
+            self.remember_module(specifier, module_handle_stub.clone());
         }
 
         // Load main module
+        let mut started = None;
         if let Some(module) = main_module {
-            let module_specifier = module.filename().to_module_specifier(&self.cwd)?;
-            let (code, sourcemap) = transpile(&module_specifier, module.contents())?;
-
-            // Now CJS translation, for node
-            #[cfg(feature = "node_experimental")]
-            let code = self
-                .module_loader
-                .translate_cjs(&module_specifier, &code)
-                .await?;
-
-            let fast_code = deno_core::FastString::from(code.clone());
-
-            let module_id = self
-                .deno_runtime()
-                .load_main_es_module_from_code(&module_specifier, fast_code)
-                .await?;
-
-            // Update source map cache
-            self.module_loader.insert_source_map(
-                module_specifier.as_str(),
-                code,
-                sourcemap.map(|s| s.to_vec()),
-            );
-
-            // Finish execution
-            let mod_load = self.deno_runtime().mod_evaluate(module_id);
-            self.with_event_loop_future(mod_load, PollEventLoopOptions::default())
-                .await?;
-            module_handle_stub = ModuleHandle::new(module, module_id, None);
+            let now = Instant::now();
+            let result = self.load_module(module, true).await;
+            if let Err(e) = &result {
+                self.observers.module_loaded(module, now, Err(e));
+            }
+            started = Some(now);
+            module_handle_stub = ModuleHandle::new(module, result?, None);
         }
 
         // Try to get the default entrypoint
         let entrypoint = self.get_module_entrypoint(&mut module_handle_stub)?;
 
-        Ok(ModuleHandle::new(
+        let handle = ModuleHandle::new(
             module_handle_stub.module(),
             module_handle_stub.id(),
             entrypoint,
-        ))
+        );
+        if let Some(started) = started {
+            self.observers
+                .module_loaded(handle.module(), started, Ok(&handle));
+        }
+
+        let specifier = self.module_specifier(handle.module().filename())?;
+        self.remember_module(specifier, handle.clone());
+        Ok(handle)
     }
 }
 
@@ -849,9 +1823,11 @@ mod test_inner_runtime {
 
     #[test]
     fn test_decode_args() {
-        let mut runtime =
-            InnerRuntime::<JsRuntime>::new(RuntimeOptions::default(), CancellationToken::new())
-                .expect("Could not load runtime");
+        let mut runtime = InnerRuntime::<JsRuntime>::new(
+            RuntimeOptions::default(),
+            HeapExhaustedToken::default(),
+        )
+        .expect("Could not load runtime");
         let mut scope = runtime.deno_runtime.handle_scope();
 
         // empty
@@ -896,9 +1872,11 @@ mod test_inner_runtime {
 
     #[test]
     fn test_put_take() {
-        let mut runtime =
-            InnerRuntime::<JsRuntime>::new(RuntimeOptions::default(), CancellationToken::new())
-                .expect("Could not load runtime");
+        let mut runtime = InnerRuntime::<JsRuntime>::new(
+            RuntimeOptions::default(),
+            HeapExhaustedToken::default(),
+        )
+        .expect("Could not load runtime");
 
         runtime.put(2usize).expect("Could not put value");
         let v = runtime.take::<usize>().expect("Could not take value");
@@ -907,9 +1885,11 @@ mod test_inner_runtime {
 
     #[test]
     fn test_register_async_function() {
-        let mut runtime =
-            InnerRuntime::<JsRuntime>::new(RuntimeOptions::default(), CancellationToken::new())
-                .expect("Could not load runtime");
+        let mut runtime = InnerRuntime::<JsRuntime>::new(
+            RuntimeOptions::default(),
+            HeapExhaustedToken::default(),
+        )
+        .expect("Could not load runtime");
         runtime
             .register_async_function(
                 "test",
@@ -935,9 +1915,11 @@ mod test_inner_runtime {
 
     #[test]
     fn test_register_function() {
-        let mut runtime =
-            InnerRuntime::<JsRuntime>::new(RuntimeOptions::default(), CancellationToken::new())
-                .expect("Could not load runtime");
+        let mut runtime = InnerRuntime::<JsRuntime>::new(
+            RuntimeOptions::default(),
+            HeapExhaustedToken::default(),
+        )
+        .expect("Could not load runtime");
         runtime
             .register_function(
                 "test",
@@ -958,9 +1940,11 @@ mod test_inner_runtime {
     #[cfg(any(feature = "web", feature = "web_stub"))]
     #[test]
     fn test_eval() {
-        let mut runtime =
-            InnerRuntime::<JsRuntime>::new(RuntimeOptions::default(), CancellationToken::new())
-                .expect("Could not load runtime");
+        let mut runtime = InnerRuntime::<JsRuntime>::new(
+            RuntimeOptions::default(),
+            HeapExhaustedToken::default(),
+        )
+        .expect("Could not load runtime");
 
         run_async_task(|| async move {
             let v = runtime.eval("2 + 2").await.expect("failed to eval");
@@ -988,9 +1972,11 @@ mod test_inner_runtime {
     #[cfg(feature = "web_stub")]
     #[test]
     fn test_base64() {
-        let mut runtime =
-            InnerRuntime::<JsRuntime>::new(RuntimeOptions::default(), CancellationToken::new())
-                .expect("Could not load runtime");
+        let mut runtime = InnerRuntime::<JsRuntime>::new(
+            RuntimeOptions::default(),
+            HeapExhaustedToken::default(),
+        )
+        .expect("Could not load runtime");
 
         run_async_task(|| async move {
             let result = runtime.eval("btoa('foo')").await.expect("failed to eval");
@@ -1017,9 +2003,11 @@ mod test_inner_runtime {
         ",
         );
 
-        let mut runtime =
-            InnerRuntime::<JsRuntime>::new(RuntimeOptions::default(), CancellationToken::new())
-                .expect("Could not load runtime");
+        let mut runtime = InnerRuntime::<JsRuntime>::new(
+            RuntimeOptions::default(),
+            HeapExhaustedToken::default(),
+        )
+        .expect("Could not load runtime");
 
         let rt = &mut runtime;
         let module = run_async_task(|| async move { rt.load_modules(Some(&module), vec![]).await });
@@ -1059,9 +2047,11 @@ mod test_inner_runtime {
         ",
         );
 
-        let mut runtime =
-            InnerRuntime::<JsRuntime>::new(RuntimeOptions::default(), CancellationToken::new())
-                .expect("Could not load runtime");
+        let mut runtime = InnerRuntime::<JsRuntime>::new(
+            RuntimeOptions::default(),
+            HeapExhaustedToken::default(),
+        )
+        .expect("Could not load runtime");
 
         let rt = &mut runtime;
         let module = run_async_task(|| async move { rt.load_modules(Some(&module), vec![]).await });
@@ -1099,9 +2089,11 @@ mod test_inner_runtime {
         );
 
         run_async_task(|| async move {
-            let mut runtime =
-                InnerRuntime::<JsRuntime>::new(RuntimeOptions::default(), CancellationToken::new())
-                    .expect("Could not load runtime");
+            let mut runtime = InnerRuntime::<JsRuntime>::new(
+                RuntimeOptions::default(),
+                HeapExhaustedToken::default(),
+            )
+            .expect("Could not load runtime");
             let handle = runtime.load_modules(Some(&module), vec![]).await?;
 
             let f = runtime.get_function_by_name(None, "fna").unwrap();
@@ -1143,9 +2135,11 @@ mod test_inner_runtime {
         ",
         );
 
-        let mut runtime =
-            InnerRuntime::<JsRuntime>::new(RuntimeOptions::default(), CancellationToken::new())
-                .expect("Could not load runtime");
+        let mut runtime = InnerRuntime::<JsRuntime>::new(
+            RuntimeOptions::default(),
+            HeapExhaustedToken::default(),
+        )
+        .expect("Could not load runtime");
 
         let rt = &mut runtime;
         let module = run_async_task(|| async move { rt.load_modules(Some(&module), vec![]).await });
@@ -1172,9 +2166,11 @@ mod test_inner_runtime {
         ",
         );
 
-        let mut runtime =
-            InnerRuntime::<JsRuntime>::new(RuntimeOptions::default(), CancellationToken::new())
-                .expect("Could not load runtime");
+        let mut runtime = InnerRuntime::<JsRuntime>::new(
+            RuntimeOptions::default(),
+            HeapExhaustedToken::default(),
+        )
+        .expect("Could not load runtime");
 
         let rt = &mut runtime;
         let module = run_async_task(|| async move {
@@ -1209,9 +2205,11 @@ mod test_inner_runtime {
         ",
         );
 
-        let mut runtime =
-            InnerRuntime::<JsRuntime>::new(RuntimeOptions::default(), CancellationToken::new())
-                .expect("Could not load runtime");
+        let mut runtime = InnerRuntime::<JsRuntime>::new(
+            RuntimeOptions::default(),
+            HeapExhaustedToken::default(),
+        )
+        .expect("Could not load runtime");
 
         let rt = &mut runtime;
         run_async_task(|| async move {
@@ -1241,9 +2239,11 @@ mod test_inner_runtime {
         ",
         );
 
-        let mut runtime =
-            InnerRuntime::<JsRuntime>::new(RuntimeOptions::default(), CancellationToken::new())
-                .expect("Could not load runtime");
+        let mut runtime = InnerRuntime::<JsRuntime>::new(
+            RuntimeOptions::default(),
+            HeapExhaustedToken::default(),
+        )
+        .expect("Could not load runtime");
 
         let rt = &mut runtime;
         run_async_task(|| async move {
@@ -1272,9 +2272,11 @@ mod test_inner_runtime {
         ",
         );
 
-        let mut runtime =
-            InnerRuntime::<JsRuntime>::new(RuntimeOptions::default(), CancellationToken::new())
-                .expect("Could not load runtime");
+        let mut runtime = InnerRuntime::<JsRuntime>::new(
+            RuntimeOptions::default(),
+            HeapExhaustedToken::default(),
+        )
+        .expect("Could not load runtime");
 
         let rt = &mut runtime;
         let module = run_async_task(|| async move { rt.load_modules(Some(&module), vec![]).await });
@@ -1320,9 +2322,11 @@ mod test_inner_runtime {
         ",
         );
 
-        let mut runtime =
-            InnerRuntime::<JsRuntime>::new(RuntimeOptions::default(), CancellationToken::new())
-                .expect("Could not load runtime");
+        let mut runtime = InnerRuntime::<JsRuntime>::new(
+            RuntimeOptions::default(),
+            HeapExhaustedToken::default(),
+        )
+        .expect("Could not load runtime");
 
         let rt = &mut runtime;
         let module_ = module.clone();
@@ -1332,9 +2336,11 @@ mod test_inner_runtime {
             );
         assert!(result);
 
-        let mut runtime =
-            InnerRuntime::<JsRuntime>::new(RuntimeOptions::default(), CancellationToken::new())
-                .expect("Could not load runtime");
+        let mut runtime = InnerRuntime::<JsRuntime>::new(
+            RuntimeOptions::default(),
+            HeapExhaustedToken::default(),
+        )
+        .expect("Could not load runtime");
 
         let rt = &mut runtime;
         let result =
@@ -1353,9 +2359,11 @@ mod test_inner_runtime {
         ",
         );
 
-        let mut runtime =
-            InnerRuntime::<JsRuntime>::new(RuntimeOptions::default(), CancellationToken::new())
-                .expect("Could not load runtime");
+        let mut runtime = InnerRuntime::<JsRuntime>::new(
+            RuntimeOptions::default(),
+            HeapExhaustedToken::default(),
+        )
+        .expect("Could not load runtime");
 
         let rt = &mut runtime;
         let module = run_async_task(|| async move { rt.load_modules(Some(&module), vec![]).await });
@@ -0,0 +1,176 @@
+//! Exposes rust types to javascript as objects, see [`JsApi`]
+use crate::{Error, Runtime};
+
+/// A rust type whose methods can be called from javascript, through an object in the global scope
+///
+/// Implemented by the [`crate::js_api`] attribute, placed on an impl block
+/// Every public method taking `&self` or `&mut self` is exposed, including async methods taking `&self`
+///
+/// - Arguments are deserialized from javascript values, and must be owned types - missing arguments are treated as `null`
+/// - Methods must return a `Result<T, E>`, where `T` is serializable and `rustyscript::Error: From<E>`
+/// - Synchronous methods return values to javascript directly, while async methods return promises
+/// - Async methods run on a clone of the value, taken when they are called, so types with async methods must implement `Clone`
+///   The value is not borrowed while they are awaited, so other methods can still be called in the meantime
+///
+/// Register an instance with [`Runtime::register_api`]:
+/// ```rust
+/// use rustyscript::{ js_api, Error, Module, Runtime };
+///
+/// struct Store {
+///     items: Vec<String>,
+/// }
+///
+/// #[js_api]
+/// impl Store {
+///     pub fn add(&mut self, item: String) -> Result<usize, Error> {
+///         self.items.push(item);
+///         Ok(self.items.len())
+///     }
+///
+///     pub fn get(&self, index: usize) -> Result<Option<String>, Error> {
+///         Ok(self.items.get(index).cloned())
+///     }
+/// }
+///
+/// # fn main() -> Result<(), Error> {
+/// let mut runtime = Runtime::new(Default::default())?;
+/// runtime.register_api("store", Store { items: vec![] })?;
+///
+/// let module = Module::new("test.js", "
+///     store.add('apple');
+///     export const item = store.get(0);
+/// ");
+/// let handle = runtime.load_module(&module)?;
+/// let item: String = runtime.get_value(Some(&handle), "item")?;
+/// assert_eq!(item, "apple");
+/// # Ok(())
+/// # }
+/// ```
+pub trait JsApi: Sized + 'static {
+    /// The names of the exposed synchronous methods
+    const METHODS: &'static [&'static str];
+
+    /// The names of the exposed async methods
+    const ASYNC_METHODS: &'static [&'static str];
+
+    /// Registers each exposed method as a function named `{namespace}.{method}`
+    ///
+    /// # Errors
+    /// Will return an error if a function cannot be registered
+    fn register_functions(self, runtime: &mut Runtime, namespace: &str) -> Result<(), Error>;
+
+    /// Returns javascript code creating the object that exposes the methods, as `globalThis[namespace]`
+    ///
+    /// # Errors
+    /// Will return an error if the names cannot be encoded
+    fn js_bindings(namespace: &str) -> Result<String, Error> {
+        let mut members = Vec::new();
+        for (methods, table) in [
+            (Self::METHODS, "functions"),
+            (Self::ASYNC_METHODS, "async_functions"),
+        ] {
+            for method in methods {
+                let name = deno_core::serde_json::to_string(method)?;
+                let function = deno_core::serde_json::to_string(&format!("{namespace}.{method}"))?;
+                members.push(format!(
+                    "{name}: (...args) => rustyscript.{table}[{function}](...args)"
+                ));
+            }
+        }
+
+        let namespace = deno_core::serde_json::to_string(namespace)?;
+        Ok(format!(
+            "globalThis[{namespace}] = Object.freeze({{ {} }});",
+            members.join(", ")
+        ))
+    }
+}
+
+/// Registers an API with a runtime, see [`Runtime::register_api`]
+pub(crate) fn register_api<T: JsApi>(
+    runtime: &mut Runtime,
+    namespace: &str,
+    api: T,
+) -> Result<(), Error> {
+    let bindings = T::js_bindings(namespace)?;
+    api.register_functions(runtime, namespace)?;
+    runtime.eval::<crate::Undefined>(bindings)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{js_api, json_args, Error, Module, Runtime, RuntimeOptions};
+
+    #[derive(Clone)]
+    struct Counter {
+        count: i64,
+    }
+
+    #[js_api]
+    impl Counter {
+        pub fn add(&mut self, n: i64) -> Result<i64, Error> {
+            self.count += n;
+            Ok(self.count)
+        }
+
+        pub fn get(&self) -> Result<i64, Error> {
+            Ok(self.count)
+        }
+
+        pub async fn delayed(&self, ms: Option<u64>) -> Result<i64, Error> {
+            tokio::time::sleep(std::time::Duration::from_millis(ms.unwrap_or(1))).await;
+            Ok(self.count)
+        }
+
+        #[allow(dead_code)]
+        fn hidden(&self) -> i64 {
+            self.count
+        }
+    }
+
+    #[test]
+    fn test_js_api() {
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        runtime
+            .register_api("counter", Counter { count: 0 })
+            .unwrap();
+
+        let module = Module::new(
+            "test.js",
+            "
+            counter.add(2);
+            export const sync = counter.add(3);
+            export const delayed = () => counter.delayed();
+            export const overlapping = async () => {
+                const pending = counter.delayed(20);
+                counter.add(1);
+                return [await pending, counter.get()];
+            };
+            export const hidden = typeof counter.hidden;
+        ",
+        );
+        let handle = runtime.load_module(&module).unwrap();
+
+        let value: i64 = runtime.get_value(Some(&handle), "sync").unwrap();
+        assert_eq!(value, 5);
+
+        let value: i64 = runtime
+            .call_function(Some(&handle), "delayed", json_args!())
+            .unwrap();
+        assert_eq!(value, 5);
+
+        // Sync methods can be called while an async one is pending, which sees the value as it was when called
+        let values: Vec<i64> = runtime
+            .call_function(Some(&handle), "overlapping", json_args!())
+            .unwrap();
+        assert_eq!(values, vec![5, 6]);
+
+        let value: String = runtime.get_value(Some(&handle), "hidden").unwrap();
+        assert_eq!(value, "undefined");
+
+        runtime
+            .eval::<i64>("counter.add('not a number')")
+            .expect_err("Invalid argument was accepted");
+    }
+}
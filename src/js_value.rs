@@ -3,6 +3,12 @@
 //!
 //! [Function] and [Promise] are both specializations of [Value] providing deserialize-time type checking
 //! and additional utility functions for interacting with the runtime
+//!
+//! [`BigInt`] is decoded eagerly into an arbitrary precision integer, and `BigInt`s nested in other
+//! values are converted to numbers or decimal strings when decoded into generic types like `serde_json::Value`
+//!
+//! All of these types can also be passed back into the runtime as function arguments
+//! They serialize as the original v8 value they reference, and not as a copy
 use deno_core::serde_v8::GlobalValue;
 use deno_core::v8::{self, HandleScope};
 use serde::Deserialize;
@@ -74,6 +80,17 @@ macro_rules! impl_v8 {
             }
         }
 
+        impl $(<$generic>)? serde::Serialize for $name $(<$generic>)?
+        $(where $generic: serde::de::DeserializeOwned,)?
+        {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: serde::Serializer,
+            {
+                self.0.serialize(serializer)
+            }
+        }
+
         #[allow(clippy::from_over_into)]
         impl $(<$generic>)? Into<v8::Global<v8::Value>> for $name $(<$generic>)? $(where $generic: serde::de::DeserializeOwned)? {
             fn into(self) -> v8::Global<v8::Value> {
@@ -190,6 +207,17 @@ impl<'de, T: V8TypeChecker> serde::Deserialize<'de> for V8Value<T> {
     }
 }
 
+/// Serializes as the referenced v8 value itself
+/// Only meaningful when used with `serde_v8` - such as in function arguments
+impl<T: V8TypeChecker> serde::Serialize for V8Value<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        GlobalValue::from(self.0.clone()).serialize(serializer)
+    }
+}
+
 /// A Deserializable javascript value, that can be stored and used later
 /// Can only be used on the same runtime it was created on
 ///
@@ -211,7 +239,7 @@ impl Value {
     {
         let mut scope = runtime.deno_runtime().handle_scope();
         let local = self.0.as_local(&mut scope);
-        Ok(deno_core::serde_v8::from_v8(&mut scope, local)?)
+        decode_v8(&mut scope, local)
     }
 
     /// Contructs a new Value from a `v8::Value` global
@@ -227,12 +255,195 @@ pub use function::*;
 mod promise;
 pub use promise::*;
 
+mod rejection;
+pub use rejection::Rejection;
+
 mod string;
 pub use string::*;
 
 mod map;
 pub use map::*;
 
+mod bigint;
+pub use bigint::BigInt;
+
+mod invoker;
+pub use invoker::{ArgEncoder, InvokeArg, InvokeArgs, TypedInvoker};
+
+mod number_policy;
+pub use number_policy::NumberPolicy;
+
+mod cycle_policy;
+pub use cycle_policy::CyclePolicy;
+
+mod type_hooks;
+pub(crate) use type_hooks::TypeHooks;
+pub use type_hooks::{Hooked, TypeHook};
+
+mod traverse;
+
+mod depth_limit;
+use depth_limit::DepthLimit;
+pub(crate) use depth_limit::MaxDecodeDepth;
+
+/// Decodes a v8 value into an arbitrary rust type
+/// Applies the runtime's [`TypeHook`]s, [`NumberPolicy`] and [`CyclePolicy`] to the value first,
+/// unless `T` is a reference to the original value
+/// If the value contains `BigInt`s that cannot be decoded directly, they are normalized and decoding is retried
+pub(crate) fn decode_v8<'s, T>(
+    scope: &mut HandleScope<'s>,
+    value: v8::Local<'s, v8::Value>,
+) -> Result<T, crate::Error>
+where
+    T: serde::de::DeserializeOwned,
+{
+    if is_v8_reference::<T>() {
+        return Ok(deno_core::serde_v8::from_v8(scope, value)?);
+    }
+
+    let policy = CyclePolicy::from_scope(scope);
+    let prepared = prepare_value(scope, value)?;
+    if policy == CyclePolicy::Unchecked {
+        return decode_limited(scope, prepared, None);
+    }
+
+    let max_depth = MaxDecodeDepth::from_scope(scope);
+    match decode_depth_limited(scope, prepared, max_depth) {
+        Err(crate::Error::DecodeDepthExceeded(_)) => {}
+        result => return result,
+    }
+
+    // Only values nested too deeply are walked, to find the circular reference responsible
+    // Unless the policy replaced one, the value is simply too deep
+    let walked = policy.apply(scope, value)?;
+    if walked.strict_equals(value) {
+        return Err(crate::Error::DecodeDepthExceeded(max_depth));
+    }
+
+    let walked = prepare_value(scope, walked)?;
+    decode_depth_limited(scope, walked, max_depth)
+}
+
+/// Decodes a value nested at most `max_depth` levels deep, failing with [`crate::Error::DecodeDepthExceeded`] past that
+fn decode_depth_limited<'s, T>(
+    scope: &mut HandleScope<'s>,
+    value: v8::Local<'s, v8::Value>,
+    max_depth: usize,
+) -> Result<T, crate::Error>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let limit = DepthLimit::new(max_depth);
+    match decode_limited(scope, value, Some(&limit)) {
+        Err(_) if limit.exceeded() => Err(crate::Error::DecodeDepthExceeded(max_depth)),
+        result => result,
+    }
+}
+
+/// Applies the runtime's [`TypeHook`]s and [`NumberPolicy`] to a value about to be decoded
+fn prepare_value<'s>(
+    scope: &mut HandleScope<'s>,
+    value: v8::Local<'s, v8::Value>,
+) -> Result<v8::Local<'s, v8::Value>, crate::Error> {
+    let value = TypeHooks::from_scope(scope).decode(scope, value)?;
+    NumberPolicy::from_scope(scope).apply(scope, value)
+}
+
+/// Decodes a value with `serde_v8`, optionally limiting how deeply it can be nested
+fn decode_limited<'s, T>(
+    scope: &mut HandleScope<'s>,
+    value: v8::Local<'s, v8::Value>,
+    limit: Option<&DepthLimit>,
+) -> Result<T, crate::Error>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let decode = |scope: &mut HandleScope<'s>, value| {
+        let mut deserializer = deno_core::serde_v8::Deserializer::new(scope, value, None);
+        match limit {
+            Some(limit) => limit.deserialize(&mut deserializer),
+            None => T::deserialize(&mut deserializer),
+        }
+    };
+
+    match decode(scope, value) {
+        Err(deno_core::serde_v8::Error::UnsupportedType) => {
+            let value = bigint::normalize_bigints(scope, value)?;
+            Ok(decode(scope, value)?)
+        }
+        result => Ok(result?),
+    }
+}
+
+/// Returns true if `T` deserializes as a reference to the original v8 value, instead of traversing it
+/// This is the case for the types in [`crate::js_value`], which wrap `serde_v8`'s reference magic types
+pub(crate) fn is_v8_reference<T>() -> bool
+where
+    T: serde::de::DeserializeOwned,
+{
+    let Some(name) = MagicProbe::struct_name::<T>() else {
+        return false;
+    };
+
+    // Magic types that hold the original value, rather than a copy of its contents
+    let references = [
+        MagicProbe::struct_name::<GlobalValue>(),
+        MagicProbe::struct_name::<deno_core::serde_v8::Value<'static>>(),
+    ];
+    references.contains(&Some(name))
+}
+
+/// A deserializer that fails immediately, capturing the name of any struct requested
+/// Used to detect `serde_v8` magic types without a v8 value to decode
+struct MagicProbe;
+impl MagicProbe {
+    /// Returns the name of the struct `T` asks to deserialize, if any
+    fn struct_name<T: serde::de::DeserializeOwned>() -> Option<&'static str> {
+        match T::deserialize(MagicProbe) {
+            Err(ProbeError(name)) => name,
+            Ok(_) => None,
+        }
+    }
+}
+
+/// The error returned by [`MagicProbe`], holding the name of the struct requested
+#[derive(Debug)]
+struct ProbeError(Option<&'static str>);
+impl std::fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "probed struct: {:?}", self.0)
+    }
+}
+impl std::error::Error for ProbeError {}
+impl serde::de::Error for ProbeError {
+    fn custom<M: std::fmt::Display>(_: M) -> Self {
+        Self(None)
+    }
+}
+
+impl<'de> serde::Deserializer<'de> for MagicProbe {
+    type Error = ProbeError;
+
+    fn deserialize_any<V: serde::de::Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        Err(ProbeError(None))
+    }
+
+    fn deserialize_struct<V: serde::de::Visitor<'de>>(
+        self,
+        name: &'static str,
+        _: &'static [&'static str],
+        _: V,
+    ) -> Result<V::Value, Self::Error> {
+        Err(ProbeError(Some(name)))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -264,4 +475,41 @@ mod test {
             .into_inner()
             .as_local(&mut runtime.deno_runtime().handle_scope());
     }
+
+    #[test]
+    fn test_value_as_argument() {
+        let module = Module::new(
+            "test.js",
+            "
+            export const obj = { a: 1 };
+            export const same = (a, b) => a === b;
+            export const apply = (f, x) => f(x);
+            export const double = (x) => x * 2;
+            export const awaited = async (p) => await p;
+            export const p = Promise.resolve(7);
+        ",
+        );
+
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let handle = runtime.load_module(&module).unwrap();
+
+        // Passed by reference - identity is preserved
+        let obj: Value = runtime.get_value(Some(&handle), "obj").unwrap();
+        let same: bool = runtime
+            .call_function(Some(&handle), "same", &(&obj, &obj))
+            .unwrap();
+        assert!(same);
+
+        let double: Function = runtime.get_value(Some(&handle), "double").unwrap();
+        let value: usize = runtime
+            .call_function(Some(&handle), "apply", &(&double, 21))
+            .unwrap();
+        assert_eq!(value, 42);
+
+        let p: Promise<usize> = runtime.get_value(Some(&handle), "p").unwrap();
+        let value: usize = runtime
+            .call_function(Some(&handle), "awaited", &(p,))
+            .unwrap();
+        assert_eq!(value, 7);
+    }
 }
@@ -0,0 +1,206 @@
+use deno_core::v8::{self, HandleScope};
+
+use super::number_policy::MAX_SAFE_INTEGER;
+
+/// A Deserializable javascript `BigInt`, with arbitrary precision
+///
+/// Unlike the other types in this module, the value is decoded eagerly
+/// so it does not need to outlive the runtime it was birthed from
+///
+/// Can be used as a field in returned objects, or passed back into the runtime as a function argument
+#[derive(Eq, Hash, PartialEq, PartialOrd, Ord, Debug, Clone, Default)]
+pub struct BigInt(num_bigint::BigInt);
+
+impl BigInt {
+    /// Creates a new `BigInt` from an arbitrary precision integer
+    #[must_use]
+    pub fn new(value: num_bigint::BigInt) -> Self {
+        Self(value)
+    }
+
+    /// Returns a reference to the underlying arbitrary precision integer
+    #[must_use]
+    pub fn as_bigint(&self) -> &num_bigint::BigInt {
+        &self.0
+    }
+
+    /// Consumes this struct and returns the underlying arbitrary precision integer
+    #[must_use]
+    pub fn into_bigint(self) -> num_bigint::BigInt {
+        self.0
+    }
+
+    /// Converts the value to an `i128`
+    /// Returns None if the value does not fit
+    #[must_use]
+    pub fn to_i128(&self) -> Option<i128> {
+        i128::try_from(&self.0).ok()
+    }
+
+    /// Converts the value to a `u128`
+    /// Returns None if the value is negative or does not fit
+    #[must_use]
+    pub fn to_u128(&self) -> Option<u128> {
+        u128::try_from(&self.0).ok()
+    }
+}
+
+impl std::fmt::Display for BigInt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+macro_rules! impl_bigint_from {
+    ($($t:ty),+) => {
+        $(
+            impl From<$t> for BigInt {
+                fn from(value: $t) -> Self {
+                    Self(value.into())
+                }
+            }
+        )+
+    };
+}
+impl_bigint_from!(i64, u64, i128, u128, num_bigint::BigInt);
+
+impl From<BigInt> for num_bigint::BigInt {
+    fn from(value: BigInt) -> Self {
+        value.0
+    }
+}
+
+impl TryFrom<BigInt> for i128 {
+    type Error = crate::Error;
+    fn try_from(value: BigInt) -> Result<Self, Self::Error> {
+        value.to_i128().ok_or_else(|| {
+            crate::Error::JsonDecode(format!("BigInt `{value}` does not fit in i128"))
+        })
+    }
+}
+
+impl TryFrom<BigInt> for u128 {
+    type Error = crate::Error;
+    fn try_from(value: BigInt) -> Result<Self, Self::Error> {
+        value.to_u128().ok_or_else(|| {
+            crate::Error::JsonDecode(format!("BigInt `{value}` does not fit in u128"))
+        })
+    }
+}
+
+impl serde::Serialize for BigInt {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        deno_core::serde_v8::BigInt::from(self.0.clone()).serialize(serializer)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for BigInt {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = deno_core::serde_v8::BigInt::deserialize(deserializer)?;
+        Ok(Self(value.into()))
+    }
+}
+
+/// Returns a copy of `value` with all nested `BigInt`s replaced by values `serde_v8` can decode generically
+///
+/// `BigInt`s within the safe integer range become numbers, and larger ones become decimal strings
+pub(crate) fn normalize_bigints<'s>(
+    scope: &mut HandleScope<'s>,
+    value: v8::Local<'s, v8::Value>,
+) -> Result<v8::Local<'s, v8::Value>, crate::Error> {
+    super::traverse::map_leaves(scope, value, &mut |scope, value| {
+        let Ok(bigint) = v8::Local::<v8::BigInt>::try_from(value) else {
+            return Ok(value);
+        };
+
+        let (i, lossless) = bigint.i64_value();
+        if lossless && (-MAX_SAFE_INTEGER..=MAX_SAFE_INTEGER).contains(&i) {
+            #[allow(clippy::cast_precision_loss)]
+            return Ok(v8::Number::new(scope, i as f64).into());
+        }
+        Ok(value.to_string(scope).map_or(value, Into::into))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Module, Runtime, RuntimeOptions};
+
+    #[derive(serde::Deserialize)]
+    struct Nested {
+        amount: BigInt,
+        count: usize,
+    }
+
+    #[test]
+    fn test_bigint() {
+        let module = Module::new(
+            "test.js",
+            "
+            export const small = 42n;
+            export const big = 170141183460469231731687303715884105727n;
+            export const negative = -12345678901234567890n;
+            export const nested = { amount: 12345678901234567890123n, count: 3 };
+            export const echo = (n) => n + 1n;
+        ",
+        );
+
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let handle = runtime.load_module(&module).unwrap();
+
+        let value: BigInt = runtime.get_value(Some(&handle), "small").unwrap();
+        assert_eq!(value.to_i128(), Some(42));
+        assert_eq!(value.to_u128(), Some(42));
+
+        let value: BigInt = runtime.get_value(Some(&handle), "big").unwrap();
+        assert_eq!(value.to_i128(), Some(i128::MAX));
+
+        let value: BigInt = runtime.get_value(Some(&handle), "negative").unwrap();
+        assert_eq!(value.to_i128(), Some(-12_345_678_901_234_567_890));
+        assert_eq!(value.to_u128(), None);
+
+        let value: Nested = runtime.get_value(Some(&handle), "nested").unwrap();
+        assert_eq!(value.amount.to_string(), "12345678901234567890123");
+        assert_eq!(value.count, 3);
+
+        let value: BigInt = runtime
+            .call_function(Some(&handle), "echo", &(BigInt::from(u128::MAX - 1),))
+            .unwrap();
+        assert_eq!(value.to_u128(), Some(u128::MAX));
+    }
+
+    #[test]
+    fn test_decode_nested_bigints() {
+        let module = Module::new(
+            "test.js",
+            "
+            export const nested = {
+                small: 42n,
+                big: 12345678901234567890123n,
+                list: [1n, 'a'],
+            };
+        ",
+        );
+
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let handle = runtime.load_module(&module).unwrap();
+
+        let value: deno_core::serde_json::Value =
+            runtime.get_value(Some(&handle), "nested").unwrap();
+        assert_eq!(
+            value,
+            deno_core::serde_json::json!({
+                "small": 42,
+                "big": "12345678901234567890123",
+                "list": [1, "a"],
+            })
+        );
+    }
+}
@@ -0,0 +1,439 @@
+use deno_core::v8::{self, GetPropertyNamesArgs, HandleScope};
+use std::fmt::Write;
+
+/// Controls how circular references are handled when decoding values
+///
+/// Decoding a value that refers back to itself would otherwise recurse until the stack overflows
+///
+/// Values decoded directly into [`crate::js_value::Value`] and its specializations are never checked,
+/// since they are not traversed. Fields of those types nested inside other values are checked
+///
+/// Values are only walked for circular references if decoding them nests deeper than
+/// [`crate::RuntimeOptions::max_decode_depth`], so most decodes never walk.
+/// The walk does not run getters or Proxy traps, and does not look behind them - a circular reference hidden
+/// there fails to decode once nested too deeply instead
+///
+/// Set with [`crate::RuntimeOptions::cycle_policy`]
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Default)]
+pub enum CyclePolicy {
+    /// Return [`crate::Error::CircularReference`], identifying the cyclic path
+    /// This is the default behaviour
+    #[default]
+    Error,
+
+    /// Replace each circular reference with an object of the form `{ "$ref": "$.path.to.target" }`
+    Reference,
+
+    /// Do not check for circular references
+    /// Decoding a circular value will overflow the stack
+    Unchecked,
+}
+
+impl CyclePolicy {
+    /// Returns the policy configured for the runtime owning `scope`
+    pub(crate) fn from_scope(scope: &mut HandleScope) -> Self {
+        let state = deno_core::JsRuntime::op_state_from(scope);
+        let state = state.borrow();
+        state.try_borrow::<Self>().copied().unwrap_or_default()
+    }
+
+    /// Applies the policy to `value`, and any values nested within it
+    ///
+    /// Properties are read through their descriptors, so getters and Proxy traps are never run
+    /// Values behind getters and Proxies are not checked
+    ///
+    /// # Errors
+    /// Will return an error if the policy is [`CyclePolicy::Error`] and a circular reference is found
+    pub(crate) fn apply<'s>(
+        self,
+        scope: &mut HandleScope<'s>,
+        value: v8::Local<'s, v8::Value>,
+    ) -> Result<v8::Local<'s, v8::Value>, crate::Error> {
+        if self == Self::Unchecked || !is_traversed(value) {
+            return Ok(value);
+        }
+
+        let mut walker = Walker {
+            policy: self,
+            value_key: v8::String::new(scope, "value").unwrap(),
+            get_key: v8::String::new(scope, "get").unwrap(),
+            set_key: v8::String::new(scope, "set").unwrap(),
+            path: Vec::new(),
+            parents: Vec::new(),
+        };
+        let replaced = walker.walk(scope, value)?;
+        Ok(replaced.unwrap_or(value))
+    }
+}
+
+/// True for the arrays and plain objects walked for circular references
+fn is_traversed(value: v8::Local<v8::Value>) -> bool {
+    value.is_object()
+        && !value.is_function()
+        && !value.is_promise()
+        && !value.is_proxy()
+        && !value.is_array_buffer()
+        && !value.is_array_buffer_view()
+}
+
+/// A step from a value to one nested within it
+#[derive(Clone, Copy)]
+enum Segment<'s> {
+    Index(u32),
+    Key(v8::Local<'s, v8::String>),
+}
+
+/// A property read without running its getter
+enum Property<'s> {
+    Data(v8::Local<'s, v8::Value>),
+    Accessor(v8::Local<'s, v8::Value>, v8::Local<'s, v8::Value>),
+}
+
+struct Walker<'s> {
+    policy: CyclePolicy,
+    value_key: v8::Local<'s, v8::String>,
+    get_key: v8::Local<'s, v8::String>,
+    set_key: v8::Local<'s, v8::String>,
+
+    /// Path from the root to the value being walked
+    path: Vec<Segment<'s>>,
+
+    /// Values being walked, and the length of the path to each
+    parents: Vec<(v8::Local<'s, v8::Value>, usize)>,
+}
+
+impl<'s> Walker<'s> {
+    /// Walks arrays and plain objects, returning a copy of `value` only if something within it was replaced
+    fn walk(
+        &mut self,
+        scope: &mut HandleScope<'s>,
+        value: v8::Local<'s, v8::Value>,
+    ) -> Result<Option<v8::Local<'s, v8::Value>>, crate::Error> {
+        if !is_traversed(value) {
+            return Ok(None);
+        }
+
+        if let Some(&(_, target)) = self
+            .parents
+            .iter()
+            .find(|(parent, _)| parent.strict_equals(value))
+        {
+            let target = render_path(scope, &self.path[..target]);
+            return match self.policy {
+                CyclePolicy::Reference => {
+                    let reference = v8::Object::new(scope);
+                    let key = v8::String::new(scope, "$ref").unwrap();
+                    let target = v8::String::new(scope, &target).unwrap();
+                    reference.set(scope, key.into(), target.into());
+                    Ok(Some(reference.into()))
+                }
+                _ => Err(crate::Error::CircularReference {
+                    path: render_path(scope, &self.path),
+                    target,
+                }),
+            };
+        }
+
+        let object = v8::Local::<v8::Object>::try_from(value)
+            .expect("Checked above that value is an object");
+        self.parents.push((value, self.path.len()));
+        let result = if let Ok(array) = v8::Local::<v8::Array>::try_from(value) {
+            self.walk_array(scope, array)
+        } else {
+            self.walk_object(scope, object)
+        };
+        self.parents.pop();
+
+        result
+    }
+
+    fn walk_array(
+        &mut self,
+        scope: &mut HandleScope<'s>,
+        array: v8::Local<'s, v8::Array>,
+    ) -> Result<Option<v8::Local<'s, v8::Value>>, crate::Error> {
+        let mut elements = Vec::new();
+        let mut changed = false;
+        for i in 0..array.length() {
+            let key = v8::Integer::new_from_unsigned(scope, i);
+            let key = key.to_string(scope).unwrap();
+            let Some(element) = self.property(scope, array.into(), key) else {
+                continue;
+            };
+
+            self.path.push(Segment::Index(i));
+            let replaced = self.walk_property(scope, &element);
+            self.path.pop();
+
+            if let Some(replaced) = replaced? {
+                changed = true;
+                elements.push((key, Property::Data(replaced)));
+            } else if self.policy == CyclePolicy::Reference {
+                elements.push((key, element));
+            }
+        }
+
+        if !changed {
+            return Ok(None);
+        }
+
+        let length = i32::try_from(array.length()).unwrap_or(i32::MAX);
+        let copy = v8::Array::new(scope, length);
+        Ok(Some(fill(scope, copy.into(), elements)))
+    }
+
+    fn walk_object(
+        &mut self,
+        scope: &mut HandleScope<'s>,
+        object: v8::Local<'s, v8::Object>,
+    ) -> Result<Option<v8::Local<'s, v8::Value>>, crate::Error> {
+        let Some(keys) = object.get_own_property_names(scope, GetPropertyNamesArgs::default())
+        else {
+            return Ok(None);
+        };
+
+        let mut properties = Vec::new();
+        let mut changed = false;
+        for i in 0..keys.length() {
+            let Some(key) = keys.get_index(scope, i).and_then(|k| k.to_string(scope)) else {
+                continue;
+            };
+            let Some(property) = self.property(scope, object, key) else {
+                continue;
+            };
+
+            self.path.push(Segment::Key(key));
+            let replaced = self.walk_property(scope, &property);
+            self.path.pop();
+
+            if let Some(replaced) = replaced? {
+                changed = true;
+                properties.push((key, Property::Data(replaced)));
+            } else if self.policy == CyclePolicy::Reference {
+                properties.push((key, property));
+            }
+        }
+
+        if !changed {
+            return Ok(None);
+        }
+
+        let copy = v8::Object::new(scope);
+        Ok(Some(fill(scope, copy, properties)))
+    }
+
+    /// Walks the value of a data property - accessors are not walked
+    fn walk_property(
+        &mut self,
+        scope: &mut HandleScope<'s>,
+        property: &Property<'s>,
+    ) -> Result<Option<v8::Local<'s, v8::Value>>, crate::Error> {
+        match property {
+            Property::Data(value) => self.walk(scope, *value),
+            Property::Accessor(..) => Ok(None),
+        }
+    }
+
+    /// Reads a property of `object` or its prototypes from its descriptor, without running getters
+    fn property(
+        &self,
+        scope: &mut HandleScope<'s>,
+        object: v8::Local<'s, v8::Object>,
+        key: v8::Local<'s, v8::String>,
+    ) -> Option<Property<'s>> {
+        let mut object = object;
+        let descriptor = loop {
+            let descriptor = object.get_own_property_descriptor(scope, key.into())?;
+            if let Ok(descriptor) = v8::Local::<v8::Object>::try_from(descriptor) {
+                break descriptor;
+            }
+
+            let prototype = object.get_prototype(scope)?;
+            if !prototype.is_object() || prototype.is_proxy() {
+                return None;
+            }
+            object = prototype.try_into().ok()?;
+        };
+
+        if descriptor.has_own_property(scope, self.value_key.into())? {
+            let value = descriptor.get(scope, self.value_key.into())?;
+            Some(Property::Data(value))
+        } else {
+            let get = descriptor.get(scope, self.get_key.into())?;
+            let set = descriptor.get(scope, self.set_key.into())?;
+            Some(Property::Accessor(get, set))
+        }
+    }
+}
+
+/// Copies properties into `copy`, keeping accessors as accessors
+fn fill<'s>(
+    scope: &mut HandleScope<'s>,
+    copy: v8::Local<'s, v8::Object>,
+    properties: Vec<(v8::Local<'s, v8::String>, Property<'s>)>,
+) -> v8::Local<'s, v8::Value> {
+    for (key, property) in properties {
+        match property {
+            Property::Data(value) => {
+                copy.set(scope, key.into(), value);
+            }
+            Property::Accessor(get, set) => {
+                let mut descriptor = v8::PropertyDescriptor::new_from_get_set(get, set);
+                descriptor.set_enumerable(true);
+                descriptor.set_configurable(true);
+                copy.define_property(scope, key.into(), &descriptor);
+            }
+        }
+    }
+
+    copy.into()
+}
+
+/// Renders a path in the form `$.key[0]`
+fn render_path(scope: &mut HandleScope, path: &[Segment]) -> String {
+    let mut rendered = String::from("$");
+    for segment in path {
+        match segment {
+            Segment::Index(i) => {
+                let _ = write!(rendered, "[{i}]");
+            }
+            Segment::Key(key) => {
+                rendered.push('.');
+                rendered.push_str(&key.to_rust_string_lossy(scope));
+            }
+        }
+    }
+    rendered
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{js_value::Value, Error, Module, Runtime, RuntimeOptions};
+
+    #[test]
+    fn test_cycle_policy() {
+        let module = Module::new(
+            "test.js",
+            "
+            const shared = { n: 1 };
+            export const acyclic = { a: shared, b: shared };
+
+            export const cyclic = { a: { list: [1] } };
+            cyclic.a.list.push(cyclic);
+        ",
+        );
+
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let handle = runtime.load_module(&module).unwrap();
+
+        // Shared references are not cycles
+        let value: deno_core::serde_json::Value =
+            runtime.get_value(Some(&handle), "acyclic").unwrap();
+        assert_eq!(value["b"]["n"], 1);
+
+        let e = runtime
+            .get_value::<deno_core::serde_json::Value>(Some(&handle), "cyclic")
+            .expect_err("Did not detect circular reference");
+        assert!(
+            matches!(&e, Error::CircularReference { path, target } if path == "$.a.list[1]" && target == "$"),
+            "Unexpected error: {e}"
+        );
+
+        // References to the original value are not traversed
+        let _: Value = runtime.get_value(Some(&handle), "cyclic").unwrap();
+
+        let mut runtime = Runtime::new(RuntimeOptions {
+            cycle_policy: CyclePolicy::Reference,
+            ..Default::default()
+        })
+        .unwrap();
+        let handle = runtime.load_module(&module).unwrap();
+        let value: deno_core::serde_json::Value =
+            runtime.get_value(Some(&handle), "cyclic").unwrap();
+        assert_eq!(
+            value,
+            deno_core::serde_json::json!({ "a": { "list": [1, { "$ref": "$" }] } })
+        );
+    }
+
+    #[test]
+    fn test_cycle_policy_getters() {
+        let module = Module::new(
+            "test.js",
+            "
+            export let reads = 0;
+            export const counted = { get value() { reads++; return { n: reads }; } };
+
+            export const hidden = { get self() { return hidden; } };
+        ",
+        );
+
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let handle = runtime.load_module(&module).unwrap();
+
+        // Getters run once per decode, as they are not walked
+        let value: deno_core::serde_json::Value =
+            runtime.get_value(Some(&handle), "counted").unwrap();
+        assert_eq!(value["value"]["n"], 1);
+        let reads: usize = runtime.get_value(Some(&handle), "reads").unwrap();
+        assert_eq!(reads, 1);
+
+        let _: deno_core::serde_json::Value = runtime.get_value(Some(&handle), "counted").unwrap();
+        let reads: usize = runtime.get_value(Some(&handle), "reads").unwrap();
+        assert_eq!(reads, 2);
+
+        // Circular references behind getters fail to decode, instead of overflowing the stack
+        let e = runtime
+            .get_value::<deno_core::serde_json::Value>(Some(&handle), "hidden")
+            .expect_err("Decoded a circular reference");
+        assert!(
+            matches!(e, Error::DecodeDepthExceeded(256)),
+            "Unexpected error: {e}"
+        );
+    }
+
+    #[test]
+    fn test_max_decode_depth() {
+        let module = Module::new(
+            "test.js",
+            "
+            export const nest = (depth) => {
+                let value = 1;
+                for (let i = 0; i < depth; i++) value = [value];
+                return value;
+            };
+        ",
+        );
+
+        // Values nested up to the limit decode, deeper ones fail with a dedicated error
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let handle = runtime.load_module(&module).unwrap();
+        let _: deno_core::serde_json::Value = runtime
+            .call_function(Some(&handle), "nest", &(200,))
+            .unwrap();
+        let e = runtime
+            .call_function::<deno_core::serde_json::Value>(Some(&handle), "nest", &(300,))
+            .expect_err("Decoded a value nested too deeply");
+        assert!(
+            matches!(e, Error::DecodeDepthExceeded(256)),
+            "Unexpected error: {e}"
+        );
+
+        let mut runtime = Runtime::new(RuntimeOptions {
+            max_decode_depth: 8,
+            ..Default::default()
+        })
+        .unwrap();
+        let handle = runtime.load_module(&module).unwrap();
+        let _: deno_core::serde_json::Value =
+            runtime.call_function(Some(&handle), "nest", &(7,)).unwrap();
+        let e = runtime
+            .call_function::<deno_core::serde_json::Value>(Some(&handle), "nest", &(9,))
+            .expect_err("Decoded a value nested too deeply");
+        assert!(
+            matches!(e, Error::DecodeDepthExceeded(8)),
+            "Unexpected error: {e}"
+        );
+    }
+}
@@ -0,0 +1,364 @@
+use deno_core::v8::HandleScope;
+use serde::de::{
+    self, DeserializeSeed, Deserializer, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor,
+};
+use std::{cell::Cell, fmt};
+
+/// The deepest a decoded value can be nested, see [`crate::RuntimeOptions::max_decode_depth`]
+///
+/// Guards against circular references hidden behind getters and Proxies, which are not walked
+#[derive(Clone, Copy)]
+pub(crate) struct MaxDecodeDepth(pub usize);
+
+impl MaxDecodeDepth {
+    /// Returns the depth configured for the runtime owning `scope`
+    pub(crate) fn from_scope(scope: &mut HandleScope) -> usize {
+        let state = deno_core::JsRuntime::op_state_from(scope);
+        let state = state.borrow();
+        state.try_borrow::<Self>().map_or(256, |depth| depth.0)
+    }
+}
+
+/// Limits how deeply nested a value can be while it is deserialized
+///
+/// Lets values be decoded without first being walked for circular references, see [`super::CyclePolicy`]
+/// Past the limit, deserialization fails and [`DepthLimit::exceeded`] is set
+pub(crate) struct DepthLimit {
+    limit: usize,
+    exceeded: Cell<bool>,
+}
+
+impl DepthLimit {
+    pub(crate) fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            exceeded: Cell::new(false),
+        }
+    }
+
+    /// True if deserialization failed because the value was nested too deeply
+    pub(crate) fn exceeded(&self) -> bool {
+        self.exceeded.get()
+    }
+
+    /// Deserializes `T`, failing if the value is nested more than `limit` sequences or maps deep
+    pub(crate) fn deserialize<'de, T, D>(&self, deserializer: D) -> Result<T, D::Error>
+    where
+        T: de::Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        T::deserialize(Limited {
+            limit: self,
+            depth: 0,
+            inner: deserializer,
+        })
+    }
+
+    fn enter<E: de::Error>(&self, depth: usize) -> Result<usize, E> {
+        if depth >= self.limit {
+            self.exceeded.set(true);
+            return Err(E::custom(format!(
+                "value is nested more than {} levels deep",
+                self.limit
+            )));
+        }
+
+        Ok(depth + 1)
+    }
+}
+
+/// Wraps a deserializer, so that the visitors it calls are limited
+struct Limited<'a, D> {
+    limit: &'a DepthLimit,
+    depth: usize,
+    inner: D,
+}
+
+/// Wraps a visitor, so that the sequences and maps it visits are limited
+struct Wrap<'a, V> {
+    limit: &'a DepthLimit,
+    depth: usize,
+    inner: V,
+}
+
+/// Wraps sequence, map and enum access, so that the values they deserialize are limited
+struct Access<'a, A> {
+    limit: &'a DepthLimit,
+    depth: usize,
+    inner: A,
+}
+
+/// Wraps a seed, so that the deserializer it is given is limited
+struct Seed<'a, S> {
+    limit: &'a DepthLimit,
+    depth: usize,
+    inner: S,
+}
+
+macro_rules! forward_deserialize {
+    ($($method:ident($($arg:ident: $ty:ty),*)),* $(,)?) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, Self::Error> {
+                let visitor = Wrap {
+                    limit: self.limit,
+                    depth: self.depth,
+                    inner: visitor,
+                };
+                self.inner.$method($($arg,)* visitor)
+            }
+        )*
+    };
+}
+
+impl<'de, D: Deserializer<'de>> Deserializer<'de> for Limited<'_, D> {
+    type Error = D::Error;
+
+    forward_deserialize! {
+        deserialize_any(), deserialize_bool(),
+        deserialize_i8(), deserialize_i16(), deserialize_i32(), deserialize_i64(), deserialize_i128(),
+        deserialize_u8(), deserialize_u16(), deserialize_u32(), deserialize_u64(), deserialize_u128(),
+        deserialize_f32(), deserialize_f64(), deserialize_char(), deserialize_str(), deserialize_string(),
+        deserialize_bytes(), deserialize_byte_buf(), deserialize_option(), deserialize_unit(),
+        deserialize_unit_struct(name: &'static str),
+        deserialize_newtype_struct(name: &'static str),
+        deserialize_seq(),
+        deserialize_tuple(len: usize),
+        deserialize_tuple_struct(name: &'static str, len: usize),
+        deserialize_map(),
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]),
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]),
+        deserialize_identifier(), deserialize_ignored_any(),
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
+
+macro_rules! forward_visit {
+    ($($method:ident($ty:ty)),* $(,)?) => {
+        $(
+            fn $method<E: de::Error>(self, v: $ty) -> Result<Self::Value, E> {
+                self.inner.$method(v)
+            }
+        )*
+    };
+}
+
+impl<'de, V: Visitor<'de>> Visitor<'de> for Wrap<'_, V> {
+    type Value = V::Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.inner.expecting(formatter)
+    }
+
+    forward_visit! {
+        visit_bool(bool),
+        visit_i8(i8), visit_i16(i16), visit_i32(i32), visit_i64(i64), visit_i128(i128),
+        visit_u8(u8), visit_u16(u16), visit_u32(u32), visit_u64(u64), visit_u128(u128),
+        visit_f32(f32), visit_f64(f64), visit_char(char),
+        visit_str(&str), visit_borrowed_str(&'de str), visit_string(String),
+        visit_bytes(&[u8]), visit_borrowed_bytes(&'de [u8]), visit_byte_buf(Vec<u8>),
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        self.inner.visit_none()
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        self.inner.visit_unit()
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        self.inner.visit_some(Limited {
+            limit: self.limit,
+            depth: self.depth,
+            inner: deserializer,
+        })
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error> {
+        self.inner.visit_newtype_struct(Limited {
+            limit: self.limit,
+            depth: self.depth,
+            inner: deserializer,
+        })
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+        let depth = self.limit.enter(self.depth)?;
+        self.inner.visit_seq(Access {
+            limit: self.limit,
+            depth,
+            inner: seq,
+        })
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        let depth = self.limit.enter(self.depth)?;
+        self.inner.visit_map(Access {
+            limit: self.limit,
+            depth,
+            inner: map,
+        })
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+        let depth = self.limit.enter(self.depth)?;
+        self.inner.visit_enum(Access {
+            limit: self.limit,
+            depth,
+            inner: data,
+        })
+    }
+}
+
+impl<'a, A> Access<'a, A> {
+    fn seed<S>(&self, seed: S) -> Seed<'a, S> {
+        Seed {
+            limit: self.limit,
+            depth: self.depth,
+            inner: seed,
+        }
+    }
+
+    fn visitor<V>(&self, visitor: V) -> Wrap<'a, V> {
+        Wrap {
+            limit: self.limit,
+            depth: self.depth,
+            inner: visitor,
+        }
+    }
+}
+
+impl<'de, A: SeqAccess<'de>> SeqAccess<'de> for Access<'_, A> {
+    type Error = A::Error;
+
+    fn next_element_seed<S: DeserializeSeed<'de>>(
+        &mut self,
+        seed: S,
+    ) -> Result<Option<S::Value>, Self::Error> {
+        let seed = self.seed(seed);
+        self.inner.next_element_seed(seed)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint()
+    }
+}
+
+impl<'de, A: MapAccess<'de>> MapAccess<'de> for Access<'_, A> {
+    type Error = A::Error;
+
+    fn next_key_seed<S: DeserializeSeed<'de>>(
+        &mut self,
+        seed: S,
+    ) -> Result<Option<S::Value>, Self::Error> {
+        let seed = self.seed(seed);
+        self.inner.next_key_seed(seed)
+    }
+
+    fn next_value_seed<S: DeserializeSeed<'de>>(
+        &mut self,
+        seed: S,
+    ) -> Result<S::Value, Self::Error> {
+        let seed = self.seed(seed);
+        self.inner.next_value_seed(seed)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, 'de, A: EnumAccess<'de>> EnumAccess<'de> for Access<'a, A> {
+    type Error = A::Error;
+    type Variant = Access<'a, A::Variant>;
+
+    fn variant_seed<S: DeserializeSeed<'de>>(
+        self,
+        seed: S,
+    ) -> Result<(S::Value, Self::Variant), Self::Error> {
+        let seed = self.seed(seed);
+        let (value, variant) = self.inner.variant_seed(seed)?;
+        Ok((
+            value,
+            Access {
+                limit: self.limit,
+                depth: self.depth,
+                inner: variant,
+            },
+        ))
+    }
+}
+
+impl<'de, A: VariantAccess<'de>> VariantAccess<'de> for Access<'_, A> {
+    type Error = A::Error;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        self.inner.unit_variant()
+    }
+
+    fn newtype_variant_seed<S: DeserializeSeed<'de>>(
+        self,
+        seed: S,
+    ) -> Result<S::Value, Self::Error> {
+        let seed = self.seed(seed);
+        self.inner.newtype_variant_seed(seed)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        let visitor = self.visitor(visitor);
+        self.inner.tuple_variant(len, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        let visitor = self.visitor(visitor);
+        self.inner.struct_variant(fields, visitor)
+    }
+}
+
+impl<'de, S: DeserializeSeed<'de>> DeserializeSeed<'de> for Seed<'_, S> {
+    type Value = S::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        self.inner.deserialize(Limited {
+            limit: self.limit,
+            depth: self.depth,
+            inner: deserializer,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DepthLimit;
+    use deno_core::serde_json::{self, json};
+
+    #[test]
+    fn test_depth_limit() {
+        let value = json!({ "a": [1, { "b": [2] }], "c": null });
+
+        let limit = DepthLimit::new(4);
+        let decoded: serde_json::Value = limit.deserialize(value.clone()).unwrap();
+        assert_eq!(decoded, value);
+        assert!(!limit.exceeded());
+
+        let limit = DepthLimit::new(3);
+        limit
+            .deserialize::<serde_json::Value, _>(value)
+            .expect_err("Depth limit was not applied");
+        assert!(limit.exceeded());
+    }
+}
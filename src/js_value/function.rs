@@ -1,5 +1,8 @@
 use super::V8Value;
-use deno_core::v8::{self, HandleScope};
+use deno_core::{
+    serde_json,
+    v8::{self, HandleScope},
+};
 use serde::Deserialize;
 
 /// A Deserializable javascript function, that can be stored and used later
@@ -85,6 +88,28 @@ impl Function {
     }
 }
 
+/// Stands in for a javascript function passed as an argument to a registered rust function
+///
+/// Arguments cross over as JSON, so each function is replaced by a token - which can be stored,
+/// and later exchanged for the function itself with [`crate::Runtime::take_function`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, Deserialize)]
+pub struct FunctionToken {
+    #[serde(rename = "$rustyscript_function")]
+    id: u32,
+}
+
+impl FunctionToken {
+    /// Reads a token from an argument, if the script passed a function
+    #[must_use]
+    pub fn from_value(value: &serde_json::Value) -> Option<Self> {
+        Self::deserialize(value).ok()
+    }
+
+    pub(crate) fn id(self) -> u32 {
+        self.id
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -0,0 +1,492 @@
+//! Typed, reusable invokers for stored functions, see [`TypedInvoker`]
+use super::Function;
+use crate::{async_bridge::AsyncBridgeExt, Error, ModuleHandle, Runtime};
+use deno_core::{
+    v8::{self, HandleScope},
+    PollEventLoopOptions,
+};
+use std::{collections::HashMap, marker::PhantomData};
+
+/// Encodes the arguments of a [`TypedInvoker`] call directly into v8 values
+///
+/// Property names given to [`ArgEncoder::key`] are created once per invoker, then reused on every call
+pub struct ArgEncoder<'a, 's> {
+    scope: &'a mut HandleScope<'s>,
+    keys: &'a mut HashMap<&'static str, v8::Global<v8::String>>,
+}
+
+impl<'s> ArgEncoder<'_, 's> {
+    /// Returns the scope values are created in
+    pub fn scope(&mut self) -> &mut HandleScope<'s> {
+        self.scope
+    }
+
+    /// Returns a property name, creating it the first time it is used by the invoker
+    ///
+    /// # Errors
+    /// Will return an error if the string cannot be created
+    pub fn key(&mut self, key: &'static str) -> Result<v8::Local<'s, v8::String>, Error> {
+        if let Some(global) = self.keys.get(key) {
+            return Ok(v8::Local::new(self.scope, global));
+        }
+
+        let local =
+            v8::String::new_from_utf8(self.scope, key.as_bytes(), v8::NewStringType::Internalized)
+                .ok_or_else(|| Error::V8Encoding(key.to_string()))?;
+        self.keys.insert(key, v8::Global::new(self.scope, local));
+        Ok(local)
+    }
+
+    /// Encode a value
+    ///
+    /// # Errors
+    /// Will return an error if the value cannot be encoded
+    pub fn encode<T: InvokeArg + ?Sized>(
+        &mut self,
+        value: &T,
+    ) -> Result<v8::Local<'s, v8::Value>, Error> {
+        value.encode(self)
+    }
+
+    /// Encode an object, from its property names and values
+    ///
+    /// # Errors
+    /// Will return an error if a property cannot be encoded
+    pub fn object(
+        &mut self,
+        fields: &[(&'static str, &dyn InvokeArg)],
+    ) -> Result<v8::Local<'s, v8::Value>, Error> {
+        let object = v8::Object::new(self.scope);
+        for (name, value) in fields {
+            let key = self.key(name)?;
+            let value = value.encode(self)?;
+            object.set(self.scope, key.into(), value);
+        }
+        Ok(object.into())
+    }
+}
+
+/// A value that can be passed to a [`TypedInvoker`], encoded without going through `serde`
+///
+/// Implemented for primitives, strings, options, and sequences
+/// Structs can implement it with [`ArgEncoder::object`]:
+/// ```rust
+/// use rustyscript::{ Error, js_value::{ ArgEncoder, InvokeArg } };
+/// use deno_core::v8;
+///
+/// struct Point { x: f64, y: f64 }
+/// impl InvokeArg for Point {
+///     fn encode<'s>(&self, encoder: &mut ArgEncoder<'_, 's>) -> Result<v8::Local<'s, v8::Value>, Error> {
+///         encoder.object(&[("x", &self.x), ("y", &self.y)])
+///     }
+/// }
+/// ```
+pub trait InvokeArg {
+    /// Encode the value into a v8 value
+    ///
+    /// # Errors
+    /// Will return an error if the value cannot be encoded
+    fn encode<'s>(
+        &self,
+        encoder: &mut ArgEncoder<'_, 's>,
+    ) -> Result<v8::Local<'s, v8::Value>, Error>;
+}
+
+impl<T: InvokeArg + ?Sized> InvokeArg for &T {
+    fn encode<'s>(
+        &self,
+        encoder: &mut ArgEncoder<'_, 's>,
+    ) -> Result<v8::Local<'s, v8::Value>, Error> {
+        (**self).encode(encoder)
+    }
+}
+
+impl InvokeArg for () {
+    fn encode<'s>(
+        &self,
+        encoder: &mut ArgEncoder<'_, 's>,
+    ) -> Result<v8::Local<'s, v8::Value>, Error> {
+        Ok(v8::undefined(encoder.scope).into())
+    }
+}
+
+impl InvokeArg for bool {
+    fn encode<'s>(
+        &self,
+        encoder: &mut ArgEncoder<'_, 's>,
+    ) -> Result<v8::Local<'s, v8::Value>, Error> {
+        Ok(v8::Boolean::new(encoder.scope, *self).into())
+    }
+}
+
+macro_rules! impl_integer_arg {
+    ($($t:ty => $new:ident as $as:ty),+) => {
+        $(
+            impl InvokeArg for $t {
+                fn encode<'s>(
+                    &self,
+                    encoder: &mut ArgEncoder<'_, 's>,
+                ) -> Result<v8::Local<'s, v8::Value>, Error> {
+                    Ok(v8::Integer::$new(encoder.scope, <$as>::from(*self)).into())
+                }
+            }
+        )+
+    };
+}
+impl_integer_arg!(
+    i8 => new as i32, i16 => new as i32, i32 => new as i32,
+    u8 => new_from_unsigned as u32, u16 => new_from_unsigned as u32, u32 => new_from_unsigned as u32
+);
+
+/// Largest integer a javascript number can hold exactly
+const MAX_SAFE_INTEGER: i64 = (1 << 53) - 1;
+
+impl InvokeArg for i64 {
+    fn encode<'s>(
+        &self,
+        encoder: &mut ArgEncoder<'_, 's>,
+    ) -> Result<v8::Local<'s, v8::Value>, Error> {
+        #[allow(clippy::cast_precision_loss)]
+        if (-MAX_SAFE_INTEGER..=MAX_SAFE_INTEGER).contains(self) {
+            Ok(v8::Number::new(encoder.scope, *self as f64).into())
+        } else {
+            Ok(v8::BigInt::new_from_i64(encoder.scope, *self).into())
+        }
+    }
+}
+
+impl InvokeArg for u64 {
+    fn encode<'s>(
+        &self,
+        encoder: &mut ArgEncoder<'_, 's>,
+    ) -> Result<v8::Local<'s, v8::Value>, Error> {
+        #[allow(clippy::cast_precision_loss)]
+        if *self <= MAX_SAFE_INTEGER.unsigned_abs() {
+            Ok(v8::Number::new(encoder.scope, *self as f64).into())
+        } else {
+            Ok(v8::BigInt::new_from_u64(encoder.scope, *self).into())
+        }
+    }
+}
+
+impl InvokeArg for f32 {
+    fn encode<'s>(
+        &self,
+        encoder: &mut ArgEncoder<'_, 's>,
+    ) -> Result<v8::Local<'s, v8::Value>, Error> {
+        Ok(v8::Number::new(encoder.scope, f64::from(*self)).into())
+    }
+}
+
+impl InvokeArg for f64 {
+    fn encode<'s>(
+        &self,
+        encoder: &mut ArgEncoder<'_, 's>,
+    ) -> Result<v8::Local<'s, v8::Value>, Error> {
+        Ok(v8::Number::new(encoder.scope, *self).into())
+    }
+}
+
+impl InvokeArg for str {
+    fn encode<'s>(
+        &self,
+        encoder: &mut ArgEncoder<'_, 's>,
+    ) -> Result<v8::Local<'s, v8::Value>, Error> {
+        let value =
+            v8::String::new_from_utf8(encoder.scope, self.as_bytes(), v8::NewStringType::Normal)
+                .ok_or_else(|| Error::V8Encoding(self.to_string()))?;
+        Ok(value.into())
+    }
+}
+
+impl InvokeArg for String {
+    fn encode<'s>(
+        &self,
+        encoder: &mut ArgEncoder<'_, 's>,
+    ) -> Result<v8::Local<'s, v8::Value>, Error> {
+        self.as_str().encode(encoder)
+    }
+}
+
+impl<T: InvokeArg> InvokeArg for Option<T> {
+    fn encode<'s>(
+        &self,
+        encoder: &mut ArgEncoder<'_, 's>,
+    ) -> Result<v8::Local<'s, v8::Value>, Error> {
+        match self {
+            Some(value) => value.encode(encoder),
+            None => Ok(v8::null(encoder.scope).into()),
+        }
+    }
+}
+
+impl<T: InvokeArg> InvokeArg for [T] {
+    fn encode<'s>(
+        &self,
+        encoder: &mut ArgEncoder<'_, 's>,
+    ) -> Result<v8::Local<'s, v8::Value>, Error> {
+        let elements = self
+            .iter()
+            .map(|value| value.encode(encoder))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(v8::Array::new_with_elements(encoder.scope, &elements).into())
+    }
+}
+
+impl<T: InvokeArg> InvokeArg for Vec<T> {
+    fn encode<'s>(
+        &self,
+        encoder: &mut ArgEncoder<'_, 's>,
+    ) -> Result<v8::Local<'s, v8::Value>, Error> {
+        self.as_slice().encode(encoder)
+    }
+}
+
+impl InvokeArg for Function {
+    fn encode<'s>(
+        &self,
+        encoder: &mut ArgEncoder<'_, 's>,
+    ) -> Result<v8::Local<'s, v8::Value>, Error> {
+        let function = self.as_global(encoder.scope);
+        Ok(v8::Local::new(encoder.scope, function).into())
+    }
+}
+
+/// The argument list of a [`TypedInvoker`] - a tuple of up to 8 [`InvokeArg`]s
+pub trait InvokeArgs {
+    /// Encode each argument into a v8 value
+    ///
+    /// # Errors
+    /// Will return an error if an argument cannot be encoded
+    fn encode_args<'s>(
+        &self,
+        encoder: &mut ArgEncoder<'_, 's>,
+    ) -> Result<Vec<v8::Local<'s, v8::Value>>, Error>;
+}
+
+macro_rules! impl_invoke_args {
+    ($($name:ident),*) => {
+        impl<$($name: InvokeArg),*> InvokeArgs for ($($name,)*) {
+            #[allow(non_snake_case, unused_variables)]
+            fn encode_args<'s>(
+                &self,
+                encoder: &mut ArgEncoder<'_, 's>,
+            ) -> Result<Vec<v8::Local<'s, v8::Value>>, Error> {
+                let ($($name,)*) = self;
+                Ok(vec![$($name.encode(encoder)?),*])
+            }
+        }
+    };
+}
+impl_invoke_args!();
+impl_invoke_args!(A);
+impl_invoke_args!(A, B);
+impl_invoke_args!(A, B, C);
+impl_invoke_args!(A, B, C, D);
+impl_invoke_args!(A, B, C, D, E);
+impl_invoke_args!(A, B, C, D, E, F);
+impl_invoke_args!(A, B, C, D, E, F, G);
+impl_invoke_args!(A, B, C, D, E, F, G, H);
+
+/// A stored function, prepared to be called repeatedly with a fixed signature
+///
+/// Built once, it keeps the function and its receiver, and the property names used by its arguments,
+/// so each call skips the lookups and `serde_json` round-trip of [`Function::call`]
+///
+/// Arguments are encoded with [`InvokeArg`], and the return value is decoded as with [`Function::call`]
+/// Like the [`Function`] it was built from, an invoker must only be used with the runtime it came from
+///
+/// ```rust
+/// use rustyscript::{ js_value::{ Function, TypedInvoker }, Module, Runtime };
+///
+/// # fn main() -> Result<(), rustyscript::Error> {
+/// let mut runtime = Runtime::new(Default::default())?;
+/// let module = runtime.load_module(&Module::new("test.js", "
+///     export const scale = (values, factor) => values.map(v => v * factor);
+/// "))?;
+///
+/// let scale: Function = runtime.get_value(Some(&module), "scale")?;
+/// let mut scale: TypedInvoker<(Vec<f64>, f64), Vec<f64>> = scale.invoker(&mut runtime, Some(&module))?;
+/// for i in 0..10 {
+///     let scaled = scale.invoke(&mut runtime, (vec![1.0, 2.0], f64::from(i)))?;
+///     assert_eq!(scaled, vec![f64::from(i), 2.0 * f64::from(i)]);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct TypedInvoker<Args, Ret> {
+    function: v8::Global<v8::Function>,
+    receiver: v8::Global<v8::Value>,
+    module_context: Option<ModuleHandle>,
+    keys: HashMap<&'static str, v8::Global<v8::String>>,
+    signature: PhantomData<fn(Args) -> Ret>,
+}
+
+impl<Args, Ret> TypedInvoker<Args, Ret>
+where
+    Args: InvokeArgs,
+    Ret: serde::de::DeserializeOwned,
+{
+    /// Prepare a function to be invoked
+    ///
+    /// If `module_context` is provided, the module's namespace is used as `this` for each call,
+    /// as with [`Function::call`]
+    ///
+    /// # Errors
+    /// Will return an error if the module's namespace cannot be found
+    pub fn new(
+        runtime: &mut Runtime,
+        function: &Function,
+        module_context: Option<&ModuleHandle>,
+    ) -> Result<Self, Error> {
+        let namespace = module_context
+            .map(|module| runtime.deno_runtime().get_module_namespace(module.id()))
+            .transpose()?;
+
+        let mut scope = runtime.deno_runtime().handle_scope();
+        let function = function.as_global(&mut scope);
+        let receiver: v8::Local<v8::Value> = match namespace {
+            Some(namespace) => v8::Local::new(&mut scope, namespace).into(),
+            None => v8::undefined(&mut scope).into(),
+        };
+        let receiver = v8::Global::new(&mut scope, receiver);
+
+        Ok(Self {
+            function,
+            receiver,
+            module_context: module_context.cloned(),
+            keys: HashMap::new(),
+            signature: PhantomData,
+        })
+    }
+
+    /// Call the function
+    ///
+    /// Blocks until the returned value is available - if it is a promise, the event loop is
+    /// run until it resolves, otherwise it is returned without running the event loop
+    ///
+    /// # Errors
+    /// Will return an error if an argument cannot be encoded, if the function throws,
+    /// or if the return value cannot be deserialized into `Ret`
+    pub fn invoke(&mut self, runtime: &mut Runtime, args: Args) -> Result<Ret, Error> {
+        runtime.block_on(|runtime| async move { self.invoke_async(runtime, args).await })
+    }
+
+    /// Call the function, see [`TypedInvoker::invoke`]
+    ///
+    /// # Errors
+    /// Will return an error if an argument cannot be encoded, if the function throws,
+    /// or if the return value cannot be deserialized into `Ret`
+    pub async fn invoke_async(&mut self, runtime: &mut Runtime, args: Args) -> Result<Ret, Error> {
+        let promise = {
+            let mut scope = runtime.deno_runtime().handle_scope();
+            let mut scope = v8::TryCatch::new(&mut scope);
+
+            let args = args.encode_args(&mut ArgEncoder {
+                scope: &mut scope,
+                keys: &mut self.keys,
+            })?;
+            let function = v8::Local::new(&mut scope, &self.function);
+            let receiver = v8::Local::new(&mut scope, &self.receiver);
+
+            let Some(value) = function.call(&mut scope, receiver, &args) else {
+                return Err(if scope.has_caught() {
+                    crate::inner_runtime::caught_error(&mut scope, self.module_context.as_ref())
+                } else {
+                    Error::Runtime("Unknown error during function execution".to_string())
+                });
+            };
+
+            if !value.is_promise() {
+                return super::decode_v8(&mut scope, value);
+            }
+            v8::Global::new(&mut scope, value)
+        };
+
+        let future = runtime.deno_runtime().resolve(promise);
+        let value = runtime
+            .deno_runtime()
+            .with_event_loop_future(future, PollEventLoopOptions::default())
+            .await?;
+
+        let mut scope = runtime.deno_runtime().handle_scope();
+        let value = v8::Local::new(&mut scope, value);
+        super::decode_v8(&mut scope, value)
+    }
+}
+
+impl Function {
+    /// Prepare this function to be called repeatedly, with a fixed signature
+    /// See [`TypedInvoker`]
+    ///
+    /// # Errors
+    /// Will return an error if the module's namespace cannot be found
+    pub fn invoker<Args, Ret>(
+        &self,
+        runtime: &mut Runtime,
+        module_context: Option<&ModuleHandle>,
+    ) -> Result<TypedInvoker<Args, Ret>, Error>
+    where
+        Args: InvokeArgs,
+        Ret: serde::de::DeserializeOwned,
+    {
+        TypedInvoker::new(runtime, self, module_context)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Module, RuntimeOptions};
+
+    struct Point {
+        x: i32,
+        y: Option<i32>,
+    }
+
+    impl InvokeArg for Point {
+        fn encode<'s>(
+            &self,
+            encoder: &mut ArgEncoder<'_, 's>,
+        ) -> Result<v8::Local<'s, v8::Value>, Error> {
+            encoder.object(&[("x", &self.x), ("y", &self.y)])
+        }
+    }
+
+    #[test]
+    fn test_typed_invoker() {
+        let module = Module::new(
+            "test.js",
+            "
+            export const describe = (p, name, tags, big) =>
+                `${name}:${p.x},${p.y}:${tags.join('|')}:${typeof big}`;
+            export const add = async (a, b) => a + b;
+            export const fail = () => { throw new Error('failed'); };
+        ",
+        );
+
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let handle = runtime.load_module(&module).unwrap();
+
+        let describe: Function = runtime.get_value(Some(&handle), "describe").unwrap();
+        let mut describe: TypedInvoker<(Point, &str, Vec<String>, u64), String> =
+            describe.invoker(&mut runtime, Some(&handle)).unwrap();
+        for i in 0..3 {
+            let point = Point { x: i, y: None };
+            let value = describe
+                .invoke(&mut runtime, (point, "p", vec!["a".to_string()], u64::MAX))
+                .unwrap();
+            assert_eq!(value, format!("p:{i},null:a:bigint"));
+        }
+        assert_eq!(describe.keys.len(), 2);
+
+        let add: Function = runtime.get_value(Some(&handle), "add").unwrap();
+        let mut add: TypedInvoker<(i64, i32), i64> = add.invoker(&mut runtime, None).unwrap();
+        assert_eq!(add.invoke(&mut runtime, (2, 3)).unwrap(), 5);
+
+        let fail: Function = runtime.get_value(Some(&handle), "fail").unwrap();
+        let mut fail: TypedInvoker<(), ()> = fail.invoker(&mut runtime, Some(&handle)).unwrap();
+        fail.invoke(&mut runtime, ())
+            .expect_err("Error was not returned");
+    }
+}
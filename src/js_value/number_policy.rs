@@ -0,0 +1,114 @@
+use deno_core::v8::{self, HandleScope};
+
+/// The largest integer that can be represented exactly by a javascript number
+pub(crate) const MAX_SAFE_INTEGER: i64 = (1 << 53) - 1;
+
+/// Controls how integers outside of javascript's safe integer range are decoded
+///
+/// Numbers larger than `Number.MAX_SAFE_INTEGER` have already lost precision in javascript
+/// and would otherwise be silently rounded when decoded into rust types
+///
+/// Set with [`crate::RuntimeOptions::number_policy`]
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Default)]
+pub enum NumberPolicy {
+    /// Decode unsafe integers as-is, possibly losing precision
+    /// This is the default behaviour
+    #[default]
+    AllowLossy,
+
+    /// Return an error if a decoded value contains an unsafe integer
+    Error,
+
+    /// Convert unsafe integers to decimal strings before decoding
+    Stringify,
+}
+
+impl NumberPolicy {
+    /// Returns the policy configured for the runtime owning `scope`
+    pub(crate) fn from_scope(scope: &mut HandleScope) -> Self {
+        let state = deno_core::JsRuntime::op_state_from(scope);
+        let state = state.borrow();
+        state.try_borrow::<Self>().copied().unwrap_or_default()
+    }
+
+    /// Applies the policy to `value`, and any values nested within it
+    ///
+    /// # Errors
+    /// Will return an error if the policy is [`NumberPolicy::Error`] and an unsafe integer is found
+    pub(crate) fn apply<'s>(
+        self,
+        scope: &mut HandleScope<'s>,
+        value: v8::Local<'s, v8::Value>,
+    ) -> Result<v8::Local<'s, v8::Value>, crate::Error> {
+        if self == Self::AllowLossy {
+            return Ok(value);
+        }
+
+        super::traverse::map_leaves(scope, value, &mut |scope, value| {
+            let Ok(n) = v8::Local::<v8::Number>::try_from(value).map(|n| n.value()) else {
+                return Ok(value);
+            };
+
+            #[allow(clippy::cast_precision_loss)]
+            let is_unsafe = n.is_finite() && n.fract() == 0.0 && n.abs() > MAX_SAFE_INTEGER as f64;
+            if !is_unsafe {
+                return Ok(value);
+            }
+
+            match self {
+                Self::Error => Err(crate::Error::JsonDecode(format!(
+                    "{n} is outside of the safe integer range and cannot be decoded without losing precision"
+                ))),
+                _ => Ok(value.to_string(scope).map_or(value, Into::into)),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Module, Runtime, RuntimeOptions};
+
+    #[test]
+    fn test_number_policy() {
+        let module = Module::new(
+            "test.js",
+            "
+            export const safe = { n: 9007199254740991 };
+            export const unsafe = { n: 2 ** 60, list: [1, 2 ** 60] };
+        ",
+        );
+
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let handle = runtime.load_module(&module).unwrap();
+        let value: deno_core::serde_json::Value =
+            runtime.get_value(Some(&handle), "unsafe").unwrap();
+        assert_eq!(value["n"], 1_152_921_504_606_846_976_u64);
+
+        let mut runtime = Runtime::new(RuntimeOptions {
+            number_policy: NumberPolicy::Error,
+            ..Default::default()
+        })
+        .unwrap();
+        let handle = runtime.load_module(&module).unwrap();
+        let value: deno_core::serde_json::Value = runtime.get_value(Some(&handle), "safe").unwrap();
+        assert_eq!(value["n"], 9_007_199_254_740_991_u64);
+        runtime
+            .get_value::<deno_core::serde_json::Value>(Some(&handle), "unsafe")
+            .expect_err("Did not reject unsafe integer");
+
+        let mut runtime = Runtime::new(RuntimeOptions {
+            number_policy: NumberPolicy::Stringify,
+            ..Default::default()
+        })
+        .unwrap();
+        let handle = runtime.load_module(&module).unwrap();
+        let value: deno_core::serde_json::Value =
+            runtime.get_value(Some(&handle), "unsafe").unwrap();
+        assert_eq!(
+            value,
+            deno_core::serde_json::json!({ "n": "1152921504606846976", "list": [1, "1152921504606846976"] })
+        );
+    }
+}
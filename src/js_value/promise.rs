@@ -1,16 +1,21 @@
 use super::V8Value;
 use crate::{async_bridge::AsyncBridgeExt, Error};
 use deno_core::{
+    error::AnyError,
+    serde_json,
     v8::{self, PromiseState},
     PollEventLoopOptions,
 };
 use serde::Deserialize;
+use std::time::Duration;
 
 /// A Deserializable javascript promise, that can be stored and used later
 /// Must live as long as the runtime it was birthed from
 ///
 /// You can turn `Promise<T>` into `Future<Output = T>` by calling `Promise::into_future`
 /// This allows you to export multiple concurrent promises without borrowing the runtime mutably
+///
+/// A rejected promise resolves to [`Error::PromiseRejected`], with the reason it was rejected with
 #[derive(Eq, Hash, PartialEq, Debug, Clone)]
 pub struct Promise<T>(V8Value<PromiseTypeChecker>, std::marker::PhantomData<T>)
 where
@@ -28,13 +33,35 @@ where
         self,
         runtime: &mut deno_core::JsRuntime,
     ) -> Result<T, crate::Error> {
-        let future = runtime.resolve(self.0 .0);
+        let future = runtime.resolve(self.0 .0.clone());
         let result = runtime
             .with_event_loop_future(future, PollEventLoopOptions::default())
-            .await?;
+            .await;
+        self.settled(runtime, result)
+    }
+
+    /// Decodes the outcome of driving the promise - keeping the reason if it was rejected
+    fn settled(
+        &self,
+        runtime: &mut deno_core::JsRuntime,
+        result: Result<v8::Global<v8::Value>, AnyError>,
+    ) -> Result<T, Error> {
         let mut scope = runtime.handle_scope();
-        let local = v8::Local::new(&mut scope, &result);
-        Ok(deno_core::serde_v8::from_v8(&mut scope, local)?)
+        match result {
+            Ok(value) => {
+                let local = v8::Local::new(&mut scope, &value);
+                super::decode_v8(&mut scope, local)
+            }
+            Err(e) => {
+                let promise = self.0.as_local(&mut scope);
+                if promise.state() == PromiseState::Rejected {
+                    let reason = promise.result(&mut scope);
+                    Err(rejection(&mut scope, reason))
+                } else {
+                    Err(e.into())
+                }
+            }
+        }
     }
 
     /// Returns a future that resolves the promise
@@ -49,12 +76,164 @@ where
     /// Blocks until the promise is resolved
     ///
     /// # Errors
-    /// Will return an error if the promise cannot be resolved into the given type,
-    /// or if a runtime error occurs
+    /// Will return [`Error::PromiseRejected`] if the promise is rejected,
+    /// or an error if the promise cannot be resolved into the given type, or if a runtime error occurs
     pub fn into_value(self, runtime: &mut crate::Runtime) -> Result<T, crate::Error> {
         runtime.block_on(move |runtime| async move { self.into_future(runtime).await })
     }
 
+    /// Blocks until the promise is resolved, or the timeout is reached
+    /// Returns `None` if the promise is still pending - it can be resolved again later
+    ///
+    /// # Errors
+    /// Will return [`Error::PromiseRejected`] if the promise is rejected,
+    /// or an error if the promise cannot be resolved into the given type, or if a runtime error occurs
+    ///
+    /// ```rust
+    /// use rustyscript::{ js_value::Promise, json_args, Module, Runtime };
+    /// use std::time::Duration;
+    ///
+    /// # fn main() -> Result<(), rustyscript::Error> {
+    /// let module = Module::new("test.js", "export const later = () => new Promise((r) => setTimeout(() => r(1), 100));");
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// let handle = runtime.load_module(&module)?;
+    /// let promise: Promise<u32> = runtime.call_function_immediate(Some(&handle), "later", json_args!())?;
+    ///
+    /// assert_eq!(promise.try_resolve_timeout(&mut runtime, Duration::from_millis(10))?, None);
+    /// assert_eq!(promise.try_resolve_timeout(&mut runtime, Duration::from_secs(5))?, Some(1));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn try_resolve_timeout(
+        &self,
+        runtime: &mut crate::Runtime,
+        timeout: Duration,
+    ) -> Result<Option<T>, crate::Error> {
+        runtime.block_on(move |runtime| async move {
+            let runtime = runtime.deno_runtime();
+            let future = runtime.resolve(self.0 .0.clone());
+            let result = runtime.with_event_loop_future(future, PollEventLoopOptions::default());
+            let result = tokio::time::timeout(timeout, result).await;
+            match result {
+                Ok(result) => self.settled(runtime, result).map(Some),
+                Err(_) => Ok(None),
+            }
+        })
+    }
+
+    /// Drives the event loop until the first of a set of promises settles
+    /// Returns the index of that promise, and its outcome - the other promises are left as they are
+    ///
+    /// # Errors
+    /// Will return an error if the set is empty, if the event loop fails,
+    /// or if it completes without any of the promises settling
+    ///
+    /// ```rust
+    /// use rustyscript::{ js_value::Promise, json_args, Module, Runtime };
+    ///
+    /// # fn main() -> Result<(), rustyscript::Error> {
+    /// let module = Module::new("test.js", "export const after = (ms) => new Promise((r) => setTimeout(() => r(ms), ms));");
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// let handle = runtime.load_module(&module)?;
+    ///
+    /// let mut promises: Vec<Promise<u32>> = Vec::new();
+    /// for ms in [50, 10] {
+    ///     promises.push(runtime.call_function_immediate(Some(&handle), "after", json_args!(ms))?);
+    /// }
+    ///
+    /// let (index, value) = Promise::race(&mut runtime, &promises)?;
+    /// assert_eq!((index, value?), (1, 10));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn race(
+        runtime: &mut crate::Runtime,
+        promises: &[Self],
+    ) -> Result<(usize, Result<T, Error>), Error> {
+        if promises.is_empty() {
+            return Err(Error::Runtime("No promises to race".to_string()));
+        }
+
+        let outcomes = Self::drive(runtime, promises, |outcomes| {
+            outcomes.iter().any(Option::is_some)
+        })?;
+        outcomes
+            .into_iter()
+            .enumerate()
+            .find_map(|(i, outcome)| outcome.map(|outcome| (i, outcome)))
+            .ok_or_else(|| Error::Runtime("No promise settled".to_string()))
+    }
+
+    /// Drives the event loop until every promise in a set has settled
+    /// Returns the outcome of each, in order - a rejection does not stop the others from being awaited
+    ///
+    /// # Errors
+    /// Will return an error if the event loop fails, or if it completes before every promise has settled
+    pub fn join_all(
+        runtime: &mut crate::Runtime,
+        promises: &[Self],
+    ) -> Result<Vec<Result<T, Error>>, Error> {
+        let outcomes = Self::drive(runtime, promises, |outcomes| {
+            outcomes.iter().all(Option::is_some)
+        })?;
+        Ok(outcomes.into_iter().flatten().collect())
+    }
+
+    /// Polls the event loop until `done` returns true for the outcomes of the promises settled so far
+    fn drive(
+        runtime: &mut crate::Runtime,
+        promises: &[Self],
+        done: impl Fn(&[Option<Result<T, Error>>]) -> bool,
+    ) -> Result<Vec<Option<Result<T, Error>>>, Error> {
+        for promise in promises {
+            promise.mark_handled(runtime);
+        }
+
+        let mut outcomes: Vec<_> = promises.iter().map(|_| None).collect();
+        let mut finished = false;
+        runtime.block_on(|runtime| async move {
+            std::future::poll_fn(|cx| loop {
+                for (promise, outcome) in promises.iter().zip(outcomes.iter_mut()) {
+                    if outcome.is_none() {
+                        if let std::task::Poll::Ready(result) = promise.poll_promise(runtime) {
+                            *outcome = Some(result);
+                        }
+                    }
+                }
+
+                if done(&outcomes) {
+                    return std::task::Poll::Ready(Ok(std::mem::take(&mut outcomes)));
+                } else if finished {
+                    return std::task::Poll::Ready(Err(Error::Runtime(
+                        "The event loop completed before the promises settled".to_string(),
+                    )));
+                }
+
+                match runtime
+                    .deno_runtime()
+                    .poll_event_loop(cx, PollEventLoopOptions::default())
+                {
+                    std::task::Poll::Pending => return std::task::Poll::Pending,
+                    std::task::Poll::Ready(Err(e)) => return std::task::Poll::Ready(Err(e.into())),
+                    std::task::Poll::Ready(Ok(())) => finished = true,
+                }
+            })
+            .await
+        })
+    }
+
+    /// Attaches an empty rejection handler, so a rejection is reported by the promise instead of the event loop
+    fn mark_handled(&self, runtime: &mut crate::Runtime) {
+        #[allow(clippy::needless_pass_by_value)]
+        fn ignore(_: &mut v8::HandleScope, _: v8::FunctionCallbackArguments, _: v8::ReturnValue) {}
+
+        let mut scope = runtime.deno_runtime().handle_scope();
+        let promise = self.0.as_local(&mut scope);
+        if let Some(handler) = v8::Function::new(&mut scope, ignore) {
+            promise.catch(&mut scope, handler);
+        }
+    }
+
     /// Checks if the promise is pending or already resolved
     pub fn is_pending(&self, runtime: &mut crate::Runtime) -> bool {
         let mut scope = runtime.deno_runtime().handle_scope();
@@ -72,21 +251,34 @@ where
         match value.state() {
             PromiseState::Pending => std::task::Poll::Pending,
             PromiseState::Rejected => {
-                let error = value.result(&mut scope);
-                let error = deno_core::error::JsError::from_v8_exception(&mut scope, error);
-                std::task::Poll::Ready(Err(error.into()))
+                let reason = value.result(&mut scope);
+                std::task::Poll::Ready(Err(rejection(&mut scope, reason)))
             }
             PromiseState::Fulfilled => {
                 let result = value.result(&mut scope);
-                match deno_core::serde_v8::from_v8::<T>(&mut scope, result) {
-                    Ok(value) => std::task::Poll::Ready(Ok(value)),
-                    Err(e) => std::task::Poll::Ready(Err(e.into())),
-                }
+                std::task::Poll::Ready(super::decode_v8::<T>(&mut scope, result))
             }
         }
     }
 }
 
+/// Converts the reason a promise was rejected with into [`Error::PromiseRejected`]
+/// The JSON copy of errors keeps their name, message and stack - other values are converted as JSON
+fn rejection(scope: &mut v8::HandleScope, reason: v8::Local<v8::Value>) -> Error {
+    let global = v8::Global::new(scope, reason);
+    // Safe because any value is a valid `Value`
+    let value = unsafe { super::Value::from_v8_unchecked(global) };
+
+    let json = if reason.is_native_error() {
+        let e = deno_core::error::JsError::from_v8_exception(scope, reason);
+        serde_json::json!({ "name": e.name, "message": e.message, "stack": e.stack })
+    } else {
+        deno_core::serde_v8::from_v8(scope, reason)
+            .unwrap_or_else(|_| serde_json::Value::String(reason.to_rust_string_lossy(scope)))
+    };
+    Error::PromiseRejected(super::Rejection::new(value, json))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -111,4 +303,120 @@ mod test {
         let value = value.into_value(&mut runtime).unwrap();
         assert_eq!(value, 42);
     }
+
+    #[test]
+    fn test_promise_rejection() {
+        let module = Module::new(
+            "test.js",
+            "
+            export const fail = () => Promise.reject({ code: 7, retry: false });
+            export const throws = async () => { throw new TypeError('bad'); };
+            export const isTypeError = (e) => e instanceof TypeError;
+            export const later = () => new Promise((resolve) => setTimeout(() => resolve(1), 50));
+        ",
+        );
+
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let handle = runtime.load_module(&module).unwrap();
+
+        let promise: Promise<usize> = runtime
+            .call_function_immediate(Some(&handle), "fail", json_args!())
+            .unwrap();
+        match promise.into_value(&mut runtime) {
+            Err(Error::PromiseRejected(reason)) => {
+                assert_eq!(
+                    reason.json(),
+                    &serde_json::json!({ "code": 7, "retry": false })
+                );
+            }
+            other => panic!("Expected a rejection, got {other:?}"),
+        }
+
+        let promise: Promise<usize> = runtime
+            .call_function_immediate(Some(&handle), "later", json_args!())
+            .unwrap();
+        let value = promise
+            .try_resolve_timeout(&mut runtime, Duration::from_millis(1))
+            .unwrap();
+        assert_eq!(value, None);
+        let value = promise
+            .try_resolve_timeout(&mut runtime, Duration::from_secs(5))
+            .unwrap();
+        assert_eq!(value, Some(1));
+
+        // Already settled, so no event loop is needed
+        let promise: Promise<usize> = runtime
+            .call_function_immediate(Some(&handle), "throws", json_args!())
+            .unwrap();
+        match promise.poll_promise(&mut runtime) {
+            std::task::Poll::Ready(Err(Error::PromiseRejected(reason))) => {
+                assert_eq!(reason.json()["name"], "TypeError");
+                assert_eq!(reason.json()["message"], "bad");
+
+                // The original error is kept, not just its JSON copy
+                let error = reason.into_value().expect("The reason was not kept");
+                let is_type_error: bool = runtime
+                    .call_function(Some(&handle), "isTypeError", &(error,))
+                    .unwrap();
+                assert!(is_type_error);
+            }
+            other => panic!("Expected a rejection, got {other:?}"),
+        }
+
+        // Only the JSON copy crosses threads
+        let promise: Promise<usize> = runtime
+            .call_function_immediate(Some(&handle), "fail", json_args!())
+            .unwrap();
+        let Err(Error::PromiseRejected(reason)) = promise.into_value(&mut runtime) else {
+            panic!("Expected a rejection");
+        };
+        let moved =
+            std::thread::spawn(move || (reason.value().is_none(), reason.json()["code"].clone()))
+                .join()
+                .unwrap();
+        assert_eq!(moved, (true, serde_json::json!(7)));
+    }
+
+    #[test]
+    fn test_promise_race_and_join() {
+        let module = Module::new(
+            "test.js",
+            "
+            export const after = (ms, value) => new Promise((resolve) => setTimeout(() => resolve(value), ms));
+            export const fail = (ms) => new Promise((_, reject) => setTimeout(() => reject('failed'), ms));
+        ",
+        );
+
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let handle = runtime.load_module(&module).unwrap();
+
+        let mut promises: Vec<Promise<usize>> = Vec::new();
+        for (function, args) in [
+            ("after", json_args!(60, 1)),
+            ("after", json_args!(10, 2)),
+            ("fail", json_args!(30)),
+        ] {
+            let promise = runtime
+                .call_function_immediate(Some(&handle), function, args)
+                .unwrap();
+            promises.push(promise);
+        }
+
+        let (index, value) = Promise::race(&mut runtime, &promises).unwrap();
+        assert_eq!(index, 1);
+        assert_eq!(value.unwrap(), 2);
+
+        let results = Promise::join_all(&mut runtime, &promises).unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap(), &1);
+        assert_eq!(results[1].as_ref().unwrap(), &2);
+        assert!(
+            matches!(&results[2], Err(Error::PromiseRejected(reason)) if reason.json() == "failed")
+        );
+
+        assert!(Promise::<usize>::race(&mut runtime, &[]).is_err());
+        assert!(Promise::<usize>::join_all(&mut runtime, &[])
+            .unwrap()
+            .is_empty());
+    }
 }
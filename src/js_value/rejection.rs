@@ -0,0 +1,114 @@
+use super::Value;
+use deno_core::serde_json;
+use std::thread::ThreadId;
+
+/// The reason a promise was rejected with, see [`crate::Error::PromiseRejected`]
+///
+/// Keeps the reason itself as a [`Value`], usable on the thread running the runtime it came from,
+/// along with a JSON copy that can be read anywhere - errors can be sent between threads, while v8 values cannot.
+/// On any other thread, or once serialized, only the JSON copy remains
+pub struct Rejection {
+    json: serde_json::Value,
+    value: Option<Value>,
+    thread: ThreadId,
+}
+
+// SAFETY: The v8 value is only read, cloned or dropped on the thread that created it
+// Elsewhere it is never touched, and is leaked on drop instead of being released
+unsafe impl Send for Rejection {}
+unsafe impl Sync for Rejection {}
+
+impl Rejection {
+    pub(crate) fn new(value: Value, json: serde_json::Value) -> Self {
+        Self {
+            json,
+            value: Some(value),
+            thread: std::thread::current().id(),
+        }
+    }
+
+    /// True on the thread that created the rejection
+    fn is_owner(&self) -> bool {
+        std::thread::current().id() == self.thread
+    }
+
+    /// The reason the promise was rejected with, as the original javascript value
+    ///
+    /// Returns None on a thread other than the one running the runtime, or if the error was deserialized
+    #[must_use]
+    pub fn value(&self) -> Option<&Value> {
+        self.value.as_ref().filter(|_| self.is_owner())
+    }
+
+    /// Consumes the rejection, returning the original javascript value - see [`Rejection::value`]
+    #[must_use]
+    pub fn into_value(mut self) -> Option<Value> {
+        if self.is_owner() {
+            self.value.take()
+        } else {
+            None
+        }
+    }
+
+    /// The reason the promise was rejected with, as JSON
+    ///
+    /// Errors become an object with their `name`, `message` and `stack`, and other values are converted as JSON
+    #[must_use]
+    pub fn json(&self) -> &serde_json::Value {
+        &self.json
+    }
+}
+
+impl Drop for Rejection {
+    fn drop(&mut self) {
+        if !self.is_owner() {
+            std::mem::forget(self.value.take());
+        }
+    }
+}
+
+impl Clone for Rejection {
+    fn clone(&self) -> Self {
+        Self {
+            json: self.json.clone(),
+            value: self.value().cloned(),
+            thread: self.thread,
+        }
+    }
+}
+
+impl std::fmt::Debug for Rejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Rejection").field(&self.json).finish()
+    }
+}
+
+impl std::fmt::Display for Rejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.json.fmt(f)
+    }
+}
+
+/// Serializes as the JSON copy of the reason
+impl serde::Serialize for Rejection {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.json.serialize(serializer)
+    }
+}
+
+/// Deserializes from the JSON copy of the reason, without the original value
+impl<'de> serde::Deserialize<'de> for Rejection {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        Ok(Self {
+            json: serde_json::Value::deserialize(deserializer)?,
+            value: None,
+            thread: std::thread::current().id(),
+        })
+    }
+}
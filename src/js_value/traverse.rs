@@ -0,0 +1,245 @@
+use deno_core::v8::{self, GetPropertyNamesArgs, HandleScope};
+use std::{collections::HashMap, num::NonZeroI32};
+
+/// Returns `value`, with `f` applied to it and every value nested within it
+///
+/// `f` is applied to a value before its contents - if it returns a different value, that value is not traversed
+/// Only arrays and plain objects are traversed, and only those containing a changed value are copied
+/// Class instances, `Map`s, `Date`s and other objects are passed to `f`, but never traversed or copied
+/// Circular references are left untouched, and a container reached twice is mapped to a single copy
+/// If `f` returns an error, traversal stops and the error is returned
+pub(crate) fn map_leaves<'s, F>(
+    scope: &mut HandleScope<'s>,
+    value: v8::Local<'s, v8::Value>,
+    f: &mut F,
+) -> Result<v8::Local<'s, v8::Value>, crate::Error>
+where
+    F: FnMut(
+        &mut HandleScope<'s>,
+        v8::Local<'s, v8::Value>,
+    ) -> Result<v8::Local<'s, v8::Value>, crate::Error>,
+{
+    let object_prototype = v8::Object::new(scope).get_prototype(scope);
+    let mut traversal = Traversal {
+        f,
+        object_prototype,
+        parents: Vec::new(),
+        mapped: HashMap::new(),
+    };
+    traversal.map_value(scope, value)
+}
+
+struct Traversal<'s, 'f, F> {
+    f: &'f mut F,
+    object_prototype: Option<v8::Local<'s, v8::Value>>,
+
+    /// Containers currently being traversed, to detect circular references
+    parents: Vec<v8::Local<'s, v8::Value>>,
+
+    /// Containers already traversed, by identity hash, and what they were mapped to
+    mapped: HashMap<NonZeroI32, Vec<(v8::Local<'s, v8::Value>, v8::Local<'s, v8::Value>)>>,
+}
+
+impl<'s, F> Traversal<'s, '_, F>
+where
+    F: FnMut(
+        &mut HandleScope<'s>,
+        v8::Local<'s, v8::Value>,
+    ) -> Result<v8::Local<'s, v8::Value>, crate::Error>,
+{
+    fn map_value(
+        &mut self,
+        scope: &mut HandleScope<'s>,
+        value: v8::Local<'s, v8::Value>,
+    ) -> Result<v8::Local<'s, v8::Value>, crate::Error> {
+        if self
+            .parents
+            .iter()
+            .any(|parent| parent.strict_equals(value))
+        {
+            return Ok(value);
+        }
+
+        let mapped = (self.f)(scope, value)?;
+        if !mapped.strict_equals(value) {
+            return Ok(mapped);
+        }
+
+        let Some(object) = self.container(scope, value) else {
+            return Ok(value);
+        };
+
+        let hash = object.get_identity_hash();
+        if let Some((_, mapped)) = self
+            .mapped
+            .get(&hash)
+            .and_then(|seen| seen.iter().find(|(seen, _)| seen.strict_equals(value)))
+        {
+            return Ok(*mapped);
+        }
+
+        self.parents.push(value);
+        let mapped = if let Ok(array) = v8::Local::<v8::Array>::try_from(value) {
+            self.map_array(scope, array)
+        } else {
+            self.map_object(scope, object)
+        };
+        self.parents.pop();
+
+        let mapped = mapped?.unwrap_or(value);
+        self.mapped.entry(hash).or_default().push((value, mapped));
+        Ok(mapped)
+    }
+
+    /// Returns the value as an object, if it is an array or a plain object
+    fn container(
+        &self,
+        scope: &mut HandleScope<'s>,
+        value: v8::Local<'s, v8::Value>,
+    ) -> Option<v8::Local<'s, v8::Object>> {
+        let object = v8::Local::<v8::Object>::try_from(value).ok()?;
+        if value.is_proxy() {
+            return None;
+        }
+        if value.is_array() {
+            return Some(object);
+        }
+
+        // Only objects created by literals, `Object.create(null)` or `new Object()`
+        let is_plain = match object.get_prototype(scope) {
+            None => true,
+            Some(prototype) => {
+                prototype.is_null()
+                    || self
+                        .object_prototype
+                        .is_some_and(|expected| expected.strict_equals(prototype))
+            }
+        };
+        is_plain.then_some(object)
+    }
+
+    /// Maps the elements of an array, returning a copy only if an element changed
+    fn map_array(
+        &mut self,
+        scope: &mut HandleScope<'s>,
+        array: v8::Local<'s, v8::Array>,
+    ) -> Result<Option<v8::Local<'s, v8::Value>>, crate::Error> {
+        let mut elements = Vec::with_capacity(array.length() as usize);
+        let mut changed = false;
+        for i in 0..array.length() {
+            let element = array
+                .get_index(scope, i)
+                .unwrap_or_else(|| v8::undefined(scope).into());
+            let mapped = self.map_value(scope, element)?;
+            changed |= !mapped.strict_equals(element);
+            elements.push(mapped);
+        }
+
+        Ok(changed.then(|| v8::Array::new_with_elements(scope, &elements).into()))
+    }
+
+    /// Maps the properties of a plain object, returning a copy only if a property changed
+    fn map_object(
+        &mut self,
+        scope: &mut HandleScope<'s>,
+        object: v8::Local<'s, v8::Object>,
+    ) -> Result<Option<v8::Local<'s, v8::Value>>, crate::Error> {
+        let Some(keys) = object.get_own_property_names(scope, GetPropertyNamesArgs::default())
+        else {
+            return Ok(None);
+        };
+
+        let mut properties = Vec::with_capacity(keys.length() as usize);
+        let mut changed = false;
+        for i in 0..keys.length() {
+            let Some(key) = keys.get_index(scope, i) else {
+                continue;
+            };
+            let Some(property) = object.get(scope, key) else {
+                continue;
+            };
+            let mapped = self.map_value(scope, property)?;
+            changed |= !mapped.strict_equals(property);
+            properties.push((key, mapped));
+        }
+
+        if !changed {
+            return Ok(None);
+        }
+
+        let copy = v8::Object::new(scope);
+        for (key, property) in properties {
+            copy.set(scope, key, property);
+        }
+        Ok(Some(copy.into()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Runtime, RuntimeOptions};
+
+    fn get<'s>(
+        scope: &mut HandleScope<'s>,
+        object: v8::Local<'s, v8::Value>,
+        key: &str,
+    ) -> v8::Local<'s, v8::Value> {
+        let object = v8::Local::<v8::Object>::try_from(object).unwrap();
+        let key = v8::String::new(scope, key).unwrap();
+        object.get(scope, key.into()).unwrap()
+    }
+
+    #[test]
+    fn test_map_leaves() {
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let value: crate::js_value::Value = runtime
+            .eval(
+                "
+                const shared = { n: 1 };
+                ({
+                    a: shared,
+                    b: shared,
+                    unchanged: { s: 'text', list: ['a'] },
+                    map: new Map([['n', 1]]),
+                    date: new Date(0),
+                    instance: new (class { n = 1 })(),
+                })
+            ",
+            )
+            .unwrap();
+
+        let mut scope = runtime.deno_runtime().handle_scope();
+        let value = v8::Local::new(&mut scope, value.into_v8());
+        let mapped = map_leaves(&mut scope, value, &mut |scope, value| {
+            Ok(if value.is_number() {
+                v8::Number::new(scope, 2.0).into()
+            } else {
+                value
+            })
+        })
+        .unwrap();
+        assert!(!mapped.strict_equals(value));
+
+        // Shared references map to a single copy
+        let a = get(&mut scope, mapped, "a");
+        let b = get(&mut scope, mapped, "b");
+        assert!(a.strict_equals(b));
+        assert!(!a.strict_equals(get(&mut scope, value, "a")));
+        assert_eq!(get(&mut scope, a, "n").number_value(&mut scope), Some(2.0));
+
+        // Containers without changes, and objects other than plain objects, are left as-is
+        for key in ["unchanged", "map", "date", "instance"] {
+            let original = get(&mut scope, value, key);
+            assert!(
+                get(&mut scope, mapped, key).strict_equals(original),
+                "{key}"
+            );
+        }
+        let instance = get(&mut scope, mapped, "instance");
+        assert_eq!(
+            get(&mut scope, instance, "n").number_value(&mut scope),
+            Some(1.0)
+        );
+    }
+}
@@ -0,0 +1,357 @@
+use deno_core::v8::{self, HandleScope};
+use std::ops::{Deref, DerefMut};
+
+/// Key used to tag [`Hooked`] values with their rust type when they are encoded
+const HOOK_TYPE_KEY: &str = "$__rustyscript_hook_type";
+
+/// Key holding the serialized rust value within a tagged [`Hooked`] value
+const HOOK_VALUE_KEY: &str = "$__rustyscript_hook_value";
+
+/// Maps a rust type to instances of a javascript class
+/// Registered with [`crate::Runtime::register_type_hook`]
+///
+/// Values cross the boundary in the serialized form of the rust type
+/// For example, a `chrono::DateTime` serializes to an ISO 8601 string:
+/// - `to_js` receives that serialized form, and returns an instance of the class
+/// - `from_js` receives an instance of the class, and returns a value the rust type can deserialize
+///
+/// All three fields are javascript expressions, evaluated once when the hook is registered
+///
+/// ```rust
+/// use rustyscript::js_value::TypeHook;
+///
+/// let hook = TypeHook::new("Date", "(s) => new Date(s)", "(d) => d.toISOString()");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeHook {
+    class: String,
+    to_js: String,
+    from_js: String,
+}
+
+impl TypeHook {
+    /// Creates a new type hook
+    ///
+    /// # Arguments
+    /// * `class` - An expression evaluating to the class constructor, such as `Date` or `MyLib.UUID`
+    /// * `to_js` - An expression evaluating to a function converting the serialized rust value into an instance
+    /// * `from_js` - An expression evaluating to a function converting an instance into a value the rust type can deserialize
+    #[must_use]
+    pub fn new(class: impl ToString, to_js: impl ToString, from_js: impl ToString) -> Self {
+        Self {
+            class: class.to_string(),
+            to_js: to_js.to_string(),
+            from_js: from_js.to_string(),
+        }
+    }
+
+    /// The expression evaluating to the class constructor
+    #[must_use]
+    pub fn class(&self) -> &str {
+        &self.class
+    }
+
+    /// The expression evaluating to the function converting serialized values into instances
+    #[must_use]
+    pub fn to_js(&self) -> &str {
+        &self.to_js
+    }
+
+    /// The expression evaluating to the function converting instances into serialized values
+    #[must_use]
+    pub fn from_js(&self) -> &str {
+        &self.from_js
+    }
+}
+
+/// Wraps a rust type so that it is passed to javascript as an instance of its registered class
+/// See [`TypeHook`]
+///
+/// Only needed for arguments - when decoding, instances of registered classes are converted automatically,
+/// so the bare type can be used in return values
+///
+/// Encoding a `Hooked<T>` with no hook registered for `T` will return an error
+#[derive(Eq, Hash, PartialEq, PartialOrd, Ord, Debug, Clone, Copy, Default)]
+pub struct Hooked<T>(pub T);
+
+impl<T> Hooked<T> {
+    /// Consume this struct and return the wrapped value
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> From<T> for Hooked<T> {
+    fn from(value: T) -> Self {
+        Self(value)
+    }
+}
+
+impl<T> Deref for Hooked<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Hooked<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<T: serde::Serialize + 'static> serde::Serialize for Hooked<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeMap;
+        let mut map = serializer.serialize_map(Some(2))?;
+        map.serialize_entry(HOOK_TYPE_KEY, std::any::type_name::<T>())?;
+        map.serialize_entry(HOOK_VALUE_KEY, &self.0)?;
+        map.end()
+    }
+}
+
+impl<'de, T: serde::Deserialize<'de>> serde::Deserialize<'de> for Hooked<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        T::deserialize(deserializer).map(Self)
+    }
+}
+
+/// A [`TypeHook`] that has been evaluated in a runtime
+#[derive(Clone)]
+struct RegisteredTypeHook {
+    rust_type: &'static str,
+    class: v8::Global<v8::Function>,
+    to_js: v8::Global<v8::Function>,
+    from_js: v8::Global<v8::Function>,
+}
+
+/// The set of type hooks registered in a runtime, stored in its `OpState`
+#[derive(Clone, Default)]
+pub(crate) struct TypeHooks(Vec<RegisteredTypeHook>);
+
+impl TypeHooks {
+    /// Evaluates `hook` in the current context and registers it for the rust type `T`
+    /// Replaces any hook previously registered for `T`
+    pub(crate) fn register<T: 'static>(
+        &mut self,
+        scope: &mut HandleScope,
+        hook: &TypeHook,
+    ) -> Result<(), crate::Error> {
+        let class = eval_function(scope, &hook.class)?;
+        let to_js = eval_function(scope, &hook.to_js)?;
+        let from_js = eval_function(scope, &hook.from_js)?;
+
+        let rust_type = std::any::type_name::<T>();
+        self.0.retain(|hook| hook.rust_type != rust_type);
+        self.0.push(RegisteredTypeHook {
+            rust_type,
+            class,
+            to_js,
+            from_js,
+        });
+        Ok(())
+    }
+
+    /// Returns the hooks registered for the runtime owning `scope`
+    pub(crate) fn from_scope(scope: &mut HandleScope) -> Self {
+        let state = deno_core::JsRuntime::op_state_from(scope);
+        let state = state.borrow();
+        state.try_borrow::<Self>().cloned().unwrap_or_default()
+    }
+
+    /// Replaces tagged [`Hooked`] values within an encoded argument with instances of their registered classes
+    pub(crate) fn encode<'s>(
+        &self,
+        scope: &mut HandleScope<'s>,
+        value: v8::Local<'s, v8::Value>,
+    ) -> Result<v8::Local<'s, v8::Value>, crate::Error> {
+        if self.0.is_empty() {
+            return Ok(value);
+        }
+
+        super::traverse::map_leaves(scope, value, &mut |scope, value| {
+            let Ok(object) = v8::Local::<v8::Object>::try_from(value) else {
+                return Ok(value);
+            };
+
+            let key = v8::String::new(scope, HOOK_TYPE_KEY).unwrap();
+            let Some(rust_type) = object.get(scope, key.into()).filter(|v| v.is_string()) else {
+                return Ok(value);
+            };
+            let rust_type = rust_type.to_rust_string_lossy(scope);
+
+            let hook = self
+                .0
+                .iter()
+                .find(|hook| hook.rust_type == rust_type)
+                .ok_or_else(|| {
+                    crate::Error::Runtime(format!("No type hook registered for `{rust_type}`"))
+                })?;
+
+            let key = v8::String::new(scope, HOOK_VALUE_KEY).unwrap();
+            let inner = object
+                .get(scope, key.into())
+                .unwrap_or_else(|| v8::undefined(scope).into());
+            call_hook(scope, &hook.to_js, inner, hook.rust_type)
+        })
+    }
+
+    /// Replaces instances of registered classes within a value with their serialized forms
+    pub(crate) fn decode<'s>(
+        &self,
+        scope: &mut HandleScope<'s>,
+        value: v8::Local<'s, v8::Value>,
+    ) -> Result<v8::Local<'s, v8::Value>, crate::Error> {
+        if self.0.is_empty() {
+            return Ok(value);
+        }
+
+        super::traverse::map_leaves(scope, value, &mut |scope, value| {
+            let Ok(object) = v8::Local::<v8::Object>::try_from(value) else {
+                return Ok(value);
+            };
+
+            for hook in &self.0 {
+                let class = v8::Local::new(scope, &hook.class);
+                if object.instance_of(scope, class.into()) == Some(true) {
+                    return call_hook(scope, &hook.from_js, value, hook.rust_type);
+                }
+            }
+
+            Ok(value)
+        })
+    }
+}
+
+/// Evaluates a javascript expression that must result in a function
+fn eval_function(
+    scope: &mut HandleScope,
+    source: &str,
+) -> Result<v8::Global<v8::Function>, crate::Error> {
+    let mut scope = v8::TryCatch::new(scope);
+    let code = v8::String::new(&mut scope, source)
+        .ok_or_else(|| crate::Error::V8Encoding(source.to_string()))?;
+
+    let value = v8::Script::compile(&mut scope, code, None).and_then(|s| s.run(&mut scope));
+    let Some(value) = value else {
+        let e = scope
+            .exception()
+            .map(|e| e.to_rust_string_lossy(&mut scope))
+            .unwrap_or_default();
+        return Err(crate::Error::Runtime(format!(
+            "Could not evaluate `{source}`: {e}"
+        )));
+    };
+
+    let function = v8::Local::<v8::Function>::try_from(value)
+        .map_err(|_| crate::Error::ValueNotCallable(source.to_string()))?;
+    Ok(v8::Global::new(&mut scope, function))
+}
+
+/// Calls a conversion function from a type hook
+fn call_hook<'s>(
+    scope: &mut HandleScope<'s>,
+    function: &v8::Global<v8::Function>,
+    value: v8::Local<'s, v8::Value>,
+    rust_type: &str,
+) -> Result<v8::Local<'s, v8::Value>, crate::Error> {
+    let mut scope = v8::EscapableHandleScope::new(scope);
+    let mut scope = v8::TryCatch::new(&mut scope);
+
+    let function = v8::Local::new(&mut scope, function);
+    let recv = v8::undefined(&mut scope).into();
+    if let Some(result) = function.call(&mut scope, recv, &[value]) {
+        Ok(scope.escape(result))
+    } else {
+        let e = scope
+            .exception()
+            .map(|e| e.to_rust_string_lossy(&mut scope))
+            .unwrap_or_default();
+        Err(crate::Error::Runtime(format!(
+            "Type hook for `{rust_type}` failed: {e}"
+        )))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Module, Runtime, RuntimeOptions};
+
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct Origin {
+        at: Point,
+    }
+
+    #[test]
+    fn test_type_hooks() {
+        let module = Module::new(
+            "test.js",
+            "
+            globalThis.Point = class Point {
+                constructor(x, y) { this.x = x; this.y = y; }
+                sum() { return this.x + this.y; }
+            };
+            export const origin = () => ({ at: new Point(0, 1) });
+            export const sum = (p) => p.sum();
+            export const year = (d) => d.getUTCFullYear();
+            export const date = () => new Date(Date.UTC(2020, 0, 1));
+        ",
+        );
+
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let handle = runtime.load_module(&module).unwrap();
+
+        runtime
+            .register_type_hook::<Point>(TypeHook::new(
+                "Point",
+                "(p) => new Point(p.x, p.y)",
+                "(p) => ({ x: p.x, y: p.y })",
+            ))
+            .unwrap();
+        runtime
+            .register_type_hook::<String>(TypeHook::new(
+                "Date",
+                "(s) => new Date(s)",
+                "(d) => d.toISOString()",
+            ))
+            .unwrap();
+
+        let value: usize = runtime
+            .call_function(Some(&handle), "sum", &(Hooked(Point { x: 2, y: 3 }),))
+            .unwrap();
+        assert_eq!(value, 5);
+
+        let value: Origin = runtime.call_function(Some(&handle), "origin", &()).unwrap();
+        assert_eq!(value.at, Point { x: 0, y: 1 });
+
+        let value: usize = runtime
+            .call_function(
+                Some(&handle),
+                "year",
+                &(Hooked("2021-06-01T00:00:00.000Z".to_string()),),
+            )
+            .unwrap();
+        assert_eq!(value, 2021);
+
+        let value: String = runtime.call_function(Some(&handle), "date", &()).unwrap();
+        assert_eq!(value, "2020-01-01T00:00:00.000Z");
+
+        // Not registered
+        runtime
+            .call_function::<usize>(Some(&handle), "sum", &(Hooked(1u8),))
+            .expect_err("Encoded an unregistered hooked type");
+    }
+}
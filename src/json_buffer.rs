@@ -0,0 +1,106 @@
+use crate::Error;
+use deno_core::{serde_json, v8};
+use serde::Deserialize;
+
+/// A javascript value serialized to JSON text by v8, which can be decoded without building a `serde_json::Value` first
+///
+/// Decoding can borrow from the buffer - for example into `&str` or `Cow<str>` fields - so large payloads
+/// are copied once, from v8 into the buffer, instead of once per intermediate value
+///
+/// Values are converted as `JSON.stringify` would - `toJSON` methods are applied, and `undefined` becomes `null`
+///
+/// See [`crate::Runtime::call_function_json`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct JsonBuffer(Vec<u8>);
+
+impl JsonBuffer {
+    /// Serializes a value to JSON with v8
+    pub(crate) fn from_v8(
+        scope: &mut v8::HandleScope,
+        value: v8::Local<v8::Value>,
+    ) -> Result<Self, Error> {
+        if value.is_undefined() || value.is_function() || value.is_symbol() {
+            return Ok(Self(b"null".to_vec()));
+        }
+
+        let scope = &mut v8::TryCatch::new(scope);
+        match v8::json::stringify(scope, value) {
+            Some(json) => Ok(Self(json.to_rust_string_lossy(scope).into_bytes())),
+            None => {
+                let e = crate::inner_runtime::caught_error(scope, None);
+                Err(Error::JsonDecode(e.to_string()))
+            }
+        }
+    }
+
+    /// Decodes the buffer, possibly borrowing from it
+    ///
+    /// # Errors
+    /// Will return an error if the JSON cannot be deserialized into the given type
+    pub fn decode<'a, T>(&'a self) -> Result<T, Error>
+    where
+        T: Deserialize<'a>,
+    {
+        serde_json::from_slice(&self.0).map_err(Error::from)
+    }
+
+    /// Returns the JSON text
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Consumes the buffer, returning the JSON text
+    #[must_use]
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{json_args, Module, Runtime, RuntimeOptions};
+    use std::borrow::Cow;
+
+    #[derive(serde::Deserialize)]
+    struct Report<'a> {
+        #[serde(borrow)]
+        title: Cow<'a, str>,
+        id: &'a str,
+        rows: Vec<u32>,
+    }
+
+    #[test]
+    fn test_json_buffer() {
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let module = runtime
+            .load_module(&Module::new(
+                "test.js",
+                "
+                export const report = async (n) => ({ title: 'Quarterly \"numbers\"', id: 'q3', rows: [...Array(n).keys()] });
+                export const nothing = () => undefined;
+                export const big = () => 1n;
+                ",
+            ))
+            .unwrap();
+
+        let buffer = runtime
+            .call_function_json(Some(&module), "report", json_args!(3))
+            .unwrap();
+        let report: Report = buffer.decode().unwrap();
+        assert_eq!(report.title, "Quarterly \"numbers\"");
+        assert!(matches!(report.title, Cow::Owned(_)));
+        assert_eq!(report.id, "q3");
+        assert_eq!(report.rows, [0, 1, 2]);
+
+        let buffer = runtime
+            .call_function_json(Some(&module), "nothing", json_args!())
+            .unwrap();
+        assert_eq!(buffer.as_bytes(), b"null");
+        assert_eq!(buffer.decode::<Option<u32>>().unwrap(), None);
+
+        assert!(runtime
+            .call_function_json(Some(&module), "big", json_args!())
+            .is_err());
+    }
+}
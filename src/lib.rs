@@ -229,13 +229,15 @@
 //! - `resolve_path`; Resolve a relative path to the current working dir
 //! - `validate`; Validate the syntax of a JS expression
 //! - `init_platform`; Initialize the V8 platform for multi-threaded applications
+//! - `set_default_locale`, `load_icu_data`; Configure the locale and ICU data used by `Intl` (full ICU data is built in)
+//! - `set_v8_flags`; Enable staged javascript features for every runtime in the process (`Temporal` is enabled already)
 //!
 //! Commonly used features have been grouped into the following feature-sets:
 //! - **`safe_extensions`** - On by default, these extensions are safe to use in a sandboxed environment
 //! - **`network_extensions`** - These extensions break sandboxing by allowing network connectivity
 //! - **`io_extensions`** - These extensions break sandboxing by allowing filesystem access (WARNING: Also allows some network access)
 //! - **`all_extensions`** - All 3 above groups are included
-//! - **`extra_features`** - Enables the `worker` feature (enabled by default), and the `snapshot_builder` feature
+//! - **`extra_features`** - Enables the `worker` feature (enabled by default), the `snapshot_builder` feature, and the `rustyscript_std` feature
 //! - **`node_experimental`** - HIGHLY EXPERIMENTAL nodeJS support that enables all available Deno extensions
 //!
 //! ## Crate features
@@ -258,6 +260,7 @@
 //! |`fs`               |Provides ops for interacting with the file system.                                                         |**NO**            |`deno_fs`, `web`,  `io`                                                                        |
 //! |`http`             |Implements the fetch standard                                                                              |**NO**            |`deno_http`, `web`, `websocket`                                                                |
 //! |`kv`               |Implements the Deno KV Connect protocol                                                                    |**NO**            |`deno_kv`, `web`, `console`                                                                    |
+//! |`sqlite`           |Synchronous sqlite databases, importable from JS as `rustyscript:sqlite` - in-memory unless configured     |**NO**            |`rusqlite`                                                                                     |
 //! |`url`              |Provides the `URL`, and `URLPattern` APIs from within JS                                                   |yes               |`deno_webidl`, `deno_url`                                                                      |
 //! |`io`               |Provides IO primitives such as stdio streams and abstraction over File System files.                       |**NO**            |`deno_io`, `rustyline`, `winapi`, `nix`, `libc`, `once_cell`                                   |
 //! |`web`              |Provides the `Event`, `TextEncoder`, `TextDecoder`, `File`, Web Cryptography, and fetch APIs from within JS|**NO**            |`deno_webidl`, `deno_web`, `deno_crypto`, `deno_fetch`, `deno_url`, `deno_net`                 |
@@ -276,8 +279,12 @@
 //! |`node_experimental`|HIGHLY EXPERIMENTAL nodeJS support that enables all available Deno extensions                              |**NO**            |For complete list, see Cargo.toml                                                              |
 //! |                   |                                                                                                           |                  |                                                                                               |
 //! |`worker`           |Enables access to the threaded worker API [`worker`]                                                       |yes               |None                                                                                           |
+//! |`metrics`          |Reports runtime and worker pool metrics through the `metrics` crate, for Prometheus and others [`metrics`]|yes               |`metrics`                                                                                      |
+//! |`server`           |Serves HTTP requests with javascript handlers over a pool of workers [`server`]                            |yes               |None                                                                                           |
 //! |`snapshot_builder` |Enables access to [`SnapshotBuilder`], a runtime for creating snapshots that can improve start-times       |yes               |None                                                                                           |
 //! |`web_stub`         |Enables a subset of `web` features that do not break sandboxing                                            |yes               |`deno_webidl`                                                                                  |
+//! |`sql_bridge`       |Lets JS run queries through the host's database connection, as `host.sql(statement, params)`             |yes               |None                                                                                           |
+//! |`rustyscript_std`  |A standard library of pure-JS utilities, importable from JS as `rustyscript:std/<name>`                    |yes               |None                                                                                           |
 //!
 //! ----
 //!
@@ -290,6 +297,9 @@
 #![allow(clippy::needless_pass_by_value)] //    Disabling some features can trigger this
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
+// Allows code generated by rustyscript-macros to be used within this crate
+extern crate self as rustyscript;
+
 #[cfg(feature = "snapshot_builder")]
 mod snapshot_builder;
 
@@ -297,21 +307,49 @@ mod snapshot_builder;
 #[cfg_attr(docsrs, doc(cfg(feature = "snapshot_builder")))]
 pub use snapshot_builder::SnapshotBuilder;
 
+#[cfg(feature = "snapshot_builder")]
+mod checkpoint;
+
+#[cfg(feature = "snapshot_builder")]
+#[cfg_attr(docsrs, doc(cfg(feature = "snapshot_builder")))]
+pub use checkpoint::Checkpoint;
+
 mod runtime_builder;
 pub use runtime_builder::RuntimeBuilder;
 
 pub mod error;
+pub mod extension_builder;
 pub mod js_value;
 pub mod module_loader;
 pub mod static_runtime;
+pub mod tenant;
 
 mod async_bridge;
+mod batch;
+mod bench;
+mod call_cache;
+mod call_options;
+mod call_result;
+mod capabilities;
 mod ext;
+mod external;
+mod fork;
+mod heap_limit;
 mod inner_runtime;
+mod js_api;
+mod json_buffer;
+mod metering;
 mod module;
 mod module_handle;
+mod module_key;
 mod module_wrapper;
+mod observer;
+mod pending_work;
+mod preemption;
+mod prepared_name;
+mod profiler;
 mod runtime;
+mod state_archive;
 mod traits;
 mod transpiler;
 mod utilities;
@@ -320,11 +358,31 @@ mod utilities;
 #[cfg_attr(docsrs, doc(cfg(feature = "worker")))]
 pub mod worker;
 
+#[cfg(feature = "worker")]
+#[cfg_attr(docsrs, doc(cfg(feature = "worker")))]
+pub mod testing;
+
+#[cfg(feature = "worker")]
+#[cfg_attr(docsrs, doc(cfg(feature = "worker")))]
+pub mod task_executor;
+
+#[cfg(feature = "metrics")]
+#[cfg_attr(docsrs, doc(cfg(feature = "metrics")))]
+pub mod metrics;
+
+#[cfg(feature = "server")]
+#[cfg_attr(docsrs, doc(cfg(feature = "server")))]
+pub mod server;
+
 // Expose a few dependencies that could be useful
 pub use deno_core;
 pub use deno_core::serde_json;
 pub use tokio;
 
+#[cfg(feature = "url_import")]
+#[cfg_attr(docsrs, doc(cfg(feature = "url_import")))]
+pub use reqwest;
+
 /// Re-exports of the deno extension crates used by this library
 pub mod extensions {
     #[cfg(feature = "broadcast_channel")]
@@ -388,6 +446,10 @@ pub mod extensions {
     pub use deno_tls;
 }
 
+#[cfg(feature = "io")]
+#[cfg_attr(docsrs, doc(cfg(feature = "io")))]
+pub use ext::io::StdinWriter;
+
 #[cfg(feature = "kv")]
 #[cfg_attr(docsrs, doc(cfg(feature = "kv")))]
 pub use ext::kv::{KvConfig, KvStore};
@@ -396,26 +458,63 @@ pub use ext::kv::{KvConfig, KvStore};
 #[cfg_attr(docsrs, doc(cfg(feature = "cache")))]
 pub use ext::cache::CacheBackend;
 
+#[cfg(feature = "sqlite")]
+#[cfg_attr(docsrs, doc(cfg(feature = "sqlite")))]
+pub use ext::sqlite::{SqliteOptions, SqliteStorage};
+
+#[cfg(feature = "sql_bridge")]
+#[cfg_attr(docsrs, doc(cfg(feature = "sql_bridge")))]
+pub use ext::sql_bridge::{SqlBridge, SqlColumn, SqlRows, SqlType, SqlValue};
+
+#[cfg(feature = "crypto")]
+#[cfg_attr(docsrs, doc(cfg(feature = "crypto")))]
+pub use ext::crypto::{KeyFormat, KeyMaterial, SecureRng};
+
 #[cfg(feature = "node_experimental")]
 #[cfg_attr(docsrs, doc(cfg(feature = "node_experimental")))]
-pub use ext::node::RustyResolver;
+pub use ext::{node::RustyResolver, runtime::BootstrapOptions};
 
 #[cfg(feature = "web")]
 #[cfg_attr(docsrs, doc(cfg(feature = "web")))]
 pub use ext::web::{
-    AllowlistWebPermissions, DefaultWebPermissions, PermissionDenied, SystemsPermissionKind,
-    WebOptions, WebPermissions,
+    AllowlistWebPermissions, Cookie, CookieJar, CookiePolicy, DefaultWebPermissions, DnsResolution,
+    DnsResolver, FetchLimits, PerformanceEntry, PerformanceEntryType, PermissionDenied,
+    ProxyConfig, SsrfProtection, StaticDnsResolver, SystemsPermissionKind, WebOptions,
+    WebPermissions,
 };
 pub use ext::ExtensionOptions;
 
 // Expose some important stuff from us
+pub use batch::{Batch, BatchResults};
+pub use bench::{BenchOptions, BenchStats};
+pub use call_options::{CallOptions, HeapGrowthCallback};
+pub use call_result::{CallResult, CallStats};
+pub use capabilities::Capabilities;
 pub use error::Error;
-pub use inner_runtime::{RsAsyncFunction, RsFunction};
-pub use module::Module;
-pub use module_handle::ModuleHandle;
+pub use ext::rustyscript::{
+    AbortHandle, AbortSignal, CallbackPanic, ExecutionTrace, ExitPolicy, LogLevel, LogRecord,
+    ProgressSender, QueueReceiver, ReentrantScope, TraceEvent, TraceMode,
+};
+pub use external::{External, ExternalStore};
+pub use inner_runtime::{CallbackContext, RsAsyncFunction, RsFunction};
+pub use js_api::JsApi;
+pub use json_buffer::JsonBuffer;
+pub use module::{LoadDirOptions, Module, SymlinkPolicy};
+pub use module_handle::{ModuleHandle, ModuleHandleMeta};
+pub use module_key::ModuleKey;
 pub use module_wrapper::ModuleWrapper;
+pub use observer::{CallInfo, RuntimeObserver};
+pub use pending_work::{PendingActivity, PendingWork};
+pub use prepared_name::PreparedName;
+pub use profiler::{CpuProfile, HeapStats, ProfileNode, ProfileOptions, RuntimeReport};
 pub use runtime::{Runtime, RuntimeOptions, Undefined};
-pub use utilities::{evaluate, import, init_platform, resolve_path, validate};
+pub use rustyscript_macros::js_api;
+pub use state_archive::StateArchive;
+pub use transpiler::{DecoratorMode, SourceMapMode, TranspilerOptions};
+pub use utilities::{
+    default_locale, evaluate, import, init_platform, load_icu_data, resolve_path,
+    set_default_locale, set_icu_data, set_v8_flags, validate,
+};
 
 #[cfg(feature = "broadcast_channel")]
 #[cfg_attr(docsrs, doc(cfg(feature = "broadcast_channel")))]
@@ -425,6 +524,14 @@ pub use ext::broadcast_channel::BroadcastChannelWrapper;
 #[cfg_attr(docsrs, doc(cfg(feature = "web")))]
 pub use hyper_util;
 
+#[cfg(feature = "web")]
+#[cfg_attr(docsrs, doc(cfg(feature = "web")))]
+pub use opentelemetry;
+
+#[cfg(feature = "web")]
+#[cfg_attr(docsrs, doc(cfg(feature = "web")))]
+pub use opentelemetry_sdk;
+
 #[cfg(test)]
 mod test {
     use crate::{include_module, Error, Module, Runtime, RuntimeOptions};
@@ -0,0 +1,335 @@
+//! Execution cost metering, see [`crate::RuntimeOptions::metering`]
+//!
+//! Modules are instrumented as they are loaded, with a call to a counter at the start of every function,
+//! and every iteration of every loop - so the cost of a call is a rough, but deterministic,
+//! measure of the work it did, independent of the speed of the host
+use crate::{ext::rustyscript::Meter, module_loader::SourceTransform, Error, Runtime};
+use deno_ast::{
+    swc::{
+        ast::{
+            ArrowExpr, BlockStmt, BlockStmtOrExpr, Constructor, DoWhileStmt, Expr, ForInStmt,
+            ForOfStmt, ForStmt, Function, GetterProp, Lit, SetterProp, Stmt, WhileStmt,
+        },
+        visit::{Visit, VisitWith},
+    },
+    MediaType, ModuleSpecifier, ParseParams, SourceRangedForSpanned, StartSourcePos,
+};
+use std::sync::Arc;
+
+/// The global function called by instrumented code, defined by the `rustyscript` extension - see [`Meter`]
+const METER: &str = "__rustyscript_meter()";
+
+/// Instruments every module loaded by the runtime, after any user-provided transform
+pub struct MeteringTransform {
+    pub inner: Option<Arc<dyn SourceTransform>>,
+}
+
+impl SourceTransform for MeteringTransform {
+    fn transform(
+        &self,
+        specifier: &ModuleSpecifier,
+        code: String,
+    ) -> Result<String, deno_core::anyhow::Error> {
+        let code = match &self.inner {
+            Some(transform) => transform.transform(specifier, code)?,
+            None => code,
+        };
+        instrument(specifier, &code)
+    }
+}
+
+/// Inserts a call to the meter at the start of every function body and loop body
+pub fn instrument(
+    specifier: &ModuleSpecifier,
+    code: &str,
+) -> Result<String, deno_core::anyhow::Error> {
+    let parsed = deno_ast::parse_program(ParseParams {
+        specifier: specifier.clone(),
+        text: code.into(),
+        media_type: MediaType::JavaScript,
+        capture_tokens: false,
+        scope_analysis: false,
+        maybe_syntax: None,
+    })?;
+
+    let mut visitor = Instrumenter::default();
+    match parsed.program_ref() {
+        deno_ast::ProgramRef::Module(module) => module.visit_with(&mut visitor),
+        deno_ast::ProgramRef::Script(script) => script.visit_with(&mut visitor),
+    }
+
+    // At the same position, constructs are closed before new ones are opened,
+    // inner constructs are closed first, and outer constructs are opened first
+    let mut insertions = visitor.insertions;
+    insertions.sort_by_key(|insertion| {
+        let order = if insertion.closing {
+            -insertion.sequence
+        } else {
+            insertion.sequence
+        };
+        (insertion.position, !insertion.closing, order)
+    });
+
+    let mut output = String::with_capacity(code.len() + insertions.len() * METER.len());
+    let mut last = 0;
+    for insertion in insertions {
+        output.push_str(&code[last..insertion.position]);
+        output.push_str(&insertion.text);
+        last = insertion.position;
+    }
+    output.push_str(&code[last..]);
+    Ok(output)
+}
+
+/// Text to insert into the source, at a byte index
+struct Insertion {
+    position: usize,
+    text: String,
+    closing: bool,
+    sequence: i64,
+}
+
+/// Collects the insertions needed to instrument a program
+#[derive(Default)]
+struct Instrumenter {
+    insertions: Vec<Insertion>,
+}
+
+impl Instrumenter {
+    fn insert(&mut self, position: usize, text: String, closing: bool) {
+        let sequence = i64::try_from(self.insertions.len()).unwrap_or(i64::MAX);
+        self.insertions.push(Insertion {
+            position,
+            text,
+            closing,
+            sequence,
+        });
+    }
+
+    /// Meters a block, after any directives such as `"use strict"`, which must stay first
+    fn meter_block(&mut self, block: &BlockStmt) {
+        let directives = block.stmts.iter().take_while(|stmt| {
+            matches!(stmt, Stmt::Expr(e) if matches!(e.expr.as_ref(), Expr::Lit(Lit::Str(_))))
+        });
+        let position = match directives.last() {
+            Some(directive) => byte_index(directive.end()),
+            None => byte_index(block.start()) + 1,
+        };
+        self.insert(position, format!("{METER};"), false);
+    }
+
+    /// Meters the body of a loop, wrapping it in a block if needed
+    fn meter_body(&mut self, body: &Stmt) {
+        if let Stmt::Block(block) = body {
+            self.meter_block(block);
+        } else {
+            self.insert(byte_index(body.start()), format!("{{ {METER}; "), false);
+            self.insert(byte_index(body.end()), " }".to_string(), true);
+        }
+    }
+}
+
+fn byte_index(position: deno_ast::SourcePos) -> usize {
+    position.as_byte_index(StartSourcePos::START_SOURCE_POS)
+}
+
+impl Visit for Instrumenter {
+    fn visit_function(&mut self, node: &Function) {
+        if let Some(body) = &node.body {
+            self.meter_block(body);
+        }
+        node.visit_children_with(self);
+    }
+
+    fn visit_constructor(&mut self, node: &Constructor) {
+        if let Some(body) = &node.body {
+            self.meter_block(body);
+        }
+        node.visit_children_with(self);
+    }
+
+    fn visit_getter_prop(&mut self, node: &GetterProp) {
+        if let Some(body) = &node.body {
+            self.meter_block(body);
+        }
+        node.visit_children_with(self);
+    }
+
+    fn visit_setter_prop(&mut self, node: &SetterProp) {
+        if let Some(body) = &node.body {
+            self.meter_block(body);
+        }
+        node.visit_children_with(self);
+    }
+
+    fn visit_arrow_expr(&mut self, node: &ArrowExpr) {
+        match node.body.as_ref() {
+            BlockStmtOrExpr::BlockStmt(body) => self.meter_block(body),
+            BlockStmtOrExpr::Expr(body) => {
+                self.insert(byte_index(body.start()), format!("({METER}, "), false);
+                self.insert(byte_index(body.end()), ")".to_string(), true);
+            }
+        }
+        node.visit_children_with(self);
+    }
+
+    fn visit_for_stmt(&mut self, node: &ForStmt) {
+        self.meter_body(&node.body);
+        node.visit_children_with(self);
+    }
+
+    fn visit_for_in_stmt(&mut self, node: &ForInStmt) {
+        self.meter_body(&node.body);
+        node.visit_children_with(self);
+    }
+
+    fn visit_for_of_stmt(&mut self, node: &ForOfStmt) {
+        self.meter_body(&node.body);
+        node.visit_children_with(self);
+    }
+
+    fn visit_while_stmt(&mut self, node: &WhileStmt) {
+        self.meter_body(&node.body);
+        node.visit_children_with(self);
+    }
+
+    fn visit_do_while_stmt(&mut self, node: &DoWhileStmt) {
+        self.meter_body(&node.body);
+        node.visit_children_with(self);
+    }
+}
+
+/// Runs `f` on the cost counter kept in the runtime's op state
+fn with_meter<R>(runtime: &mut Runtime, f: impl FnOnce(&mut Meter) -> R) -> Result<R, Error> {
+    let state = runtime.deno_runtime().op_state();
+    let mut state = state.try_borrow_mut()?;
+    Ok(f(state.borrow_mut::<Meter>()))
+}
+
+/// Resets the cost counter before a call, and sets the limit for the call
+pub fn start(runtime: &mut Runtime, limit: Option<u64>) -> Result<(), Error> {
+    with_meter(runtime, |meter| meter.start(limit))
+}
+
+/// Removes the limit after a call, returning the cost of the call
+pub fn stop(runtime: &mut Runtime) -> Result<u64, Error> {
+    with_meter(runtime, Meter::stop)
+}
+
+/// Returns the cost counted since the last call started
+pub fn cost(runtime: &mut Runtime) -> Result<u64, Error> {
+    with_meter(runtime, |meter| meter.cost())
+}
+
+/// Removes the limit after a call, failing the call if its cost exceeded the limit
+///
+/// The limit is checked here as well as by the meter itself, so the call fails even if the script caught the error
+pub fn finish<T>(
+    runtime: &mut Runtime,
+    limit: Option<u64>,
+    result: Result<T, Error>,
+) -> Result<T, Error> {
+    let cost = stop(runtime)?;
+    match limit {
+        Some(limit) if cost > limit => Err(Error::CostExceeded { limit }),
+        _ => result,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_instrument() {
+        let specifier = ModuleSpecifier::parse("file:///test.js").unwrap();
+        let code = "
+            function f() { 'use strict'; return 1; }
+            const g = (x) => x * 2;
+            for (let i = 0; i < 3; i++) while (false) f();
+            class A { constructor() {} get x() { return 1; } }
+        ";
+
+        let instrumented = instrument(&specifier, code).unwrap();
+        assert!(instrumented.contains(&format!("'use strict';{METER};")));
+        assert!(instrumented.contains(&format!("=> ({METER}, x * 2)")));
+        assert!(instrumented.contains(&format!("{{ {METER}; while (false) {{ {METER}; f(); }} }}")));
+        assert!(instrumented.contains(&format!("constructor() {{{METER};}}")));
+        assert!(instrumented.contains(&format!("get x() {{{METER}; return 1; }}")));
+
+        instrument(&specifier, "syntax error").expect_err("Invalid code was instrumented");
+    }
+
+    #[test]
+    fn test_metering() {
+        let mut runtime = Runtime::new(crate::RuntimeOptions {
+            metering: true,
+            ..Default::default()
+        })
+        .unwrap();
+        let module = crate::Module::new(
+            "test.js",
+            "
+            export const spin = (n) => { for (let i = 0; i < n; i++) {} };
+            export const stubborn = () => {
+                try { for (;;) {} } catch {}
+                return 'escaped';
+            };
+            export const tamper = () => {
+                // Try to stop the meter, or reset it with a higher limit
+                try { Deno.core.ops.op_meter = () => {}; } catch {}
+                try { globalThis.__rustyscript_meter = () => {}; } catch {}
+                for (const name of ['__rustyscript_meter_start', '__rustyscript_meter_stop']) {
+                    try { globalThis[name]?.(Number.MAX_SAFE_INTEGER); } catch {}
+                }
+                for (let i = 0; i < 1000; i++) {}
+            };
+        ",
+        );
+        let handle = runtime.load_module(&module).unwrap();
+
+        // The cost is deterministic - one for the call, and one per iteration
+        let options = crate::CallOptions {
+            max_cost: Some(100),
+            ..Default::default()
+        };
+        for _ in 0..2 {
+            runtime
+                .call_function_with_options::<crate::Undefined>(
+                    Some(&handle),
+                    "spin",
+                    &(10,),
+                    &options,
+                )
+                .unwrap();
+            assert_eq!(runtime.execution_cost().unwrap(), 11);
+        }
+
+        let e = runtime
+            .call_function_with_options::<crate::Undefined>(
+                Some(&handle),
+                "spin",
+                &(1000,),
+                &options,
+            )
+            .expect_err("Cost limit was not enforced");
+        assert!(matches!(e, Error::CostExceeded { limit: 100 }), "{e}");
+
+        // Catching the error does not escape the limit
+        let e = runtime
+            .call_function_with_options::<String>(Some(&handle), "stubborn", &(), &options)
+            .expect_err("Cost limit was escaped");
+        assert!(matches!(e, Error::CostExceeded { limit: 100 }), "{e}");
+
+        // Scripts cannot stop or reset the meter
+        let e = runtime
+            .call_function_with_options::<crate::Undefined>(Some(&handle), "tamper", &(), &options)
+            .expect_err("Cost limit was reset by the script");
+        assert!(matches!(e, Error::CostExceeded { limit: 100 }), "{e}");
+
+        // Limits only apply to the call they were given for
+        runtime
+            .call_function::<crate::Undefined>(Some(&handle), "spin", &(1000,))
+            .unwrap();
+    }
+}
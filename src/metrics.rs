@@ -0,0 +1,283 @@
+//! Runtime and worker pool metrics, reported through the [`metrics`](::metrics) crate facade
+//!
+//! Nothing is collected unless a recorder is installed - for Prometheus, use `metrics-exporter-prometheus`
+//!
+//! | Metric                                | Type      | Labels                | Source                             |
+//! |---------------------------------------|-----------|-----------------------|------------------------------------|
+//! |`rustyscript_runtimes`                 | gauge     |                       | [`RuntimeMetrics::observer`]       |
+//! |`rustyscript_calls_total`              | counter   | `function`, `outcome` | [`RuntimeMetrics::observer`]       |
+//! |`rustyscript_call_duration_seconds`    | histogram | `function`            | [`RuntimeMetrics::observer`]       |
+//! |`rustyscript_timeouts_total`           | counter   | `function`            | [`RuntimeMetrics::observer`]       |
+//! |`rustyscript_module_loads_total`       | counter   | `outcome`             | [`RuntimeMetrics::observer`]       |
+//! |`rustyscript_module_load_seconds`      | histogram |                       | [`RuntimeMetrics::observer`]       |
+//! |`rustyscript_heap_used_bytes`          | gauge     |                       | [`RuntimeMetrics::record_heap`]    |
+//! |`rustyscript_heap_total_bytes`         | gauge     |                       | [`RuntimeMetrics::record_heap`]    |
+//! |`rustyscript_heap_limit_bytes`         | gauge     |                       | [`RuntimeMetrics::record_heap`]    |
+//! |`rustyscript_workers`                  | gauge     | `state`               | [`RuntimeMetrics::record_pool`]    |
+//! |`rustyscript_worker_queue_depth`       | gauge     |                       | [`RuntimeMetrics::record_pool`]    |
+//!
+//! Every metric also carries the labels given to [`RuntimeMetrics::with_label`]
+//!
+//! ```rust
+//! use rustyscript::{ json_args, metrics::RuntimeMetrics, Error, Module, Runtime, RuntimeOptions };
+//!
+//! # fn main() -> Result<(), Error> {
+//! let metrics = RuntimeMetrics::new().with_label("tenant", "acme");
+//! let mut runtime = Runtime::new(RuntimeOptions {
+//!     observers: vec![metrics.observer()],
+//!     ..Default::default()
+//! })?;
+//!
+//! let module = runtime.load_module(&Module::new("test.js", "export const f = () => 1;"))?;
+//! runtime.call_function::<i64>(Some(&module), "f", json_args!())?;
+//!
+//! // Gauges are sampled on demand, for example before each scrape
+//! metrics.record_heap(&mut runtime);
+//! # Ok(())
+//! # }
+//! ```
+use crate::{CallInfo, Error, Module, ModuleHandle, Runtime, RuntimeObserver};
+use ::metrics::{counter, gauge, histogram, Label};
+use deno_core::v8;
+use std::time::Duration;
+
+/// Reports metrics for runtimes and worker pools, see the [module docs](self)
+///
+/// Cloning is cheap - clones report with the same labels
+#[derive(Clone, Debug, Default)]
+pub struct RuntimeMetrics {
+    labels: Vec<Label>,
+}
+
+impl RuntimeMetrics {
+    /// Creates a reporter with no labels
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a label to every metric reported, such as the name of a tenant or pool
+    #[must_use]
+    pub fn with_label(mut self, key: &str, value: &str) -> Self {
+        self.labels
+            .push(Label::new(key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Returns an observer reporting the calls and module loads of a runtime
+    /// Register it with [`crate::RuntimeOptions::observers`]
+    ///
+    /// The runtime is counted by `rustyscript_runtimes` until it is dropped
+    #[must_use]
+    pub fn observer(&self) -> Box<dyn RuntimeObserver> {
+        gauge!("rustyscript_runtimes", self.labels.clone()).increment(1.0);
+        Box::new(MetricsObserver(self.clone()))
+    }
+
+    /// Samples the v8 heap usage of a runtime
+    #[allow(clippy::cast_precision_loss)]
+    pub fn record_heap(&self, runtime: &mut Runtime) {
+        let mut stats = v8::HeapStatistics::default();
+        runtime
+            .deno_runtime()
+            .v8_isolate()
+            .get_heap_statistics(&mut stats);
+
+        gauge!("rustyscript_heap_used_bytes", self.labels.clone())
+            .set(stats.used_heap_size() as f64);
+        gauge!("rustyscript_heap_total_bytes", self.labels.clone())
+            .set(stats.total_heap_size() as f64);
+        gauge!("rustyscript_heap_limit_bytes", self.labels.clone())
+            .set(stats.heap_size_limit() as f64);
+    }
+
+    /// Samples the state of each worker in a pool, and the number of queries awaiting a response
+    /// Workers busy for longer than `max_busy` are reported as wedged, see [`crate::worker::Worker::health`]
+    #[cfg(feature = "worker")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "worker")))]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn record_pool<W>(&self, pool: &crate::worker::WorkerPool<W>, max_busy: Duration)
+    where
+        W: crate::worker::InnerWorker,
+    {
+        use crate::worker::WorkerHealth;
+
+        let (mut idle, mut busy, mut wedged, mut stopped) = (0, 0, 0, 0);
+        let mut queue_depth = 0;
+        for id in 0..pool.len() {
+            let Some(worker) = pool.worker_by_id(id) else {
+                continue;
+            };
+            let worker = worker.borrow();
+            queue_depth += worker.queue_depth();
+            match worker.health(max_busy) {
+                WorkerHealth::Idle => idle += 1,
+                WorkerHealth::Busy(_) => busy += 1,
+                WorkerHealth::Wedged(_) => wedged += 1,
+                WorkerHealth::Stopped => stopped += 1,
+            }
+        }
+
+        for (state, count) in [
+            ("idle", idle),
+            ("busy", busy),
+            ("wedged", wedged),
+            ("stopped", stopped),
+        ] {
+            gauge!("rustyscript_workers", self.labels_with("state", state)).set(f64::from(count));
+        }
+
+        gauge!("rustyscript_worker_queue_depth", self.labels.clone()).set(queue_depth as f64);
+    }
+
+    fn labels_with(&self, key: &'static str, value: &str) -> Vec<Label> {
+        let mut labels = self.labels.clone();
+        labels.push(Label::new(key, value.to_string()));
+        labels
+    }
+}
+
+/// Reports the work done by a single runtime, see [`RuntimeMetrics::observer`]
+struct MetricsObserver(RuntimeMetrics);
+
+impl Drop for MetricsObserver {
+    fn drop(&mut self) {
+        gauge!("rustyscript_runtimes", self.0.labels.clone()).decrement(1.0);
+    }
+}
+
+impl RuntimeObserver for MetricsObserver {
+    fn on_module_loaded(
+        &self,
+        _module: &Module,
+        duration: Duration,
+        outcome: Result<&ModuleHandle, &Error>,
+    ) {
+        let outcome = if outcome.is_ok() { "ok" } else { "error" };
+        counter!(
+            "rustyscript_module_loads_total",
+            self.0.labels_with("outcome", outcome)
+        )
+        .increment(1);
+        histogram!("rustyscript_module_load_seconds", self.0.labels.clone())
+            .record(duration.as_secs_f64());
+    }
+
+    fn after_call(&self, call: &CallInfo, duration: Duration, outcome: Result<(), &Error>) {
+        let function = call.function.unwrap_or("<entrypoint>");
+        let labels = self.0.labels_with("function", function);
+
+        let outcome = match outcome {
+            Ok(()) => "ok",
+            Err(Error::Timeout { .. }) => {
+                counter!("rustyscript_timeouts_total", labels.clone()).increment(1);
+                "timeout"
+            }
+            Err(_) => "error",
+        };
+
+        let mut outcome_labels = labels.clone();
+        outcome_labels.push(Label::new("outcome", outcome));
+        counter!("rustyscript_calls_total", outcome_labels).increment(1);
+        histogram!("rustyscript_call_duration_seconds", labels).record(duration.as_secs_f64());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{json_args, RuntimeOptions};
+    use ::metrics::{
+        Counter, Gauge, Histogram, Key, KeyName, Metadata, Recorder, SharedString, Unit,
+    };
+    use std::{
+        collections::BTreeMap,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, Mutex,
+        },
+    };
+
+    /// Keeps counters and gauges, keyed by name and label values
+    #[derive(Default)]
+    struct TestRecorder(Mutex<BTreeMap<String, Arc<AtomicU64>>>);
+
+    impl TestRecorder {
+        fn slot(&self, key: &Key) -> Arc<AtomicU64> {
+            let labels: Vec<_> = key.labels().map(Label::value).collect();
+            let name = format!("{}{{{}}}", key.name(), labels.join(","));
+            self.0.lock().unwrap().entry(name).or_default().clone()
+        }
+
+        fn counter(&self, name: &str) -> u64 {
+            self.0.lock().unwrap()[name].load(Ordering::SeqCst)
+        }
+
+        fn gauge(&self, name: &str) -> f64 {
+            f64::from_bits(self.counter(name))
+        }
+    }
+
+    impl Recorder for TestRecorder {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            Counter::from_arc(self.slot(key))
+        }
+
+        fn register_gauge(&self, key: &Key, _: &Metadata<'_>) -> Gauge {
+            Gauge::from_arc(self.slot(key))
+        }
+
+        fn register_histogram(&self, _: &Key, _: &Metadata<'_>) -> Histogram {
+            Histogram::noop()
+        }
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)] // Gauges here only hold small integers
+    fn test_runtime_metrics() {
+        let recorder = TestRecorder::default();
+        ::metrics::with_local_recorder(&recorder, || {
+            let metrics = RuntimeMetrics::new().with_label("tenant", "a");
+            let mut runtime = Runtime::new(RuntimeOptions {
+                observers: vec![metrics.observer()],
+                timeout: Duration::from_millis(200),
+                ..Default::default()
+            })
+            .unwrap();
+            assert_eq!(recorder.gauge("rustyscript_runtimes{a}"), 1.0);
+
+            let module = runtime
+                .load_module(&Module::new(
+                    "test.js",
+                    "export const f = () => 1; export const spin = () => { while (true) {} };",
+                ))
+                .unwrap();
+            assert_eq!(recorder.counter("rustyscript_module_loads_total{a,ok}"), 1);
+
+            for _ in 0..2 {
+                runtime
+                    .call_function::<i64>(Some(&module), "f", json_args!())
+                    .unwrap();
+            }
+            runtime
+                .call_function::<()>(Some(&module), "spin", json_args!())
+                .unwrap_err();
+            assert_eq!(recorder.counter("rustyscript_calls_total{a,f,ok}"), 2);
+            assert_eq!(
+                recorder.counter("rustyscript_calls_total{a,spin,timeout}"),
+                1
+            );
+            assert_eq!(recorder.counter("rustyscript_timeouts_total{a,spin}"), 1);
+
+            metrics.record_heap(&mut runtime);
+            assert!(recorder.gauge("rustyscript_heap_used_bytes{a}") > 0.0);
+
+            drop(runtime);
+            assert_eq!(recorder.gauge("rustyscript_runtimes{a}"), 0.0);
+        });
+    }
+}
@@ -1,277 +1,569 @@
-use maybe_path::MaybePathBuf;
-use serde::{Deserialize, Serialize};
-use std::borrow::Cow;
-use std::ffi::OsStr;
-use std::fmt::Display;
-use std::fs::{read_dir, read_to_string};
-use std::path::{Path, PathBuf};
-
-/// Creates a static module
-///
-/// This is just a macro around [`Module::new_static`]
-///
-/// # Arguments
-/// * `filename` - A string representing the filename of the module.
-/// * `contents` - A string containing the contents of the module.
-///
-/// Note that the contents argument is optional;
-/// if not provided, the macro will attempt to include the file at the given path.
-///
-/// # Example
-///
-/// ```rust
-/// use rustyscript::{ module, Module };
-///
-/// const MY_SCRIPT: Module = module!(
-///     "filename.js",
-///     "export const myValue = 42;"
-/// );
-/// ```
-#[macro_export]
-macro_rules! module {
-    ($filename:literal, $contents:literal) => {
-        $crate::Module::new_static($filename, $contents)
-    };
-
-    ($filename:literal) => {
-        Module::new_static($filename, include_str!($filename))
-    };
-}
-
-/// Creates a static module based on a statically included file
-///
-/// # Arguments
-/// * `filename` - A string representing the filename of the module.
-///
-/// See [module] for an example
-#[macro_export]
-macro_rules! include_module {
-    ($filename:literal) => {
-        Module::new_static($filename, include_str!($filename))
-    };
-}
-
-#[derive(Clone, Debug, Eq, PartialEq, Serialize, Default)]
-/// Represents a piece of javascript for execution.
-///
-/// Can be loaded from data at runtime, with `Module::new`, or from a file with `Module::load`.
-///
-/// It can also be loaded statically with `Module::new_static` or `module!`
-pub struct Module {
-    filename: MaybePathBuf<'static>,
-    contents: Cow<'static, str>,
-}
-
-impl<'de> Deserialize<'de> for Module {
-    fn deserialize<D>(deserializer: D) -> Result<Module, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        #[derive(Deserialize)]
-        struct OwnedModule {
-            filename: PathBuf,
-            contents: String,
-        }
-
-        let OwnedModule { filename, contents } = OwnedModule::deserialize(deserializer)?;
-        Ok(Module::new(filename, contents))
-    }
-}
-
-impl Display for Module {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.filename().display())
-    }
-}
-
-impl Module {
-    /// Creates a new `Module` instance with the given filename and contents.
-    ///
-    /// If filename is relative it will be resolved to the current working dir at runtime
-    ///
-    /// # Arguments
-    /// * `filename` - A string representing the filename of the module.
-    /// * `contents` - A string containing the contents of the module.
-    ///
-    /// # Returns
-    /// A new `Module` instance.
-    ///
-    /// # Example
-    ///
-    /// ```rust
-    /// use rustyscript::Module;
-    ///
-    /// let module = Module::new("module.js", "console.log('Hello, World!');");
-    /// ```
-    #[must_use]
-    pub fn new(filename: impl AsRef<Path>, contents: impl ToString) -> Self {
-        let filename = MaybePathBuf::Owned(filename.as_ref().to_path_buf());
-        let contents = Cow::Owned(contents.to_string());
-
-        Self { filename, contents }
-    }
-
-    /// Creates a new `Module` instance with the given filename and contents.  
-    /// The function is const, and the filename and contents are static strings.
-    ///
-    /// If filename is relative it will be resolved to the current working dir at runtime
-    ///
-    /// # Arguments
-    /// * `filename` - A string representing the filename of the module.
-    /// * `contents` - A string containing the contents of the module.
-    ///
-    /// # Returns
-    /// A new `Module` instance.
-    ///
-    /// # Example
-    ///
-    /// ```rust
-    /// use rustyscript::Module;
-    ///
-    /// let module = Module::new("module.js", "console.log('Hello, World!');");
-    /// ```
-    #[must_use]
-    pub const fn new_static(filename: &'static str, contents: &'static str) -> Self {
-        Self {
-            filename: MaybePathBuf::new_str(filename),
-            contents: Cow::Borrowed(contents),
-        }
-    }
-
-    /// Loads a `Module` instance from a file with the given filename.
-    ///
-    /// # Arguments
-    /// * `filename` - A string representing the filename of the module file.
-    ///
-    /// # Returns
-    /// A `Result` containing the loaded `Module` instance or an `std::io::Error` if there
-    /// are issues reading the file.
-    ///
-    /// # Errors
-    /// Will return an error if the file cannot be read.
-    ///
-    /// # Example
-    ///
-    /// ```rust
-    /// use rustyscript::Module;
-    ///
-    /// # fn main() -> Result<(), rustyscript::Error> {
-    /// let module = Module::load("src/ext/rustyscript/rustyscript.js")?;
-    /// # Ok(())
-    /// # }
-    /// ```
-    pub fn load(filename: impl AsRef<Path>) -> Result<Self, std::io::Error> {
-        let contents = read_to_string(filename.as_ref())?;
-        Ok(Self::new(filename, &contents))
-    }
-
-    /// Attempt to load all `.js`/`.ts` files in a given directory
-    ///
-    /// Fails if any of the files cannot be loaded
-    ///
-    /// # Arguments
-    /// * `directory` - A string representing the target directory
-    ///
-    /// # Returns
-    /// A `Result` containing a vec of loaded `Module` instances or an `std::io::Error` if there
-    /// are issues reading a file.
-    ///
-    /// # Errors
-    /// Will return an error if the directory cannot be read, or if any contained file cannot be read.
-    ///
-    /// # Example
-    ///
-    /// ```rust
-    /// use rustyscript::Module;
-    ///
-    /// # fn main() -> Result<(), rustyscript::Error> {
-    /// let all_modules = Module::load_dir("src/ext/rustyscript")?;
-    /// # Ok(())
-    /// # }
-    /// ```
-    pub fn load_dir(directory: impl AsRef<Path>) -> Result<Vec<Self>, std::io::Error> {
-        let mut files: Vec<Self> = Vec::new();
-        for file in read_dir(directory)? {
-            let file = file?;
-            if let Some(filename) = file.path().to_str() {
-                // Skip non-js files
-                let extension = Path::new(&filename)
-                    .extension()
-                    .and_then(OsStr::to_str)
-                    .unwrap_or_default();
-                if !["js", "ts"].contains(&extension) {
-                    continue;
-                }
-
-                files.push(Self::load(filename)?);
-            }
-        }
-
-        Ok(files)
-    }
-
-    /// Returns the filename of the module.
-    ///
-    /// # Returns
-    /// A reference to a string containing the filename.
-    ///
-    /// # Example
-    ///
-    /// ```rust
-    /// use rustyscript::Module;
-    ///
-    /// let module = Module::new("module.js", "console.log('Hello, World!');");
-    /// println!("Filename: {:?}", module.filename());
-    /// ```
-    #[must_use]
-    pub fn filename(&self) -> &Path {
-        self.filename.as_ref()
-    }
-
-    /// Returns the contents of the module.
-    ///
-    /// # Returns
-    /// A reference to a string containing the module contents.
-    ///
-    /// # Example
-    ///
-    /// ```rust
-    /// use rustyscript::Module;
-    ///
-    /// let module = Module::new("module.js", "console.log('Hello, World!');");
-    /// println!("Module Contents: {}", module.contents());
-    /// ```
-    #[must_use]
-    pub fn contents(&self) -> &str {
-        &self.contents
-    }
-}
-
-#[cfg(test)]
-mod test_module {
-    use super::*;
-
-    #[test]
-    fn test_new_module() {
-        let module = Module::new("module.js", "console.log('Hello, World!');");
-        assert_eq!(module.filename().to_str().unwrap(), "module.js");
-        assert_eq!(module.contents(), "console.log('Hello, World!');");
-    }
-
-    #[test]
-    fn test_load_module() {
-        let module =
-            Module::load("src/ext/rustyscript/rustyscript.js").expect("Failed to load module");
-        assert_eq!(
-            module.filename().to_str().unwrap(),
-            "src/ext/rustyscript/rustyscript.js"
-        );
-    }
-
-    #[test]
-    fn test_load_dir() {
-        let modules =
-            Module::load_dir("src/ext/rustyscript").expect("Failed to load modules from directory");
-        assert!(!modules.is_empty());
-    }
-}
+use maybe_path::MaybePathBuf;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fmt::Display;
+use std::fs::{read_dir, read_to_string};
+use std::path::{Path, PathBuf};
+
+/// Creates a static module
+///
+/// This is just a macro around [`Module::new_static`]
+///
+/// # Arguments
+/// * `filename` - A string representing the filename of the module.
+/// * `contents` - A string containing the contents of the module.
+///
+/// Note that the contents argument is optional;
+/// if not provided, the macro will attempt to include the file at the given path.
+///
+/// # Example
+///
+/// ```rust
+/// use rustyscript::{ module, Module };
+///
+/// const MY_SCRIPT: Module = module!(
+///     "filename.js",
+///     "export const myValue = 42;"
+/// );
+/// ```
+#[macro_export]
+macro_rules! module {
+    ($filename:literal, $contents:literal) => {
+        $crate::Module::new_static($filename, $contents)
+    };
+
+    ($filename:literal) => {
+        Module::new_static($filename, include_str!($filename))
+    };
+}
+
+/// Creates a static module based on a statically included file
+///
+/// # Arguments
+/// * `filename` - A string representing the filename of the module.
+///
+/// See [module] for an example
+#[macro_export]
+macro_rules! include_module {
+    ($filename:literal) => {
+        Module::new_static($filename, include_str!($filename))
+    };
+}
+
+/// Controls how symbolic links are handled by [`Module::load_dir_with`]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum SymlinkPolicy {
+    /// Symbolic links are ignored
+    /// This is the default behaviour
+    #[default]
+    Skip,
+
+    /// Symbolic links to files and directories are followed
+    /// Each directory is only visited once, so cyclic links are safe
+    Follow,
+}
+
+/// Options for [`Module::load_dir_with`]
+///
+/// Glob patterns are matched against paths relative to the directory being loaded, using `/` as a separator
+/// - `*` does not match across directories, while `**/` matches any number of them
+#[derive(Clone, Debug)]
+pub struct LoadDirOptions {
+    /// Files are only loaded if their path matches at least one of these patterns
+    ///
+    /// Default: `**/*.js` and `**/*.ts`
+    pub include: Vec<String>,
+
+    /// Files and directories matching any of these patterns are skipped
+    ///
+    /// Default: none
+    pub exclude: Vec<String>,
+
+    /// Maximum depth of subdirectories to load from
+    /// `Some(0)` only loads files directly in the directory, and `None` has no limit
+    ///
+    /// Default: `None`
+    pub max_depth: Option<usize>,
+
+    /// How symbolic links are handled
+    ///
+    /// Default: [`SymlinkPolicy::Skip`]
+    pub symlinks: SymlinkPolicy,
+
+    /// Names of files and directories that are skipped wherever they appear
+    ///
+    /// Default: `node_modules`
+    pub ignore: Vec<String>,
+
+    /// Load hidden files, and descend into hidden directories (names starting with `.`)
+    ///
+    /// Default: false
+    pub include_hidden: bool,
+}
+
+impl Default for LoadDirOptions {
+    fn default() -> Self {
+        Self {
+            include: vec!["**/*.js".to_string(), "**/*.ts".to_string()],
+            exclude: Vec::default(),
+            max_depth: None,
+            symlinks: SymlinkPolicy::default(),
+            ignore: vec!["node_modules".to_string()],
+            include_hidden: false,
+        }
+    }
+}
+
+/// State for a single call to [`Module::load_dir_with`]
+struct DirWalker<'a> {
+    root: &'a Path,
+    options: &'a LoadDirOptions,
+    include: Vec<glob::Pattern>,
+    exclude: Vec<glob::Pattern>,
+    visited: HashSet<PathBuf>,
+    modules: Vec<Module>,
+    failures: Vec<(PathBuf, String)>,
+}
+
+impl<'a> DirWalker<'a> {
+    fn new(root: &'a Path, options: &'a LoadDirOptions) -> Result<Self, crate::Error> {
+        let compile = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| {
+                    glob::Pattern::new(pattern).map_err(|e| {
+                        crate::Error::Runtime(format!("Invalid glob pattern `{pattern}`: {e}"))
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        Ok(Self {
+            root,
+            options,
+            include: compile(&options.include)?,
+            exclude: compile(&options.exclude)?,
+            visited: HashSet::new(),
+            modules: Vec::new(),
+            failures: Vec::new(),
+        })
+    }
+
+    fn matches(patterns: &[glob::Pattern], path: &Path) -> bool {
+        let options = glob::MatchOptions {
+            require_literal_separator: true,
+            ..Default::default()
+        };
+        patterns
+            .iter()
+            .any(|pattern| pattern.matches_path_with(path, options))
+    }
+
+    fn walk(&mut self, directory: &Path, depth: usize) {
+        // Guards against symlink cycles
+        if self.options.symlinks == SymlinkPolicy::Follow {
+            let canonical = directory
+                .canonicalize()
+                .unwrap_or_else(|_| directory.to_path_buf());
+            if !self.visited.insert(canonical) {
+                return;
+            }
+        }
+
+        let entries = match read_dir(directory) {
+            Ok(entries) => entries.collect::<Result<Vec<_>, _>>(),
+            Err(e) => Err(e),
+        };
+        let mut entries = match entries {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.failures.push((directory.to_path_buf(), e.to_string()));
+                return;
+            }
+        };
+        entries.sort_by_key(std::fs::DirEntry::path);
+
+        for entry in entries {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !self.options.include_hidden && name.starts_with('.') {
+                continue;
+            }
+            if self.options.ignore.contains(&name) {
+                continue;
+            }
+
+            let relative = path.strip_prefix(self.root).unwrap_or(&path);
+            if Self::matches(&self.exclude, relative) {
+                continue;
+            }
+
+            let is_symlink = entry.file_type().is_ok_and(|t| t.is_symlink());
+            if is_symlink && self.options.symlinks == SymlinkPolicy::Skip {
+                continue;
+            }
+
+            // Follows symlinks
+            let metadata = match std::fs::metadata(&path) {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    self.failures.push((path, e.to_string()));
+                    continue;
+                }
+            };
+
+            if metadata.is_dir() {
+                if self.options.max_depth.is_none_or(|max| depth < max) {
+                    self.walk(&path, depth + 1);
+                }
+            } else if Self::matches(&self.include, relative) {
+                match Module::load(&path) {
+                    Ok(module) => self.modules.push(module),
+                    Err(e) => self.failures.push((path, e.to_string())),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Default)]
+/// Represents a piece of javascript for execution.
+///
+/// Can be loaded from data at runtime, with `Module::new`, or from a file with `Module::load`.
+///
+/// It can also be loaded statically with `Module::new_static` or `module!`
+pub struct Module {
+    filename: MaybePathBuf<'static>,
+    contents: Cow<'static, str>,
+}
+
+impl<'de> Deserialize<'de> for Module {
+    fn deserialize<D>(deserializer: D) -> Result<Module, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct OwnedModule {
+            filename: PathBuf,
+            contents: String,
+        }
+
+        let OwnedModule { filename, contents } = OwnedModule::deserialize(deserializer)?;
+        Ok(Module::new(filename, contents))
+    }
+}
+
+impl Display for Module {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.filename().display())
+    }
+}
+
+impl Module {
+    /// Creates a new `Module` instance with the given filename and contents.
+    ///
+    /// If filename is relative it will be resolved to the current working dir at runtime
+    ///
+    /// # Arguments
+    /// * `filename` - A string representing the filename of the module.
+    /// * `contents` - A string containing the contents of the module.
+    ///
+    /// # Returns
+    /// A new `Module` instance.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rustyscript::Module;
+    ///
+    /// let module = Module::new("module.js", "console.log('Hello, World!');");
+    /// ```
+    #[must_use]
+    pub fn new(filename: impl AsRef<Path>, contents: impl ToString) -> Self {
+        let filename = MaybePathBuf::Owned(filename.as_ref().to_path_buf());
+        let contents = Cow::Owned(contents.to_string());
+
+        Self { filename, contents }
+    }
+
+    /// Creates a new `Module` instance with the given filename and contents.  
+    /// The function is const, and the filename and contents are static strings.
+    ///
+    /// If filename is relative it will be resolved to the current working dir at runtime
+    ///
+    /// # Arguments
+    /// * `filename` - A string representing the filename of the module.
+    /// * `contents` - A string containing the contents of the module.
+    ///
+    /// # Returns
+    /// A new `Module` instance.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rustyscript::Module;
+    ///
+    /// let module = Module::new("module.js", "console.log('Hello, World!');");
+    /// ```
+    #[must_use]
+    pub const fn new_static(filename: &'static str, contents: &'static str) -> Self {
+        Self {
+            filename: MaybePathBuf::new_str(filename),
+            contents: Cow::Borrowed(contents),
+        }
+    }
+
+    /// Loads a `Module` instance from a file with the given filename.
+    ///
+    /// # Arguments
+    /// * `filename` - A string representing the filename of the module file.
+    ///
+    /// # Returns
+    /// A `Result` containing the loaded `Module` instance or an `std::io::Error` if there
+    /// are issues reading the file.
+    ///
+    /// # Errors
+    /// Will return an error if the file cannot be read.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rustyscript::Module;
+    ///
+    /// # fn main() -> Result<(), rustyscript::Error> {
+    /// let module = Module::load("src/ext/rustyscript/rustyscript.js")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn load(filename: impl AsRef<Path>) -> Result<Self, std::io::Error> {
+        let contents = read_to_string(filename.as_ref())?;
+        Ok(Self::new(filename, &contents))
+    }
+
+    /// Attempt to load all `.js`/`.ts` files in a given directory
+    ///
+    /// Fails if any of the files cannot be loaded
+    /// Subdirectories are not searched - see [`Module::load_dir_with`] for more control
+    ///
+    /// # Arguments
+    /// * `directory` - A string representing the target directory
+    ///
+    /// # Returns
+    /// A `Result` containing a vec of loaded `Module` instances or an `std::io::Error` if there
+    /// are issues reading a file.
+    ///
+    /// # Errors
+    /// Will return an error if the directory cannot be read, or if any contained file cannot be read.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rustyscript::Module;
+    ///
+    /// # fn main() -> Result<(), rustyscript::Error> {
+    /// let all_modules = Module::load_dir("src/ext/rustyscript")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn load_dir(directory: impl AsRef<Path>) -> Result<Vec<Self>, std::io::Error> {
+        let mut files: Vec<Self> = Vec::new();
+        for file in read_dir(directory)? {
+            let file = file?;
+            if let Some(filename) = file.path().to_str() {
+                // Skip non-js files
+                let extension = Path::new(&filename)
+                    .extension()
+                    .and_then(OsStr::to_str)
+                    .unwrap_or_default();
+                if !["js", "ts"].contains(&extension) {
+                    continue;
+                }
+
+                files.push(Self::load(filename)?);
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// Attempt to load files from a directory and its subdirectories, filtered by the given options
+    ///
+    /// Every matching file is attempted, and modules are returned in path order
+    ///
+    /// # Arguments
+    /// * `directory` - A string representing the target directory
+    /// * `options` - Filters, recursion depth and symlink handling - see [`LoadDirOptions`]
+    ///
+    /// # Errors
+    /// Will return [`crate::Error::ModuleLoadFailed`], naming each file or directory that could not be read,
+    /// or an error if any of the glob patterns are invalid
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rustyscript::{ Module, LoadDirOptions };
+    ///
+    /// # fn main() -> Result<(), rustyscript::Error> {
+    /// let modules = Module::load_dir_with("src/ext", &LoadDirOptions {
+    ///     include: vec!["**/*.js".to_string()],
+    ///     exclude: vec!["node/**".to_string()],
+    ///     max_depth: Some(1),
+    ///     ..Default::default()
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn load_dir_with(
+        directory: impl AsRef<Path>,
+        options: &LoadDirOptions,
+    ) -> Result<Vec<Self>, crate::Error> {
+        let directory = directory.as_ref();
+        let mut walker = DirWalker::new(directory, options)?;
+        walker.walk(directory, 0);
+
+        if walker.failures.is_empty() {
+            Ok(walker.modules)
+        } else {
+            Err(crate::Error::ModuleLoadFailed {
+                failures: walker.failures,
+            })
+        }
+    }
+
+    /// Returns the filename of the module.
+    ///
+    /// # Returns
+    /// A reference to a string containing the filename.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rustyscript::Module;
+    ///
+    /// let module = Module::new("module.js", "console.log('Hello, World!');");
+    /// println!("Filename: {:?}", module.filename());
+    /// ```
+    #[must_use]
+    pub fn filename(&self) -> &Path {
+        self.filename.as_ref()
+    }
+
+    /// Returns the contents of the module.
+    ///
+    /// # Returns
+    /// A reference to a string containing the module contents.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rustyscript::Module;
+    ///
+    /// let module = Module::new("module.js", "console.log('Hello, World!');");
+    /// println!("Module Contents: {}", module.contents());
+    /// ```
+    #[must_use]
+    pub fn contents(&self) -> &str {
+        &self.contents
+    }
+}
+
+#[cfg(test)]
+mod test_module {
+    use super::*;
+
+    #[test]
+    fn test_new_module() {
+        let module = Module::new("module.js", "console.log('Hello, World!');");
+        assert_eq!(module.filename().to_str().unwrap(), "module.js");
+        assert_eq!(module.contents(), "console.log('Hello, World!');");
+    }
+
+    #[test]
+    fn test_load_module() {
+        let module =
+            Module::load("src/ext/rustyscript/rustyscript.js").expect("Failed to load module");
+        assert_eq!(
+            module.filename().to_str().unwrap(),
+            "src/ext/rustyscript/rustyscript.js"
+        );
+    }
+
+    #[test]
+    fn test_load_dir() {
+        let modules =
+            Module::load_dir("src/ext/rustyscript").expect("Failed to load modules from directory");
+        assert!(!modules.is_empty());
+    }
+
+    #[test]
+    fn test_load_dir_with() {
+        // A fixture, so that changes to the crate's own files cannot affect the results
+        let dir = std::env::temp_dir().join(format!("rustyscript_load_dir_{}", std::process::id()));
+        for file in [
+            "main.js",
+            "notes.md",
+            "lib/types.ts",
+            "lib/util.js",
+            "lib/nested/deep.js",
+            "vendor/dep.js",
+            "node_modules/pkg/index.js",
+        ] {
+            let path = dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "export const value = 1;").unwrap();
+        }
+
+        let filenames = |options: &LoadDirOptions| {
+            Module::load_dir_with(&dir, options)
+                .expect("Failed to load modules from directory")
+                .iter()
+                .map(|m| m.filename().strip_prefix(&dir).unwrap().to_path_buf())
+                .collect::<Vec<_>>()
+        };
+        let paths = |paths: &[&str]| paths.iter().map(PathBuf::from).collect::<Vec<_>>();
+
+        // Every module, in path order - `node_modules` is ignored by default
+        assert_eq!(
+            filenames(&LoadDirOptions::default()),
+            paths(&[
+                "lib/nested/deep.js",
+                "lib/types.ts",
+                "lib/util.js",
+                "main.js",
+                "vendor/dep.js"
+            ])
+        );
+
+        // Only the top level
+        let options = LoadDirOptions {
+            max_depth: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(filenames(&options), paths(&["main.js"]));
+
+        // Recursive, filtered
+        let options = LoadDirOptions {
+            include: vec!["lib/*.js".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(filenames(&options), paths(&["lib/util.js"]));
+
+        let options = LoadDirOptions {
+            exclude: vec!["lib".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(filenames(&options), paths(&["main.js", "vendor/dep.js"]));
+
+        std::fs::remove_dir_all(&dir).ok();
+
+        // Failures name the path
+        let e = Module::load_dir_with("src/does_not_exist", &LoadDirOptions::default())
+            .expect_err("Loaded a missing directory");
+        assert!(
+            matches!(&e, crate::Error::ModuleLoadFailed { failures } if failures[0].0 == Path::new("src/does_not_exist")),
+            "Unexpected error: {e}"
+        );
+    }
+}
@@ -1,7 +1,9 @@
 use deno_core::v8;
 use deno_core::ModuleId;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
-use crate::Module;
+use crate::{Capabilities, Module, ModuleKey};
 
 /// Represents a loaded instance of a module within a runtime
 #[derive(Clone, Debug, Eq, PartialEq, Default)]
@@ -9,6 +11,7 @@ pub struct ModuleHandle {
     entrypoint: Option<v8::Global<v8::Function>>,
     module_id: ModuleId,
     module: Module,
+    capabilities: Option<Arc<Capabilities>>,
 }
 
 impl ModuleHandle {
@@ -22,6 +25,7 @@ impl ModuleHandle {
             module_id,
             entrypoint,
             module: module.clone(),
+            capabilities: None,
         }
     }
 
@@ -57,4 +61,56 @@ impl ModuleHandle {
     pub fn entrypoint(&self) -> &Option<v8::Global<v8::Function>> {
         &self.entrypoint
     }
+
+    /// Return the capabilities this module was restricted to, if any
+    ///
+    /// See [`crate::Runtime::load_module_with_capabilities`]
+    #[must_use]
+    pub fn capabilities(&self) -> Option<&Capabilities> {
+        self.capabilities.as_deref()
+    }
+
+    /// Return the shared capabilities, to be applied to calls made through this handle
+    pub(crate) fn shared_capabilities(&self) -> Option<Arc<Capabilities>> {
+        self.capabilities.clone()
+    }
+
+    /// Restrict this handle to the given capabilities
+    pub(crate) fn set_capabilities(&mut self, capabilities: Arc<Capabilities>) {
+        self.capabilities = Some(capabilities);
+    }
+}
+
+/// A serializable description of a [`ModuleHandle`], created with [`crate::Runtime::handle_meta`]
+///
+/// A handle cannot leave the runtime that created it, but its description can be persisted, or sent to another process -
+/// a supervisor can record which modules each worker loaded, and rebuild its routing tables after a restart
+///
+/// Turn it back into a handle with [`crate::Runtime::rebind_handle`]
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct ModuleHandleMeta {
+    /// The specifier the module was loaded as
+    pub specifier: String,
+
+    /// The ID of the module within the runtime that loaded it
+    pub module_id: ModuleId,
+
+    /// The name of the module's entrypoint function, if it has one
+    ///
+    /// Anonymous functions have an empty name
+    pub entrypoint: Option<String>,
+}
+
+impl ModuleHandleMeta {
+    /// Return a key for the described module
+    ///
+    /// Unlike the description, a key stays valid if the module is loaded again with a different ID
+    ///
+    /// # Errors
+    /// Will return an error if the specifier is not a valid URL
+    pub fn key(&self) -> Result<ModuleKey, crate::Error> {
+        let specifier = deno_core::ModuleSpecifier::parse(&self.specifier)
+            .map_err(|e| crate::Error::Runtime(format!("Invalid module specifier: {e}")))?;
+        Ok(ModuleKey::new(specifier))
+    }
 }
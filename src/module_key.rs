@@ -0,0 +1,37 @@
+use deno_core::ModuleSpecifier;
+
+/// Identifies a module by its specifier, rather than by a loaded instance like [`crate::ModuleHandle`]
+///
+/// A handle is only valid for the runtime that loaded it, and refers to a single load of the module
+/// A key stays valid when the module is loaded again, or when the runtime is recreated -
+/// such as a [`crate::tenant::TenantManager`] resuming a suspended tenant - so it can be kept in host data structures
+///
+/// Keys are created with [`crate::Runtime::module_key`], and used with [`crate::Runtime::call_function_by_key`]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ModuleKey(ModuleSpecifier);
+
+impl ModuleKey {
+    /// Create a key for the module with the given specifier
+    #[must_use]
+    pub fn new(specifier: ModuleSpecifier) -> Self {
+        Self(specifier)
+    }
+
+    /// Return the specifier of the module
+    #[must_use]
+    pub fn specifier(&self) -> &ModuleSpecifier {
+        &self.0
+    }
+}
+
+impl From<ModuleSpecifier> for ModuleKey {
+    fn from(specifier: ModuleSpecifier) -> Self {
+        Self(specifier)
+    }
+}
+
+impl std::fmt::Display for ModuleKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
@@ -5,18 +5,46 @@ use deno_core::{anyhow::Error, ModuleLoader, ModuleSpecifier};
 use std::{cell::RefCell, path::PathBuf, rc::Rc};
 
 mod cache_provider;
+#[cfg(feature = "url_import")]
+mod fetch_credentials;
+#[cfg(feature = "url_import")]
+mod fetch_stats;
 mod import_provider;
 mod inner_loader;
+mod module_graph;
+mod origin_policy;
+mod path_redactions;
+mod source_map;
+mod source_transform;
 
 use inner_loader::InnerRustyLoader;
-pub(crate) use inner_loader::LoaderOptions;
+pub(crate) use inner_loader::{LoaderCaches, LoaderOptions};
 
 // Public exports
 pub use cache_provider::{ClonableSource, ModuleCacheProvider};
+#[cfg(feature = "url_import")]
+pub(crate) use fetch_credentials::credential_headers;
+#[cfg(feature = "url_import")]
+#[cfg_attr(docsrs, doc(cfg(feature = "url_import")))]
+pub use fetch_credentials::FetchCredentials;
+#[cfg(feature = "url_import")]
+#[cfg_attr(docsrs, doc(cfg(feature = "url_import")))]
+pub use fetch_stats::FetchStats;
+#[cfg(feature = "url_import")]
+pub(crate) use fetch_stats::FetchTracker;
 pub use import_provider::ImportProvider;
+pub use module_graph::{ModuleGraph, ModuleOrigin, ModuleProvenance, ProvenanceReport};
+pub use origin_policy::{OriginPolicy, PolicyViolation, ViolationKind};
+pub use path_redactions::PathRedactions;
+pub use source_map::OriginalLocation;
+pub use source_transform::SourceTransform;
 
 use crate::transpiler::ExtensionTranspiler;
 
+/// Callback used to resolve specifiers passed to `import.meta.resolve`
+pub(crate) type ImportMetaResolver =
+    Box<dyn Fn(&dyn ModuleLoader, String, String) -> Result<ModuleSpecifier, Error>>;
+
 /// The primary module loader implementation for rustyscript
 /// This structure manages fetching module code, transpilation, and caching
 pub(crate) struct RustyLoader {
@@ -49,12 +77,110 @@ impl RustyLoader {
         self.inner_mut().add_source_map(file_name, code, source_map);
     }
 
+    /// Translates a position in a loaded module's transpiled code into its original source
+    /// Returns `None` if the module was not transpiled, or the position has no mapping
+    pub fn map_location(
+        &self,
+        specifier: &str,
+        line: u32,
+        column: u32,
+    ) -> Option<OriginalLocation> {
+        let inner = self.inner();
+        let (source, source_map) = inner.get_source_map(specifier)?;
+        source_map::map_location(source, source_map.as_ref()?, line, column)
+    }
+
+    /// Transpiles a module's code, if it is typescript, JSX or TSX
+    /// Used for modules loaded directly from rust, which do not pass through the loader
+    pub fn transpile(
+        &self,
+        specifier: &ModuleSpecifier,
+        code: &str,
+    ) -> Result<crate::transpiler::ModuleContents, Error> {
+        self.inner().transpile(specifier, code)
+    }
+
+    /// Applies the source transform, if one is set, to a module's transpiled code
+    /// Used for modules loaded directly from rust, which do not pass through the loader
+    pub fn transform_source(
+        &self,
+        specifier: &ModuleSpecifier,
+        code: String,
+    ) -> Result<String, Error> {
+        self.inner().transform_source(specifier, code)
+    }
+
+    /// Stores a v8 code cache for a module, used to skip compilation when it is next loaded
+    pub fn add_code_cache(&self, specifier: &ModuleSpecifier, data: Vec<u8>) {
+        self.inner_mut().add_code_cache(specifier, data);
+    }
+
+    /// Checks if a code cache has been stored for a module
+    pub fn has_code_cache(&self, specifier: &ModuleSpecifier) -> bool {
+        self.inner().has_code_cache(specifier)
+    }
+
+    /// Stages already-prepared code for a module loaded from rust
+    /// Allows it to be loaded through the loader, and therefore use a stored code cache
+    pub fn stage_source(&self, specifier: &ModuleSpecifier, code: String) {
+        self.inner_mut().stage_source(specifier, code);
+    }
+
+    /// Registers a module's source without loading it
+    /// It is only loaded and evaluated once something imports it
+    pub fn add_lazy_module(&self, specifier: &ModuleSpecifier, contents: String) {
+        self.inner_mut().add_lazy_module(specifier, contents);
+    }
+
+    /// Makes imports of a bare specifier resolve to the given module
+    pub fn add_alias(&self, name: &str, specifier: ModuleSpecifier) {
+        self.inner_mut().add_alias(name, specifier);
+    }
+
+    /// Returns a copy of the caches that can be shared with another loader
+    pub fn caches(&self) -> LoaderCaches {
+        self.inner().caches()
+    }
+
+    /// Adds caches copied from another loader
+    pub fn extend_caches(&self, caches: LoaderCaches) {
+        self.inner_mut().extend_caches(caches);
+    }
+
+    /// Returns statistics about the remote modules fetched so far
+    #[cfg(feature = "url_import")]
+    pub fn fetch_stats(&self) -> FetchStats {
+        self.inner().fetch_stats()
+    }
+
+    /// Records the code of a module loaded without passing through the loader, such as from rust
+    pub fn record_module(&self, specifier: &ModuleSpecifier, origin: ModuleOrigin, code: &[u8]) {
+        self.inner_mut().record_module(specifier, origin, code);
+    }
+
+    /// Returns every module loaded so far, and where its code came from
+    pub fn module_graph(&self) -> ModuleGraph {
+        self.inner().module_graph().clone()
+    }
+
     /// Get an extension transpiler that can be injected into a `deno_core::JsRuntime`
     pub fn as_extension_transpiler(self: &Rc<Self>) -> ExtensionTranspiler {
         let loader = self.clone();
         Rc::new(move |specifier, code| loader.inner().transpile_extension(&specifier, &code))
     }
 
+    /// Get an `import.meta.resolve` callback that can be injected into a `deno_core::JsRuntime`
+    ///
+    /// Resolves specifiers without loading them - by default they would be treated as dynamic imports
+    pub fn as_import_meta_resolver(self: &Rc<Self>) -> ImportMetaResolver {
+        let loader = self.clone();
+        Box::new(move |_, specifier, referrer| {
+            loader
+                .inner_mut()
+                .import_meta_resolve(&specifier, &referrer)
+        })
+    }
+
     /// Transpile a module from CJS to ESM
     #[allow(dead_code)]
     pub async fn translate_cjs(
@@ -0,0 +1,101 @@
+use deno_core::{
+    anyhow::{anyhow, Error},
+    ModuleSpecifier,
+};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
+/// Supplies credentials for remote module fetches (`url_import` crate feature)
+///
+/// Called before each module is fetched, so secrets can be looked up on demand
+/// instead of being stored in the runtime's options - for example a bearer token for a private registry
+///
+/// Redirects are followed by the loader, which calls this again with each new URL, so headers returned for
+/// one host are never sent to another - unless a custom [`crate::RuntimeOptions::module_fetch_client`]
+/// follows redirects itself. The returned headers are also marked as sensitive, hiding them from debug output
+///
+/// `npm:` packages are not covered, since they are resolved from a local `node_modules` directory
+/// and never downloaded by rustyscript
+///
+/// Implemented for closures of the form `Fn(&ModuleSpecifier) -> Result<Vec<(String, String)>, Error>`:
+/// ```rust
+/// use rustyscript::{ RuntimeOptions, deno_core::{ ModuleSpecifier, anyhow::Error } };
+/// use std::sync::Arc;
+///
+/// let options = RuntimeOptions {
+///     module_fetch_credentials: Some(Arc::new(|url: &ModuleSpecifier| {
+///         let mut headers = vec![];
+///         if url.host_str() == Some("registry.internal") {
+///             let token = std::env::var("REGISTRY_TOKEN")?;
+///             headers.push(("Authorization".to_string(), format!("Bearer {token}")));
+///         }
+///         Ok::<_, Error>(headers)
+///     })),
+///     ..Default::default()
+/// };
+/// ```
+pub trait FetchCredentials: Send + Sync {
+    /// Returns the headers to send when fetching a module
+    ///
+    /// # Arguments
+    /// - `url`: The URL of the module being fetched
+    ///
+    /// # Errors
+    /// Any error returned will prevent the module from loading
+    fn headers(&self, url: &ModuleSpecifier) -> Result<Vec<(String, String)>, Error>;
+}
+
+impl<F> FetchCredentials for F
+where
+    F: Fn(&ModuleSpecifier) -> Result<Vec<(String, String)>, Error> + Send + Sync,
+{
+    fn headers(&self, url: &ModuleSpecifier) -> Result<Vec<(String, String)>, Error> {
+        self(url)
+    }
+}
+
+/// Gets the headers for a fetch from a credentials provider, marking each as sensitive
+pub(crate) fn credential_headers(
+    credentials: &dyn FetchCredentials,
+    url: &ModuleSpecifier,
+) -> Result<HeaderMap, Error> {
+    let mut headers = HeaderMap::new();
+    for (name, value) in credentials.headers(url)? {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| anyhow!("Invalid header name for {url}: {name}"))?;
+        let mut value = HeaderValue::from_str(&value)
+            .map_err(|_| anyhow!("Invalid value for header {name} for {url}"))?;
+        value.set_sensitive(true);
+        headers.append(name, value);
+    }
+    Ok(headers)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_credential_headers() {
+        let credentials = |url: &ModuleSpecifier| {
+            Ok(match url.host_str() {
+                Some("registry.internal") => {
+                    vec![("Authorization".to_string(), "Bearer secret".to_string())]
+                }
+                Some("bad.internal") => vec![("Bad Name".to_string(), String::new())],
+                _ => vec![],
+            })
+        };
+
+        let url = ModuleSpecifier::parse("https://registry.internal/mod.js").unwrap();
+        let headers = credential_headers(&credentials, &url).unwrap();
+        let value = &headers["authorization"];
+        assert_eq!(value, "Bearer secret");
+        assert!(value.is_sensitive());
+
+        let url = ModuleSpecifier::parse("https://example.com/mod.js").unwrap();
+        assert!(credential_headers(&credentials, &url).unwrap().is_empty());
+
+        let url = ModuleSpecifier::parse("https://bad.internal/mod.js").unwrap();
+        credential_headers(&credentials, &url).expect_err("Invalid header name was accepted");
+    }
+}
@@ -0,0 +1,97 @@
+use std::time::{Duration, Instant};
+
+/// Statistics about the remote modules fetched by a runtime, see [`crate::Runtime::fetch_stats`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FetchStats {
+    /// Number of modules fetched successfully
+    pub fetched: usize,
+
+    /// Number of fetches that failed
+    pub failed: usize,
+
+    /// Total size of the fetched modules, in bytes
+    pub bytes: usize,
+
+    /// Wall-clock time during which at least one fetch was in progress
+    ///
+    /// Independent imports are fetched concurrently, so this is usually far lower than `cumulative`
+    pub elapsed: Duration,
+
+    /// Sum of the time taken by each individual fetch
+    pub cumulative: Duration,
+}
+
+/// Tracks fetches as they start and finish, so overlapping fetches are only timed once
+#[derive(Default)]
+pub(crate) struct FetchTracker {
+    stats: FetchStats,
+    in_flight: usize,
+    busy_since: Option<Instant>,
+}
+
+impl FetchTracker {
+    /// Records the start of a fetch, returning the time it started
+    pub fn start(&mut self) -> Instant {
+        let now = Instant::now();
+        if self.in_flight == 0 {
+            self.busy_since = Some(now);
+        }
+        self.in_flight += 1;
+        now
+    }
+
+    /// Records the end of a fetch started at `started`
+    /// `bytes` is None if the fetch failed
+    pub fn finish(&mut self, started: Instant, bytes: Option<usize>) {
+        match bytes {
+            Some(bytes) => {
+                self.stats.fetched += 1;
+                self.stats.bytes += bytes;
+            }
+            None => self.stats.failed += 1,
+        }
+        self.stats.cumulative += started.elapsed();
+
+        self.in_flight = self.in_flight.saturating_sub(1);
+        if self.in_flight == 0 {
+            if let Some(since) = self.busy_since.take() {
+                self.stats.elapsed += since.elapsed();
+            }
+        }
+    }
+
+    /// Returns the statistics so far, including any fetches still in progress
+    pub fn stats(&self) -> FetchStats {
+        let mut stats = self.stats;
+        if let Some(since) = self.busy_since {
+            stats.elapsed += since.elapsed();
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_overlapping_fetches() {
+        let mut tracker = FetchTracker::default();
+
+        let a = tracker.start();
+        std::thread::sleep(Duration::from_millis(20));
+        let b = tracker.start();
+        std::thread::sleep(Duration::from_millis(20));
+        tracker.finish(a, Some(10));
+        tracker.finish(b, None);
+
+        let stats = tracker.stats();
+        assert_eq!(stats.fetched, 1);
+        assert_eq!(stats.failed, 1);
+        assert_eq!(stats.bytes, 10);
+
+        // The overlap is only counted once in the wall-clock time
+        assert!(stats.elapsed >= Duration::from_millis(40));
+        assert!(stats.cumulative >= stats.elapsed + Duration::from_millis(20));
+    }
+}
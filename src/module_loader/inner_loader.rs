@@ -3,13 +3,17 @@
 #![allow(dead_code)]
 use crate::module_loader::{ClonableSource, ModuleCacheProvider};
 use crate::traits::ToModuleSpecifier;
-use crate::transpiler::{transpile, transpile_extension, ExtensionTranspilation};
+use crate::transpiler::{
+    transpile, transpile_extension, ExtensionTranspilation, ModuleContents, TranspilerOptions,
+};
 use deno_core::anyhow::{anyhow, Error};
 use deno_core::error::AnyError;
 use deno_core::futures::FutureExt;
 use deno_core::{
     FastString, ModuleLoadResponse, ModuleSource, ModuleSourceCode, ModuleSpecifier, ModuleType,
+    SourceCodeCacheInfo,
 };
+use std::borrow::Cow;
 use std::cell::RefCell;
 use std::path::PathBuf;
 use std::rc::Rc;
@@ -30,11 +34,29 @@ use node_resolver::InNpmPackageChecker;
 #[cfg(feature = "node_experimental")]
 use node_resolver::{NodeResolutionKind, ResolutionMode};
 
-use super::ImportProvider;
+use super::{
+    ImportProvider, ModuleGraph, ModuleOrigin, OriginPolicy, PathRedactions, SourceTransform,
+};
+
+#[cfg(feature = "url_import")]
+use super::{credential_headers, FetchCredentials, FetchStats, FetchTracker};
 
 /// Stores the source code and source ma#![allow(deprecated)]p for loaded modules
 type SourceMapCache = HashMap<String, (String, Option<Vec<u8>>)>;
 
+/// Most redirects followed when fetching a remote module - the same limit as reqwest's default policy
+#[cfg(feature = "url_import")]
+const MAX_REDIRECTS: usize = 10;
+
+/// Caches kept by the loader that can be copied into another runtime's loader
+/// See [`crate::Runtime::fork`]
+#[derive(Clone, Default)]
+pub struct LoaderCaches {
+    code_cache: HashMap<String, Vec<u8>>,
+    lazy_modules: HashMap<String, String>,
+    aliases: HashMap<String, ModuleSpecifier>,
+}
+
 /// Options for the `RustyLoader` struct
 /// Not for public use
 #[derive(Default)]
@@ -59,8 +81,42 @@ pub struct LoaderOptions {
     /// A whitelist of custom schema prefixes that are allowed to be loaded
     pub schema_whlist: HashSet<String>,
 
+    /// An optional transformation applied to the code of every loaded module
+    pub source_transform: Option<Arc<dyn SourceTransform>>,
+
+    /// The maximum number of remote modules fetched at once, or 0 for no limit
+    pub max_concurrent_fetches: usize,
+
+    /// If true, dynamic `import()` calls are rejected
+    pub disallow_dynamic_imports: bool,
+
+    /// An optional policy restricting where modules may be imported from
+    pub origin_policy: Option<OriginPolicy>,
+
+    /// If true, the origin policy also applies to the main module
+    /// Set for web workers, whose main module is chosen by the script starting them
+    pub policy_checks_main_module: bool,
+
+    /// Options used when transpiling typescript, JSX and TSX modules
+    pub transpiler_options: TranspilerOptions,
+
+    /// An optional client used to fetch remote modules, instead of a default one
+    #[cfg(feature = "url_import")]
+    pub http_client: Option<reqwest::Client>,
+
+    /// An optional source of credentials for remote module fetches
+    #[cfg(feature = "url_import")]
+    pub fetch_credentials: Option<Arc<dyn FetchCredentials>>,
+
     /// The current working directory for the loader
     pub cwd: PathBuf,
+
+    /// The virtual base url of modules loaded from rust, if any
+    /// Modules under it are never fetched
+    pub base_url: Option<ModuleSpecifier>,
+
+    /// Host directories shown to scripts as virtual urls
+    pub path_redactions: PathRedactions,
 }
 
 #[cfg(feature = "node_experimental")]
@@ -93,7 +149,28 @@ pub struct InnerRustyLoader {
     source_map_cache: SourceMapCache,
     import_provider: Option<Box<dyn ImportProvider>>,
     schema_whlist: HashSet<String>,
+    source_transform: Option<Arc<dyn SourceTransform>>,
+    disallow_dynamic_imports: bool,
+    origin_policy: Option<OriginPolicy>,
+    policy_checks_main_module: bool,
+    transpiler_options: TranspilerOptions,
+    code_cache: HashMap<String, Vec<u8>>,
+    staged_sources: HashMap<String, String>,
+    lazy_modules: HashMap<String, String>,
+    aliases: HashMap<String, ModuleSpecifier>,
+    module_graph: ModuleGraph,
     cwd: PathBuf,
+    base_url: Option<ModuleSpecifier>,
+    path_redactions: PathRedactions,
+
+    #[cfg(feature = "url_import")]
+    http_client: reqwest::Client,
+    #[cfg(feature = "url_import")]
+    fetch_credentials: Option<Arc<dyn FetchCredentials>>,
+    #[cfg(feature = "url_import")]
+    fetch_limit: Arc<tokio::sync::Semaphore>,
+    #[cfg(feature = "url_import")]
+    fetch_tracker: FetchTracker,
 
     #[cfg(feature = "node_experimental")]
     node: NodeProvider,
@@ -109,7 +186,38 @@ impl InnerRustyLoader {
             source_map_cache: options.source_map_cache,
             import_provider: options.import_provider,
             schema_whlist: options.schema_whlist,
+            source_transform: options.source_transform,
+            disallow_dynamic_imports: options.disallow_dynamic_imports,
+            origin_policy: options.origin_policy,
+            policy_checks_main_module: options.policy_checks_main_module,
+            transpiler_options: options.transpiler_options,
+            code_cache: HashMap::new(),
+            staged_sources: HashMap::new(),
+            lazy_modules: HashMap::new(),
+            aliases: HashMap::new(),
+            module_graph: ModuleGraph::default(),
             cwd: options.cwd,
+            base_url: options.base_url,
+            path_redactions: options.path_redactions,
+
+            #[cfg(feature = "url_import")]
+            http_client: options.http_client.unwrap_or_else(|| {
+                reqwest::Client::builder()
+                    .redirect(reqwest::redirect::Policy::none())
+                    .build()
+                    .unwrap_or_default()
+            }),
+            #[cfg(feature = "url_import")]
+            fetch_credentials: options.fetch_credentials,
+            #[cfg(feature = "url_import")]
+            fetch_limit: Arc::new(tokio::sync::Semaphore::new(
+                match options.max_concurrent_fetches {
+                    0 => tokio::sync::Semaphore::MAX_PERMITS,
+                    n => n,
+                },
+            )),
+            #[cfg(feature = "url_import")]
+            fetch_tracker: FetchTracker::default(),
 
             #[cfg(feature = "node_experimental")]
             node: NodeProvider::new(options.node_resolver),
@@ -121,6 +229,14 @@ impl InnerRustyLoader {
         self.cwd = cwd;
     }
 
+    /// Returns true if the specifier falls under the virtual base url
+    /// Such modules are only ever provided from rust
+    fn is_virtual(&self, specifier: &ModuleSpecifier) -> bool {
+        self.base_url
+            .as_ref()
+            .is_some_and(|base| specifier.as_str().starts_with(base.as_str()))
+    }
+
     /// Adds a module specifier to the whitelist
     /// This allows the module to be loaded from the filesystem
     /// If they are included from rust first
@@ -135,6 +251,91 @@ impl InnerRustyLoader {
         self.fs_whlist.contains(specifier)
     }
 
+    /// Transpiles a module's code, if it is typescript, JSX or TSX
+    pub fn transpile(
+        &self,
+        specifier: &ModuleSpecifier,
+        code: &str,
+    ) -> Result<ModuleContents, Error> {
+        transpile(specifier, code, &self.transpiler_options)
+    }
+
+    /// Applies the source transform, if one is set, to a module's transpiled code
+    pub fn transform_source(
+        &self,
+        specifier: &ModuleSpecifier,
+        code: String,
+    ) -> Result<String, Error> {
+        match &self.source_transform {
+            Some(transform) => transform.transform(specifier, code),
+            None => Ok(code),
+        }
+    }
+
+    /// Stores a v8 code cache for a module, used to skip compilation when it is next loaded
+    pub fn add_code_cache(&mut self, specifier: &ModuleSpecifier, data: Vec<u8>) {
+        self.code_cache.insert(specifier.to_string(), data);
+    }
+
+    /// Checks if a code cache has been stored for a module
+    pub fn has_code_cache(&self, specifier: &ModuleSpecifier) -> bool {
+        self.code_cache.contains_key(specifier.as_str())
+    }
+
+    /// Returns the stored code cache for a module, in the form expected by `ModuleSource`
+    /// v8 validates the cache against the module's source, and ignores it if they do not match
+    fn code_cache_info(&self, specifier: &ModuleSpecifier) -> Option<SourceCodeCacheInfo> {
+        self.code_cache
+            .get(specifier.as_str())
+            .map(|data| SourceCodeCacheInfo {
+                hash: 0,
+                data: Some(Cow::Owned(data.clone())),
+            })
+    }
+
+    /// Stages already-prepared code for a module loaded from rust
+    /// The next load of that specifier returns it instead of fetching the module
+    pub fn stage_source(&mut self, specifier: &ModuleSpecifier, code: String) {
+        self.staged_sources.insert(specifier.to_string(), code);
+    }
+
+    /// Registers a module's source without loading it
+    /// It is only fetched from here, and evaluated, once something imports it
+    pub fn add_lazy_module(&mut self, specifier: &ModuleSpecifier, contents: String) {
+        self.whitelist_add(specifier.as_str());
+        self.lazy_modules.insert(specifier.to_string(), contents);
+    }
+
+    /// Makes imports of a bare specifier, such as `lodash`, resolve to the given module
+    pub fn add_alias(&mut self, name: &str, specifier: ModuleSpecifier) {
+        self.aliases.insert(name.to_string(), specifier);
+    }
+
+    /// Returns a copy of the code caches, lazy modules and aliases
+    pub fn caches(&self) -> LoaderCaches {
+        LoaderCaches {
+            code_cache: self.code_cache.clone(),
+            lazy_modules: self.lazy_modules.clone(),
+            aliases: self.aliases.clone(),
+        }
+    }
+
+    /// Adds caches copied from another loader, keeping any existing entries
+    pub fn extend_caches(&mut self, caches: LoaderCaches) {
+        for (specifier, contents) in &caches.lazy_modules {
+            self.whitelist_add(specifier);
+            self.lazy_modules
+                .entry(specifier.clone())
+                .or_insert_with(|| contents.clone());
+        }
+        for (specifier, data) in caches.code_cache {
+            self.code_cache.entry(specifier).or_insert(data);
+        }
+        for (name, specifier) in caches.aliases {
+            self.aliases.entry(name).or_insert(specifier);
+        }
+    }
+
     #[allow(clippy::unused_self)]
     pub fn transpile_extension(
         &self,
@@ -152,6 +353,61 @@ impl InnerRustyLoader {
         referrer: &str,
         kind: deno_core::ResolutionKind,
     ) -> Result<ModuleSpecifier, Error> {
+        let is_dynamic_import = kind == deno_core::ResolutionKind::DynamicImport;
+        let aliased = self.aliases.contains_key(specifier);
+        let url = self.resolve_specifier(specifier, referrer, kind)?;
+
+        // Modules loaded from rust are not subject to the policy
+        if let Some(policy) = &self.origin_policy {
+            if referrer != "." || self.policy_checks_main_module {
+                policy.check(&url, referrer, is_dynamic_import, aliased)?;
+            }
+        }
+
+        Ok(url)
+    }
+
+    /// Resolves a specifier for `import.meta.resolve`
+    ///
+    /// Aliases and import providers apply as for an import, but nothing is loaded - so permission to load
+    /// the module is not needed, and the dynamic import restrictions do not apply
+    pub fn import_meta_resolve(
+        &mut self,
+        specifier: &str,
+        referrer: &str,
+    ) -> Result<ModuleSpecifier, Error> {
+        self.resolve_specifier(specifier, referrer, deno_core::ResolutionKind::Import)
+            .or_else(|_| Ok(deno_core::resolve_import(specifier, referrer)?))
+    }
+
+    fn resolve_specifier(
+        &mut self,
+        specifier: &str,
+        referrer: &str,
+        kind: deno_core::ResolutionKind,
+    ) -> Result<ModuleSpecifier, Error> {
+        if self.disallow_dynamic_imports && kind == deno_core::ResolutionKind::DynamicImport {
+            return Err(anyhow!("dynamic imports are not allowed here: {specifier}"));
+        }
+
+        // Bare specifiers aliased to modules provided from rust
+        if let Some(url) = self.aliases.get(specifier) {
+            return Ok(url.clone());
+        }
+
+        // The sqlite module, provided by an extension
+        #[cfg(feature = "sqlite")]
+        if let Some(url) = crate::ext::sqlite::resolve(specifier) {
+            return Ok(url);
+        }
+
+        // Standard library modules, provided by an extension
+        #[cfg(feature = "rustyscript_std")]
+        if specifier.starts_with("rustyscript:") {
+            return crate::ext::rustyscript_std::resolve(specifier)
+                .ok_or_else(|| anyhow!("unknown standard library module: {specifier}"));
+        }
+
         //
         // Handle import aliasing for node imports
         #[cfg(feature = "node_experimental")]
@@ -180,8 +436,9 @@ impl InnerRustyLoader {
             }
         }
 
-        // Resolve the module specifier to an absolute URL
+        // Resolve the module specifier to an absolute URL - files in redacted directories go by their virtual url
         let url = deno_core::resolve_import(specifier, referrer)?;
+        let url = self.path_redactions.to_virtual(&url).unwrap_or(url);
 
         // Check if the module is in the cache
         if self
@@ -200,12 +457,28 @@ impl InnerRustyLoader {
             }
         }
 
+        // Virtual modules are loaded from rust, or not at all - see `load`
+        if self.is_virtual(&url) {
+            return Ok(url);
+        }
+
         if referrer == "." {
             // Added from rust, add to the whitelist
             // so we can load it from the filesystem
             self.whitelist_add(url.as_str());
         }
 
+        // Redacted modules are allowed if the host files they stand for would be
+        if let Some(host) = self.path_redactions.to_host(&url) {
+            let allowed = cfg!(feature = "fs_import")
+                || self.whitelist_has(host.as_str())
+                || self.whitelist_has(url.as_str());
+            if !allowed {
+                return Err(anyhow!("requested module is not loaded: {specifier}"));
+            }
+            return Ok(url);
+        }
+
         // We check permissions first
         match url.scheme() {
             // Remote fetch imports
@@ -276,11 +549,55 @@ impl InnerRustyLoader {
         let module_specifier = module_specifier.clone();
         let maybe_referrer = maybe_referrer.cloned();
 
+        // Modules loaded from rust are staged ahead of time
+        let staged = inner
+            .borrow_mut()
+            .staged_sources
+            .remove(module_specifier.as_str());
+        if let Some(code) = staged {
+            let code_cache = inner.borrow().code_cache_info(&module_specifier);
+            return ModuleLoadResponse::Sync(Ok(ModuleSource::new(
+                ModuleType::JavaScript,
+                ModuleSourceCode::String(code.into()),
+                &module_specifier,
+                code_cache,
+            )));
+        }
+
+        // Then modules registered from rust without being loaded
+        let lazy = inner
+            .borrow()
+            .lazy_modules
+            .get(module_specifier.as_str())
+            .cloned();
+        if let Some(contents) = lazy {
+            return ModuleLoadResponse::Async(
+                async move {
+                    Self::handle_load(
+                        inner,
+                        module_specifier,
+                        ModuleOrigin::Host,
+                        |inner, specifier| Self::translate_cjs(inner, specifier, contents),
+                    )
+                    .await
+                }
+                .boxed_local(),
+            );
+        }
+
         // Check if the module is in the cache first
-        if let Some(cache) = &inner.borrow().cache_provider {
-            if let Some(source) = cache.get(&module_specifier) {
-                return deno_core::ModuleLoadResponse::Sync(Ok(source));
-            }
+        let cached = inner
+            .borrow()
+            .cache_provider
+            .as_ref()
+            .and_then(|cache| cache.get(&module_specifier));
+        if let Some(source) = cached {
+            inner.borrow_mut().module_graph.record(
+                &module_specifier,
+                ModuleOrigin::Cache,
+                source.code.as_bytes(),
+            );
+            return deno_core::ModuleLoadResponse::Sync(Ok(source));
         }
 
         // Next check the import provider
@@ -295,26 +612,68 @@ impl InnerRustyLoader {
         if let Some(result) = provider_result {
             return ModuleLoadResponse::Async(
                 async move {
-                    Self::handle_load(inner, module_specifier, |_, _| async move { result }).await
+                    Self::handle_load(
+                        inner,
+                        module_specifier,
+                        ModuleOrigin::ImportProvider,
+                        |_, _| async move { result },
+                    )
+                    .await
+                }
+                .boxed_local(),
+            );
+        }
+
+        // Redacted modules are read from the host files they stand for
+        let host = inner.borrow().path_redactions.to_host(&module_specifier);
+        if let Some(host) = host {
+            let origin = ModuleOrigin::File(host.to_file_path().unwrap_or_default());
+            return ModuleLoadResponse::Async(
+                async move {
+                    Self::handle_load(inner, module_specifier, origin, |inner, _| {
+                        Self::load_file(inner, host)
+                    })
+                    .await
                 }
                 .boxed_local(),
             );
         }
 
+        // Virtual modules not provided from rust do not exist
+        if inner.borrow().is_virtual(&module_specifier) {
+            return ModuleLoadResponse::Sync(Err(anyhow!(
+                "requested module is not loaded: {module_specifier}"
+            )));
+        }
+
         // We check permissions next
         match module_specifier.scheme() {
             // Remote fetch imports
             #[cfg(feature = "url_import")]
             "https" | "http" => ModuleLoadResponse::Async(
-                async move { Self::handle_load(inner, module_specifier, Self::load_remote).await }
-                    .boxed_local(),
+                async move {
+                    Self::handle_load(
+                        inner,
+                        module_specifier,
+                        ModuleOrigin::Remote,
+                        Self::load_remote,
+                    )
+                    .await
+                }
+                .boxed_local(),
             ),
 
             // FS imports
-            "file" => ModuleLoadResponse::Async(
-                async move { Self::handle_load(inner, module_specifier, Self::load_file).await }
+            "file" => {
+                let origin =
+                    ModuleOrigin::File(module_specifier.to_file_path().unwrap_or_default());
+                ModuleLoadResponse::Async(
+                    async move {
+                        Self::handle_load(inner, module_specifier, origin, Self::load_file).await
+                    }
                     .boxed_local(),
-            ),
+                )
+            }
 
             // Default deny-all
             _ => ModuleLoadResponse::Sync(Err(anyhow!(
@@ -375,19 +734,101 @@ impl InnerRustyLoader {
         Ok(content)
     }
 
+    /// Fetches a remote module
+    ///
+    /// `deno_core` loads the independent imports of a module concurrently,
+    /// so the number of fetches in flight at once is bounded by `max_concurrent_fetches`
     #[cfg(feature = "url_import")]
     async fn load_remote(
-        _: Rc<RefCell<Self>>,
+        inner: Rc<RefCell<Self>>,
         module_specifier: ModuleSpecifier,
     ) -> Result<String, Error> {
-        let response = reqwest::get(module_specifier).await?;
-        Ok(response.text().await?)
+        let (client, limit, credentials) = {
+            let inner = inner.borrow();
+            (
+                inner.http_client.clone(),
+                inner.fetch_limit.clone(),
+                inner.fetch_credentials.clone(),
+            )
+        };
+
+        let _permit = limit.acquire_owned().await?;
+
+        let started = inner.borrow_mut().fetch_tracker.start();
+        let result = Self::fetch_remote(&client, credentials.as_deref(), &module_specifier).await;
+
+        inner
+            .borrow_mut()
+            .fetch_tracker
+            .finish(started, result.as_ref().ok().map(String::len));
+        result
+    }
+
+    /// Fetches the code of a remote module, following redirects
+    ///
+    /// Redirects are followed here instead of by the client, so that credentials are looked up again for each URL
+    /// and headers meant for one host are never sent to another
+    #[cfg(feature = "url_import")]
+    async fn fetch_remote(
+        client: &reqwest::Client,
+        credentials: Option<&dyn FetchCredentials>,
+        module_specifier: &ModuleSpecifier,
+    ) -> Result<String, Error> {
+        let mut url = module_specifier.clone();
+        for _ in 0..=MAX_REDIRECTS {
+            let mut request = client.get(url.clone());
+            if let Some(credentials) = credentials {
+                request = request.headers(credential_headers(credentials, &url)?);
+            }
+
+            let response = request.send().await?;
+            let location = match response.status().as_u16() {
+                301 | 302 | 303 | 307 | 308 => response
+                    .headers()
+                    .get(reqwest::header::LOCATION)
+                    .and_then(|location| location.to_str().ok())
+                    .map(str::to_string),
+                _ => None,
+            };
+
+            match location {
+                Some(location) => url = url.join(&location)?,
+                None => return Ok(response.text().await?),
+            }
+        }
+
+        Err(anyhow!(
+            "Too many redirects while fetching `{module_specifier}`"
+        ))
+    }
+
+    /// Returns statistics about the remote modules fetched so far
+    #[cfg(feature = "url_import")]
+    pub fn fetch_stats(&self) -> FetchStats {
+        self.fetch_tracker.stats()
+    }
+
+    /// Records the code of a module loaded without passing through the loader, such as from rust
+    pub fn record_module(
+        &mut self,
+        specifier: &ModuleSpecifier,
+        origin: ModuleOrigin,
+        code: &[u8],
+    ) {
+        self.module_graph.record(specifier, origin, code);
+    }
+
+    /// Returns every module loaded so far, and where its code came from
+    pub fn module_graph(&self) -> &ModuleGraph {
+        &self.module_graph
     }
 
     /// Loads a module's source code from the cache or from the provided handler
+    /// The code is recorded in the module graph as coming from `origin`, unless it was cached
     async fn handle_load<F, Fut>(
         inner: Rc<RefCell<Self>>,
         module_specifier: ModuleSpecifier,
+        origin: ModuleOrigin,
         handler: F,
     ) -> Result<ModuleSource, deno_core::error::AnyError>
     where
@@ -395,12 +836,17 @@ impl InnerRustyLoader {
         Fut: std::future::Future<Output = Result<String, deno_core::error::AnyError>>,
     {
         // Check if the module is in the cache first
-        if let Some(Some(source)) = inner
+        let cached = inner
             .borrow()
             .cache_provider
             .as_ref()
-            .map(|p| p.get(&module_specifier))
-        {
+            .and_then(|p| p.get(&module_specifier));
+        if let Some(source) = cached {
+            inner.borrow_mut().module_graph.record(
+                &module_specifier,
+                ModuleOrigin::Cache,
+                source.code.as_bytes(),
+            );
             return Ok(source);
         }
 
@@ -420,14 +866,27 @@ impl InnerRustyLoader {
 
         // Load the module code, and transpile it if necessary
         let code = handler(inner.clone(), module_specifier.clone()).await?;
-        let (tcode, source_map) = transpile(&module_specifier, &code)?;
+        inner
+            .borrow_mut()
+            .module_graph
+            .record(&module_specifier, origin, code.as_bytes());
+        let (tcode, source_map) = inner.borrow().transpile(&module_specifier, &code)?;
+        let (tcode, code_cache) = if module_type == ModuleType::JavaScript {
+            let inner = inner.borrow();
+            (
+                inner.transform_source(&module_specifier, tcode)?,
+                inner.code_cache_info(&module_specifier),
+            )
+        } else {
+            (tcode, None)
+        };
 
         // Create the module source
         let mut source = ModuleSource::new(
             module_type,
             ModuleSourceCode::String(tcode.into()),
             &module_specifier,
-            None,
+            code_cache,
         );
 
         // Add the source to our source cache
@@ -0,0 +1,165 @@
+use deno_core::ModuleSpecifier;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{path::PathBuf, time::SystemTime};
+
+/// Where the code of a loaded module came from, see [`ModuleProvenance`]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModuleOrigin {
+    /// Provided from rust, such as with [`crate::Runtime::load_module`] or [`crate::Runtime::register_lazy_modules`]
+    Host,
+
+    /// Read from a file on the host - the path is the real one, even for modules named by [`PathRedactions`](super::PathRedactions)
+    File(PathBuf),
+
+    /// Fetched from the module's URL (`url_import` crate feature)
+    Remote,
+
+    /// Returned by the runtime's [`ImportProvider`](super::ImportProvider)
+    ImportProvider,
+
+    /// Returned by the runtime's [`ModuleCacheProvider`](super::ModuleCacheProvider), without being fetched again
+    Cache,
+}
+
+/// Provenance of a single module loaded by a runtime, see [`ModuleGraph`]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModuleProvenance {
+    /// The specifier the module was loaded as
+    pub specifier: String,
+
+    /// Where the module's code came from
+    pub origin: ModuleOrigin,
+
+    /// Hex-encoded SHA-256 hash of the module's code, as loaded - before it was transpiled or transformed
+    pub sha256: String,
+
+    /// Size of the module's code, in bytes
+    pub size: usize,
+
+    /// When the module's code was loaded, or fetched
+    pub fetched_at: SystemTime,
+}
+
+/// Every module loaded by a runtime, and where its code came from
+///
+/// Lets hosts audit exactly what code ran in the sandbox - see [`ModuleGraph::report`]
+/// Modules built into rustyscript and its extensions are not included
+///
+/// See [`crate::Runtime::module_graph`]
+#[derive(Clone, Debug, Default)]
+pub struct ModuleGraph {
+    modules: Vec<ModuleProvenance>,
+}
+
+impl ModuleGraph {
+    /// Records the code of a module as it is loaded
+    ///
+    /// A module loaded again with identical code is only recorded once
+    pub(crate) fn record(
+        &mut self,
+        specifier: &ModuleSpecifier,
+        origin: ModuleOrigin,
+        code: &[u8],
+    ) {
+        let sha256 = format!("{:x}", Sha256::digest(code));
+        let seen = self
+            .modules
+            .iter()
+            .any(|m| m.specifier == specifier.as_str() && m.sha256 == sha256);
+        if seen {
+            return;
+        }
+
+        self.modules.push(ModuleProvenance {
+            specifier: specifier.to_string(),
+            origin,
+            sha256,
+            size: code.len(),
+            fetched_at: SystemTime::now(),
+        });
+    }
+
+    /// Returns the modules loaded so far, in load order
+    #[must_use]
+    pub fn modules(&self) -> &[ModuleProvenance] {
+        &self.modules
+    }
+
+    /// Finds a loaded module by its specifier
+    /// If its code changed between loads, the latest is returned
+    #[must_use]
+    pub fn get(&self, specifier: &str) -> Option<&ModuleProvenance> {
+        self.modules.iter().rev().find(|m| m.specifier == specifier)
+    }
+
+    /// Generates a provenance report for everything loaded so far, which can be serialized for auditing
+    ///
+    /// ```rust
+    /// use rustyscript::{ Error, Module, Runtime };
+    ///
+    /// # fn main() -> Result<(), Error> {
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// runtime.load_module(&Module::new("main.js", "export const value = 1;"))?;
+    ///
+    /// let report = runtime.module_graph().report();
+    /// assert_eq!(report.modules.len(), 1);
+    /// assert_eq!(report.total_size, 23);
+    ///
+    /// let json = rustyscript::serde_json::to_string_pretty(&report)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn report(&self) -> ProvenanceReport {
+        ProvenanceReport {
+            generated_at: SystemTime::now(),
+            total_size: self.modules.iter().map(|m| m.size).sum(),
+            modules: self.modules.clone(),
+        }
+    }
+}
+
+/// A provenance report for the modules loaded by a runtime, see [`ModuleGraph::report`]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProvenanceReport {
+    /// When the report was generated
+    pub generated_at: SystemTime,
+
+    /// Total size of the code loaded, in bytes
+    pub total_size: usize,
+
+    /// Every module loaded, in load order
+    pub modules: Vec<ModuleProvenance>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let mut graph = ModuleGraph::default();
+        let specifier = ModuleSpecifier::parse("file:///app/main.js").unwrap();
+
+        graph.record(&specifier, ModuleOrigin::Host, b"abc");
+        graph.record(&specifier, ModuleOrigin::Host, b"abc");
+        assert_eq!(graph.modules().len(), 1);
+
+        let module = graph.get("file:///app/main.js").unwrap();
+        assert_eq!(module.size, 3);
+        assert_eq!(
+            module.sha256,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+
+        // Changed code is recorded again
+        graph.record(&specifier, ModuleOrigin::Host, b"abcd");
+        assert_eq!(graph.get("file:///app/main.js").unwrap().size, 4);
+
+        let report = graph.report();
+        assert_eq!(report.modules.len(), 2);
+        assert_eq!(report.total_size, 7);
+    }
+}
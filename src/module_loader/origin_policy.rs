@@ -0,0 +1,251 @@
+use deno_core::ModuleSpecifier;
+use std::{collections::HashSet, fmt::Display, sync::Arc};
+
+type ViolationCallback = Arc<dyn Fn(&PolicyViolation) + Send + Sync>;
+
+/// Schemes of modules provided by the runtime and its extensions, which every policy allows
+const INTERNAL_SCHEMES: [&str; 3] = ["ext:", "host:", "rustyscript:"];
+
+/// Controls where a runtime's modules may be imported from, in one place
+///
+/// The policy is checked by the module loader for every import made from javascript,
+/// after the runtime's other options such as [`crate::RuntimeOptions::schema_whlist`] allowed it
+/// Modules loaded from rust, such as with [`crate::Runtime::load_module`], are not restricted
+///
+/// Each blocked import is reported to [`OriginPolicy::on_violation`], and fails with a catchable error
+///
+/// ```rust
+/// use rustyscript::{ module_loader::OriginPolicy, RuntimeOptions };
+/// use std::sync::Arc;
+///
+/// let options = RuntimeOptions {
+///     origin_policy: Some(OriginPolicy {
+///         allowed_origins: Some(["file:".to_string(), "https://deno.land".to_string()].into()),
+///         allow_dynamic_import: false,
+///         on_violation: Some(Arc::new(|violation| eprintln!("blocked: {violation}"))),
+///         ..Default::default()
+///     }),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Clone)]
+pub struct OriginPolicy {
+    /// Origins modules may be imported from, or `None` to allow any origin
+    ///
+    /// An entry is either a scheme, such as `file:` or `node:`, which allows every module using it,
+    /// or a scheme and host, such as `https://deno.land`, which allows every module on that host
+    ///
+    /// Modules provided by the runtime are always allowed - extension modules (`ext:`), host namespaces (`host:`),
+    /// the standard library (`rustyscript:`), and modules aliased from rust with [`crate::Runtime::alias_module`]
+    ///
+    /// Default: `None`
+    pub allowed_origins: Option<HashSet<String>>,
+
+    /// Whether `import()` may be used
+    ///
+    /// Default: `true`
+    pub allow_dynamic_import: bool,
+
+    /// Whether scripts may start web workers (`node_experimental` crate feature)
+    ///
+    /// When false, or if the worker's module is from an origin that is not allowed, the worker fails to start
+    ///
+    /// Default: `true`
+    pub allow_workers: bool,
+
+    /// Optional callback, called with each import blocked by the policy
+    ///
+    /// Default: `None`
+    pub on_violation: Option<ViolationCallback>,
+}
+
+impl Default for OriginPolicy {
+    fn default() -> Self {
+        Self {
+            allowed_origins: None,
+            allow_dynamic_import: true,
+            allow_workers: true,
+            on_violation: None,
+        }
+    }
+}
+
+impl std::fmt::Debug for OriginPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OriginPolicy")
+            .field("allowed_origins", &self.allowed_origins)
+            .field("allow_dynamic_import", &self.allow_dynamic_import)
+            .field("allow_workers", &self.allow_workers)
+            .field("on_violation", &self.on_violation.is_some())
+            .finish()
+    }
+}
+
+impl OriginPolicy {
+    /// Returns true if modules may be imported from the given specifier's origin
+    #[must_use]
+    pub fn permits_origin(&self, specifier: &ModuleSpecifier) -> bool {
+        let Some(allowed) = &self.allowed_origins else {
+            return true;
+        };
+
+        let scheme = format!("{}:", specifier.scheme());
+        INTERNAL_SCHEMES.contains(&scheme.as_str())
+            || allowed.contains(&scheme)
+            || specifier.origin().is_tuple()
+                && allowed.contains(&specifier.origin().ascii_serialization())
+    }
+
+    /// Checks an import against the policy, reporting it if it is blocked
+    /// `aliased` imports resolve to modules provided from rust, so their origin is not checked
+    pub(crate) fn check(
+        &self,
+        specifier: &ModuleSpecifier,
+        referrer: &str,
+        is_dynamic_import: bool,
+        aliased: bool,
+    ) -> Result<(), PolicyViolation> {
+        let kind = if is_dynamic_import && !self.allow_dynamic_import {
+            ViolationKind::DynamicImport
+        } else if !aliased && !self.permits_origin(specifier) {
+            ViolationKind::Origin
+        } else {
+            return Ok(());
+        };
+
+        let violation = PolicyViolation {
+            kind,
+            specifier: specifier.clone(),
+            referrer: referrer.to_string(),
+        };
+        self.report(&violation);
+        Err(violation)
+    }
+
+    /// Returns the policy for a web worker started from the given module
+    ///
+    /// If the worker is not allowed, the violation is reported and the returned policy blocks every import,
+    /// including the worker's main module - so that the worker fails to start
+    #[cfg(feature = "node_experimental")]
+    pub(crate) fn for_worker(&self, main_module: &ModuleSpecifier) -> Self {
+        if self.allow_workers && self.permits_origin(main_module) {
+            return self.clone();
+        }
+
+        self.report(&PolicyViolation {
+            kind: ViolationKind::Worker,
+            specifier: main_module.clone(),
+            referrer: String::new(),
+        });
+        Self {
+            allowed_origins: Some(HashSet::new()),
+            on_violation: None,
+            ..self.clone()
+        }
+    }
+
+    fn report(&self, violation: &PolicyViolation) {
+        if let Some(on_violation) = &self.on_violation {
+            on_violation(violation);
+        }
+    }
+}
+
+/// The rule of an [`OriginPolicy`] that blocked an import
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// The module's origin is not in [`OriginPolicy::allowed_origins`]
+    Origin,
+
+    /// The module was imported with `import()`, and [`OriginPolicy::allow_dynamic_import`] is false
+    DynamicImport,
+
+    /// A web worker was started, and [`OriginPolicy::allow_workers`] is false or its origin is not allowed
+    Worker,
+}
+
+/// An import blocked by an [`OriginPolicy`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PolicyViolation {
+    /// The rule that was violated
+    pub kind: ViolationKind,
+
+    /// The module that was blocked
+    pub specifier: ModuleSpecifier,
+
+    /// The module that imported it, if any
+    pub referrer: String,
+}
+
+impl Display for PolicyViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            ViolationKind::Origin => write!(
+                f,
+                "module origin is not allowed by policy: {}",
+                self.specifier
+            ),
+            ViolationKind::DynamicImport => write!(
+                f,
+                "dynamic import is not allowed by policy: {}",
+                self.specifier
+            ),
+            ViolationKind::Worker => {
+                write!(f, "worker is not allowed by policy: {}", self.specifier)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PolicyViolation {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_origin_policy() {
+        let policy = OriginPolicy {
+            allowed_origins: Some(["file:".to_string(), "https://deno.land".to_string()].into()),
+            allow_dynamic_import: false,
+            ..Default::default()
+        };
+
+        let allowed = [
+            "file:///app/main.js",
+            "https://deno.land/std/mod.ts",
+            "ext:core/mod.js",
+            "host:db",
+            "rustyscript:std/uuid",
+        ];
+        for specifier in allowed {
+            let specifier = ModuleSpecifier::parse(specifier).unwrap();
+            assert!(policy.permits_origin(&specifier), "{specifier}");
+        }
+
+        let blocked = [
+            "https://example.com/mod.ts",
+            "http://deno.land/mod.ts",
+            "node:fs",
+        ];
+        for specifier in blocked {
+            let specifier = ModuleSpecifier::parse(specifier).unwrap();
+            assert!(!policy.permits_origin(&specifier), "{specifier}");
+        }
+
+        let specifier = ModuleSpecifier::parse("file:///app/lib.js").unwrap();
+        let violation = policy
+            .check(&specifier, "file:///app/main.js", true, false)
+            .unwrap_err();
+        assert_eq!(violation.kind, ViolationKind::DynamicImport);
+        policy
+            .check(&specifier, "file:///app/main.js", false, false)
+            .unwrap();
+
+        // Aliased modules are provided from rust, wherever they resolve to
+        let specifier = ModuleSpecifier::parse("https://example.com/mod.ts").unwrap();
+        policy
+            .check(&specifier, "file:///app/main.js", false, true)
+            .unwrap();
+    }
+}
@@ -0,0 +1,135 @@
+use crate::Error;
+use deno_core::ModuleSpecifier;
+use std::path::Path;
+
+/// Rewrites host directories into virtual urls, so scripts never see where their files live on the server
+///
+/// Modules in a mapped directory are named by their virtual url - in stack traces, error messages and `import.meta.url` -
+/// and imports of virtual urls are loaded from the host files they stand for
+///
+/// See [`crate::RuntimeOptions::path_redactions`]
+///
+/// ```rust
+/// use rustyscript::module_loader::PathRedactions;
+///
+/// # fn main() -> Result<(), rustyscript::Error> {
+/// let redactions = PathRedactions::new().with_mapping("/srv/app/scripts", "app:///scripts/")?;
+/// assert_eq!(
+///     redactions.redact("failed at file:///srv/app/scripts/main.js:1:5"),
+///     "failed at app:///scripts/main.js:1:5"
+/// );
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct PathRedactions(Vec<Mapping>);
+
+#[derive(Clone, Debug)]
+struct Mapping {
+    host_url: ModuleSpecifier,
+    host_path: String,
+    virtual_url: ModuleSpecifier,
+}
+
+impl PathRedactions {
+    /// Creates an empty set of redactions
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maps a host directory, and everything in it, to a virtual url such as `app:///scripts/`
+    /// Mappings are checked in the order they were added
+    ///
+    /// # Errors
+    /// Will return an error if the directory is not an absolute path, or the virtual url is not valid
+    pub fn with_mapping(
+        mut self,
+        host_dir: impl AsRef<Path>,
+        virtual_url: &str,
+    ) -> Result<Self, Error> {
+        let host_dir = host_dir.as_ref();
+        let host_url = ModuleSpecifier::from_directory_path(host_dir).map_err(|()| {
+            Error::Runtime(format!("{} is not an absolute path", host_dir.display()))
+        })?;
+
+        let mut virtual_url = ModuleSpecifier::parse(virtual_url)?;
+        if !virtual_url.path().ends_with('/') {
+            virtual_url.set_path(&format!("{}/", virtual_url.path()));
+        }
+
+        let host_path = host_url.to_file_path().map_or_else(
+            |()| host_dir.display().to_string(),
+            |p| p.display().to_string(),
+        );
+
+        self.0.push(Mapping {
+            host_url,
+            host_path,
+            virtual_url,
+        });
+        Ok(self)
+    }
+
+    /// Returns true if no directories are mapped
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Converts the specifier of a host file into its virtual url, if it is in a mapped directory
+    #[must_use]
+    pub fn to_virtual(&self, specifier: &ModuleSpecifier) -> Option<ModuleSpecifier> {
+        self.0.iter().find_map(|m| {
+            let rest = specifier.as_str().strip_prefix(m.host_url.as_str())?;
+            m.virtual_url.join(rest).ok()
+        })
+    }
+
+    /// Converts a virtual url back into the specifier of the host file it stands for
+    #[must_use]
+    pub fn to_host(&self, specifier: &ModuleSpecifier) -> Option<ModuleSpecifier> {
+        self.0.iter().find_map(|m| {
+            let rest = specifier.as_str().strip_prefix(m.virtual_url.as_str())?;
+            m.host_url.join(rest).ok()
+        })
+    }
+
+    /// Rewrites every mapped host path or file url found in a string, such as an error message from the host
+    #[must_use]
+    pub fn redact(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for m in &self.0 {
+            text = text.replace(m.host_url.as_str(), m.virtual_url.as_str());
+            text = text.replace(&m.host_path, m.virtual_url.as_str());
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_path_redactions() {
+        let dir = std::env::temp_dir().join("scripts");
+        let redactions = PathRedactions::new()
+            .with_mapping(&dir, "app:///scripts")
+            .unwrap();
+
+        let host = ModuleSpecifier::from_file_path(dir.join("lib/util.js")).unwrap();
+        let virtual_url = redactions.to_virtual(&host).unwrap();
+        assert_eq!(virtual_url.as_str(), "app:///scripts/lib/util.js");
+        assert_eq!(redactions.to_host(&virtual_url), Some(host));
+
+        let other = ModuleSpecifier::parse("file:///elsewhere/main.js").unwrap();
+        assert_eq!(redactions.to_virtual(&other), None);
+
+        let message = format!("Could not read {}", dir.join("main.js").display());
+        assert_eq!(
+            redactions.redact(&message),
+            "Could not read app:///scripts/main.js"
+        );
+    }
+}
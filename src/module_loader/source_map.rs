@@ -0,0 +1,41 @@
+use deno_core::sourcemap::SourceMap;
+
+/// A position in a module's original source code, before it was transpiled
+/// See [`crate::Runtime::map_location`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OriginalLocation {
+    /// The specifier of the original source, as recorded in the source map
+    pub specifier: String,
+
+    /// Line number in the original source, starting at 1
+    pub line: u32,
+
+    /// Column number in the original source, starting at 1
+    pub column: u32,
+
+    /// The original name of the identifier at this position, if the source map records one
+    pub name: Option<String>,
+
+    /// The text of the line in the original source
+    pub source_line: Option<String>,
+}
+
+/// Translates a 1-based position in transpiled code into its position in the original source
+pub(crate) fn map_location(
+    source: &str,
+    source_map: &[u8],
+    line: u32,
+    column: u32,
+) -> Option<OriginalLocation> {
+    let source_map = SourceMap::from_slice(source_map).ok()?;
+    let token = source_map.lookup_token(line.checked_sub(1)?, column.checked_sub(1)?)?;
+
+    let line = token.get_src_line();
+    Some(OriginalLocation {
+        specifier: token.get_source()?.to_string(),
+        line: line + 1,
+        column: token.get_src_col() + 1,
+        name: token.get_name().map(str::to_string),
+        source_line: source.lines().nth(line as usize).map(str::to_string),
+    })
+}
@@ -0,0 +1,43 @@
+use deno_core::{anyhow::Error, ModuleSpecifier};
+
+/// A transformation applied to the code of every javascript module loaded by the runtime
+///
+/// Runs after the module has been fetched and transpiled, so it always receives javascript
+/// This applies to modules loaded from rust, as well as those imported by scripts
+///
+/// Useful for injecting instrumentation, such as coverage counters, API shims or header banners
+///
+/// Note that transforms adding or removing lines will shift the line numbers reported in errors
+///
+/// Implemented for closures of the form `Fn(&ModuleSpecifier, String) -> Result<String, Error>`:
+/// ```rust
+/// use rustyscript::{ RuntimeOptions, deno_core::{ ModuleSpecifier, anyhow::Error } };
+/// use std::sync::Arc;
+///
+/// let options = RuntimeOptions {
+///     source_transform: Some(Arc::new(|_: &ModuleSpecifier, code: String| {
+///         Ok::<_, Error>(format!("/* instrumented */ {code}"))
+///     })),
+///     ..Default::default()
+/// };
+/// ```
+pub trait SourceTransform: Send + Sync {
+    /// Transform the code of a module
+    ///
+    /// # Arguments
+    /// - `specifier`: The module specifier of the module being loaded
+    /// - `code`: The module's transpiled javascript code
+    ///
+    /// # Errors
+    /// Any error returned will prevent the module from loading
+    fn transform(&self, specifier: &ModuleSpecifier, code: String) -> Result<String, Error>;
+}
+
+impl<F> SourceTransform for F
+where
+    F: Fn(&ModuleSpecifier, String) -> Result<String, Error> + Send + Sync,
+{
+    fn transform(&self, specifier: &ModuleSpecifier, code: String) -> Result<String, Error> {
+        self(specifier, code)
+    }
+}
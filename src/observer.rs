@@ -0,0 +1,198 @@
+//! Hooks notified as a runtime loads modules and calls functions, see [`RuntimeObserver`]
+use crate::{Error, Module, ModuleHandle};
+use std::time::{Duration, Instant};
+
+/// A call made into javascript, as seen by a [`RuntimeObserver`]
+#[derive(Clone, Copy, Debug)]
+pub struct CallInfo<'a> {
+    /// The module the function was called through, if any
+    pub module: Option<&'a ModuleHandle>,
+
+    /// The name of the function called, or None for a module's entrypoint
+    pub function: Option<&'a str>,
+}
+
+impl CallInfo<'_> {
+    /// Calls made by rustyscript itself, to its own javascript helpers, are not observed
+    fn is_internal(&self) -> bool {
+        self.function
+            .is_some_and(|name| name.starts_with("__rustyscript_"))
+    }
+}
+
+/// Observes the work done by a runtime, for concerns like audit logging, metrics, or billing
+///
+/// Register observers with [`crate::RuntimeOptions::observers`]
+/// Every method has an empty default, so only the events of interest need to be implemented
+///
+/// Calls made through [`crate::Runtime::call_function`], [`crate::Runtime::call_entrypoint`],
+/// and the methods built on them, are observed - including their async and immediate variants
+///
+/// ```rust
+/// use rustyscript::{ json_args, CallInfo, Error, Module, Runtime, RuntimeObserver, RuntimeOptions };
+/// use std::{ sync::atomic::{ AtomicUsize, Ordering }, sync::Arc, time::Duration };
+///
+/// struct CallCounter(Arc<AtomicUsize>);
+/// impl RuntimeObserver for CallCounter {
+///     fn after_call(&self, call: &CallInfo, duration: Duration, outcome: Result<(), &Error>) {
+///         self.0.fetch_add(1, Ordering::Relaxed);
+///     }
+/// }
+///
+/// # fn main() -> Result<(), Error> {
+/// let calls = Arc::new(AtomicUsize::new(0));
+/// let mut runtime = Runtime::new(RuntimeOptions {
+///     observers: vec![Box::new(CallCounter(calls.clone()))],
+///     ..Default::default()
+/// })?;
+///
+/// let module = runtime.load_module(&Module::new("test.js", "export const f = () => 1;"))?;
+/// runtime.call_function::<i64>(Some(&module), "f", json_args!())?;
+/// assert_eq!(calls.load(Ordering::Relaxed), 1);
+/// # Ok(())
+/// # }
+/// ```
+#[allow(unused_variables)]
+pub trait RuntimeObserver {
+    /// Called after a module is loaded and evaluated, or fails to load
+    /// The duration includes transpiling the module, and running its top-level code
+    fn on_module_loaded(
+        &self,
+        module: &Module,
+        duration: Duration,
+        outcome: Result<&ModuleHandle, &Error>,
+    ) {
+    }
+
+    /// Called before a function is called
+    fn before_call(&self, call: &CallInfo) {}
+
+    /// Called once a call completes, with the time it took - including resolving the returned promise
+    fn after_call(&self, call: &CallInfo, duration: Duration, outcome: Result<(), &Error>) {}
+}
+
+/// The observers registered with a runtime
+pub(crate) struct Observers(Vec<Box<dyn RuntimeObserver>>);
+
+impl Observers {
+    pub(crate) fn new(observers: Vec<Box<dyn RuntimeObserver>>) -> Self {
+        Self(observers)
+    }
+
+    /// Notifies observers of a loaded module
+    pub(crate) fn module_loaded(
+        &self,
+        module: &Module,
+        started: Instant,
+        outcome: Result<&ModuleHandle, &Error>,
+    ) {
+        let duration = started.elapsed();
+        for observer in &self.0 {
+            observer.on_module_loaded(module, duration, outcome);
+        }
+    }
+
+    /// Notifies observers of a call, returning the time it started
+    pub(crate) fn before_call(&self, call: &CallInfo) -> Instant {
+        if !call.is_internal() {
+            for observer in &self.0 {
+                observer.before_call(call);
+            }
+        }
+        Instant::now()
+    }
+
+    /// Notifies observers of a completed call
+    pub(crate) fn after_call<T>(
+        &self,
+        call: &CallInfo,
+        started: Instant,
+        result: &Result<T, Error>,
+    ) {
+        if call.is_internal() {
+            return;
+        }
+
+        let duration = started.elapsed();
+        for observer in &self.0 {
+            observer.after_call(call, duration, result.as_ref().map(|_| ()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{json_args, Runtime, RuntimeOptions};
+    use std::{cell::RefCell, rc::Rc};
+
+    #[derive(Clone, Default)]
+    struct Recorder(Rc<RefCell<Vec<String>>>);
+    impl RuntimeObserver for Recorder {
+        fn on_module_loaded(
+            &self,
+            module: &Module,
+            _duration: Duration,
+            outcome: Result<&ModuleHandle, &Error>,
+        ) {
+            let name = module.filename().display();
+            self.0
+                .borrow_mut()
+                .push(format!("load {name} {}", outcome.is_ok()));
+        }
+
+        fn before_call(&self, call: &CallInfo) {
+            let name = call.function.unwrap_or("<entrypoint>");
+            self.0.borrow_mut().push(format!("before {name}"));
+        }
+
+        fn after_call(&self, call: &CallInfo, _duration: Duration, outcome: Result<(), &Error>) {
+            let name = call.function.unwrap_or("<entrypoint>");
+            self.0
+                .borrow_mut()
+                .push(format!("after {name} {}", outcome.is_ok()));
+        }
+    }
+
+    #[test]
+    fn test_observer() {
+        let recorder = Recorder::default();
+        let mut runtime = Runtime::new(RuntimeOptions {
+            observers: vec![Box::new(recorder.clone())],
+            metering: true,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let module = Module::new(
+            "test.js",
+            "export default () => 1; export const fail = () => { throw 1; };",
+        );
+        let handle = runtime.load_module(&module).unwrap();
+        runtime
+            .load_module(&Module::new("bad.js", "throw new Error('bad');"))
+            .unwrap_err();
+
+        runtime
+            .call_entrypoint::<i64>(&handle, json_args!())
+            .unwrap();
+        runtime
+            .call_function::<()>(Some(&handle), "fail", json_args!())
+            .unwrap_err();
+
+        // Internal calls are not observed
+        runtime.execution_cost().unwrap();
+
+        assert_eq!(
+            *recorder.0.borrow(),
+            [
+                "load test.js true",
+                "load bad.js false",
+                "before <entrypoint>",
+                "after <entrypoint> true",
+                "before fail",
+                "after fail false",
+            ]
+        );
+    }
+}
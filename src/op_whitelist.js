@@ -89,6 +89,28 @@ export const whitelist = {
     "op_register_entrypoint": "Rustyscript builtin",
     "call_registered_function": "Rustyscript builtin",
     "call_registered_function_async": "Rustyscript builtin",
+    "call_registered_function_blocking": "Rustyscript builtin",
+    "op_queue_push": "Rustyscript builtin",
+    "op_progress_open": "Rustyscript builtin",
+    "call_registered_progress_function": "Rustyscript builtin",
+    "call_reentrant_function": "Rustyscript builtin",
+    "op_capture_function": "Rustyscript builtin",
+    "op_progress_next": "Rustyscript builtin",
+    "op_bench_now": "Rustyscript builtin",
+    "op_meter": "Rustyscript builtin - counts execution cost, the host resets it",
+    "op_context_gate": "Rustyscript builtin - only reports whether the host opened the context gate",
+    "op_abort_wait": "Rustyscript builtin",
+    "op_namespace_functions": "Rustyscript builtin",
+    "op_host_log": "Rustyscript builtin",
+    "op_trace_value": "Rustyscript builtin",
+    "op_trace_timer": "Rustyscript builtin",
+    "op_rustyscript_exit": "Rustyscript builtin - node_experimental only, behind Deno.exit - ends the runtime, or the process with ExitPolicy::Process",
+    "op_script_args": "Rustyscript builtin",
+    "op_sqlite_open": "Rustyscript builtin - sqlite, databases stay in memory or within the storage directory",
+    "op_sqlite_exec": "Rustyscript builtin - sqlite, ATTACH and VACUUM are refused",
+    "op_sqlite_prepare": "Rustyscript builtin - sqlite",
+    "op_sqlite_query": "Rustyscript builtin - sqlite",
+    "op_sql_bridge_query": "Rustyscript builtin - sql_bridge, runs queries through the host's SqlBridge, which decides what is allowed",
     "op_panic2": "Panic stub to replace op_panic",
 
     //
@@ -162,6 +184,7 @@ export const whitelist = {
     "op_crypto_decrypt": "deno_crypto",
     "op_crypto_subtle_digest": "deno_crypto",
     "op_crypto_random_uuid": "deno_crypto",
+    "op_crypto_host_rng": "init_crypto - reports whether the host provided an rng",
     "op_crypto_wrap_key": "deno_crypto",
     "op_crypto_unwrap_key": "deno_crypto",
     "op_crypto_base64url_decode": "deno_crypto",
@@ -201,6 +224,11 @@ export const whitelist = {
     "op_url_parse": "deno_url",
     "op_url_get_serialization": "deno_url",
     "op_url_parse_with_base": "deno_url",
+    "op_stub_url_parse": "Rustyscript builtin - web_stub URL",
+    "op_stub_url_can_parse": "Rustyscript builtin - web_stub URL",
+    "op_stub_url_reparse": "Rustyscript builtin - web_stub URL",
+    "op_stub_url_parse_search_params": "Rustyscript builtin - web_stub URL",
+    "op_stub_url_stringify_search_params": "Rustyscript builtin - web_stub URL",
     "op_url_parse_search_params": "deno_url",
     "op_url_stringify_search_params": "deno_url",
     "op_urlpattern_parse": "deno_url",
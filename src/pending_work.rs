@@ -0,0 +1,177 @@
+//! A summary of the work keeping a runtime's event loop alive, see [`crate::Runtime::pending_work`]
+use deno_core::stats::{RuntimeActivity, RuntimeActivityStatsFilter};
+use std::fmt::Display;
+
+/// A single item of outstanding work in the event loop
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PendingActivity {
+    /// An async op that has not completed, such as a fetch or a call to an async rust function
+    Op {
+        /// The name of the op
+        name: String,
+
+        /// The stack trace where the op was started
+        /// Only recorded when [`crate::RuntimeOptions::trace_pending_work`] is enabled
+        trace: Option<String>,
+    },
+
+    /// A timer created by `setTimeout`, or an interval created by `setInterval`
+    Timer {
+        /// True for intervals
+        repeats: bool,
+
+        /// The stack trace where the timer was created
+        /// Only recorded when [`crate::RuntimeOptions::trace_pending_work`] is enabled
+        trace: Option<String>,
+    },
+
+    /// An open resource, such as a file or socket
+    Resource {
+        /// The kind of resource
+        name: String,
+    },
+}
+
+impl Display for PendingActivity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let trace = match self {
+            Self::Op { name, trace } => {
+                write!(f, "op {name}")?;
+                trace
+            }
+            Self::Timer { repeats, trace } => {
+                f.write_str(if *repeats { "interval" } else { "timer" })?;
+                trace
+            }
+            Self::Resource { name } => return write!(f, "resource {name}"),
+        };
+
+        if let Some(trace) = trace {
+            for line in trace.lines() {
+                write!(f, "\n    {}", line.trim())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The ops, timers, and resources keeping a runtime's event loop alive
+///
+/// Returned by [`crate::Runtime::pending_work`], and by [`crate::Runtime::await_event_loop_with_deadline`]
+/// when the event loop does not finish in time - to diagnose scripts that never settle
+///
+/// Promises that are not waiting on an op or timer cannot be listed - they do not keep the event loop alive
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PendingWork {
+    /// The outstanding work, with ops first, then resources, then timers
+    pub activities: Vec<PendingActivity>,
+}
+
+impl PendingWork {
+    /// Captures the pending work of a runtime
+    pub(crate) fn capture(runtime: &deno_core::JsRuntime) -> Self {
+        let snapshot = runtime
+            .runtime_activity_stats_factory()
+            .capture(&RuntimeActivityStatsFilter::all())
+            .dump();
+
+        let activities = snapshot
+            .active
+            .into_iter()
+            .map(|activity| match activity {
+                RuntimeActivity::AsyncOp(_, trace, name) => PendingActivity::Op {
+                    name: name.to_string(),
+                    trace: trace.map(|t| t.to_string()),
+                },
+                RuntimeActivity::Resource(_, _, name) => PendingActivity::Resource { name },
+                RuntimeActivity::Timer(_, trace) => PendingActivity::Timer {
+                    repeats: false,
+                    trace: trace.map(|t| t.to_string()),
+                },
+                RuntimeActivity::Interval(_, trace) => PendingActivity::Timer {
+                    repeats: true,
+                    trace: trace.map(|t| t.to_string()),
+                },
+            })
+            .collect();
+        Self { activities }
+    }
+
+    /// Returns true if there is no outstanding work
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.activities.is_empty()
+    }
+
+    /// Returns the pending async ops, with their traces if recorded
+    pub fn ops(&self) -> impl Iterator<Item = &PendingActivity> {
+        self.activities
+            .iter()
+            .filter(|a| matches!(a, PendingActivity::Op { .. }))
+    }
+
+    /// Returns the pending timers and intervals, with their traces if recorded
+    pub fn timers(&self) -> impl Iterator<Item = &PendingActivity> {
+        self.activities
+            .iter()
+            .filter(|a| matches!(a, PendingActivity::Timer { .. }))
+    }
+}
+
+impl Display for PendingWork {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} pending", self.activities.len())?;
+        for activity in &self.activities {
+            write!(f, "\n  - {activity}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[cfg(any(feature = "web", feature = "web_stub"))]
+mod test {
+    use super::*;
+    use crate::{Runtime, RuntimeOptions};
+    use deno_core::PollEventLoopOptions;
+    use std::time::Duration;
+
+    #[test]
+    fn test_pending_work() {
+        let mut runtime = Runtime::new(RuntimeOptions {
+            trace_pending_work: true,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(runtime.pending_work().timers().count(), 0);
+
+        runtime
+            .eval::<()>("function keepAlive() { setInterval(() => {}, 1000); } keepAlive();")
+            .unwrap();
+        let pending = runtime
+            .block_on_event_loop_with_deadline(
+                PollEventLoopOptions::default(),
+                Duration::from_millis(10),
+            )
+            .unwrap()
+            .expect("The event loop should not have completed");
+
+        let timers: Vec<_> = pending.timers().collect();
+        assert_eq!(timers.len(), 1);
+        let PendingActivity::Timer { repeats, trace } = timers[0] else {
+            unreachable!();
+        };
+        assert!(repeats);
+        assert!(trace.as_deref().unwrap_or_default().contains("keepAlive"));
+
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        runtime.eval::<()>("setTimeout(() => {}, 1)").unwrap();
+        let pending = runtime
+            .block_on_event_loop_with_deadline(
+                PollEventLoopOptions::default(),
+                Duration::from_secs(5),
+            )
+            .unwrap();
+        assert!(pending.is_none());
+    }
+}
@@ -0,0 +1,146 @@
+//! Epoch-based preemption of long-running scripts, see [`crate::RuntimeOptions::preemption_interval`]
+//!
+//! A ticker thread advances an epoch counter at a fixed interval
+//! Each blocking call sets a deadline, measured in epochs, and once the deadline has passed
+//! the ticker asks v8 to interrupt the isolate - which happens at the next function call or loop back-edge
+//!
+//! The interrupt runs on the isolate's own thread, and checks the deadline again before terminating execution,
+//! so an interrupt that arrives after its call has finished does not affect the next one
+use deno_core::v8;
+use std::{
+    ffi::c_void,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+/// State shared between the ticker thread, the interrupt callback, and the runtime
+#[derive(Default)]
+struct EpochState {
+    /// Number of ticks so far
+    epoch: AtomicU64,
+
+    /// Epoch at which the current call is preempted, or 0 if no call is running
+    deadline: AtomicU64,
+
+    /// Set once the interrupt has terminated execution
+    fired: AtomicBool,
+
+    /// Stops the ticker thread
+    stopped: AtomicBool,
+}
+
+impl EpochState {
+    fn expired(&self) -> bool {
+        let deadline = self.deadline.load(Ordering::Acquire);
+        deadline != 0 && self.epoch.load(Ordering::Acquire) >= deadline
+    }
+}
+
+/// Preempts scripts that run past their deadline, by interrupting the isolate
+///
+/// Cheap to clone - the ticker thread stops once every clone is dropped
+#[derive(Clone)]
+pub struct Preemption(Rc<Ticker>);
+
+/// The ticker thread, along with the state it shares with the runtime
+struct Ticker {
+    state: Arc<EpochState>,
+    isolate: v8::IsolateHandle,
+    interval: Duration,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Preemption {
+    /// Starts the ticker thread for an isolate, advancing the epoch once per interval
+    pub fn start(isolate: v8::IsolateHandle, interval: Duration) -> Self {
+        let state = Arc::new(EpochState::default());
+
+        let ticker_state = state.clone();
+        let ticker_isolate = isolate.clone();
+        let ticker = std::thread::spawn(move || {
+            let state = ticker_state;
+            while !state.stopped.load(Ordering::Acquire) {
+                std::thread::park_timeout(interval);
+                state.epoch.fetch_add(1, Ordering::AcqRel);
+
+                if state.expired() && !state.fired.load(Ordering::Acquire) {
+                    // Ownership of this reference passes to the interrupt callback
+                    let data = Arc::into_raw(state.clone()) as *mut c_void;
+                    if !ticker_isolate.request_interrupt(interrupt, data) {
+                        // The isolate is gone, so the callback will never run
+                        drop(unsafe { Arc::from_raw(data as *const EpochState) });
+                        return;
+                    }
+                }
+            }
+        });
+
+        Self(Rc::new(Ticker {
+            state,
+            isolate,
+            interval,
+            thread: Some(ticker),
+        }))
+    }
+
+    /// Sets the deadline for a call that should run for at most `timeout`
+    ///
+    /// Returns the previous deadline, to be restored with [`Preemption::disarm`] once the call completes
+    pub fn arm(&self, timeout: Duration) -> u64 {
+        let ticker = &self.0;
+        let ticks = timeout.as_nanos() / ticker.interval.as_nanos().max(1);
+        let ticks = u64::try_from(ticks).unwrap_or(u64::MAX);
+
+        // The current tick is already partly over, so wait at least one full tick past it
+        let deadline = ticker
+            .state
+            .epoch
+            .load(Ordering::Acquire)
+            .saturating_add(ticks)
+            .saturating_add(1);
+
+        let previous = ticker.state.deadline.load(Ordering::Acquire);
+        let deadline = if previous == 0 {
+            deadline
+        } else {
+            deadline.min(previous)
+        };
+        ticker.state.deadline.swap(deadline, Ordering::AcqRel)
+    }
+
+    /// Restores the deadline returned by [`Preemption::arm`]
+    ///
+    /// Returns true if the call was preempted, in which case the isolate is made usable again
+    pub fn disarm(&self, previous: u64) -> bool {
+        let ticker = &self.0;
+        ticker.state.deadline.store(previous, Ordering::Release);
+        let fired = ticker.state.fired.swap(false, Ordering::AcqRel);
+        if fired {
+            ticker.isolate.cancel_terminate_execution();
+        }
+        fired
+    }
+}
+
+impl Drop for Ticker {
+    fn drop(&mut self) {
+        self.state.stopped.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Runs on the isolate's thread, at the next safe point after an interrupt is requested
+extern "C" fn interrupt(isolate: &mut v8::Isolate, data: *mut c_void) {
+    let state = unsafe { Arc::from_raw(data as *const EpochState) };
+    if state.expired() && !state.fired.swap(true, Ordering::AcqRel) {
+        isolate.terminate_execution();
+    }
+}
@@ -0,0 +1,83 @@
+use crate::{traits::ToV8String, Error};
+use deno_core::v8::{self, HandleScope};
+
+/// A name converted to a v8 string ahead of time, for lookups repeated in tight loops
+/// See [`crate::Runtime::prepare_name`]
+///
+/// Names are internalized, and cached by the runtime - preparing the same name twice returns the same string
+/// Must live as long as the runtime it was birthed from
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PreparedName {
+    name: &'static str,
+    key: v8::Global<v8::String>,
+}
+
+impl PreparedName {
+    pub(crate) fn new(scope: &mut HandleScope<'_>, name: &'static str) -> Result<Self, Error> {
+        let key =
+            v8::String::new_from_utf8(scope, name.as_bytes(), v8::NewStringType::Internalized)
+                .ok_or_else(|| Error::V8Encoding(name.to_string()))?;
+
+        Ok(Self {
+            name,
+            key: v8::Global::new(scope, key),
+        })
+    }
+
+    /// Returns the name this was prepared from
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl std::fmt::Display for PreparedName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name)
+    }
+}
+
+impl ToV8String for PreparedName {
+    fn to_v8_string<'a>(
+        &self,
+        scope: &mut HandleScope<'a>,
+    ) -> Result<v8::Local<'a, v8::String>, Error> {
+        Ok(v8::Local::new(scope, &self.key))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{json_args, Error, Module, Runtime, RuntimeOptions};
+
+    #[test]
+    fn test_prepared_names() {
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let module = runtime
+            .load_module(&Module::new(
+                "test.js",
+                "export const add = (a, b) => a + b; globalThis.base = 10;",
+            ))
+            .unwrap();
+
+        let add = runtime.prepare_name("add").unwrap();
+        assert_eq!(runtime.prepare_name("add").unwrap(), add);
+        assert_eq!(add.to_string(), "add");
+
+        let value: i64 = runtime
+            .call_function_prepared(Some(&module), &add, json_args!(1, 2))
+            .unwrap();
+        assert_eq!(value, 3);
+
+        // Globals are found as a fallback, as with unprepared names
+        let base = runtime.prepare_name("base").unwrap();
+        let value: i64 = runtime.get_value_prepared(Some(&module), &base).unwrap();
+        assert_eq!(value, 10);
+
+        let missing = runtime.prepare_name("missing").unwrap();
+        let err = runtime
+            .get_value_prepared::<i64>(None, &missing)
+            .unwrap_err();
+        assert!(matches!(err, Error::ValueNotFound(name) if name == "missing"));
+    }
+}
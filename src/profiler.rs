@@ -0,0 +1,614 @@
+//! CPU and heap profiling for script code, see [`crate::Runtime::profile`]
+use crate::{async_bridge::AsyncBridgeExt, Error, Runtime};
+use deno_core::{
+    serde_json::{self, json, Value},
+    v8, InspectorSessionKind, InspectorSessionOptions, LocalInspectorSession, PollEventLoopOptions,
+};
+use std::{collections::HashMap, fmt::Write, time::Duration};
+
+/// Options for [`crate::Runtime::profile`]
+#[derive(Clone, Copy, Debug)]
+pub struct ProfileOptions {
+    /// Time between CPU samples
+    ///
+    /// Default: 100 microseconds
+    pub sampling_interval: Duration,
+}
+
+impl Default for ProfileOptions {
+    fn default() -> Self {
+        Self {
+            sampling_interval: Duration::from_micros(100),
+        }
+    }
+}
+
+/// A snapshot of the v8 heap statistics of a runtime
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HeapStats {
+    /// Bytes used by live objects
+    pub used_heap_size: usize,
+
+    /// Bytes reserved for the heap
+    pub total_heap_size: usize,
+
+    /// The maximum size the heap can grow to
+    pub heap_size_limit: usize,
+
+    /// Bytes of memory allocated outside of the heap, such as array buffers
+    pub external_memory: usize,
+
+    /// Bytes allocated by v8 itself
+    pub malloced_memory: usize,
+}
+
+impl HeapStats {
+    /// Capture the current heap statistics of a runtime
+    pub fn capture(runtime: &mut Runtime) -> Self {
+        let mut stats = v8::HeapStatistics::default();
+        runtime
+            .deno_runtime()
+            .v8_isolate()
+            .get_heap_statistics(&mut stats);
+
+        Self {
+            used_heap_size: stats.used_heap_size(),
+            total_heap_size: stats.total_heap_size(),
+            heap_size_limit: stats.heap_size_limit(),
+            external_memory: stats.external_memory(),
+            malloced_memory: stats.malloced_memory(),
+        }
+    }
+}
+
+/// A function in the call tree of a [`CpuProfile`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProfileNode {
+    /// Unique ID of the node within the profile
+    pub id: u32,
+
+    /// ID of the calling node, or `None` for the root
+    pub parent: Option<u32>,
+
+    /// Name of the function - empty for anonymous functions
+    pub function_name: String,
+
+    /// URL of the script containing the function
+    pub url: String,
+
+    /// Zero-based line of the function in the script
+    pub line: u32,
+
+    /// Zero-based column of the function in the script
+    pub column: u32,
+
+    /// Number of samples taken while this function was at the top of the stack
+    pub hit_count: u32,
+}
+
+impl ProfileNode {
+    /// A name for the node suitable for display
+    #[must_use]
+    pub fn display_name(&self) -> &str {
+        if self.function_name.is_empty() {
+            "(anonymous)"
+        } else {
+            &self.function_name
+        }
+    }
+}
+
+/// A sampled CPU profile, as recorded by the v8 inspector
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CpuProfile {
+    /// Every function in the call tree
+    pub nodes: Vec<ProfileNode>,
+
+    /// ID of the node at the top of the stack for each sample
+    pub samples: Vec<u32>,
+
+    /// Time spent in each sample
+    pub sample_durations: Vec<Duration>,
+}
+
+impl CpuProfile {
+    /// Parse a profile returned by the inspector's `Profiler.stop` method
+    fn from_inspector(profile: &Value) -> Result<Self, Error> {
+        let invalid = || Error::Runtime("Invalid CPU profile".to_string());
+        let as_u32 = |v: &Value| v.as_u64().and_then(|v| u32::try_from(v).ok());
+
+        let mut nodes = Vec::new();
+        let mut parents = HashMap::new();
+        for node in profile["nodes"].as_array().ok_or_else(invalid)? {
+            let id = as_u32(&node["id"]).ok_or_else(invalid)?;
+            for child in node["children"].as_array().into_iter().flatten() {
+                parents.insert(as_u32(child).ok_or_else(invalid)?, id);
+            }
+
+            let frame = &node["callFrame"];
+            nodes.push(ProfileNode {
+                id,
+                parent: None,
+                function_name: frame["functionName"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                url: frame["url"].as_str().unwrap_or_default().to_string(),
+                line: as_u32(&frame["lineNumber"]).unwrap_or_default(),
+                column: as_u32(&frame["columnNumber"]).unwrap_or_default(),
+                hit_count: as_u32(&node["hitCount"]).unwrap_or_default(),
+            });
+        }
+        for node in &mut nodes {
+            node.parent = parents.get(&node.id).copied();
+        }
+
+        let samples = profile["samples"]
+            .as_array()
+            .ok_or_else(invalid)?
+            .iter()
+            .map(|s| as_u32(s).ok_or_else(invalid))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Each delta is the time since the previous sample, so a sample lasts until the next one
+        let deltas: Vec<u64> = profile["timeDeltas"]
+            .as_array()
+            .ok_or_else(invalid)?
+            .iter()
+            .map(|d| d.as_i64().unwrap_or_default().max(0).unsigned_abs())
+            .collect();
+        let start = profile["startTime"].as_u64().unwrap_or_default();
+        let end = profile["endTime"].as_u64().unwrap_or_default();
+        let last_sample = start + deltas.iter().sum::<u64>();
+
+        let sample_durations = (0..samples.len())
+            .map(|i| match deltas.get(i + 1) {
+                Some(delta) => Duration::from_micros(*delta),
+                None => Duration::from_micros(end.saturating_sub(last_sample)),
+            })
+            .collect();
+
+        Ok(Self {
+            nodes,
+            samples,
+            sample_durations,
+        })
+    }
+
+    /// Returns the stack for a sample, from the root caller to the function that was running
+    ///
+    /// The root node of the profile itself is not included
+    #[must_use]
+    pub fn stack(&self, node_id: u32) -> Vec<&ProfileNode> {
+        let by_id: HashMap<_, _> = self.nodes.iter().map(|n| (n.id, n)).collect();
+        let mut stack = Vec::new();
+        let mut current = by_id.get(&node_id).copied();
+        while let Some(node) = current {
+            if node.parent.is_none() {
+                break;
+            }
+            stack.push(node);
+            current = node.parent.and_then(|id| by_id.get(&id).copied());
+        }
+
+        stack.reverse();
+        stack
+    }
+
+    /// Total time covered by the samples
+    #[must_use]
+    pub fn duration(&self) -> Duration {
+        self.sample_durations.iter().sum()
+    }
+}
+
+/// CPU and heap usage recorded by [`crate::Runtime::profile`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RuntimeReport {
+    /// The sampled CPU profile
+    pub cpu: CpuProfile,
+
+    /// Heap statistics before the profiled code ran
+    pub heap_before: HeapStats,
+
+    /// Heap statistics after the profiled code ran
+    pub heap_after: HeapStats,
+
+    /// Wall-clock time spent running the profiled code
+    pub wall_time: Duration,
+}
+
+impl RuntimeReport {
+    /// Growth of the v8 heap while the profiled code ran, in bytes
+    /// None if the heap shrank, which means a garbage collection ran during the profile
+    #[must_use]
+    pub fn heap_growth(&self) -> Option<usize> {
+        self.heap_after
+            .used_heap_size
+            .checked_sub(self.heap_before.used_heap_size)
+    }
+
+    /// Export the CPU profile in the speedscope format, which can be opened at <https://www.speedscope.app>
+    ///
+    /// `name` is the title shown for the profile
+    #[must_use]
+    pub fn to_speedscope(&self, name: &str) -> String {
+        let mut frames = Vec::new();
+        let mut frame_ids = HashMap::new();
+        let mut frame_for = |node: &ProfileNode| {
+            let key = (
+                node.display_name().to_string(),
+                node.url.clone(),
+                node.line,
+                node.column,
+            );
+            *frame_ids.entry(key).or_insert_with(|| {
+                frames.push(json!({
+                    "name": node.display_name(),
+                    "file": node.url,
+                    "line": node.line + 1,
+                    "col": node.column + 1,
+                }));
+                frames.len() - 1
+            })
+        };
+
+        let samples: Vec<Vec<usize>> = self
+            .cpu
+            .samples
+            .iter()
+            .map(|id| {
+                self.cpu
+                    .stack(*id)
+                    .into_iter()
+                    .map(&mut frame_for)
+                    .collect()
+            })
+            .collect();
+        let weights: Vec<u64> = self.cpu.sample_durations.iter().map(micros).collect();
+
+        json!({
+            "$schema": "https://www.speedscope.app/file-format-schema.json",
+            "exporter": "rustyscript",
+            "name": name,
+            "activeProfileIndex": 0,
+            "shared": { "frames": frames },
+            "profiles": [{
+                "type": "sampled",
+                "name": name,
+                "unit": "microseconds",
+                "startValue": 0,
+                "endValue": micros(&self.cpu.duration()),
+                "samples": samples,
+                "weights": weights,
+            }],
+        })
+        .to_string()
+    }
+
+    /// Export the CPU profile in the pprof protobuf format, for use with `go tool pprof` and compatible viewers
+    ///
+    /// The output is not compressed - `pprof` accepts it as-is
+    #[must_use]
+    pub fn to_pprof(&self) -> Vec<u8> {
+        pprof::encode(self)
+    }
+
+    /// A short human-readable summary of the report, listing the functions with the most self time
+    #[must_use]
+    pub fn summary(&self, top: usize) -> String {
+        let mut self_time: HashMap<u32, Duration> = HashMap::new();
+        for (id, duration) in self.cpu.samples.iter().zip(&self.cpu.sample_durations) {
+            *self_time.entry(*id).or_default() += *duration;
+        }
+
+        let mut by_time: Vec<_> = self
+            .cpu
+            .nodes
+            .iter()
+            .filter_map(|node| self_time.get(&node.id).map(|time| (node, *time)))
+            .filter(|(node, _)| node.parent.is_some())
+            .collect();
+        by_time.sort_by(|a, b| b.1.cmp(&a.1));
+
+        let mut out = format!(
+            "Wall time: {:?}, sampled: {:?}, heap: {} -> {} bytes\n",
+            self.wall_time,
+            self.cpu.duration(),
+            self.heap_before.used_heap_size,
+            self.heap_after.used_heap_size
+        );
+        for (node, time) in by_time.into_iter().take(top) {
+            let _ = writeln!(
+                out,
+                "{time:>12?}  {} ({}:{}:{})",
+                node.display_name(),
+                node.url,
+                node.line + 1,
+                node.column + 1
+            );
+        }
+        out
+    }
+}
+
+fn micros(duration: &Duration) -> u64 {
+    u64::try_from(duration.as_micros()).unwrap_or(u64::MAX)
+}
+
+/// Sends a message to the inspector, driving the event loop until it responds
+fn post(
+    runtime: &mut Runtime,
+    session: &mut LocalInspectorSession,
+    method: &str,
+    params: Option<Value>,
+) -> Result<Value, Error> {
+    runtime.block_on(|runtime| async move {
+        let message = Box::pin(session.post_message(method, params));
+        let result = runtime
+            .deno_runtime()
+            .with_event_loop_future(message, PollEventLoopOptions::default())
+            .await?;
+        Ok(result)
+    })
+}
+
+/// Runs code under the profiler, see [`crate::Runtime::profile`]
+pub(crate) fn profile<T>(
+    runtime: &mut Runtime,
+    options: ProfileOptions,
+    f: impl FnOnce(&mut Runtime) -> Result<T, Error>,
+) -> Result<(T, RuntimeReport), Error> {
+    runtime.deno_runtime().maybe_init_inspector();
+    let inspector = runtime.deno_runtime().inspector();
+    let mut session = inspector
+        .borrow()
+        .create_local_session(InspectorSessionOptions {
+            kind: InspectorSessionKind::NonBlocking {
+                wait_for_disconnect: false,
+            },
+        });
+
+    let interval = u64::try_from(options.sampling_interval.as_micros()).unwrap_or(u64::MAX);
+    post(runtime, &mut session, "Profiler.enable", None)?;
+    post(
+        runtime,
+        &mut session,
+        "Profiler.setSamplingInterval",
+        Some(json!({ "interval": interval.max(1) })),
+    )?;
+
+    let heap_before = HeapStats::capture(runtime);
+    post(runtime, &mut session, "Profiler.start", None)?;
+
+    let start = std::time::Instant::now();
+    let result = f(runtime);
+    let wall_time = start.elapsed();
+
+    // Stop the profiler even if the code failed, so the next profile can start
+    let stopped = post(runtime, &mut session, "Profiler.stop", None);
+    let _ = post(runtime, &mut session, "Profiler.disable", None);
+    let heap_after = HeapStats::capture(runtime);
+
+    let value = result?;
+    let cpu = CpuProfile::from_inspector(&stopped?["profile"])?;
+    Ok((
+        value,
+        RuntimeReport {
+            cpu,
+            heap_before,
+            heap_after,
+            wall_time,
+        },
+    ))
+}
+
+/// A minimal encoder for the pprof `profile.proto` format
+mod pprof {
+    use super::RuntimeReport;
+    use std::collections::HashMap;
+
+    /// Writes protobuf wire format
+    #[derive(Default)]
+    struct Writer(Vec<u8>);
+    impl Writer {
+        fn varint(&mut self, mut value: u64) {
+            while value >= 0x80 {
+                #[allow(clippy::cast_possible_truncation)]
+                self.0.push((value as u8) | 0x80);
+                value >>= 7;
+            }
+            #[allow(clippy::cast_possible_truncation)]
+            self.0.push(value as u8);
+        }
+
+        fn uint(&mut self, field: u64, value: u64) {
+            self.varint(field << 3);
+            self.varint(value);
+        }
+
+        fn bytes(&mut self, field: u64, value: &[u8]) {
+            self.varint((field << 3) | 2);
+            self.varint(value.len() as u64);
+            self.0.extend_from_slice(value);
+        }
+
+        fn packed(&mut self, field: u64, values: impl IntoIterator<Item = u64>) {
+            let mut inner = Writer::default();
+            for value in values {
+                inner.varint(value);
+            }
+            self.bytes(field, &inner.0);
+        }
+
+        fn message(&mut self, field: u64, build: impl FnOnce(&mut Writer)) {
+            let mut inner = Writer::default();
+            build(&mut inner);
+            self.bytes(field, &inner.0);
+        }
+    }
+
+    /// Interns strings into the profile's string table
+    #[derive(Default)]
+    struct Strings {
+        table: Vec<String>,
+        ids: HashMap<String, u64>,
+    }
+    impl Strings {
+        fn id(&mut self, s: &str) -> u64 {
+            if self.table.is_empty() {
+                self.table.push(String::new());
+                self.ids.insert(String::new(), 0);
+            }
+            if let Some(id) = self.ids.get(s) {
+                return *id;
+            }
+            let id = self.table.len() as u64;
+            self.table.push(s.to_string());
+            self.ids.insert(s.to_string(), id);
+            id
+        }
+    }
+
+    pub fn encode(report: &RuntimeReport) -> Vec<u8> {
+        let mut strings = Strings::default();
+        let mut out = Writer::default();
+
+        // Sample types: a count, and the time spent
+        let samples_type = (strings.id("samples"), strings.id("count"));
+        let cpu_type = (strings.id("cpu"), strings.id("nanoseconds"));
+        for (kind, unit) in [samples_type, cpu_type] {
+            out.message(1, |w| {
+                w.uint(1, kind);
+                w.uint(2, unit);
+            });
+        }
+
+        // Samples, with locations from the leaf to the root
+        for (id, duration) in report.cpu.samples.iter().zip(&report.cpu.sample_durations) {
+            let stack = report.cpu.stack(*id);
+            let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+            out.message(2, |w| {
+                w.packed(1, stack.iter().rev().map(|node| u64::from(node.id)));
+                w.packed(2, [1, nanos]);
+            });
+        }
+
+        // One location and function per node, sharing its ID
+        for node in report.cpu.nodes.iter().filter(|n| n.parent.is_some()) {
+            let id = u64::from(node.id);
+            let line = u64::from(node.line) + 1;
+            out.message(4, |w| {
+                w.uint(1, id);
+                w.message(4, |w| {
+                    w.uint(1, id);
+                    w.uint(2, line);
+                });
+            });
+
+            let name = strings.id(node.display_name());
+            let filename = strings.id(&node.url);
+            out.message(5, |w| {
+                w.uint(1, id);
+                w.uint(2, name);
+                w.uint(3, name);
+                w.uint(4, filename);
+                w.uint(5, line);
+            });
+        }
+
+        for s in &strings.table {
+            out.bytes(6, s.as_bytes());
+        }
+
+        let duration = u64::try_from(report.wall_time.as_nanos()).unwrap_or(u64::MAX);
+        out.uint(10, duration);
+        out.message(11, |w| {
+            w.uint(1, cpu_type.0);
+            w.uint(2, cpu_type.1);
+        });
+
+        out.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{json_args, Module, RuntimeOptions};
+
+    fn sample_profile() -> Value {
+        json!({
+            "nodes": [
+                { "id": 1, "callFrame": { "functionName": "(root)", "url": "", "lineNumber": -1, "columnNumber": -1 }, "hitCount": 0, "children": [2] },
+                { "id": 2, "callFrame": { "functionName": "outer", "url": "file:///a.js", "lineNumber": 0, "columnNumber": 0 }, "hitCount": 1, "children": [3] },
+                { "id": 3, "callFrame": { "functionName": "", "url": "file:///a.js", "lineNumber": 4, "columnNumber": 2 }, "hitCount": 2 },
+            ],
+            "startTime": 1000,
+            "endTime": 1400,
+            "samples": [2, 3, 3],
+            "timeDeltas": [0, 100, 200],
+        })
+    }
+
+    #[test]
+    fn test_parse_profile() {
+        let profile = CpuProfile::from_inspector(&sample_profile()).unwrap();
+        assert_eq!(profile.nodes[2].parent, Some(2));
+        assert_eq!(
+            profile.sample_durations,
+            vec![
+                Duration::from_micros(100),
+                Duration::from_micros(200),
+                Duration::from_micros(100)
+            ]
+        );
+
+        let stack: Vec<_> = profile.stack(3).iter().map(|n| n.display_name()).collect();
+        assert_eq!(stack, vec!["outer", "(anonymous)"]);
+    }
+
+    #[test]
+    fn test_exporters() {
+        let report = RuntimeReport {
+            cpu: CpuProfile::from_inspector(&sample_profile()).unwrap(),
+            ..Default::default()
+        };
+
+        let speedscope: Value = serde_json::from_str(&report.to_speedscope("test")).unwrap();
+        assert_eq!(speedscope["shared"]["frames"].as_array().unwrap().len(), 2);
+        assert_eq!(speedscope["profiles"][0]["samples"][1], json!([0, 1]));
+        assert_eq!(speedscope["profiles"][0]["endValue"], 400);
+
+        let pprof = report.to_pprof();
+        assert!(!pprof.is_empty());
+        assert!(pprof.windows(5).any(|w| w == b"outer"));
+    }
+
+    #[test]
+    fn test_profile() {
+        let module = Module::new(
+            "test.js",
+            "export const work = (n) => { let s = 0; for (let i = 0; i < n; i++) s += Math.sqrt(i); return s; };",
+        );
+
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let handle = runtime.load_module(&module).unwrap();
+
+        let (value, report) = runtime
+            .profile(ProfileOptions::default(), |runtime| {
+                runtime.call_function::<f64>(Some(&handle), "work", json_args!(1_000_000))
+            })
+            .unwrap();
+        assert!(value > 0.0);
+        assert!(!report.cpu.nodes.is_empty());
+        assert!(report.wall_time > Duration::ZERO);
+
+        // The profiler can be started again
+        runtime
+            .profile(ProfileOptions::default(), |runtime| {
+                runtime.eval::<()>("1 + 1")
+            })
+            .unwrap();
+    }
+}
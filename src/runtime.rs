@@ -1,11 +1,12 @@
 use crate::{
     async_bridge::{AsyncBridge, AsyncBridgeExt},
-    inner_runtime::{InnerRuntime, RsAsyncFunction, RsFunction},
+    capabilities::CapabilityScope,
+    inner_runtime::{CallbackContext, InnerRuntime, RsAsyncFunction, RsFunction},
     js_value::Function,
-    Error, Module, ModuleHandle,
+    CallInfo, CallOptions, Capabilities, Error, Module, ModuleHandle, ModuleKey,
 };
-use deno_core::PollEventLoopOptions;
-use std::{path::Path, rc::Rc, time::Duration};
+use deno_core::{serde_json, PollEventLoopOptions};
+use std::{path::Path, rc::Rc, sync::Arc, time::Duration};
 use tokio_util::sync::CancellationToken;
 
 /// Represents the set of options accepted by the runtime constructor
@@ -32,6 +33,7 @@ pub type Undefined = crate::js_value::Value;
 pub struct Runtime {
     inner: InnerRuntime<deno_core::JsRuntime>,
     tokio: AsyncBridge,
+    fork_options: Option<crate::fork::OptionsFactory>,
 }
 
 impl Runtime {
@@ -76,8 +78,7 @@ impl Runtime {
     ///
     pub fn new(options: RuntimeOptions) -> Result<Self, Error> {
         let tokio = AsyncBridge::new(options.timeout)?;
-        let inner = InnerRuntime::new(options, tokio.heap_exhausted_token())?;
-        Ok(Self { inner, tokio })
+        Self::with_bridge(options, tokio)
     }
 
     /// Creates a new instance of the runtime with the provided options and a pre-configured tokio runtime.  
@@ -90,8 +91,108 @@ impl Runtime {
         tokio: Rc<tokio::runtime::Runtime>,
     ) -> Result<Self, Error> {
         let tokio = AsyncBridge::with_tokio_runtime(options.timeout, tokio);
-        let inner = InnerRuntime::new(options, tokio.heap_exhausted_token())?;
-        Ok(Self { inner, tokio })
+        Self::with_bridge(options, tokio)
+    }
+
+    fn with_bridge(options: RuntimeOptions, mut tokio: AsyncBridge) -> Result<Self, Error> {
+        let preemption_interval = options.preemption_interval;
+        let mut inner = InnerRuntime::new(options, tokio.heap_exhausted_token())?;
+
+        let isolate = inner.deno_runtime().v8_isolate().thread_safe_handle();
+        tokio.enable_preemption(isolate, preemption_interval)?;
+
+        let script_exit = inner
+            .deno_runtime()
+            .op_state()
+            .borrow()
+            .borrow::<crate::ext::rustyscript::ExitState>()
+            .exit
+            .clone();
+        tokio.watch_exit(script_exit);
+        Ok(Self {
+            inner,
+            tokio,
+            fork_options: None,
+        })
+    }
+
+    /// Creates a new runtime that can be cloned with [`Runtime::fork`]
+    ///
+    /// Since options such as extensions cannot be copied, they are created by `options`,
+    /// which is called once for this runtime and once for each fork
+    ///
+    /// # Errors
+    /// Can fail in the same cases as [`Runtime::new`]
+    pub fn forkable(options: impl Fn() -> RuntimeOptions + 'static) -> Result<Self, Error> {
+        let options: crate::fork::OptionsFactory = Rc::new(options);
+        let mut runtime = Self::new(options())?;
+        runtime.fork_options = Some(options);
+        Ok(runtime)
+    }
+
+    /// Creates a new runtime configured like this one, for scaling out identical runtimes
+    ///
+    /// The fork is created from the same options - including the snapshot and extensions - and shares this runtime's tokio runtime  
+    /// It also receives:
+    /// - The sync, async and reentrant functions registered so far, which are shared with this runtime rather than copied
+    /// - The code caches, lazy modules and aliases added to the module loader
+    /// - The current directory and timeout
+    ///
+    /// Javascript state is not copied - load modules into the fork as needed, and expose any objects
+    /// with [`Runtime::register_api`] again. Cancellable and progress-reporting functions are not carried over
+    ///
+    /// The fork can itself be forked
+    ///
+    /// # Errors
+    /// Will return an error if the runtime was not created with [`Runtime::forkable`], or if the new runtime cannot be created
+    ///
+    /// ```rust
+    /// use rustyscript::{ Module, Runtime, RuntimeOptions, serde_json::Value };
+    ///
+    /// # fn main() -> Result<(), rustyscript::Error> {
+    /// let mut runtime = Runtime::forkable(RuntimeOptions::default)?;
+    /// runtime.register_function("double", |args| Ok(Value::from(args[0].as_i64().unwrap_or(0) * 2)))?;
+    ///
+    /// let mut fork = runtime.fork()?;
+    /// let value: i64 = fork.eval("rustyscript.functions.double(21)")?;
+    /// assert_eq!(value, 42);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn fork(&self) -> Result<Self, Error> {
+        let options = self.fork_options.clone().ok_or_else(|| {
+            Error::Runtime("Only runtimes created with Runtime::forkable can be forked".to_string())
+        })?;
+
+        let mut fork = Self::with_tokio_runtime(options(), self.tokio_runtime())?;
+        fork.fork_options = Some(options);
+        fork.set_timeout(self.timeout());
+        fork.set_current_dir(self.current_dir())?;
+        fork.inner
+            .module_loader
+            .extend_caches(self.inner.module_loader.caches());
+
+        for (name, definition) in self.inner.function_definitions.iter() {
+            fork.inner.define_function(name, definition.clone())?;
+        }
+        for (name, callback) in self.inner.function_definitions.iter_reentrant() {
+            fork.inner
+                .register_reentrant_function(name, callback.clone())?;
+        }
+
+        Ok(fork)
+    }
+
+    /// Recreates the handle of a module that was restored from a startup snapshot
+    #[cfg(feature = "snapshot_builder")]
+    pub(crate) fn restore_module_handle(
+        &mut self,
+        module: &Module,
+        id: deno_core::ModuleId,
+    ) -> Result<ModuleHandle, Error> {
+        let mut handle = ModuleHandle::new(module, id, None);
+        let entrypoint = self.inner.get_module_entrypoint(&mut handle)?;
+        Ok(ModuleHandle::new(module, id, entrypoint))
     }
 
     /// Access the underlying deno runtime instance directly
@@ -111,11 +212,17 @@ impl Runtime {
         self.tokio.timeout()
     }
 
+    /// Sets the timeout for the runtime, replacing [`RuntimeOptions::timeout`]
+    /// Applies to every blocking call made after this one
+    pub fn set_timeout(&mut self, timeout: std::time::Duration) {
+        self.tokio.set_timeout(timeout);
+    }
+
     /// Returns the heap exhausted token for the runtime  
     /// Used to detect when the runtime has run out of memory
     #[must_use]
     pub fn heap_exhausted_token(&self) -> CancellationToken {
-        self.tokio.heap_exhausted_token()
+        self.tokio.heap_exhausted_token().token()
     }
 
     /// Destroy the v8 runtime, releasing all resources  
@@ -195,6 +302,107 @@ impl Runtime {
         self.block_on(|runtime| async move { runtime.await_event_loop(options, timeout).await })
     }
 
+    /// Run the JS event loop to completion, or until the deadline is reached
+    ///
+    /// Unlike [`Runtime::await_event_loop`], reaching the deadline is reported:
+    /// Returns `None` if the event loop completed, or a summary of the work still pending once the deadline passed
+    ///
+    /// # Errors
+    /// Can fail if a runtime error occurs during the event loop's execution
+    pub async fn await_event_loop_with_deadline(
+        &mut self,
+        options: PollEventLoopOptions,
+        deadline: Duration,
+    ) -> Result<Option<crate::PendingWork>, Error> {
+        tokio::select! {
+            r = self.inner.deno_runtime().run_event_loop(options) => r.map(|()| None).map_err(|e| self.inner.event_loop_error(e).into()),
+            () = tokio::time::sleep(deadline) => Ok(Some(self.pending_work())),
+        }
+    }
+
+    /// Run the JS event loop to completion, or until the deadline is reached
+    ///
+    /// This is the blocking variant of [`Runtime::await_event_loop_with_deadline`]
+    ///
+    /// # Errors
+    /// Can fail if a runtime error occurs during the event loop's execution
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rustyscript::{ Error, Runtime, RuntimeOptions };
+    /// use std::time::Duration;
+    ///
+    /// # fn main() -> Result<(), Error> {
+    /// let mut runtime = Runtime::new(RuntimeOptions {
+    ///     trace_pending_work: true,
+    ///     ..Default::default()
+    /// })?;
+    /// runtime.eval::<()>("setInterval(() => {}, 1000)")?;
+    ///
+    /// let options = Default::default();
+    /// if let Some(pending) = runtime.block_on_event_loop_with_deadline(options, Duration::from_millis(10))? {
+    ///     eprintln!("The event loop did not settle: {pending}");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn block_on_event_loop_with_deadline(
+        &mut self,
+        options: PollEventLoopOptions,
+        deadline: Duration,
+    ) -> Result<Option<crate::PendingWork>, Error> {
+        self.block_on(|runtime| async move {
+            runtime
+                .await_event_loop_with_deadline(options, deadline)
+                .await
+        })
+    }
+
+    /// Returns the async ops, timers, and resources currently keeping the event loop alive
+    ///
+    /// Stack traces showing where each op and timer was created are included if
+    /// [`RuntimeOptions::trace_pending_work`] is enabled
+    pub fn pending_work(&mut self) -> crate::PendingWork {
+        crate::PendingWork::capture(self.deno_runtime())
+    }
+
+    /// Removes and returns the javascript errors raised by the event loop so far, oldest first
+    ///
+    /// Errors thrown by background work - such as timers, or promises rejected without a handler - surface from
+    /// whichever call happens to be driving the event loop at the time. They are still returned by that call,
+    /// but are also recorded here, so they can be observed reliably and told apart from the call's own failure
+    ///
+    /// Each error includes the stack where it was thrown. Up to 256 errors are kept, discarding the oldest first
+    ///
+    /// ```rust
+    /// use rustyscript::{ Runtime, Undefined };
+    ///
+    /// # fn main() -> Result<(), rustyscript::Error> {
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// let _ = runtime.eval::<Undefined>("setTimeout(() => { throw new Error('background failure'); }, 0); undefined");
+    /// let _ = runtime.block_on_event_loop(Default::default(), None);
+    ///
+    /// let errors = runtime.take_event_loop_errors();
+    /// assert_eq!(errors.len(), 1);
+    /// assert!(errors[0].exception_message.contains("background failure"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn take_event_loop_errors(&mut self) -> Vec<deno_core::error::JsError> {
+        self.inner.event_loop_errors.drain(..).collect()
+    }
+
+    /// Sets a callback, called with each javascript error raised by the event loop as it happens
+    ///
+    /// See [`Runtime::take_event_loop_errors`] - errors are recorded there as well
+    pub fn set_event_loop_error_hook(
+        &mut self,
+        hook: impl Fn(&deno_core::error::JsError) + 'static,
+    ) {
+        self.inner.event_loop_error_hook = Some(Box::new(hook));
+    }
+
     /// Remove and return a value from the state, if one exists
     /// ```rust
     /// use rustyscript::{ Runtime };
@@ -267,1160 +475,5229 @@ impl Runtime {
         self.inner.register_function(name, callback)
     }
 
-    /// Register a non-blocking rust function to be callable from JS
-    /// - The [`crate::async_callback`] macro can be used to simplify this process
+    /// Removes a registered function, of any kind, so scripts can no longer call it
+    /// Returns true if a function with that name was registered
+    ///
+    /// Useful for hosts that enable and disable plugins while running  
+    /// Registering a function under an existing name replaces it instead
     ///
     /// # Errors
     /// Since this function borrows the state, it can fail if the state cannot be borrowed mutably
     ///
     /// ```rust
-    /// use rustyscript::{ Runtime, Module, serde_json::Value, async_callback, Error };
+    /// use rustyscript::{ Runtime, serde_json::Value };
     ///
     /// # fn main() -> Result<(), rustyscript::Error> {
-    /// let module = Module::new("test.js", " rustyscript.async_functions.add(1, 2); ");
     /// let mut runtime = Runtime::new(Default::default())?;
-    /// runtime.register_async_function("add", async_callback!(
-    ///     |a: i64, b: i64| async move {
-    ///         Ok::<i64, Error>(a + b)
-    ///     }
-    /// ))?;
+    /// runtime.register_function("plugin_action", |_| Ok(Value::Null))?;
+    /// assert_eq!(runtime.list_functions()?, vec!["plugin_action"]);
     ///
+    /// assert!(runtime.unregister_function("plugin_action")?);
+    /// assert!(runtime.list_functions()?.is_empty());
     /// # Ok(())
     /// # }
     /// ```
-    pub fn register_async_function<F>(&mut self, name: &str, callback: F) -> Result<(), Error>
-    where
-        F: RsAsyncFunction,
-    {
-        self.inner.register_async_function(name, callback)
+    pub fn unregister_function(&mut self, name: &str) -> Result<bool, Error> {
+        self.inner.unregister_function(name)
     }
 
-    /// Evaluate a piece of non-ECMAScript-module JavaScript code  
-    /// The expression is evaluated in the global context, so changes persist
-    ///
-    /// Blocks on promise resolution, and runs the event loop to completion
-    ///
-    /// Asynchronous code is supported, partially
-    /// - Top-level await is not supported
-    /// - The event loop will be run to completion after the expression is evaluated
-    ///
-    /// For top-level await support, use one of:
-    /// - `call_function_async`
-    /// - `call_stored_function_async`
-    /// - `load_module_async`
-    /// - `load_modules_async`
+    /// Returns the names of every registered function - sync, async, and progress-reporting - in sorted order
     ///
-    /// Or any of the `_immmediate` variants, paired with [`crate::js_value::Promise`]
+    /// Functions registered in a namespace are listed as `{namespace}.{name}`
     ///
-    /// # Arguments
-    /// * `expr` - A string representing the JavaScript expression to evaluate
+    /// # Errors
+    /// Since this function borrows the state, it can fail if the state cannot be borrowed mutably
+    pub fn list_functions(&mut self) -> Result<Vec<String>, Error> {
+        self.inner.list_functions()
+    }
+
+    /// Sets a fallback, called when a script calls a function through `rustyscript.functions` or
+    /// `rustyscript.async_functions` that is not registered
     ///
-    /// # Returns
-    /// A `Result` containing the deserialized result of the expression (`T`)  
-    /// or an error (`Error`) if the expression cannot be evaluated or if the
-    /// result cannot be deserialized.
+    /// The hook receives the function's name and arguments, and its result is returned to the script
+    /// It can return [`Error::ValueNotCallable`] to keep the default behaviour for some names  
+    /// Functions denied by a module's [`crate::Capabilities`] are still rejected without calling the hook
     ///
     /// # Errors
-    /// Can fail if the expression cannot be evaluated, or if the result cannot be deserialized into the requested type
+    /// Since this function borrows the state, it can fail if the state cannot be borrowed mutably
     ///
-    /// # Example
     /// ```rust
-    /// use rustyscript::{ Runtime, Error };
+    /// use rustyscript::{ Error, Runtime, serde_json::Value };
     ///
-    /// # fn main() -> Result<(), Error> {
+    /// # fn main() -> Result<(), rustyscript::Error> {
     /// let mut runtime = Runtime::new(Default::default())?;
+    /// runtime.set_missing_function_hook(|name, _args| {
+    ///     Err(Error::Runtime(format!("the plugin providing `{name}` is disabled")))
+    /// })?;
     ///
-    /// let value: u32 = runtime.eval("2 + 2")?;
-    /// assert_eq!(4, value);
-    ///
-    /// let value: String = runtime.eval("new Promise(resolve => resolve('test'))")?;
-    /// assert_eq!("test", value);
-    ///
+    /// let err = runtime.eval::<Value>("rustyscript.functions.export_pdf()").unwrap_err();
+    /// assert!(err.to_string().contains("is disabled"));
     /// # Ok(())
     /// # }
     /// ```
-    pub fn eval<T>(&mut self, expr: impl ToString) -> Result<T, Error>
+    pub fn set_missing_function_hook<F>(&mut self, hook: F) -> Result<(), Error>
     where
-        T: serde::de::DeserializeOwned,
+        F: Fn(&str, &[serde_json::Value]) -> Result<serde_json::Value, Error> + 'static,
     {
-        self.block_on(|runtime| async move { runtime.eval_async(expr).await })
+        self.inner
+            .put(crate::ext::rustyscript::MissingFunctionHook(Box::new(hook)))
     }
 
-    /// Evaluate a piece of non-ECMAScript-module JavaScript code  
-    /// The expression is evaluated in the global context, so changes persist
-    ///
-    /// Awaits promise resolution, and runs the event loop to completion
+    /// Sets a hook notified when a registered rust function panics
     ///
-    /// Asynchronous code is supported, partially
-    /// - Top-level await is not supported
-    /// - The event loop will be run to completion after the expression is evaluated
-    ///
-    /// For top-level await support, use one of:
-    /// - `call_function_async`
-    /// - `call_stored_function_async`
-    /// - `load_module_async`
-    /// - `load_modules_async`
+    /// Panics in rust functions are always caught at the boundary with javascript, and thrown in the script
+    /// as an error named `PanicError` carrying the panic message - the runtime stays usable afterwards  
+    /// The hook lets the host log or report the panic, or stop using a runtime it no longer trusts
     ///
-    /// Or any of the `_immmediate` variants, paired with [`crate::js_value::Promise`]
+    /// Panics can only be caught when the crate is built with `panic = "unwind"`, the default
     ///
-    /// # Arguments
-    /// * `expr` - A string representing the JavaScript expression to evaluate
+    /// # Errors
+    /// Since this function borrows the state, it can fail if the state cannot be borrowed mutably
     ///
-    /// # Returns
-    /// A `Result` containing the deserialized result of the expression (`T`)  
-    /// or an error (`Error`) if the expression cannot be evaluated or if the
-    /// result cannot be deserialized.
+    /// ```rust
+    /// use rustyscript::{ Runtime, serde_json::Value };
     ///
-    /// # Errors
-    /// Can fail if the expression cannot be evaluated, or if the result cannot be deserialized into the requested type
+    /// # fn main() -> Result<(), rustyscript::Error> {
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// runtime.register_function("parse", |_| panic!("bad input"))?;
+    /// runtime.set_panic_hook(|panic| {
+    ///     eprintln!("`{}` panicked: {}", panic.function, panic.message);
+    /// })?;
     ///
-    /// # Example
-    /// For an example, see [`Runtime::eval`]
-    pub async fn eval_async<T>(&mut self, expr: impl ToString) -> Result<T, Error>
+    /// let message: String = runtime.eval("(() => { try { rustyscript.functions.parse() } catch (e) { return e.message } })()")?;
+    /// assert_eq!(message, "Function `parse` panicked: bad input");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_panic_hook<F>(&mut self, hook: F) -> Result<(), Error>
     where
-        T: serde::de::DeserializeOwned,
+        F: Fn(&crate::CallbackPanic) + 'static,
     {
-        let result = self.inner.eval(expr.to_string()).await?;
-        let result = self.inner.resolve_with_event_loop(result).await?;
-        self.inner.decode_value(result)
+        self.inner
+            .put(crate::ext::rustyscript::PanicHook(std::rc::Rc::new(hook)))
     }
 
-    /// Evaluate a piece of non-ECMAScript-module JavaScript code  
-    /// The expression is evaluated in the global context, so changes persist
-    ///
-    /// Does not await promise resolution, or run the event loop  
-    /// Promises can be returned by specifying the return type as [`crate::js_value::Promise`]  
-    /// The event loop should be run using [`Runtime::await_event_loop`]
+    /// Register a rust function to be callable from JS, grouped under a namespace
+    /// - Called from JS as `rustyscript.api.{namespace}.{name}`, or as `rustyscript.functions["{namespace}.{name}"]`
+    /// - The namespace can also be made importable as a module, with [`Runtime::expose_namespace`]
     ///
-    /// Note that this function needs to be async because calls to `setTimeout` must be evaluated from within an async runtime.
+    /// Namespaces keep large host APIs organized, instead of placing every function in one flat list
     ///
-    /// Asynchronous code is supported, partially
-    /// - Top-level await is not supported
+    /// # Errors
+    /// Since this function borrows the state, it can fail if the state cannot be borrowed mutably
     ///
-    /// For top-level await support, use one of:
-    /// - `call_function_async`
-    /// - `call_stored_function_async`
-    /// - `load_module_async`
-    /// - `load_modules_async`
+    /// ```rust
+    /// use rustyscript::{ Runtime, serde_json::Value };
     ///
-    /// Or any of the `_immmediate` variants, paired with [`crate::js_value::Promise`]
+    /// # fn main() -> Result<(), rustyscript::Error> {
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// runtime.register_function_in("db", "query", |args| {
+    ///     Ok(Value::String(format!("rows for {}", args[0])))
+    /// })?;
     ///
-    /// # Arguments
-    /// * `expr` - A string representing the JavaScript expression to evaluate
+    /// let rows: String = runtime.eval("rustyscript.api.db.query('users')")?;
+    /// assert_eq!(rows, "rows for \"users\"");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn register_function_in<F>(
+        &mut self,
+        namespace: &str,
+        name: &str,
+        callback: F,
+    ) -> Result<(), Error>
+    where
+        F: RsFunction,
+    {
+        self.inner
+            .register_function(&format!("{namespace}.{name}"), callback)
+    }
+
+    /// Register a non-blocking rust function to be callable from JS, grouped under a namespace
+    /// - Called from JS as `rustyscript.api.{namespace}.{name}`, which returns a promise
     ///
-    /// # Returns
-    /// A `Result` containing the deserialized result of the expression (`T`)  
-    /// or an error (`Error`) if the expression cannot be evaluated or if the
-    /// result cannot be deserialized.
+    /// See [`Runtime::register_function_in`]
     ///
     /// # Errors
-    /// Can fail if the expression cannot be evaluated, or if the result cannot be deserialized into the requested type
-    ///
-    /// # Example
-    /// For an example, see [`Runtime::eval`]
-    pub async fn eval_immediate<T>(&mut self, expr: impl ToString) -> Result<T, Error>
+    /// Since this function borrows the state, it can fail if the state cannot be borrowed mutably
+    pub fn register_async_function_in<F>(
+        &mut self,
+        namespace: &str,
+        name: &str,
+        callback: F,
+    ) -> Result<(), Error>
     where
-        T: serde::de::DeserializeOwned,
+        F: RsAsyncFunction,
     {
-        let result = self.inner.eval(expr.to_string()).await?;
-        self.inner.decode_value(result)
+        self.inner
+            .register_async_function(&format!("{namespace}.{name}"), callback)
     }
 
-    /// Calls a stored javascript function and deserializes its return value.
-    ///
-    /// Returns a future that resolves when:
-    /// - The event loop is resolved, and
-    /// - If the value is a promise, the promise is resolved
+    /// Makes the functions registered in a namespace importable, as a module named `host:{namespace}`
     ///
-    /// See [`Runtime::call_function`] for an example
+    /// The module exports each function under its name, as with `rustyscript.api.{namespace}`  
+    /// Its exports are fixed when this is called, so register the namespace's functions first
     ///
-    /// Note that synchronous functions are run synchronously. Returned promises will be run asynchronously, however.
+    /// # Errors
+    /// Will return an error if the namespace has no registered functions, or if its name is not a valid module specifier
     ///
-    /// # Arguments
-    /// * `module_context` - Optional handle to a module providing global context for the function
-    /// * `function` - A The function object
-    /// * `args` - The arguments to pass to the function
+    /// ```rust
+    /// use rustyscript::{ Runtime, Module, serde_json::Value };
     ///
-    /// # Returns
-    /// A `Result` containing the deserialized result of the function call (`T`)  
-    /// or an error (`Error`) if there are issues with calling the function,
-    /// or if the result cannot be deserialized.
+    /// # fn main() -> Result<(), rustyscript::Error> {
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// runtime.register_function_in("db", "query", |_| Ok(Value::from(3)))?;
+    /// runtime.expose_namespace("db")?;
     ///
-    /// # Errors
-    /// Can fail if there are issues with calling the function, or if the result cannot be deserialized into the requested type
-    pub async fn call_stored_function_async<T>(
-        &mut self,
-        module_context: Option<&ModuleHandle>,
-        function: &Function,
-        args: &impl serde::ser::Serialize,
-    ) -> Result<T, Error>
-    where
-        T: serde::de::DeserializeOwned,
-    {
-        let function = function.as_global(&mut self.deno_runtime().handle_scope());
-        let result = self
-            .inner
-            .call_function_by_ref(module_context, &function, args)?;
-        let result = self.inner.resolve_with_event_loop(result).await?;
-        self.inner.decode_value(result)
+    /// let module = Module::new("test.js", "
+    ///     import { query } from 'host:db';
+    ///     export const count = query();
+    /// ");
+    /// let handle = runtime.load_module(&module)?;
+    /// let count: usize = runtime.get_value(Some(&handle), "count")?;
+    /// assert_eq!(count, 3);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn expose_namespace(&mut self, namespace: &str) -> Result<(), Error> {
+        self.inner.expose_namespace(namespace)
     }
 
-    /// Calls a stored javascript function and deserializes its return value.
+    /// Expose a rust value to javascript, as an object named `namespace` in the global scope
+    /// - The value's type must implement [`crate::JsApi`], usually through the [`crate::js_api`] attribute
+    /// - Each exposed method is also registered as a function named `{namespace}.{method}`
     ///
-    /// Blocks until:
-    /// - The event loop is resolved, and
-    /// - If the value is a promise, the promise is resolved
+    /// # Errors
+    /// Can fail if the functions cannot be registered, or the object cannot be created
     ///
-    /// See [`Runtime::call_function`] for an example
+    /// ```rust
+    /// use rustyscript::{ js_api, Error, Runtime };
     ///
-    /// # Arguments
-    /// * `module_context` - Optional handle to a module providing global context for the function
-    /// * `function` - A The function object
-    /// * `args` - The arguments to pass to the function
+    /// struct Greeter;
     ///
-    /// # Returns
-    /// A `Result` containing the deserialized result of the function call (`T`)  
-    /// or an error (`Error`) if there are issues with calling the function,
-    /// or if the result cannot be deserialized.
+    /// #[js_api]
+    /// impl Greeter {
+    ///     pub fn greet(&self, name: String) -> Result<String, Error> {
+    ///         Ok(format!("Hello, {name}!"))
+    ///     }
+    /// }
     ///
-    /// # Errors
-    /// Can fail if there are issues with calling the function, or if the result cannot be deserialized into the requested type
-    pub fn call_stored_function<T>(
-        &mut self,
-        module_context: Option<&ModuleHandle>,
-        function: &Function,
-        args: &impl serde::ser::Serialize,
-    ) -> Result<T, Error>
+    /// # fn main() -> Result<(), Error> {
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// runtime.register_api("greeter", Greeter)?;
+    ///
+    /// let greeting: String = runtime.eval("greeter.greet('world')")?;
+    /// assert_eq!(greeting, "Hello, world!");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn register_api<T>(&mut self, namespace: &str, api: T) -> Result<(), Error>
     where
-        T: deno_core::serde::de::DeserializeOwned,
+        T: crate::JsApi,
     {
-        self.block_on(|runtime| async move {
-            runtime
-                .call_stored_function_async(module_context, function, args)
-                .await
-        })
+        crate::js_api::register_api(self, namespace, api)
     }
 
-    /// Calls a stored javascript function and deserializes its return value.
+    /// Store a rust value in the runtime, returning an opaque handle to it
+    /// - The handle can be passed into javascript, stored by scripts, and passed back into registered functions
+    /// - Registered functions can resolve the handle using [`Runtime::external_store`]
     ///
-    /// Will not attempt to resolve promises, or run the event loop  
-    /// Promises can be returned by specifying the return type as [`crate::js_value::Promise`]  
-    /// The event loop should be run using [`Runtime::await_event_loop`]
+    /// This avoids serializing large host objects that scripts only need to reference
     ///
-    /// See [`Runtime::call_function`] for an example
+    /// # Errors
+    /// Since this function borrows the state, it can fail if the state cannot be borrowed mutably
     ///
-    /// # Arguments
-    /// * `module_context` - Optional handle to a module providing global context for the function
-    /// * `function` - A The function object
-    /// * `args` - The arguments to pass to the function
+    /// ```rust
+    /// use rustyscript::{ Runtime, Module, External, serde_json };
     ///
-    /// # Returns
-    /// A `Result` containing the deserialized result of the function call (`T`)  
-    /// or an error (`Error`) if there are issues with calling the function,
-    /// or if the result cannot be deserialized.
+    /// # fn main() -> Result<(), rustyscript::Error> {
+    /// let module = Module::new("test.js", " export const f = (h) => rustyscript.functions.len(h); ");
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// let handle = runtime.load_module(&module)?;
     ///
-    /// # Errors
-    /// Can fail if there are issues with calling the function, or if the result cannot be deserialized into the requested type
-    pub fn call_stored_function_immediate<T>(
-        &mut self,
-        module_context: Option<&ModuleHandle>,
-        function: &Function,
-        args: &impl serde::ser::Serialize,
-    ) -> Result<T, Error>
+    /// let store = runtime.external_store()?;
+    /// runtime.register_function("len", move |args| {
+    ///     let handle: External<Vec<u8>> = serde_json::from_value(args[0].clone())?;
+    ///     let len = store.get(handle).map_or(0, |v| v.len());
+    ///     Ok(len.into())
+    /// })?;
+    ///
+    /// let external = runtime.create_external(vec![0u8; 1024])?;
+    /// let len: usize = runtime.call_function(Some(&handle), "f", &(external,))?;
+    /// assert_eq!(len, 1024);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn create_external<T>(&mut self, value: T) -> Result<crate::External<T>, Error>
     where
-        T: deno_core::serde::de::DeserializeOwned,
+        T: 'static,
     {
-        let function = function.as_global(&mut self.deno_runtime().handle_scope());
-        let result = self
-            .inner
-            .call_function_by_ref(module_context, &function, args)?;
-        self.inner.decode_value(result)
+        Ok(self.inner.external_store()?.insert(value))
     }
 
-    /// Calls a javascript function within the Deno runtime by its name and deserializes its return value.
+    /// Returns the store holding values created with [`Runtime::create_external`]
+    /// The store can be cloned into registered functions to resolve handles passed in by scripts
     ///
-    /// Returns a future that resolves when:
-    /// - The event loop is resolved, and
-    /// - If the value is a promise, the promise is resolved
+    /// # Errors
+    /// Since this function borrows the state, it can fail if the state cannot be borrowed mutably
+    pub fn external_store(&mut self) -> Result<crate::ExternalStore, Error> {
+        self.inner.external_store()
+    }
+
+    /// Returns the receiving end of the queue filled by `rustyscript.queue.push(item)`
     ///
-    /// Note that synchronous functions are run synchronously. Returned promises will be run asynchronously, however.
+    /// The queue is bounded by [`RuntimeOptions::queue_capacity`]
+    /// Once it is full, the promise returned by `push` only resolves after an item is received
     ///
-    /// See [`Runtime::call_function`] for an example
+    /// # Errors
+    /// Will return an error if the receiver has already been taken,
+    /// or if the state cannot be borrowed mutably
     ///
-    /// # Arguments
-    /// * `module_context` - Optional handle to a module to search - if None, or if the search fails, the global context is used
-    /// * `name` - A string representing the name of the javascript function to call.
-    /// * `args` - The arguments to pass to the function
+    /// ```rust
+    /// use rustyscript::{ Runtime, Module };
     ///
-    /// # Returns
-    /// A `Result` containing the deserialized result of the function call (`T`)  
-    /// or an error (`Error`) if the function cannot be found, if there are issues with
-    /// calling the function, or if the result cannot be deserialized.
+    /// # fn main() -> Result<(), rustyscript::Error> {
+    /// let module = Module::new("test.js", "
+    ///     for (let i = 0; i < 3; i++) {
+    ///         await rustyscript.queue.push({ id: i });
+    ///     }
+    /// ");
     ///
-    /// # Errors
-    /// Fails if the function cannot be found, if there are issues with calling the function,
-    /// Or if the result cannot be deserialized into the requested type
-    pub async fn call_function_async<T>(
-        &mut self,
-        module_context: Option<&ModuleHandle>,
-        name: &str,
-        args: &impl serde::ser::Serialize,
-    ) -> Result<T, Error>
-    where
-        T: deno_core::serde::de::DeserializeOwned,
-    {
-        let function = self.inner.get_function_by_name(module_context, name)?;
-        let result = self
-            .inner
-            .call_function_by_ref(module_context, &function, args)?;
-        let result = self.inner.resolve_with_event_loop(result).await?;
-        self.inner.decode_value(result)
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// let mut receiver = runtime.queue_receiver()?;
+    /// runtime.load_module(&module)?;
+    ///
+    /// let item = receiver.try_recv().unwrap();
+    /// assert_eq!(item["id"], 0);
+    /// assert_eq!(receiver.len(), 2);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn queue_receiver(&mut self) -> Result<crate::QueueReceiver, Error> {
+        self.inner.queue_receiver()
     }
 
-    /// Calls a javascript function within the Deno runtime by its name and deserializes its return value.
-    ///
-    /// Blocks until:
-    /// - The event loop is resolved, and
-    /// - If the value is a promise, the promise is resolved
+    /// Removes and returns the records written by scripts with `host.log`, oldest first
     ///
-    /// # Arguments
-    /// * `module_context` - Optional handle to a module to search - if None, or if the search fails, the global context is used
-    /// * `name` - A string representing the name of the javascript function to call.
-    /// * `args` - The arguments to pass to the function
+    /// Scripts call `host.log.info(message, fields)` - or `trace`, `debug`, `warn` and `error` -
+    /// or `host.log(level, message, fields)`, where `fields` is an optional object of structured data
     ///
-    /// # Returns
-    /// A `Result` containing the deserialized result of the function call (`T`)  
-    /// or an error (`Error`) if the function cannot be found, if there are issues with
-    /// calling the function, or if the result cannot be deserialized.
+    /// Records are kept in a buffer of [`RuntimeOptions::log_capacity`] entries, separately from the console,
+    /// so they are collected even when console output is discarded or captured
     ///
     /// # Errors
-    /// Fails if the function cannot be found, if there are issues with calling the function,  
-    /// Or if the result cannot be deserialized into the requested type
-    ///
-    /// # Example
+    /// Since this function borrows the state, it can fail if the state cannot be borrowed mutably
     ///
     /// ```rust
-    /// use rustyscript::{ json_args, Runtime, Module, Error };
+    /// use rustyscript::{ LogLevel, Runtime };
     ///
-    /// # fn main() -> Result<(), Error> {
+    /// # fn main() -> Result<(), rustyscript::Error> {
     /// let mut runtime = Runtime::new(Default::default())?;
-    /// let module = Module::new("/path/to/module.js", "export function f() { return 2; };");
-    /// let module = runtime.load_module(&module)?;
-    /// let value: usize = runtime.call_function(Some(&module), "f", json_args!())?;
+    /// runtime.eval::<()>("host.log.warn('low disk space', { free: 1024 })")?;
+    ///
+    /// let logs = runtime.drain_logs()?;
+    /// assert_eq!(logs[0].level, LogLevel::Warn);
+    /// assert_eq!(logs[0].fields["free"], 1024);
     /// # Ok(())
     /// # }
     /// ```
-    pub fn call_function<T>(
-        &mut self,
-        module_context: Option<&ModuleHandle>,
-        name: &str,
-        args: &impl serde::ser::Serialize,
-    ) -> Result<T, Error>
-    where
-        T: deno_core::serde::de::DeserializeOwned,
-    {
-        self.block_on(|runtime| async move {
-            runtime
-                .call_function_async(module_context, name, args)
-                .await
-        })
+    pub fn drain_logs(&mut self) -> Result<Vec<crate::LogRecord>, Error> {
+        self.inner.drain_logs()
     }
 
-    /// Calls a javascript function within the Deno runtime by its name and deserializes its return value.
-    ///
-    /// Will not attempt to resolve promises, or run the event loop  
-    /// Promises can be returned by specifying the return type as [`crate::js_value::Promise`]  
-    /// The event loop should be run using [`Runtime::await_event_loop`]
-    ///
-    /// # Arguments
-    /// * `module_context` - Optional handle to a module to search - if None, or if the search fails, the global context is used
-    /// * `name` - A string representing the name of the javascript function to call.
-    /// * `args` - The arguments to pass to the function
+    /// Returns the events recorded since the last call, when [`RuntimeOptions::trace`] is [`crate::TraceMode::Record`]
     ///
-    /// # Returns
-    /// A `Result` containing the deserialized result of the function call (`T`)  
-    /// or an error (`Error`) if the function cannot be found, if there are issues with
-    /// calling the function, or if the result cannot be deserialized.
+    /// While replaying, returns the events of the trace that have not been replayed yet
+    /// Once a replay is complete, this is empty
     ///
     /// # Errors
-    /// Fails if the function cannot be found, if there are issues with calling the function,  
-    /// Or if the result cannot be deserialized into the requested type
-    ///
-    /// # Example
+    /// Will return an error if tracing is not enabled, or if the state cannot be borrowed
     ///
     /// ```rust
-    /// use rustyscript::{ json_args, Runtime, Module, Error };
+    /// use rustyscript::{ Runtime, RuntimeOptions, TraceMode };
     ///
-    /// # fn main() -> Result<(), Error> {
-    /// let mut runtime = Runtime::new(Default::default())?;
-    /// let module = Module::new("/path/to/module.js", "export function f() { return 2; };");
-    /// let module = runtime.load_module(&module)?;
-    /// let value: usize = runtime.call_function_immediate(Some(&module), "f", json_args!())?;
+    /// # fn main() -> Result<(), rustyscript::Error> {
+    /// let code = "Math.random() < 1";
+    ///
+    /// let mut runtime = Runtime::new(RuntimeOptions { trace: TraceMode::Record, ..Default::default() })?;
+    /// runtime.eval::<bool>(code)?;
+    /// let trace = runtime.take_trace()?;
+    ///
+    /// // The same random number is returned when replaying
+    /// let mut runtime = Runtime::new(RuntimeOptions { trace: TraceMode::Replay(trace), ..Default::default() })?;
+    /// runtime.eval::<bool>(code)?;
+    /// assert!(runtime.take_trace()?.events.is_empty());
     /// # Ok(())
     /// # }
     /// ```
-    pub fn call_function_immediate<T>(
-        &mut self,
-        module_context: Option<&ModuleHandle>,
-        name: &str,
-        args: &impl serde::ser::Serialize,
-    ) -> Result<T, Error>
-    where
-        T: deno_core::serde::de::DeserializeOwned,
-    {
-        let function = self.inner.get_function_by_name(module_context, name)?;
-        let result = self
-            .inner
-            .call_function_by_ref(module_context, &function, args)?;
-        self.inner.decode_value(result)
+    pub fn take_trace(&mut self) -> Result<crate::ExecutionTrace, Error> {
+        self.inner.take_trace()
     }
 
-    /// Get a value from a runtime instance
-    ///
-    /// Blocks until:
-    /// - The event loop is resolved, and
-    /// - If the value is a promise, the promise is resolved
+    /// Runs `f` with an ambient context object, which scripts can read with `rustyscript.context()`
     ///
-    /// # Arguments
-    /// * `module_context` - Optional handle to a module to search - if None, or if the search fails, the global context is used
-    /// * `name` - A string representing the name of the value to find
+    /// Useful for passing request-scoped data, such as a request ID or the current user, without mutating `globalThis`
+    /// The context is deeply frozen, and is cleared once `f` returns - `rustyscript.context()` returns `null` outside of it
+    /// Promises and timers created during `f` keep the context, even if they settle afterwards
     ///
-    /// # Returns
-    /// A `Result` containing the deserialized result or an error (`Error`) if the value cannot be found,
-    /// Or if the result cannot be deserialized into the requested type
+    /// Calls can be nested, in which case the innermost context applies
     ///
     /// # Errors
-    /// Can fail if the value cannot be found, or if the result cannot be deserialized.
+    /// Will return an error if the context cannot be serialized, or if `f` returns an error
     ///
     /// # Example
-    ///
     /// ```rust
-    /// use rustyscript::{ Runtime, Module, Error };
+    /// use rustyscript::{ json_args, Runtime, Module };
+    /// use serde_json::json;
+    ///
+    /// # fn main() -> Result<(), rustyscript::Error> {
+    /// let module = Module::new("test.js", "
+    ///     export const requestId = () => rustyscript.context()?.requestId;
+    /// ");
     ///
-    /// # fn main() -> Result<(), Error> {
     /// let mut runtime = Runtime::new(Default::default())?;
-    /// let module = Module::new("/path/to/module.js", "globalThis.my_value = 2;");
     /// let module = runtime.load_module(&module)?;
-    /// let value: usize = runtime.get_value(Some(&module), "my_value")?;
+    ///
+    /// let id: String = runtime.with_context(&json!({ "requestId": "abc" }), |runtime| {
+    ///     runtime.call_function(Some(&module), "requestId", json_args!())
+    /// })?;
+    /// assert_eq!(id, "abc");
     /// # Ok(())
     /// # }
     /// ```
-    pub fn get_value<T>(
+    pub fn with_context<C, R>(
         &mut self,
-        module_context: Option<&ModuleHandle>,
-        name: &str,
-    ) -> Result<T, Error>
+        context: &C,
+        f: impl FnOnce(&mut Self) -> Result<R, Error>,
+    ) -> Result<R, Error>
     where
-        T: serde::de::DeserializeOwned,
+        C: serde::Serialize,
     {
-        self.block_on(|runtime| async move { runtime.get_value_async(module_context, name).await })
+        self.call_context_function("__rustyscript_enter_context", &(context,))?;
+        let result = f(self);
+        self.call_context_function("__rustyscript_exit_context", &())?;
+        result
     }
 
-    /// Get a value from a runtime instance
+    /// Calls one of the context globals, which refuse to run unless the host opened the gate first
+    fn call_context_function(
+        &mut self,
+        name: &str,
+        args: &impl serde::ser::Serialize,
+    ) -> Result<(), Error> {
+        let set_gate = |runtime: &mut Self, open: bool| -> Result<(), Error> {
+            let state = runtime.deno_runtime().op_state();
+            let mut state = state.try_borrow_mut()?;
+            state
+                .borrow_mut::<crate::ext::rustyscript::ContextGate>()
+                .set(open);
+            Ok(())
+        };
+
+        set_gate(self, true)?;
+        let result = self.call_function_immediate::<Undefined>(None, name, args);
+        set_gate(self, false)?;
+        result.map(|_| ())
+    }
+
+    /// Creates a javascript `AbortSignal` that can be aborted from rust
     ///
-    /// Returns a future that resolves when:
-    /// - The event loop is resolved, and
-    /// - If the value is a promise, the promise is resolved
+    /// Pass the signal into a call as an argument, and abort it later with [`crate::AbortSignal::abort`],
+    /// or from another thread with the [`crate::AbortHandle`] returned by [`crate::AbortSignal::handle`]
+    /// Cancellation is cooperative - the script sees the abort once it yields to the event loop
     ///
-    /// See [`Runtime::get_value`] for an example
+    /// Requires the `web` or `web_stub` feature, which provide `AbortController`
     ///
-    /// # Arguments
-    /// * `module_context` - Optional handle to a module to search - if None, or if the search fails, the global context is used
-    /// * `name` - A string representing the name of the value to find
+    /// # Errors
+    /// Will return an error if `AbortController` is not available, or if the state cannot be borrowed mutably
     ///
-    /// # Returns
-    /// A `Result` containing the deserialized result or an error (`Error`) if the value cannot be found,  
-    /// Or if the result cannot be deserialized into the requested type
+    /// ```rust
+    /// use rustyscript::{ Runtime, Module };
+    /// use std::time::Duration;
     ///
-    /// # Errors
-    /// Can fail if the value cannot be found, or if the result cannot be deserialized.
-    pub async fn get_value_async<T>(
-        &mut self,
-        module_context: Option<&ModuleHandle>,
-        name: &str,
-    ) -> Result<T, Error>
-    where
-        T: serde::de::DeserializeOwned,
-    {
-        let result = self.inner.get_value_ref(module_context, name)?;
-        let result = self.inner.resolve_with_event_loop(result).await?;
-        self.inner.decode_value(result)
+    /// # fn main() -> Result<(), rustyscript::Error> {
+    /// let module = Module::new("test.js", "
+    ///     export async function work(signal) {
+    ///         while (!signal.aborted) {
+    ///             await new Promise((r) => setTimeout(r, 5));
+    ///         }
+    ///         return signal.reason;
+    ///     }
+    /// ");
+    ///
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// let module = runtime.load_module(&module)?;
+    ///
+    /// let signal = runtime.create_abort_signal()?;
+    /// let handle = signal.handle();
+    /// std::thread::spawn(move || {
+    ///     std::thread::sleep(Duration::from_millis(20));
+    ///     handle.abort_with_reason(&"cancelled").unwrap();
+    /// });
+    ///
+    /// let reason: String = runtime.call_function(Some(&module), "work", &(&signal,))?;
+    /// assert_eq!(reason, "cancelled");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn create_abort_signal(&mut self) -> Result<crate::AbortSignal, Error> {
+        use crate::ext::rustyscript::AbortResource;
+
+        let (resource, handle) = AbortResource::new();
+        let state = self.deno_runtime().op_state();
+        let rid = state.try_borrow_mut()?.resource_table.add(resource);
+
+        let signal = self.call_function_immediate(None, "__rustyscript_abort_signal", &(rid,));
+        match signal {
+            Ok(signal) => Ok(AbortResource::signal(signal, handle)),
+            Err(e) => {
+                state
+                    .try_borrow_mut()?
+                    .resource_table
+                    .take::<AbortResource>(rid)
+                    .ok();
+                Err(e)
+            }
+        }
     }
 
-    /// Get a value from a runtime instance
+    /// Returns the `performance.mark` and `performance.measure` entries recorded by scripts so far (`web` crate feature)
     ///
-    /// Will not attempt to resolve promises, or run the event loop  
-    /// Promises can be returned by specifying the return type as [`crate::js_value::Promise`]  
-    /// The event loop should be run using [`Runtime::await_event_loop`]
+    /// Lets a host collect the instrumentation in its scripts alongside its own metrics
+    /// Entries are kept until a script calls `performance.clearMarks` or `performance.clearMeasures`
     ///
-    /// # Arguments
-    /// * `module_context` - Optional handle to a module to search - if None, or if the search fails, the global context is used
-    /// * `name` - A string representing the name of the value to find
+    /// # Errors
+    /// Will return an error if an entry's `detail` cannot be deserialized
     ///
-    /// # Returns
-    /// A `Result` containing the deserialized result or an error (`Error`) if the value cannot be found,
-    /// Or if the result cannot be deserialized into the requested type
+    /// # Example
+    /// ```rust
+    /// use rustyscript::{ Runtime, Module, PerformanceEntryType };
+    ///
+    /// # fn main() -> Result<(), rustyscript::Error> {
+    /// let module = Module::new("test.js", "
+    ///     performance.mark('start');
+    ///     performance.measure('setup', { start: 'start', detail: { step: 1 } });
+    /// ");
+    ///
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// runtime.load_module(&module)?;
+    ///
+    /// let entries = runtime.performance_entries()?;
+    /// assert_eq!(entries[0].entry_type, PerformanceEntryType::Mark);
+    /// assert_eq!(entries[1].name, "setup");
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "web")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "web")))]
+    pub fn performance_entries(&mut self) -> Result<Vec<crate::PerformanceEntry>, Error> {
+        self.call_function_immediate(None, "__rustyscript_performance_entries", &())
+    }
+
+    /// Sets the span that spans recorded by later calls will be children of (`web` crate feature)
+    ///
+    /// Lets the spans from a script - such as the ones `fetch` creates - appear under the host's own span,
+    /// like the span of the server request that the script is handling
+    /// Spans are only recorded if [`crate::WebOptions::span_processor`] is set
+    ///
+    /// The context applies to every call until it is changed, or cleared with `None`,
+    /// and is captured by the promises and timers those calls create
     ///
     /// # Errors
-    /// Can fail if the value cannot be found, or if the result cannot be deserialized.
+    /// Will return an error if the context cannot be applied
     ///
     /// # Example
-    ///
     /// ```rust
-    /// use rustyscript::{ Runtime, Module, Error };
+    /// use rustyscript::{ Runtime, opentelemetry::trace::{SpanContext, SpanId, TraceFlags, TraceId, TraceState} };
     ///
-    /// # fn main() -> Result<(), Error> {
+    /// # fn main() -> Result<(), rustyscript::Error> {
     /// let mut runtime = Runtime::new(Default::default())?;
-    /// let module = Module::new("/path/to/module.js", "globalThis.my_value = 2;");
-    /// let module = runtime.load_module(&module)?;
-    /// let value: usize = runtime.get_value_immediate(Some(&module), "my_value")?;
+    ///
+    /// // Usually obtained from the host's current span, or from an incoming `traceparent` header
+    /// let parent = SpanContext::new(
+    ///     TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap(),
+    ///     SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+    ///     TraceFlags::SAMPLED,
+    ///     true,
+    ///     TraceState::default(),
+    /// );
+    /// runtime.set_trace_context(Some(&parent))?;
+    ///
+    /// // ... calls made here create spans under `parent`
+    ///
+    /// runtime.set_trace_context(None)?;
     /// # Ok(())
     /// # }
     /// ```
-    pub fn get_value_immediate<T>(
+    #[cfg(feature = "web")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "web")))]
+    pub fn set_trace_context(
         &mut self,
-        module_context: Option<&ModuleHandle>,
-        name: &str,
-    ) -> Result<T, Error>
-    where
-        T: serde::de::DeserializeOwned,
-    {
-        let result = self.inner.get_value_ref(module_context, name)?;
-        self.inner.decode_value(result)
+        context: Option<&opentelemetry::trace::SpanContext>,
+    ) -> Result<(), Error> {
+        let parent = context.filter(|c| c.is_valid()).map(|c| {
+            serde_json::json!({
+                "traceId": c.trace_id().to_string(),
+                "spanId": c.span_id().to_string(),
+                "traceFlags": c.trace_flags().to_u8(),
+            })
+        });
+        self.call_function_immediate(None, "__rustyscript_set_trace_context", &(parent,))
     }
 
-    /// Executes the given module, and returns a handle allowing you to extract values
-    /// and call functions
+    /// Imports key material into the runtime as a Web Crypto `CryptoKey`
     ///
-    /// Blocks until the module has been executed AND the event loop has fully resolved  
-    /// See [`Runtime::load_module_async`] for a non-blocking variant, or use with async
-    /// background tasks
-    ///
-    /// # Arguments
-    /// * `module` - A `Module` object containing the module's filename and contents.
+    /// The returned key can be passed to functions as an argument, so the host can provision keys
+    /// to scripts without placing the raw bytes in a global - with `extractable` false, scripts can use it but not read it
     ///
-    /// # Returns
-    /// A `Result` containing a handle for the loaded module
-    /// or an error (`Error`) if there are issues with loading or executing the module
+    /// `algorithm` and `usages` are passed to `SubtleCrypto.importKey` as-is,
+    /// such as `{ "name": "HMAC", "hash": "SHA-256" }` and `["sign", "verify"]`
     ///
     /// # Errors
-    /// Can fail if the module cannot be loaded, or execution fails
+    /// Will return an error if the key material is invalid, or does not match the algorithm or usages
     ///
     /// # Example
-    ///
     /// ```rust
-    /// // Create a module with filename and contents
-    /// use rustyscript::{Runtime, Module, Error};
+    /// use rustyscript::{ KeyMaterial, Module, Runtime, serde_json::json };
     ///
-    /// # fn main() -> Result<(), Error> {
+    /// # fn main() -> Result<(), rustyscript::Error> {
     /// let mut runtime = Runtime::new(Default::default())?;
-    /// let module = Module::new("test.js", "export default () => 'test'");
-    /// runtime.load_module(&module);
+    /// let module = runtime.load_module(&Module::new("test.js", "
+    ///     export const sign = (key, data) => crypto.subtle.sign('HMAC', key, new Uint8Array(data));
+    /// "))?;
+    ///
+    /// let material = KeyMaterial::Raw(vec![0; 32]);
+    /// let algorithm = json!({ "name": "HMAC", "hash": "SHA-256" });
+    /// let key = runtime.import_crypto_key(&material, &algorithm, false, &["sign"])?;
+    ///
+    /// let _: rustyscript::Undefined = runtime.call_function(Some(&module), "sign", &(&key, [1, 2, 3]))?;
     /// # Ok(())
     /// # }
     /// ```
-    pub fn load_module(&mut self, module: &Module) -> Result<ModuleHandle, Error> {
+    #[cfg(feature = "crypto")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "crypto")))]
+    pub fn import_crypto_key(
+        &mut self,
+        material: &crate::KeyMaterial,
+        algorithm: &serde_json::Value,
+        extractable: bool,
+        usages: &[&str],
+    ) -> Result<crate::js_value::Value, Error> {
         self.block_on(|runtime| async move {
-            let handle = runtime.load_module_async(module).await;
             runtime
-                .await_event_loop(PollEventLoopOptions::default(), None)
-                .await?;
-            handle
+                .import_crypto_key_async(material, algorithm, extractable, usages)
+                .await
         })
     }
 
-    /// Executes the given module, and returns a handle allowing you to extract values
-    /// and call functions
+    /// Imports key material into the runtime as a Web Crypto `CryptoKey`
     ///
-    /// Returns a future that resolves to the handle for the loaded module  
-    /// Makes no attempt to fully resolve the event loop - call [`Runtime::await_event_loop`]
-    /// to resolve background tasks and async listeners
+    /// See [`Runtime::import_crypto_key`]
     ///
-    /// # Arguments
-    /// * `module` - A `Module` object containing the module's filename and contents.
+    /// # Errors
+    /// Will return an error if the key material is invalid, or does not match the algorithm or usages
+    #[cfg(feature = "crypto")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "crypto")))]
+    pub async fn import_crypto_key_async(
+        &mut self,
+        material: &crate::KeyMaterial,
+        algorithm: &serde_json::Value,
+        extractable: bool,
+        usages: &[&str],
+    ) -> Result<crate::js_value::Value, Error> {
+        let args = (material, algorithm, extractable, usages);
+        self.call_function_async(None, "__rustyscript_import_crypto_key", &args)
+            .await
+    }
+
+    /// Exports a Web Crypto `CryptoKey`, such as one generated by a script, in the given format
     ///
-    /// # Returns
-    /// A `Result` containing a handle for the loaded module
-    /// or an error (`Error`) if there are issues with loading or executing the module
+    /// Only keys created as extractable can be exported
     ///
     /// # Errors
-    /// Can fail if the module cannot be loaded, or execution fails
-    ///
-    /// See [`Runtime::load_module`] for an example
-    pub async fn load_module_async(&mut self, module: &Module) -> Result<ModuleHandle, Error> {
-        self.inner.load_modules(None, vec![module]).await
+    /// Will return an error if the value is not a `CryptoKey`, the key is not extractable,
+    /// or the key cannot be represented in the format
+    #[cfg(feature = "crypto")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "crypto")))]
+    pub fn export_crypto_key(
+        &mut self,
+        key: &crate::js_value::Value,
+        format: crate::KeyFormat,
+    ) -> Result<crate::KeyMaterial, Error> {
+        self.block_on(|runtime| async move { runtime.export_crypto_key_async(key, format).await })
     }
 
-    /// Executes the given module, and returns a handle allowing you to extract values
-    /// and call functions.
+    /// Exports a Web Crypto `CryptoKey`, such as one generated by a script, in the given format
     ///
-    /// Blocks until all modules have been executed AND the event loop has fully resolved  
-    /// See [`Runtime::load_module_async`] for a non-blocking variant, or use with async
-    /// background tasks
+    /// See [`Runtime::export_crypto_key`]
     ///
-    /// This will load 'module' as the main module, and the others as side-modules.  
-    /// Only one main module can be loaded per runtime
+    /// # Errors
+    /// Will return an error if the value is not a `CryptoKey`, the key is not extractable,
+    /// or the key cannot be represented in the format
+    #[cfg(feature = "crypto")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "crypto")))]
+    pub async fn export_crypto_key_async(
+        &mut self,
+        key: &crate::js_value::Value,
+        format: crate::KeyFormat,
+    ) -> Result<crate::KeyMaterial, Error> {
+        self.call_function_async(None, "__rustyscript_export_crypto_key", &(key, format))
+            .await
+    }
+
+    /// Connects `reader` as the stdin of the runtime's scripts, in place of the process stdin (`io` crate feature)
     ///
-    /// # Arguments
-    /// * `module` - A `Module` object containing the module's filename and contents.
-    /// * `side_modules` - A set of additional modules to be loaded into memory for use
+    /// Scripts read it through `Deno.stdin`, and see the end of their input once `reader` is exhausted
+    /// For input pushed by the host, see [`Runtime::write_stdin`]
     ///
-    /// # Returns
-    /// A `Result` containing a handle for the loaded module
-    /// or an error (`Error`) if there are issues with loading or executing the module
+    /// # Errors
+    /// Will return an error if the runtime was created without stdio, see [`crate::ExtensionOptions::io_pipes`]
+    #[cfg(feature = "io")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "io")))]
+    pub fn set_stdin(&mut self, reader: impl tokio::io::AsyncRead + 'static) -> Result<(), Error> {
+        use crate::ext::io::{HostStdinResource, STDIN_RID};
+
+        let state = self.deno_runtime().op_state();
+        let mut state = state.try_borrow_mut()?;
+        if !state.resource_table.has(STDIN_RID) {
+            return Err(Error::Runtime("The runtime has no stdin".to_string()));
+        }
+
+        // Writers for the previous stdin are closed
+        state.try_take::<crate::StdinWriter>();
+        state
+            .resource_table
+            .replace(STDIN_RID, HostStdinResource::new(reader));
+        Ok(())
+    }
+
+    /// Returns a writer feeding the stdin of the runtime's scripts (`io` crate feature)
+    ///
+    /// The first call replaces the process stdin - later calls return writers for the same input
+    /// The writer is `Send`, so input can be fed while the runtime is busy with a call
     ///
     /// # Errors
-    /// Can fail if the module cannot be loaded, or execution fails
+    /// Will return an error if the runtime was created without stdio, see [`crate::ExtensionOptions::io_pipes`]
     ///
     /// # Example
-    ///
     /// ```rust
-    /// // Create a module with filename and contents
-    /// use rustyscript::{Runtime, Module, Error};
+    /// use rustyscript::{json_args, Runtime, Module, Error};
     ///
     /// # fn main() -> Result<(), Error> {
     /// let mut runtime = Runtime::new(Default::default())?;
-    /// let module = Module::new("test.js", "export default () => 'test'");
-    /// runtime.load_modules(&module, vec![]);
+    /// let module = Module::new("test.js", "export const read = () => new Response(Deno.stdin.readable).text();");
+    /// let handle = runtime.load_module(&module)?;
+    ///
+    /// runtime.write_stdin("hello")?;
+    /// runtime.close_stdin();
+    ///
+    /// let input: String = runtime.call_function(Some(&handle), "read", json_args!())?;
+    /// assert_eq!(input, "hello");
     /// # Ok(())
     /// # }
     /// ```
-    pub fn load_modules(
-        &mut self,
-        module: &Module,
-        side_modules: Vec<&Module>,
-    ) -> Result<ModuleHandle, Error> {
-        self.block_on(move |runtime| async move {
-            let handle = runtime.load_modules_async(module, side_modules).await;
-            runtime
-                .await_event_loop(PollEventLoopOptions::default(), None)
-                .await?;
-            handle
-        })
+    #[cfg(feature = "io")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "io")))]
+    pub fn stdin_writer(&mut self) -> Result<crate::StdinWriter, Error> {
+        let state = self.deno_runtime().op_state();
+        let existing = state
+            .try_borrow_mut()?
+            .try_borrow::<crate::StdinWriter>()
+            .cloned();
+        if let Some(writer) = existing {
+            return Ok(writer);
+        }
+
+        let (writer, reader) = crate::StdinWriter::channel();
+        self.set_stdin(reader)?;
+        state.try_borrow_mut()?.put(writer.clone());
+        Ok(writer)
     }
 
-    /// Executes the given module, and returns a handle allowing you to extract values
-    /// and call functions.
+    /// Sends bytes to the stdin of the runtime's scripts, see [`Runtime::stdin_writer`] (`io` crate feature)
     ///
-    /// Returns a future that resolves to the handle for the loaded module  
-    /// Makes no attempt to resolve the event loop - call [`Runtime::await_event_loop`] to
-    /// resolve background tasks and async listeners
+    /// # Errors
+    /// Will return an error if the runtime was created without stdio, see [`crate::ExtensionOptions::io_pipes`]
+    #[cfg(feature = "io")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "io")))]
+    pub fn write_stdin(&mut self, bytes: impl Into<Vec<u8>>) -> Result<(), Error> {
+        self.stdin_writer()?.write(bytes)
+    }
+
+    /// Ends the input written with [`Runtime::write_stdin`] (`io` crate feature)
+    ///
+    /// Scripts see the end of their input once every [`crate::StdinWriter`] is dropped
+    #[cfg(feature = "io")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "io")))]
+    pub fn close_stdin(&mut self) {
+        let state = self.deno_runtime().op_state();
+        if let Ok(mut state) = state.try_borrow_mut() {
+            state.try_take::<crate::StdinWriter>();
+        }
+    }
+
+    /// Bundles the data persisted by the runtime's scripts into a single archive
     ///
-    /// This will load 'module' as the main module, and the others as side-modules.  
-    /// Only one main module can be loaded per runtime
+    /// Includes the files of every configured storage directory - `localStorage`, local `Deno.openKv` databases,
+    /// and a sqlite cache if [`crate::ExtensionOptions::cache_storage_dir`] is set
+    /// See [`crate::StateArchive`] for details
     ///
-    /// See [`Runtime::load_modules`] for an example
+    /// The stores are sqlite databases, so this should be called while scripts are not writing to them
     ///
-    /// # Arguments
-    /// * `module` - A `Module` object containing the module's filename and contents.
-    /// * `side_modules` - A set of additional modules to be loaded into memory for use
+    /// # Errors
+    /// Will return an error if a storage directory cannot be read
+    pub fn export_state(&self) -> Result<crate::StateArchive, Error> {
+        let mut archive = crate::StateArchive::new();
+        for (label, dir) in &self.inner.storage_dirs {
+            archive.add_dir(label, dir)?;
+        }
+        Ok(archive)
+    }
+
+    /// Creates a new runtime, after restoring an archive from [`Runtime::export_state`] into its storage directories
     ///
-    /// # Returns
-    /// A `Result` containing a handle for the loaded main module, or the last side-module
-    /// or an error (`Error`) if there are issues with loading or executing the modules
+    /// The files are written before the runtime is created, since the stores cannot be replaced while open
+    /// Stores without a directory configured in the options are skipped
     ///
     /// # Errors
-    /// Can fail if the modules cannot be loaded, or execution fails
-    pub async fn load_modules_async(
-        &mut self,
-        module: &Module,
-        side_modules: Vec<&Module>,
-    ) -> Result<ModuleHandle, Error> {
-        self.inner.load_modules(Some(module), side_modules).await
+    /// Will return an error if the archive cannot be restored, or the runtime cannot be created
+    pub fn import_state(
+        archive: &crate::StateArchive,
+        options: RuntimeOptions,
+    ) -> Result<Self, Error> {
+        archive.restore(&options)?;
+        Self::new(options)
     }
 
-    /// Executes the entrypoint function of a module within the Deno runtime.
+    /// Returns statistics about the remote modules fetched by this runtime so far (`url_import` crate feature)
     ///
-    /// Blocks until:
-    /// - The event loop is resolved, and
-    /// - If the value is a promise, the promise is resolved
+    /// Includes the number of modules fetched, their total size, and how long fetching took
+    /// Independent imports are fetched concurrently, bounded by [`RuntimeOptions::max_concurrent_fetches`]
+    #[cfg(feature = "url_import")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "url_import")))]
+    #[must_use]
+    pub fn fetch_stats(&self) -> crate::module_loader::FetchStats {
+        self.inner.module_loader.fetch_stats()
+    }
+
+    /// Returns every module loaded by this runtime so far, and where its code came from
+    ///
+    /// Includes modules loaded from rust, and everything they imported - from files, remote URLs, or providers  
+    /// See [`crate::module_loader::ModuleGraph::report`] to generate a provenance report for auditing
+    #[must_use]
+    pub fn module_graph(&self) -> crate::module_loader::ModuleGraph {
+        self.inner.module_loader.module_graph()
+    }
+
+    /// Translates a position in a loaded module's transpiled code back to its original source, such as a typescript file
+    ///
+    /// Allows hosts to map locations from stack traces collected elsewhere, such as in logs, using the runtime's source maps  
+    /// Lines and columns start at 1, as in javascript stack traces
     ///
     /// # Arguments
-    /// * `module_context` - A handle returned by loading a module into the runtime
+    /// * `specifier` - The module's URL as it appears in stack traces, or a path relative to the runtime's working directory
+    /// * `line` - Line number in the transpiled code
+    /// * `column` - Column number in the transpiled code
     ///
     /// # Returns
-    /// A `Result` containing the deserialized result of the entrypoint execution (`T`)  
-    /// if successful, or an error (`Error`) if the entrypoint is missing, the execution fails,
-    /// or the result cannot be deserialized.
-    ///
-    /// # Errors
-    /// Can fail if the module cannot be loaded, if the entrypoint is missing, if the execution fails,  
-    /// Or if the result cannot be deserialized into the requested type
+    /// The original location, or `None` if the module has not been loaded, was not transpiled,
+    /// or the position has no mapping
     ///
     /// # Example
-    ///
     /// ```rust
-    /// use rustyscript::{json_args, Runtime, Module, Error};
+    /// use rustyscript::{ Runtime, Module, Error };
     ///
     /// # fn main() -> Result<(), Error> {
     /// let mut runtime = Runtime::new(Default::default())?;
-    /// let module = Module::new("test.js", "export default () => 'test'");
-    /// let module = runtime.load_module(&module)?;
+    /// let module = Module::new("test.ts", "type N = number;\nexport const f = (n: N): N => n;");
+    /// runtime.load_module(&module)?;
     ///
-    /// // Run the entrypoint and handle the result
-    /// let value: String = runtime.call_entrypoint(&module, json_args!())?;
+    /// let location = runtime.map_location("test.ts", 1, 1).expect("No mapping found");
+    /// assert_eq!(location.line, 2);
     /// # Ok(())
     /// # }
     /// ```
-    pub fn call_entrypoint<T>(
-        &mut self,
-        module_context: &ModuleHandle,
-        args: &impl serde::ser::Serialize,
-    ) -> Result<T, Error>
-    where
-        T: deno_core::serde::de::DeserializeOwned,
-    {
-        self.block_on(
-            |runtime| async move { runtime.call_entrypoint_async(module_context, args).await },
-        )
+    #[must_use]
+    pub fn map_location(
+        &self,
+        specifier: &str,
+        line: u32,
+        column: u32,
+    ) -> Option<crate::module_loader::OriginalLocation> {
+        let specifier = if deno_core::specifier_has_uri_scheme(specifier) {
+            specifier.to_string()
+        } else {
+            self.inner
+                .module_specifier(Path::new(specifier))
+                .ok()?
+                .to_string()
+        };
+
+        self.inner
+            .module_loader
+            .map_location(&specifier, line, column)
     }
 
-    /// Executes the entrypoint function of a module within the Deno runtime.
+    /// Register a conversion between a rust type and instances of a javascript class
+    /// - Arguments wrapped in [`crate::js_value::Hooked`] are converted into instances of the class
+    /// - Instances of the class are converted to the serialized form of the rust type when decoding values
     ///
-    /// Returns a future that resolves when:
-    /// - The event loop is resolved, and
-    /// - If the value is a promise, the promise is resolved
+    /// Registering a second hook for the same rust type replaces the first
     ///
-    /// Note that synchronous functions are run synchronously. Returned promises will be run asynchronously, however.
+    /// # Errors
+    /// Will return an error if any of the hook's expressions cannot be evaluated,
+    /// or do not result in functions
     ///
-    /// See [`Runtime::call_entrypoint`] for an example
+    /// ```rust
+    /// use rustyscript::{ Runtime, Module, js_value::{ Hooked, TypeHook } };
     ///
-    /// # Arguments
-    /// * `module_context` - A handle returned by loading a module into the runtime
+    /// # fn main() -> Result<(), rustyscript::Error> {
+    /// let module = Module::new("test.js", " export const year = (d) => d.getUTCFullYear(); ");
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// let handle = runtime.load_module(&module)?;
     ///
-    /// # Returns
-    /// A `Result` containing the deserialized result of the entrypoint execution (`T`)  
-    /// if successful, or an error (`Error`) if the entrypoint is missing, the execution fails,
-    /// or the result cannot be deserialized.
+    /// // Exchange ISO 8601 strings as `Date` objects
+    /// runtime.register_type_hook::<String>(TypeHook::new(
+    ///     "Date",
+    ///     "(s) => new Date(s)",
+    ///     "(d) => d.toISOString()",
+    /// ))?;
+    ///
+    /// let date = Hooked("2021-06-01T00:00:00.000Z".to_string());
+    /// let year: usize = runtime.call_function(Some(&handle), "year", &(date,))?;
+    /// assert_eq!(year, 2021);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn register_type_hook<T>(&mut self, hook: crate::js_value::TypeHook) -> Result<(), Error>
+    where
+        T: 'static,
+    {
+        self.inner.register_type_hook::<T>(&hook)
+    }
+
+    /// Register a non-blocking rust function to be callable from JS
+    /// - The [`crate::async_callback`] macro can be used to simplify this process
+    ///
+    /// Scripts that need the result synchronously can call it with `rustyscript.blocking_functions.name(...)`,
+    /// or `rustyscript.call_blocking(name, args, deadline_ms)`, which pause the event loop until the future resolves
+    /// - The call fails if it does not complete within the deadline, 5 seconds by default
+    /// - Timers and IO only make progress while blocking on a multi-threaded tokio runtime (see [`Runtime::with_tokio_runtime`])
+    ///   On a current-thread runtime, such a call holds the thread until the deadline passes - and since scripts choose the deadline,
+    ///   the runtime's own timeouts cannot interrupt it
     ///
     /// # Errors
-    /// Can fail if the module cannot be loaded, if the entrypoint is missing, if the execution fails,  
-    /// Or if the result cannot be deserialized into the requested type
-    pub async fn call_entrypoint_async<T>(
-        &mut self,
-        module_context: &ModuleHandle,
-        args: &impl serde::ser::Serialize,
-    ) -> Result<T, Error>
+    /// Since this function borrows the state, it can fail if the state cannot be borrowed mutably
+    ///
+    /// ```rust
+    /// use rustyscript::{ Runtime, Module, serde_json::Value, async_callback, Error };
+    ///
+    /// # fn main() -> Result<(), rustyscript::Error> {
+    /// let module = Module::new("test.js", " rustyscript.async_functions.add(1, 2); ");
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// runtime.register_async_function("add", async_callback!(
+    ///     |a: i64, b: i64| async move {
+    ///         Ok::<i64, Error>(a + b)
+    ///     }
+    /// ))?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn register_async_function<F>(&mut self, name: &str, callback: F) -> Result<(), Error>
     where
-        T: deno_core::serde::de::DeserializeOwned,
+        F: RsAsyncFunction,
     {
-        if let Some(entrypoint) = module_context.entrypoint() {
-            let result = self
-                .inner
-                .call_function_by_ref(Some(module_context), entrypoint, args)?;
-            let result = self.inner.resolve_with_event_loop(result).await?;
-            self.inner.decode_value(result)
-        } else {
-            Err(Error::MissingEntrypoint(module_context.module().clone()))
-        }
+        self.inner.register_async_function(name, callback)
     }
 
-    /// Executes the entrypoint function of a module within the Deno runtime.
+    /// Register a cancellable, non-blocking rust function to be callable from JS
+    /// - The future is spawned on the runtime's tokio runtime, so it must be `Send`
+    /// - The callback receives a [`CallbackContext`], with a handle to that tokio runtime and a cancellation token
     ///
-    /// Will not attempt to resolve promises, or run the event loop  
-    /// Promises can be returned by specifying the return type as [`crate::js_value::Promise`]  
-    /// The event loop should be run using [`Runtime::await_event_loop`]
+    /// The token is cancelled when the blocking call that triggered the callback times out or exhausts the heap,
+    /// or when the runtime is dropped. Once cancelled, the spawned task is aborted and the call fails
     ///
-    /// # Arguments
-    /// * `module_context` - A handle returned by loading a module into the runtime
+    /// Calls made through the `_async` and `_immediate` methods are not covered: they have no timeout of their own,
+    /// and dropping their future does not cancel the token - the callback runs until it finishes, or the runtime is dropped
     ///
-    /// # Returns
-    /// A `Result` containing the deserialized result of the entrypoint execution (`T`)
-    /// if successful, or an error (`Error`) if the entrypoint is missing, the execution fails,
-    /// or the result cannot be deserialized.
+    /// Calls from JS are identical to those made to [`Runtime::register_async_function`]
     ///
     /// # Errors
-    /// Can fail if the module cannot be loaded, if the entrypoint is missing, if the execution fails,
-    /// Or if the result cannot be deserialized into the requested type
+    /// Since this function borrows the state, it can fail if the state cannot be borrowed mutably
     ///
-    /// # Example
+    /// ```rust
+    /// use rustyscript::{ Runtime, Module, serde_json::Value, tokio };
+    ///
+    /// # fn main() -> Result<(), rustyscript::Error> {
+    /// let module = Module::new("test.js", " rustyscript.async_functions.fetch_data(); ");
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// runtime.register_cancellable_function("fetch_data", |args, context| async move {
+    ///     tokio::select! {
+    ///         () = context.cancellation_token().cancelled() => Ok(Value::Null),
+    ///         () = tokio::time::sleep(std::time::Duration::from_millis(10)) => Ok(Value::Bool(true)),
+    ///     }
+    /// })?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn register_cancellable_function<F, Fut>(
+        &mut self,
+        name: &str,
+        callback: F,
+    ) -> Result<(), Error>
+    where
+        F: Fn(Vec<serde_json::Value>, CallbackContext) -> Fut + 'static,
+        Fut: std::future::Future<Output = Result<serde_json::Value, Error>> + Send + 'static,
+    {
+        let handle = self.tokio.tokio_runtime().handle().clone();
+        let cancellation = self.tokio.callback_cancellation();
+
+        self.inner.register_async_function(name, move |args| {
+            let token = cancellation.token();
+            let context = CallbackContext::new(handle.clone(), token.clone());
+            let task = handle.spawn(callback(args, context));
+
+            Box::pin(async move {
+                let abort = task.abort_handle();
+                tokio::select! {
+                    result = task => result?,
+                    () = token.cancelled() => {
+                        abort.abort();
+                        Err(Error::Runtime("Async function was cancelled".to_string()))
+                    }
+                }
+            })
+        })?;
+
+        // Bound to this runtime's cancellation, so it cannot be shared with a fork
+        self.inner.function_definitions.remove(name);
+        Ok(())
+    }
+
+    /// Register a non-blocking rust function that reports progress to the calling script
+    /// - The callback receives a [`crate::ProgressSender`], used to send progress events before the final result
+    ///
+    /// Called from JS using `rustyscript.progress_functions.name(...args)`, which returns a promise for the final result
+    /// The promise also exposes the progress events:
+    /// - `call.onProgress(listener)` calls the listener with each event
+    /// - `for await (const event of call)` iterates over the events, until the function completes
+    ///
+    /// # Errors
+    /// Since this function borrows the state, it can fail if the state cannot be borrowed mutably
     ///
     /// ```rust
-    /// use rustyscript::{json_args, Runtime, Module, Error};
+    /// use rustyscript::{ Runtime, Module, serde_json::Value };
+    ///
+    /// # fn main() -> Result<(), rustyscript::Error> {
+    /// let module = Module::new("test.js", "
+    ///     const call = rustyscript.progress_functions.process(3);
+    ///     for await (const step of call) {
+    ///         console.log(`Processed ${step} files`);
+    ///     }
+    ///     console.log(await call);
+    /// ");
     ///
-    /// # fn main() -> Result<(), Error> {
     /// let mut runtime = Runtime::new(Default::default())?;
-    /// let module = Module::new("test.js", "export default () => 'test'");
-    /// let module = runtime.load_module(&module)?;
+    /// runtime.register_progress_function("process", |args, progress| async move {
+    ///     let count = args[0].as_u64().unwrap_or_default();
+    ///     for i in 1..=count {
+    ///         progress.send(&i)?;
+    ///     }
+    ///     Ok(Value::from("done"))
+    /// })?;
     ///
-    /// // Run the entrypoint and handle the result
-    /// let value: String = runtime.call_entrypoint_immediate(&module, json_args!())?;
+    /// runtime.load_module(&module)?;
     /// # Ok(())
     /// # }
     /// ```
-    pub fn call_entrypoint_immediate<T>(
+    pub fn register_progress_function<F, Fut>(
         &mut self,
-        module_context: &ModuleHandle,
-        args: &impl serde::ser::Serialize,
-    ) -> Result<T, Error>
+        name: &str,
+        callback: F,
+    ) -> Result<(), Error>
     where
-        T: deno_core::serde::de::DeserializeOwned,
+        F: Fn(Vec<serde_json::Value>, crate::ProgressSender) -> Fut + 'static,
+        Fut: std::future::Future<Output = Result<serde_json::Value, Error>> + 'static,
     {
-        if let Some(entrypoint) = module_context.entrypoint() {
-            let result = self.block_on(|runtime| async move {
-                runtime
-                    .inner
-                    .call_function_by_ref(Some(module_context), entrypoint, args)
-            })?;
-            self.inner.decode_value(result)
-        } else {
-            Err(Error::MissingEntrypoint(module_context.module().clone()))
-        }
+        self.inner.register_progress_function(
+            name,
+            Box::new(move |args, progress| Box::pin(callback(args, progress))),
+        )
     }
 
-    /// Loads a module into a new runtime, executes the entry function and returns the
-    /// result of the module's execution, deserialized into the specified Rust type (`T`).
+    /// Register a rust function that can call back into the runtime while it runs
+    /// - The callback receives a [`crate::ReentrantScope`], used to evaluate code or call script functions
+    /// - Functions passed as arguments are `null` in the JSON arguments, but can be read with [`crate::ReentrantScope::argument`]
+    ///
+    /// Called from JS using `rustyscript.reentrant_functions.name(...args)`  
+    /// Everything runs synchronously, on top of the calling script's stack
+    ///
+    /// # Errors
+    /// Since this function borrows the state, it can fail if the state cannot be borrowed mutably
+    ///
+    /// ```rust
+    /// use rustyscript::{ js_value::Function, json_args, Runtime, serde_json::Value };
+    ///
+    /// # fn main() -> Result<(), rustyscript::Error> {
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// runtime.register_reentrant_function("retry", |scope, args| {
+    ///     let attempt: Function = scope.argument(0)?;
+    ///     let limit = args[1].as_u64().unwrap_or(3);
+    ///     for i in 0..limit {
+    ///         let value: Value = scope.call(&attempt, &json_args!(i))?;
+    ///         if !value.is_null() {
+    ///             return Ok(value);
+    ///         }
+    ///     }
+    ///     Ok(Value::Null)
+    /// })?;
+    ///
+    /// let value: u64 = runtime.eval("rustyscript.reentrant_functions.retry((i) => i == 2 ? i : null, 5)")?;
+    /// assert_eq!(value, 2);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn register_reentrant_function<F>(&mut self, name: &str, callback: F) -> Result<(), Error>
+    where
+        F: Fn(&mut crate::ReentrantScope, &[serde_json::Value]) -> Result<serde_json::Value, Error>
+            + 'static,
+    {
+        self.inner
+            .register_reentrant_function(name, std::rc::Rc::new(callback))
+    }
+
+    /// Evaluate a piece of non-ECMAScript-module JavaScript code  
+    /// The expression is evaluated in the global context, so changes persist
+    ///
+    /// Blocks on promise resolution, and runs the event loop to completion
+    ///
+    /// Asynchronous code is supported, partially
+    /// - Top-level await is not supported
+    /// - The event loop will be run to completion after the expression is evaluated
+    ///
+    /// For top-level await support, use one of:
+    /// - `call_function_async`
+    /// - `call_stored_function_async`
+    /// - `load_module_async`
+    /// - `load_modules_async`
+    ///
+    /// Or any of the `_immmediate` variants, paired with [`crate::js_value::Promise`]
     ///
     /// # Arguments
-    /// * `module` - A `Module` object containing the module's filename and contents.
-    /// * `side_modules` - A set of additional modules to be loaded into memory for use
-    /// * `runtime_options` - Options for the creation of the runtime
-    /// * `entrypoint_args` - Arguments to pass to the entrypoint function
+    /// * `expr` - A string representing the JavaScript expression to evaluate
     ///
     /// # Returns
-    /// A `Result` containing the deserialized result of the entrypoint execution (`T`)  
-    /// if successful, or an error (`Error`) if the entrypoint is missing, the execution fails,
-    /// or the result cannot be deserialized.
+    /// A `Result` containing the deserialized result of the expression (`T`)  
+    /// or an error (`Error`) if the expression cannot be evaluated or if the
+    /// result cannot be deserialized.
     ///
     /// # Errors
-    /// Can fail if the module cannot be loaded, if the entrypoint is missing, if the execution fails,  
-    /// Or if the result cannot be deserialized into the requested type
+    /// Can fail if the expression cannot be evaluated, or if the result cannot be deserialized into the requested type
     ///
     /// # Example
-    ///
     /// ```rust
-    /// // Create a module with filename and contents
-    /// use rustyscript::{json_args, Runtime, Module, Error};
+    /// use rustyscript::{ Runtime, Error };
     ///
     /// # fn main() -> Result<(), Error> {
-    /// let module = Module::new("test.js", "export default () => 2");
-    /// let value: usize = Runtime::execute_module(&module, vec![], Default::default(), json_args!())?;
+    /// let mut runtime = Runtime::new(Default::default())?;
+    ///
+    /// let value: u32 = runtime.eval("2 + 2")?;
+    /// assert_eq!(4, value);
+    ///
+    /// let value: String = runtime.eval("new Promise(resolve => resolve('test'))")?;
+    /// assert_eq!("test", value);
+    ///
     /// # Ok(())
     /// # }
     /// ```
-    pub fn execute_module<T>(
-        module: &Module,
-        side_modules: Vec<&Module>,
-        runtime_options: RuntimeOptions,
-        entrypoint_args: &impl serde::ser::Serialize,
-    ) -> Result<T, Error>
+    pub fn eval<T>(&mut self, expr: impl ToString) -> Result<T, Error>
     where
-        T: deno_core::serde::de::DeserializeOwned,
+        T: serde::de::DeserializeOwned,
     {
-        let mut runtime = Runtime::new(runtime_options)?;
-        let module = runtime.load_modules(module, side_modules)?;
-        let value: T = runtime.call_entrypoint(&module, entrypoint_args)?;
-        Ok(value)
+        self.block_on(|runtime| async move { runtime.eval_async(expr).await })
     }
-}
 
-impl AsyncBridgeExt for Runtime {
-    fn bridge(&self) -> &AsyncBridge {
-        &self.tokio
+    /// Evaluate a piece of non-ECMAScript-module JavaScript code  
+    /// The expression is evaluated in the global context, so changes persist
+    ///
+    /// Awaits promise resolution, and runs the event loop to completion
+    ///
+    /// Asynchronous code is supported, partially
+    /// - Top-level await is not supported
+    /// - The event loop will be run to completion after the expression is evaluated
+    ///
+    /// For top-level await support, use one of:
+    /// - `call_function_async`
+    /// - `call_stored_function_async`
+    /// - `load_module_async`
+    /// - `load_modules_async`
+    ///
+    /// Or any of the `_immmediate` variants, paired with [`crate::js_value::Promise`]
+    ///
+    /// # Arguments
+    /// * `expr` - A string representing the JavaScript expression to evaluate
+    ///
+    /// # Returns
+    /// A `Result` containing the deserialized result of the expression (`T`)  
+    /// or an error (`Error`) if the expression cannot be evaluated or if the
+    /// result cannot be deserialized.
+    ///
+    /// # Errors
+    /// Can fail if the expression cannot be evaluated, or if the result cannot be deserialized into the requested type
+    ///
+    /// # Example
+    /// For an example, see [`Runtime::eval`]
+    pub async fn eval_async<T>(&mut self, expr: impl ToString) -> Result<T, Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let result = self.inner.eval(expr.to_string()).await?;
+        let result = self.inner.resolve_with_event_loop(result).await?;
+        self.inner.decode_value(result)
     }
-}
-
-#[cfg(test)]
-mod test_runtime {
-    use crate::json_args;
-    use std::time::Duration;
-
-    use super::*;
-    use deno_core::extension;
 
+    /// Evaluate a piece of non-ECMAScript-module JavaScript code  
+    /// The expression is evaluated in the global context, so changes persist
+    ///
+    /// Does not await promise resolution, or run the event loop  
+    /// Promises can be returned by specifying the return type as [`crate::js_value::Promise`]  
+    /// The event loop should be run using [`Runtime::await_event_loop`]
+    ///
+    /// Note that this function needs to be async because calls to `setTimeout` must be evaluated from within an async runtime.
+    ///
+    /// Asynchronous code is supported, partially
+    /// - Top-level await is not supported
+    ///
+    /// For top-level await support, use one of:
+    /// - `call_function_async`
+    /// - `call_stored_function_async`
+    /// - `load_module_async`
+    /// - `load_modules_async`
+    ///
+    /// Or any of the `_immmediate` variants, paired with [`crate::js_value::Promise`]
+    ///
+    /// # Arguments
+    /// * `expr` - A string representing the JavaScript expression to evaluate
+    ///
+    /// # Returns
+    /// A `Result` containing the deserialized result of the expression (`T`)  
+    /// or an error (`Error`) if the expression cannot be evaluated or if the
+    /// result cannot be deserialized.
+    ///
+    /// # Errors
+    /// Can fail if the expression cannot be evaluated, or if the result cannot be deserialized into the requested type
+    ///
+    /// # Example
+    /// For an example, see [`Runtime::eval`]
+    pub async fn eval_immediate<T>(&mut self, expr: impl ToString) -> Result<T, Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let result = self.inner.eval(expr.to_string()).await?;
+        self.inner.decode_value(result)
+    }
+
+    /// Calls a stored javascript function and deserializes its return value.
+    ///
+    /// Returns a future that resolves when:
+    /// - The event loop is resolved, and
+    /// - If the value is a promise, the promise is resolved
+    ///
+    /// See [`Runtime::call_function`] for an example
+    ///
+    /// Note that synchronous functions are run synchronously. Returned promises will be run asynchronously, however.
+    ///
+    /// # Arguments
+    /// * `module_context` - Optional handle to a module providing global context for the function
+    /// * `function` - A The function object
+    /// * `args` - The arguments to pass to the function
+    ///
+    /// # Returns
+    /// A `Result` containing the deserialized result of the function call (`T`)  
+    /// or an error (`Error`) if there are issues with calling the function,
+    /// or if the result cannot be deserialized.
+    ///
+    /// # Errors
+    /// Can fail if there are issues with calling the function, or if the result cannot be deserialized into the requested type
+    pub async fn call_stored_function_async<T>(
+        &mut self,
+        module_context: Option<&ModuleHandle>,
+        function: &Function,
+        args: &impl serde::ser::Serialize,
+    ) -> Result<T, Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let scope = self.enter_capabilities(module_context);
+        let result = async {
+            let function = function.as_global(&mut self.deno_runtime().handle_scope());
+            let result = self
+                .inner
+                .call_function_by_ref(module_context, &function, args)?;
+            let result = scope
+                .limit(self.inner.resolve_with_event_loop(result))
+                .await?;
+            self.inner.decode_value(result)
+        }
+        .await;
+        self.exit_capabilities(scope, result)
+    }
+
+    /// Exchanges a token, received in place of a function passed to a registered rust function, for the function itself
+    ///
+    /// Each token can only be taken once, and only by the call it was passed to - tokens forged by a script are refused
+    /// At most 1024 functions are kept at once, after which the oldest untaken functions are released
+    ///
+    /// # Errors
+    /// Will return an error if the token was already taken
+    ///
+    /// ```rust
+    /// use rustyscript::{ js_value::FunctionToken, json_args, Runtime, serde_json::Value };
+    /// use std::{ cell::RefCell, rc::Rc };
+    ///
+    /// # fn main() -> Result<(), rustyscript::Error> {
+    /// let handlers = Rc::new(RefCell::new(Vec::new()));
+    /// let handlers_ = handlers.clone();
+    ///
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// runtime.register_function("on_message", move |args| {
+    ///     handlers_.borrow_mut().extend(args.first().and_then(FunctionToken::from_value));
+    ///     Ok(Value::Null)
+    /// })?;
+    /// runtime.eval::<Value>("rustyscript.functions.on_message((msg) => msg.toUpperCase())")?;
+    ///
+    /// // Later, once the runtime is no longer busy
+    /// for token in handlers.take() {
+    ///     let handler = runtime.take_function(token)?;
+    ///     let reply: String = handler.call(&mut runtime, None, &json_args!("hello"))?;
+    ///     assert_eq!(reply, "HELLO");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn take_function(
+        &mut self,
+        token: crate::js_value::FunctionToken,
+    ) -> Result<Function, Error> {
+        let function = {
+            let state = self.deno_runtime().op_state();
+            let mut state = state.try_borrow_mut()?;
+            state
+                .try_borrow_mut::<crate::ext::rustyscript::CapturedFunctions>()
+                .and_then(|captured| captured.take(token.id()))
+        };
+        let function = function.ok_or_else(|| {
+            Error::Runtime("The function was already taken from its token, or released".to_string())
+        })?;
+
+        let mut scope = self.deno_runtime().handle_scope();
+        Function::try_from_v8(&mut scope, function)
+    }
+
+    /// Calls a stored javascript function and deserializes its return value.
+    ///
+    /// Blocks until:
+    /// - The event loop is resolved, and
+    /// - If the value is a promise, the promise is resolved
+    ///
+    /// See [`Runtime::call_function`] for an example
+    ///
+    /// # Arguments
+    /// * `module_context` - Optional handle to a module providing global context for the function
+    /// * `function` - A The function object
+    /// * `args` - The arguments to pass to the function
+    ///
+    /// # Returns
+    /// A `Result` containing the deserialized result of the function call (`T`)  
+    /// or an error (`Error`) if there are issues with calling the function,
+    /// or if the result cannot be deserialized.
+    ///
+    /// # Errors
+    /// Can fail if there are issues with calling the function, or if the result cannot be deserialized into the requested type
+    pub fn call_stored_function<T>(
+        &mut self,
+        module_context: Option<&ModuleHandle>,
+        function: &Function,
+        args: &impl serde::ser::Serialize,
+    ) -> Result<T, Error>
+    where
+        T: deno_core::serde::de::DeserializeOwned,
+    {
+        self.block_on(|runtime| async move {
+            runtime
+                .call_stored_function_async(module_context, function, args)
+                .await
+        })
+    }
+
+    /// Calls a stored javascript function and deserializes its return value.
+    ///
+    /// Will not attempt to resolve promises, or run the event loop  
+    /// Promises can be returned by specifying the return type as [`crate::js_value::Promise`]  
+    /// The event loop should be run using [`Runtime::await_event_loop`]
+    ///
+    /// See [`Runtime::call_function`] for an example
+    ///
+    /// # Arguments
+    /// * `module_context` - Optional handle to a module providing global context for the function
+    /// * `function` - A The function object
+    /// * `args` - The arguments to pass to the function
+    ///
+    /// # Returns
+    /// A `Result` containing the deserialized result of the function call (`T`)  
+    /// or an error (`Error`) if there are issues with calling the function,
+    /// or if the result cannot be deserialized.
+    ///
+    /// # Errors
+    /// Can fail if there are issues with calling the function, or if the result cannot be deserialized into the requested type
+    pub fn call_stored_function_immediate<T>(
+        &mut self,
+        module_context: Option<&ModuleHandle>,
+        function: &Function,
+        args: &impl serde::ser::Serialize,
+    ) -> Result<T, Error>
+    where
+        T: deno_core::serde::de::DeserializeOwned,
+    {
+        let function = function.as_global(&mut self.deno_runtime().handle_scope());
+        let scope = self.enter_capabilities(module_context);
+        let result = self
+            .inner
+            .call_function_by_ref(module_context, &function, args)
+            .and_then(|result| self.inner.decode_value(result));
+        self.exit_capabilities(scope, result)
+    }
+
+    /// Calls a javascript function within the Deno runtime by its name and deserializes its return value.
+    ///
+    /// Returns a future that resolves when:
+    /// - The event loop is resolved, and
+    /// - If the value is a promise, the promise is resolved
+    ///
+    /// Note that synchronous functions are run synchronously. Returned promises will be run asynchronously, however.
+    ///
+    /// See [`Runtime::call_function`] for an example
+    ///
+    /// # Arguments
+    /// * `module_context` - Optional handle to a module to search - if None, or if the search fails, the global context is used
+    /// * `name` - A string representing the name of the javascript function to call.
+    /// * `args` - The arguments to pass to the function
+    ///
+    /// # Returns
+    /// A `Result` containing the deserialized result of the function call (`T`)  
+    /// or an error (`Error`) if the function cannot be found, if there are issues with
+    /// calling the function, or if the result cannot be deserialized.
+    ///
+    /// # Errors
+    /// Fails if the function cannot be found, if there are issues with calling the function,
+    /// Or if the result cannot be deserialized into the requested type
+    pub async fn call_function_async<T>(
+        &mut self,
+        module_context: Option<&ModuleHandle>,
+        name: &str,
+        args: &impl serde::ser::Serialize,
+    ) -> Result<T, Error>
+    where
+        T: deno_core::serde::de::DeserializeOwned,
+    {
+        let call = CallInfo {
+            module: module_context,
+            function: Some(name),
+        };
+        let started = self.inner.observers.before_call(&call);
+
+        let scope = self.enter_capabilities(module_context);
+        let result = async {
+            let function = self.inner.get_function_by_name(module_context, name)?;
+            let result = self
+                .inner
+                .call_function_by_ref(module_context, &function, args)?;
+            let result = scope
+                .limit(self.inner.resolve_with_event_loop(result))
+                .await?;
+            self.inner.decode_value(result)
+        }
+        .await;
+        let result = self.exit_capabilities(scope, result);
+
+        self.inner.observers.after_call(&call, started, &result);
+        result
+    }
+
+    /// Calls a javascript function within the Deno runtime by its name and deserializes its return value.
+    ///
+    /// Blocks until:
+    /// - The event loop is resolved, and
+    /// - If the value is a promise, the promise is resolved
+    ///
+    /// # Arguments
+    /// * `module_context` - Optional handle to a module to search - if None, or if the search fails, the global context is used
+    /// * `name` - A string representing the name of the javascript function to call.
+    /// * `args` - The arguments to pass to the function
+    ///
+    /// # Returns
+    /// A `Result` containing the deserialized result of the function call (`T`)  
+    /// or an error (`Error`) if the function cannot be found, if there are issues with
+    /// calling the function, or if the result cannot be deserialized.
+    ///
+    /// # Errors
+    /// Fails if the function cannot be found, if there are issues with calling the function,  
+    /// Or if the result cannot be deserialized into the requested type
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rustyscript::{ json_args, Runtime, Module, Error };
+    ///
+    /// # fn main() -> Result<(), Error> {
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// let module = Module::new("/path/to/module.js", "export function f() { return 2; };");
+    /// let module = runtime.load_module(&module)?;
+    /// let value: usize = runtime.call_function(Some(&module), "f", json_args!())?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn call_function<T>(
+        &mut self,
+        module_context: Option<&ModuleHandle>,
+        name: &str,
+        args: &impl serde::ser::Serialize,
+    ) -> Result<T, Error>
+    where
+        T: deno_core::serde::de::DeserializeOwned,
+    {
+        self.block_on(|runtime| async move {
+            runtime
+                .call_function_async(module_context, name, args)
+                .await
+        })
+    }
+
+    /// Calls a javascript function by its name, and serializes its return value to JSON with v8
+    ///
+    /// Blocks until the event loop is resolved, and the value resolved if it is a promise - as [`Runtime::call_function`]  
+    /// The [`crate::JsonBuffer`] returned can be decoded into types borrowing from it,
+    /// without building an intermediate `serde_json::Value`
+    ///
+    /// # Errors
+    /// Fails if the function cannot be found, if there are issues with calling the function,  
+    /// Or if the result cannot be serialized to JSON, such as a `BigInt`
+    ///
+    /// ```rust
+    /// use rustyscript::{ json_args, Runtime, Module, Error };
+    ///
+    /// # fn main() -> Result<(), Error> {
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// let module = Module::new("test.js", "export const names = () => ['ada', 'grace'];");
+    /// let module = runtime.load_module(&module)?;
+    ///
+    /// let buffer = runtime.call_function_json(Some(&module), "names", json_args!())?;
+    /// let names: Vec<&str> = buffer.decode()?;
+    /// assert_eq!(names, ["ada", "grace"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn call_function_json(
+        &mut self,
+        module_context: Option<&ModuleHandle>,
+        name: &str,
+        args: &impl serde::ser::Serialize,
+    ) -> Result<crate::JsonBuffer, Error> {
+        let value: crate::js_value::Value = self.call_function(module_context, name, args)?;
+        let mut scope = self.deno_runtime().handle_scope();
+        let value = deno_core::v8::Local::new(&mut scope, value.as_v8());
+        crate::JsonBuffer::from_v8(&mut scope, value)
+    }
+
+    /// Calls a pure javascript function by its name, reusing the result of an earlier call with the same arguments
+    ///
+    /// Behaves as [`Runtime::call_function`] the first time - the result is then kept, keyed by the module,
+    /// the function name, and the arguments serialized as JSON  
+    /// Errors are not cached, and results are discarded when the module is loaded again
+    ///
+    /// The function must be pure - side effects, and results depending on anything other than the arguments,
+    /// are not repeated for a cached call  
+    /// See [`RuntimeOptions::call_cache_capacity`]
+    ///
+    /// # Errors
+    /// Fails if the function cannot be found, if there are issues with calling the function,  
+    /// Or if the result cannot be deserialized into the requested type
+    ///
+    /// ```rust
+    /// use rustyscript::{ json_args, Runtime, Module, Error };
+    ///
+    /// # fn main() -> Result<(), Error> {
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// let module = Module::new("test.js", "export const fib = (n) => n < 2 ? n : fib(n - 1) + fib(n - 2);");
+    /// let module = runtime.load_module(&module)?;
+    ///
+    /// let value: u64 = runtime.call_cached(&module, "fib", json_args!(25))?;
+    /// let again: u64 = runtime.call_cached(&module, "fib", json_args!(25))?; // Not called again
+    /// assert_eq!(value, again);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn call_cached<T>(
+        &mut self,
+        module_context: &ModuleHandle,
+        name: &str,
+        args: &impl serde::ser::Serialize,
+    ) -> Result<T, Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let key = deno_core::serde_json::to_string(args)?;
+        let module = module_context.id();
+
+        let value = match self.inner.call_cache.get(module, name, &key) {
+            Some(value) => value,
+            None => {
+                let value: deno_core::serde_json::Value =
+                    self.call_function(Some(module_context), name, args)?;
+                self.inner
+                    .call_cache
+                    .insert(module, name, &key, value.clone());
+                value
+            }
+        };
+        Ok(deno_core::serde_json::from_value(value)?)
+    }
+
+    /// Discards every result kept by [`Runtime::call_cached`]
+    pub fn clear_call_cache(&mut self) {
+        self.inner.call_cache.clear();
+    }
+
+    /// Calls a javascript function within the Deno runtime by its name, applying limits to the call
+    ///
+    /// See [`Runtime::call_function_with_options`] for an example
+    ///
+    /// # Errors
+    /// Fails in the same cases as [`Runtime::call_function_async`],
+    /// or if the call exceeds one of the limits in `options`
+    pub async fn call_function_with_options_async<T>(
+        &mut self,
+        module_context: Option<&ModuleHandle>,
+        name: &str,
+        args: &impl serde::ser::Serialize,
+        options: &CallOptions,
+    ) -> Result<T, Error>
+    where
+        T: deno_core::serde::de::DeserializeOwned,
+    {
+        if options.max_cost.is_some() && !self.inner.metering {
+            return Err(Error::Runtime(
+                "CallOptions::max_cost requires RuntimeOptions::metering".to_string(),
+            ));
+        }
+
+        crate::metering::start(self, options.max_cost)?;
+        let guard = options.max_heap_growth.map(|limit| {
+            crate::heap_limit::HeapGrowthGuard::start(
+                self,
+                limit,
+                options.on_heap_growth_limit.clone(),
+            )
+        });
+        let mut result = self.call_function_async(module_context, name, args).await;
+        if let Some(guard) = guard {
+            result = guard.finish(result);
+        }
+        crate::metering::finish(self, options.max_cost, result)
+    }
+
+    /// Calls a javascript function within the Deno runtime by its name, applying limits to the call
+    ///
+    /// Behaves like [`Runtime::call_function`], and also resets the count returned by [`Runtime::execution_cost`]
+    /// so that it measures this call alone
+    ///
+    /// # Errors
+    /// Fails in the same cases as [`Runtime::call_function`],
+    /// or if the call exceeds one of the limits in `options`
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rustyscript::{ json_args, CallOptions, Error, Module, Runtime, RuntimeOptions };
+    ///
+    /// # fn main() -> Result<(), Error> {
+    /// let mut runtime = Runtime::new(RuntimeOptions {
+    ///     metering: true,
+    ///     ..Default::default()
+    /// })?;
+    /// let module = Module::new("test.js", "export const spin = (n) => { for (let i = 0; i < n; i++) {} };");
+    /// let module = runtime.load_module(&module)?;
+    ///
+    /// let options = CallOptions { max_cost: Some(1000), ..Default::default() };
+    /// runtime.call_function_with_options::<()>(Some(&module), "spin", json_args!(10), &options)?;
+    /// println!("cost: {}", runtime.execution_cost()?);
+    ///
+    /// let result = runtime.call_function_with_options::<()>(Some(&module), "spin", json_args!(10_000), &options);
+    /// assert!(matches!(result, Err(Error::CostExceeded { limit: 1000 })));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn call_function_with_options<T>(
+        &mut self,
+        module_context: Option<&ModuleHandle>,
+        name: &str,
+        args: &impl serde::ser::Serialize,
+        options: &CallOptions,
+    ) -> Result<T, Error>
+    where
+        T: deno_core::serde::de::DeserializeOwned,
+    {
+        self.block_on(|runtime| async move {
+            runtime
+                .call_function_with_options_async(module_context, name, args, options)
+                .await
+        })
+    }
+
+    /// Calls a javascript function within the Deno runtime by its name, recording what the call printed and cost
+    ///
+    /// Behaves like [`Runtime::call_function_async`], see [`Runtime::call_function_with_result`] for details
+    ///
+    /// # Errors
+    /// Fails in the same cases as [`Runtime::call_function_async`]
+    pub async fn call_function_with_result_async<T>(
+        &mut self,
+        module_context: Option<&ModuleHandle>,
+        name: &str,
+        args: &impl serde::ser::Serialize,
+    ) -> Result<crate::CallResult<T>, Error>
+    where
+        T: deno_core::serde::de::DeserializeOwned,
+    {
+        let recorder = crate::call_result::CallRecorder::start(self, self.inner.metering)?;
+        let result = self.call_function_async(module_context, name, args).await;
+        recorder.finish(self, result)
+    }
+
+    /// Calls a javascript function within the Deno runtime by its name, recording what the call printed and cost
+    ///
+    /// Behaves like [`Runtime::call_function`], but returns a [`crate::CallResult`] containing:
+    /// - The deserialized return value - use a tuple to receive several values returned as an array
+    /// - Everything the call printed to stdout, which is captured instead of being printed
+    /// - The time taken, and the heap usage and execution cost of the call
+    ///
+    /// # Errors
+    /// Fails in the same cases as [`Runtime::call_function`]
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rustyscript::{ json_args, Error, Module, Runtime };
+    ///
+    /// # fn main() -> Result<(), Error> {
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// let module = Module::new("test.js", "
+    ///     export function split(s) {
+    ///         console.log('splitting', s);
+    ///         const [a, b] = s.split(':');
+    ///         return [a, Number(b)];
+    ///     }
+    /// ");
+    /// let module = runtime.load_module(&module)?;
+    ///
+    /// let result = runtime.call_function_with_result::<(String, u32)>(Some(&module), "split", json_args!("a:1"))?;
+    /// assert_eq!(result.value, ("a".to_string(), 1));
+    /// assert_eq!(result.stdout, "splitting a:1\n");
+    /// println!("took {:?}", result.duration);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn call_function_with_result<T>(
+        &mut self,
+        module_context: Option<&ModuleHandle>,
+        name: &str,
+        args: &impl serde::ser::Serialize,
+    ) -> Result<crate::CallResult<T>, Error>
+    where
+        T: deno_core::serde::de::DeserializeOwned,
+    {
+        self.block_on(|runtime| async move {
+            runtime
+                .call_function_with_result_async(module_context, name, args)
+                .await
+        })
+    }
+
+    /// Start a batch of function calls and value lookups, which are run together
+    ///
+    /// Running many small calls as a batch avoids most of the overhead of crossing into javascript for each one
+    /// See [`crate::Batch`] for details and an example
+    #[must_use]
+    pub fn batch(&mut self) -> crate::Batch<'_> {
+        crate::Batch::new(self)
+    }
+
+    /// Runs the entries of a batch, see [`crate::Batch`]
+    pub(crate) async fn run_batch(
+        &mut self,
+        entries: &[crate::batch::BatchEntry],
+    ) -> Result<Vec<Result<serde_json::Value, Error>>, Error> {
+        self.inner.run_batch(entries).await
+    }
+
+    /// Returns the execution cost measured since the start of the last call to [`Runtime::call_function_with_options`],
+    /// or since the runtime was created
+    ///
+    /// The cost is the number of function calls and loop iterations in instrumented modules,
+    /// so it is always 0 unless [`RuntimeOptions::metering`] is enabled
+    ///
+    /// # Errors
+    /// Will return an error if the cost cannot be read from the runtime
+    pub fn execution_cost(&mut self) -> Result<u64, Error> {
+        crate::metering::cost(self)
+    }
+
+    /// Benchmarks a javascript function, calling it repeatedly with the same arguments
+    ///
+    /// Each call is timed from within v8 using a monotonic clock, so the cost of crossing
+    /// between rust and javascript is not included. Promises returned by the function are awaited
+    ///
+    /// The function is first called [`crate::BenchOptions::warmup_iterations`] times without being timed
+    ///
+    /// # Arguments
+    /// * `module_context` - Optional handle to a module to search - if None, or if the search fails, the global context is used
+    /// * `name` - A string representing the name of the javascript function to benchmark
+    /// * `args` - The arguments to pass to the function on each call
+    /// * `options` - The number of warmup and timed calls
+    ///
+    /// # Errors
+    /// Fails if the function cannot be found, or if any call to it fails
+    ///
+    /// ```rust
+    /// use rustyscript::{ json_args, Runtime, Module, BenchOptions };
+    ///
+    /// # fn main() -> Result<(), rustyscript::Error> {
+    /// let module = Module::new("test.js", "
+    ///     export const join = (n) => Array.from({ length: n }, (_, i) => i).join(',');
+    /// ");
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// let handle = runtime.load_module(&module)?;
+    ///
+    /// let stats = runtime.bench_function(Some(&handle), "join", json_args!(100), BenchOptions::default())?;
+    /// println!("mean: {:?}, p95: {:?}", stats.mean, stats.p95);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn bench_function(
+        &mut self,
+        module_context: Option<&ModuleHandle>,
+        name: &str,
+        args: &impl serde::ser::Serialize,
+        options: crate::BenchOptions,
+    ) -> Result<crate::BenchStats, Error> {
+        crate::bench::bench_function(self, module_context, name, args, options)
+    }
+
+    /// Runs `f` under the v8 CPU profiler, and records heap statistics before and after it
+    ///
+    /// The returned [`crate::RuntimeReport`] can be exported to speedscope or pprof to find
+    /// where time was spent. Only javascript running inside `f` is sampled
+    ///
+    /// # Errors
+    /// Fails if the profiler cannot be started, or if `f` fails
+    ///
+    /// ```rust
+    /// use rustyscript::{ json_args, Runtime, Module, ProfileOptions };
+    ///
+    /// # fn main() -> Result<(), rustyscript::Error> {
+    /// let module = Module::new("test.js", "
+    ///     export const join = (n) => Array.from({ length: n }, (_, i) => i).join(',');
+    /// ");
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// let handle = runtime.load_module(&module)?;
+    ///
+    /// let (_, report) = runtime.profile(ProfileOptions::default(), |runtime| {
+    ///     runtime.call_function::<String>(Some(&handle), "join", json_args!(10000))
+    /// })?;
+    /// println!("{}", report.summary(10));
+    /// std::fs::write("profile.speedscope.json", report.to_speedscope("join"))?;
+    /// # std::fs::remove_file("profile.speedscope.json")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn profile<T>(
+        &mut self,
+        options: crate::ProfileOptions,
+        f: impl FnOnce(&mut Self) -> Result<T, Error>,
+    ) -> Result<(T, crate::RuntimeReport), Error> {
+        crate::profiler::profile(self, options, f)
+    }
+
+    /// Calls a javascript function within the Deno runtime by its name and deserializes its return value.
+    ///
+    /// Will not attempt to resolve promises, or run the event loop  
+    /// Promises can be returned by specifying the return type as [`crate::js_value::Promise`]  
+    /// The event loop should be run using [`Runtime::await_event_loop`]
+    ///
+    /// # Arguments
+    /// * `module_context` - Optional handle to a module to search - if None, or if the search fails, the global context is used
+    /// * `name` - A string representing the name of the javascript function to call.
+    /// * `args` - The arguments to pass to the function
+    ///
+    /// # Returns
+    /// A `Result` containing the deserialized result of the function call (`T`)  
+    /// or an error (`Error`) if the function cannot be found, if there are issues with
+    /// calling the function, or if the result cannot be deserialized.
+    ///
+    /// # Errors
+    /// Fails if the function cannot be found, if there are issues with calling the function,  
+    /// Or if the result cannot be deserialized into the requested type
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rustyscript::{ json_args, Runtime, Module, Error };
+    ///
+    /// # fn main() -> Result<(), Error> {
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// let module = Module::new("/path/to/module.js", "export function f() { return 2; };");
+    /// let module = runtime.load_module(&module)?;
+    /// let value: usize = runtime.call_function_immediate(Some(&module), "f", json_args!())?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn call_function_immediate<T>(
+        &mut self,
+        module_context: Option<&ModuleHandle>,
+        name: &str,
+        args: &impl serde::ser::Serialize,
+    ) -> Result<T, Error>
+    where
+        T: deno_core::serde::de::DeserializeOwned,
+    {
+        let call = CallInfo {
+            module: module_context,
+            function: Some(name),
+        };
+        let started = self.inner.observers.before_call(&call);
+
+        let result = self
+            .inner
+            .get_function_by_name(module_context, name)
+            .and_then(|function| {
+                let scope = self.enter_capabilities(module_context);
+                let result = self
+                    .inner
+                    .call_function_by_ref(module_context, &function, args)
+                    .and_then(|result| self.inner.decode_value(result));
+                self.exit_capabilities(scope, result)
+            });
+
+        self.inner.observers.after_call(&call, started, &result);
+        result
+    }
+
+    /// Get a value from a runtime instance
+    ///
+    /// Blocks until:
+    /// - The event loop is resolved, and
+    /// - If the value is a promise, the promise is resolved
+    ///
+    /// # Arguments
+    /// * `module_context` - Optional handle to a module to search - if None, or if the search fails, the global context is used
+    /// * `name` - A string representing the name of the value to find
+    ///
+    /// # Returns
+    /// A `Result` containing the deserialized result or an error (`Error`) if the value cannot be found,
+    /// Or if the result cannot be deserialized into the requested type
+    ///
+    /// # Errors
+    /// Can fail if the value cannot be found, or if the result cannot be deserialized.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rustyscript::{ Runtime, Module, Error };
+    ///
+    /// # fn main() -> Result<(), Error> {
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// let module = Module::new("/path/to/module.js", "globalThis.my_value = 2;");
+    /// let module = runtime.load_module(&module)?;
+    /// let value: usize = runtime.get_value(Some(&module), "my_value")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn get_value<T>(
+        &mut self,
+        module_context: Option<&ModuleHandle>,
+        name: &str,
+    ) -> Result<T, Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        self.block_on(|runtime| async move { runtime.get_value_async(module_context, name).await })
+    }
+
+    /// Get a value from a runtime instance
+    ///
+    /// Returns a future that resolves when:
+    /// - The event loop is resolved, and
+    /// - If the value is a promise, the promise is resolved
+    ///
+    /// See [`Runtime::get_value`] for an example
+    ///
+    /// # Arguments
+    /// * `module_context` - Optional handle to a module to search - if None, or if the search fails, the global context is used
+    /// * `name` - A string representing the name of the value to find
+    ///
+    /// # Returns
+    /// A `Result` containing the deserialized result or an error (`Error`) if the value cannot be found,  
+    /// Or if the result cannot be deserialized into the requested type
+    ///
+    /// # Errors
+    /// Can fail if the value cannot be found, or if the result cannot be deserialized.
+    pub async fn get_value_async<T>(
+        &mut self,
+        module_context: Option<&ModuleHandle>,
+        name: &str,
+    ) -> Result<T, Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let result = self.inner.get_value_ref(module_context, name)?;
+        let result = self.inner.resolve_with_event_loop(result).await?;
+        self.inner.decode_value(result)
+    }
+
+    /// Get a value from a runtime instance
+    ///
+    /// Will not attempt to resolve promises, or run the event loop  
+    /// Promises can be returned by specifying the return type as [`crate::js_value::Promise`]  
+    /// The event loop should be run using [`Runtime::await_event_loop`]
+    ///
+    /// # Arguments
+    /// * `module_context` - Optional handle to a module to search - if None, or if the search fails, the global context is used
+    /// * `name` - A string representing the name of the value to find
+    ///
+    /// # Returns
+    /// A `Result` containing the deserialized result or an error (`Error`) if the value cannot be found,
+    /// Or if the result cannot be deserialized into the requested type
+    ///
+    /// # Errors
+    /// Can fail if the value cannot be found, or if the result cannot be deserialized.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rustyscript::{ Runtime, Module, Error };
+    ///
+    /// # fn main() -> Result<(), Error> {
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// let module = Module::new("/path/to/module.js", "globalThis.my_value = 2;");
+    /// let module = runtime.load_module(&module)?;
+    /// let value: usize = runtime.get_value_immediate(Some(&module), "my_value")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn get_value_immediate<T>(
+        &mut self,
+        module_context: Option<&ModuleHandle>,
+        name: &str,
+    ) -> Result<T, Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let result = self.inner.get_value_ref(module_context, name)?;
+        self.inner.decode_value(result)
+    }
+
+    /// Converts a name to a v8 string once, so it can be reused by [`Runtime::get_value_prepared`]
+    /// and [`Runtime::call_function_prepared`] - instead of being converted on every lookup
+    ///
+    /// Prepared names are cached, so preparing the same name again is cheap
+    ///
+    /// # Errors
+    /// Can fail if the name cannot be encoded as a v8 string
+    ///
+    /// ```rust
+    /// use rustyscript::{ json_args, Runtime, Module, Error };
+    ///
+    /// # fn main() -> Result<(), Error> {
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// let module = Module::new("test.js", "export const score = (n) => n * 2;");
+    /// let module = runtime.load_module(&module)?;
+    ///
+    /// let score = runtime.prepare_name("score")?;
+    /// let mut total = 0;
+    /// for i in 0..1000 {
+    ///     total += runtime.call_function_prepared::<u64>(Some(&module), &score, json_args!(i))?;
+    /// }
+    /// assert_eq!(total, 999_000);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn prepare_name(&mut self, name: &'static str) -> Result<crate::PreparedName, Error> {
+        self.inner.prepare_name(name)
+    }
+
+    /// Get a value from a runtime instance, by a name prepared with [`Runtime::prepare_name`]
+    ///
+    /// Like [`Runtime::get_value_immediate`], does not wait for the event loop or resolve promises
+    ///
+    /// # Errors
+    /// Can fail if the value cannot be found, or if the result cannot be deserialized.
+    pub fn get_value_prepared<T>(
+        &mut self,
+        module_context: Option<&ModuleHandle>,
+        name: &crate::PreparedName,
+    ) -> Result<T, Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let result = self.inner.get_value_ref(module_context, name)?;
+        self.inner.decode_value(result)
+    }
+
+    /// Calls a javascript function by a name prepared with [`Runtime::prepare_name`]
+    ///
+    /// Like [`Runtime::call_function_immediate`], does not wait for the event loop or resolve promises
+    ///
+    /// # Errors
+    /// Fails if the function cannot be found, if there are issues with calling the function,  
+    /// Or if the result cannot be deserialized into the requested type
+    pub fn call_function_prepared<T>(
+        &mut self,
+        module_context: Option<&ModuleHandle>,
+        name: &crate::PreparedName,
+        args: &impl serde::ser::Serialize,
+    ) -> Result<T, Error>
+    where
+        T: deno_core::serde::de::DeserializeOwned,
+    {
+        let call = CallInfo {
+            module: module_context,
+            function: Some(name.as_str()),
+        };
+        let started = self.inner.observers.before_call(&call);
+
+        let result = self
+            .inner
+            .get_function_by_name(module_context, name)
+            .and_then(|function| {
+                let scope = self.enter_capabilities(module_context);
+                let result = self
+                    .inner
+                    .call_function_by_ref(module_context, &function, args)
+                    .and_then(|result| self.inner.decode_value(result));
+                self.exit_capabilities(scope, result)
+            });
+
+        self.inner.observers.after_call(&call, started, &result);
+        result
+    }
+
+    /// Executes the given module, and returns a handle allowing you to extract values
+    /// and call functions
+    ///
+    /// Blocks until the module has been executed AND the event loop has fully resolved  
+    /// See [`Runtime::load_module_async`] for a non-blocking variant, or use with async
+    /// background tasks
+    ///
+    /// # Arguments
+    /// * `module` - A `Module` object containing the module's filename and contents.
+    ///
+    /// # Returns
+    /// A `Result` containing a handle for the loaded module
+    /// or an error (`Error`) if there are issues with loading or executing the module
+    ///
+    /// # Errors
+    /// Can fail if the module cannot be loaded, or execution fails
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// // Create a module with filename and contents
+    /// use rustyscript::{Runtime, Module, Error};
+    ///
+    /// # fn main() -> Result<(), Error> {
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// let module = Module::new("test.js", "export default () => 'test'");
+    /// runtime.load_module(&module);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn load_module(&mut self, module: &Module) -> Result<ModuleHandle, Error> {
+        self.block_on(|runtime| async move {
+            let handle = runtime.load_module_async(module).await;
+            runtime
+                .await_event_loop(PollEventLoopOptions::default(), None)
+                .await?;
+            handle
+        })
+    }
+
+    /// Executes the given module, and returns a handle allowing you to extract values
+    /// and call functions
+    ///
+    /// Returns a future that resolves to the handle for the loaded module  
+    /// Makes no attempt to fully resolve the event loop - call [`Runtime::await_event_loop`]
+    /// to resolve background tasks and async listeners
+    ///
+    /// # Arguments
+    /// * `module` - A `Module` object containing the module's filename and contents.
+    ///
+    /// # Returns
+    /// A `Result` containing a handle for the loaded module
+    /// or an error (`Error`) if there are issues with loading or executing the module
+    ///
+    /// # Errors
+    /// Can fail if the module cannot be loaded, or execution fails
+    ///
+    /// See [`Runtime::load_module`] for an example
+    pub async fn load_module_async(&mut self, module: &Module) -> Result<ModuleHandle, Error> {
+        self.inner.load_modules(None, vec![module]).await
+    }
+
+    /// Executes the given module with command-line style arguments, and returns a handle allowing you to extract values
+    /// and call functions
+    ///
+    /// The arguments are available to the script as `rustyscript.args`, and as `process.argv` if node is enabled
+    /// (in place of the bootstrap arguments from [`crate::BootstrapOptions::args`], which `Deno.args` keeps reporting),
+    /// so scripts written for the command line can be reused unchanged  
+    /// They remain set until the next call to this function
+    ///
+    /// Blocks until the module has been executed AND the event loop has fully resolved  
+    /// See [`Runtime::load_module_with_args_async`] for a non-blocking variant
+    ///
+    /// # Errors
+    /// Can fail if the module cannot be loaded, or execution fails
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rustyscript::{Runtime, Module, Error};
+    ///
+    /// # fn main() -> Result<(), Error> {
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// let module = Module::new("test.js", "export const flag = rustyscript.args[1];");
+    /// let handle = runtime.load_module_with_args(&module, &["--flag", "x"])?;
+    ///
+    /// let flag: String = runtime.get_value(Some(&handle), "flag")?;
+    /// assert_eq!(flag, "x");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn load_module_with_args(
+        &mut self,
+        module: &Module,
+        args: &[&str],
+    ) -> Result<ModuleHandle, Error> {
+        self.set_script_args(module, args);
+        self.load_module(module)
+    }
+
+    /// Executes the given module with command-line style arguments, and returns a handle allowing you to extract values
+    /// and call functions
+    ///
+    /// Returns a future that resolves to the handle for the loaded module  
+    /// Makes no attempt to fully resolve the event loop - call [`Runtime::await_event_loop`]
+    /// to resolve background tasks and async listeners
+    ///
+    /// # Errors
+    /// Can fail if the module cannot be loaded, or execution fails
+    ///
+    /// See [`Runtime::load_module_with_args`] for an example
+    pub async fn load_module_with_args_async(
+        &mut self,
+        module: &Module,
+        args: &[&str],
+    ) -> Result<ModuleHandle, Error> {
+        self.set_script_args(module, args);
+        self.load_module_async(module).await
+    }
+
+    fn set_script_args(&mut self, module: &Module, args: &[&str]) {
+        let state = self.deno_runtime().op_state();
+        state.borrow_mut().put(crate::ext::rustyscript::ScriptArgs {
+            module: Some(module.filename().display().to_string()),
+            args: args.iter().map(ToString::to_string).collect(),
+        });
+    }
+
+    /// Executes the given module with a restricted view of the runtime, and returns a handle
+    /// allowing you to extract values and call functions
+    ///
+    /// The restrictions apply while the module is evaluated, and to every call made through the returned handle  
+    /// See [`Capabilities`] for what can be restricted, and what the restrictions do not cover
+    ///
+    /// Blocks until the module has been executed AND the event loop has fully resolved  
+    /// See [`Runtime::load_module_with_capabilities_async`] for a non-blocking variant
+    ///
+    /// # Arguments
+    /// * `module` - A `Module` object containing the module's filename and contents.
+    /// * `capabilities` - The restrictions to apply to the module
+    ///
+    /// # Errors
+    /// Can fail if the module cannot be loaded, if execution fails, if it exceeds the capabilities' limits,
+    /// or if `max_cost` is set without [`RuntimeOptions::metering`]
+    ///
+    /// See [`Capabilities`] for an example
+    pub fn load_module_with_capabilities(
+        &mut self,
+        module: &Module,
+        capabilities: Capabilities,
+    ) -> Result<ModuleHandle, Error> {
+        capabilities.validate(self.inner.metering)?;
+        self.block_on(|runtime| async move {
+            let capabilities = Arc::new(capabilities);
+            let mut context = ModuleHandle::default();
+            context.set_capabilities(capabilities.clone());
+
+            let scope = runtime.enter_capabilities(Some(&context));
+            let result = scope
+                .limit(async {
+                    let handle = runtime.inner.load_modules(None, vec![module]).await;
+                    runtime
+                        .await_event_loop(PollEventLoopOptions::default(), None)
+                        .await?;
+                    handle
+                })
+                .await;
+            let mut handle = runtime.exit_capabilities(scope, result)?;
+            handle.set_capabilities(capabilities);
+            runtime.remember_module(&handle)?;
+            Ok(handle)
+        })
+    }
+
+    /// Executes the given module with a restricted view of the runtime, and returns a handle
+    /// allowing you to extract values and call functions
+    ///
+    /// Returns a future that resolves to the handle for the loaded module  
+    /// Makes no attempt to fully resolve the event loop - call [`Runtime::await_event_loop`]
+    /// to resolve background tasks and async listeners
+    ///
+    /// # Arguments
+    /// * `module` - A `Module` object containing the module's filename and contents.
+    /// * `capabilities` - The restrictions to apply to the module
+    ///
+    /// # Errors
+    /// Can fail if the module cannot be loaded, if execution fails, if it exceeds the capabilities' limits,
+    /// or if `max_cost` is set without [`RuntimeOptions::metering`]
+    ///
+    /// See [`Capabilities`] for an example
+    pub async fn load_module_with_capabilities_async(
+        &mut self,
+        module: &Module,
+        capabilities: Capabilities,
+    ) -> Result<ModuleHandle, Error> {
+        capabilities.validate(self.inner.metering)?;
+        let capabilities = Arc::new(capabilities);
+        let mut context = ModuleHandle::default();
+        context.set_capabilities(capabilities.clone());
+
+        let scope = self.enter_capabilities(Some(&context));
+        let result = scope
+            .limit(self.inner.load_modules(None, vec![module]))
+            .await;
+        let mut handle = self.exit_capabilities(scope, result)?;
+        handle.set_capabilities(capabilities);
+        self.remember_module(&handle)?;
+        Ok(handle)
+    }
+
+    /// Replaces the recorded handle of a module, so [`Runtime::find_module`] returns it with its capabilities
+    fn remember_module(&mut self, handle: &ModuleHandle) -> Result<(), Error> {
+        let specifier = self.inner.module_specifier(handle.module().filename())?;
+        self.inner.remember_module(specifier, handle.clone());
+        Ok(())
+    }
+
+    /// Applies the capabilities of a module, if any, until [`Runtime::exit_capabilities`] is called
+    fn enter_capabilities(&mut self, module_context: Option<&ModuleHandle>) -> CapabilityScope {
+        let capabilities = module_context.and_then(ModuleHandle::shared_capabilities);
+        let preemption = self.tokio.preemption();
+        let state = self.deno_runtime().op_state();
+        let mut state = state.borrow_mut();
+        CapabilityScope::enter(&mut state, capabilities, preemption)
+    }
+
+    /// Restores the capabilities that were in place before [`Runtime::enter_capabilities`]
+    fn exit_capabilities<T>(
+        &mut self,
+        scope: CapabilityScope,
+        result: Result<T, Error>,
+    ) -> Result<T, Error> {
+        let state = self.deno_runtime().op_state();
+        let mut state = state.borrow_mut();
+        scope.exit(&mut state, result)
+    }
+
+    /// Executes the given module, and returns a handle allowing you to extract values
+    /// and call functions.
+    ///
+    /// Blocks until all modules have been executed AND the event loop has fully resolved  
+    /// See [`Runtime::load_module_async`] for a non-blocking variant, or use with async
+    /// background tasks
+    ///
+    /// This will load 'module' as the main module, and the others as side-modules.  
+    /// Only one main module can be loaded per runtime
+    ///
+    /// Side modules are all evaluated, even if nothing imports them  
+    /// See [`Runtime::register_lazy_modules`] to only evaluate those that are imported
+    ///
+    /// # Arguments
+    /// * `module` - A `Module` object containing the module's filename and contents.
+    /// * `side_modules` - A set of additional modules to be loaded into memory for use
+    ///
+    /// # Returns
+    /// A `Result` containing a handle for the loaded module
+    /// or an error (`Error`) if there are issues with loading or executing the module
+    ///
+    /// # Errors
+    /// Can fail if the module cannot be loaded, or execution fails
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// // Create a module with filename and contents
+    /// use rustyscript::{Runtime, Module, Error};
+    ///
+    /// # fn main() -> Result<(), Error> {
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// let module = Module::new("test.js", "export default () => 'test'");
+    /// runtime.load_modules(&module, vec![]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn load_modules(
+        &mut self,
+        module: &Module,
+        side_modules: Vec<&Module>,
+    ) -> Result<ModuleHandle, Error> {
+        self.block_on(move |runtime| async move {
+            let handle = runtime.load_modules_async(module, side_modules).await;
+            runtime
+                .await_event_loop(PollEventLoopOptions::default(), None)
+                .await?;
+            handle
+        })
+    }
+
+    /// Executes the given module, and returns a handle allowing you to extract values
+    /// and call functions.
+    ///
+    /// Returns a future that resolves to the handle for the loaded module  
+    /// Makes no attempt to resolve the event loop - call [`Runtime::await_event_loop`] to
+    /// resolve background tasks and async listeners
+    ///
+    /// This will load 'module' as the main module, and the others as side-modules.  
+    /// Only one main module can be loaded per runtime
+    ///
+    /// See [`Runtime::load_modules`] for an example
+    ///
+    /// # Arguments
+    /// * `module` - A `Module` object containing the module's filename and contents.
+    /// * `side_modules` - A set of additional modules to be loaded into memory for use
+    ///
+    /// # Returns
+    /// A `Result` containing a handle for the loaded main module, or the last side-module
+    /// or an error (`Error`) if there are issues with loading or executing the modules
+    ///
+    /// # Errors
+    /// Can fail if the modules cannot be loaded, or execution fails
+    pub async fn load_modules_async(
+        &mut self,
+        module: &Module,
+        side_modules: Vec<&Module>,
+    ) -> Result<ModuleHandle, Error> {
+        self.inner.load_modules(Some(module), side_modules).await
+    }
+
+    /// Returns the handle of a loaded module, by the filename it was loaded with or its specifier
+    ///
+    /// Useful when handles are not kept, or module names come from configuration  
+    /// If a module was loaded more than once, the latest handle is returned
+    ///
+    /// ```rust
+    /// use rustyscript::{ json_args, Error, Module, Runtime };
+    ///
+    /// # fn main() -> Result<(), Error> {
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// runtime.load_module(&Module::new("test.js", "export const f = () => 2;"))?;
+    ///
+    /// let handle = runtime.find_module("test.js").cloned().expect("module was loaded");
+    /// let value: i64 = runtime.call_function(Some(&handle), "f", json_args!())?;
+    /// assert_eq!(value, 2);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn find_module(&self, name: &str) -> Option<&ModuleHandle> {
+        self.inner.find_module(name)
+    }
+
+    /// Returns the handles of the modules loaded so far, with their specifiers, in load order
+    /// A module loaded more than once appears once, with its latest handle, at the position of its latest load
+    ///
+    /// Side modules loaded with [`Runtime::load_modules`] are included, but have no entrypoint
+    #[must_use]
+    pub fn loaded_modules(&self) -> &[(deno_core::ModuleSpecifier, ModuleHandle)] {
+        &self.inner.loaded_modules
+    }
+
+    /// Returns a key for a module, which stays valid when the module is loaded again
+    /// The filename is resolved against the runtime's current directory, as it is when the module is loaded
+    ///
+    /// The module does not need to be loaded yet - see [`ModuleKey`]
+    ///
+    /// # Errors
+    /// Will return an error if the filename cannot be resolved to a specifier
+    pub fn module_key(&self, filename: impl AsRef<Path>) -> Result<ModuleKey, Error> {
+        let specifier = self.inner.module_specifier(filename.as_ref())?;
+        Ok(ModuleKey::new(specifier))
+    }
+
+    /// Returns the latest handle of the module a key refers to, if it is loaded
+    #[must_use]
+    pub fn resolve_module_key(&self, key: &ModuleKey) -> Option<&ModuleHandle> {
+        self.inner
+            .loaded_modules
+            .iter()
+            .rev()
+            .find(|(specifier, _)| specifier == key.specifier())
+            .map(|(_, handle)| handle)
+    }
+
+    /// Returns a serializable description of a handle, which can be turned back into a handle
+    /// with [`Runtime::rebind_handle`]
+    ///
+    /// # Errors
+    /// Will return an error if the module's filename cannot be resolved to a specifier
+    ///
+    /// ```rust
+    /// use rustyscript::{ serde_json, Error, Module, ModuleHandleMeta, Runtime };
+    ///
+    /// # fn main() -> Result<(), Error> {
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// let handle = runtime.load_module(&Module::new("test.js", "export default function main() {}"))?;
+    ///
+    /// let json = serde_json::to_string(&runtime.handle_meta(&handle)?)?;
+    /// let meta: ModuleHandleMeta = serde_json::from_str(&json)?;
+    /// assert_eq!(meta.entrypoint.as_deref(), Some("main"));
+    ///
+    /// let handle = runtime.rebind_handle(&meta)?;
+    /// assert!(handle.entrypoint().is_some());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn handle_meta(&mut self, handle: &ModuleHandle) -> Result<crate::ModuleHandleMeta, Error> {
+        let specifier = self.inner.module_specifier(handle.module().filename())?;
+        let entrypoint = handle.entrypoint().as_ref().map(|f| self.function_name(f));
+
+        Ok(crate::ModuleHandleMeta {
+            specifier: specifier.to_string(),
+            module_id: handle.id(),
+            entrypoint,
+        })
+    }
+
+    /// Turns a description created by [`Runtime::handle_meta`] back into a handle
+    ///
+    /// The description is checked against the modules loaded into this runtime - the module must be loaded,
+    /// with the same ID and entrypoint, so a description from before a restart is only accepted if the
+    /// modules were loaded again in the same order. Use [`crate::ModuleHandleMeta::key`] when the ID may change
+    ///
+    /// # Errors
+    /// Will return an error if the module is not loaded, or was loaded with a different ID or entrypoint
+    pub fn rebind_handle(&mut self, meta: &crate::ModuleHandleMeta) -> Result<ModuleHandle, Error> {
+        let handle = self
+            .inner
+            .loaded_modules
+            .iter()
+            .rev()
+            .find(|(specifier, _)| specifier.as_str() == meta.specifier)
+            .map(|(_, handle)| handle.clone())
+            .ok_or_else(|| {
+                Error::ModuleNotFound(format!("Module not loaded: {}", meta.specifier))
+            })?;
+
+        if handle.id() != meta.module_id {
+            return Err(Error::Runtime(format!(
+                "Module {} was loaded with ID {}, not {}",
+                meta.specifier,
+                handle.id(),
+                meta.module_id
+            )));
+        }
+
+        let entrypoint = handle.entrypoint().as_ref().map(|f| self.function_name(f));
+        if entrypoint != meta.entrypoint {
+            return Err(Error::Runtime(format!(
+                "Module {} has entrypoint {:?}, not {:?}",
+                meta.specifier, entrypoint, meta.entrypoint
+            )));
+        }
+
+        Ok(handle)
+    }
+
+    /// Returns the name of a function, or an empty string for anonymous functions
+    fn function_name(
+        &mut self,
+        function: &deno_core::v8::Global<deno_core::v8::Function>,
+    ) -> String {
+        let mut scope = self.deno_runtime().handle_scope();
+        let function = deno_core::v8::Local::new(&mut scope, function);
+        function
+            .get_name(&mut scope)
+            .to_rust_string_lossy(&mut scope)
+    }
+
+    /// Calls a javascript function exported by the module a key refers to
+    ///
+    /// Behaves like [`Runtime::call_function_async`], using the latest handle of the module
+    ///
+    /// # Errors
+    /// Fails if the module is not loaded, or in the same cases as [`Runtime::call_function_async`]
+    pub async fn call_function_by_key_async<T>(
+        &mut self,
+        key: &ModuleKey,
+        name: &str,
+        args: &impl serde::ser::Serialize,
+    ) -> Result<T, Error>
+    where
+        T: deno_core::serde::de::DeserializeOwned,
+    {
+        let handle = self
+            .resolve_module_key(key)
+            .cloned()
+            .ok_or_else(|| Error::ModuleNotFound(format!("Module not loaded: {key}")))?;
+        self.call_function_async(Some(&handle), name, args).await
+    }
+
+    /// Calls a javascript function exported by the module a key refers to
+    ///
+    /// Behaves like [`Runtime::call_function`], using the latest handle of the module  
+    /// Unlike a [`ModuleHandle`], the key remains valid if the module is loaded again, even into a new runtime
+    ///
+    /// # Errors
+    /// Fails if the module is not loaded, or in the same cases as [`Runtime::call_function`]
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rustyscript::{ json_args, Error, Module, Runtime };
+    ///
+    /// # fn main() -> Result<(), Error> {
+    /// let module = Module::new("plugin.js", "export const version = () => 1;");
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// let key = runtime.module_key("plugin.js")?;
+    /// runtime.load_module(&module)?;
+    ///
+    /// // Replace the runtime - the key is still valid once the module is loaded again
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// runtime.load_module(&module)?;
+    /// let version: u32 = runtime.call_function_by_key(&key, "version", json_args!())?;
+    /// assert_eq!(version, 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn call_function_by_key<T>(
+        &mut self,
+        key: &ModuleKey,
+        name: &str,
+        args: &impl serde::ser::Serialize,
+    ) -> Result<T, Error>
+    where
+        T: deno_core::serde::de::DeserializeOwned,
+    {
+        self.block_on(
+            |runtime| async move { runtime.call_function_by_key_async(key, name, args).await },
+        )
+    }
+
+    /// Registers modules with the runtime, without loading or evaluating them
+    ///
+    /// A registered module is only transpiled and evaluated once it is imported by another module,
+    /// so large libraries of modules can be provided up front at little cost when most go unused
+    ///
+    /// Imports of registered modules are allowed even if `fs_import` is disabled
+    ///
+    /// # Arguments
+    /// * `modules` - The modules to register, which can be imported by their filenames
+    ///
+    /// # Errors
+    /// Can fail if a module's filename cannot be resolved to a module specifier  
+    /// Errors in the modules themselves are only reported once they are imported
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustyscript::{ json_args, Runtime, Module };
+    ///
+    /// # fn main() -> Result<(), rustyscript::Error> {
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// runtime.register_lazy_modules(vec![
+    ///     &Module::new("math.js", "export const double = (n) => n * 2;"),
+    ///     &Module::new("unused.js", "throw new Error('never evaluated');"),
+    /// ])?;
+    ///
+    /// let module = Module::new("main.js", "export { double } from './math.js';");
+    /// let handle = runtime.load_module(&module)?;
+    /// let value: i64 = runtime.call_function(Some(&handle), "double", json_args!(2))?;
+    /// assert_eq!(value, 4);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn register_lazy_modules(&mut self, modules: Vec<&Module>) -> Result<(), Error> {
+        self.inner.register_lazy_modules(modules)
+    }
+
+    /// Makes imports of a bare specifier, such as `lodash`, resolve to a module provided from rust
+    ///
+    /// A lighter alternative to import maps - useful for shipping a curated standard library
+    /// that scripts can import under familiar names  
+    /// The module is registered as with [`Runtime::register_lazy_modules`], so it is only evaluated once imported,
+    /// and every alias for it shares a single instance
+    ///
+    /// Aliases take priority over all other resolution, including [`crate::module_loader::ImportProvider`]s
+    ///
+    /// # Arguments
+    /// * `name` - The exact specifier to alias, such as `lodash` or `std/path`
+    /// * `module` - The module imports of `name` will resolve to
+    ///
+    /// # Errors
+    /// Can fail if the module's filename cannot be resolved to a module specifier
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustyscript::{ Runtime, Module };
+    ///
+    /// # fn main() -> Result<(), rustyscript::Error> {
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// let lodash = Module::new("stdlib/lodash.js", "export const sum = (a) => a.reduce((x, y) => x + y, 0);");
+    /// runtime.alias_module("lodash", &lodash)?;
+    ///
+    /// let module = Module::new("main.js", "import { sum } from 'lodash'; export const total = sum([1, 2, 3]);");
+    /// let handle = runtime.load_module(&module)?;
+    /// let total: i64 = runtime.get_value(Some(&handle), "total")?;
+    /// assert_eq!(total, 6);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn alias_module(&mut self, name: &str, module: &Module) -> Result<(), Error> {
+        self.inner.alias_module(name, module)
+    }
+
+    /// Returns the v8 code cache for a loaded module - its compiled bytecode
+    ///
+    /// The cache can be persisted, and given to [`Runtime::add_code_cache`] on a later run
+    /// to skip parsing and compiling the module when it is loaded again
+    ///
+    /// v8 checks the cache against the module's source, and the version and flags of v8 itself
+    /// If they do not match, the cache is ignored and the module is compiled as normal
+    ///
+    /// # Arguments
+    /// * `handle` - A handle returned by loading a module into the runtime
+    ///
+    /// # Errors
+    /// Can fail if the module cannot be transpiled or compiled
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustyscript::{ Runtime, Module };
+    ///
+    /// # fn main() -> Result<(), rustyscript::Error> {
+    /// let module = Module::new("test.js", "export const f = () => 42;");
+    ///
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// let handle = runtime.load_module(&module)?;
+    /// let cache = runtime.module_code_cache(&handle)?;
+    ///
+    /// // On a later run
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// runtime.add_code_cache(module.filename(), cache)?;
+    /// runtime.load_module(&module)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn module_code_cache(&mut self, handle: &ModuleHandle) -> Result<Vec<u8>, Error> {
+        self.block_on(
+            |runtime| async move { runtime.inner.module_code_cache(handle.module()).await },
+        )
+    }
+
+    /// Adds a v8 code cache, from [`Runtime::module_code_cache`], for the module with the given filename
+    ///
+    /// The cache is used whenever that module is loaded - from rust, or imported by a script
+    /// Caches that do not match the module's source are ignored
+    ///
+    /// # Errors
+    /// Can fail if the filename cannot be resolved to a module specifier
+    pub fn add_code_cache(
+        &mut self,
+        filename: impl AsRef<Path>,
+        data: Vec<u8>,
+    ) -> Result<(), Error> {
+        self.inner.add_code_cache(filename.as_ref(), data)
+    }
+
+    /// Executes the entrypoint function of a module within the Deno runtime.
+    ///
+    /// Blocks until:
+    /// - The event loop is resolved, and
+    /// - If the value is a promise, the promise is resolved
+    ///
+    /// # Arguments
+    /// * `module_context` - A handle returned by loading a module into the runtime
+    ///
+    /// # Returns
+    /// A `Result` containing the deserialized result of the entrypoint execution (`T`)  
+    /// if successful, or an error (`Error`) if the entrypoint is missing, the execution fails,
+    /// or the result cannot be deserialized.
+    ///
+    /// # Errors
+    /// Can fail if the module cannot be loaded, if the entrypoint is missing, if the execution fails,  
+    /// Or if the result cannot be deserialized into the requested type
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rustyscript::{json_args, Runtime, Module, Error};
+    ///
+    /// # fn main() -> Result<(), Error> {
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// let module = Module::new("test.js", "export default () => 'test'");
+    /// let module = runtime.load_module(&module)?;
+    ///
+    /// // Run the entrypoint and handle the result
+    /// let value: String = runtime.call_entrypoint(&module, json_args!())?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn call_entrypoint<T>(
+        &mut self,
+        module_context: &ModuleHandle,
+        args: &impl serde::ser::Serialize,
+    ) -> Result<T, Error>
+    where
+        T: deno_core::serde::de::DeserializeOwned,
+    {
+        self.block_on(
+            |runtime| async move { runtime.call_entrypoint_async(module_context, args).await },
+        )
+    }
+
+    /// Executes the entrypoint function of a module within the Deno runtime.
+    ///
+    /// Returns a future that resolves when:
+    /// - The event loop is resolved, and
+    /// - If the value is a promise, the promise is resolved
+    ///
+    /// Note that synchronous functions are run synchronously. Returned promises will be run asynchronously, however.
+    ///
+    /// See [`Runtime::call_entrypoint`] for an example
+    ///
+    /// # Arguments
+    /// * `module_context` - A handle returned by loading a module into the runtime
+    ///
+    /// # Returns
+    /// A `Result` containing the deserialized result of the entrypoint execution (`T`)  
+    /// if successful, or an error (`Error`) if the entrypoint is missing, the execution fails,
+    /// or the result cannot be deserialized.
+    ///
+    /// # Errors
+    /// Can fail if the module cannot be loaded, if the entrypoint is missing, if the execution fails,  
+    /// Or if the result cannot be deserialized into the requested type
+    pub async fn call_entrypoint_async<T>(
+        &mut self,
+        module_context: &ModuleHandle,
+        args: &impl serde::ser::Serialize,
+    ) -> Result<T, Error>
+    where
+        T: deno_core::serde::de::DeserializeOwned,
+    {
+        let call = CallInfo {
+            module: Some(module_context),
+            function: None,
+        };
+        let started = self.inner.observers.before_call(&call);
+
+        let result = if let Some(entrypoint) = module_context.entrypoint() {
+            let scope = self.enter_capabilities(Some(module_context));
+            let result = async {
+                let result =
+                    self.inner
+                        .call_function_by_ref(Some(module_context), entrypoint, args)?;
+                let result = scope
+                    .limit(self.inner.resolve_with_event_loop(result))
+                    .await?;
+                self.inner.decode_value(result)
+            }
+            .await;
+            self.exit_capabilities(scope, result)
+        } else {
+            Err(Error::MissingEntrypoint(module_context.module().clone()))
+        };
+
+        self.inner.observers.after_call(&call, started, &result);
+        result
+    }
+
+    /// Executes the entrypoint function of a module, recording what the call printed and cost
+    ///
+    /// Behaves like [`Runtime::call_entrypoint`] - see [`Runtime::call_function_with_result`] for details
+    ///
+    /// # Errors
+    /// Fails in the same cases as [`Runtime::call_entrypoint`]
+    pub fn call_entrypoint_with_result<T>(
+        &mut self,
+        module_context: &ModuleHandle,
+        args: &impl serde::ser::Serialize,
+    ) -> Result<crate::CallResult<T>, Error>
+    where
+        T: deno_core::serde::de::DeserializeOwned,
+    {
+        self.block_on(|runtime| async move {
+            let metering = runtime.inner.metering;
+            let recorder = crate::call_result::CallRecorder::start(runtime, metering)?;
+            let result = runtime.call_entrypoint_async(module_context, args).await;
+            recorder.finish(runtime, result)
+        })
+    }
+
+    /// Executes the entrypoint function of a module within the Deno runtime.
+    ///
+    /// Will not attempt to resolve promises, or run the event loop  
+    /// Promises can be returned by specifying the return type as [`crate::js_value::Promise`]  
+    /// The event loop should be run using [`Runtime::await_event_loop`]
+    ///
+    /// # Arguments
+    /// * `module_context` - A handle returned by loading a module into the runtime
+    ///
+    /// # Returns
+    /// A `Result` containing the deserialized result of the entrypoint execution (`T`)
+    /// if successful, or an error (`Error`) if the entrypoint is missing, the execution fails,
+    /// or the result cannot be deserialized.
+    ///
+    /// # Errors
+    /// Can fail if the module cannot be loaded, if the entrypoint is missing, if the execution fails,
+    /// Or if the result cannot be deserialized into the requested type
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rustyscript::{json_args, Runtime, Module, Error};
+    ///
+    /// # fn main() -> Result<(), Error> {
+    /// let mut runtime = Runtime::new(Default::default())?;
+    /// let module = Module::new("test.js", "export default () => 'test'");
+    /// let module = runtime.load_module(&module)?;
+    ///
+    /// // Run the entrypoint and handle the result
+    /// let value: String = runtime.call_entrypoint_immediate(&module, json_args!())?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn call_entrypoint_immediate<T>(
+        &mut self,
+        module_context: &ModuleHandle,
+        args: &impl serde::ser::Serialize,
+    ) -> Result<T, Error>
+    where
+        T: deno_core::serde::de::DeserializeOwned,
+    {
+        let call = CallInfo {
+            module: Some(module_context),
+            function: None,
+        };
+        let started = self.inner.observers.before_call(&call);
+
+        let result = if let Some(entrypoint) = module_context.entrypoint() {
+            let scope = self.enter_capabilities(Some(module_context));
+            let result = self
+                .block_on(|runtime| async move {
+                    runtime
+                        .inner
+                        .call_function_by_ref(Some(module_context), entrypoint, args)
+                })
+                .and_then(|result| self.inner.decode_value(result));
+            self.exit_capabilities(scope, result)
+        } else {
+            Err(Error::MissingEntrypoint(module_context.module().clone()))
+        };
+
+        self.inner.observers.after_call(&call, started, &result);
+        result
+    }
+
+    /// Loads a module into a new runtime, executes the entry function and returns the
+    /// result of the module's execution, deserialized into the specified Rust type (`T`).
+    ///
+    /// # Arguments
+    /// * `module` - A `Module` object containing the module's filename and contents.
+    /// * `side_modules` - A set of additional modules to be loaded into memory for use
+    /// * `runtime_options` - Options for the creation of the runtime
+    /// * `entrypoint_args` - Arguments to pass to the entrypoint function
+    ///
+    /// # Returns
+    /// A `Result` containing the deserialized result of the entrypoint execution (`T`)  
+    /// if successful, or an error (`Error`) if the entrypoint is missing, the execution fails,
+    /// or the result cannot be deserialized.
+    ///
+    /// # Errors
+    /// Can fail if the module cannot be loaded, if the entrypoint is missing, if the execution fails,  
+    /// Or if the result cannot be deserialized into the requested type
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// // Create a module with filename and contents
+    /// use rustyscript::{json_args, Runtime, Module, Error};
+    ///
+    /// # fn main() -> Result<(), Error> {
+    /// let module = Module::new("test.js", "export default () => 2");
+    /// let value: usize = Runtime::execute_module(&module, vec![], Default::default(), json_args!())?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn execute_module<T>(
+        module: &Module,
+        side_modules: Vec<&Module>,
+        runtime_options: RuntimeOptions,
+        entrypoint_args: &impl serde::ser::Serialize,
+    ) -> Result<T, Error>
+    where
+        T: deno_core::serde::de::DeserializeOwned,
+    {
+        let mut runtime = Runtime::new(runtime_options)?;
+        let module = runtime.load_modules(module, side_modules)?;
+        let value: T = runtime.call_entrypoint(&module, entrypoint_args)?;
+        Ok(value)
+    }
+}
+
+impl AsyncBridgeExt for Runtime {
+    fn bridge(&self) -> &AsyncBridge {
+        &self.tokio
+    }
+}
+
+#[cfg(test)]
+mod test_runtime {
+    use crate::json_args;
+    use std::time::Duration;
+
+    use super::*;
+    use deno_core::extension;
+
+    #[test]
+    fn test_new() {
+        Runtime::new(RuntimeOptions::default()).expect("Could not create the runtime");
+
+        extension!(test_extension);
+        Runtime::new(RuntimeOptions {
+            extensions: vec![test_extension::init_ops_and_esm()],
+            ..Default::default()
+        })
+        .expect("Could not create runtime with extensions");
+    }
+
+    #[test]
+    fn test_get_value() {
+        let module = Module::new(
+            "test.js",
+            "
+            globalThis.a = 2;
+            export const b = 'test';
+            export const fnc = null;
+        ",
+        );
+
+        let mut runtime =
+            Runtime::new(RuntimeOptions::default()).expect("Could not create the runtime");
+        let module = runtime
+            .load_modules(&module, vec![])
+            .expect("Could not load module");
+
+        assert_eq!(
+            2,
+            runtime
+                .get_value::<usize>(Some(&module), "a")
+                .expect("Could not find global")
+        );
+        assert_eq!(
+            "test",
+            runtime
+                .get_value::<String>(Some(&module), "b")
+                .expect("Could not find export")
+        );
+        runtime
+            .get_value::<Undefined>(Some(&module), "c")
+            .expect_err("Could not detect null");
+        runtime
+            .get_value::<Undefined>(Some(&module), "d")
+            .expect_err("Could not detect undeclared");
+    }
+
+    #[test]
+    fn test_load_module() {
+        let mut runtime =
+            Runtime::new(RuntimeOptions::default()).expect("Could not create the runtime");
+        let module = Module::new(
+            "test.js",
+            "
+            export default () => 2;
+        ",
+        );
+        let module = runtime
+            .load_modules(&module, vec![])
+            .expect("Could not load module");
+        assert_ne!(0, module.id());
+
+        let mut runtime =
+            Runtime::new(RuntimeOptions::default()).expect("Could not create the runtime");
+        let module1 = Module::new(
+            "importme.js",
+            "
+            export const value = 2;
+        ",
+        );
+        let module2 = Module::new(
+            "test.js",
+            "
+            import { value } from './importme.js';
+            rustyscript.register_entrypoint(() => value);
+        ",
+        );
+        runtime
+            .load_module(&module1)
+            .expect("Could not load modules");
+        let module = runtime
+            .load_module(&module2)
+            .expect("Could not load modules");
+        let value: usize = runtime
+            .call_entrypoint(&module, json_args!())
+            .expect("Could not call exported fn");
+        assert_eq!(2, value);
+
+        let mut runtime = Runtime::new(RuntimeOptions {
+            timeout: Duration::from_millis(50),
+            ..Default::default()
+        })
+        .expect("Could not create the runtime");
+        let module = Module::new(
+            "test.js",
+            "
+            await new Promise(r => setTimeout(r, 2000));
+        ",
+        );
+        runtime
+            .load_modules(&module, vec![])
+            .expect_err("Did not interupt after timeout");
+    }
+
+    #[test]
+    fn test_preemption() {
+        let mut runtime = Runtime::new(RuntimeOptions {
+            timeout: Duration::from_millis(50),
+            preemption_interval: Some(Duration::from_millis(5)),
+            ..Default::default()
+        })
+        .expect("Could not create the runtime");
+
+        let e = runtime
+            .eval::<Undefined>("while (true) {}")
+            .expect_err("Did not preempt synchronous loop");
+        assert!(
+            matches!(e, Error::Timeout { limit, .. } if limit == Duration::from_millis(50)),
+            "Unexpected error: {e}"
+        );
+
+        // The runtime remains usable, and fast calls are unaffected
+        for _ in 0..20 {
+            let value: i64 = runtime.eval("1 + 1").expect("Preempted a fast call");
+            assert_eq!(value, 2);
+        }
+
+        // A zero interval is refused
+        Runtime::new(RuntimeOptions {
+            preemption_interval: Some(Duration::ZERO),
+            ..Default::default()
+        })
+        .err()
+        .expect("Accepted a zero preemption interval");
+    }
+
+    #[test]
+    fn test_load_modules() {
+        let mut runtime =
+            Runtime::new(RuntimeOptions::default()).expect("Could not create the runtime");
+        let module = Module::new(
+            "test.js",
+            "
+            rustyscript.register_entrypoint(() => 2);
+        ",
+        );
+        let module = runtime
+            .load_modules(&module, vec![])
+            .expect("Could not load module");
+        assert_ne!(0, module.id());
+
+        let mut runtime =
+            Runtime::new(RuntimeOptions::default()).expect("Could not create the runtime");
+        let module1 = Module::new(
+            "importme.js",
+            "
+            export const value = 2;
+        ",
+        );
+        let module2 = Module::new(
+            "test.js",
+            "
+            import { value } from './importme.js';
+            rustyscript.register_entrypoint(() => value);
+        ",
+        );
+        let module = runtime
+            .load_modules(&module2, vec![&module1])
+            .expect("Could not load modules");
+        let value: usize = runtime
+            .call_entrypoint(&module, json_args!())
+            .expect("Could not call exported fn");
+        assert_eq!(2, value);
+
+        let mut runtime = Runtime::new(RuntimeOptions {
+            timeout: Duration::from_millis(50),
+            ..Default::default()
+        })
+        .expect("Could not create the runtime");
+        let module = Module::new(
+            "test.js",
+            "
+            await new Promise(r => setTimeout(r, 5000));
+        ",
+        );
+        let e = runtime
+            .load_modules(&module, vec![])
+            .expect_err("Did not interupt after timeout");
+        assert!(
+            matches!(e, Error::Timeout { elapsed, limit } if elapsed >= limit && limit == Duration::from_millis(50)),
+            "Unexpected error: {e}"
+        );
+    }
+
+    #[test]
+    fn test_call_entrypoint() {
+        let mut runtime =
+            Runtime::new(RuntimeOptions::default()).expect("Could not create the runtime");
+        let module = Module::new(
+            "test.js",
+            "
+            rustyscript.register_entrypoint(() => 2);
+        ",
+        );
+        let module = runtime
+            .load_modules(&module, vec![])
+            .expect("Could not load module");
+        let value: usize = runtime
+            .call_entrypoint(&module, json_args!())
+            .expect("Could not call registered fn");
+        assert_eq!(2, value);
+
+        let mut runtime = Runtime::new(RuntimeOptions {
+            default_entrypoint: Some("load".to_string()),
+            ..Default::default()
+        })
+        .expect("Could not create the runtime");
+        let module = Module::new(
+            "test.js",
+            "
+            export const load = () => 2;
+        ",
+        );
+        let module = runtime
+            .load_modules(&module, vec![])
+            .expect("Could not load module");
+        let value: usize = runtime
+            .call_entrypoint(&module, json_args!())
+            .expect("Could not call exported fn");
+        assert_eq!(2, value);
+
+        let mut runtime =
+            Runtime::new(RuntimeOptions::default()).expect("Could not create the runtime");
+        let module = Module::new(
+            "test.js",
+            "
+            export const load = () => 2;
+        ",
+        );
+        let module = runtime
+            .load_modules(&module, vec![])
+            .expect("Could not load module");
+        runtime
+            .call_entrypoint::<Undefined>(&module, json_args!())
+            .expect_err("Did not detect no entrypoint");
+    }
+
+    #[test]
+    fn test_execute_module() {
+        let module = Module::new(
+            "test.js",
+            "
+            rustyscript.register_entrypoint(() => 2);
+        ",
+        );
+        let value: usize =
+            Runtime::execute_module(&module, vec![], RuntimeOptions::default(), json_args!())
+                .expect("Could not exec module");
+        assert_eq!(2, value);
+
+        let module = Module::new(
+            "test.js",
+            "
+            function load() { return 2; }
+        ",
+        );
+        Runtime::execute_module::<Undefined>(
+            &module,
+            vec![],
+            RuntimeOptions::default(),
+            json_args!(),
+        )
+        .expect_err("Could not detect no entrypoint");
+    }
+
+    #[test]
+    fn call_function() {
+        let module = Module::new(
+            "test.js",
+            "
+            globalThis.fna = (i) => i;
+            export function fnb() { return 'test'; }
+            export const fnc = 2;
+            export const fne = () => {};
+        ",
+        );
+
+        let mut runtime =
+            Runtime::new(RuntimeOptions::default()).expect("Could not create the runtime");
+        let module = runtime
+            .load_modules(&module, vec![])
+            .expect("Could not load module");
+
+        let result: usize = runtime
+            .call_function(Some(&module), "fna", json_args!(2))
+            .expect("Could not call global");
+        assert_eq!(2, result);
+
+        let result: String = runtime
+            .call_function(Some(&module), "fnb", json_args!())
+            .expect("Could not call export");
+        assert_eq!("test", result);
+
+        runtime
+            .call_function::<Undefined>(Some(&module), "fnc", json_args!())
+            .expect_err("Did not detect non-function");
+        runtime
+            .call_function::<Undefined>(Some(&module), "fnd", json_args!())
+            .expect_err("Did not detect undefined");
+        runtime
+            .call_function::<Undefined>(Some(&module), "fne", json_args!())
+            .expect("Did not allow undefined return");
+    }
+
+    #[test]
+    fn test_cancellable_function() {
+        use std::sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        };
+
+        let mut runtime = Runtime::new(RuntimeOptions {
+            timeout: Duration::from_millis(50),
+            ..Default::default()
+        })
+        .expect("Could not create the runtime");
+
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let flag = cancelled.clone();
+        runtime
+            .register_cancellable_function("wait", move |_, context| {
+                let flag = flag.clone();
+                async move {
+                    context.cancellation_token().cancelled().await;
+                    flag.store(true, Ordering::SeqCst);
+                    Ok(serde_json::Value::Null)
+                }
+            })
+            .expect("Could not register function");
+        runtime
+            .register_cancellable_function("add", |args, _| async move {
+                let a = args[0].as_i64().unwrap_or_default();
+                let b = args[1].as_i64().unwrap_or_default();
+                Ok(serde_json::Value::from(a + b))
+            })
+            .expect("Could not register function");
+
+        let module = Module::new(
+            "test.js",
+            "
+            export const add = (a, b) => rustyscript.async_functions.add(a, b);
+            export const wait = () => rustyscript.async_functions.wait();
+        ",
+        );
+        let module = runtime.load_module(&module).expect("Could not load module");
+
+        let value: i64 = runtime
+            .call_function(Some(&module), "add", json_args!(2, 3))
+            .expect("Could not call function");
+        assert_eq!(value, 5);
+
+        let e = runtime
+            .call_function::<Undefined>(Some(&module), "wait", json_args!())
+            .expect_err("Did not time out");
+        assert!(matches!(e, Error::Timeout { .. }), "Unexpected error: {e}");
+
+        // The spawned task only makes progress while the tokio runtime is driven
+        runtime
+            .tokio_runtime()
+            .block_on(tokio::time::sleep(Duration::from_millis(10)));
+        assert!(cancelled.load(Ordering::SeqCst));
+
+        // Later calls receive fresh tokens
+        let value: i64 = runtime
+            .call_function(Some(&module), "add", json_args!(1, 1))
+            .expect("Could not call function");
+        assert_eq!(value, 2);
+    }
+
+    #[test]
+    fn test_progress_function() {
+        let mut runtime =
+            Runtime::new(RuntimeOptions::default()).expect("Could not create the runtime");
+        runtime
+            .register_progress_function("count", |args, progress| async move {
+                // Events sent right before completing are still delivered before the result
+                let n = args[0].as_i64().unwrap_or_default();
+                for i in 0..n {
+                    progress.send(&i)?;
+                }
+                Ok(serde_json::Value::from(n))
+            })
+            .expect("Could not register function");
+
+        let module = Module::new(
+            "test.js",
+            "
+            export async function iterate(n) {
+                const call = rustyscript.progress_functions.count(n);
+                const events = [];
+                for await (const event of call) events.push(event);
+                return { events, result: await call };
+            }
+
+            export async function listen(n) {
+                const events = [];
+                const result = await rustyscript.progress_functions.count(n).onProgress((e) => events.push(e));
+                return { events, result };
+            }
+
+            export const missing = () => rustyscript.progress_functions.missing();
+        ",
+        );
+        let module = runtime.load_module(&module).expect("Could not load module");
+
+        for name in ["iterate", "listen"] {
+            let value: serde_json::Value = runtime
+                .call_function(Some(&module), name, json_args!(3))
+                .expect("Could not call function");
+            assert_eq!(
+                value,
+                serde_json::json!({ "events": [0, 1, 2], "result": 3 })
+            );
+        }
+
+        runtime
+            .call_function::<Undefined>(Some(&module), "missing", json_args!())
+            .expect_err("Called an unregistered function");
+    }
+
+    #[test]
+    fn test_source_transform() {
+        let mut runtime = Runtime::new(RuntimeOptions {
+            source_transform: Some(std::sync::Arc::new(
+                |specifier: &deno_core::ModuleSpecifier, code: String| {
+                    if specifier.path().ends_with("banned.js") {
+                        return Err(deno_core::anyhow::anyhow!("banned module"));
+                    }
+                    Ok(format!(
+                        "globalThis.loaded = (globalThis.loaded ?? 0) + 1;\n{code}"
+                    ))
+                },
+            )),
+            ..Default::default()
+        })
+        .expect("Could not create the runtime");
+
+        let main = Module::new("main.ts", "export const n: number = globalThis.loaded;");
+        let side = Module::new("side.js", "");
+        let handle = runtime
+            .load_modules(&main, vec![&side])
+            .expect("Could not load modules");
+        let n: usize = runtime
+            .get_value(Some(&handle), "n")
+            .expect("Could not get value");
+        assert_eq!(n, 2);
+
+        runtime
+            .load_module(&Module::new("banned.js", ""))
+            .expect_err("Transform did not reject module");
+    }
+
+    #[test]
+    fn test_lazy_modules() {
+        let mut runtime =
+            Runtime::new(RuntimeOptions::default()).expect("Could not create the runtime");
+        runtime
+            .register_lazy_modules(vec![
+                &Module::new(
+                    "used.ts",
+                    "globalThis.evaluated.push('used'); export const n: number = 1;",
+                ),
+                &Module::new("unused.js", "globalThis.evaluated.push('unused');"),
+            ])
+            .expect("Could not register modules");
+        runtime
+            .eval::<Undefined>("globalThis.evaluated = []")
+            .expect("Could not eval");
+
+        let module = Module::new(
+            "main.js",
+            "
+            import { n } from './used.ts';
+            export const value = n;
+            export const load = () => import('./unused.js').then(() => globalThis.evaluated);
+        ",
+        );
+        let handle = runtime.load_module(&module).expect("Could not load module");
+        let value: i64 = runtime
+            .get_value(Some(&handle), "value")
+            .expect("Could not get value");
+        assert_eq!(value, 1);
+
+        let evaluated: Vec<String> = runtime
+            .eval("globalThis.evaluated")
+            .expect("Could not eval");
+        assert_eq!(evaluated, vec!["used"]);
+
+        // Dynamic imports are also served
+        let evaluated: Vec<String> = runtime
+            .call_function(Some(&handle), "load", json_args!())
+            .expect("Could not import module");
+        assert_eq!(evaluated, vec!["used", "unused"]);
+    }
+
+    #[test]
+    fn test_code_cache() {
+        let module = Module::new("test.ts", "export const f = (n: number) => n * 2;");
+        let importer = Module::new("importer.js", "export { f } from './test.ts';");
+
+        let mut runtime =
+            Runtime::new(RuntimeOptions::default()).expect("Could not create the runtime");
+        let handle = runtime.load_module(&module).expect("Could not load module");
+        let cache = runtime
+            .module_code_cache(&handle)
+            .expect("Could not create code cache");
+        assert!(!cache.is_empty());
+
+        // Modules loaded from rust use the cache
+        let mut runtime =
+            Runtime::new(RuntimeOptions::default()).expect("Could not create the runtime");
+        runtime
+            .add_code_cache(module.filename(), cache.clone())
+            .expect("Could not add code cache");
+        let handle = runtime.load_module(&module).expect("Could not load module");
+        let value: i64 = runtime
+            .call_function(Some(&handle), "f", json_args!(2))
+            .expect("Could not call function");
+        assert_eq!(value, 4);
+
+        // As do side modules
+        let mut runtime =
+            Runtime::new(RuntimeOptions::default()).expect("Could not create the runtime");
+        runtime
+            .add_code_cache(module.filename(), cache)
+            .expect("Could not add code cache");
+        let handle = runtime
+            .load_modules(&importer, vec![&module])
+            .expect("Could not load modules");
+        let value: i64 = runtime
+            .call_function(Some(&handle), "f", json_args!(3))
+            .expect("Could not call function");
+        assert_eq!(value, 6);
+
+        // A cache that does not match the source is ignored
+        let mut runtime =
+            Runtime::new(RuntimeOptions::default()).expect("Could not create the runtime");
+        runtime
+            .add_code_cache(module.filename(), vec![0; 64])
+            .expect("Could not add code cache");
+        let handle = runtime.load_module(&module).expect("Could not load module");
+        let value: i64 = runtime
+            .call_function(Some(&handle), "f", json_args!(4))
+            .expect("Could not call function");
+        assert_eq!(value, 8);
+    }
+
+    #[test]
+    fn test_queue() {
+        let mut runtime = Runtime::new(RuntimeOptions {
+            queue_capacity: 2,
+            ..Default::default()
+        })
+        .expect("Could not create the runtime");
+
+        let mut receiver = runtime.queue_receiver().expect("Could not take receiver");
+        runtime
+            .queue_receiver()
+            .expect_err("Receiver was taken twice");
+
+        // Pushes beyond the capacity wait for the consumer
+        let consumer = std::thread::spawn(move || {
+            let mut items = vec![];
+            while let Some(item) = receiver.blocking_recv() {
+                items.push(item.as_i64().unwrap_or_default());
+            }
+            items
+        });
+
+        let module = Module::new(
+            "test.js",
+            "
+            for (let i = 0; i < 5; i++) {
+                await rustyscript.queue.push(i);
+            }
+        ",
+        );
+        runtime.load_module(&module).expect("Could not load module");
+        drop(runtime);
+
+        assert_eq!(consumer.join().unwrap(), vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_heap_exhaustion_handled() {
+        let mut runtime = Runtime::new(RuntimeOptions {
+            max_heap_size: Some(100 * 1024 * 1024),
+            ..Default::default()
+        })
+        .expect("Could not create the runtime");
+        let module = Module::new(
+            "test.js",
+            "const largeArray = new Array(40 * 1024 * 1024).fill('a');",
+        );
+        let e = runtime
+            .load_modules(&module, vec![])
+            .expect_err("Did not detect heap exhaustion");
+        assert!(
+            matches!(e, Error::HeapExhausted { limit, .. } if limit == 100 * 1024 * 1024),
+            "Unexpected error: {e}"
+        );
+    }
+
+    #[test]
+    fn test_harden() {
+        let mut runtime = Runtime::new(RuntimeOptions {
+            harden: true,
+            ..Default::default()
+        })
+        .expect("Could not create the runtime");
+
+        // Sloppy scripts fail silently
+        let polluted: bool = runtime
+            .eval("Object.prototype.polluted = true; Array.prototype.map = null; 'polluted' in {}")
+            .unwrap();
+        assert!(!polluted);
+
+        // Modules are strict, so tampering throws
+        let module = Module::new("test.js", "Object.prototype.polluted = true;");
+        runtime
+            .load_module(&module)
+            .expect_err("Prototype was not frozen");
+
+        // Overriding inherited properties on an object still works
+        let value: String = runtime
+            .eval(
+                "
+                const o = {}; o.toString = () => 'custom';
+                const e = new Error('m'); e.name = 'CustomError';
+                [1, 2].map((x) => x * 2).join() + String(o) + e.name
+            ",
+            )
+            .unwrap();
+        assert_eq!(value, "2,4customCustomError");
+
+        // Error constructors are locked, but the stack trace limit can still be changed
+        let value: String = runtime
+            .eval(
+                "
+                Error.prepareStackTrace = () => 'hooked';
+                Error.captureStackTrace = null;
+                TypeError.prepareStackTrace = () => 'hooked';
+                Error.stackTraceLimit = 5;
+                [typeof Error.prepareStackTrace, typeof Error.captureStackTrace, typeof TypeError.prepareStackTrace, Error.stackTraceLimit].join()
+            ",
+            )
+            .unwrap();
+        assert_eq!(value, "undefined,function,undefined,5");
+    }
+
+    #[test]
+    fn test_disallow_dynamic_code() {
+        let mut runtime = Runtime::new(RuntimeOptions {
+            disallow_dynamic_code: true,
+            ..Default::default()
+        })
+        .expect("Could not create the runtime");
+
+        let module = Module::new(
+            "test.js",
+            "
+            const attempt = (f) => { try { f(); return 'allowed'; } catch (e) { return e.name; } };
+            export const evalResult = attempt(() => eval('1 + 1'));
+            export const functionResult = attempt(() => new Function('return 1')());
+            export const importResult = await import('./other.js').then(() => 'allowed', (e) => e.message);
+        ",
+        );
+        let module = runtime.load_module(&module).unwrap();
+        for name in ["evalResult", "functionResult"] {
+            let value: String = runtime.get_value(Some(&module), name).unwrap();
+            assert_eq!(value, "EvalError");
+        }
+        let value: String = runtime.get_value(Some(&module), "importResult").unwrap();
+        assert!(
+            value.contains("dynamic imports are not allowed"),
+            "Unexpected result: {value}"
+        );
+
+        // Code supplied from rust still runs
+        let value: i64 = runtime.eval("2 + 2").unwrap();
+        assert_eq!(value, 4);
+    }
+
+    #[test]
+    fn test_origin_policy() {
+        use crate::module_loader::{OriginPolicy, ViolationKind};
+        use std::sync::{Arc, Mutex};
+
+        let violations = Arc::new(Mutex::new(vec![]));
+        let reported = violations.clone();
+        let mut runtime = Runtime::new(RuntimeOptions {
+            origin_policy: Some(OriginPolicy {
+                allow_dynamic_import: false,
+                on_violation: Some(Arc::new(move |violation| {
+                    reported.lock().unwrap().push(violation.clone());
+                })),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+        .expect("Could not create the runtime");
+
+        // Static imports of modules loaded from rust are allowed
+        let lib = Module::new("lib.js", "export const value = 2;");
+        runtime.load_module(&lib).unwrap();
+        let module = Module::new(
+            "main.js",
+            "
+            import { value } from './lib.js';
+            export const staticResult = value;
+            export const dynamicResult = await import('./lib.js').then(() => 'allowed', (e) => e.message);
+        ",
+        );
+        let module = runtime.load_module(&module).unwrap();
+
+        let value: i64 = runtime.get_value(Some(&module), "staticResult").unwrap();
+        assert_eq!(value, 2);
+        let value: String = runtime.get_value(Some(&module), "dynamicResult").unwrap();
+        assert!(value.contains("dynamic import is not allowed"), "{value}");
+
+        let violations = violations.lock().unwrap();
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].kind, ViolationKind::DynamicImport);
+        assert!(violations[0].specifier.as_str().ends_with("lib.js"));
+    }
+
+    #[test]
+    fn test_origin_policy_internal_modules() {
+        use crate::module_loader::OriginPolicy;
+
+        // Modules provided by the runtime are allowed, even when no origin is
+        let mut runtime = Runtime::new(RuntimeOptions {
+            origin_policy: Some(OriginPolicy {
+                allowed_origins: Some(std::collections::HashSet::new()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+        .expect("Could not create the runtime");
+
+        runtime
+            .register_function_in("math", "two", |_| Ok(serde_json::json!(2)))
+            .unwrap();
+        runtime.expose_namespace("math").unwrap();
+        runtime
+            .alias_module(
+                "config",
+                &Module::new("config.js", "export const three = 3;"),
+            )
+            .unwrap();
+
+        let module = Module::new(
+            "main.js",
+            "
+            import { two } from 'host:math';
+            import { three } from 'config';
+            export const sum = two() + three;
+            export const blocked = await import('./other.js').then(() => 'allowed', (e) => e.message);
+        ",
+        );
+        let module = runtime.load_module(&module).unwrap();
+
+        let sum: i64 = runtime.get_value(Some(&module), "sum").unwrap();
+        assert_eq!(sum, 5);
+        let blocked: String = runtime.get_value(Some(&module), "blocked").unwrap();
+        assert!(
+            blocked.contains("module origin is not allowed"),
+            "{blocked}"
+        );
+    }
+
+    #[cfg(feature = "node_experimental")]
     #[test]
-    fn test_new() {
-        Runtime::new(RuntimeOptions::default()).expect("Could not create the runtime");
+    fn test_origin_policy_workers() {
+        use crate::module_loader::{OriginPolicy, ViolationKind};
+        use std::sync::{Arc, Mutex};
 
-        extension!(test_extension);
-        Runtime::new(RuntimeOptions {
-            extensions: vec![test_extension::init_ops_and_esm()],
+        let violations = Arc::new(Mutex::new(vec![]));
+        let reported = violations.clone();
+        let mut runtime = Runtime::new(RuntimeOptions {
+            origin_policy: Some(OriginPolicy {
+                allow_workers: false,
+                on_violation: Some(Arc::new(move |violation| {
+                    reported.lock().unwrap().push(violation.clone());
+                })),
+                ..Default::default()
+            }),
             ..Default::default()
         })
-        .expect("Could not create runtime with extensions");
+        .expect("Could not create the runtime");
+
+        // The worker's own script is blocked, not just its imports
+        let path = std::env::current_dir()
+            .unwrap()
+            .join("examples/javascript/example_module.js");
+        let url = deno_core::url::Url::from_file_path(path).unwrap();
+        let module = Module::new(
+            "main.js",
+            format!(
+                "
+                export const result = await new Promise((resolve) => {{
+                    const timer = setTimeout(() => resolve('started'), 1000);
+                    const worker = new Worker('{url}', {{ type: 'module' }});
+                    worker.onerror = (e) => {{
+                        e.preventDefault();
+                        clearTimeout(timer);
+                        resolve('blocked');
+                    }};
+                }});
+                "
+            ),
+        );
+        let module = runtime.load_module(&module).unwrap();
+
+        let value: String = runtime.get_value(Some(&module), "result").unwrap();
+        assert_eq!(value, "blocked");
+
+        let violations = violations.lock().unwrap();
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].kind, ViolationKind::Worker);
+        assert_eq!(violations[0].specifier, url);
     }
 
     #[test]
-    fn test_get_value() {
+    fn test_map_location() {
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let module = Module::new(
+            "test.ts",
+            "type Num = number;\nexport function double(value: Num): Num {\n    return value * 2;\n}\n",
+        );
+        runtime.load_module(&module).unwrap();
+
+        let location = runtime
+            .map_location("test.ts", 2, 5)
+            .expect("No mapping found");
+        assert!(location.specifier.ends_with("test.ts"));
+        assert_eq!((location.line, location.column), (3, 5));
+        assert_eq!(
+            location.source_line.as_deref(),
+            Some("    return value * 2;")
+        );
+
+        // Modules that were not transpiled have no source map
+        let module = Module::new("test.js", "export const a = 1;");
+        runtime.load_module(&module).unwrap();
+        assert!(runtime.map_location("test.js", 1, 1).is_none());
+        assert!(runtime.map_location("missing.ts", 1, 1).is_none());
+    }
+
+    #[test]
+    fn test_transpiler_options() {
+        let module = Module::new(
+            "decorators.ts",
+            "
+            function prefixed(method: any, context: ClassMethodDecoratorContext) {
+                return function (this: any) { return `${String(context.name)}:${method.call(this)}`; };
+            }
+            class Greeter {
+                @prefixed greet() { return 'hi'; }
+            }
+            export const result = new Greeter().greet();
+        ",
+        );
+
+        // v8 cannot run decorators untransformed
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        runtime
+            .load_module(&module)
+            .expect_err("Decorators should not be supported by default");
+
+        let mut runtime = Runtime::new(RuntimeOptions {
+            transpiler_options: crate::TranspilerOptions {
+                decorators: crate::DecoratorMode::Proposal,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .unwrap();
+        let handle = runtime.load_module(&module).unwrap();
+        let value: String = runtime.get_value(Some(&handle), "result").unwrap();
+        assert_eq!(value, "greet:hi");
+    }
+
+    #[test]
+    fn test_import_meta() {
+        #[derive(serde::Deserialize)]
+        struct Meta {
+            main: bool,
+            resolved: String,
+            filename: String,
+            dirname: String,
+        }
+
+        // Resolving is not a dynamic import, so it is still allowed
+        let mut runtime = Runtime::new(RuntimeOptions {
+            disallow_dynamic_code: true,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let code = "export const meta = {
+            main: import.meta.main,
+            resolved: import.meta.resolve('./lib/util.js'),
+            filename: import.meta.filename,
+            dirname: import.meta.dirname,
+        };";
+        let main = Module::new("src/main.js", code);
+        let side = Module::new("src/side.js", code);
+        let handle = runtime.load_modules(&main, vec![&side]).unwrap();
+
+        let meta: Meta = runtime.get_value(Some(&handle), "meta").unwrap();
+        assert!(meta.main);
+        assert!(meta.resolved.starts_with("file://"));
+        assert!(meta.resolved.ends_with("/src/lib/util.js"));
+        assert!(Path::new(&meta.filename).ends_with("src/main.js"));
+        assert!(Path::new(&meta.dirname).ends_with("src"));
+
+        let other = Module::new("src/other.js", code);
+        let handle = runtime.load_module(&other).unwrap();
+        let meta: Meta = runtime.get_value(Some(&handle), "meta").unwrap();
+        assert!(!meta.main);
+    }
+
+    #[test]
+    fn test_alias_module() {
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let std = Module::new(
+            "stdlib/strings.ts",
+            "globalThis.loads = (globalThis.loads ?? 0) + 1; export const shout = (s: string) => s.toUpperCase();",
+        );
+        runtime.alias_module("strings", &std).unwrap();
+        runtime.alias_module("std/strings", &std).unwrap();
+
+        let module = Module::new(
+            "main.js",
+            "
+            import { shout } from 'strings';
+            import * as again from 'std/strings';
+            export const value = shout('hi') + again.shout('!');
+            export const loads = globalThis.loads;
+            export const dynamic = () => import('strings').then((m) => m.shout('dynamic'));
+        ",
+        );
+        let handle = runtime.load_module(&module).unwrap();
+        let value: String = runtime.get_value(Some(&handle), "value").unwrap();
+        assert_eq!(value, "HI!");
+        let loads: i64 = runtime.get_value(Some(&handle), "loads").unwrap();
+        assert_eq!(loads, 1);
+
+        let value: String = runtime
+            .call_function(Some(&handle), "dynamic", json_args!())
+            .unwrap();
+        assert_eq!(value, "DYNAMIC");
+
+        // Unaliased bare specifiers still fail
+        let module = Module::new("other.js", "import 'lodash';");
+        runtime
+            .load_module(&module)
+            .expect_err("Bare specifier resolved");
+    }
+
+    #[test]
+    #[cfg(feature = "url_import")]
+    fn test_module_fetch_redirects() {
+        use std::io::{Read, Write};
+
+        // Serves a redirect from 127.0.0.1 to localhost, and records the requests it was sent
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let mut requests = vec![];
+            for response in [
+                format!("HTTP/1.1 302 Found\r\nLocation: http://localhost:{port}/final.js\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"),
+                "HTTP/1.1 200 OK\r\nContent-Type: application/javascript\r\nContent-Length: 23\r\nConnection: close\r\n\r\nexport const value = 2;".to_string(),
+            ] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = vec![0; 4096];
+                let len = stream.read(&mut request).unwrap();
+                requests.push(String::from_utf8_lossy(&request[..len]).to_lowercase());
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+            requests
+        });
+
+        let mut runtime = Runtime::new(RuntimeOptions {
+            module_fetch_credentials: Some(std::sync::Arc::new(
+                |url: &deno_core::ModuleSpecifier| {
+                    Ok::<_, deno_core::anyhow::Error>(match url.host_str() {
+                        Some("127.0.0.1") => vec![("X-Api-Key".to_string(), "secret".to_string())],
+                        _ => vec![],
+                    })
+                },
+            )),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let module = Module::new(
+            "test.js",
+            &format!("export {{ value }} from 'http://127.0.0.1:{port}/mod.js';"),
+        );
+        let module = runtime.load_module(&module).unwrap();
+        let value: usize = runtime.get_value(Some(&module), "value").unwrap();
+        assert_eq!(value, 2);
+
+        // Credentials for the first host are not sent to the second
+        let requests = server.join().unwrap();
+        assert!(requests[0].contains("x-api-key: secret"));
+        assert!(requests[1].starts_with("get /final.js"));
+        assert!(!requests[1].contains("x-api-key"));
+    }
+
+    #[test]
+    #[cfg(feature = "web")]
+    fn test_proxy_clients() {
+        let check = "[typeof Deno.createHttpClient, typeof Deno.core.ops.op_fetch, typeof fetch]";
+
+        // Scripts cannot create or use clients that would skip a required proxy
+        let mut options = RuntimeOptions::default();
+        options.extension_options.web.proxy_config =
+            Some(crate::ProxyConfig::new("http://proxy.internal:3128").unwrap());
+        let mut runtime = Runtime::new(options).unwrap();
+        let types: Vec<String> = runtime.eval(check).unwrap();
+        assert_eq!(types, vec!["undefined", "undefined", "function"]);
+
+        let mut options = RuntimeOptions::default();
+        options.extension_options.web.proxy_config = Some(
+            crate::ProxyConfig::new("http://proxy.internal:3128")
+                .unwrap()
+                .allow_unproxied(true),
+        );
+        let mut runtime = Runtime::new(options).unwrap();
+        let types: Vec<String> = runtime.eval(check).unwrap();
+        assert_eq!(types, vec!["function", "function", "function"]);
+    }
+
+    #[test]
+    #[cfg(feature = "web")]
+    fn test_span_processor() {
+        use crate::opentelemetry::trace::{SpanContext, SpanId, TraceFlags, TraceId, TraceState};
+        use crate::opentelemetry_sdk::{export::trace::SpanData, trace::SpanProcessor};
+        use std::sync::Mutex;
+
+        #[derive(Debug, Default)]
+        struct Collector(Mutex<Vec<SpanData>>);
+        impl SpanProcessor for Collector {
+            fn on_start(
+                &self,
+                _: &mut crate::opentelemetry_sdk::trace::Span,
+                _: &crate::opentelemetry::Context,
+            ) {
+            }
+            fn on_end(&self, span: SpanData) {
+                self.0.lock().unwrap().push(span);
+            }
+            fn force_flush(&self) -> crate::opentelemetry::trace::TraceResult<()> {
+                Ok(())
+            }
+            fn shutdown(&self) -> crate::opentelemetry::trace::TraceResult<()> {
+                Ok(())
+            }
+        }
+
+        let collector = Arc::new(Collector::default());
+        let mut options = RuntimeOptions::default();
+        options.extension_options.web.span_processor = Some(collector.clone());
+        let mut runtime = Runtime::new(options).unwrap();
+
+        let parent = SpanContext::new(
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap(),
+            SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::default(),
+        );
+        runtime.set_trace_context(Some(&parent)).unwrap();
+
+        // Exports a span through the exporter scripts use with the opentelemetry js library
         let module = Module::new(
             "test.js",
             "
-            globalThis.a = 2;
-            export const b = 'test';
-            export const fnc = null;
+            const parent = new Deno.telemetry.ContextManager().active()
+                .getValue(Symbol.for('OpenTelemetry Context Key SPAN'))
+                .spanContext();
+            export function exportSpan() {
+                const span = {
+                    spanContext: () => ({ traceId: parent.traceId, spanId: '1111111111111111', traceFlags: 1 }),
+                    parentSpanId: parent.spanId,
+                    kind: 2,
+                    name: 'work',
+                    status: { code: 0 },
+                    attributes: { 'job.id': 7 },
+                    instrumentationLibrary: { name: 'test' },
+                    links: [], events: [],
+                    droppedAttributesCount: 0, droppedLinksCount: 0, droppedEventsCount: 0,
+                    startTime: [1, 0], endTime: [2, 0],
+                };
+                let result;
+                new Deno.telemetry.SpanExporter().export([span], (r) => result = r);
+                return result.code;
+            }
         ",
         );
+        let module = runtime.load_module(&module).unwrap();
+        let code: usize = runtime
+            .call_function(Some(&module), "exportSpan", json_args!())
+            .unwrap();
+        assert_eq!(code, 0);
 
-        let mut runtime =
-            Runtime::new(RuntimeOptions::default()).expect("Could not create the runtime");
-        let module = runtime
-            .load_modules(&module, vec![])
-            .expect("Could not load module");
+        let spans = collector.0.lock().unwrap();
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].name, "work");
+        assert_eq!(spans[0].span_context.trace_id(), parent.trace_id());
+        assert_eq!(spans[0].parent_span_id, parent.span_id());
+        assert_eq!(spans[0].attributes[0].key.as_str(), "job.id");
+        assert_eq!(spans[0].instrumentation_scope.name(), "test");
+    }
 
-        assert_eq!(
-            2,
+    #[test]
+    #[cfg(feature = "web")]
+    fn test_performance_entries() {
+        use crate::PerformanceEntryType;
+
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        assert!(runtime.performance_entries().unwrap().is_empty());
+
+        let module = Module::new(
+            "test.js",
+            "
+            performance.mark('start', { detail: 'begin' });
+            performance.mark('end');
+            performance.measure('total', 'start', 'end');
+            globalThis.performance = undefined;
+        ",
+        );
+        runtime.load_module(&module).unwrap();
+
+        let entries = runtime.performance_entries().unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].name, "start");
+        assert_eq!(entries[0].detail, Some(serde_json::json!("begin")));
+        assert_eq!(entries[2].entry_type, PerformanceEntryType::Measure);
+        assert!(entries[2].duration >= 0.0);
+        assert_eq!(entries[2].start_time, entries[0].start_time);
+    }
+
+    #[test]
+    fn test_module_stall_timeout() {
+        let mut runtime = Runtime::new(RuntimeOptions {
+            module_stall_timeout: Some(Duration::from_millis(50)),
+            ..Default::default()
+        })
+        .unwrap();
+        runtime
+            .register_async_function("never", |_| Box::pin(std::future::pending()))
+            .unwrap();
+
+        let module = Module::new("stalled.js", "await rustyscript.async_functions.never();");
+        let error = runtime.load_module(&module).unwrap_err();
+        let Error::ModuleStalled {
+            module, pending, ..
+        } = error
+        else {
+            panic!("Unexpected error: {error}");
+        };
+        assert!(module.ends_with("stalled.js"));
+        assert!(pending.contains("call_registered_function_async"));
+
+        #[cfg(any(feature = "web", feature = "web_stub"))]
+        {
+            // Waiting on timers that keep firing is not a stall
+            let module = Module::new(
+                "timer.js",
+                "for (let i = 0; i < 10; i++) await new Promise((resolve) => setTimeout(resolve, 20));",
+            );
+            runtime.load_module(&module).unwrap();
+
+            // But a timer that does not fire within the limit is
+            let module = Module::new(
+                "late_timer.js",
+                "await new Promise((resolve) => setTimeout(resolve, 1000));",
+            );
+            let error = runtime.load_module(&module).unwrap_err();
+            assert!(
+                matches!(error, Error::ModuleStalled { .. }),
+                "Unexpected error: {error}"
+            );
+
+            // Nor does an interval that fires less often than the limit keep a module alive
+            let module = Module::new(
+                "interval.js",
+                "
+                setInterval(() => {}, 1000);
+                await rustyscript.async_functions.never();
+            ",
+            );
+            let error = runtime.load_module(&module).unwrap_err();
+            assert!(
+                matches!(error, Error::ModuleStalled { .. }),
+                "Unexpected error: {error}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_find_module() {
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        assert!(runtime.find_module("a.js").is_none());
+
+        let side = Module::new("lib/a.js", "export const name = 'a';");
+        let main = Module::new(
+            "main.js",
+            "export const name = 'main'; export default () => 1;",
+        );
+        runtime.load_modules(&main, vec![&side]).unwrap();
+        runtime
+            .load_module_with_capabilities(
+                &Module::new("restricted.js", "export const name = 'restricted';"),
+                crate::Capabilities::default(),
+            )
+            .unwrap();
+
+        let names: Vec<_> = runtime
+            .loaded_modules()
+            .iter()
+            .map(|(specifier, _)| specifier.path().rsplit('/').next().unwrap().to_string())
+            .collect();
+        assert_eq!(names, ["a.js", "main.js", "restricted.js"]);
+
+        let handle = runtime.find_module("lib/a.js").cloned().unwrap();
+        let name: String = runtime.get_value(Some(&handle), "name").unwrap();
+        assert_eq!(name, "a");
+        assert!(handle.entrypoint().is_none());
+
+        let specifier = runtime.loaded_modules()[1].0.to_string();
+        let handle = runtime.find_module(&specifier).cloned().unwrap();
+        assert!(handle.entrypoint().is_some());
+
+        let handle = runtime.find_module("./restricted.js").unwrap();
+        assert!(handle.capabilities().is_some());
+
+        // Loading a module again replaces its handle, rather than adding another
+        for _ in 0..3 {
             runtime
-                .get_value::<usize>(Some(&module), "a")
-                .expect("Could not find global")
+                .load_module(&Module::new(
+                    "restricted.js",
+                    "export const name = 'again';",
+                ))
+                .unwrap();
+        }
+        assert_eq!(runtime.loaded_modules().len(), 3);
+        let handle = runtime.find_module("restricted.js").cloned().unwrap();
+        assert!(handle.capabilities().is_none());
+    }
+
+    #[test]
+    fn test_rebind_handle() {
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let side = Module::new("side.js", "export const name = 'side';");
+        let main = Module::new("main.js", "export default function run() { return 1; }");
+        let main_handle = runtime.load_modules(&main, vec![&side]).unwrap();
+        let side_handle = runtime.find_module("side.js").cloned().unwrap();
+
+        let meta = runtime.handle_meta(&main_handle).unwrap();
+        assert_eq!(meta.entrypoint.as_deref(), Some("run"));
+        assert!(meta.specifier.ends_with("/main.js"));
+
+        // Round-trip through serde, as a supervisor would
+        let json = serde_json::to_string(&meta).unwrap();
+        let meta: crate::ModuleHandleMeta = serde_json::from_str(&json).unwrap();
+        let handle = runtime.rebind_handle(&meta).unwrap();
+        assert_eq!(handle.id(), main_handle.id());
+        let value: i64 = runtime.call_entrypoint(&handle, json_args!()).unwrap();
+        assert_eq!(value, 1);
+
+        let side_meta = runtime.handle_meta(&side_handle).unwrap();
+        assert_eq!(side_meta.entrypoint, None);
+        assert_eq!(
+            runtime.rebind_handle(&side_meta).unwrap().id(),
+            side_handle.id()
         );
         assert_eq!(
-            "test",
             runtime
-                .get_value::<String>(Some(&module), "b")
-                .expect("Could not find export")
+                .resolve_module_key(&side_meta.key().unwrap())
+                .unwrap()
+                .id(),
+            side_handle.id()
+        );
+
+        let stale = crate::ModuleHandleMeta {
+            module_id: 1000,
+            ..meta.clone()
+        };
+        assert!(runtime.rebind_handle(&stale).is_err());
+
+        let renamed = crate::ModuleHandleMeta {
+            entrypoint: Some("other".to_string()),
+            ..meta.clone()
+        };
+        assert!(runtime.rebind_handle(&renamed).is_err());
+
+        let missing = crate::ModuleHandleMeta {
+            specifier: "file:///missing.js".to_string(),
+            ..meta
+        };
+        assert!(matches!(
+            runtime.rebind_handle(&missing),
+            Err(Error::ModuleNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_call_with_result() {
+        let mut runtime = Runtime::new(RuntimeOptions {
+            metering: true,
+            ..Default::default()
+        })
+        .unwrap();
+        let module = Module::new(
+            "test.js",
+            "
+            export default async () => { console.log('entry'); return 1; };
+            export function pair(n) {
+                console.log('pair', n);
+                console.error('not captured');
+                for (let i = 0; i < n; i++) {}
+                return [n, 'x'.repeat(n)];
+            }
+            ",
         );
+        let module = runtime.load_module(&module).unwrap();
+
+        let result = runtime
+            .call_function_with_result::<(usize, String)>(Some(&module), "pair", json_args!(3))
+            .unwrap();
+        assert_eq!(result.value, (3, "xxx".to_string()));
+        assert_eq!(result.stdout, "pair 3\n");
+        assert!(result.stats.heap_used > 0);
+        assert!(result.stats.cost.unwrap() > 3);
+
+        let result = runtime
+            .call_entrypoint_with_result::<u32>(&module, json_args!())
+            .unwrap();
+        assert_eq!(result.value, 1);
+        assert_eq!(result.stdout, "entry\n");
+
+        let result = runtime
+            .call_function_with_result::<crate::Undefined>(Some(&module), "missing", json_args!())
+            .unwrap_err();
+        assert!(matches!(result, Error::ValueNotFound(_)));
+    }
+
+    #[test]
+    fn test_blocking_functions() {
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
         runtime
-            .get_value::<Undefined>(Some(&module), "c")
-            .expect_err("Could not detect null");
+            .register_async_function(
+                "double",
+                crate::async_callback!(|n: i64| async move { Ok::<i64, Error>(n * 2) }),
+            )
+            .unwrap();
+        runtime
+            .register_async_function(
+                "threaded",
+                crate::async_callback!(|n: i64| async move {
+                    let (tx, rx) = tokio::sync::oneshot::channel();
+                    std::thread::spawn(move || {
+                        std::thread::sleep(Duration::from_millis(10));
+                        tx.send(n + 1).ok();
+                    });
+                    rx.await.map_err(|e| Error::Runtime(e.to_string()))
+                }),
+            )
+            .unwrap();
+        runtime
+            .register_async_function(
+                "sleep",
+                crate::async_callback!(|ms: u64| async move {
+                    tokio::time::sleep(Duration::from_millis(ms)).await;
+                    Ok::<u64, Error>(ms)
+                }),
+            )
+            .unwrap();
+
+        let value: i64 = runtime
+            .eval("rustyscript.blocking_functions.double(21)")
+            .unwrap();
+        assert_eq!(value, 42);
+
+        let value: i64 = runtime
+            .eval("rustyscript.call_blocking('threaded', [1], 1000)")
+            .unwrap();
+        assert_eq!(value, 2);
+
+        // The current-thread runtime cannot drive timers while blocked
+        let error = runtime
+            .eval::<u64>("rustyscript.call_blocking('sleep', [1000], 20)")
+            .unwrap_err();
+        assert!(error.to_string().contains("did not complete within"));
+
+        for deadline in ["-1", "1e300", "NaN"] {
+            let error = runtime
+                .eval::<u64>(format!(
+                    "rustyscript.call_blocking('double', [1], {deadline})"
+                ))
+                .unwrap_err();
+            assert!(error.to_string().contains("Invalid deadline"), "{error}");
+        }
+    }
+
+    #[test]
+    fn test_unregister_function() {
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        runtime
+            .register_function("a", |_| Ok(serde_json::json!("sync")))
+            .unwrap();
+        runtime
+            .register_async_function(
+                "b",
+                crate::async_callback!(|s: String| async move { Ok::<String, Error>(s) }),
+            )
+            .unwrap();
+        assert_eq!(runtime.list_functions().unwrap(), vec!["a", "b"]);
+
+        // Replacing a function with one of the other kind removes the original
+        runtime
+            .register_async_function(
+                "a",
+                crate::async_callback!(|s: String| async move { Ok::<String, Error>(s) }),
+            )
+            .unwrap();
+        assert_eq!(runtime.list_functions().unwrap(), vec!["a", "b"]);
+        let value: String = runtime
+            .eval("rustyscript.async_functions.a('replaced')")
+            .unwrap();
+        assert_eq!(value, "replaced");
+        assert!(runtime
+            .eval::<serde_json::Value>("rustyscript.functions.a()")
+            .is_err());
+
+        assert!(runtime.unregister_function("b").unwrap());
+        assert!(!runtime.unregister_function("b").unwrap());
+        assert_eq!(runtime.list_functions().unwrap(), vec!["a"]);
+        assert!(runtime
+            .eval::<serde_json::Value>("rustyscript.async_functions.b()")
+            .is_err());
+
+        // Missing functions of both kinds go to the hook
+        runtime
+            .set_missing_function_hook(|name, args| Ok(serde_json::json!([name, args.len()])))
+            .unwrap();
+        let value: serde_json::Value = runtime.eval("rustyscript.functions.b(1, 2)").unwrap();
+        assert_eq!(value, serde_json::json!(["b", 2]));
+        let value: serde_json::Value = runtime.eval("rustyscript.async_functions.c()").unwrap();
+        assert_eq!(value, serde_json::json!(["c", 0]));
+    }
+
+    #[test]
+    fn test_event_loop_errors() {
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let seen = Rc::new(std::cell::RefCell::new(Vec::new()));
+        let seen_ = seen.clone();
+        runtime.set_event_loop_error_hook(move |e| {
+            seen_.borrow_mut().push(e.exception_message.clone());
+        });
+
+        let _ = runtime.eval::<Undefined>(
+            "setTimeout(() => { throw new Error('timer failed'); }, 10); undefined",
+        );
+        let result = runtime.block_on_event_loop(PollEventLoopOptions::default(), None);
+        assert!(result.is_err());
+
+        let errors = runtime.take_event_loop_errors();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].exception_message.contains("timer failed"));
+        assert_eq!(seen.borrow().len(), 1);
+        assert!(runtime.take_event_loop_errors().is_empty());
+
+        // Errors from the call itself are not event loop errors
+        let _ = runtime.eval::<Undefined>("throw new Error('direct')");
+        assert!(runtime.take_event_loop_errors().is_empty());
+    }
+
+    #[test]
+    fn test_panic_hook() {
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        runtime
+            .register_function("parse", |_| panic!("bad input"))
+            .unwrap();
+        runtime
+            .register_async_function(
+                "fetch",
+                crate::async_callback!(|n: i64| async move {
+                    if n > 0 {
+                        panic!("lost connection");
+                    }
+                    Ok::<i64, Error>(n)
+                }),
+            )
+            .unwrap();
+
+        let seen = Rc::new(std::cell::RefCell::new(Vec::new()));
+        let seen_ = seen.clone();
+        runtime
+            .set_panic_hook(move |panic| seen_.borrow_mut().push(panic.clone()))
+            .unwrap();
+
+        let module = runtime
+            .load_module(&Module::new(
+                "panics.js",
+                "
+                const caught = async (f) => { try { await f(); } catch (e) { return [e.name, e.message]; } };
+                export const callSync = () => caught(() => rustyscript.functions.parse());
+                export const callAsync = (n) => caught(() => rustyscript.async_functions.fetch(n));
+                ",
+            ))
+            .unwrap();
+
+        let caught: Vec<String> = runtime
+            .call_function(Some(&module), "callSync", json_args!())
+            .unwrap();
+        assert_eq!(
+            caught,
+            ["PanicError", "Function `parse` panicked: bad input"]
+        );
+
+        let caught: Vec<String> = runtime
+            .call_function(Some(&module), "callAsync", json_args!(1))
+            .unwrap();
+        assert_eq!(
+            caught,
+            ["PanicError", "Function `fetch` panicked: lost connection"]
+        );
+
+        assert_eq!(
+            *seen.borrow(),
+            [
+                crate::CallbackPanic {
+                    function: "parse".to_string(),
+                    message: "bad input".to_string(),
+                },
+                crate::CallbackPanic {
+                    function: "fetch".to_string(),
+                    message: "lost connection".to_string(),
+                },
+            ]
+        );
+
+        // The runtime is still usable
+        let value: i64 = runtime
+            .eval("rustyscript.async_functions.fetch(0)")
+            .unwrap();
+        assert_eq!(value, 0);
+    }
+
+    #[test]
+    fn test_call_cached() {
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let source = "
+            globalThis.calls = 0;
+            export const square = (n) => { globalThis.calls++; return n * n; };
+            export const fail = () => { globalThis.calls++; throw new Error('nope'); };
+        ";
+        let module = runtime
+            .load_module(&Module::new("pure.js", source))
+            .unwrap();
+
+        for _ in 0..3 {
+            let value: i64 = runtime
+                .call_cached(&module, "square", json_args!(4))
+                .unwrap();
+            assert_eq!(value, 16);
+        }
+        let value: i64 = runtime
+            .call_cached(&module, "square", json_args!(5))
+            .unwrap();
+        assert_eq!(value, 25);
+        let calls: i64 = runtime.eval("globalThis.calls").unwrap();
+        assert_eq!(calls, 2);
+
+        // Errors are not cached
+        assert!(runtime
+            .call_cached::<i64>(&module, "fail", json_args!())
+            .is_err());
+        assert!(runtime
+            .call_cached::<i64>(&module, "fail", json_args!())
+            .is_err());
+        let calls: i64 = runtime.eval("globalThis.calls").unwrap();
+        assert_eq!(calls, 4);
+
+        // Loading the module again discards its results
+        let reloaded = runtime
+            .load_module(&Module::new("pure.js", source))
+            .unwrap();
+        let _: i64 = runtime
+            .call_cached(&reloaded, "square", json_args!(4))
+            .unwrap();
+        let calls: i64 = runtime.eval("globalThis.calls").unwrap();
+        assert_eq!(calls, 5);
+
+        runtime.clear_call_cache();
+        let _: i64 = runtime
+            .call_cached(&reloaded, "square", json_args!(4))
+            .unwrap();
+        let calls: i64 = runtime.eval("globalThis.calls").unwrap();
+        assert_eq!(calls, 6);
+    }
+
+    #[test]
+    fn test_module_graph() {
+        use crate::module_loader::ModuleOrigin;
+
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let lib = Module::new("lib.js", "export const n = 1;");
+        let main = Module::new(
+            "main.js",
+            "import { n } from './lib.js'; export const value = n;",
+        );
+        runtime.register_lazy_modules(vec![&lib]).unwrap();
+        runtime.load_module(&main).unwrap();
+        runtime.load_module(&main).unwrap();
+
+        let graph = runtime.module_graph();
+        let names: Vec<_> = graph
+            .modules()
+            .iter()
+            .map(|m| m.specifier.rsplit('/').next().unwrap())
+            .collect();
+        assert_eq!(names, ["main.js", "lib.js"]);
+        assert!(graph
+            .modules()
+            .iter()
+            .all(|m| m.origin == ModuleOrigin::Host));
+
+        let report = graph.report();
+        assert_eq!(
+            report.total_size,
+            lib.contents().len() + main.contents().len()
+        );
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["modules"][1]["size"], lib.contents().len());
+        assert_eq!(json["modules"][1]["origin"], "host");
+    }
+
+    #[test]
+    fn test_reentrant_functions() {
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        runtime
+            .register_function("add", |args| {
+                let sum: i64 = args.iter().filter_map(serde_json::Value::as_i64).sum();
+                Ok(sum.into())
+            })
+            .unwrap();
+        runtime
+            .register_reentrant_function("apply", |scope, args| {
+                assert_eq!(args[0], serde_json::Value::Null);
+                let f: crate::js_value::Function = scope.argument(0)?;
+                let value: i64 = scope.call(&f, &json_args!(args[1].clone()))?;
+
+                // Other functions can be called while this one runs
+                let offset: i64 = scope.eval("rustyscript.functions.add(globalThis.offset, 1)")?;
+                Ok((value + offset).into())
+            })
+            .unwrap();
+        assert_eq!(runtime.list_functions().unwrap(), vec!["add", "apply"]);
+
+        let value: i64 = runtime
+            .eval("globalThis.offset = 10; rustyscript.reentrant_functions.apply((n) => n * 2, 4)")
+            .unwrap();
+        assert_eq!(value, 19);
+
+        // Errors thrown by the script come back to the callback
+        let err = runtime
+            .eval::<i64>(
+                "rustyscript.reentrant_functions.apply(() => { throw new Error('nope') }, 1)",
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("nope"));
+
+        // Calls can nest
+        let value: i64 = runtime
+            .eval("rustyscript.reentrant_functions.apply((n) => rustyscript.reentrant_functions.apply((m) => m + 1, n), 1)")
+            .unwrap();
+        assert_eq!(value, 24);
+
+        assert!(runtime.unregister_function("apply").unwrap());
+        assert!(runtime
+            .eval::<i64>("rustyscript.reentrant_functions.apply(() => 1, 1)")
+            .is_err());
+    }
+
+    #[test]
+    fn test_function_arguments() {
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let tokens = Rc::new(std::cell::RefCell::new(Vec::new()));
+        let tokens_ = tokens.clone();
+        runtime
+            .register_function("subscribe", move |args| {
+                let token = crate::js_value::FunctionToken::from_value(&args[1]);
+                tokens_.borrow_mut().extend(token);
+                Ok(args[0].clone())
+            })
+            .unwrap();
+
+        // Other arguments are untouched
+        let value: String = runtime
+            .eval("rustyscript.functions.subscribe('a', (n) => n + 1)")
+            .unwrap();
+        assert_eq!(value, "a");
+        runtime
+            .eval::<String>("rustyscript.functions.subscribe('b', 5)")
+            .unwrap();
+        assert_eq!(tokens.borrow().len(), 1);
+
+        let token = tokens.borrow()[0];
+        let f = runtime.take_function(token).unwrap();
+        let value: i64 = f.call(&mut runtime, None, &json_args!(1)).unwrap();
+        assert_eq!(value, 2);
+
+        // Tokens can only be taken once
+        assert!(runtime.take_function(token).is_err());
+
+        // Scripts cannot forge a token for a function captured by another call
         runtime
-            .get_value::<Undefined>(Some(&module), "d")
-            .expect_err("Could not detect undeclared");
+            .eval::<String>("rustyscript.functions.subscribe('c', (n) => n * 2)")
+            .unwrap();
+        let id = serde_json::to_value(tokens.borrow()[1]).unwrap();
+        for forged in [
+            format!("{id}"),
+            format!("[{id}]"),
+            format!("{{ inner: {id} }}"),
+        ] {
+            let err = runtime
+                .eval::<String>(&format!("rustyscript.functions.subscribe('d', {forged})"))
+                .unwrap_err();
+            assert!(err.to_string().contains("Function tokens"), "{err}");
+        }
+        assert_eq!(tokens.borrow().len(), 2);
+
+        // Untaken functions are released once too many are kept
+        runtime
+            .eval::<()>(
+                "for (let i = 0; i < 1024; i++) rustyscript.functions.subscribe('e', () => i)",
+            )
+            .unwrap();
+        let oldest = tokens.borrow()[1];
+        assert!(runtime.take_function(oldest).is_err());
+        let newest = *tokens.borrow().last().unwrap();
+        assert!(runtime.take_function(newest).is_ok());
     }
 
     #[test]
-    fn test_load_module() {
-        let mut runtime =
-            Runtime::new(RuntimeOptions::default()).expect("Could not create the runtime");
-        let module = Module::new(
-            "test.js",
-            "
-            export default () => 2;
-        ",
-        );
-        let module = runtime
-            .load_modules(&module, vec![])
-            .expect("Could not load module");
-        assert_ne!(0, module.id());
+    fn test_load_module_with_args() {
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let args: Vec<String> = runtime.eval("rustyscript.args").unwrap();
+        assert!(args.is_empty());
 
-        let mut runtime =
-            Runtime::new(RuntimeOptions::default()).expect("Could not create the runtime");
-        let module1 = Module::new(
-            "importme.js",
-            "
-            export const value = 2;
-        ",
-        );
-        let module2 = Module::new(
-            "test.js",
-            "
-            import { value } from './importme.js';
-            rustyscript.register_entrypoint(() => value);
-        ",
-        );
-        runtime
-            .load_module(&module1)
-            .expect("Could not load modules");
-        let module = runtime
-            .load_module(&module2)
-            .expect("Could not load modules");
-        let value: usize = runtime
-            .call_entrypoint(&module, json_args!())
-            .expect("Could not call exported fn");
-        assert_eq!(2, value);
+        let module = Module::new("cli.js", "export const args = [...rustyscript.args];");
+        let handle = runtime
+            .load_module_with_args(&module, &["--flag", "x"])
+            .unwrap();
+        let args: Vec<String> = runtime.get_value(Some(&handle), "args").unwrap();
+        assert_eq!(args, vec!["--flag", "x"]);
+    }
 
+    #[test]
+    fn test_module_base_url() {
         let mut runtime = Runtime::new(RuntimeOptions {
-            timeout: Duration::from_millis(50),
+            module_base_url: Some("https://app.example/scripts".parse().unwrap()),
             ..Default::default()
         })
-        .expect("Could not create the runtime");
-        let module = Module::new(
-            "test.js",
-            "
-            await new Promise(r => setTimeout(r, 2000));
-        ",
+        .unwrap();
+
+        runtime
+            .load_module(&Module::new(
+                "lib/util.js",
+                "export const url = import.meta.url;",
+            ))
+            .unwrap();
+        let handle = runtime
+            .load_module(&Module::new(
+                "main.js",
+                "
+                import { url as utilUrl } from './lib/util.js';
+                export const urls = [import.meta.url, utilUrl];
+                export const fail = () => { throw new Error('boom'); };
+                ",
+            ))
+            .unwrap();
+
+        let urls: Vec<String> = runtime.get_value(Some(&handle), "urls").unwrap();
+        assert_eq!(
+            urls,
+            vec![
+                "https://app.example/scripts/main.js",
+                "https://app.example/scripts/lib/util.js"
+            ]
         );
+
+        // Host paths do not appear in errors
+        let e = runtime
+            .call_function::<()>(Some(&handle), "fail", json_args!())
+            .unwrap_err()
+            .to_string();
+        assert!(e.contains("https://app.example/scripts/main.js"), "{e}");
+        let cwd = std::env::current_dir().unwrap();
+        assert!(!e.contains(&*cwd.to_string_lossy()), "{e}");
+
+        // Virtual modules are never fetched
         runtime
-            .load_modules(&module, vec![])
-            .expect_err("Did not interupt after timeout");
+            .load_module(&Module::new("bad.js", "import './missing.js';"))
+            .expect_err("Missing virtual module was loaded");
     }
 
     #[test]
-    fn test_load_modules() {
-        let mut runtime =
-            Runtime::new(RuntimeOptions::default()).expect("Could not create the runtime");
-        let module = Module::new(
-            "test.js",
-            "
-            rustyscript.register_entrypoint(() => 2);
-        ",
-        );
-        let module = runtime
-            .load_modules(&module, vec![])
-            .expect("Could not load module");
-        assert_ne!(0, module.id());
+    fn test_path_redactions() {
+        let dir = std::env::current_dir().unwrap().join("scripts");
+        let mut runtime = Runtime::new(RuntimeOptions {
+            path_redactions: crate::module_loader::PathRedactions::new()
+                .with_mapping(&dir, "app:///scripts/")
+                .unwrap(),
+            ..Default::default()
+        })
+        .unwrap();
 
-        let mut runtime =
-            Runtime::new(RuntimeOptions::default()).expect("Could not create the runtime");
-        let module1 = Module::new(
-            "importme.js",
-            "
-            export const value = 2;
-        ",
-        );
-        let module2 = Module::new(
-            "test.js",
-            "
-            import { value } from './importme.js';
-            rustyscript.register_entrypoint(() => value);
-        ",
+        runtime
+            .register_lazy_modules(vec![&Module::new(
+                "scripts/util.js",
+                "export const url = import.meta.url;",
+            )])
+            .unwrap();
+        let handle = runtime
+            .load_module(&Module::new(
+                "scripts/main.js",
+                "
+                import { url as utilUrl } from './util.js';
+                export const urls = [import.meta.url, utilUrl];
+                export const fail = () => { throw new Error('boom'); };
+                ",
+            ))
+            .unwrap();
+
+        let urls: Vec<String> = runtime.get_value(Some(&handle), "urls").unwrap();
+        assert_eq!(
+            urls,
+            vec!["app:///scripts/main.js", "app:///scripts/util.js"]
         );
-        let module = runtime
-            .load_modules(&module2, vec![&module1])
-            .expect("Could not load modules");
-        let value: usize = runtime
-            .call_entrypoint(&module, json_args!())
-            .expect("Could not call exported fn");
-        assert_eq!(2, value);
 
+        let e = runtime
+            .call_function::<()>(Some(&handle), "fail", json_args!())
+            .unwrap_err()
+            .to_string();
+        assert!(e.contains("app:///scripts/main.js"), "{e}");
+        assert!(!e.contains(&*dir.to_string_lossy()), "{e}");
+    }
+
+    #[cfg(feature = "node_experimental")]
+    #[test]
+    fn test_bootstrap_options() {
         let mut runtime = Runtime::new(RuntimeOptions {
-            timeout: Duration::from_millis(50),
+            extension_options: crate::ExtensionOptions {
+                bootstrap: crate::BootstrapOptions {
+                    args: vec!["--flag".to_string(), "x".to_string()],
+                    deno_version: "1.2.3".to_string(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
             ..Default::default()
         })
-        .expect("Could not create the runtime");
-        let module = Module::new(
-            "test.js",
-            "
-            await new Promise(r => setTimeout(r, 5000));
-        ",
-        );
-        runtime
-            .load_modules(&module, vec![])
-            .expect_err("Did not interupt after timeout");
+        .unwrap();
+
+        let args: Vec<String> = runtime.eval("Deno.args").unwrap();
+        assert_eq!(args, vec!["--flag", "x"]);
+
+        let version: String = runtime.eval("Deno.version.deno").unwrap();
+        assert_eq!(version, "1.2.3");
+
+        // `process.argv` reports the bootstrap arguments, until a module is given its own
+        let argv: Vec<String> = runtime.eval("process.argv.slice(2)").unwrap();
+        assert_eq!(argv, vec!["--flag", "x"]);
+
+        let module = Module::new("cli.js", "export const argv = process.argv.slice(2);");
+        let handle = runtime
+            .load_module_with_args(&module, &["--other"])
+            .unwrap();
+        let argv: Vec<String> = runtime.get_value(Some(&handle), "argv").unwrap();
+        assert_eq!(argv, vec!["--other"]);
+
+        let args: Vec<String> = runtime.eval("Deno.args").unwrap();
+        assert_eq!(args, vec!["--flag", "x"]);
     }
 
+    #[cfg(feature = "node_experimental")]
     #[test]
-    fn test_call_entrypoint() {
-        let mut runtime =
-            Runtime::new(RuntimeOptions::default()).expect("Could not create the runtime");
-        let module = Module::new(
-            "test.js",
-            "
-            rustyscript.register_entrypoint(() => 2);
-        ",
-        );
-        let module = runtime
-            .load_modules(&module, vec![])
-            .expect("Could not load module");
-        let value: usize = runtime
-            .call_entrypoint(&module, json_args!())
-            .expect("Could not call registered fn");
-        assert_eq!(2, value);
+    fn test_script_exit() {
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        let e = runtime
+            .eval::<Undefined>("Deno.exit(3); while (true) {}")
+            .expect_err("Exit did not terminate the runtime");
+        assert!(matches!(e, Error::ScriptExited(3)), "{e}");
+
+        // The runtime stays terminated
+        let e = runtime.eval::<usize>("1 + 1").unwrap_err();
+        assert!(matches!(e, Error::ScriptExited(3)), "{e}");
+    }
 
+    #[cfg(not(feature = "node_experimental"))]
+    #[test]
+    fn test_script_exit_unavailable() {
+        // Without `Deno.exit`, scripts cannot reach the exit op either
         let mut runtime = Runtime::new(RuntimeOptions {
-            default_entrypoint: Some("load".to_string()),
+            exit_policy: crate::ExitPolicy::Process,
             ..Default::default()
         })
-        .expect("Could not create the runtime");
-        let module = Module::new(
-            "test.js",
-            "
-            export const load = () => 2;
-        ",
+        .unwrap();
+        let kind: String = runtime
+            .eval("typeof Deno.core.ops.op_rustyscript_exit")
+            .unwrap();
+        assert_eq!(kind, "undefined");
+    }
+
+    #[test]
+    fn test_fork() {
+        assert!(Runtime::new(RuntimeOptions::default())
+            .unwrap()
+            .fork()
+            .is_err());
+
+        let mut runtime = Runtime::forkable(|| RuntimeOptions {
+            default_entrypoint: Some("main".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        runtime.set_timeout(Duration::from_secs(5));
+
+        let calls = Rc::new(std::cell::Cell::new(0));
+        let calls_ = calls.clone();
+        runtime
+            .register_function("count", move |_| {
+                calls_.set(calls_.get() + 1);
+                Ok(serde_json::json!(calls_.get()))
+            })
+            .unwrap();
+        runtime
+            .register_async_function(
+                "echo",
+                crate::async_callback!(|s: String| async move { Ok::<String, Error>(s) }),
+            )
+            .unwrap();
+        runtime
+            .register_function("removed", |_| Ok(serde_json::Value::Null))
+            .unwrap();
+        runtime.unregister_function("removed").unwrap();
+        runtime
+            .register_reentrant_function("apply", |scope, args| {
+                let f: crate::js_value::Function = scope.argument(0)?;
+                scope.call(&f, &json_args!(args[1].clone()))
+            })
+            .unwrap();
+        runtime
+            .alias_module("lib", &Module::new("lib.js", "export const name = 'lib';"))
+            .unwrap();
+
+        let mut fork = runtime.fork().unwrap();
+        assert_eq!(fork.timeout(), Duration::from_secs(5));
+        assert_eq!(
+            fork.list_functions().unwrap(),
+            vec!["apply", "count", "echo"]
         );
-        let module = runtime
-            .load_modules(&module, vec![])
-            .expect("Could not load module");
-        let value: usize = runtime
-            .call_entrypoint(&module, json_args!())
-            .expect("Could not call exported fn");
-        assert_eq!(2, value);
 
-        let mut runtime =
-            Runtime::new(RuntimeOptions::default()).expect("Could not create the runtime");
+        // Functions are shared, not copied
+        let _: i64 = runtime.eval("rustyscript.functions.count()").unwrap();
+        let count: i64 = fork.eval("rustyscript.functions.count()").unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(calls.get(), 2);
+
+        let echo: String = fork
+            .eval("rustyscript.async_functions.echo('forked')")
+            .unwrap();
+        assert_eq!(echo, "forked");
+
+        let applied: i64 = fork
+            .eval("rustyscript.reentrant_functions.apply((n) => n + 1, 1)")
+            .unwrap();
+        assert_eq!(applied, 2);
+
+        // Options come from the factory, and loader caches are copied
         let module = Module::new(
-            "test.js",
-            "
-            export const load = () => 2;
-        ",
+            "main.js",
+            "import { name } from 'lib'; export const main = () => name;",
         );
-        let module = runtime
-            .load_modules(&module, vec![])
-            .expect("Could not load module");
-        runtime
-            .call_entrypoint::<Undefined>(&module, json_args!())
-            .expect_err("Did not detect no entrypoint");
+        let handle = fork.load_module(&module).unwrap();
+        let name: String = fork.call_entrypoint(&handle, json_args!()).unwrap();
+        assert_eq!(name, "lib");
+
+        // Forks can be forked
+        let mut nested = fork.fork().unwrap();
+        let count: i64 = nested.eval("rustyscript.functions.count()").unwrap();
+        assert_eq!(count, 3);
     }
 
     #[test]
-    fn test_execute_module() {
-        let module = Module::new(
-            "test.js",
-            "
-            rustyscript.register_entrypoint(() => 2);
-        ",
-        );
-        let value: usize =
-            Runtime::execute_module(&module, vec![], RuntimeOptions::default(), json_args!())
-                .expect("Could not exec module");
-        assert_eq!(2, value);
+    fn test_function_namespaces() {
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
+        runtime
+            .register_function_in(
+                "math",
+                "add",
+                crate::sync_callback!(|a: i64, b: i64| Ok::<i64, Error>(a + b)),
+            )
+            .unwrap();
+        runtime
+            .register_async_function_in(
+                "math",
+                "double",
+                crate::async_callback!(|a: i64| async move { Ok::<i64, Error>(a * 2) }),
+            )
+            .unwrap();
+        runtime
+            .register_function_in("math.nested", "hidden", |_| Ok(serde_json::Value::Null))
+            .unwrap();
+
+        assert!(runtime.expose_namespace("missing").is_err());
+        runtime.expose_namespace("math").unwrap();
 
         let module = Module::new(
             "test.js",
             "
-            function load() { return 2; }
+            import { add, double } from 'host:math';
+            export const sum = add(1, 2);
+            export const doubled = await double(4);
+            export const names = Object.keys(rustyscript.api.math);
+            export const flat = rustyscript.functions['math.add'](2, 2);
+            export const missing = rustyscript.api.missing === undefined;
         ",
         );
-        Runtime::execute_module::<Undefined>(
-            &module,
-            vec![],
-            RuntimeOptions::default(),
-            json_args!(),
-        )
-        .expect_err("Could not detect no entrypoint");
+        let module = runtime.load_module(&module).unwrap();
+
+        let sum: i64 = runtime.get_value(Some(&module), "sum").unwrap();
+        assert_eq!(sum, 3);
+        let doubled: i64 = runtime.get_value(Some(&module), "doubled").unwrap();
+        assert_eq!(doubled, 8);
+        let names: Vec<String> = runtime.get_value(Some(&module), "names").unwrap();
+        assert_eq!(names, vec!["add", "double"]);
+        let flat: i64 = runtime.get_value(Some(&module), "flat").unwrap();
+        assert_eq!(flat, 4);
+        let missing: bool = runtime.get_value(Some(&module), "missing").unwrap();
+        assert!(missing);
     }
 
     #[test]
-    fn call_function() {
+    fn test_with_context() {
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
         let module = Module::new(
             "test.js",
             "
-            globalThis.fna = (i) => i;
-            export function fnb() { return 'test'; }
-            export const fnc = 2;
-            export const fne = () => {};
+            export const context = () => rustyscript.context();
+            export function mutate() {
+                'use strict';
+                rustyscript.context().user.name = 'changed';
+            }
+            export function later() {
+                return new Promise((r) => setTimeout(() => r(rustyscript.context()), 10));
+            }
         ",
         );
+        let module = runtime.load_module(&module).unwrap();
 
-        let mut runtime =
-            Runtime::new(RuntimeOptions::default()).expect("Could not create the runtime");
-        let module = runtime
-            .load_modules(&module, vec![])
-            .expect("Could not load module");
+        let value: serde_json::Value = runtime
+            .call_function(Some(&module), "context", json_args!())
+            .unwrap();
+        assert!(value.is_null());
 
-        let result: usize = runtime
-            .call_function(Some(&module), "fna", json_args!(2))
-            .expect("Could not call global");
-        assert_eq!(2, result);
+        let context = serde_json::json!({ "user": { "name": "bob" } });
+        runtime
+            .with_context(&context, |runtime| {
+                let value: serde_json::Value =
+                    runtime.call_function(Some(&module), "context", json_args!())?;
+                assert_eq!(value, context);
 
-        let result: String = runtime
-            .call_function(Some(&module), "fnb", json_args!())
-            .expect("Could not call export");
-        assert_eq!("test", result);
+                // The context is frozen
+                let err = runtime
+                    .call_function::<Undefined>(Some(&module), "mutate", json_args!())
+                    .unwrap_err();
+                assert!(err.to_string().contains("read only"), "{err}");
 
+                // Nested contexts replace the outer one, until they end
+                runtime.with_context(&1, |runtime| {
+                    let value: usize =
+                        runtime.call_function(Some(&module), "context", json_args!())?;
+                    assert_eq!(value, 1);
+                    Ok(())
+                })?;
+                let value: serde_json::Value =
+                    runtime.call_function(Some(&module), "context", json_args!())?;
+                assert_eq!(value, context);
+                Ok(())
+            })
+            .unwrap();
+
+        let value: serde_json::Value = runtime
+            .call_function(Some(&module), "context", json_args!())
+            .unwrap();
+        assert!(value.is_null());
+
+        // Promises created during the call keep its context
+        let promise: crate::js_value::Promise<serde_json::Value> = runtime
+            .with_context(&"request", |runtime| {
+                runtime.call_function_immediate(Some(&module), "later", json_args!())
+            })
+            .unwrap();
+        let value = promise.into_value(&mut runtime).unwrap();
+        assert_eq!(value, "request");
+
+        // Scripts cannot set or leave a context themselves
+        let forge = Module::new(
+            "forge.js",
+            "
+            export const forge = () => globalThis.__rustyscript_enter_context({ admin: true });
+            export const leave = () => globalThis.__rustyscript_exit_context();
+        ",
+        );
+        let forge = runtime.load_module(&forge).unwrap();
+        let context = serde_json::json!({ "user": "bob" });
         runtime
-            .call_function::<Undefined>(Some(&module), "fnc", json_args!())
-            .expect_err("Did not detect non-function");
-        runtime
-            .call_function::<Undefined>(Some(&module), "fnd", json_args!())
-            .expect_err("Did not detect undefined");
-        runtime
-            .call_function::<Undefined>(Some(&module), "fne", json_args!())
-            .expect("Did not allow undefined return");
+            .with_context(&context, |runtime| {
+                for name in ["forge", "leave"] {
+                    let err = runtime
+                        .call_function::<Undefined>(Some(&forge), name, json_args!())
+                        .unwrap_err();
+                    assert!(err.to_string().contains("only be set by the host"), "{err}");
+                }
+                let value: serde_json::Value =
+                    runtime.call_function(Some(&module), "context", json_args!())?;
+                assert_eq!(value, context);
+                Ok(())
+            })
+            .unwrap();
     }
 
     #[test]
-    fn test_heap_exhaustion_handled() {
-        let mut runtime = Runtime::new(RuntimeOptions {
-            max_heap_size: Some(100 * 1024 * 1024),
-            ..Default::default()
-        })
-        .expect("Could not create the runtime");
+    fn test_abort_signal() {
+        let mut runtime = Runtime::new(RuntimeOptions::default()).unwrap();
         let module = Module::new(
             "test.js",
-            "const largeArray = new Array(40 * 1024 * 1024).fill('a');",
+            "
+            export function listen(signal) {
+                globalThis.events = [];
+                signal.addEventListener('abort', () => events.push(signal.reason.name));
+            }
+            export const events = () => globalThis.events;
+            export async function work(signal) {
+                while (!signal.aborted) {
+                    await new Promise((r) => setTimeout(r, 5));
+                }
+                return signal.reason;
+            }
+        ",
         );
+        let module = runtime.load_module(&module).unwrap();
+
+        // Aborted with the default reason, outside of a call
+        let signal = runtime.create_abort_signal().unwrap();
         runtime
-            .load_modules(&module, vec![])
-            .expect_err("Did not detect heap exhaustion");
+            .call_function::<Undefined>(Some(&module), "listen", &(&signal,))
+            .unwrap();
+        assert!(!signal.is_aborted());
+        signal.abort();
+        assert!(signal.is_aborted());
+        runtime
+            .block_on_event_loop(PollEventLoopOptions::default(), None)
+            .unwrap();
+        let events: Vec<String> = runtime
+            .call_function(Some(&module), "events", json_args!())
+            .unwrap();
+        assert_eq!(events, vec!["AbortError"]);
+
+        // Aborted from another thread, while the call is running
+        let signal = runtime.create_abort_signal().unwrap();
+        let handle = signal.handle();
+        let thread = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            handle
+                .abort_with_reason(&serde_json::json!({ "code": 42 }))
+                .unwrap();
+        });
+        let reason: serde_json::Value = runtime
+            .call_function(Some(&module), "work", &(&signal,))
+            .unwrap();
+        thread.join().unwrap();
+        assert_eq!(reason, serde_json::json!({ "code": 42 }));
+
+        // A signal that is never aborted does not keep the event loop alive
+        let _signal = runtime.create_abort_signal().unwrap();
+        runtime
+            .block_on_event_loop(PollEventLoopOptions::default(), None)
+            .unwrap();
     }
 }
@@ -64,6 +64,60 @@ impl RuntimeBuilder {
         self
     }
 
+    /// Measure the execution cost of calls, by instrumenting loaded modules
+    ///
+    /// See [`crate::RuntimeOptions::metering`]
+    #[must_use]
+    pub fn with_metering(mut self) -> Self {
+        self.0.metering = true;
+        self
+    }
+
+    /// Freeze the built-in prototypes and constructors before any user code runs
+    ///
+    /// See [`crate::RuntimeOptions::harden`]
+    #[must_use]
+    pub fn with_harden(mut self) -> Self {
+        self.0.harden = true;
+        self
+    }
+
+    /// Disallow `eval`, `new Function` and dynamic `import()` within loaded scripts
+    ///
+    /// See [`crate::RuntimeOptions::disallow_dynamic_code`]
+    #[must_use]
+    pub fn with_dynamic_code_disallowed(mut self) -> Self {
+        self.0.disallow_dynamic_code = true;
+        self
+    }
+
+    /// Restrict where modules may be imported from
+    ///
+    /// See [`crate::RuntimeOptions::origin_policy`]
+    #[must_use]
+    pub fn with_origin_policy(mut self, policy: crate::module_loader::OriginPolicy) -> Self {
+        self.0.origin_policy = Some(policy);
+        self
+    }
+
+    /// Set the options used to transpile typescript, JSX and TSX modules
+    ///
+    /// See [`crate::RuntimeOptions::transpiler_options`]
+    #[must_use]
+    pub fn with_transpiler_options(mut self, options: crate::TranspilerOptions) -> Self {
+        self.0.transpiler_options = options;
+        self
+    }
+
+    /// Enforce the timeout while synchronous javascript is running, checking it once per interval
+    ///
+    /// See [`crate::RuntimeOptions::preemption_interval`]
+    #[must_use]
+    pub fn with_preemption_interval(mut self, interval: std::time::Duration) -> Self {
+        self.0.preemption_interval = Some(interval);
+        self
+    }
+
     /// Optional maximum heap size for the runtime
     #[must_use]
     pub fn with_max_heap_size(mut self, max_heap_size: usize) -> Self {
@@ -71,6 +125,100 @@ impl RuntimeBuilder {
         self
     }
 
+    /// Set how integers outside of javascript's safe integer range are handled when decoding values
+    #[must_use]
+    pub fn with_number_policy(mut self, number_policy: crate::js_value::NumberPolicy) -> Self {
+        self.0.number_policy = number_policy;
+        self
+    }
+
+    /// Set how circular references are handled when decoding values
+    #[must_use]
+    pub fn with_cycle_policy(mut self, cycle_policy: crate::js_value::CyclePolicy) -> Self {
+        self.0.cycle_policy = cycle_policy;
+        self
+    }
+
+    /// Set the deepest a value can be nested when decoded
+    #[must_use]
+    pub fn with_max_decode_depth(mut self, max_decode_depth: usize) -> Self {
+        self.0.max_decode_depth = max_decode_depth;
+        self
+    }
+
+    /// Set what happens when a script calls `Deno.exit` or `process.exit`
+    #[must_use]
+    pub fn with_exit_policy(mut self, exit_policy: crate::ExitPolicy) -> Self {
+        self.0.exit_policy = exit_policy;
+        self
+    }
+
+    /// Set a virtual base url for modules loaded from rust, such as `https://app.example/scripts/`
+    /// See [`crate::RuntimeOptions::module_base_url`]
+    #[must_use]
+    pub fn with_module_base_url(mut self, base_url: deno_core::ModuleSpecifier) -> Self {
+        self.0.module_base_url = Some(base_url);
+        self
+    }
+
+    /// Set the host directories shown to scripts as virtual urls
+    /// See [`crate::module_loader::PathRedactions`]
+    #[must_use]
+    pub fn with_path_redactions(
+        mut self,
+        path_redactions: crate::module_loader::PathRedactions,
+    ) -> Self {
+        self.0.path_redactions = path_redactions;
+        self
+    }
+
+    /// Set the maximum number of items waiting in the queue filled by `rustyscript.queue.push`
+    #[must_use]
+    pub fn with_queue_capacity(mut self, queue_capacity: usize) -> Self {
+        self.0.queue_capacity = queue_capacity;
+        self
+    }
+
+    /// Set the maximum number of results kept by [`crate::Runtime::call_cached`]
+    ///
+    /// See [`crate::RuntimeOptions::call_cache_capacity`]
+    #[must_use]
+    pub fn with_call_cache_capacity(mut self, call_cache_capacity: usize) -> Self {
+        self.0.call_cache_capacity = call_cache_capacity;
+        self
+    }
+
+    /// Set the maximum number of remote modules fetched at once when resolving imports
+    #[must_use]
+    pub fn with_max_concurrent_fetches(mut self, max_concurrent_fetches: usize) -> Self {
+        self.0.max_concurrent_fetches = max_concurrent_fetches;
+        self
+    }
+
+    /// Set the HTTP client used to fetch remote modules
+    ///
+    /// Use this to configure proxies, timeouts, authorization headers, or custom TLS roots
+    /// Redirects are followed by the loader, so the client should not follow them itself - see [`RuntimeOptions::module_fetch_client`]
+    #[cfg(feature = "url_import")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "url_import")))]
+    #[must_use]
+    pub fn with_module_fetch_client(mut self, client: reqwest::Client) -> Self {
+        self.0.module_fetch_client = Some(client);
+        self
+    }
+
+    /// Set the source of credentials for remote module fetches, such as per-host authorization headers
+    #[cfg(feature = "url_import")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "url_import")))]
+    #[must_use]
+    pub fn with_module_fetch_credentials(
+        mut self,
+        credentials: std::sync::Arc<dyn crate::module_loader::FetchCredentials>,
+    ) -> Self {
+        self.0.module_fetch_credentials = Some(credentials);
+        self
+    }
+
     /// Optional import provider for the module loader
     #[must_use]
     pub fn with_import_provider(mut self, import_provider: Box<dyn ImportProvider>) -> Self {
@@ -78,6 +226,16 @@ impl RuntimeBuilder {
         self
     }
 
+    /// Set a transformation applied to the code of every javascript module loaded by the runtime
+    #[must_use]
+    pub fn with_source_transform(
+        mut self,
+        source_transform: std::sync::Arc<dyn crate::module_loader::SourceTransform>,
+    ) -> Self {
+        self.0.source_transform = Some(source_transform);
+        self
+    }
+
     /// Set the startup snapshot for the runtime
     ///
     /// This will reduce load times, but requires the same extensions to be loaded as when the snapshot was created
@@ -163,6 +321,15 @@ impl RuntimeBuilder {
         self
     }
 
+    /// Set the directory of a sqlite cache, so that it is included by [`crate::Runtime::export_state`]
+    #[cfg(feature = "cache")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "cache")))]
+    #[must_use]
+    pub fn with_cache_storage_dir(mut self, dir: std::path::PathBuf) -> Self {
+        self.0.extension_options.cache_storage_dir = Some(dir);
+        self
+    }
+
     /// Set the options for the broadcast channel extension
     #[cfg(feature = "broadcast_channel")]
     #[cfg_attr(docsrs, doc(cfg(feature = "broadcast_channel")))]
@@ -236,6 +403,17 @@ impl RuntimeBuilder {
         self
     }
 
+    /// Proxy settings for fetch, with credentials and a no-proxy list
+    ///
+    /// See [`crate::ProxyConfig`]
+    #[cfg(feature = "web")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "web")))]
+    #[must_use]
+    pub fn with_proxy_config(mut self, proxy: crate::ext::web::ProxyConfig) -> Self {
+        self.0.extension_options.web.proxy_config = Some(proxy);
+        self
+    }
+
     /// Request builder hook for fetch
     #[cfg(feature = "web")]
     #[cfg_attr(docsrs, doc(cfg(feature = "web")))]
@@ -306,6 +484,32 @@ impl RuntimeBuilder {
         self
     }
 
+    /// Hook mapping hostnames to addresses, or rejecting them, before connecting
+    ///
+    /// See [`crate::WebOptions::dns_resolver`]
+    #[cfg(feature = "web")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "web")))]
+    #[must_use]
+    pub fn with_dns_resolver(
+        mut self,
+        resolver: std::sync::Arc<dyn crate::ext::web::DnsResolver>,
+    ) -> Self {
+        self.0.extension_options.web.dns_resolver = Some(resolver);
+        self
+    }
+
+    /// Block connections to private, loopback, link-local and cloud metadata addresses
+    ///
+    /// Applied on top of any resolver set with [`Self::with_dns_resolver`] - call this afterwards
+    /// See [`crate::SsrfProtection`]
+    #[cfg(feature = "web")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "web")))]
+    #[must_use]
+    pub fn with_ssrf_protection(mut self) -> Self {
+        self.0.extension_options.web.enable_ssrf_protection();
+        self
+    }
+
     /// Blob store for the web related extensions
     #[cfg(feature = "web")]
     #[cfg_attr(docsrs, doc(cfg(feature = "web")))]
@@ -0,0 +1,591 @@
+//! Serving HTTP requests with javascript handlers, running across a pool of workers
+//!
+//! The server is not tied to an HTTP library - requests from any framework are converted into a [`ScriptRequest`],
+//! passed as the only argument of the handler function, and its return value converted back into a [`ScriptResponse`]
+//!
+//! Requests are queued for a [`WorkerPool`], and each is handed to an idle worker, waiting for one if all are busy
+//! The handler runs with a timeout - a worker that stops, or runs past the timeout, is replaced before it is handed the next request
+//!
+//! A handler may return `{ status, headers, body }` to control the response - any other value is sent as a JSON body, with status 200
+//!
+//! ```rust
+//! use rustyscript::{ server::{ ScriptRequest, ScriptServer, ScriptServerOptions }, Module, Error };
+//!
+//! # fn main() -> Result<(), Error> {
+//! let module = Module::new("handler.js", "
+//!     export const handle = (req) => req.method === 'POST'
+//!         ? { status: 201, body: { created: req.body.name } }
+//!         : { hello: req.path };
+//! ");
+//! let server = ScriptServer::new(module, ScriptServerOptions::default())?;
+//!
+//! let response = server.handle(ScriptRequest::new("GET", "/world"));
+//! assert_eq!(response.status, 200);
+//! assert_eq!(response.body, rustyscript::serde_json::json!({ "hello": "/world" }));
+//!
+//! let request = ScriptRequest::new("POST", "/users").with_body_bytes(br#"{ "name": "bob" }"#)?;
+//! let response = server.handle(request);
+//! assert_eq!(response.status, 201);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! From an async framework such as `axum`, use [`ScriptServer::handle_async`], which keeps the handler off the executor's threads:
+//! ```rust,ignore
+//! async fn route(State(server): State<ScriptServer>, method: Method, uri: Uri, body: Bytes) -> impl IntoResponse {
+//!     let request = ScriptRequest::new(method.as_str(), uri.path()).with_query(uri.query());
+//!     let response = match request.with_body_bytes(&body) {
+//!         Ok(request) => server.handle_async(request).await,
+//!         Err(e) => ScriptResponse::from_error(&e),
+//!     };
+//!     (StatusCode::from_u16(response.status).unwrap(), response.body_bytes())
+//! }
+//! ```
+use crate::{
+    serde_json,
+    worker::{
+        DefaultWorker, DefaultWorkerOptions, DefaultWorkerQuery, DefaultWorkerResponse,
+        WorkerHealth, WorkerPool,
+    },
+    Error, Module,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// Options for a [`ScriptServer`]
+#[derive(Clone)]
+pub struct ScriptServerOptions {
+    /// Options for each worker in the pool
+    /// The handler's module is added to [`DefaultWorkerOptions::preload_modules`]
+    pub worker_options: DefaultWorkerOptions,
+
+    /// Number of workers in the pool, and so the number of requests handled at once
+    ///
+    /// Default: 4
+    pub workers: u32,
+
+    /// Name of the function exported by the module, that handles each request
+    ///
+    /// Default: `handle`
+    pub handler: String,
+
+    /// Maximum time spent running the handler for a request
+    /// Timed out requests get a 504 response
+    ///
+    /// Default: 30s
+    pub request_timeout: Duration,
+
+    /// Maximum time a request waits for a worker to become available
+    /// Requests that cannot get one in time fail with [`Error::WorkerUnavailable`], and get a 503 response
+    ///
+    /// Default: 30s
+    pub checkout_timeout: Duration,
+}
+
+impl Default for ScriptServerOptions {
+    fn default() -> Self {
+        Self {
+            worker_options: DefaultWorkerOptions::default(),
+            workers: 4,
+            handler: "handle".to_string(),
+            request_timeout: Duration::from_secs(30),
+            checkout_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// An HTTP request, as seen by the handler function
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScriptRequest {
+    /// The request method, such as `GET`
+    pub method: String,
+
+    /// The path of the request, without the query string
+    pub path: String,
+
+    /// The query string, without the leading `?`
+    pub query: Option<String>,
+
+    /// The request headers - names are lowercase
+    pub headers: BTreeMap<String, String>,
+
+    /// The request body, decoded from JSON - `null` if there was none
+    pub body: serde_json::Value,
+}
+
+impl ScriptRequest {
+    /// Creates a request with no query, headers or body
+    #[must_use]
+    pub fn new(method: &str, path: &str) -> Self {
+        Self {
+            method: method.to_uppercase(),
+            path: path.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Sets the query string
+    #[must_use]
+    pub fn with_query(mut self, query: Option<&str>) -> Self {
+        self.query = query.map(str::to_string);
+        self
+    }
+
+    /// Adds a header, replacing any with the same name
+    #[must_use]
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.insert(name.to_lowercase(), value.to_string());
+        self
+    }
+
+    /// Sets the body
+    #[must_use]
+    pub fn with_body(mut self, body: serde_json::Value) -> Self {
+        self.body = body;
+        self
+    }
+
+    /// Sets the body from the raw bytes of a JSON request body
+    /// An empty body becomes `null`
+    ///
+    /// # Errors
+    /// Will return an error if the body is not valid JSON
+    pub fn with_body_bytes(mut self, body: &[u8]) -> Result<Self, Error> {
+        self.body = if body.iter().all(u8::is_ascii_whitespace) {
+            serde_json::Value::Null
+        } else {
+            serde_json::from_slice(body)?
+        };
+        Ok(self)
+    }
+}
+
+/// An HTTP response, built from the value returned by the handler function
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScriptResponse {
+    /// The response status code
+    pub status: u16,
+
+    /// The response headers
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+
+    /// The response body, sent as JSON
+    #[serde(default)]
+    pub body: serde_json::Value,
+}
+
+impl ScriptResponse {
+    /// Creates a response with no headers
+    #[must_use]
+    pub fn new(status: u16, body: serde_json::Value) -> Self {
+        Self {
+            status,
+            headers: BTreeMap::new(),
+            body,
+        }
+    }
+
+    /// Converts the value returned by a handler
+    ///
+    /// Objects with a numeric `status` are read as `{ status, headers, body }` - any other value becomes the body of a 200 response
+    #[must_use]
+    pub fn from_value(value: serde_json::Value) -> Self {
+        if value.get("status").is_some_and(serde_json::Value::is_u64) {
+            if let Ok(response) = serde_json::from_value(value.clone()) {
+                return response;
+            }
+        }
+
+        Self::new(200, value)
+    }
+
+    /// Converts an error into a response with a JSON body of `{ error }`
+    ///
+    /// - [`Error::Timeout`] becomes 504
+    /// - [`Error::WorkerHasStopped`] and [`Error::WorkerUnavailable`] become 503
+    /// - JSON decoding errors, such as an invalid request body, become 400
+    /// - Anything else becomes 500
+    #[must_use]
+    pub fn from_error(error: &Error) -> Self {
+        let status = match error {
+            Error::Timeout { .. } => 504,
+            Error::WorkerHasStopped | Error::WorkerUnavailable(_) => 503,
+            Error::JsonDecode(_) => 400,
+            _ => 500,
+        };
+        Self::new(status, serde_json::json!({ "error": error.to_string() }))
+    }
+
+    /// Returns the body, encoded as JSON
+    #[must_use]
+    pub fn body_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(&self.body).unwrap_or_default()
+    }
+}
+
+/// Serves HTTP requests with a javascript handler, see the [module docs](self)
+///
+/// The server can be cloned and shared between threads - clones use the same pool of workers
+#[derive(Clone)]
+pub struct ScriptServer(Arc<ServerState>);
+
+struct ServerState {
+    jobs: Mutex<Sender<Job>>,
+    idle: Arc<AtomicUsize>,
+    module_name: String,
+    options: ScriptServerOptions,
+}
+
+/// Where to send the result of a request
+type Reply = Sender<Result<DefaultWorkerResponse, Error>>;
+
+/// A request waiting for a worker
+struct Job {
+    query: DefaultWorkerQuery,
+    queued: Instant,
+    reply: Reply,
+}
+
+/// How often the dispatcher checks on busy workers
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+impl ScriptServer {
+    /// Starts the workers, and loads the handler's module into each of them
+    ///
+    /// # Errors
+    /// Will return an error if a worker cannot be started, or the module fails to load
+    pub fn new(module: Module, options: ScriptServerOptions) -> Result<Self, Error> {
+        let module_name = module.filename().to_string_lossy().to_string();
+        let mut worker_options = options.worker_options.clone();
+        worker_options.preload_modules.push(module);
+
+        // The pool cannot leave the thread it was created on, so it is created by the dispatcher
+        let (jobs, receiver) = channel();
+        let (started, startup) = channel();
+        let idle = Arc::new(AtomicUsize::new(options.workers as usize));
+        let dispatcher = Dispatcher {
+            jobs: receiver,
+            idle: Arc::clone(&idle),
+            options: options.clone(),
+        };
+        let workers = options.workers;
+        std::thread::spawn(move || {
+            match WorkerPool::<DefaultWorker>::new(worker_options, workers) {
+                Ok(pool) => {
+                    started.send(Ok(())).ok();
+                    dispatcher.run(pool);
+                }
+                Err(e) => {
+                    started.send(Err(e)).ok();
+                }
+            }
+        });
+        startup.recv().map_err(|_| Error::WorkerHasStopped)??;
+
+        Ok(Self(Arc::new(ServerState {
+            jobs: Mutex::new(jobs),
+            idle,
+            module_name,
+            options,
+        })))
+    }
+
+    /// Returns the options the server was created with
+    #[must_use]
+    pub fn options(&self) -> &ScriptServerOptions {
+        &self.0.options
+    }
+
+    /// Returns the number of workers not currently handling a request
+    #[must_use]
+    pub fn idle_workers(&self) -> usize {
+        self.0.idle.load(Ordering::SeqCst)
+    }
+
+    /// Handles a request, blocking the current thread until the handler returns
+    /// Errors are converted with [`ScriptResponse::from_error`]
+    #[must_use]
+    pub fn handle(&self, request: ScriptRequest) -> ScriptResponse {
+        match self.call(request) {
+            Ok(response) => response,
+            Err(e) => ScriptResponse::from_error(&e),
+        }
+    }
+
+    /// Handles a request on tokio's blocking threads, see [`ScriptServer::handle`]
+    ///
+    /// Must be called from within a tokio runtime
+    pub async fn handle_async(&self, request: ScriptRequest) -> ScriptResponse {
+        let server = self.clone();
+        tokio::task::spawn_blocking(move || server.handle(request))
+            .await
+            .unwrap_or_else(|e| {
+                ScriptResponse::from_error(&Error::Runtime(format!("Request handler failed: {e}")))
+            })
+    }
+
+    /// Handles a request, returning errors rather than converting them into responses
+    ///
+    /// # Errors
+    /// Will return [`Error::WorkerUnavailable`] if no worker became available in time,
+    /// or an error if the handler fails or times out, or if the request cannot be converted into a javascript value
+    pub fn call(&self, request: ScriptRequest) -> Result<ScriptResponse, Error> {
+        let (reply, result) = channel();
+        let job = Job {
+            query: DefaultWorkerQuery::CallFunctionByName {
+                module_name: Some(self.0.module_name.clone()),
+                name: self.0.options.handler.clone(),
+                args: vec![serde_json::to_value(request)?],
+            },
+            queued: Instant::now(),
+            reply,
+        };
+        self.0
+            .jobs
+            .lock()
+            .map_err(|_| Error::Runtime("Server worker pool is poisoned".to_string()))?
+            .send(job)
+            .map_err(|_| Error::WorkerHasStopped)?;
+
+        match result.recv().map_err(|_| Error::WorkerHasStopped)?? {
+            DefaultWorkerResponse::Value(v) => Ok(ScriptResponse::from_value(v)),
+            DefaultWorkerResponse::Error(e) => Err(e),
+            _ => Err(Error::Runtime(
+                "Unexpected response from the worker".to_string(),
+            )),
+        }
+    }
+}
+
+/// Owns the [`WorkerPool`], handing queued requests to idle workers and collecting their responses
+/// Runs until every clone of the server is dropped
+struct Dispatcher {
+    jobs: Receiver<Job>,
+    idle: Arc<AtomicUsize>,
+    options: ScriptServerOptions,
+}
+
+impl Dispatcher {
+    fn run(self, mut pool: WorkerPool<DefaultWorker>) {
+        let mut queue = VecDeque::new();
+        let mut in_flight: Vec<Option<Reply>> = (0..pool.len()).map(|_| None).collect();
+        let mut alive = vec![true; pool.len()];
+
+        loop {
+            // Sleep until a request arrives, unless there is work to check on
+            let waiting = queue.is_empty() && in_flight.iter().all(Option::is_none);
+            let received = if waiting {
+                self.jobs.recv().map_err(|_| RecvTimeoutError::Disconnected)
+            } else {
+                self.jobs.recv_timeout(POLL_INTERVAL)
+            };
+            match received {
+                Ok(job) => queue.push_back(job),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            queue.extend(self.jobs.try_iter());
+
+            self.collect(&mut pool, &mut in_flight, &mut alive);
+            self.expire(&mut queue);
+            self.assign(&mut pool, &mut queue, &mut in_flight, &mut alive);
+        }
+
+        pool.shutdown();
+    }
+
+    /// Sends the responses of finished workers, and replaces those that stopped or ran past the timeout
+    fn collect(
+        &self,
+        pool: &mut WorkerPool<DefaultWorker>,
+        in_flight: &mut [Option<Reply>],
+        alive: &mut [bool],
+    ) {
+        let timeout = self.options.request_timeout;
+        let health = pool.health(timeout);
+        for id in 0..in_flight.len() {
+            if in_flight[id].is_none() {
+                continue;
+            }
+            let Some(worker) = pool.worker_by_id(id) else {
+                continue;
+            };
+
+            let received = worker.borrow().try_receive();
+            let result = match (received, health[id]) {
+                (Ok(Some(response)), _) => Ok(response),
+                (Ok(None), WorkerHealth::Wedged(elapsed)) => Err(Error::Timeout {
+                    elapsed,
+                    limit: timeout,
+                }),
+                (Ok(None), WorkerHealth::Stopped) | (Err(_), _) => Err(Error::WorkerHasStopped),
+                (Ok(None), _) => continue,
+            };
+
+            // A stopped or timed out worker may be in a bad state, so it is not reused
+            // If it cannot be replaced, the pool shrinks - queued requests time out if it empties
+            if result.is_err() {
+                alive[id] = pool.replace_worker(id).is_ok();
+            }
+
+            // The worker is counted as idle before the caller gets its response
+            if alive[id] {
+                self.idle.fetch_add(1, Ordering::SeqCst);
+            }
+            if let Some(reply) = in_flight[id].take() {
+                reply.send(result).ok();
+            }
+        }
+    }
+
+    /// Fails requests that have waited longer than the checkout timeout
+    fn expire(&self, queue: &mut VecDeque<Job>) {
+        let timeout = self.options.checkout_timeout;
+        while queue
+            .front()
+            .is_some_and(|job| job.queued.elapsed() >= timeout)
+        {
+            if let Some(job) = queue.pop_front() {
+                job.reply.send(Err(Error::WorkerUnavailable(timeout))).ok();
+            }
+        }
+    }
+
+    /// Hands queued requests to idle workers
+    fn assign(
+        &self,
+        pool: &mut WorkerPool<DefaultWorker>,
+        queue: &mut VecDeque<Job>,
+        in_flight: &mut [Option<Reply>],
+        alive: &mut [bool],
+    ) {
+        for id in 0..in_flight.len() {
+            if !alive[id] || in_flight[id].is_some() {
+                continue;
+            }
+            let Some(worker) = pool.worker_by_id(id) else {
+                continue;
+            };
+            let Some(Job { query, reply, .. }) = queue.pop_front() else {
+                break;
+            };
+
+            let sent = worker.borrow().send(query);
+            match sent {
+                Ok(()) => {
+                    self.idle.fetch_sub(1, Ordering::SeqCst);
+                    in_flight[id] = Some(reply);
+                }
+                Err(e) => {
+                    alive[id] = pool.replace_worker(id).is_ok();
+                    if !alive[id] {
+                        self.idle.fetch_sub(1, Ordering::SeqCst);
+                    }
+                    reply.send(Err(e)).ok();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    const MODULE: &str = "
+        export const handle = (req) => {
+            switch (req.path) {
+                case '/echo': return { status: 201, headers: { 'x-method': req.method }, body: req.body };
+                case '/query': return req.query;
+                case '/fail': throw new Error('handler failed');
+                case '/slow': while (true) {}
+                default: return { path: req.path, auth: req.headers['authorization'] ?? null };
+            }
+        };
+    ";
+
+    #[test]
+    fn test_script_server() {
+        let options = ScriptServerOptions {
+            workers: 2,
+            request_timeout: Duration::from_millis(500),
+            ..Default::default()
+        };
+        let server = ScriptServer::new(Module::new("handler.js", MODULE), options).unwrap();
+
+        let response =
+            server.handle(ScriptRequest::new("get", "/").with_header("Authorization", "token"));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, json!({ "path": "/", "auth": "token" }));
+
+        let request = ScriptRequest::new("POST", "/echo")
+            .with_body_bytes(br#"{ "a": [1, 2] }"#)
+            .unwrap();
+        let response = server.handle(request);
+        assert_eq!(response.status, 201);
+        assert_eq!(response.headers["x-method"], "POST");
+        assert_eq!(response.body_bytes(), br#"{"a":[1,2]}"#);
+
+        let response = server.handle(ScriptRequest::new("GET", "/query").with_query(Some("a=1")));
+        assert_eq!(response.body, json!("a=1"));
+
+        let response = server.handle(ScriptRequest::new("GET", "/fail"));
+        assert_eq!(response.status, 500);
+
+        let response = server.handle(ScriptRequest::new("GET", "/slow"));
+        assert_eq!(response.status, 504);
+
+        // Requests from several threads share the pool, including the replaced worker
+        std::thread::scope(|s| {
+            for i in 0..4 {
+                let server = server.clone();
+                s.spawn(move || {
+                    let response = server.handle(ScriptRequest::new("GET", &format!("/{i}")));
+                    assert_eq!(response.body["path"], format!("/{i}"));
+                });
+            }
+        });
+        assert_eq!(server.idle_workers(), 2);
+    }
+
+    #[test]
+    fn test_checkout_timeout() {
+        let options = ScriptServerOptions {
+            workers: 1,
+            request_timeout: Duration::from_secs(1),
+            checkout_timeout: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let server = ScriptServer::new(Module::new("handler.js", MODULE), options).unwrap();
+
+        // The only worker is busy for longer than the checkout timeout
+        std::thread::scope(|s| {
+            let slow = s.spawn(|| server.handle(ScriptRequest::new("GET", "/slow")));
+            while server.idle_workers() > 0 {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+
+            let e = server
+                .call(ScriptRequest::new("GET", "/"))
+                .expect_err("Request was given a busy worker");
+            assert!(matches!(e, Error::WorkerUnavailable(_)), "{e}");
+            assert_eq!(ScriptResponse::from_error(&e).status, 503);
+
+            assert_eq!(slow.join().unwrap().status, 504);
+        });
+
+        // The replaced worker handles the next request
+        let response = server.handle(ScriptRequest::new("GET", "/"));
+        assert_eq!(response.status, 200);
+    }
+}
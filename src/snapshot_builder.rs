@@ -91,8 +91,7 @@ impl SnapshotBuilder {
     ///
     pub fn new(options: RuntimeOptions) -> Result<Self, Error> {
         let tokio = AsyncBridge::new(options.timeout)?;
-        let inner = InnerRuntime::new(options, tokio.heap_exhausted_token())?;
-        Ok(Self { inner, tokio })
+        Self::with_bridge(options, tokio)
     }
 
     /// Creates a new instance of the runtime with the provided options and a pre-configured tokio runtime.
@@ -105,7 +104,15 @@ impl SnapshotBuilder {
         tokio: Rc<tokio::runtime::Runtime>,
     ) -> Result<Self, Error> {
         let tokio = AsyncBridge::with_tokio_runtime(options.timeout, tokio);
-        let inner = InnerRuntime::new(options, tokio.heap_exhausted_token())?;
+        Self::with_bridge(options, tokio)
+    }
+
+    fn with_bridge(options: RuntimeOptions, mut tokio: AsyncBridge) -> Result<Self, Error> {
+        let preemption_interval = options.preemption_interval;
+        let mut inner = InnerRuntime::new(options, tokio.heap_exhausted_token())?;
+
+        let isolate = inner.deno_runtime().v8_isolate().thread_safe_handle();
+        tokio.enable_preemption(isolate, preemption_interval)?;
         Ok(Self { inner, tokio })
     }
 
@@ -130,7 +137,7 @@ impl SnapshotBuilder {
     /// Used to detect when the runtime has run out of memory
     #[must_use]
     pub fn heap_exhausted_token(&self) -> CancellationToken {
-        self.tokio.heap_exhausted_token()
+        self.tokio.heap_exhausted_token().token()
     }
 
     /// Destroy the v8 runtime, releasing all resources
@@ -804,6 +811,18 @@ impl SnapshotBuilder {
         let deno_rt: JsRuntimeForSnapshot = self.inner.into_inner();
         deno_rt.snapshot()
     }
+
+    /// Consumes the runtime and returns a checkpoint of its state, which can be restored into new runtimes
+    ///
+    /// Unlike [`SnapshotBuilder::finish`], the checkpoint can be used directly, without being written to a file
+    /// The handles given are restored along with each runtime - see [`crate::Checkpoint::restore`]
+    ///
+    /// Entrypoints registered with `rustyscript.register_entrypoint` are not restored,
+    /// but default exports and the default entrypoint still apply
+    #[must_use]
+    pub fn into_checkpoint(self, handles: &[ModuleHandle]) -> crate::Checkpoint {
+        crate::Checkpoint::new(self.finish(), handles)
+    }
 }
 
 impl AsyncBridgeExt for SnapshotBuilder {
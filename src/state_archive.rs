@@ -0,0 +1,401 @@
+//! Portable archives of the data persisted by a runtime's scripts, see [`StateArchive`]
+use crate::{Error, RuntimeOptions};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// Identifies the archive format, and its version
+const MAGIC: &[u8; 8] = b"RSSTATE1";
+
+/// The data persisted by a runtime's scripts, bundled into a single portable archive
+///
+/// Created by [`crate::Runtime::export_state`], which collects the files of every configured storage directory:
+/// - `webstorage`: [`crate::ExtensionOptions::webstorage_origin_storage_dir`], holding `localStorage`
+/// - `kv`: the directory of a local [`crate::KvStore`], holding the databases opened by `Deno.openKv`
+/// - `cache`: [`crate::ExtensionOptions::cache_storage_dir`], holding a sqlite `CacheStorage`
+///
+/// An archive is restored with [`crate::Runtime::import_state`], which writes the files into the storage directories
+/// of the new runtime's options - so a tenant's state can be moved to other paths, or another host
+///
+/// The stores are sqlite databases, so state should be exported while scripts are not writing to them
+///
+/// ```rust
+/// use rustyscript::{ Runtime, RuntimeOptions, StateArchive };
+///
+/// # fn main() -> Result<(), rustyscript::Error> {
+/// // Options setting the storage directories, such as `webstorage_origin_storage_dir`
+/// let options = RuntimeOptions::default;
+///
+/// let runtime = Runtime::new(options())?;
+/// let bytes = runtime.export_state()?.to_bytes();
+/// drop(runtime);
+///
+/// // Later, possibly on another host
+/// let archive = StateArchive::from_bytes(&bytes)?;
+/// let runtime = Runtime::import_state(&archive, options())?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StateArchive {
+    /// Files, keyed by the label of their store and their path relative to its directory
+    files: BTreeMap<(String, String), Vec<u8>>,
+}
+
+impl StateArchive {
+    /// Create a new, empty archive
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns true if the archive contains no files
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Returns the labels of the stores in the archive, such as `webstorage` or `kv`
+    #[must_use]
+    pub fn labels(&self) -> Vec<&str> {
+        let mut labels: Vec<&str> = self.files.keys().map(|(label, _)| label.as_str()).collect();
+        labels.dedup();
+        labels
+    }
+
+    /// Returns the paths of the files stored under a label, relative to the store's directory
+    pub fn files<'a>(&'a self, label: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.files
+            .keys()
+            .filter(move |(l, _)| l == label)
+            .map(|(_, path)| path.as_str())
+    }
+
+    /// Add every file in a directory, and its subdirectories, under the given label
+    ///
+    /// Sqlite shared-memory files (`-shm`) are skipped, since sqlite recreates them
+    /// A directory that does not exist yet is treated as empty
+    ///
+    /// # Errors
+    /// Will return an error if the directory cannot be read
+    pub fn add_dir(&mut self, label: &str, dir: impl AsRef<Path>) -> Result<(), Error> {
+        let dir = dir.as_ref();
+        if !dir.exists() {
+            return Ok(());
+        }
+
+        let mut pending = vec![dir.to_path_buf()];
+        while let Some(current) = pending.pop() {
+            for entry in std::fs::read_dir(&current)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    pending.push(path);
+                    continue;
+                }
+
+                let relative = path
+                    .strip_prefix(dir)
+                    .map_err(|e| Error::Runtime(e.to_string()))?
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                if relative.ends_with("-shm") {
+                    continue;
+                }
+
+                let data = std::fs::read(&path)?;
+                self.files.insert((label.to_string(), relative), data);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write the files stored under a label into a directory, creating it if needed
+    ///
+    /// Existing files with the same names are replaced, along with any sqlite journals left beside them
+    /// Files are never written through a symlink that leads outside of the directory
+    ///
+    /// # Errors
+    /// Will return an error if a file cannot be written, or would be written outside of the directory
+    pub fn restore_dir(&self, label: &str, dir: impl AsRef<Path>) -> Result<(), Error> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+        let root = dir.canonicalize()?;
+
+        // Checked up front, so a rejected archive leaves the directory untouched
+        for path in self.files(label) {
+            if !resolve(&dir.join(path))?.starts_with(&root) {
+                return Err(Error::Runtime(format!(
+                    "State archive path leads outside of {}: {path}",
+                    dir.display()
+                )));
+            }
+        }
+
+        for path in self.files(label) {
+            let target = dir.join(path);
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+
+            for suffix in ["-wal", "-shm"] {
+                let journal = format!("{path}{suffix}");
+                let journal_path = dir.join(&journal);
+                if journal_path.exists() && !self.files.contains_key(&(label.to_string(), journal))
+                {
+                    std::fs::remove_file(journal_path)?;
+                }
+            }
+
+            std::fs::write(target, &self.files[&(label.to_string(), path.to_string())])?;
+        }
+
+        Ok(())
+    }
+
+    /// Restore the archive into the storage directories configured in a runtime's options
+    ///
+    /// Stores without a directory configured in the options are skipped
+    /// Called by [`crate::Runtime::import_state`], before the runtime is created
+    ///
+    /// # Errors
+    /// Will return an error if a file cannot be written
+    pub fn restore(&self, options: &RuntimeOptions) -> Result<(), Error> {
+        for (label, dir) in storage_dirs(&options.extension_options) {
+            self.restore_dir(label, dir)?;
+        }
+        Ok(())
+    }
+
+    /// Encode the archive as bytes
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend((self.files.len() as u64).to_le_bytes());
+        for ((label, path), data) in &self.files {
+            for field in [label.as_bytes(), path.as_bytes(), data] {
+                bytes.extend((field.len() as u64).to_le_bytes());
+                bytes.extend(field);
+            }
+        }
+        bytes
+    }
+
+    /// Decode an archive created by [`StateArchive::to_bytes`]
+    ///
+    /// # Errors
+    /// Will return an error if the bytes are not a valid archive,
+    /// or if a path in the archive would be written outside of its store's directory
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let rest = bytes.strip_prefix(MAGIC).ok_or_else(invalid_archive)?;
+        let mut reader = Reader(rest);
+
+        let mut files = BTreeMap::new();
+        let count = reader.read_len()?;
+        for _ in 0..count {
+            let label = reader.string()?;
+            let path = reader.string()?;
+            let data = reader.field()?.to_vec();
+
+            let safe = !path.is_empty()
+                && path.split('/').all(|part| {
+                    !part.is_empty() && part != "." && part != ".." && !part.contains('\\')
+                })
+                && Path::new(&path).is_relative();
+            if !safe {
+                return Err(Error::Runtime(format!(
+                    "Unsafe path in state archive: {path}"
+                )));
+            }
+
+            files.insert((label, path), data);
+        }
+
+        Ok(Self { files })
+    }
+}
+
+/// Resolves a path that may not exist yet, following any symlinks along the way
+/// The nearest existing ancestor is canonicalized, so the result can be compared against another canonical path
+/// regardless of links, case, or platform prefixes
+fn resolve(path: &Path) -> Result<PathBuf, Error> {
+    let mut existing = path;
+    let mut missing = Vec::new();
+    while existing.symlink_metadata().is_err() {
+        missing.push(existing.file_name().unwrap_or_default());
+        existing = existing
+            .parent()
+            .ok_or_else(|| Error::Runtime(format!("Cannot resolve {}", path.display())))?;
+    }
+
+    let mut resolved = existing.canonicalize()?;
+    resolved.extend(missing.into_iter().rev());
+    Ok(resolved)
+}
+
+fn invalid_archive() -> Error {
+    Error::Runtime("Invalid state archive".to_string())
+}
+
+/// Reads the length-prefixed fields of an encoded archive
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.0.len() < len {
+            return Err(invalid_archive());
+        }
+        let (field, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(field)
+    }
+
+    fn read_len(&mut self) -> Result<usize, Error> {
+        let len = self.take(8)?.try_into().map_err(|_| invalid_archive())?;
+        usize::try_from(u64::from_le_bytes(len)).map_err(|_| invalid_archive())
+    }
+
+    fn field(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.read_len()?;
+        self.take(len)
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        String::from_utf8(self.field()?.to_vec()).map_err(|_| invalid_archive())
+    }
+}
+
+/// Returns the storage directories configured in a runtime's extension options, with their labels
+pub(crate) fn storage_dirs(options: &crate::ExtensionOptions) -> Vec<(&'static str, PathBuf)> {
+    #[allow(unused_mut)]
+    let mut dirs = Vec::new();
+
+    #[cfg(feature = "webstorage")]
+    if let Some(dir) = &options.webstorage_origin_storage_dir {
+        dirs.push(("webstorage", dir.clone()));
+    }
+
+    #[cfg(feature = "kv")]
+    if let Some(dir) = options.kv_store.local_path() {
+        dirs.push(("kv", dir.to_path_buf()));
+    }
+
+    #[cfg(feature = "cache")]
+    if let Some(dir) = &options.cache_storage_dir {
+        dirs.push(("cache", dir.clone()));
+    }
+
+    let _ = options;
+    dirs
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rustyscript_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_state_archive() {
+        let source = temp_dir("state_source");
+        std::fs::create_dir_all(source.join("nested")).unwrap();
+        std::fs::write(source.join("db.sqlite3"), b"database").unwrap();
+        std::fs::write(source.join("db.sqlite3-shm"), b"shared memory").unwrap();
+        std::fs::write(source.join("nested/response"), b"body").unwrap();
+
+        let mut archive = StateArchive::new();
+        archive.add_dir("kv", &source).unwrap();
+        archive.add_dir("missing", source.join("missing")).unwrap();
+        assert_eq!(archive.labels(), vec!["kv"]);
+        assert_eq!(
+            archive.files("kv").collect::<Vec<_>>(),
+            vec!["db.sqlite3", "nested/response"]
+        );
+
+        let decoded = StateArchive::from_bytes(&archive.to_bytes()).unwrap();
+        assert_eq!(decoded, archive);
+
+        // Stale journals are removed on restore
+        let target = temp_dir("state_target");
+        std::fs::create_dir_all(&target).unwrap();
+        std::fs::write(target.join("db.sqlite3-wal"), b"stale").unwrap();
+        decoded.restore_dir("kv", &target).unwrap();
+        assert_eq!(
+            std::fs::read(target.join("db.sqlite3")).unwrap(),
+            b"database"
+        );
+        assert_eq!(
+            std::fs::read(target.join("nested/response")).unwrap(),
+            b"body"
+        );
+        assert!(!target.join("db.sqlite3-wal").exists());
+
+        StateArchive::from_bytes(b"not an archive").expect_err("Invalid archive was decoded");
+        let mut unsafe_archive = StateArchive::new();
+        unsafe_archive
+            .files
+            .insert(("kv".to_string(), "../escape".to_string()), vec![]);
+        StateArchive::from_bytes(&unsafe_archive.to_bytes())
+            .expect_err("Archive with an unsafe path was decoded");
+
+        // Symlinks inside the directory are not followed outside of it
+        #[cfg(unix)]
+        {
+            let outside = temp_dir("state_outside");
+            std::fs::create_dir_all(&outside).unwrap();
+            let linked = temp_dir("state_linked");
+            std::fs::create_dir_all(&linked).unwrap();
+            std::os::unix::fs::symlink(&outside, linked.join("nested")).unwrap();
+
+            decoded
+                .restore_dir("kv", &linked)
+                .expect_err("Archive was restored through a symlink");
+            assert!(!outside.join("response").exists());
+            assert!(!linked.join("db.sqlite3").exists());
+
+            let _ = std::fs::remove_dir_all(outside);
+            let _ = std::fs::remove_dir_all(linked);
+        }
+
+        let _ = std::fs::remove_dir_all(source);
+        let _ = std::fs::remove_dir_all(target);
+    }
+
+    #[cfg(feature = "webstorage")]
+    #[test]
+    fn test_export_import_state() {
+        let options = |dir: PathBuf| RuntimeOptions {
+            extension_options: crate::ExtensionOptions {
+                webstorage_origin_storage_dir: Some(dir),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let source = temp_dir("webstorage_source");
+        let mut runtime = crate::Runtime::new(options(source.clone())).unwrap();
+        runtime
+            .eval::<crate::Undefined>("localStorage.setItem('visits', '3')")
+            .unwrap();
+        let archive = runtime.export_state().unwrap();
+        drop(runtime);
+        assert_eq!(archive.labels(), vec!["webstorage"]);
+
+        let target = temp_dir("webstorage_target");
+        let archive = StateArchive::from_bytes(&archive.to_bytes()).unwrap();
+        let mut runtime = crate::Runtime::import_state(&archive, options(target.clone())).unwrap();
+        let visits: String = runtime.eval("localStorage.getItem('visits')").unwrap();
+        assert_eq!(visits, "3");
+
+        drop(runtime);
+        let _ = std::fs::remove_dir_all(source);
+        let _ = std::fs::remove_dir_all(target);
+    }
+}
@@ -0,0 +1,531 @@
+//! A background job runner, executing javascript functions across a pool of workers
+//!
+//! Jobs name a function, and optionally the module providing it - modules are loaded into every worker
+//! through [`DefaultWorkerOptions::preload_modules`], so jobs refer to them by filename
+//!
+//! Jobs are queued, and run as workers become available, up to a concurrency limit
+//! A job that fails, or runs past its timeout, is retried up to its retry limit - a timed out worker is replaced
+//!
+//! ```rust
+//! use rustyscript::{ task_executor::{ Job, TaskExecutor, TaskExecutorOptions }, Module, Error };
+//!
+//! # fn main() -> Result<(), Error> {
+//! let mut options = TaskExecutorOptions::default();
+//! options.worker_options.preload_modules = vec![Module::new("jobs.js", "export const square = (n) => n * n;")];
+//! let executor = TaskExecutor::new(options)?;
+//!
+//! let jobs = (1..=4)
+//!     .map(|n| executor.submit(Job::new(Some("jobs.js"), "square", vec![n.into()])))
+//!     .collect::<Result<Vec<_>, _>>()?;
+//!
+//! let results = jobs.into_iter().map(|job| job.wait::<i64>()).collect::<Result<Vec<_>, _>>()?;
+//! assert_eq!(results, vec![1, 4, 9, 16]);
+//! # Ok(())
+//! # }
+//! ```
+use crate::{
+    serde_json,
+    worker::{
+        DefaultWorker, DefaultWorkerOptions, DefaultWorkerQuery, DefaultWorkerResponse, WorkerPool,
+    },
+    Error,
+};
+use deno_core::futures::channel::oneshot;
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{channel, Receiver, Sender, TryRecvError},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+/// Options for a [`TaskExecutor`]
+#[derive(Clone)]
+pub struct TaskExecutorOptions {
+    /// Options for each worker in the pool
+    /// Modules used by jobs should be listed in [`DefaultWorkerOptions::preload_modules`]
+    pub worker_options: DefaultWorkerOptions,
+
+    /// Number of workers in the pool
+    ///
+    /// Default: 4
+    pub workers: u32,
+
+    /// Maximum number of jobs running at once - 0 allows one job per worker
+    ///
+    /// Default: 0
+    pub max_concurrency: usize,
+
+    /// Timeout for jobs that do not set their own, see [`Job::with_timeout`]
+    ///
+    /// Default: `None`
+    pub default_timeout: Option<Duration>,
+
+    /// Number of retries for jobs that do not set their own, see [`Job::with_retries`]
+    ///
+    /// Default: 0
+    pub default_retries: u32,
+
+    /// How long the executor waits between checks on running jobs, when none have finished
+    ///
+    /// Default: 1ms
+    pub poll_interval: Duration,
+}
+
+impl Default for TaskExecutorOptions {
+    fn default() -> Self {
+        Self {
+            worker_options: DefaultWorkerOptions {
+                timeout: Duration::MAX,
+                ..Default::default()
+            },
+            workers: 4,
+            max_concurrency: 0,
+            default_timeout: None,
+            default_retries: 0,
+            poll_interval: Duration::from_millis(1),
+        }
+    }
+}
+
+/// A call to a javascript function, to be run by a [`TaskExecutor`]
+#[derive(Debug, Clone)]
+pub struct Job {
+    module_name: Option<String>,
+    function: String,
+    args: Vec<serde_json::Value>,
+    timeout: Option<Duration>,
+    retries: Option<u32>,
+}
+
+impl Job {
+    /// Create a job calling a function by name
+    ///
+    /// If `module_name` is given, the function is looked up in the preloaded module with that filename,
+    /// otherwise in the global scope
+    #[must_use]
+    pub fn new(module_name: Option<&str>, function: &str, args: Vec<serde_json::Value>) -> Self {
+        Self {
+            module_name: module_name.map(str::to_string),
+            function: function.to_string(),
+            args,
+            timeout: None,
+            retries: None,
+        }
+    }
+
+    /// Set how long each attempt at the job may run, replacing [`TaskExecutorOptions::default_timeout`]
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set how many times the job is retried if it fails, replacing [`TaskExecutorOptions::default_retries`]
+    #[must_use]
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = Some(retries);
+        self
+    }
+}
+
+/// The status of a job submitted to a [`TaskExecutor`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobStatus {
+    /// The job is waiting for a worker
+    Queued,
+
+    /// The job is running - `attempt` starts at 1, and counts retries
+    Running {
+        /// The current attempt
+        attempt: u32,
+    },
+
+    /// The job finished successfully
+    Succeeded,
+
+    /// The job failed, and has no retries left
+    Failed,
+}
+
+/// A handle to a job submitted to a [`TaskExecutor`], used to check its status and get its result
+pub struct JobHandle {
+    id: u64,
+    status: Arc<Mutex<JobStatus>>,
+    result: oneshot::Receiver<Result<serde_json::Value, Error>>,
+}
+
+impl JobHandle {
+    /// Returns the id of the job, unique within its executor
+    #[must_use]
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns the current status of the job
+    #[must_use]
+    pub fn status(&self) -> JobStatus {
+        *self
+            .status
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Block until the job finishes, and return its result
+    ///
+    /// # Errors
+    /// Will return the error of the job's last attempt if it failed,
+    /// an error if the executor stopped before running it,
+    /// or if the result cannot be deserialized into the requested type
+    pub fn wait<T>(self) -> Result<T, Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let value = deno_core::futures::executor::block_on(self.result)??;
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Wait for the job to finish, and return its result
+    ///
+    /// # Errors
+    /// Will return the error of the job's last attempt if it failed,
+    /// an error if the executor stopped before running it,
+    /// or if the result cannot be deserialized into the requested type
+    pub async fn result<T>(self) -> Result<T, Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let value = self.result.await??;
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+/// A job in the executor's queue, along with the state shared with its handle
+struct QueuedJob {
+    job: Job,
+    timeout: Option<Duration>,
+    retries: u32,
+    attempt: u32,
+    status: Arc<Mutex<JobStatus>>,
+    result: oneshot::Sender<Result<serde_json::Value, Error>>,
+}
+
+impl QueuedJob {
+    fn set_status(&self, status: JobStatus) {
+        *self
+            .status
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner) = status;
+    }
+}
+
+/// Runs queued javascript jobs across a pool of workers, see the [module-level documentation](self)
+///
+/// The pool is owned by a background thread, so the executor itself can be shared between threads
+/// Dropping the executor lets queued jobs finish in the background; use [`TaskExecutor::shutdown`] to wait for them
+pub struct TaskExecutor {
+    jobs: Option<Sender<QueuedJob>>,
+    thread: Option<JoinHandle<()>>,
+    next_id: AtomicU64,
+    default_timeout: Option<Duration>,
+    default_retries: u32,
+}
+
+impl TaskExecutor {
+    /// Start a new executor, and its pool of workers
+    ///
+    /// # Errors
+    /// Will return an error if the workers cannot be started
+    pub fn new(options: TaskExecutorOptions) -> Result<Self, Error> {
+        let (jobs_tx, jobs_rx) = channel();
+        let (init_tx, init_rx) = channel();
+
+        let default_timeout = options.default_timeout;
+        let default_retries = options.default_retries;
+        let thread = std::thread::spawn(move || {
+            let pool = match WorkerPool::<DefaultWorker>::new(
+                options.worker_options.clone(),
+                options.workers,
+            ) {
+                Ok(pool) => pool,
+                Err(e) => {
+                    init_tx.send(Err(e)).ok();
+                    return;
+                }
+            };
+
+            if init_tx.send(Ok(())).is_ok() {
+                Dispatcher::new(pool, &options).run(&jobs_rx);
+            }
+        });
+
+        match init_rx.recv() {
+            Ok(Ok(())) => Ok(Self {
+                jobs: Some(jobs_tx),
+                thread: Some(thread),
+                next_id: AtomicU64::new(0),
+                default_timeout,
+                default_retries,
+            }),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err(Error::Runtime(
+                "Could not start task executor thread".to_string(),
+            )),
+        }
+    }
+
+    /// Add a job to the queue
+    ///
+    /// # Errors
+    /// Will return an error if the executor has stopped
+    pub fn submit(&self, job: Job) -> Result<JobHandle, Error> {
+        let status = Arc::new(Mutex::new(JobStatus::Queued));
+        let (result_tx, result_rx) = oneshot::channel();
+
+        let queued = QueuedJob {
+            timeout: job.timeout.or(self.default_timeout),
+            retries: job.retries.unwrap_or(self.default_retries),
+            job,
+            attempt: 0,
+            status: status.clone(),
+            result: result_tx,
+        };
+
+        self.jobs
+            .as_ref()
+            .ok_or(Error::WorkerHasStopped)?
+            .send(queued)
+            .map_err(|_| Error::WorkerHasStopped)?;
+
+        Ok(JobHandle {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            status,
+            result: result_rx,
+        })
+    }
+
+    /// Stop accepting jobs, and wait for every queued job to finish
+    pub fn shutdown(mut self) {
+        self.jobs.take();
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+/// Runs on the executor's thread, assigning queued jobs to workers and collecting their results
+struct Dispatcher {
+    pool: WorkerPool<DefaultWorker>,
+    queue: VecDeque<QueuedJob>,
+    running: Vec<Option<(QueuedJob, Instant)>>,
+    limit: usize,
+    poll_interval: Duration,
+}
+
+impl Dispatcher {
+    fn new(pool: WorkerPool<DefaultWorker>, options: &TaskExecutorOptions) -> Self {
+        let limit = match options.max_concurrency {
+            0 => pool.len(),
+            n => n.min(pool.len()),
+        };
+
+        Self {
+            running: (0..pool.len()).map(|_| None).collect(),
+            pool,
+            queue: VecDeque::new(),
+            limit,
+            poll_interval: options.poll_interval,
+        }
+    }
+
+    fn run(mut self, jobs: &Receiver<QueuedJob>) {
+        let mut open = true;
+        loop {
+            // Wait for new jobs only when there is nothing else to do
+            if self.queue.is_empty() && self.running.iter().all(Option::is_none) {
+                if !open {
+                    break;
+                }
+                match jobs.recv() {
+                    Ok(job) => self.queue.push_back(job),
+                    Err(_) => break,
+                }
+            }
+
+            while open {
+                match jobs.try_recv() {
+                    Ok(job) => self.queue.push_back(job),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => open = false,
+                }
+            }
+
+            self.start_jobs();
+            if !self.collect_jobs() && self.running.iter().any(Option::is_some) {
+                std::thread::sleep(self.poll_interval);
+            }
+        }
+
+        self.pool.shutdown();
+    }
+
+    /// Start queued jobs on idle workers, up to the concurrency limit
+    fn start_jobs(&mut self) {
+        for id in 0..self.running.len() {
+            if self.running.iter().flatten().count() >= self.limit {
+                break;
+            }
+            if self.running[id].is_some() {
+                continue;
+            }
+            let Some(mut queued) = self.queue.pop_front() else {
+                break;
+            };
+
+            queued.attempt += 1;
+            queued.set_status(JobStatus::Running {
+                attempt: queued.attempt,
+            });
+
+            let query = DefaultWorkerQuery::CallFunctionByName {
+                module_name: queued.job.module_name.clone(),
+                name: queued.job.function.clone(),
+                args: queued.job.args.clone(),
+            };
+            let sent = match self.pool.worker_by_id(id) {
+                Some(worker) => worker.borrow().send(query),
+                None => Err(Error::WorkerHasStopped),
+            };
+
+            match sent {
+                Ok(()) => self.running[id] = Some((queued, Instant::now())),
+                Err(e) => {
+                    self.pool.replace_worker(id).ok();
+                    self.finish(queued, Err(e));
+                }
+            }
+        }
+    }
+
+    /// Collect the results of finished jobs, and stop jobs that have run past their timeout
+    /// Returns true if any job finished
+    fn collect_jobs(&mut self) -> bool {
+        let mut progress = false;
+        for id in 0..self.running.len() {
+            let Some((queued, started)) = &self.running[id] else {
+                continue;
+            };
+            let Some(worker) = self.pool.worker_by_id(id) else {
+                continue;
+            };
+
+            let response = worker.borrow().try_receive();
+            let result = match response {
+                Ok(Some(DefaultWorkerResponse::Value(value))) => Ok(value),
+                Ok(Some(DefaultWorkerResponse::Error(e))) => Err(e),
+                Ok(Some(_)) => Err(Error::Runtime(
+                    "Unexpected response from the worker".to_string(),
+                )),
+
+                Ok(None) => match queued.timeout {
+                    Some(limit) if started.elapsed() > limit => {
+                        let elapsed = started.elapsed();
+                        self.pool.replace_worker(id).ok();
+                        Err(Error::Timeout { elapsed, limit })
+                    }
+                    _ => continue,
+                },
+
+                Err(e) => {
+                    self.pool.replace_worker(id).ok();
+                    Err(e)
+                }
+            };
+
+            if let Some((queued, _)) = self.running[id].take() {
+                self.finish(queued, result);
+            }
+            progress = true;
+        }
+        progress
+    }
+
+    /// Complete a job, or queue it again if it failed and has retries left
+    fn finish(&mut self, queued: QueuedJob, result: Result<serde_json::Value, Error>) {
+        if result.is_err() && queued.attempt <= queued.retries {
+            queued.set_status(JobStatus::Queued);
+            self.queue.push_back(queued);
+            return;
+        }
+
+        queued.set_status(if result.is_ok() {
+            JobStatus::Succeeded
+        } else {
+            JobStatus::Failed
+        });
+        queued.result.send(result).ok();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Module;
+
+    #[test]
+    fn test_task_executor() {
+        let mut options = TaskExecutorOptions {
+            workers: 1,
+            ..Default::default()
+        };
+        options.worker_options.preload_modules = vec![Module::new(
+            "jobs.js",
+            "
+            let calls = 0;
+            export const square = (n) => n * n;
+            export const flaky = async () => {
+                if (++calls < 3) throw new Error('not yet');
+                return calls;
+            };
+            export const spin = () => { for (;;) {} };
+        ",
+        )];
+        let executor = TaskExecutor::new(options).unwrap();
+
+        // Jobs are retried until they succeed, or run out of retries
+        let flaky = executor
+            .submit(Job::new(Some("jobs.js"), "flaky", vec![]).with_retries(1))
+            .unwrap();
+        let e = flaky.wait::<i64>().expect_err("Job did not fail");
+        assert!(e.to_string().contains("not yet"), "{e}");
+
+        let flaky = executor
+            .submit(Job::new(Some("jobs.js"), "flaky", vec![]).with_retries(5))
+            .unwrap();
+        assert_eq!(flaky.wait::<i64>().unwrap(), 3);
+
+        // Runaway jobs are stopped, and their worker replaced
+        let spin = executor
+            .submit(
+                Job::new(Some("jobs.js"), "spin", vec![]).with_timeout(Duration::from_millis(50)),
+            )
+            .unwrap();
+        let square = executor
+            .submit(Job::new(Some("jobs.js"), "square", vec![3.into()]))
+            .unwrap();
+        assert_ne!(spin.id(), square.id());
+
+        let e = spin.wait::<()>().expect_err("Job was not timed out");
+        assert!(matches!(e, Error::Timeout { .. }), "{e}");
+        assert_eq!(square.wait::<i64>().unwrap(), 9);
+
+        let missing = executor
+            .submit(Job::new(Some("missing.js"), "square", vec![]))
+            .unwrap();
+        missing.wait::<i64>().expect_err("Missing module was found");
+        executor.shutdown();
+    }
+}
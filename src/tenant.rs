@@ -0,0 +1,413 @@
+//! Hosts scripts for many tenants, each in its own runtime
+//!
+//! A [`TenantManager`] owns a set of named runtimes, each created from its own [`TenantConfig`],
+//! so every tenant can have its own permissions, timeouts, heap limits, and modules
+//!
+//! To bound resource use, the least recently used tenants can be suspended automatically,
+//! once too many runtimes are active, or their combined heap usage grows too large
+//! A suspended tenant's runtime is dropped, and recreated from its configuration when it is next used
+//!
+//! ```rust
+//! use rustyscript::{ json_args, Module, tenant::{ TenantConfig, TenantManager, TenantManagerOptions } };
+//!
+//! # fn main() -> Result<(), rustyscript::Error> {
+//! let mut manager = TenantManager::new(TenantManagerOptions {
+//!     max_active: Some(100),
+//!     ..Default::default()
+//! });
+//!
+//! let module = Module::new("plugin.js", "export const greet = (name) => `Hello, ${name}!`;");
+//! manager.create("acme", TenantConfig::new(vec![module]))?;
+//!
+//! let greeting: String = manager.call_function("acme", "greet", json_args!("world"))?;
+//! assert_eq!(greeting, "Hello, world!");
+//! # Ok(())
+//! # }
+//! ```
+use crate::{Error, Module, ModuleHandle, Runtime, RuntimeOptions};
+use std::collections::HashMap;
+
+/// The configuration of a single tenant
+pub struct TenantConfig {
+    /// Creates the options for the tenant's runtime
+    ///
+    /// Called whenever the runtime is created, including when a suspended tenant is resumed
+    /// Permissions, timeouts and heap limits for the tenant are set here
+    ///
+    /// Default: `RuntimeOptions::default`
+    pub options: Box<dyn Fn() -> RuntimeOptions>,
+
+    /// Modules loaded, in order, whenever the tenant's runtime is created
+    pub modules: Vec<Module>,
+}
+
+impl TenantConfig {
+    /// Create a configuration that loads the given modules into a runtime with default options
+    #[must_use]
+    pub fn new(modules: Vec<Module>) -> Self {
+        Self {
+            options: Box::new(RuntimeOptions::default),
+            modules,
+        }
+    }
+
+    /// Set the function creating the options for the tenant's runtime
+    #[must_use]
+    pub fn with_options(mut self, options: impl Fn() -> RuntimeOptions + 'static) -> Self {
+        self.options = Box::new(options);
+        self
+    }
+}
+
+/// Options for a [`TenantManager`]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TenantManagerOptions {
+    /// Maximum number of tenants with an active runtime
+    /// Beyond this, the least recently used tenants are suspended
+    ///
+    /// Default: `None` (no limit)
+    pub max_active: Option<usize>,
+
+    /// Maximum combined v8 heap usage of the active runtimes, in bytes
+    /// Beyond this, the least recently used tenants are suspended
+    ///
+    /// The tenant currently being used is never suspended - use [`RuntimeOptions::max_heap_size`] to limit a single tenant
+    ///
+    /// Default: `None` (no limit)
+    pub max_total_heap: Option<usize>,
+}
+
+/// A tenant's runtime, along with the modules loaded into it
+struct ActiveTenant {
+    runtime: Runtime,
+    handles: Vec<ModuleHandle>,
+}
+
+/// A tenant registered with the manager, which may or may not have an active runtime
+struct Tenant {
+    config: TenantConfig,
+    active: Option<ActiveTenant>,
+    last_used: u64,
+}
+
+impl Tenant {
+    /// Creates the tenant's runtime, and loads its modules
+    fn activate(&mut self) -> Result<&mut ActiveTenant, Error> {
+        if self.active.is_none() {
+            let mut runtime = Runtime::new((self.config.options)())?;
+            let handles = self
+                .config
+                .modules
+                .iter()
+                .map(|module| runtime.load_module(module))
+                .collect::<Result<Vec<_>, _>>()?;
+            self.active = Some(ActiveTenant { runtime, handles });
+        }
+
+        self.active
+            .as_mut()
+            .ok_or_else(|| Error::Runtime("Tenant could not be activated".to_string()))
+    }
+}
+
+/// Owns a set of named runtimes, one per tenant
+///
+/// Runtimes are not `Send`, so the manager must stay on the thread it was created on
+/// See the [module-level documentation](self) for an example
+pub struct TenantManager {
+    options: TenantManagerOptions,
+    tenants: HashMap<String, Tenant>,
+    clock: u64,
+}
+
+impl TenantManager {
+    /// Create a new, empty tenant manager
+    #[must_use]
+    pub fn new(options: TenantManagerOptions) -> Self {
+        Self {
+            options,
+            tenants: HashMap::new(),
+            clock: 0,
+        }
+    }
+
+    /// Returns the options used by the manager
+    #[must_use]
+    pub fn options(&self) -> &TenantManagerOptions {
+        &self.options
+    }
+
+    /// Register a new tenant, and start its runtime
+    ///
+    /// # Errors
+    /// Will return an error if a tenant with that name already exists,
+    /// or if the runtime cannot be created or its modules cannot be loaded
+    /// In that case the tenant is not registered
+    pub fn create(&mut self, name: &str, config: TenantConfig) -> Result<(), Error> {
+        if self.tenants.contains_key(name) {
+            return Err(Error::Runtime(format!("Tenant already exists: {name}")));
+        }
+
+        let mut tenant = Tenant {
+            config,
+            active: None,
+            last_used: self.tick(),
+        };
+        tenant.activate()?;
+
+        self.tenants.insert(name.to_string(), tenant);
+        self.enforce_limits(name);
+        Ok(())
+    }
+
+    /// Returns true if a tenant with the given name is registered
+    #[must_use]
+    pub fn contains(&self, name: &str) -> bool {
+        self.tenants.contains_key(name)
+    }
+
+    /// Returns true if the tenant is registered, and its runtime is active
+    #[must_use]
+    pub fn is_active(&self, name: &str) -> bool {
+        self.tenants.get(name).is_some_and(|t| t.active.is_some())
+    }
+
+    /// Returns the names of all registered tenants, in no particular order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.tenants.keys().map(String::as_str)
+    }
+
+    /// Returns the number of registered tenants
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.tenants.len()
+    }
+
+    /// Returns true if no tenants are registered
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.tenants.is_empty()
+    }
+
+    /// Run a function with a tenant's runtime, and the handles of the modules loaded into it
+    ///
+    /// A suspended tenant is resumed first, which recreates its runtime and reloads its modules
+    /// Other tenants may be suspended afterwards to stay within the manager's limits
+    ///
+    /// # Errors
+    /// Will return an error if the tenant does not exist, if it could not be resumed,
+    /// or if the function returns an error
+    pub fn execute<T, F>(&mut self, name: &str, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut Runtime, &[ModuleHandle]) -> Result<T, Error>,
+    {
+        let tick = self.tick();
+        let tenant = self
+            .tenants
+            .get_mut(name)
+            .ok_or_else(|| Error::Runtime(format!("No such tenant: {name}")))?;
+        tenant.last_used = tick;
+
+        let active = tenant.activate()?;
+        let result = f(&mut active.runtime, &active.handles);
+
+        self.enforce_limits(name);
+        result
+    }
+
+    /// Call a function exported by a tenant's modules
+    ///
+    /// The last module in the tenant's configuration is searched first, followed by the global context
+    ///
+    /// # Errors
+    /// Will return an error if the tenant does not exist or could not be resumed,
+    /// if the function cannot be found, or if the call fails
+    pub fn call_function<T>(
+        &mut self,
+        name: &str,
+        function: &str,
+        args: &impl serde::Serialize,
+    ) -> Result<T, Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        self.execute(name, |runtime, handles| {
+            runtime.call_function(handles.last(), function, args)
+        })
+    }
+
+    /// Suspend a tenant, dropping its runtime and all of its state
+    ///
+    /// The tenant remains registered, and its runtime is recreated when it is next used
+    ///
+    /// # Errors
+    /// Will return an error if the tenant does not exist
+    pub fn suspend(&mut self, name: &str) -> Result<(), Error> {
+        let tenant = self
+            .tenants
+            .get_mut(name)
+            .ok_or_else(|| Error::Runtime(format!("No such tenant: {name}")))?;
+        tenant.active = None;
+        Ok(())
+    }
+
+    /// Remove a tenant entirely, dropping its runtime
+    ///
+    /// Returns the tenant's configuration, or None if it did not exist
+    pub fn evict(&mut self, name: &str) -> Option<TenantConfig> {
+        self.tenants.remove(name).map(|tenant| tenant.config)
+    }
+
+    /// Returns the combined v8 heap usage of all active runtimes, in bytes
+    pub fn total_heap_usage(&mut self) -> usize {
+        self.tenants
+            .values_mut()
+            .filter_map(|tenant| tenant.active.as_mut())
+            .map(|active| crate::bench::used_heap_size(&mut active.runtime))
+            .sum()
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    /// Suspends the least recently used tenants, other than `keep`, until the limits are met
+    fn enforce_limits(&mut self, keep: &str) {
+        loop {
+            let active = self.tenants.values().filter(|t| t.active.is_some()).count();
+            let over_count = self.options.max_active.is_some_and(|max| active > max);
+            let over_heap = match self.options.max_total_heap {
+                Some(max) if !over_count => self.total_heap_usage() > max,
+                _ => false,
+            };
+            if !over_count && !over_heap {
+                return;
+            }
+
+            let lru = self
+                .tenants
+                .iter_mut()
+                .filter(|(name, tenant)| tenant.active.is_some() && name.as_str() != keep)
+                .min_by_key(|(_, tenant)| tenant.last_used);
+            match lru {
+                Some((_, tenant)) => tenant.active = None,
+                None => return,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::json_args;
+
+    fn counter_config() -> TenantConfig {
+        TenantConfig::new(vec![Module::new(
+            "counter.js",
+            "let count = 0; export const increment = () => ++count;",
+        )])
+    }
+
+    #[test]
+    fn test_tenant_manager() {
+        let mut manager = TenantManager::new(TenantManagerOptions {
+            max_active: Some(2),
+            ..Default::default()
+        });
+
+        for name in ["a", "b", "c"] {
+            manager.create(name, counter_config()).unwrap();
+        }
+        manager
+            .create("a", counter_config())
+            .expect_err("Duplicate tenant was created");
+        assert_eq!(manager.len(), 3);
+
+        // The least recently used tenant was suspended
+        assert!(!manager.is_active("a"));
+        assert!(manager.is_active("b") && manager.is_active("c"));
+
+        // Tenants are isolated from each other
+        let n: i64 = manager
+            .call_function("b", "increment", json_args!())
+            .unwrap();
+        assert_eq!(n, 1);
+        let n: i64 = manager
+            .call_function("b", "increment", json_args!())
+            .unwrap();
+        assert_eq!(n, 2);
+        let n: i64 = manager
+            .call_function("c", "increment", json_args!())
+            .unwrap();
+        assert_eq!(n, 1);
+
+        // Resuming a tenant suspends the least recently used one - b
+        let n: i64 = manager
+            .call_function("a", "increment", json_args!())
+            .unwrap();
+        assert_eq!(n, 1);
+        assert!(!manager.is_active("b"));
+
+        // Suspended tenants lose their state
+        let n: i64 = manager
+            .call_function("b", "increment", json_args!())
+            .unwrap();
+        assert_eq!(n, 1);
+
+        assert!(manager.evict("b").is_some());
+        manager
+            .call_function::<i64>("b", "increment", json_args!())
+            .expect_err("Evicted tenant was used");
+    }
+
+    #[test]
+    fn test_module_key_across_suspension() {
+        let mut manager = TenantManager::new(TenantManagerOptions::default());
+        manager.create("a", counter_config()).unwrap();
+
+        let key = manager
+            .execute("a", |runtime, _| runtime.module_key("counter.js"))
+            .unwrap();
+        let call = |manager: &mut TenantManager| {
+            manager.execute("a", |runtime, _| {
+                runtime.call_function_by_key::<i64>(&key, "increment", json_args!())
+            })
+        };
+        assert_eq!(call(&mut manager).unwrap(), 1);
+
+        // The key still refers to the module once the runtime is recreated
+        manager.suspend("a").unwrap();
+        assert_eq!(call(&mut manager).unwrap(), 1);
+
+        let missing = manager
+            .execute("a", |runtime, _| runtime.module_key("missing.js"))
+            .unwrap();
+        let result = manager.execute("a", |runtime, _| {
+            runtime.call_function_by_key::<i64>(&missing, "increment", json_args!())
+        });
+        assert!(matches!(result, Err(Error::ModuleNotFound(_))));
+    }
+
+    #[test]
+    fn test_tenant_heap_limit() {
+        let mut manager = TenantManager::new(TenantManagerOptions {
+            max_total_heap: Some(1),
+            ..Default::default()
+        });
+
+        manager.create("a", counter_config()).unwrap();
+        manager.create("b", counter_config()).unwrap();
+
+        // Only the tenant in use is kept
+        assert!(!manager.is_active("a"));
+        assert!(manager.is_active("b"));
+
+        let config = TenantConfig::new(vec![Module::new("bad.js", "syntax error")]);
+        manager
+            .create("bad", config)
+            .expect_err("Tenant with a bad module was created");
+        assert!(!manager.contains("bad"));
+    }
+}
@@ -0,0 +1,418 @@
+//! Runs javascript test suites from rust, so embedded scripts can be tested under `cargo test`
+//!
+//! Test modules register tests with a global `test` function, which accepts the same forms as `Deno.test`:
+//! ```javascript
+//! test("addition", () => {
+//!     if (1 + 1 !== 2) throw new Error("math is broken");
+//! });
+//!
+//! test({ name: "slow", ignore: true, fn: async () => {} });
+//! ```
+//!
+//! A test passes if its function returns (or its promise resolves) without throwing
+//!
+//! ```rust
+//! use rustyscript::{ Module, testing::{ TestRunner, TestRunnerOptions } };
+//!
+//! # fn main() -> Result<(), rustyscript::Error> {
+//! let module = Module::new("math.test.js", "
+//!     test('addition', () => {
+//!         if (1 + 1 !== 2) throw new Error('math is broken');
+//!     });
+//! ");
+//!
+//! let report = TestRunner::new(TestRunnerOptions::default()).run(&[module])?;
+//! report.assert_success();
+//! # Ok(())
+//! # }
+//! ```
+use crate::{
+    worker::{InnerWorker, WorkerPool},
+    Error, Module, Runtime, RuntimeOptions, Undefined,
+};
+use std::{
+    path::PathBuf,
+    sync::mpsc::{channel, RecvTimeoutError},
+    time::{Duration, Instant},
+};
+
+/// Installs the `test` global, and the functions used by the runner to list and run tests
+const HARNESS: &str = include_str!("testing/harness.js");
+
+/// Options for a [`TestRunner`]
+#[derive(Clone, Debug)]
+pub struct TestRunnerOptions {
+    /// Maximum time a single test can run before it is stopped and reported as timed out
+    ///
+    /// Default: 30 seconds
+    pub timeout: Duration,
+
+    /// Only run tests whose names contain this string
+    ///
+    /// Default: `None`
+    pub filter: Option<String>,
+
+    /// Number of worker threads used to run test modules in parallel
+    /// Tests within a module always run in order, on the same thread
+    /// With `0`, modules are run one at a time on the current thread
+    ///
+    /// Default: 0
+    pub workers: u32,
+}
+
+impl Default for TestRunnerOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            filter: None,
+            workers: 0,
+        }
+    }
+}
+
+/// The outcome of a single test
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TestOutcome {
+    /// The test completed without throwing
+    Passed,
+
+    /// The test threw an error, which is included
+    Failed(String),
+
+    /// The test was registered with `ignore: true`
+    Ignored,
+
+    /// The test did not complete within [`TestRunnerOptions::timeout`]
+    TimedOut,
+}
+
+/// The result of a single test
+#[derive(Clone, Debug)]
+pub struct TestResult {
+    /// The filename of the module the test was registered by
+    pub module: PathBuf,
+
+    /// The name of the test
+    pub name: String,
+
+    /// The outcome of the test
+    pub outcome: TestOutcome,
+
+    /// How long the test took to run
+    pub duration: Duration,
+}
+
+/// The results of a [`TestRunner::run`]
+#[derive(Debug, Default)]
+pub struct TestReport {
+    /// Results for each test that was run, in module order
+    /// Tests excluded by the filter, or by another test's `only: true`, are not included
+    pub results: Vec<TestResult>,
+
+    /// Modules that could not be loaded, and the reason for each
+    pub load_failures: Vec<(PathBuf, Error)>,
+}
+
+impl TestReport {
+    /// Returns the tests that passed
+    pub fn passed(&self) -> impl Iterator<Item = &TestResult> {
+        self.results
+            .iter()
+            .filter(|r| r.outcome == TestOutcome::Passed)
+    }
+
+    /// Returns the tests that failed or timed out
+    pub fn failed(&self) -> impl Iterator<Item = &TestResult> {
+        self.results
+            .iter()
+            .filter(|r| matches!(r.outcome, TestOutcome::Failed(_) | TestOutcome::TimedOut))
+    }
+
+    /// Returns true if every module loaded, and no test failed or timed out
+    #[must_use]
+    pub fn is_success(&self) -> bool {
+        self.load_failures.is_empty() && self.failed().next().is_none()
+    }
+
+    /// Panics with a summary of the failures, unless [`TestReport::is_success`]
+    /// Intended for use within `#[test]` functions
+    ///
+    /// # Panics
+    /// Will panic if a module could not be loaded, or a test failed or timed out
+    pub fn assert_success(&self) {
+        assert!(self.is_success(), "{self}");
+    }
+}
+
+impl std::fmt::Display for TestReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (module, e) in &self.load_failures {
+            writeln!(f, "{}: could not load module: {e}", module.display())?;
+        }
+
+        for result in self.failed() {
+            let reason = match &result.outcome {
+                TestOutcome::Failed(e) => e.as_str(),
+                _ => "timed out",
+            };
+            writeln!(f, "{} > {}: {reason}", result.module.display(), result.name)?;
+        }
+
+        let ignored = self
+            .results
+            .iter()
+            .filter(|r| r.outcome == TestOutcome::Ignored)
+            .count();
+        write!(
+            f,
+            "{} passed, {} failed, {ignored} ignored",
+            self.passed().count(),
+            self.failed().count() + self.load_failures.len()
+        )
+    }
+}
+
+/// Loads javascript test modules and runs the tests they register
+///
+/// Each module is loaded into a fresh runtime, so modules cannot interfere with each other
+/// See the [module-level documentation](self) for an example
+pub struct TestRunner {
+    options: TestRunnerOptions,
+}
+
+impl TestRunner {
+    /// Create a new test runner
+    #[must_use]
+    pub fn new(options: TestRunnerOptions) -> Self {
+        Self { options }
+    }
+
+    /// Returns the options used by the runner
+    #[must_use]
+    pub fn options(&self) -> &TestRunnerOptions {
+        &self.options
+    }
+
+    /// Load each module, and run the tests it registers
+    ///
+    /// # Errors
+    /// Will return an error if a worker thread could not be started, or stopped unexpectedly
+    /// Failing tests and modules are reported in the returned [`TestReport`] instead
+    pub fn run(&self, modules: &[Module]) -> Result<TestReport, Error> {
+        let mut report = TestReport::default();
+
+        if self.options.workers == 0 {
+            for module in modules {
+                run_module(&self.options, module).merge_into(&mut report);
+            }
+            return Ok(report);
+        }
+
+        let mut pool = WorkerPool::<TestWorker>::new(self.options.clone(), self.options.workers)?;
+        let mut pending = Vec::with_capacity(modules.len());
+        for module in modules {
+            let worker = pool.next_worker();
+            worker.borrow().send(module.clone())?;
+            pending.push(worker);
+        }
+
+        // Each worker answers its queries in order
+        for worker in pending {
+            worker.borrow().receive()?.merge_into(&mut report);
+        }
+
+        pool.shutdown();
+        Ok(report)
+    }
+}
+
+/// Details of a registered test, as reported by the harness
+#[derive(serde::Deserialize)]
+struct TestInfo {
+    name: String,
+    ignore: bool,
+    only: bool,
+}
+
+/// The results of running a single module
+struct ModuleResults {
+    results: Vec<TestResult>,
+    load_failure: Option<(PathBuf, Error)>,
+}
+
+impl ModuleResults {
+    fn merge_into(self, report: &mut TestReport) {
+        report.results.extend(self.results);
+        report.load_failures.extend(self.load_failure);
+    }
+}
+
+/// Runs test modules on a worker thread
+struct TestWorker;
+impl InnerWorker for TestWorker {
+    type Runtime = TestRunnerOptions;
+    type RuntimeOptions = TestRunnerOptions;
+    type Query = Module;
+    type Response = ModuleResults;
+
+    fn init_runtime(options: Self::RuntimeOptions) -> Result<Self::Runtime, Error> {
+        // A fresh runtime is created for each module
+        Ok(options)
+    }
+
+    fn handle_query(options: &mut Self::Runtime, module: Self::Query) -> Self::Response {
+        run_module(options, &module)
+    }
+}
+
+/// Loads a module into a fresh runtime, and runs its tests
+fn run_module(options: &TestRunnerOptions, module: &Module) -> ModuleResults {
+    let filename = module.filename().to_path_buf();
+    let mut results = Vec::new();
+
+    let tests = Runtime::new(RuntimeOptions {
+        timeout: options.timeout,
+        ..Default::default()
+    })
+    .and_then(|mut runtime| {
+        runtime.eval::<Undefined>(HARNESS)?;
+        runtime.load_module(module)?;
+        let tests: Vec<TestInfo> = runtime.call_function(None, "__rustyscript_list_tests", &())?;
+        Ok((runtime, tests))
+    });
+    let (mut runtime, tests) = match tests {
+        Ok(v) => v,
+        Err(e) => {
+            return ModuleResults {
+                results,
+                load_failure: Some((filename, e)),
+            }
+        }
+    };
+
+    let has_only = tests.iter().any(|t| t.only);
+    for (i, test) in tests.into_iter().enumerate() {
+        let filtered = options
+            .filter
+            .as_ref()
+            .is_some_and(|filter| !test.name.contains(filter.as_str()));
+        if filtered || (has_only && !test.only) {
+            continue;
+        }
+
+        let start = Instant::now();
+        let outcome = if test.ignore {
+            TestOutcome::Ignored
+        } else {
+            run_test(&mut runtime, i, options.timeout)
+        };
+
+        results.push(TestResult {
+            module: filename.clone(),
+            name: test.name,
+            outcome,
+            duration: start.elapsed(),
+        });
+    }
+
+    ModuleResults {
+        results,
+        load_failure: None,
+    }
+}
+
+/// Runs a single test, stopping it if it exceeds the timeout
+///
+/// The runtime's own timeout covers tests waiting on the event loop,
+/// while a watchdog thread interrupts tests stuck in synchronous code
+fn run_test(runtime: &mut Runtime, index: usize, timeout: Duration) -> TestOutcome {
+    let isolate = runtime.deno_runtime().v8_isolate().thread_safe_handle();
+    let (done_tx, done_rx) = channel::<()>();
+    let watchdog = std::thread::spawn(move || {
+        let expired = done_rx.recv_timeout(timeout) == Err(RecvTimeoutError::Timeout);
+        if expired {
+            isolate.terminate_execution();
+        }
+        expired
+    });
+
+    let result = runtime.call_function::<Undefined>(None, "__rustyscript_run_test", &(index,));
+    drop(done_tx);
+    let interrupted = watchdog.join().unwrap_or_default();
+
+    if interrupted {
+        // Allow the remaining tests to run
+        runtime
+            .deno_runtime()
+            .v8_isolate()
+            .cancel_terminate_execution();
+        return TestOutcome::TimedOut;
+    }
+
+    match result {
+        Ok(_) => TestOutcome::Passed,
+        Err(Error::Timeout { .. }) => TestOutcome::TimedOut,
+        Err(e) => TestOutcome::Failed(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_runner() {
+        let modules = [
+            Module::new(
+                "a.test.js",
+                "
+                test('passes', () => {});
+                test('fails', () => { throw new Error('expected failure'); });
+                Deno.test({ name: 'ignored', ignore: true, fn: () => {} });
+                test('async', async () => { await new Promise((r) => setTimeout(r, 1)); });
+                test('hangs', () => { while (true) {} });
+            ",
+            ),
+            Module::new(
+                "b.test.js",
+                "test('only', { only: true }, () => {}); test('skipped', () => {});",
+            ),
+            Module::new("c.test.js", "syntax error"),
+        ];
+
+        for workers in [0, 2] {
+            let runner = TestRunner::new(TestRunnerOptions {
+                timeout: Duration::from_millis(500),
+                workers,
+                ..Default::default()
+            });
+            let report = runner.run(&modules).expect("Could not run tests");
+
+            let outcomes: Vec<_> = report
+                .results
+                .iter()
+                .map(|r| (r.name.as_str(), &r.outcome))
+                .collect();
+            assert_eq!(outcomes.len(), 6);
+            assert_eq!(outcomes[0], ("passes", &TestOutcome::Passed));
+            assert!(
+                matches!(outcomes[1], ("fails", TestOutcome::Failed(e)) if e.contains("expected failure"))
+            );
+            assert_eq!(outcomes[2], ("ignored", &TestOutcome::Ignored));
+            assert_eq!(outcomes[3], ("async", &TestOutcome::Passed));
+            assert_eq!(outcomes[4], ("hangs", &TestOutcome::TimedOut));
+            assert_eq!(outcomes[5], ("only", &TestOutcome::Passed));
+
+            assert_eq!(report.load_failures.len(), 1);
+            assert!(!report.is_success());
+        }
+
+        let runner = TestRunner::new(TestRunnerOptions {
+            filter: Some("pass".to_string()),
+            ..Default::default()
+        });
+        let report = runner.run(&modules[..1]).expect("Could not run tests");
+        report.assert_success();
+        assert_eq!(report.results.len(), 1);
+    }
+}
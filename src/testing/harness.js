@@ -0,0 +1,32 @@
+// Test registration for `rustyscript::testing::TestRunner`
+(() => {
+    const tests = [];
+
+    // Accepts the same forms as Deno.test:
+    // test(name, fn), test(fn), test({ name, fn, ignore, only }), test(name, { ignore, only }, fn)
+    const register = (a, b, c) => {
+        let test;
+        if (typeof a === 'function') test = { name: a.name, fn: a };
+        else if (typeof a === 'string' && typeof b === 'function') test = { name: a, fn: b };
+        else if (typeof a === 'string') test = { ...b, name: a, fn: c };
+        else test = { ...a };
+
+        if (typeof test.fn !== 'function') throw new TypeError('A test requires a function');
+        if (!test.name) throw new TypeError('A test requires a name');
+        tests.push(test);
+    };
+
+    Object.defineProperty(globalThis, 'test', { value: register, writable: true, configurable: true });
+    if (globalThis.Deno && !('test' in globalThis.Deno)) {
+        Object.defineProperty(globalThis.Deno, 'test', { value: register, writable: true, configurable: true });
+    }
+
+    Object.defineProperties(globalThis, {
+        '__rustyscript_list_tests': {
+            value: () => tests.map((t) => ({ name: t.name, ignore: !!t.ignore, only: !!t.only })),
+        },
+        '__rustyscript_run_test': {
+            value: async (i) => { await tests[i].fn(); },
+        },
+    });
+})();
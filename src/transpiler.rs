@@ -17,6 +17,69 @@ use std::rc::Rc;
 
 pub type ModuleContents = (String, Option<SourceMapData>);
 
+/// Options controlling how typescript, JSX and TSX modules are transpiled
+/// See [`crate::RuntimeOptions::transpiler_options`]
+#[derive(Clone, Debug, Default)]
+pub struct TranspilerOptions {
+    /// How decorators are transformed
+    ///
+    /// Default: [`DecoratorMode::Unchanged`]
+    pub decorators: DecoratorMode,
+
+    /// Only strip imports marked with `type`, as with typescript's `verbatimModuleSyntax`
+    ///
+    /// Default: `false`
+    pub verbatim_module_syntax: bool,
+
+    /// How source maps are emitted
+    ///
+    /// Default: [`SourceMapMode::Separate`]
+    pub source_maps: SourceMapMode,
+
+    /// Include the original source code in emitted source maps
+    ///
+    /// Default: `false`
+    pub inline_sources: bool,
+
+    /// Remove comments from the transpiled code
+    ///
+    /// Default: `false`
+    pub remove_comments: bool,
+}
+
+/// How decorators are transformed when transpiling
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DecoratorMode {
+    /// Decorators are left as-is
+    /// v8 does not support decorator syntax yet, so modules using them will fail to load
+    #[default]
+    Unchanged,
+
+    /// Typescript's experimental decorators, as with `experimentalDecorators` in `tsconfig.json`
+    Legacy {
+        /// Emit type metadata for decorated members, as with `emitDecoratorMetadata`, for use with `reflect-metadata`
+        emit_metadata: bool,
+    },
+
+    /// Standard TC39 decorators - <https://github.com/tc39/proposal-decorators>
+    Proposal,
+}
+
+/// How source maps are emitted when transpiling
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SourceMapMode {
+    /// Source maps are kept separately, and used for error messages and [`crate::Runtime::map_location`]
+    #[default]
+    Separate,
+
+    /// Source maps are embedded in the transpiled code, as a `sourceMappingURL` comment
+    /// Useful for external tools such as debuggers, but they are not available to [`crate::Runtime::map_location`]
+    Inline,
+
+    /// No source maps are emitted - errors will point into the transpiled code
+    None,
+}
+
 fn should_transpile(media_type: MediaType) -> bool {
     matches!(
         media_type,
@@ -33,7 +96,11 @@ fn should_transpile(media_type: MediaType) -> bool {
 
 ///
 /// Transpiles source code from TS to JS without typechecking
-pub fn transpile(module_specifier: &ModuleSpecifier, code: &str) -> Result<ModuleContents, Error> {
+pub fn transpile(
+    module_specifier: &ModuleSpecifier,
+    code: &str,
+    options: &TranspilerOptions,
+) -> Result<ModuleContents, Error> {
     let mut media_type = MediaType::from_specifier(module_specifier);
 
     if media_type == MediaType::Unknown && module_specifier.as_str().contains("/node:") {
@@ -55,6 +122,15 @@ pub fn transpile(module_specifier: &ModuleSpecifier, code: &str) -> Result<Modul
         })?;
 
         let transpile_options = deno_ast::TranspileOptions {
+            use_ts_decorators: matches!(options.decorators, DecoratorMode::Legacy { .. }),
+            use_decorators_proposal: options.decorators == DecoratorMode::Proposal,
+            emit_metadata: matches!(
+                options.decorators,
+                DecoratorMode::Legacy {
+                    emit_metadata: true
+                }
+            ),
+            verbatim_module_syntax: options.verbatim_module_syntax,
             ..Default::default()
         };
 
@@ -63,9 +139,13 @@ pub fn transpile(module_specifier: &ModuleSpecifier, code: &str) -> Result<Modul
         };
 
         let emit_options = deno_ast::EmitOptions {
-            remove_comments: false,
-            source_map: deno_ast::SourceMapOption::Separate,
-            inline_sources: false,
+            remove_comments: options.remove_comments,
+            source_map: match options.source_maps {
+                SourceMapMode::Separate => deno_ast::SourceMapOption::Separate,
+                SourceMapMode::Inline => deno_ast::SourceMapOption::Inline,
+                SourceMapMode::None => deno_ast::SourceMapOption::None,
+            },
+            inline_sources: options.inline_sources,
             ..Default::default()
         };
         let res = parsed
@@ -91,7 +171,7 @@ pub fn transpile_extension(
     specifier: &ModuleSpecifier,
     code: &str,
 ) -> Result<(FastString, Option<Cow<'static, [u8]>>), AnyError> {
-    let (code, source_map) = transpile(specifier, code)?;
+    let (code, source_map) = transpile(specifier, code, &TranspilerOptions::default())?;
     let code = FastString::from(code);
     Ok((code, source_map))
 }
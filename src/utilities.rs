@@ -123,6 +123,100 @@ pub fn init_platform(thread_pool_size: u32, idle_task_support: bool) {
     deno_core::JsRuntime::init_platform(Some(platform.into()), true);
 }
 
+/// Set V8 flags, such as `--harmony-shadow-realm`, to enable staged javascript features
+///
+/// Flags apply to every runtime in the process, since V8 does not support per-isolate flags  
+/// They must be set before the first runtime is created - changing flags afterwards is not supported by V8
+///
+/// The `Temporal` API is already enabled, using V8's staged implementation
+///
+/// # Errors
+/// Will return an error listing any flags V8 did not recognize - the others are still applied
+pub fn set_v8_flags(flags: &[&str]) -> Result<(), Error> {
+    // The first argument is the program name, as on a command line
+    let args = std::iter::once(String::new())
+        .chain(flags.iter().map(ToString::to_string))
+        .collect();
+    let unrecognized = deno_core::v8_set_flags(args);
+
+    match unrecognized.get(1..) {
+        Some(flags) if !flags.is_empty() => Err(Error::Runtime(format!(
+            "Unrecognized V8 flags: {}",
+            flags.join(", ")
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Provide ICU data used by `Intl` and locale-aware string methods, such as `toLocaleString`
+///
+/// Full ICU data is built in, so this is only needed for custom data - such as a newer release, or a reduced set of locales  
+/// The data is searched before the built-in data, for every runtime in the process
+///
+/// Must be called before the first runtime is created, as ICU caches the data it loads
+///
+/// # Errors
+/// Will return an error if the data is empty, not 16-byte aligned, or rejected by ICU
+pub fn set_icu_data(data: &'static [u8]) -> Result<(), Error> {
+    if data.is_empty() || data.as_ptr().align_offset(16) != 0 {
+        return Err(Error::Runtime(
+            "ICU data must be non-empty and 16-byte aligned".to_string(),
+        ));
+    }
+
+    deno_core::v8::icu::set_common_data_74(data)
+        .map_err(|code| Error::Runtime(format!("ICU data was rejected (error code {code})")))
+}
+
+/// Load ICU data from a file, such as an `icudt74l.dat` common data file - see [`set_icu_data`]
+///
+/// The data is kept in memory for the life of the process
+///
+/// # Errors
+/// Will return an error if the file cannot be read, or if the data is rejected by ICU
+pub fn load_icu_data(path: impl AsRef<Path>) -> Result<(), Error> {
+    let bytes = std::fs::read(path)?;
+
+    // Copied into 16-byte chunks, since ICU requires aligned data
+    let chunks: Vec<IcuChunk> = bytes
+        .chunks(16)
+        .map(|bytes| {
+            let mut chunk = [0; 16];
+            chunk[..bytes.len()].copy_from_slice(bytes);
+            IcuChunk(chunk)
+        })
+        .collect();
+    let chunks: &'static [IcuChunk] = Box::leak(chunks.into_boxed_slice());
+
+    // SAFETY: The chunks are plain byte arrays with no padding, covering at least `bytes.len()` bytes
+    let data = unsafe { std::slice::from_raw_parts(chunks.as_ptr().cast::<u8>(), bytes.len()) };
+    set_icu_data(data)
+}
+
+/// 16 bytes of ICU data, aligned as ICU requires
+#[repr(C, align(16))]
+struct IcuChunk([u8; 16]);
+
+/// Set the default locale used by `Intl` and locale-aware string methods, as a BCP 47 language tag such as `en-US`
+///
+/// Applies to every runtime in the process - scripts can still request other locales explicitly
+///
+/// # Errors
+/// Will return an error if the tag contains a null byte
+pub fn set_default_locale(locale: &str) -> Result<(), Error> {
+    if locale.contains('\0') {
+        return Err(Error::Runtime(format!("Invalid locale: {locale:?}")));
+    }
+    deno_core::v8::icu::set_default_locale(locale);
+    Ok(())
+}
+
+/// Returns the default locale used by `Intl`, as a BCP 47 language tag
+#[must_use]
+pub fn default_locale() -> String {
+    deno_core::v8::icu::get_language_tag()
+}
+
 #[macro_use]
 mod runtime_macros {
     /// Map a series of values into a form which javascript functions can understand
@@ -269,6 +363,32 @@ mod test_runtime {
     use super::*;
     use deno_core::{futures::FutureExt, serde_json};
 
+    #[test]
+    fn test_v8_flags() {
+        let error = set_v8_flags(&["--not-a-real-flag"]).unwrap_err();
+        assert!(error.to_string().contains("--not-a-real-flag"));
+
+        // Temporal is enabled for every runtime
+        let date: String =
+            evaluate("Temporal.PlainDate.from('2020-01-15').add({ days: 1 }).toString()").unwrap();
+        assert_eq!(date, "2020-01-16");
+    }
+
+    #[test]
+    fn test_icu() {
+        // Full ICU data is built in
+        let month: String = evaluate(
+            "new Intl.DateTimeFormat('de-DE', { month: 'long', timeZone: 'UTC' }).format(new Date(Date.UTC(2020, 0, 15)))",
+        )
+        .unwrap();
+        assert_eq!(month, "Januar");
+        assert!(!default_locale().is_empty());
+
+        set_icu_data(&[]).unwrap_err();
+        load_icu_data("does_not_exist.dat").unwrap_err();
+        set_default_locale("en\0US").unwrap_err();
+    }
+
     #[test]
     fn test_callback() {
         let add = sync_callback!(|a: i64, b: i64| { Ok::<i64, Error>(a + b) });
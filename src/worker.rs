@@ -17,10 +17,13 @@
 //! }
 
 use crate::{Error, RuntimeOptions};
-use std::cell::RefCell;
+use deno_core::v8;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
 use std::rc::Rc;
-use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
 use std::thread::{spawn, JoinHandle};
+use std::time::{Duration, Instant};
 
 /// A pool of worker threads that can be used to run javascript code in parallel
 /// Uses a round-robin strategy to distribute work between workers
@@ -90,6 +93,50 @@ where
         Some(Rc::clone(self.workers.get(id)?))
     }
 
+    /// Check the health of every worker in the pool, see [`Worker::health`]
+    #[must_use]
+    pub fn health(&self, max_busy: Duration) -> Vec<WorkerHealth> {
+        self.workers
+            .iter()
+            .map(|worker| worker.borrow().health(max_busy))
+            .collect()
+    }
+
+    /// Replace a worker with a new one, started with the pool's options
+    ///
+    /// Any javascript the old worker is running is terminated, if its runtime supports it
+    /// The old worker is not waited for - its thread exits once its current query ends
+    ///
+    /// # Errors
+    /// Will return an error if there is no worker with that id, or if the new runtime cannot be initialized
+    pub fn replace_worker(&mut self, id: usize) -> Result<(), Error> {
+        let slot = self
+            .workers
+            .get(id)
+            .ok_or_else(|| Error::Runtime(format!("No worker with id {id}")))?;
+
+        let worker = Worker::new(self.options.clone())?;
+        let old = std::mem::replace(&mut *slot.borrow_mut(), worker);
+        old.terminate();
+        Ok(())
+    }
+
+    /// Replace every worker that has stopped, or has been working on the same query for longer than `max_busy`
+    /// Returns the number of workers replaced
+    ///
+    /// # Errors
+    /// Will return an error if a new runtime cannot be initialized
+    pub fn replace_unhealthy(&mut self, max_busy: Duration) -> Result<usize, Error> {
+        let mut replaced = 0;
+        for (id, health) in self.health(max_busy).into_iter().enumerate() {
+            if matches!(health, WorkerHealth::Wedged(_) | WorkerHealth::Stopped) {
+                self.replace_worker(id)?;
+                replaced += 1;
+            }
+        }
+        Ok(replaced)
+    }
+
     /// Get the next worker in the pool
     pub fn next_worker(&mut self) -> Rc<RefCell<Worker<W>>> {
         let worker = &self.workers[self.next_worker];
@@ -106,6 +153,71 @@ where
         self.next_worker().borrow().send_and_await(query)
     }
 
+    /// Distribute a workload over every worker in the pool, and collect the results
+    ///
+    /// Each item is turned into a query by `query`, and sent to an idle worker;
+    /// the worker's response is then turned into a result by `response`
+    /// Workers run concurrently, with at most one query in flight each
+    ///
+    /// If a worker stops or panics while handling an item, the item is retried on another worker
+    /// An item is only given up on once it has failed on as many workers as the pool contains
+    ///
+    /// Results are returned in the same order as the items
+    ///
+    /// ```rust
+    /// use rustyscript::{ Error, worker::{ DefaultWorker, DefaultWorkerQuery, DefaultWorkerResponse, WorkerPool } };
+    ///
+    /// # fn main() -> Result<(), Error> {
+    /// let mut pool = WorkerPool::<DefaultWorker>::new(Default::default(), 2)?;
+    /// let results = pool.map(
+    ///     1..=4,
+    ///     |n| DefaultWorkerQuery::Eval(format!("{n} * {n}")),
+    ///     |response| match response {
+    ///         DefaultWorkerResponse::Value(v) => Ok(v.as_i64().unwrap_or_default()),
+    ///         _ => Err(Error::Runtime("Unexpected response".to_string())),
+    ///     },
+    /// );
+    ///
+    /// let results = results.into_iter().collect::<Result<Vec<_>, _>>()?;
+    /// assert_eq!(results, vec![1, 4, 9, 16]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn map<I, T>(
+        &mut self,
+        items: impl IntoIterator<Item = I>,
+        query: impl Fn(&I) -> W::Query,
+        response: impl Fn(W::Response) -> Result<T, Error>,
+    ) -> Vec<Result<T, Error>> {
+        let mut map = PoolMap::new(&self.workers, items);
+        while !map.is_done() {
+            map.dispatch(&query);
+            map.collect(true, &response);
+        }
+        map.into_results()
+    }
+
+    /// Distribute a workload over every worker in the pool, and collect the results
+    ///
+    /// Unlike [`WorkerPool::map`], this does not block the current thread while waiting for the workers;
+    /// idle polls are spaced out by `poll_interval`
+    pub async fn map_async<I, T>(
+        &mut self,
+        items: impl IntoIterator<Item = I>,
+        query: impl Fn(&I) -> W::Query,
+        response: impl Fn(W::Response) -> Result<T, Error>,
+        poll_interval: std::time::Duration,
+    ) -> Vec<Result<T, Error>> {
+        let mut map = PoolMap::new(&self.workers, items);
+        while !map.is_done() {
+            map.dispatch(&query);
+            if !map.collect(false, &response) {
+                tokio::time::sleep(poll_interval).await;
+            }
+        }
+        map.into_results()
+    }
+
     /// Evaluate a string of non-ecma javascript code in a separate thread
     /// The code is evaluated in a new runtime instance, which is then destroyed
     /// Returns a handle to the thread that is running the code
@@ -122,6 +234,150 @@ where
     }
 }
 
+/// An item of a [`WorkerPool::map`], along with its position and the number of workers it has failed on
+struct MapItem<I> {
+    index: usize,
+    item: I,
+    failures: usize,
+}
+
+/// The state of a [`WorkerPool::map`] - the items waiting for a worker, and those in flight on each worker
+struct PoolMap<'a, W, I, T>
+where
+    W: InnerWorker,
+{
+    workers: &'a [Rc<RefCell<Worker<W>>>],
+    alive: Vec<bool>,
+    in_flight: Vec<Option<(u64, MapItem<I>)>>,
+    pending: VecDeque<MapItem<I>>,
+    results: Vec<Option<Result<T, Error>>>,
+    sequence: u64,
+}
+
+impl<'a, W, I, T> PoolMap<'a, W, I, T>
+where
+    W: InnerWorker,
+{
+    fn new(workers: &'a [Rc<RefCell<Worker<W>>>], items: impl IntoIterator<Item = I>) -> Self {
+        let pending: VecDeque<_> = items
+            .into_iter()
+            .enumerate()
+            .map(|(index, item)| MapItem {
+                index,
+                item,
+                failures: 0,
+            })
+            .collect();
+
+        Self {
+            workers,
+            alive: vec![true; workers.len()],
+            in_flight: workers.iter().map(|_| None).collect(),
+            results: pending.iter().map(|_| None).collect(),
+            pending,
+            sequence: 0,
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.pending.is_empty() && self.in_flight.iter().all(Option::is_none)
+    }
+
+    /// Send pending items to every idle worker
+    fn dispatch(&mut self, query: &impl Fn(&I) -> W::Query) {
+        for id in 0..self.workers.len() {
+            if !self.alive[id] || self.in_flight[id].is_some() {
+                continue;
+            }
+            let Some(item) = self.pending.pop_front() else {
+                break;
+            };
+
+            match self.workers[id].borrow().send(query(&item.item)) {
+                Ok(()) => {
+                    self.sequence += 1;
+                    self.in_flight[id] = Some((self.sequence, item));
+                }
+                Err(e) => self.fail(id, item, e),
+            }
+        }
+
+        // Nothing is left to run the remaining items
+        if !self.alive.contains(&true) {
+            while let Some(item) = self.pending.pop_front() {
+                self.results[item.index] = Some(Err(Error::WorkerHasStopped));
+            }
+        }
+    }
+
+    /// Collect the responses of any workers that have finished
+    ///
+    /// If `block` is set, waits for the worker that has been busy the longest
+    /// Returns true if any item was completed or failed
+    fn collect(
+        &mut self,
+        block: bool,
+        response: &impl Fn(W::Response) -> Result<T, Error>,
+    ) -> bool {
+        let oldest = self
+            .in_flight
+            .iter()
+            .enumerate()
+            .filter_map(|(id, slot)| slot.as_ref().map(|(sequence, _)| (*sequence, id)))
+            .min()
+            .map(|(_, id)| id);
+
+        let mut progress = false;
+        for id in 0..self.workers.len() {
+            if self.in_flight[id].is_none() {
+                continue;
+            }
+
+            let worker = self.workers[id].borrow();
+            let received = if block && Some(id) == oldest {
+                worker.receive().map(Some)
+            } else {
+                worker.try_receive()
+            };
+            drop(worker);
+
+            match received {
+                Ok(None) => continue,
+                Ok(Some(value)) => {
+                    if let Some((_, item)) = self.in_flight[id].take() {
+                        self.results[item.index] = Some(response(value));
+                    }
+                }
+                Err(e) => {
+                    if let Some((_, item)) = self.in_flight[id].take() {
+                        self.fail(id, item, e);
+                    }
+                }
+            }
+            progress = true;
+        }
+        progress
+    }
+
+    /// Marks a worker as failed, and retries its item on another worker if it has not failed on all of them
+    fn fail(&mut self, id: usize, mut item: MapItem<I>, e: Error) {
+        self.alive[id] = false;
+        item.failures += 1;
+        if item.failures >= self.workers.len() {
+            self.results[item.index] = Some(Err(e));
+        } else {
+            self.pending.push_front(item);
+        }
+    }
+
+    fn into_results(self) -> Vec<Result<T, Error>> {
+        self.results
+            .into_iter()
+            .map(|result| result.unwrap_or(Err(Error::WorkerHasStopped)))
+            .collect()
+    }
+}
+
 /// A worker thread that can be used to run javascript code in a separate thread
 /// Contains a channel pair for communication, and a single runtime instance
 ///
@@ -136,6 +392,14 @@ where
     handle: Option<JoinHandle<()>>,
     tx: Option<Sender<W::Query>>,
     rx: Receiver<W::Response>,
+    isolate: Option<v8::IsolateHandle>,
+
+    /// When each query still awaiting a response was sent, oldest first
+    pending: RefCell<VecDeque<Instant>>,
+    last_activity: Cell<Instant>,
+
+    /// Number of responses to queries that timed out, to be dropped when they arrive
+    discard: Cell<usize>,
 }
 
 impl<W> Worker<W>
@@ -149,38 +413,46 @@ where
     pub fn new(options: W::RuntimeOptions) -> Result<Self, Error> {
         let (qtx, qrx) = channel();
         let (rtx, rrx) = channel();
-        let (init_tx, init_rx) = channel::<Option<Error>>();
+        let (init_tx, init_rx) = channel::<Result<Option<v8::IsolateHandle>, Error>>();
 
         let handle = spawn(move || {
             let rx = qrx;
             let tx = rtx;
             let itx = init_tx;
 
-            let runtime = match W::init_runtime(options) {
+            let mut runtime = match W::init_runtime(options) {
                 Ok(rt) => rt,
                 Err(e) => {
-                    itx.send(Some(e)).ok(); // Stopping anyway, so no need to check for errors
+                    itx.send(Err(e)).ok(); // Stopping anyway, so no need to check for errors
                     return;
                 }
             };
 
-            if itx.send(None).is_ok() {
+            let isolate = W::isolate_handle(&mut runtime);
+            if itx.send(Ok(isolate)).is_ok() {
                 W::thread(runtime, rx, tx);
             }
         });
 
-        let worker = Self {
+        let mut worker = Self {
             handle: Some(handle),
             tx: Some(qtx),
             rx: rrx,
+            isolate: None,
+            pending: RefCell::new(VecDeque::new()),
+            last_activity: Cell::new(Instant::now()),
+            discard: Cell::new(0),
         };
 
         // Wait for initialization to complete
         match init_rx.recv() {
-            Ok(None) => Ok(worker),
+            Ok(Ok(isolate)) => {
+                worker.isolate = isolate;
+                Ok(worker)
+            }
 
             // Initialization failed
-            Ok(Some(e)) => Err(e),
+            Ok(Err(e)) => Err(e),
 
             // Parser crashed on startup
             _ => {
@@ -241,7 +513,10 @@ where
             Some(tx) => tx,
         }
         .send(query)
-        .map_err(|e| Error::Runtime(e.to_string()))
+        .map_err(|e| Error::Runtime(e.to_string()))?;
+
+        self.pending.borrow_mut().push_back(Instant::now());
+        Ok(())
     }
 
     /// Receive a response from the worker
@@ -250,7 +525,33 @@ where
     /// # Errors
     /// Will return an error if the worker has already been stopped, or if the worker thread panicked
     pub fn receive(&self) -> Result<W::Response, Error> {
-        self.rx.recv().map_err(|e| Error::Runtime(e.to_string()))
+        loop {
+            let response = self.rx.recv().map_err(|e| Error::Runtime(e.to_string()))?;
+            if let Some(response) = self.accept(response) {
+                return Ok(response);
+            }
+        }
+    }
+
+    /// Receive a response from the worker, waiting at most `timeout`
+    /// This will return `Ok(None)` if no response arrives in time
+    ///
+    /// # Errors
+    /// Will return an error if the worker has already been stopped, or if the worker thread panicked
+    pub fn receive_timeout(&self, timeout: Duration) -> Result<Option<W::Response>, Error> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.rx.recv_timeout(remaining) {
+                Ok(response) => {
+                    if let Some(response) = self.accept(response) {
+                        return Ok(Some(response));
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => return Ok(None),
+                Err(e) => return Err(Error::Runtime(e.to_string())),
+            }
+        }
     }
 
     /// Try to receive a response from the worker without blocking
@@ -259,12 +560,88 @@ where
     /// # Errors
     /// Will return an error if the worker has already been stopped, or if the worker thread panicked
     pub fn try_receive(&self) -> Result<Option<W::Response>, Error> {
-        match self.rx.try_recv() {
-            Ok(v) => Ok(Some(v)),
-            Err(e) => match e {
-                std::sync::mpsc::TryRecvError::Empty => Ok(None),
-                std::sync::mpsc::TryRecvError::Disconnected => Err(Error::Runtime(e.to_string())),
-            },
+        loop {
+            match self.rx.try_recv() {
+                Ok(v) => {
+                    if let Some(v) = self.accept(v) {
+                        return Ok(Some(v));
+                    }
+                }
+                Err(e) => {
+                    return match e {
+                        std::sync::mpsc::TryRecvError::Empty => Ok(None),
+                        std::sync::mpsc::TryRecvError::Disconnected => {
+                            Err(Error::Runtime(e.to_string()))
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Records a response as received, returning it unless it belongs to a query that timed out
+    fn accept(&self, response: W::Response) -> Option<W::Response> {
+        self.pending.borrow_mut().pop_front();
+        self.last_activity.set(Instant::now());
+
+        let discard = self.discard.get();
+        if discard > 0 {
+            self.discard.set(discard - 1);
+            return None;
+        }
+        Some(response)
+    }
+
+    /// Returns true if the worker thread is still running
+    /// A worker stops once it is shut down, or if its thread panics
+    #[must_use]
+    pub fn is_alive(&self) -> bool {
+        self.tx.is_some() && self.handle.as_ref().is_some_and(|h| !h.is_finished())
+    }
+
+    /// Returns when the worker last finished a query, or when it was created if it has not yet finished one
+    #[must_use]
+    pub fn last_activity(&self) -> Instant {
+        self.last_activity.get()
+    }
+
+    /// Returns the number of queries sent to the worker that are still awaiting a response
+    #[must_use]
+    pub fn queue_depth(&self) -> usize {
+        self.pending.borrow().len()
+    }
+
+    /// Returns how long the worker has been working on its current query, or `None` if it is idle
+    #[must_use]
+    pub fn busy_for(&self) -> Option<Duration> {
+        self.pending.borrow().front().map(Instant::elapsed)
+    }
+
+    /// Check the health of the worker, without sending it a query
+    ///
+    /// A worker that has been working on the same query for longer than `max_busy` is considered wedged,
+    /// for example by an infinite loop in javascript, and can be stopped with [`Worker::terminate`]
+    #[must_use]
+    pub fn health(&self, max_busy: Duration) -> WorkerHealth {
+        if !self.is_alive() {
+            return WorkerHealth::Stopped;
+        }
+
+        match self.busy_for() {
+            None => WorkerHealth::Idle,
+            Some(busy) if busy > max_busy => WorkerHealth::Wedged(busy),
+            Some(busy) => WorkerHealth::Busy(busy),
+        }
+    }
+
+    /// Interrupt any javascript the worker is running, failing its current query
+    /// The worker's runtime cannot be used again afterwards, so the worker should then be replaced
+    ///
+    /// Returns false if the worker's runtime does not support interruption - see [`InnerWorker::isolate_handle`]
+    pub fn terminate(&self) -> bool {
+        match &self.isolate {
+            Some(isolate) => isolate.terminate_execution(),
+            None => false,
         }
     }
 
@@ -279,6 +656,48 @@ where
         self.receive()
     }
 
+    /// Send a request to the worker and wait at most `timeout` for a response
+    ///
+    /// If the query runs too long, any javascript it is running is terminated,
+    /// and the worker is left usable for the next query - see [`InnerWorker::isolate_handle`]
+    /// If the runtime cannot be interrupted, the late response is dropped when it arrives
+    ///
+    /// Should not be used while other queries sent with [`Worker::send`] are still awaiting a response
+    ///
+    /// # Errors
+    /// Will return [`Error::Timeout`] if no response arrives in time,
+    /// or an error if the worker has already been stopped, or if the worker thread panicked
+    pub fn send_and_await_timeout(
+        &self,
+        query: W::Query,
+        timeout: Duration,
+    ) -> Result<W::Response, Error> {
+        let start = Instant::now();
+        self.send(query)?;
+        if let Some(response) = self.receive_timeout(timeout)? {
+            return Ok(response);
+        }
+
+        // Give the interrupted query the same time again to unwind, and drop its failed response
+        let finished = match &self.isolate {
+            Some(isolate) => {
+                isolate.terminate_execution();
+                let finished = self.receive_timeout(timeout)?.is_some();
+                isolate.cancel_terminate_execution();
+                finished
+            }
+            None => false,
+        };
+        if !finished {
+            self.discard.set(self.discard.get() + 1);
+        }
+
+        Err(Error::Timeout {
+            elapsed: start.elapsed(),
+            limit: timeout,
+        })
+    }
+
     /// Consume the worker and wait for the thread to finish
     ///
     /// WARNING: If implementing a custom `thread` function, make sure to handle rx failures gracefully
@@ -297,6 +716,22 @@ where
     }
 }
 
+/// The health of a worker, see [`Worker::health`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkerHealth {
+    /// The worker is waiting for a query
+    Idle,
+
+    /// The worker has been working on its current query for the given time
+    Busy(Duration),
+
+    /// The worker has been working on its current query for longer than allowed
+    Wedged(Duration),
+
+    /// The worker thread has stopped, or panicked
+    Stopped,
+}
+
 /// An implementation of the worker trait for a specific runtime
 /// This allows flexibility in the runtime used by the worker
 /// As well as the types of queries and responses that can be used
@@ -333,6 +768,15 @@ where
     /// Can fail if the runtime cannot be initialized (usually due to extension issues)
     fn init_runtime(options: Self::RuntimeOptions) -> Result<Self::Runtime, Error>;
 
+    /// Returns a handle to the isolate used by the runtime, allowing [`Worker::terminate`] to interrupt it
+    /// Called once, after the runtime is initialized
+    ///
+    /// Returns `None` by default, in which case wedged workers cannot be terminated
+    fn isolate_handle(runtime: &mut Self::Runtime) -> Option<v8::IsolateHandle> {
+        let _ = runtime;
+        None
+    }
+
     /// Handle a query sent to the worker
     /// Must always return a response of some kind
     fn handle_query(runtime: &mut Self::Runtime, query: Self::Query) -> Self::Response;
@@ -357,32 +801,117 @@ where
 /// This is the simplest way to use the worker, as it requires no additional setup
 /// It attempts to provide as much functionality as possible from the standard runtime
 ///
-/// Please note that it uses `serde_json::Value` for queries and responses, which comes with a performance cost
+/// Please note that it uses `serde_json::Value` for queries and responses, which comes with a performance cost  
+/// Large results can be returned as JSON text instead, with [`DefaultWorker::call_function_json`]
 /// For a more performant worker, or to use extensions and/or loader caches, you'll need to implement your own worker
 pub struct DefaultWorker(Worker<DefaultWorker>);
 impl InnerWorker for DefaultWorker {
     type Runtime = (
         crate::Runtime,
         std::collections::HashMap<deno_core::ModuleId, crate::ModuleHandle>,
+        FunctionRegistry,
     );
     type RuntimeOptions = DefaultWorkerOptions;
     type Query = DefaultWorkerQuery;
     type Response = DefaultWorkerResponse;
 
     fn init_runtime(options: Self::RuntimeOptions) -> Result<Self::Runtime, Error> {
-        let runtime = crate::Runtime::new(crate::RuntimeOptions {
+        #[allow(unused_mut)]
+        let mut extension_options = crate::ExtensionOptions::default();
+
+        #[cfg(feature = "crypto")]
+        {
+            extension_options.crypto_seed = options.crypto_seed;
+            extension_options.crypto_rng = options.crypto_rng;
+        }
+
+        #[cfg(feature = "io")]
+        {
+            extension_options.io_pipes = options.io_pipes;
+        }
+
+        #[cfg(feature = "fs")]
+        if let Some(filesystem) = options.filesystem {
+            extension_options.filesystem = filesystem;
+        }
+
+        #[cfg(feature = "cache")]
+        if let Some(dir) = &options.cache_storage_dir {
+            let cache =
+                crate::CacheBackend::new_sqlite(dir).map_err(|e| Error::Runtime(e.to_string()))?;
+            extension_options.cache = Some(cache);
+            extension_options.cache_storage_dir = Some(dir.clone());
+        }
+
+        #[cfg(feature = "broadcast_channel")]
+        {
+            extension_options.broadcast_channel = options.broadcast_channel;
+        }
+
+        #[cfg(feature = "sqlite")]
+        {
+            extension_options.sqlite = options.sqlite;
+        }
+
+        #[cfg(feature = "sql_bridge")]
+        {
+            extension_options.sql_bridge = options.sql_bridge;
+        }
+
+        #[cfg(feature = "node_experimental")]
+        {
+            extension_options.bootstrap = options.bootstrap;
+        }
+
+        #[cfg(feature = "webstorage")]
+        {
+            extension_options
+                .webstorage_origin_storage_dir
+                .clone_from(&options.webstorage_origin_storage_dir);
+        }
+
+        #[cfg(feature = "kv")]
+        if let Some(path) = &options.kv_store_path {
+            extension_options.kv_store =
+                crate::KvStore::new_local(Some(path.clone()), None, crate::KvConfig::default());
+        }
+
+        let mut runtime = crate::Runtime::new(crate::RuntimeOptions {
             default_entrypoint: options.default_entrypoint,
             timeout: options.timeout,
+            preemption_interval: options.preemption_interval,
+            max_heap_size: options.max_heap_size,
             shared_array_buffer_store: options.shared_array_buffer_store,
             startup_snapshot: options.startup_snapshot,
+            extension_options,
             ..Default::default()
         })?;
-        let modules = std::collections::HashMap::new();
-        Ok((runtime, modules))
+
+        if let Some(dir) = &options.current_dir {
+            runtime.set_current_dir(dir)?;
+        }
+
+        let mut modules = std::collections::HashMap::new();
+
+        for module in &options.preload_modules {
+            let handle = runtime.load_module(module)?;
+            modules.insert(handle.id(), handle);
+        }
+
+        if let Some(code) = &options.startup_script {
+            runtime.eval::<crate::js_value::Value>(code)?;
+        }
+
+        Ok((runtime, modules, FunctionRegistry::default()))
     }
 
+    fn isolate_handle(runtime: &mut Self::Runtime) -> Option<v8::IsolateHandle> {
+        Some(runtime.0.deno_runtime().v8_isolate().thread_safe_handle())
+    }
+
+    #[allow(clippy::too_many_lines)]
     fn handle_query(runtime: &mut Self::Runtime, query: Self::Query) -> Self::Response {
-        let (runtime, modules) = runtime;
+        let (runtime, modules, functions) = runtime;
         match query {
             DefaultWorkerQuery::Eval(code) => match runtime.eval(&code) {
                 Ok(v) => Self::Response::Value(v),
@@ -437,6 +966,63 @@ impl InnerWorker for DefaultWorker {
                 }
             }
 
+            DefaultWorkerQuery::CallFunctionJson(id, name, args) => {
+                let handle = match id.map(|id| module_by_id(modules, id)).transpose() {
+                    Ok(handle) => handle,
+                    Err(e) => return Self::Response::Error(e),
+                };
+
+                match runtime.call_function_json(handle, &name, &args) {
+                    Ok(json) => Self::Response::Json(json),
+                    Err(e) => Self::Response::Error(e),
+                }
+            }
+
+            DefaultWorkerQuery::ModuleByName(name) => match module_by_name(modules, &name) {
+                Ok(handle) => Self::Response::ModuleId(handle.id()),
+                Err(e) => Self::Response::Error(e),
+            },
+
+            DefaultWorkerQuery::CallFunctionByName {
+                module_name,
+                name,
+                args,
+            } => {
+                let handle = match module_name.map(|m| module_by_name(modules, &m)).transpose() {
+                    Ok(handle) => handle,
+                    Err(e) => return Self::Response::Error(e),
+                };
+
+                match runtime.call_function(handle, &name, &args) {
+                    Ok(v) => Self::Response::Value(v),
+                    Err(e) => Self::Response::Error(e),
+                }
+            }
+
+            DefaultWorkerQuery::EvalWithTimeout(code, timeout) => {
+                let previous = runtime.timeout();
+                runtime.set_timeout(timeout);
+                let result = runtime.eval(&code);
+                runtime.set_timeout(previous);
+
+                match result {
+                    Ok(v) => Self::Response::Value(v),
+                    Err(e) => Self::Response::Error(e),
+                }
+            }
+
+            DefaultWorkerQuery::SetGlobal(name, value) => {
+                match set_global(runtime, &name, &value) {
+                    Ok(()) => Self::Response::Ok(()),
+                    Err(e) => Self::Response::Error(e),
+                }
+            }
+
+            DefaultWorkerQuery::RemoveModule(id) => match modules.remove(&id) {
+                Some(_) => Self::Response::Ok(()),
+                None => Self::Response::Error(Error::Runtime("Module not found".to_string())),
+            },
+
             DefaultWorkerQuery::GetValue(id, name) => {
                 let handle = if let Some(id) = id {
                     match modules.get(&id) {
@@ -456,16 +1042,147 @@ impl InnerWorker for DefaultWorker {
                     Err(e) => Self::Response::Error(e),
                 }
             }
+
+            DefaultWorkerQuery::GetFunction(id, name) => {
+                let handle = match id.map(|id| module_by_id(modules, id)).transpose() {
+                    Ok(handle) => handle,
+                    Err(e) => return Self::Response::Error(e),
+                };
+
+                match runtime.get_value(handle, &name) {
+                    Ok(f) => Self::Response::FunctionToken(functions.insert(f, handle.cloned())),
+                    Err(e) => Self::Response::Error(e),
+                }
+            }
+
+            DefaultWorkerQuery::CallForFunction(id, name, args) => {
+                let handle = match id.map(|id| module_by_id(modules, id)).transpose() {
+                    Ok(handle) => handle,
+                    Err(e) => return Self::Response::Error(e),
+                };
+
+                match runtime.call_function(handle, &name, &args) {
+                    Ok(f) => Self::Response::FunctionToken(functions.insert(f, handle.cloned())),
+                    Err(e) => Self::Response::Error(e),
+                }
+            }
+
+            DefaultWorkerQuery::CallToken(token, args) => {
+                let (function, handle) = match functions.get(token) {
+                    Ok(entry) => entry,
+                    Err(e) => return Self::Response::Error(e),
+                };
+
+                match runtime.call_stored_function(handle.as_ref(), function, &args) {
+                    Ok(v) => Self::Response::Value(v),
+                    Err(e) => Self::Response::Error(e),
+                }
+            }
+
+            DefaultWorkerQuery::ReleaseToken(token) => match functions.remove(token) {
+                Ok(()) => Self::Response::Ok(()),
+                Err(e) => Self::Response::Error(e),
+            },
         }
     }
 }
-impl DefaultWorker {
-    /// Create a new worker instance
-    ///
-    /// # Errors
-    /// Can fail if the runtime cannot be initialized (usually due to extension issues)
-    pub fn new(options: DefaultWorkerOptions) -> Result<Self, Error> {
-        Worker::new(options).map(Self)
+
+/// Identifies a javascript function held by a [`DefaultWorker`], so it can be called from another thread
+///
+/// Obtained with [`DefaultWorker::function_token`] or [`DefaultWorker::call_for_function`], and valid
+/// until released with [`DefaultWorker::release_token`]
+///
+/// Unrelated to [`crate::js_value::FunctionToken`], which stands in for functions passed to rust callbacks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct WorkerFunctionToken(u64);
+
+/// The functions held by a [`DefaultWorker`] on behalf of its callers, see [`WorkerFunctionToken`]
+#[derive(Default)]
+pub struct FunctionRegistry {
+    functions: std::collections::HashMap<
+        WorkerFunctionToken,
+        (crate::js_value::Function, Option<crate::ModuleHandle>),
+    >,
+    next: u64,
+}
+
+impl FunctionRegistry {
+    /// Holds a function, with the module it is called in
+    fn insert(
+        &mut self,
+        function: crate::js_value::Function,
+        handle: Option<crate::ModuleHandle>,
+    ) -> WorkerFunctionToken {
+        let token = WorkerFunctionToken(self.next);
+        self.next += 1;
+        self.functions.insert(token, (function, handle));
+        token
+    }
+
+    fn get(
+        &self,
+        token: WorkerFunctionToken,
+    ) -> Result<&(crate::js_value::Function, Option<crate::ModuleHandle>), Error> {
+        self.functions
+            .get(&token)
+            .ok_or_else(|| Error::Runtime(format!("Unknown function token: {}", token.0)))
+    }
+
+    fn remove(&mut self, token: WorkerFunctionToken) -> Result<(), Error> {
+        self.functions
+            .remove(&token)
+            .map(|_| ())
+            .ok_or_else(|| Error::Runtime(format!("Unknown function token: {}", token.0)))
+    }
+}
+
+/// Finds a module loaded into a [`DefaultWorker`] by its id
+fn module_by_id(
+    modules: &std::collections::HashMap<deno_core::ModuleId, crate::ModuleHandle>,
+    id: deno_core::ModuleId,
+) -> Result<&crate::ModuleHandle, Error> {
+    modules
+        .get(&id)
+        .ok_or_else(|| Error::Runtime("Module not found".to_string()))
+}
+
+/// Finds a module loaded into a [`DefaultWorker`] by its filename
+fn module_by_name<'a>(
+    modules: &'a std::collections::HashMap<deno_core::ModuleId, crate::ModuleHandle>,
+    name: &str,
+) -> Result<&'a crate::ModuleHandle, Error> {
+    let path = std::path::Path::new(name);
+    modules
+        .values()
+        .find(|handle| handle.module().filename() == path)
+        .ok_or_else(|| Error::Runtime(format!("Module not found: {name}")))
+}
+
+/// Sets a property of the global object of a [`DefaultWorker`]'s runtime
+fn set_global(
+    runtime: &mut crate::Runtime,
+    name: &str,
+    value: &crate::serde_json::Value,
+) -> Result<(), Error> {
+    use crate::traits::ToV8String;
+
+    let context = runtime.deno_runtime().main_context();
+    let mut scope = runtime.deno_runtime().handle_scope();
+    let global = context.open(&mut scope).global(&mut scope);
+
+    let key = name.to_v8_string(&mut scope)?;
+    let value = deno_core::serde_v8::to_v8(&mut scope, value)?;
+    global.set(&mut scope, key.into(), value);
+    Ok(())
+}
+
+impl DefaultWorker {
+    /// Create a new worker instance
+    ///
+    /// # Errors
+    /// Can fail if the runtime cannot be initialized (usually due to extension issues)
+    pub fn new(options: DefaultWorkerOptions) -> Result<Self, Error> {
+        Worker::new(options).map(Self)
     }
 
     /// Get a reference to the underlying worker instance
@@ -528,6 +1245,24 @@ impl DefaultWorker {
         }
     }
 
+    /// Find a module loaded into the worker by its filename, such as one of [`DefaultWorkerOptions::preload_modules`]
+    /// Returns the module id of the module
+    ///
+    /// # Errors
+    /// Can fail if no module with that filename has been loaded
+    pub fn module_by_name(&self, filename: &str) -> Result<deno_core::ModuleId, Error> {
+        match self
+            .0
+            .send_and_await(DefaultWorkerQuery::ModuleByName(filename.to_string()))?
+        {
+            DefaultWorkerResponse::ModuleId(id) => Ok(id),
+            DefaultWorkerResponse::Error(e) => Err(e),
+            _ => Err(Error::Runtime(
+                "Unexpected response from the worker".to_string(),
+            )),
+        }
+    }
+
     /// Call the entrypoint function in a module
     /// Returns the result of the function call
     /// The module id must be the id of a module loaded with `load_main_module` or `load_module`
@@ -587,6 +1322,126 @@ impl DefaultWorker {
         }
     }
 
+    /// Call a function in a module, returning its result as JSON text
+    /// Unlike [`DefaultWorker::call_function`], no `serde_json::Value` is built on either side of the worker -
+    /// and the result can be decoded into types borrowing from the buffer, see [`crate::JsonBuffer`]
+    ///
+    /// # Errors
+    /// Can fail if the function is not found, if the function returns an error,
+    /// Or if the return value cannot be serialized to JSON
+    pub fn call_function_json(
+        &self,
+        module_context: Option<deno_core::ModuleId>,
+        name: String,
+        args: Vec<crate::serde_json::Value>,
+    ) -> Result<crate::JsonBuffer, Error> {
+        match self.0.send_and_await(DefaultWorkerQuery::CallFunctionJson(
+            module_context,
+            name,
+            args,
+        ))? {
+            DefaultWorkerResponse::Json(json) => Ok(json),
+            DefaultWorkerResponse::Error(e) => Err(e),
+            _ => Err(Error::Runtime(
+                "Unexpected response from the worker".to_string(),
+            )),
+        }
+    }
+
+    /// Call a function by name, in a module found by its filename rather than its id
+    /// Returns the result of the function call
+    /// If `module_name` is `None`, the function is looked up in the global scope
+    ///
+    /// # Errors
+    /// Can fail if the module or function is not found, if the function returns an error,
+    /// Or if the return value cannot be deserialized into the requested type
+    pub fn call_function_by_name<T>(
+        &self,
+        module_name: Option<&str>,
+        name: &str,
+        args: Vec<crate::serde_json::Value>,
+    ) -> Result<T, Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let query = DefaultWorkerQuery::CallFunctionByName {
+            module_name: module_name.map(str::to_string),
+            name: name.to_string(),
+            args,
+        };
+        match self.0.send_and_await(query)? {
+            DefaultWorkerResponse::Value(v) => {
+                crate::serde_json::from_value(v).map_err(Error::from)
+            }
+            DefaultWorkerResponse::Error(e) => Err(e),
+            _ => Err(Error::Runtime(
+                "Unexpected response from the worker".to_string(),
+            )),
+        }
+    }
+
+    /// Evaluate a string of javascript code, with a timeout replacing [`DefaultWorkerOptions::timeout`] for this call only
+    /// Returns the result of the evaluation
+    ///
+    /// # Errors
+    /// Can fail if the timeout is reached, if a runtime error occurs during evaluation,
+    /// Or if the return value cannot be deserialized into the requested type
+    pub fn eval_with_timeout<T>(
+        &self,
+        code: String,
+        timeout: std::time::Duration,
+    ) -> Result<T, Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        match self
+            .0
+            .send_and_await(DefaultWorkerQuery::EvalWithTimeout(code, timeout))?
+        {
+            DefaultWorkerResponse::Value(v) => Ok(crate::serde_json::from_value(v)?),
+            DefaultWorkerResponse::Error(e) => Err(e),
+            _ => Err(Error::Runtime(
+                "Unexpected response from the worker".to_string(),
+            )),
+        }
+    }
+
+    /// Set a global variable in the worker's runtime, visible to all modules
+    ///
+    /// # Errors
+    /// Can fail if the value cannot be serialized, or cannot be converted to a javascript value
+    pub fn set_global(&self, name: &str, value: &impl serde::Serialize) -> Result<(), Error> {
+        let value = crate::serde_json::to_value(value)?;
+        match self
+            .0
+            .send_and_await(DefaultWorkerQuery::SetGlobal(name.to_string(), value))?
+        {
+            DefaultWorkerResponse::Ok(()) => Ok(()),
+            DefaultWorkerResponse::Error(e) => Err(e),
+            _ => Err(Error::Runtime(
+                "Unexpected response from the worker".to_string(),
+            )),
+        }
+    }
+
+    /// Remove a module from the worker, so it can no longer be used in queries
+    /// The module's code stays loaded in the runtime, but its handle is released
+    ///
+    /// # Errors
+    /// Can fail if the module is not found
+    pub fn remove_module(&self, id: deno_core::ModuleId) -> Result<(), Error> {
+        match self
+            .0
+            .send_and_await(DefaultWorkerQuery::RemoveModule(id))?
+        {
+            DefaultWorkerResponse::Ok(()) => Ok(()),
+            DefaultWorkerResponse::Error(e) => Err(e),
+            _ => Err(Error::Runtime(
+                "Unexpected response from the worker".to_string(),
+            )),
+        }
+    }
+
     /// Get a value from a module
     /// The module id must be the id of a module loaded with `load_main_module` or `load_module`
     ///
@@ -613,6 +1468,101 @@ impl DefaultWorker {
             )),
         }
     }
+
+    /// Get a function from a module, or the global scope if `module_context` is `None`
+    /// Returns a token that can be sent to other threads, and called with [`DefaultWorker::call_token`]
+    ///
+    /// The worker holds the function until the token is released with [`DefaultWorker::release_token`]
+    ///
+    /// # Errors
+    /// Can fail if the module or function is not found, or if the value is not a function
+    pub fn function_token(
+        &self,
+        module_context: Option<deno_core::ModuleId>,
+        name: &str,
+    ) -> Result<WorkerFunctionToken, Error> {
+        self.function_token_query(DefaultWorkerQuery::GetFunction(
+            module_context,
+            name.to_string(),
+        ))
+    }
+
+    /// Call a function that returns a function, such as a factory building a callback
+    /// Returns a token for the returned function, see [`DefaultWorker::function_token`]
+    ///
+    /// # Errors
+    /// Can fail if the module or function is not found, if the function returns an error,
+    /// Or if the function does not return a function
+    pub fn call_for_function(
+        &self,
+        module_context: Option<deno_core::ModuleId>,
+        name: &str,
+        args: Vec<crate::serde_json::Value>,
+    ) -> Result<WorkerFunctionToken, Error> {
+        self.function_token_query(DefaultWorkerQuery::CallForFunction(
+            module_context,
+            name.to_string(),
+            args,
+        ))
+    }
+
+    /// Call a function held by the worker, in the module it was obtained from
+    /// Returns the result of the function call
+    ///
+    /// # Errors
+    /// Can fail if the token has been released, if the function returns an error,
+    /// Or if the return value cannot be deserialized into the requested type
+    pub fn call_token<T>(
+        &self,
+        token: WorkerFunctionToken,
+        args: Vec<crate::serde_json::Value>,
+    ) -> Result<T, Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        match self
+            .0
+            .send_and_await(DefaultWorkerQuery::CallToken(token, args))?
+        {
+            DefaultWorkerResponse::Value(v) => {
+                crate::serde_json::from_value(v).map_err(Error::from)
+            }
+            DefaultWorkerResponse::Error(e) => Err(e),
+            _ => Err(Error::Runtime(
+                "Unexpected response from the worker".to_string(),
+            )),
+        }
+    }
+
+    /// Release a function held by the worker, invalidating its token
+    ///
+    /// # Errors
+    /// Can fail if the token has already been released
+    pub fn release_token(&self, token: WorkerFunctionToken) -> Result<(), Error> {
+        match self
+            .0
+            .send_and_await(DefaultWorkerQuery::ReleaseToken(token))?
+        {
+            DefaultWorkerResponse::Ok(()) => Ok(()),
+            DefaultWorkerResponse::Error(e) => Err(e),
+            _ => Err(Error::Runtime(
+                "Unexpected response from the worker".to_string(),
+            )),
+        }
+    }
+
+    fn function_token_query(
+        &self,
+        query: DefaultWorkerQuery,
+    ) -> Result<WorkerFunctionToken, Error> {
+        match self.0.send_and_await(query)? {
+            DefaultWorkerResponse::FunctionToken(token) => Ok(token),
+            DefaultWorkerResponse::Error(e) => Err(e),
+            _ => Err(Error::Runtime(
+                "Unexpected response from the worker".to_string(),
+            )),
+        }
+    }
 }
 impl AsRef<Worker<DefaultWorker>> for DefaultWorker {
     fn as_ref(&self) -> &Worker<DefaultWorker> {
@@ -638,6 +1588,83 @@ pub struct DefaultWorkerOptions {
     /// Optional shared array buffer store to use for the runtime
     /// Allows data-sharing between runtimes across threads
     pub shared_array_buffer_store: Option<deno_core::SharedArrayBufferStore>,
+
+    /// Modules loaded into the runtime when the worker starts, so the first request does not pay for them
+    /// Their ids can be found with [`DefaultWorker::module_by_name`]
+    pub preload_modules: Vec<crate::Module>,
+
+    /// Optional javascript code evaluated when the worker starts, after the preloaded modules
+    pub startup_script: Option<String>,
+
+    /// Optional maximum heap size for the runtime, see [`crate::RuntimeOptions::max_heap_size`]
+    pub max_heap_size: Option<usize>,
+
+    /// Optional interval at which to enforce the timeout while javascript is running,
+    /// see [`crate::RuntimeOptions::preemption_interval`]
+    pub preemption_interval: Option<std::time::Duration>,
+
+    /// Optional working directory for the runtime, see [`crate::Runtime::set_current_dir`]
+    pub current_dir: Option<std::path::PathBuf>,
+
+    /// Optional seed for the random number generator, see [`crate::ExtensionOptions::crypto_seed`]
+    #[cfg(feature = "crypto")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "crypto")))]
+    pub crypto_seed: Option<u64>,
+
+    /// Optional directory for `localStorage`, see [`crate::ExtensionOptions::webstorage_origin_storage_dir`]
+    #[cfg(feature = "webstorage")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "webstorage")))]
+    pub webstorage_origin_storage_dir: Option<std::path::PathBuf>,
+
+    /// Optional directory for a local key-value store, see [`crate::KvStore::new_local`]
+    /// Defaults to an in-memory store
+    #[cfg(feature = "kv")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "kv")))]
+    pub kv_store_path: Option<std::path::PathBuf>,
+
+    /// Optional entropy source for the runtime, see [`crate::ExtensionOptions::crypto_rng`]
+    #[cfg(feature = "crypto")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "crypto")))]
+    pub crypto_rng: Option<std::sync::Arc<dyn crate::SecureRng>>,
+
+    /// Optional stdin/out/err pipes for the runtime, see [`crate::ExtensionOptions::io_pipes`]
+    /// Every worker created from these options shares the same pipes
+    #[cfg(feature = "io")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "io")))]
+    pub io_pipes: Option<deno_io::Stdio>,
+
+    /// Filesystem implementation for the runtime, see [`crate::ExtensionOptions::filesystem`]
+    /// Defaults to the real filesystem
+    #[cfg(feature = "fs")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "fs")))]
+    pub filesystem: Option<deno_fs::FileSystemRc>,
+
+    /// Optional directory for a sqlite-backed cache, see [`crate::CacheBackend::new_sqlite`]
+    /// Defaults to an in-memory cache
+    #[cfg(feature = "cache")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "cache")))]
+    pub cache_storage_dir: Option<std::path::PathBuf>,
+
+    /// Broadcast channel for the runtime, see [`crate::ExtensionOptions::broadcast_channel`]
+    /// Workers given clones of the same channel can message each other
+    #[cfg(feature = "broadcast_channel")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "broadcast_channel")))]
+    pub broadcast_channel: deno_broadcast_channel::InMemoryBroadcastChannel,
+
+    /// Storage and connection limits for the `sqlite` extension, see [`crate::ExtensionOptions::sqlite`]
+    #[cfg(feature = "sqlite")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "sqlite")))]
+    pub sqlite: crate::SqliteOptions,
+
+    /// Optional host database for `host.sql`, see [`crate::ExtensionOptions::sql_bridge`]
+    #[cfg(feature = "sql_bridge")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "sql_bridge")))]
+    pub sql_bridge: Option<std::sync::Arc<dyn crate::SqlBridge>>,
+
+    /// Startup options seen by scripts, see [`crate::ExtensionOptions::bootstrap`]
+    #[cfg(feature = "node_experimental")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "node_experimental")))]
+    pub bootstrap: crate::BootstrapOptions,
 }
 
 /// Query types for the default worker
@@ -662,8 +1689,55 @@ pub enum DefaultWorkerQuery {
         Vec<crate::serde_json::Value>,
     ),
 
+    /// Calls a function in a module, returning its result as JSON text - see [`crate::JsonBuffer`]
+    CallFunctionJson(
+        Option<deno_core::ModuleId>,
+        String,
+        Vec<crate::serde_json::Value>,
+    ),
+
+    /// Finds a loaded module by its filename
+    ModuleByName(String),
+
+    /// Calls a function, in a module found by its filename, or in the global scope if none is given
+    CallFunctionByName {
+        /// Filename of the module providing the function
+        module_name: Option<String>,
+
+        /// Name of the function
+        name: String,
+
+        /// Arguments to pass to the function
+        args: Vec<crate::serde_json::Value>,
+    },
+
+    /// Evaluates a string of javascript code, with a timeout for this call only
+    EvalWithTimeout(String, std::time::Duration),
+
+    /// Sets a global variable
+    SetGlobal(String, crate::serde_json::Value),
+
+    /// Removes a module from the worker
+    RemoveModule(deno_core::ModuleId),
+
     /// Gets a value from a module
     GetValue(Option<deno_core::ModuleId>, String),
+
+    /// Holds a function from a module, or the global scope, returning a token for it
+    GetFunction(Option<deno_core::ModuleId>, String),
+
+    /// Calls a function, and holds the function it returns, returning a token for it
+    CallForFunction(
+        Option<deno_core::ModuleId>,
+        String,
+        Vec<crate::serde_json::Value>,
+    ),
+
+    /// Calls a function held by the worker
+    CallToken(WorkerFunctionToken, Vec<crate::serde_json::Value>),
+
+    /// Releases a function held by the worker
+    ReleaseToken(WorkerFunctionToken),
 }
 
 /// Response types for the default worker
@@ -672,12 +1746,495 @@ pub enum DefaultWorkerResponse {
     /// A successful response with a value
     Value(crate::serde_json::Value),
 
+    /// A successful response with a value serialized to JSON text
+    Json(crate::JsonBuffer),
+
     /// A successful response with a module id
     ModuleId(deno_core::ModuleId),
 
+    /// A successful response with a token for a function held by the worker
+    FunctionToken(WorkerFunctionToken),
+
     /// A successful response with no value
     Ok(()),
 
     /// An error response
     Error(Error),
 }
+
+/// Generates the messages, dispatch and typed client for a worker API
+///
+/// Each function becomes a variant of the generated `Query` and `Response` enums, a function of the `Handler` trait
+/// implemented by the worker, and a method of the `Client` trait implemented for [`Worker`]
+/// Functions return `Result<T, Error>` - the declared return type is the `T`
+///
+/// The items are generated in a module with the given name, which can see the items of the module it is declared in
+///
+/// ```rust
+/// use rustyscript::{worker::{InnerWorker, Worker}, worker_api, Error, Runtime};
+///
+/// worker_api! {
+///     /// The API of the calculator worker
+///     pub mod calculator {
+///         /// Evaluates an expression
+///         fn eval(code: String) -> i32;
+///
+///         /// Adds two numbers in javascript
+///         fn add(a: i32, b: i32) -> i32;
+///     }
+/// }
+/// use calculator::Client;
+///
+/// struct Calculator;
+/// impl calculator::Handler for Calculator {
+///     fn eval(runtime: &mut Runtime, code: String) -> Result<i32, Error> {
+///         runtime.eval(&code)
+///     }
+///
+///     fn add(runtime: &mut Runtime, a: i32, b: i32) -> Result<i32, Error> {
+///         runtime.eval(&format!("{a} + {b}"))
+///     }
+/// }
+///
+/// impl InnerWorker for Calculator {
+///     type Runtime = Runtime;
+///     type RuntimeOptions = ();
+///     type Query = calculator::Query;
+///     type Response = calculator::Response;
+///
+///     fn init_runtime((): ()) -> Result<Runtime, Error> {
+///         Runtime::new(Default::default())
+///     }
+///
+///     fn handle_query(runtime: &mut Runtime, query: calculator::Query) -> calculator::Response {
+///         <Self as calculator::Handler>::dispatch(runtime, query)
+///     }
+/// }
+///
+/// # fn main() -> Result<(), Error> {
+/// let worker = Worker::<Calculator>::new(())?;
+/// assert_eq!(worker.eval("5 + 5".to_string())?, 10);
+/// assert_eq!(worker.add(2, 3)?, 5);
+/// # Ok(())
+/// # }
+/// ```
+#[macro_export]
+macro_rules! worker_api {
+    (
+        $(#[$meta:meta])*
+        $vis:vis mod $name:ident {
+            $(
+                $(#[$fn_meta:meta])*
+                fn $fn:ident ( $( $arg:ident : $arg_ty:ty ),* $(,)? ) -> $ret:ty ;
+            )*
+        }
+    ) => {
+        $(#[$meta])*
+        #[allow(non_camel_case_types)]
+        $vis mod $name {
+            #[allow(unused_imports)]
+            use super::*;
+
+            /// Queries accepted by the worker, one for each function of the API
+            pub enum Query {
+                $(
+                    $(#[$fn_meta])*
+                    $fn { $( $arg: $arg_ty ),* },
+                )*
+            }
+
+            /// Responses sent by the worker, one for each function of the API
+            pub enum Response {
+                $(
+                    $(#[$fn_meta])*
+                    $fn(::std::result::Result<$ret, $crate::Error>),
+                )*
+            }
+
+            /// Implemented by the worker, to handle each function of the API
+            pub trait Handler: $crate::worker::InnerWorker<Query = Query, Response = Response> {
+                $(
+                    $(#[$fn_meta])*
+                    ///
+                    /// # Errors
+                    /// The error is returned to the caller
+                    fn $fn(
+                        runtime: &mut Self::Runtime,
+                        $( $arg: $arg_ty ),*
+                    ) -> ::std::result::Result<$ret, $crate::Error>;
+                )*
+
+                /// Calls the function matching a query - use as the worker's `handle_query`
+                fn dispatch(runtime: &mut Self::Runtime, query: Query) -> Response {
+                    match query {
+                        $(
+                            Query::$fn { $( $arg ),* } => {
+                                Response::$fn(<Self as Handler>::$fn(runtime, $( $arg ),*))
+                            }
+                        )*
+                    }
+                }
+            }
+
+            /// Typed methods calling each function of the API on a worker
+            pub trait Client {
+                $(
+                    $(#[$fn_meta])*
+                    ///
+                    /// # Errors
+                    /// Will return an error if the function fails, or if the worker has stopped
+                    fn $fn(&self, $( $arg: $arg_ty ),*) -> ::std::result::Result<$ret, $crate::Error>;
+                )*
+            }
+
+            impl<W: Handler> Client for $crate::worker::Worker<W> {
+                $(
+                    fn $fn(&self, $( $arg: $arg_ty ),*) -> ::std::result::Result<$ret, $crate::Error> {
+                        #[allow(unreachable_patterns)]
+                        match self.send_and_await(Query::$fn { $( $arg ),* })? {
+                            Response::$fn(result) => result,
+                            _ => Err($crate::Error::Runtime(
+                                "Unexpected response from worker".to_string(),
+                            )),
+                        }
+                    }
+                )*
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    /// Doubles numbers, but the first worker to see a negative number panics
+    struct FlakyWorker;
+    impl InnerWorker for FlakyWorker {
+        type Runtime = Arc<AtomicBool>;
+        type RuntimeOptions = Arc<AtomicBool>;
+        type Query = i64;
+        type Response = i64;
+
+        fn init_runtime(options: Self::RuntimeOptions) -> Result<Self::Runtime, Error> {
+            Ok(options)
+        }
+
+        fn handle_query(crashed: &mut Self::Runtime, query: Self::Query) -> Self::Response {
+            assert!(
+                query >= 0 || crashed.swap(true, Ordering::SeqCst),
+                "Worker crashed"
+            );
+            query * 2
+        }
+    }
+
+    #[test]
+    fn test_pool_map() {
+        let mut pool =
+            WorkerPool::<DefaultWorker>::new(DefaultWorkerOptions::default(), 3).unwrap();
+        let results = pool.map(
+            0..10,
+            |n| DefaultWorkerQuery::Eval(format!("if ({n} == 5) throw new Error('five'); {n} + 1")),
+            |response| match response {
+                DefaultWorkerResponse::Value(v) => Ok(v.as_i64().unwrap_or_default()),
+                DefaultWorkerResponse::Error(e) => Err(e),
+                _ => Err(Error::Runtime("Unexpected response".to_string())),
+            },
+        );
+        assert_eq!(results.len(), 10);
+        for (n, result) in results.into_iter().enumerate() {
+            if n == 5 {
+                result.expect_err("Error was not returned");
+            } else {
+                assert_eq!(result.unwrap(), i64::try_from(n).unwrap() + 1);
+            }
+        }
+
+        // Items are retried on another worker when theirs panics
+        let mut pool = WorkerPool::<FlakyWorker>::new(Arc::new(AtomicBool::new(false)), 2).unwrap();
+        let results = pool.map(vec![1, -2, 3, 4], |n| *n, Ok);
+        let results = results.into_iter().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(results, vec![2, -4, 6, 8]);
+
+        // Stopped workers are skipped in later maps
+        let results = pool.map(vec![-1, 2, 3], |n| *n, Ok);
+        let results = results.into_iter().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(results, vec![-2, 4, 6]);
+    }
+
+    #[test]
+    fn test_worker_health() {
+        let mut pool = WorkerPool::<DefaultWorker>::new(
+            DefaultWorkerOptions {
+                timeout: Duration::from_secs(30),
+                ..Default::default()
+            },
+            2,
+        )
+        .unwrap();
+        let max_busy = Duration::from_millis(50);
+        assert_eq!(
+            pool.health(max_busy),
+            vec![WorkerHealth::Idle, WorkerHealth::Idle]
+        );
+
+        // Wedge the first worker in an infinite loop
+        let wedged = pool.worker_by_id(0).unwrap();
+        let started = wedged.borrow().last_activity();
+        wedged
+            .borrow()
+            .send(DefaultWorkerQuery::Eval("for (;;) {}".to_string()))
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(matches!(pool.health(max_busy)[0], WorkerHealth::Wedged(_)));
+        assert_eq!(pool.health(max_busy)[1], WorkerHealth::Idle);
+
+        // The wedged query fails once the worker is terminated
+        assert!(wedged.borrow().terminate());
+        assert!(matches!(
+            wedged.borrow().receive().unwrap(),
+            DefaultWorkerResponse::Error(_)
+        ));
+        assert!(wedged.borrow().last_activity() > started);
+        assert_eq!(wedged.borrow().busy_for(), None);
+
+        // Wedged workers are replaced in place
+        let wedged = pool.worker_by_id(1).unwrap();
+        wedged
+            .borrow()
+            .send(DefaultWorkerQuery::Eval("for (;;) {}".to_string()))
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(pool.replace_unhealthy(max_busy).unwrap(), 1);
+        assert!(wedged.borrow().is_alive());
+        assert_eq!(pool.health(max_busy)[1], WorkerHealth::Idle);
+
+        let worker = pool.worker_by_id(1).unwrap();
+        let value = worker
+            .borrow()
+            .send_and_await(DefaultWorkerQuery::Eval("1 + 1".to_string()))
+            .unwrap();
+        assert!(matches!(value, DefaultWorkerResponse::Value(v) if v == 2));
+    }
+
+    #[test]
+    fn test_send_and_await_timeout() {
+        let worker = DefaultWorker::new(DefaultWorkerOptions {
+            timeout: Duration::from_secs(30),
+            ..Default::default()
+        })
+        .unwrap();
+        let worker = worker.as_worker();
+
+        let e = worker
+            .send_and_await_timeout(
+                DefaultWorkerQuery::Eval("for (;;) {}".to_string()),
+                Duration::from_millis(50),
+            )
+            .expect_err("Query was not timed out");
+        assert!(matches!(e, Error::Timeout { .. }), "{e}");
+        assert_eq!(worker.busy_for(), None);
+
+        // The worker is still usable
+        let value = worker
+            .send_and_await_timeout(
+                DefaultWorkerQuery::Eval("1 + 1".to_string()),
+                Duration::from_secs(5),
+            )
+            .unwrap();
+        assert!(matches!(value, DefaultWorkerResponse::Value(v) if v == 2));
+    }
+
+    #[cfg(feature = "snapshot_builder")]
+    #[test]
+    fn test_pool_from_snapshot() {
+        let snapshot = crate::SnapshotBuilder::new(RuntimeOptions::default())
+            .unwrap()
+            .with_expression("globalThis.fromSnapshot = 42")
+            .unwrap()
+            .finish();
+
+        let mut pool = WorkerPool::<DefaultWorker>::new(
+            DefaultWorkerOptions {
+                startup_snapshot: Some(Box::leak(snapshot)),
+                max_heap_size: Some(64 * 1024 * 1024),
+                current_dir: Some(std::env::temp_dir()),
+                ..Default::default()
+            },
+            2,
+        )
+        .unwrap();
+
+        for _ in 0..pool.len() {
+            let value = pool
+                .send_and_await(DefaultWorkerQuery::Eval("fromSnapshot".to_string()))
+                .unwrap();
+            assert!(matches!(value, DefaultWorkerResponse::Value(v) if v == 42));
+        }
+    }
+
+    #[test]
+    fn test_preload_modules() {
+        let worker = DefaultWorker::new(DefaultWorkerOptions {
+            preload_modules: vec![crate::Module::new(
+                "math.js",
+                "export const square = (n) => n * n;",
+            )],
+            startup_script: Some("globalThis.ready = true;".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        let id = worker.module_by_name("math.js").unwrap();
+        let value: i64 = worker
+            .call_function(Some(id), "square".to_string(), vec![3.into()])
+            .unwrap();
+        assert_eq!(value, 9);
+        assert!(worker.eval::<bool>("ready".to_string()).unwrap());
+        worker
+            .module_by_name("missing.js")
+            .expect_err("Missing module was found");
+    }
+
+    #[test]
+    fn test_default_worker_queries() {
+        let worker = DefaultWorker::new(DefaultWorkerOptions {
+            timeout: std::time::Duration::from_secs(5),
+            preload_modules: vec![crate::Module::new(
+                "greet.js",
+                "export const greet = (name) => `${globalThis.greeting}, ${name}`;",
+            )],
+            ..Default::default()
+        })
+        .unwrap();
+
+        worker.set_global("greeting", &"hello").unwrap();
+        let value: String = worker
+            .call_function_by_name(Some("greet.js"), "greet", vec!["world".into()])
+            .unwrap();
+        assert_eq!(value, "hello, world");
+
+        let id = worker.module_by_name("greet.js").unwrap();
+        let json = worker
+            .call_function_json(Some(id), "greet".to_string(), vec!["json".into()])
+            .unwrap();
+        assert_eq!(json.decode::<&str>().unwrap(), "hello, json");
+
+        worker
+            .call_function_by_name::<String>(Some("missing.js"), "greet", vec![])
+            .expect_err("Missing module was found");
+
+        let e = worker
+            .eval_with_timeout::<()>(
+                "new Promise(() => setTimeout(() => {}, 10000))".to_string(),
+                std::time::Duration::from_millis(50),
+            )
+            .expect_err("Timeout was not applied");
+        assert!(matches!(e, Error::Timeout { .. }), "{e}");
+        assert_eq!(worker.eval::<i64>("1 + 1".to_string()).unwrap(), 2);
+
+        let id = worker.module_by_name("greet.js").unwrap();
+        worker.remove_module(id).unwrap();
+        worker
+            .module_by_name("greet.js")
+            .expect_err("Removed module was found");
+        worker
+            .remove_module(id)
+            .expect_err("Removed module was removed again");
+    }
+
+    #[test]
+    fn test_function_tokens() {
+        let worker = DefaultWorker::new(DefaultWorkerOptions {
+            timeout: Duration::from_secs(5),
+            ..Default::default()
+        })
+        .unwrap();
+        let id = worker
+            .load_module(crate::Module::new(
+                "tokens.js",
+                "
+                export const double = (n) => n * 2;
+                export const counter = (start) => { let n = start; return () => ++n; };
+                export const value = 5;
+                ",
+            ))
+            .unwrap();
+
+        let double = worker.function_token(Some(id), "double").unwrap();
+        let value: i64 = worker.call_token(double, vec![21.into()]).unwrap();
+        assert_eq!(value, 42);
+
+        // Functions returned by a call keep their state between calls
+        let counter = worker
+            .call_for_function(Some(id), "counter", vec![10.into()])
+            .unwrap();
+        assert_eq!(worker.call_token::<i64>(counter, vec![]).unwrap(), 11);
+        assert_eq!(worker.call_token::<i64>(counter, vec![]).unwrap(), 12);
+
+        worker
+            .function_token(Some(id), "value")
+            .expect_err("A value was held as a function");
+
+        worker.release_token(counter).unwrap();
+        worker
+            .call_token::<i64>(counter, vec![])
+            .expect_err("A released function was called");
+        worker
+            .release_token(counter)
+            .expect_err("A released function was released again");
+    }
+
+    crate::worker_api! {
+        mod counter_api {
+            fn add(amount: i64) -> i64;
+            fn fail(message: String) -> ();
+        }
+    }
+
+    /// Keeps a running total, without a javascript runtime
+    struct CounterWorker;
+    impl counter_api::Handler for CounterWorker {
+        fn add(total: &mut i64, amount: i64) -> Result<i64, Error> {
+            *total += amount;
+            Ok(*total)
+        }
+
+        fn fail(_: &mut i64, message: String) -> Result<(), Error> {
+            Err(Error::Runtime(message))
+        }
+    }
+    impl InnerWorker for CounterWorker {
+        type Runtime = i64;
+        type RuntimeOptions = i64;
+        type Query = counter_api::Query;
+        type Response = counter_api::Response;
+
+        fn init_runtime(options: Self::RuntimeOptions) -> Result<Self::Runtime, Error> {
+            Ok(options)
+        }
+
+        fn handle_query(total: &mut Self::Runtime, query: Self::Query) -> Self::Response {
+            <Self as counter_api::Handler>::dispatch(total, query)
+        }
+    }
+
+    #[test]
+    fn test_worker_api() {
+        use counter_api::Client;
+
+        let worker = Worker::<CounterWorker>::new(10).unwrap();
+        assert_eq!(worker.add(5).unwrap(), 15);
+        assert_eq!(worker.add(-3).unwrap(), 12);
+
+        let e = worker.fail("nope".to_string()).unwrap_err();
+        assert!(
+            matches!(e, Error::Runtime(ref message) if message == "nope"),
+            "{e}"
+        );
+    }
+}